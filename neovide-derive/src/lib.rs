@@ -25,6 +25,8 @@ fn stream(input: DeriveInput, prefix: String) -> TokenStream {
 }
 
 fn struct_stream(name: Ident, prefix: String, data: &DataStruct) -> TokenStream {
+    // WindowSettings are listed as Window in the settings panel
+    let category = name.to_string().trim_end_matches("Settings").to_string();
     let fragments = data.fields.iter().map(|field| match field.ident {
         Some(ref ident) => {
             let vim_setting_name = format!("{prefix}{ident}");
@@ -45,6 +47,7 @@ fn struct_stream(name: Ident, prefix: String, data: &DataStruct) -> TokenStream
                     update_func,
                     reader_func
                 );
+                crate::settings::SETTINGS.set_setting_category(#vim_setting_name, #category);
             }}
         }
         None => {
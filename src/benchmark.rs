@@ -0,0 +1,372 @@
+//! Measures how fast the editor and the renderer get through standard workloads, without a window
+//! or Neovim, for `--benchmark`. The workloads are generated the same way every time, so that the
+//! results of different commits can be compared.
+
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use clap::ValueEnum;
+use rmpv::Value;
+use serde::Serialize;
+
+use crate::{
+    bridge::parse_redraw_event,
+    cmd_line::CmdLineSettings,
+    editor::{Editor, EditorCommand},
+    headless_replay::root_grid_surface,
+    renderer::Renderer,
+};
+
+const COLUMNS: u64 = 120;
+const ROWS: u64 = 40;
+// Frames taking longer than this would miss the next vblank of a 60Hz display
+const FRAME_BUDGET: Duration = Duration::from_micros(16_667);
+const WARMUP_FRAMES: usize = 10;
+// The frames are drawn into a raster surface, so the GPU backend of the window isn't measured
+const BACKEND: &str = "cpu-raster";
+
+const WORDS: [&str; 16] = [
+    "fn", "let", "mut", "self", "match", "Some", "None", "return", "impl", "for", "in", "while",
+    "editor", "window", "renderer", "grid",
+];
+const COLORS: [u64; 8] = [
+    0xf7768e, 0x9ece6a, 0xe0af68, 0x7aa2f7, 0xbb9af7, 0x7dcfff, 0xc0caf5, 0x565f89,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Workload {
+    /// Scrolling through a large file line by line
+    Scroll,
+    /// Pasting a lot of text, which redraws the whole screen every frame
+    Paste,
+    /// A terminal printing colored output as fast as it can
+    TerminalCat,
+    /// All of the above
+    All,
+}
+
+impl Workload {
+    fn name(&self) -> &'static str {
+        match self {
+            Workload::Scroll => "scroll",
+            Workload::Paste => "paste",
+            Workload::TerminalCat => "terminal-cat",
+            Workload::All => "all",
+        }
+    }
+
+    // Each frame is the arguments of one redraw notification
+    fn frames(&self) -> Vec<Vec<Value>> {
+        match self {
+            Workload::Scroll => (0..1000)
+                .map(|frame| {
+                    vec![
+                        event("grid_scroll", [1, 0, ROWS, 0, COLUMNS, 1, 0]),
+                        grid_line(ROWS - 1, &code_line(ROWS + frame)),
+                        event("grid_cursor_goto", [1, ROWS / 2, 0]),
+                        event("flush", []),
+                    ]
+                })
+                .collect(),
+            Workload::Paste => (0..300)
+                .map(|frame| {
+                    let mut events: Vec<Value> = (0..ROWS)
+                        .map(|row| grid_line(row, &code_line(frame * ROWS + row)))
+                        .collect();
+                    events.push(event("grid_cursor_goto", [1, ROWS - 1, 0]));
+                    events.push(event("flush", []));
+                    events
+                })
+                .collect(),
+            Workload::TerminalCat => (0..1000)
+                .map(|frame| {
+                    let lines = 8;
+                    let mut events = vec![event("grid_scroll", [1, 0, ROWS, 0, COLUMNS, lines, 0])];
+                    for line in 0..lines {
+                        let row = ROWS - lines + line;
+                        events.push(grid_line(row, &log_line(frame * lines + line)));
+                    }
+                    events.push(event("flush", []));
+                    events
+                })
+                .collect(),
+            Workload::All => Vec::new(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct BenchmarkResult {
+    version: &'static str,
+    timestamp: u64,
+    workload: &'static str,
+    backend: &'static str,
+    frames: usize,
+    seconds: f64,
+    fps: f64,
+    average_ms: f64,
+    p99_ms: f64,
+    max_ms: f64,
+    dropped_frames: usize,
+}
+
+impl BenchmarkResult {
+    fn new(workload: Workload, frame_times: &mut [Duration]) -> Self {
+        frame_times.sort_unstable();
+        let total: Duration = frame_times.iter().sum();
+        let frames = frame_times.len();
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        let p99 = frame_times
+            .get((frames * 99 / 100).min(frames.saturating_sub(1)))
+            .copied()
+            .unwrap_or_default();
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default(),
+            workload: workload.name(),
+            backend: BACKEND,
+            frames,
+            seconds: total.as_secs_f64(),
+            fps: frames as f64 / total.as_secs_f64().max(f64::EPSILON),
+            average_ms: ms(total) / frames.max(1) as f64,
+            p99_ms: ms(p99),
+            max_ms: ms(frame_times.last().copied().unwrap_or_default()),
+            dropped_frames: frame_times
+                .iter()
+                .filter(|time| **time > FRAME_BUDGET)
+                .count(),
+        }
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "{:<13} {:>5} frames {:>8.1} fps  avg {:>6.2}ms  p99 {:>6.2}ms  max {:>6.2}ms  dropped {}",
+            self.workload,
+            self.frames,
+            self.fps,
+            self.average_ms,
+            self.p99_ms,
+            self.max_ms,
+            self.dropped_frames
+        )
+    }
+}
+
+/// Runs the workloads given with `--benchmark`, printing the results and appending them to the
+/// `--benchmark-output` file as JSON lines.
+pub fn run(settings: &CmdLineSettings) -> Result<String, String> {
+    let workloads = match settings.benchmark {
+        Some(Workload::All) | None => {
+            vec![Workload::Scroll, Workload::Paste, Workload::TerminalCat]
+        }
+        Some(workload) => vec![workload],
+    };
+
+    // The renderer takes the draw commands the editor sends, which it can only register for once,
+    // so both are shared by the workloads
+    let mut renderer = Renderer::new(1.0);
+    let mut editor = Editor::new();
+    println!("Drawing with Skia's CPU rasteriser, the GPU isn't part of the results");
+
+    let count = workloads.len();
+    for workload in workloads {
+        let result = measure(workload, &mut renderer, &mut editor)?;
+        println!("{}", result.summary());
+
+        if let Some(path) = &settings.benchmark_output {
+            let line = serde_json::to_string(&result).map_err(|error| error.to_string())?;
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| writeln!(file, "{line}"))
+                .map_err(|error| format!("Could not write {path}: {error}"))?;
+        }
+    }
+    Ok(format!("Ran {count} benchmarks"))
+}
+
+// The setup redraws the whole grid, so nothing of the previous workload is left over
+fn measure(
+    workload: Workload,
+    renderer: &mut Renderer,
+    editor: &mut Editor,
+) -> Result<BenchmarkResult, String> {
+    send(editor, setup_events())?;
+    let mut surface = root_grid_surface(renderer, editor)?;
+    for _ in 0..WARMUP_FRAMES {
+        renderer.draw_frame(surface.canvas(), FRAME_BUDGET.as_secs_f32());
+    }
+
+    let mut frame_times = Vec::new();
+    for frame in workload.frames() {
+        let started_at = Instant::now();
+        send(editor, frame)?;
+        renderer.draw_frame(surface.canvas(), FRAME_BUDGET.as_secs_f32());
+        frame_times.push(started_at.elapsed());
+    }
+    Ok(BenchmarkResult::new(workload, &mut frame_times))
+}
+
+// Parsed here like the bridge does, since that's part of the work for every frame
+fn send(editor: &mut Editor, events: Vec<Value>) -> Result<(), String> {
+    for event in events {
+        for parsed_event in parse_redraw_event(event).map_err(|error| error.to_string())? {
+            editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(parsed_event));
+        }
+    }
+    Ok(())
+}
+
+fn setup_events() -> Vec<Value> {
+    let mut events = vec![
+        event("grid_resize", [1, COLUMNS, ROWS]),
+        event("default_colors_set", [0xc0caf5, 0x1a1b26, 0xff007c, 0, 0]),
+    ];
+    for (index, color) in COLORS.iter().enumerate() {
+        let attributes = Value::Map(vec![(Value::from("foreground"), Value::from(*color))]);
+        events.push(Value::Array(vec![
+            Value::from("hl_attr_define"),
+            Value::Array(vec![
+                Value::from(index as u64 + 1),
+                attributes,
+                Value::Map(Vec::new()),
+                Value::Array(Vec::new()),
+            ]),
+        ]));
+    }
+    for row in 0..ROWS {
+        events.push(grid_line(row, &code_line(row)));
+    }
+    events.push(event("flush", []));
+    events
+}
+
+fn event<const N: usize>(name: &str, arguments: [u64; N]) -> Value {
+    let arguments = arguments.iter().map(|argument| Value::from(*argument));
+    Value::Array(vec![Value::from(name), Value::Array(arguments.collect())])
+}
+
+fn grid_line(row: u64, segments: &[(String, u64)]) -> Value {
+    let mut cells = Vec::new();
+    let mut width = 0;
+    for (text, highlight) in segments {
+        for (index, character) in text.chars().enumerate() {
+            if width == COLUMNS {
+                break;
+            }
+            let mut cell = vec![Value::from(character.to_string())];
+            // Like Neovim, the highlight is only given when it changes
+            if index == 0 {
+                cell.push(Value::from(*highlight));
+            }
+            cells.push(Value::Array(cell));
+            width += 1;
+        }
+    }
+    if width < COLUMNS {
+        cells.push(Value::Array(vec![
+            Value::from(" "),
+            Value::from(0),
+            Value::from(COLUMNS - width),
+        ]));
+    }
+    Value::Array(vec![
+        Value::from("grid_line"),
+        Value::Array(vec![
+            Value::from(1),
+            Value::from(row),
+            Value::from(0),
+            Value::Array(cells),
+        ]),
+    ])
+}
+
+// A deterministic sequence, so that every run draws the same text
+fn pseudo_random(seed: u64) -> u64 {
+    seed.wrapping_mul(6364136223846793005)
+        .wrapping_add(1442695040888963407)
+        >> 33
+}
+
+fn code_line(line: u64) -> Vec<(String, u64)> {
+    let indent = pseudo_random(line) % 4 * 4;
+    let mut segments = vec![(" ".repeat(indent as usize), 0)];
+    let word_count = 3 + pseudo_random(line + 1) % 10;
+    for index in 0..word_count {
+        let random = pseudo_random(line * 31 + index);
+        let word = WORDS[(random % WORDS.len() as u64) as usize];
+        let highlight = random % (COLORS.len() as u64 + 1);
+        segments.push((format!("{word} "), highlight));
+    }
+    segments
+}
+
+fn log_line(line: u64) -> Vec<(String, u64)> {
+    let level = ["INFO", "WARN", "DEBUG", "ERROR"][(pseudo_random(line) % 4) as usize];
+    let highlight = pseudo_random(line) % COLORS.len() as u64 + 1;
+    let mut segments = vec![
+        (format!("[{:08}] ", line), 8),
+        (format!("{level:<5} "), highlight),
+    ];
+    segments.extend(code_line(line).into_iter().skip(1));
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn workloads_are_the_same_every_time() {
+        for workload in [Workload::Scroll, Workload::Paste, Workload::TerminalCat] {
+            assert_eq!(workload.frames(), workload.frames());
+        }
+    }
+
+    #[test]
+    fn workloads_parse_as_redraw_events() {
+        let mut events = setup_events();
+        for workload in [Workload::Scroll, Workload::Paste, Workload::TerminalCat] {
+            events.extend(workload.frames().into_iter().take(2).flatten());
+        }
+        for event in events {
+            assert!(parse_redraw_event(event).is_ok());
+        }
+    }
+
+    #[test]
+    fn lines_fill_the_grid() {
+        let line = grid_line(0, &code_line(7));
+        let cells = line.as_array().unwrap()[1].as_array().unwrap()[3]
+            .as_array()
+            .unwrap();
+        let width: u64 = cells
+            .iter()
+            .map(|cell| {
+                let cell = cell.as_array().unwrap();
+                cell.get(2).and_then(Value::as_u64).unwrap_or(1)
+            })
+            .sum();
+        assert_eq!(width, COLUMNS);
+    }
+
+    #[test]
+    fn counts_frames_over_budget_as_dropped() {
+        let mut frame_times = vec![
+            Duration::from_millis(5),
+            Duration::from_millis(20),
+            Duration::from_millis(10),
+        ];
+        let result = BenchmarkResult::new(Workload::Scroll, &mut frame_times);
+        assert_eq!(result.frames, 3);
+        assert_eq!(result.dropped_frames, 1);
+        assert_eq!(result.max_ms, 20.0);
+    }
+}
@@ -0,0 +1,116 @@
+use log::info;
+use nvim_rs::Neovim;
+use rmpv::Value;
+
+use crate::bridge::NeovimWriter;
+
+// nvim_create_autocmd, which the Lua integrations are built on, arrived with Neovim 0.7
+const LUA_INTEGRATIONS_API_LEVEL: u64 = 9;
+
+/// What the connected Neovim supports, as reported by `nvim_get_api_info`. Features it lacks are
+/// left out instead of failing.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ApiInfo {
+    pub channel: Option<u64>,
+    pub api_level: u64,
+    ui_options: Vec<String>,
+}
+
+impl ApiInfo {
+    pub async fn query(nvim: &Neovim<NeovimWriter>) -> Self {
+        let api_info = match nvim.get_api_info().await {
+            Ok(info) => Self::parse(&info),
+            Err(_) => Self::default(),
+        };
+        info!(
+            "Neovim api level {}, ui options {:?}",
+            api_info.api_level, api_info.ui_options
+        );
+        api_info
+    }
+
+    // A [channel, metadata] pair, with the api level in metadata.version
+    fn parse(info: &[Value]) -> Self {
+        let channel = info.first().and_then(Value::as_u64);
+        let metadata = info.get(1);
+        let api_level = metadata
+            .and_then(|metadata| map_value(metadata, "version"))
+            .and_then(|version| map_value(version, "api_level"))
+            .and_then(Value::as_u64)
+            .unwrap_or_default();
+        let ui_options = metadata
+            .and_then(|metadata| map_value(metadata, "ui_options"))
+            .and_then(Value::as_array)
+            .map(|options| {
+                options
+                    .iter()
+                    .filter_map(|option| option.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            channel,
+            api_level,
+            ui_options,
+        }
+    }
+
+    /// Whether nvim_ui_attach accepts the option, like `ext_multigrid`.
+    pub fn supports_ui_option(&self, option: &str) -> bool {
+        self.ui_options.iter().any(|supported| supported == option)
+    }
+
+    pub fn supports_lua_integrations(&self) -> bool {
+        self.api_level >= LUA_INTEGRATIONS_API_LEVEL
+    }
+}
+
+fn map_value<'a>(map: &'a Value, key: &str) -> Option<&'a Value> {
+    map.as_map()?
+        .iter()
+        .find(|(name, _)| name.as_str() == Some(key))
+        .map(|(_, value)| value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api_info(api_level: u64, ui_options: &[&str]) -> Vec<Value> {
+        let version = Value::Map(vec![(Value::from("api_level"), Value::from(api_level))]);
+        let ui_options = ui_options
+            .iter()
+            .map(|option| Value::from(*option))
+            .collect();
+        let metadata = Value::Map(vec![
+            (Value::from("version"), version),
+            (Value::from("ui_options"), Value::Array(ui_options)),
+        ]);
+        vec![Value::from(3), metadata]
+    }
+
+    #[test]
+    fn parses_level_and_ui_options() {
+        let info = ApiInfo::parse(&api_info(11, &["rgb", "ext_linegrid", "ext_multigrid"]));
+        assert_eq!(info.channel, Some(3));
+        assert_eq!(info.api_level, 11);
+        assert!(info.supports_ui_option("ext_multigrid"));
+        assert!(!info.supports_ui_option("ext_hlstate"));
+        assert!(info.supports_lua_integrations());
+    }
+
+    #[test]
+    fn old_neovim_lacks_lua_integrations() {
+        let info = ApiInfo::parse(&api_info(6, &["rgb", "ext_linegrid"]));
+        assert!(!info.supports_lua_integrations());
+    }
+
+    #[test]
+    fn missing_metadata_supports_nothing() {
+        let info = ApiInfo::parse(&[Value::from(1)]);
+        assert_eq!(info.channel, Some(1));
+        assert_eq!(info.api_level, 0);
+        assert!(!info.supports_ui_option("rgb"));
+    }
+}
@@ -1,14 +1,44 @@
 use std::error::Error;
 
+#[cfg(target_os = "macos")]
+use log::warn;
+use parking_lot::Mutex;
 use rmpv::Value;
 
 use crate::clipboard;
 
-pub fn get_clipboard_contents(format: Option<&str>) -> Result<Value, Box<dyn Error + Send + Sync>> {
-    let clipboard_raw = clipboard::get_contents()?.replace('\r', "");
-    let is_line_paste = clipboard_raw.ends_with('\n');
+// Big clipboards are transferred in pieces of this many lines, so that a single message doesn't
+// block the connection to a remote or WSL Neovim for long
+pub const CLIPBOARD_CHUNK_LINES: usize = 5000;
+// Pastes are sent to nvim_paste in pieces of about this size, so that Neovim can show the progress
+// and the rest can be cancelled
+pub const PASTE_CHUNK_BYTES: usize = 64 * 1024;
+// How much of a paste the confirmation shows
+const PREVIEW_LINES: usize = 5;
+const PREVIEW_LINE_LENGTH: usize = 80;
+
+lazy_static! {
+    // The lines of the last paste still being fetched, and of the copy still being sent
+    static ref PENDING_PASTE: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    static ref PENDING_COPY: Mutex<Vec<String>> = Mutex::new(Vec::new());
+}
 
-    let lines = if let Some("dos") = format {
+fn chunk(lines: &[String], offset: usize) -> Value {
+    let end = (offset + CLIPBOARD_CHUNK_LINES).min(lines.len());
+    let chunk = lines
+        .get(offset..end)
+        .unwrap_or_default()
+        .iter()
+        .map(|line| Value::from(line.as_str()))
+        .collect::<Vec<_>>();
+    Value::from(chunk)
+}
+
+/// Splits the text of the OS clipboard into the lines of a register, with `\r` added back for
+/// files in dos format.
+fn clipboard_lines(clipboard_raw: &str, format: Option<&str>) -> Vec<String> {
+    let clipboard_raw = clipboard_raw.replace('\r', "");
+    if let Some("dos") = format {
         // Add \r to lines if current file format is dos.
         clipboard_raw.replace('\n', "\r\n")
     } else {
@@ -16,37 +46,236 @@ pub fn get_clipboard_contents(format: Option<&str>) -> Result<Value, Box<dyn Err
         clipboard_raw
     }
     .split('\n')
-    .map(Value::from)
-    .collect::<Vec<Value>>();
+    .map(String::from)
+    .collect()
+}
+
+pub fn get_clipboard_contents(format: Option<&str>) -> Result<Value, Box<dyn Error + Send + Sync>> {
+    let clipboard_raw = clipboard::get_contents()?;
+    let is_line_paste = clipboard_raw.replace('\r', "").ends_with('\n');
+    let lines = clipboard_lines(&clipboard_raw, format);
 
-    let lines = Value::from(lines);
     // v paste is normal paste (everything in lines is pasted)
     // V paste is paste with extra endline (line paste)
     // If you want V paste, copy text with extra endline.
     let paste_mode = Value::from(if is_line_paste { "V" } else { "v" });
+    let first_chunk = chunk(&lines, 0);
+    let line_count = Value::from(lines.len());
+    *PENDING_PASTE.lock() = lines;
+
+    // Return [content: [String], paste_mode: v or V, line_count], the rest of the lines are
+    // fetched with get_clipboard_chunk
+    Ok(Value::from(vec![first_chunk, paste_mode, line_count]))
+}
+
+/// Warns when an application turned on Secure Keyboard Entry, which keeps clipboard managers and
+/// text expanders from typing into Neovide.
+#[cfg(target_os = "macos")]
+pub fn warn_about_secure_input() {
+    #[link(name = "Carbon", kind = "framework")]
+    extern "C" {
+        fn IsSecureEventInputEnabled() -> u8;
+    }
+    if unsafe { IsSecureEventInputEnabled() } != 0 {
+        warn!(
+            "Secure Keyboard Entry is on, clipboard managers can't paste into Neovide until the \
+             application that enabled it turns it off"
+        );
+    }
+}
+
+/// Returns the lines of the last paste starting at `offset`.
+pub fn get_clipboard_chunk(offset: &Value) -> Result<Value, Box<dyn Error + Send + Sync>> {
+    let offset = offset.as_u64().ok_or("expected a line offset")? as usize;
+    Ok(chunk(&PENDING_PASTE.lock(), offset))
+}
 
-    // Return [content: [String], paste_mode: v or V]
-    Ok(Value::from(vec![lines, paste_mode]))
+/// Turns the lines of a register into the text for the OS clipboard.
+fn clipboard_text(lines: &[String], endline: &str) -> String {
+    lines
+        .iter()
+        .map(|line| {
+            // Neovim represents NUL characters in lines as newlines, but NUL ends the text in the
+            // clipboard of Windows
+            line.replace(['\r', '\n', '\0'], "")
+        })
+        .collect::<Vec<_>>()
+        .join(endline)
 }
 
-pub fn set_clipboard_contents(value: &Value) -> Result<Value, Box<dyn Error + Send + Sync>> {
+/// Sets the OS clipboard to `lines`. When `more` is true, further lines follow in the next call
+/// and the clipboard is only set once all of them arrived.
+pub fn set_clipboard_contents(
+    value: &Value,
+    more: bool,
+) -> Result<Value, Box<dyn Error + Send + Sync>> {
     #[cfg(not(windows))]
     let endline = "\n";
     #[cfg(windows)]
     let endline = "\r\n";
 
-    let lines = value
-        .as_array()
-        .map(|arr| {
-            arr.iter()
-                .filter_map(|x| x.as_str().map(String::from))
-                .map(|s| s.replace('\r', "")) // strip \r
-                .collect::<Vec<String>>()
-                .join(endline)
-        })
-        .ok_or("can't build string from provided text")?;
+    let lines = value.as_array().map(|arr| {
+        arr.iter()
+            // Text that isn't valid UTF-8, like from files in other encodings, can't be
+            // converted to UTF-16 for Windows as is
+            .filter_map(|line| match line {
+                Value::String(line) => Some(String::from_utf8_lossy(line.as_bytes()).into()),
+                _ => None,
+            })
+            .collect::<Vec<String>>()
+    });
 
-    clipboard::set_contents(lines)?;
+    let mut pending = PENDING_COPY.lock();
+    let lines = match lines {
+        Some(lines) => lines,
+        None => {
+            // The chunks sent before belong to the failed copy, not to the next one
+            pending.clear();
+            return Err("can't build string from provided text".into());
+        }
+    };
+    pending.extend(lines);
+    if more {
+        return Ok(Value::Nil);
+    }
+
+    // Taken before setting the clipboard, so that the copy is cleared whether that works or not
+    let lines = std::mem::take(&mut *pending);
+    drop(pending);
+    clipboard::set_contents(clipboard_text(&lines, endline))?;
 
     Ok(Value::Nil)
 }
+
+/// Splits pasted text into pieces of at most `max_bytes`, never inside a character or a `\r\n`.
+pub fn paste_chunks(text: &str, max_bytes: usize) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut rest = text;
+    while rest.len() > max_bytes {
+        let mut end = max_bytes;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        if rest[..end].ends_with('\r') && rest[end..].starts_with('\n') {
+            end -= 1;
+        }
+        // A piece can't be empty, at worst it's a single character
+        if end == 0 {
+            end = rest.chars().next().map_or(rest.len(), char::len_utf8);
+        }
+        let (chunk, remaining) = rest.split_at(end);
+        chunks.push(chunk);
+        rest = remaining;
+    }
+    chunks.push(rest);
+    chunks
+}
+
+/// The phase argument of nvim_paste for the piece at `index` of `count` pieces.
+pub fn paste_phase(index: usize, count: usize) -> i64 {
+    match index {
+        _ if count == 1 => -1,
+        0 => 1,
+        index if index + 1 == count => 3,
+        _ => 2,
+    }
+}
+
+/// The first lines of pasted text for the confirmation, shortened to fit into a dialog.
+pub fn paste_preview(text: &str) -> String {
+    let mut preview: Vec<String> = text
+        .lines()
+        .take(PREVIEW_LINES)
+        .map(|line| {
+            if line.chars().count() > PREVIEW_LINE_LENGTH {
+                let start: String = line.chars().take(PREVIEW_LINE_LENGTH).collect();
+                format!("{start}…")
+            } else {
+                line.to_string()
+            }
+        })
+        .collect();
+    if text.lines().nth(PREVIEW_LINES).is_some() {
+        preview.push("…".to_string());
+    }
+    preview.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn clipboard_lines_follow_the_file_format() {
+        assert_eq!(
+            clipboard_lines("one\r\ntwo\n", None),
+            lines(&["one", "two", ""])
+        );
+        assert_eq!(
+            clipboard_lines("one\ntwo", Some("dos")),
+            lines(&["one\r", "two"])
+        );
+    }
+
+    #[test]
+    fn clipboard_text_drops_nul_characters() {
+        assert_eq!(clipboard_text(&lines(&["a\nb", "c\r"]), "\r\n"), "ab\r\nc");
+    }
+
+    #[test]
+    fn failed_copies_are_dropped() {
+        let chunk = Value::from(vec![Value::from("first")]);
+        set_clipboard_contents(&chunk, true).unwrap();
+        assert!(set_clipboard_contents(&Value::from("not lines"), false).is_err());
+        assert!(PENDING_COPY.lock().is_empty());
+    }
+
+    #[test]
+    fn big_pastes_are_chunked() {
+        let lines: Vec<String> = (0..CLIPBOARD_CHUNK_LINES + 10)
+            .map(|line| line.to_string())
+            .collect();
+        assert_eq!(
+            chunk(&lines, 0).as_array().unwrap().len(),
+            CLIPBOARD_CHUNK_LINES
+        );
+        let rest = chunk(&lines, CLIPBOARD_CHUNK_LINES);
+        assert_eq!(rest.as_array().unwrap().len(), 10);
+        assert_eq!(rest.as_array().unwrap()[0].as_str(), Some("5000"));
+        assert!(chunk(&lines, lines.len() + 1)
+            .as_array()
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn pastes_are_split_between_characters() {
+        assert_eq!(paste_chunks("abcdef", 4), vec!["abcd", "ef"]);
+        assert_eq!(paste_chunks("aé", 2), vec!["a", "é"]);
+        assert_eq!(paste_chunks("ab\r\ncd", 3), vec!["ab", "\r\nc", "d"]);
+        assert_eq!(paste_chunks("", 4), vec![""]);
+    }
+
+    #[test]
+    fn paste_previews_are_shortened() {
+        assert_eq!(paste_preview("one\ntwo"), "one\ntwo");
+        let text = (0..10).map(|line| line.to_string()).collect::<Vec<_>>();
+        assert_eq!(paste_preview(&text.join("\n")), "0\n1\n2\n3\n4\n…");
+        let long_line = "a".repeat(PREVIEW_LINE_LENGTH + 1);
+        assert_eq!(
+            paste_preview(&long_line),
+            format!("{}…", "a".repeat(PREVIEW_LINE_LENGTH))
+        );
+    }
+
+    #[test]
+    fn paste_phases_start_continue_and_end() {
+        assert_eq!(paste_phase(0, 1), -1);
+        let phases: Vec<i64> = (0..4).map(|index| paste_phase(index, 4)).collect();
+        assert_eq!(phases, vec![1, 2, 2, 3]);
+    }
+}
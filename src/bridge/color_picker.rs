@@ -0,0 +1,168 @@
+use rmpv::Value;
+
+type Rgb = (u8, u8, u8);
+
+fn parse_hex_color(color: &str) -> Option<Rgb> {
+    let hex = color.trim().strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |index: usize| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok();
+    Some((channel(0)?, channel(2)?, channel(4)?))
+}
+
+fn format_hex_color((red, green, blue): Rgb) -> String {
+    format!("#{red:02x}{green:02x}{blue:02x}")
+}
+
+/// Shows the color picker of the OS, starting at `initial` (a `#rrggbb` color, white when
+/// missing), and returns the chosen color in the same format, or nil when it was cancelled.
+pub async fn pick_color(initial: &Value) -> Value {
+    let initial = initial
+        .as_str()
+        .and_then(parse_hex_color)
+        .unwrap_or((255, 255, 255));
+
+    // The dialogs block until they are closed
+    tokio::task::spawn_blocking(move || platform_pick_color(initial))
+        .await
+        .ok()
+        .flatten()
+        .map(|color| Value::from(format_hex_color(color)))
+        .unwrap_or(Value::Nil)
+}
+
+#[cfg(target_os = "windows")]
+fn platform_pick_color((red, green, blue): Rgb) -> Option<Rgb> {
+    use std::mem::{size_of, zeroed};
+    use winapi::um::{
+        commdlg::{ChooseColorW, CC_FULLOPEN, CC_RGBINIT, CHOOSECOLORW},
+        wingdi::{GetBValue, GetGValue, GetRValue, RGB},
+    };
+
+    // The custom colors of the dialog aren't remembered between calls
+    let mut custom_colors = [RGB(255, 255, 255); 16];
+    let mut choose_color: CHOOSECOLORW = unsafe { zeroed() };
+    choose_color.lStructSize = size_of::<CHOOSECOLORW>() as u32;
+    choose_color.rgbResult = RGB(red, green, blue);
+    choose_color.lpCustColors = custom_colors.as_mut_ptr();
+    choose_color.Flags = CC_FULLOPEN | CC_RGBINIT;
+
+    if unsafe { ChooseColorW(&mut choose_color) } == 0 {
+        return None;
+    }
+    let color = choose_color.rgbResult;
+    Some((GetRValue(color), GetGValue(color), GetBValue(color)))
+}
+
+#[cfg(target_os = "macos")]
+fn platform_pick_color((red, green, blue): Rgb) -> Option<Rgb> {
+    use std::process::Command;
+
+    // AppleScript has the only blocking color picker, it works with 16 bit channels
+    let script = format!(
+        "choose color default color {{{}, {}, {}}}",
+        red as u32 * 257,
+        green as u32 * 257,
+        blue as u32 * 257
+    );
+    let output = Command::new("osascript")
+        .args(["-e", &script])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    parse_applescript_color(&String::from_utf8_lossy(&output.stdout))
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn platform_pick_color(initial: Rgb) -> Option<Rgb> {
+    use std::process::Command;
+
+    let initial = format_hex_color(initial);
+    // There is no common dialog API, so the dialogs of GNOME and KDE are tried
+    let output = Command::new("zenity")
+        .args(["--color-selection", "--color", &initial])
+        .output()
+        .or_else(|_| {
+            Command::new("kdialog")
+                .args(["--getcolor", "--default", &initial])
+                .output()
+        })
+        .map_err(|error| {
+            log::error!("Could not show a color picker, install zenity or kdialog: {error}")
+        })
+        .ok()
+        .filter(|output| output.status.success())?;
+    parse_dialog_color(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Parses the `65535, 0, 0` output of AppleScript.
+#[cfg(any(target_os = "macos", test))]
+fn parse_applescript_color(output: &str) -> Option<Rgb> {
+    let channels: Vec<u32> = output
+        .trim()
+        .split(',')
+        .map(|channel| channel.trim().parse().ok())
+        .collect::<Option<_>>()?;
+    match channels.as_slice() {
+        [red, green, blue] => Some(((red / 257) as u8, (green / 257) as u8, (blue / 257) as u8)),
+        _ => None,
+    }
+}
+
+/// Parses the output of zenity, which is `rgb(255,0,0)` or `rgba(255,0,0,0.5)`, or of kdialog,
+/// which is `#ff0000`.
+#[cfg(any(not(any(target_os = "windows", target_os = "macos")), test))]
+fn parse_dialog_color(output: &str) -> Option<Rgb> {
+    let output = output.trim();
+    if output.starts_with('#') {
+        return parse_hex_color(output);
+    }
+
+    let channels = output
+        .strip_prefix("rgba(")
+        .or_else(|| output.strip_prefix("rgb("))?
+        .strip_suffix(')')?;
+    let channels: Vec<u8> = channels
+        .split(',')
+        .take(3)
+        .map(|channel| channel.trim().parse().ok())
+        .collect::<Option<_>>()?;
+    match channels.as_slice() {
+        [red, green, blue] => Some((*red, *green, *blue)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_colors() {
+        assert_eq!(parse_hex_color("#ff8000"), Some((255, 128, 0)));
+        assert_eq!(parse_hex_color("ff8000"), None);
+        assert_eq!(parse_hex_color("#fff"), None);
+        assert_eq!(format_hex_color((255, 128, 0)), "#ff8000");
+    }
+
+    #[test]
+    fn applescript_colors() {
+        assert_eq!(
+            parse_applescript_color("65535, 32896, 0\n"),
+            Some((255, 128, 0))
+        );
+        assert_eq!(parse_applescript_color(""), None);
+    }
+
+    #[test]
+    fn dialog_colors() {
+        assert_eq!(parse_dialog_color("rgb(255,128,0)\n"), Some((255, 128, 0)));
+        assert_eq!(
+            parse_dialog_color("rgba(255,128,0,0.5)"),
+            Some((255, 128, 0))
+        );
+        assert_eq!(parse_dialog_color("#ff8000\n"), Some((255, 128, 0)));
+        assert_eq!(parse_dialog_color("red"), None);
+    }
+}
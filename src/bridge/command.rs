@@ -1,25 +1,29 @@
 use std::{
     env,
-    path::Path,
+    ffi::OsString,
+    path::{Path, PathBuf},
     process::{Command as StdCommand, Stdio},
 };
 
-use log::{debug, error, warn};
+use log::{debug, error, info, log_enabled, warn, Level};
+use rfd::{MessageButtons, MessageDialog, MessageLevel};
 use tokio::process::Command as TokioCommand;
 
-use crate::{cmd_line::CmdLineSettings, settings::*};
+use crate::{
+    bridge::{session_autosave::restore_session_args, wsl::check_wsl_distro},
+    cmd_line::CmdLineSettings,
+    i18n::tr,
+    settings::*,
+};
 
 pub fn create_nvim_command() -> TokioCommand {
     let mut cmd = build_nvim_cmd();
 
     debug!("Starting neovim with: {:?}", cmd);
 
-    #[cfg(not(debug_assertions))]
+    // Streamed into the log, and shown when Neovim fails to start
     cmd.stderr(Stdio::piped());
 
-    #[cfg(debug_assertions)]
-    cmd.stderr(Stdio::inherit());
-
     #[cfg(windows)]
     set_windows_creation_flags(&mut cmd);
 
@@ -32,14 +36,23 @@ fn set_windows_creation_flags(cmd: &mut TokioCommand) {
 }
 
 fn build_nvim_cmd() -> TokioCommand {
+    let settings = SETTINGS.get::<CmdLineSettings>();
+    if cfg!(target_os = "windows") && settings.wsl {
+        if let Err(message) = check_wsl_distro(settings.wsl_distro.as_deref()) {
+            exit_with_error(&message);
+        }
+    }
+
     if let Some(path) = SETTINGS.get::<CmdLineSettings>().neovim_bin {
         if platform_exists(&path) {
+            log_nvim_version(&path);
             return build_nvim_cmd_with_args(&path);
         } else {
             warn!("NEOVIM_BIN is invalid falling back to first bin in PATH");
         }
     }
-    if let Some(path) = platform_which("nvim") {
+    if let Some(path) = find_nvim_bin() {
+        log_nvim_version(&path);
         build_nvim_cmd_with_args(&path)
     } else {
         error!("nvim not found!");
@@ -47,12 +60,171 @@ fn build_nvim_cmd() -> TokioCommand {
     }
 }
 
-// Creates a shell command if needed on this platform (wsl or macos)
+// Shown in a dialog, since nothing else is there yet to show it and Neovide is often started
+// without a terminal
+fn exit_with_error(message: &str) -> ! {
+    error!("{}", message);
+    MessageDialog::new()
+        .set_level(MessageLevel::Error)
+        .set_title(&tr("error.title"))
+        .set_description(message)
+        .set_buttons(MessageButtons::Ok)
+        .show();
+    std::process::exit(1);
+}
+
+// Candidates from the config file come first, then whatever is on the PATH, and as a last resort
+// the places version managers and package managers install to, which might not be on the PATH
+// when started from a desktop launcher
+fn find_nvim_bin() -> Option<String> {
+    let configured_candidates = configured_candidates(env::var_os(NEOVIM_BIN_CANDIDATES_ENV));
+
+    path_candidates(configured_candidates, platform_exists)
+        .or_else(|| platform_which("nvim"))
+        .or_else(|| {
+            if SETTINGS.get::<CmdLineSettings>().wsl {
+                // The well known locations are on the host, not in WSL
+                None
+            } else {
+                path_candidates(well_known_nvim_locations(dirs::home_dir()), platform_exists)
+            }
+        })
+}
+
+// The config file joins them like the PATH
+fn configured_candidates(candidates: Option<OsString>) -> Vec<PathBuf> {
+    candidates
+        .map(|candidates| env::split_paths(&candidates).collect())
+        .unwrap_or_default()
+}
+
+// The first candidate that exists, in order
+fn path_candidates(candidates: Vec<PathBuf>, exists: impl Fn(&str) -> bool) -> Option<String> {
+    candidates
+        .into_iter()
+        .map(|candidate| candidate.to_string_lossy().to_string())
+        .find(|candidate| {
+            debug!("Checking for nvim at {}", candidate);
+            exists(candidate)
+        })
+}
+
+#[cfg(not(target_os = "windows"))]
+fn well_known_nvim_locations(home_dir: Option<PathBuf>) -> Vec<PathBuf> {
+    let mut locations = Vec::new();
+    if let Some(home_dir) = home_dir {
+        // bob and mason use the XDG layout on macOS as well
+        locations.push(home_dir.join(".local/share/bob/nvim-bin/nvim"));
+        locations.push(home_dir.join(".local/share/nvim/mason/bin/nvim"));
+        locations.push(home_dir.join(".local/bin/nvim"));
+    }
+    // homebrew on Apple Silicon, Intel and Linux
+    locations.push(PathBuf::from("/opt/homebrew/bin/nvim"));
+    locations.push(PathBuf::from("/usr/local/bin/nvim"));
+    locations.push(PathBuf::from("/home/linuxbrew/.linuxbrew/bin/nvim"));
+    // The snap, which isn't on the PATH of every desktop session
+    locations.push(PathBuf::from("/snap/bin/nvim"));
+    locations
+}
+
+#[cfg(target_os = "windows")]
+fn well_known_nvim_locations(home_dir: Option<PathBuf>) -> Vec<PathBuf> {
+    let mut locations = Vec::new();
+    if let Some(data_dir) = dirs::data_local_dir() {
+        // bob and mason
+        locations.push(data_dir.join("bob\\nvim-bin\\nvim.exe"));
+        locations.push(data_dir.join("nvim-data\\mason\\bin\\nvim.exe"));
+    }
+    if let Some(home_dir) = home_dir {
+        locations.push(home_dir.join("scoop\\shims\\nvim.exe"));
+    }
+    locations
+}
+
+fn log_nvim_version(bin: &str) {
+    info!("Using nvim at {}", bin);
+
+    // Asking nvim for its version takes a bit, skip it when nobody is going to read it
+    if !log_enabled!(Level::Info) || SETTINGS.get::<CmdLineSettings>().wsl || use_host_spawn() {
+        return;
+    }
+
+    let mut version_command = StdCommand::new(bin);
+    version_command.arg("--version");
+    #[cfg(windows)]
+    std::os::windows::process::CommandExt::creation_flags(
+        &mut version_command,
+        winapi::um::winbase::CREATE_NO_WINDOW,
+    );
+
+    match version_command.output() {
+        Ok(output) => {
+            let version = String::from_utf8_lossy(&output.stdout);
+            info!(
+                "nvim version: {}",
+                version.lines().next().unwrap_or_default()
+            );
+        }
+        Err(error) => warn!("Could not get the version of {}: {}", bin, error),
+    }
+}
+
+fn wsl_distro_args() -> Vec<String> {
+    match SETTINGS.get::<CmdLineSettings>().wsl_distro {
+        Some(distro) => vec!["--distribution".to_string(), distro],
+        None => Vec::new(),
+    }
+}
+
+// The shell nvim and the lookups are run through in WSL and on macOS, and whether it's a login
+// shell. A login shell picks up the PATH from the profile scripts, but slow ones delay the startup.
+// Terminals already did that for their children, so it's skipped when started from one on macOS.
+fn platform_shell() -> (String, bool) {
+    let settings = SETTINGS.get::<CmdLineSettings>();
+    let shell = match settings.wsl_shell {
+        Some(shell) => shell,
+        // Expanded by the default shell of the distribution
+        None if settings.wsl => "$SHELL".to_string(),
+        None => env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string()),
+    };
+    let login = !settings.no_login_shell && (settings.wsl || env::var_os("TERM").is_none());
+    (shell, login)
+}
+
+// The shell followed by the arguments making it run the command line
+fn shell_invocation(shell: String, login: bool, command_line: String) -> Vec<String> {
+    let mut invocation = vec![shell];
+    if login {
+        invocation.push("-l".to_string());
+    }
+    invocation.push("-c".to_string());
+    invocation.push(command_line);
+    invocation
+}
+
+// A Flatpak sandbox can't see the host's nvim, so it gets started outside of the sandbox instead.
+// Snaps with classic confinement already run on the host, and strict ones can't reach it at all.
+fn use_host_spawn() -> bool {
+    cfg!(target_os = "linux")
+        && !SETTINGS.get::<CmdLineSettings>().no_host_spawn
+        && Path::new("/.flatpak-info").exists()
+}
+
+// Creates a shell command if needed on this platform (wsl, macos or a flatpak sandbox)
 fn create_platform_shell_command(command: &str, args: &[&str]) -> Option<StdCommand> {
+    if SETTINGS.get::<CmdLineSettings>().no_shell {
+        return create_platform_exec_command(command, args);
+    }
+
     if cfg!(target_os = "windows") && SETTINGS.get::<CmdLineSettings>().wsl {
+        let (shell, login) = platform_shell();
         let mut result = StdCommand::new("wsl");
-        result.args(["$SHELL", "-lc"]);
-        result.arg(format!("{} {}", command, args.join(" ")));
+        result.args(wsl_distro_args());
+        result.args(shell_invocation(
+            shell,
+            login,
+            shell_command_line(command, args),
+        ));
         #[cfg(windows)]
         std::os::windows::process::CommandExt::creation_flags(
             &mut result,
@@ -61,14 +233,44 @@ fn create_platform_shell_command(command: &str, args: &[&str]) -> Option<StdComm
 
         Some(result)
     } else if cfg!(target_os = "macos") {
-        let shell = env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string());
-        let mut result = StdCommand::new(shell);
+        let (shell, login) = platform_shell();
+        let mut invocation = shell_invocation(shell, login, shell_command_line(command, args));
+        let mut result = StdCommand::new(invocation.remove(0));
+        result.args(invocation);
 
-        if env::var_os("TERM").is_none() {
-            result.arg("-l");
-        }
-        result.arg("-c");
-        result.arg(format!("{} {}", command, args.join(" ")));
+        Some(result)
+    } else if use_host_spawn() {
+        // A login shell, so that the PATH is the one the user has on the host
+        let mut result = StdCommand::new("flatpak-spawn");
+        result.args(["--host", "sh", "-lc"]);
+        result.arg(shell_command_line(command, args));
+
+        Some(result)
+    } else {
+        None
+    }
+}
+
+// Like create_platform_shell_command, but without a shell in between, for --no-shell. Only WSL and
+// Flatpak sandboxes still need a command to reach nvim, elsewhere the PATH of Neovide is used.
+fn create_platform_exec_command(command: &str, args: &[&str]) -> Option<StdCommand> {
+    if cfg!(target_os = "windows") && SETTINGS.get::<CmdLineSettings>().wsl {
+        let mut result = StdCommand::new("wsl");
+        result.args(wsl_distro_args());
+        result.arg("--exec");
+        result.arg(command);
+        result.args(args);
+        #[cfg(windows)]
+        std::os::windows::process::CommandExt::creation_flags(
+            &mut result,
+            winapi::um::winbase::CREATE_NO_WINDOW,
+        );
+
+        Some(result)
+    } else if use_host_spawn() {
+        let mut result = StdCommand::new("flatpak-spawn");
+        result.args(["--host", command]);
+        result.args(args);
 
         Some(result)
     } else {
@@ -93,6 +295,14 @@ fn platform_exists(bin: &str) -> bool {
 
 #[cfg(not(target_os = "windows"))]
 fn platform_exists(bin: &str) -> bool {
+    if use_host_spawn() {
+        // The host's file system isn't visible from inside the sandbox
+        return StdCommand::new("flatpak-spawn")
+            .args(["--host", "test", "-x", bin])
+            .output()
+            .map(|output| output.status.success())
+            .unwrap_or(false);
+    }
     Path::new(&bin).exists()
 }
 
@@ -117,38 +327,192 @@ fn platform_which(bin: &str) -> Option<String> {
     }
 }
 
-#[cfg(target_os = "macos")]
-fn nvim_cmd_impl(bin: &str, args: &[String]) -> TokioCommand {
-    let shell = env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string());
-    let mut cmd = TokioCommand::new(shell);
+// Builds the command line a posix shell has to run, quoting the arguments so that they reach the
+// command verbatim. The binary itself is left as is, so that the shell can still expand it.
+fn shell_command_line(bin: &str, args: &[impl AsRef<str>]) -> String {
     let args_str = args
         .iter()
-        .map(|arg| shlex::quote(arg))
+        .map(|arg| shlex::quote(arg.as_ref()))
         .collect::<Vec<_>>()
         .join(" ");
-    if env::var_os("TERM").is_none() {
-        cmd.arg("-l");
+    format!("{bin} {args_str}")
+}
+
+// Starts nvim itself. With --no-shell no shell set up the environment, so the directory of nvim is
+// put on the PATH explicitly, for the tools installed next to it.
+fn direct_nvim_cmd(bin: &str, args: &[String]) -> TokioCommand {
+    let mut cmd = TokioCommand::new(bin);
+    cmd.args(args);
+    if SETTINGS.get::<CmdLineSettings>().no_shell {
+        if let Some(path) = path_with_bin_dir(bin, env::var_os("PATH")) {
+            cmd.env("PATH", path);
+        }
     }
-    cmd.arg("-c");
-    cmd.arg(&format!("{} {}", bin, args_str));
+    cmd
+}
+
+// The PATH with the directory of the binary in front, or None when it's already on it
+fn path_with_bin_dir(bin: &str, path: Option<OsString>) -> Option<OsString> {
+    let bin_dir = Path::new(bin)
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())?;
+    let mut dirs: Vec<PathBuf> = path
+        .map(|path| env::split_paths(&path).collect())
+        .unwrap_or_default();
+    if dirs.iter().any(|dir| dir == bin_dir) {
+        return None;
+    }
+    dirs.insert(0, bin_dir.to_path_buf());
+    env::join_paths(dirs).ok()
+}
+
+#[cfg(target_os = "macos")]
+fn nvim_cmd_impl(bin: &str, args: &[String]) -> TokioCommand {
+    if SETTINGS.get::<CmdLineSettings>().no_shell {
+        return direct_nvim_cmd(bin, args);
+    }
+
+    let (shell, login) = platform_shell();
+    let mut invocation = shell_invocation(shell, login, shell_command_line(bin, args));
+    let mut cmd = TokioCommand::new(invocation.remove(0));
+    cmd.args(invocation);
     cmd
 }
 
 #[cfg(not(target_os = "macos"))]
 fn nvim_cmd_impl(bin: &str, args: &[String]) -> TokioCommand {
-    if cfg!(target_os = "windows") && SETTINGS.get::<CmdLineSettings>().wsl {
+    let settings = SETTINGS.get::<CmdLineSettings>();
+    if cfg!(target_os = "windows") && settings.wsl && settings.no_shell {
         let mut cmd = TokioCommand::new("wsl");
-        cmd.args(["$SHELL", "-lc", &format!("{} {}", bin, args.join(" "))]);
+        cmd.args(wsl_distro_args());
+        cmd.arg("--exec");
+        cmd.arg(bin);
+        cmd.args(args);
         cmd
-    } else {
-        let mut cmd = TokioCommand::new(bin);
+    } else if cfg!(target_os = "windows") && settings.wsl {
+        let (shell, login) = platform_shell();
+        let mut cmd = TokioCommand::new("wsl");
+        cmd.args(wsl_distro_args());
+        cmd.args(shell_invocation(
+            shell,
+            login,
+            shell_command_line(bin, args),
+        ));
+        cmd
+    } else if use_host_spawn() {
+        let mut cmd = TokioCommand::new("flatpak-spawn");
+        // --watch-bus makes sure nvim goes away together with the sandbox
+        cmd.args(["--host", "--watch-bus"]);
+        if let Ok(current_dir) = env::current_dir() {
+            cmd.arg(format!("--directory={}", current_dir.to_string_lossy()));
+        }
+        cmd.arg(bin);
         cmd.args(args);
         cmd
+    } else {
+        direct_nvim_cmd(bin, args)
     }
 }
 
 fn build_nvim_cmd_with_args(bin: &str) -> TokioCommand {
+    // Already contains the layout flag (-p, -o or -O), the files and their +line or +/pattern
+    // positioning, followed by whatever was given after --
     let mut args = vec!["--embed".to_string()];
+    // Before the files, which are opened on top of the session
+    args.extend(restore_session_args());
     args.extend(SETTINGS.get::<CmdLineSettings>().neovim_args);
     nvim_cmd_impl(bin, &args)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::iter;
+
+    use super::*;
+
+    #[test]
+    fn test_shell_command_line_quotes_arguments() {
+        let args: Vec<String> = vec!["--embed", "-u", "NONE", "-c", "echo 'hi there'", "$HOME"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let command_line = shell_command_line("nvim", &args);
+        assert_eq!(
+            shlex::split(&command_line),
+            Some(iter::once("nvim".to_string()).chain(args).collect())
+        );
+    }
+
+    #[test]
+    fn test_configured_candidates_are_split_like_the_path() {
+        let candidates = env::join_paths(["/opt/nvim/bin/nvim", "/usr/bin/nvim"]).ok();
+        assert_eq!(
+            configured_candidates(candidates),
+            vec![
+                PathBuf::from("/opt/nvim/bin/nvim"),
+                PathBuf::from("/usr/bin/nvim")
+            ]
+        );
+        assert!(configured_candidates(None).is_empty());
+    }
+
+    #[test]
+    fn test_path_candidates_picks_the_first_that_exists() {
+        let candidates = vec![
+            PathBuf::from("/missing/nvim"),
+            PathBuf::from("/first/nvim"),
+            PathBuf::from("/second/nvim"),
+        ];
+        assert_eq!(
+            path_candidates(candidates.clone(), |candidate| candidate != "/missing/nvim"),
+            Some("/first/nvim".to_string())
+        );
+        assert_eq!(path_candidates(candidates, |_| false), None);
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn test_well_known_nvim_locations_prefer_the_home_directory() {
+        assert_eq!(
+            well_known_nvim_locations(Some(PathBuf::from("/home/me"))),
+            [
+                "/home/me/.local/share/bob/nvim-bin/nvim",
+                "/home/me/.local/share/nvim/mason/bin/nvim",
+                "/home/me/.local/bin/nvim",
+                "/opt/homebrew/bin/nvim",
+                "/usr/local/bin/nvim",
+                "/home/linuxbrew/.linuxbrew/bin/nvim",
+                "/snap/bin/nvim",
+            ]
+            .map(PathBuf::from)
+        );
+        assert_eq!(
+            well_known_nvim_locations(None).first(),
+            Some(&PathBuf::from("/opt/homebrew/bin/nvim"))
+        );
+    }
+
+    #[test]
+    fn test_path_with_bin_dir() {
+        let path = env::join_paths(["/usr/bin", "/bin"]).unwrap();
+        assert_eq!(
+            path_with_bin_dir("/opt/homebrew/bin/nvim", Some(path.clone())),
+            env::join_paths(["/opt/homebrew/bin", "/usr/bin", "/bin"]).ok()
+        );
+        assert_eq!(path_with_bin_dir("/usr/bin/nvim", Some(path)), None);
+        assert_eq!(path_with_bin_dir("nvim", None), None);
+    }
+
+    #[test]
+    fn test_shell_invocation_login_is_optional() {
+        assert_eq!(
+            shell_invocation("$SHELL".to_string(), true, "nvim --embed".to_string()),
+            vec!["$SHELL", "-l", "-c", "nvim --embed"]
+        );
+        assert_eq!(
+            shell_invocation("fish".to_string(), false, "nvim --embed".to_string()),
+            vec!["fish", "-c", "nvim --embed"]
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! Records the redraw notifications of Neovim along with when they arrived for `--record-events`,
+//! and plays them back at the same pace instead of starting Neovim for `--replay-events`. The file
+//! is a sequence of msgpack values, each a `[microseconds since the start, arguments]` pair with
+//! the arguments of one redraw notification.
+
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Cursor, Write},
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{error, info};
+use parking_lot::Mutex;
+use rmpv::{decode::read_value, encode::write_value, Value};
+
+use crate::{
+    bridge::{parse_redraw_event, ParallelCommand, UiCommand},
+    editor::EditorCommand,
+    event_aggregator::EVENT_AGGREGATOR,
+    running_tracker::*,
+};
+
+pub struct EventRecorder {
+    started_at: Instant,
+    file: Mutex<BufWriter<File>>,
+}
+
+impl EventRecorder {
+    pub fn create(path: &str) -> Option<Self> {
+        match File::create(path) {
+            Ok(file) => {
+                info!("Recording the redraw events to {}", path);
+                Some(Self {
+                    started_at: Instant::now(),
+                    file: Mutex::new(BufWriter::new(file)),
+                })
+            }
+            Err(error) => {
+                error!("Could not record the redraw events to {}: {}", path, error);
+                None
+            }
+        }
+    }
+
+    // Flushed right away, since Neovide may quit without dropping the recorder
+    pub fn record(&self, arguments: &[Value]) {
+        let elapsed = self.started_at.elapsed().as_micros() as u64;
+        let record = Value::Array(vec![Value::from(elapsed), Value::Array(arguments.to_vec())]);
+        let mut file = self.file.lock();
+        let result = write_value(&mut *file, &record)
+            .map_err(|error| error.to_string())
+            .and_then(|_| file.flush().map_err(|error| error.to_string()));
+        if let Err(error) = result {
+            error!("Could not record the redraw events: {}", error);
+        }
+    }
+}
+
+/// Plays back the recorded events instead of starting Neovim. The window stays open afterwards,
+/// so that the result can be looked at.
+pub fn start_event_replay(path: String) {
+    thread::spawn(move || match read_records(&path) {
+        Ok(records) => replay(&path, records),
+        Err(error) => {
+            error!("{}", error);
+            RUNNING_TRACKER.quit("the events could not be replayed");
+        }
+    });
+
+    // Nothing handles the input without Neovim, except for closing the window
+    thread::spawn(|| {
+        let mut ui_command_receiver = EVENT_AGGREGATOR.register_event::<UiCommand>();
+        while let Some(ui_command) = ui_command_receiver.blocking_recv() {
+            if let UiCommand::Parallel(ParallelCommand::Quit | ParallelCommand::Detach) = ui_command
+            {
+                RUNNING_TRACKER.quit("window closed during the replay");
+            }
+        }
+    });
+}
+
+fn replay(path: &str, records: Vec<(Duration, Vec<Value>)>) {
+    let started_at = Instant::now();
+    let mut event_count = 0;
+    for (time, arguments) in records {
+        if let Some(wait) = time.checked_sub(started_at.elapsed()) {
+            thread::sleep(wait);
+        }
+        for events in arguments {
+            match parse_redraw_event(events) {
+                Ok(parsed_events) => {
+                    event_count += parsed_events.len();
+                    for parsed_event in parsed_events {
+                        EVENT_AGGREGATOR.send(EditorCommand::NeovimRedrawEvent(parsed_event));
+                    }
+                }
+                Err(error) => error!("Could not parse a replayed event: {}", error),
+            }
+        }
+    }
+    info!(
+        "Replayed {} events from {} in {}ms",
+        event_count,
+        path,
+        started_at.elapsed().as_millis()
+    );
+}
+
+fn read_records(path: &str) -> Result<Vec<(Duration, Vec<Value>)>, String> {
+    let contents = fs::read(path).map_err(|error| format!("Could not read {path}: {error}"))?;
+    parse_records(&contents).map_err(|error| format!("Could not read {path}: {error}"))
+}
+
+fn parse_records(contents: &[u8]) -> Result<Vec<(Duration, Vec<Value>)>, String> {
+    let mut cursor = Cursor::new(contents);
+    let mut records = Vec::new();
+    while (cursor.position() as usize) < contents.len() {
+        let record = read_value(&mut cursor).map_err(|error| error.to_string())?;
+        let record = match record {
+            Value::Array(record) => record,
+            _ => return Err(format!("Invalid record {record}")),
+        };
+        match record.as_slice() {
+            [time, Value::Array(arguments)] => {
+                let time = time
+                    .as_u64()
+                    .ok_or_else(|| format!("Invalid record time {time}"))?;
+                records.push((Duration::from_micros(time), arguments.clone()));
+            }
+            _ => return Err(format!("Invalid record {record:?}")),
+        }
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_what_was_recorded() {
+        let arguments = vec![Value::Array(vec![
+            Value::from("grid_cursor_goto"),
+            Value::Array(vec![Value::from(1), Value::from(2), Value::from(3)]),
+        ])];
+        let mut contents = Vec::new();
+        for time in [0u64, 1500] {
+            let record = Value::Array(vec![Value::from(time), Value::Array(arguments.clone())]);
+            write_value(&mut contents, &record).unwrap();
+        }
+
+        let records = parse_records(&contents).unwrap();
+        assert_eq!(
+            records,
+            vec![
+                (Duration::ZERO, arguments.clone()),
+                (Duration::from_micros(1500), arguments)
+            ]
+        );
+    }
+
+    #[test]
+    fn truncated_recordings_are_an_error() {
+        let mut contents = Vec::new();
+        let record = Value::Array(vec![Value::from(0), Value::Array(vec![])]);
+        write_value(&mut contents, &record).unwrap();
+        contents.push(0x92);
+        assert!(parse_records(&contents).is_err());
+    }
+}
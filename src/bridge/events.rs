@@ -119,7 +119,7 @@ pub enum WindowAnchor {
     SouthEast,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum EditorMode {
     // The set of modes reported will change in new versions of Nvim, for
     // instance more sub-modes and temporary states might be represented as
@@ -139,6 +139,9 @@ pub enum RedrawEvent {
     SetTitle {
         title: String,
     },
+    SetIcon {
+        icon: String,
+    },
     ModeInfoSet {
         cursor_modes: Vec<CursorMode>,
     },
@@ -166,6 +169,10 @@ pub enum RedrawEvent {
         id: u64,
         style: Style,
     },
+    HighlightGroupSet {
+        name: String,
+        id: u64,
+    },
     GridLine {
         grid: u64,
         row: u64,
@@ -194,6 +201,7 @@ pub enum RedrawEvent {
     },
     WindowPosition {
         grid: u64,
+        window: u64,
         start_row: u64,
         start_column: u64,
         width: u64,
@@ -201,6 +209,7 @@ pub enum RedrawEvent {
     },
     WindowFloatPosition {
         grid: u64,
+        window: u64,
         anchor: WindowAnchor,
         anchor_grid: u64,
         anchor_row: f64,
@@ -275,6 +284,47 @@ pub enum RedrawEvent {
     MessageHistoryShow {
         entries: Vec<(MessageKind, StyledContent)>,
     },
+    PopupMenuShow {
+        items: Vec<PopupMenuItem>,
+        selected: Option<u64>,
+        row: u64,
+        column: u64,
+        grid: u64,
+    },
+    PopupMenuSelect {
+        selected: Option<u64>,
+    },
+    PopupMenuHide,
+    TabLineUpdate {
+        current: u64,
+        tabs: Vec<TabLineTab>,
+    },
+}
+
+/// A tab of the tabline, sent while `ext_tabline` is enabled.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TabLineTab {
+    pub tab: u64,
+    pub name: String,
+}
+
+/// How a plugin wants a tab of the tabline shown, set with `neovide.set_tabline`. A missing title
+/// keeps the name Neovim gave the tab.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TabContents {
+    pub tab: u64,
+    pub title: Option<String>,
+    pub icon: String,
+    pub modified: bool,
+}
+
+/// An item of the popup menu, sent while `ext_popupmenu` is enabled.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PopupMenuItem {
+    pub word: String,
+    pub kind: String,
+    pub menu: String,
+    pub info: String,
 }
 
 fn unpack_color(packed_color: u64) -> Color4f {
@@ -290,6 +340,26 @@ fn unpack_color(packed_color: u64) -> Color4f {
     }
 }
 
+/// The background a window fills its grid with, reported by the autocommands registered in
+/// setup.rs.
+#[derive(Clone, Debug)]
+pub struct WindowBackground {
+    pub window: u64,
+    pub color: Option<Color4f>,
+    pub is_current: bool,
+}
+
+/// The search pattern with the index of the match under the cursor and the number of matches,
+/// reported by the autocommands registered in setup.rs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchCount {
+    pub pattern: String,
+    pub current: u64,
+    pub total: u64,
+    /// Counting stopped at the limit or timed out, so there are more than `total` matches
+    pub incomplete: bool,
+}
+
 fn extract_values<const REQ: usize>(values: Vec<Value>) -> Result<[Value; REQ]> {
     if REQ > values.len() {
         Err(ParseError::Format(format!("{values:?}")))
@@ -361,6 +431,18 @@ fn parse_bool(bool_value: Value) -> Result<bool> {
     bool_value.try_into().map_err(ParseError::Bool)
 }
 
+// Window and tabpage handles are sent as msgpack extension values wrapping the handle number
+fn parse_handle(handle_value: Value) -> Result<u64> {
+    if let Value::Ext(_, data) = &handle_value {
+        let handle = rmpv::decode::read_value(&mut data.as_slice()).ok();
+        handle
+            .and_then(|handle| handle.as_u64())
+            .ok_or(ParseError::U64(handle_value))
+    } else {
+        parse_u64(handle_value)
+    }
+}
+
 fn parse_set_title(set_title_arguments: Vec<Value>) -> Result<RedrawEvent> {
     let [title] = extract_values(set_title_arguments)?;
 
@@ -369,6 +451,14 @@ fn parse_set_title(set_title_arguments: Vec<Value>) -> Result<RedrawEvent> {
     })
 }
 
+fn parse_set_icon(set_icon_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let [icon] = extract_values(set_icon_arguments)?;
+
+    Ok(RedrawEvent::SetIcon {
+        icon: parse_string(icon)?,
+    })
+}
+
 fn parse_mode_info_set(mode_info_set_arguments: Vec<Value>) -> Result<RedrawEvent> {
     let [_cursor_style_enabled, mode_info] = extract_values(mode_info_set_arguments)?;
 
@@ -523,15 +613,42 @@ fn parse_style(style_map: Value) -> Result<Style> {
 }
 
 fn parse_hl_attr_define(hl_attr_define_arguments: Vec<Value>) -> Result<RedrawEvent> {
-    let [id, attributes, _terminal_attributes, _info] = extract_values(hl_attr_define_arguments)?;
+    let [id, attributes, _terminal_attributes, info] = extract_values(hl_attr_define_arguments)?;
 
-    let style = parse_style(attributes)?;
+    let mut style = parse_style(attributes)?;
+    style.highlight_groups = parse_highlight_groups(info);
     Ok(RedrawEvent::HighlightAttributesDefine {
         id: parse_u64(id)?,
         style,
     })
 }
 
+// The info of ext_hlstate, a list of the highlights the attributes were combined from
+fn parse_highlight_groups(info: Value) -> Vec<String> {
+    match info {
+        Value::Array(items) => items
+            .iter()
+            .filter_map(|item| {
+                item.as_map()?
+                    .iter()
+                    .find(|(key, _)| key.as_str() == Some("hi_name"))
+                    .and_then(|(_, name)| name.as_str())
+                    .map(String::from)
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn parse_hl_group_set(hl_group_set_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let [name, id] = extract_values(hl_group_set_arguments)?;
+
+    Ok(RedrawEvent::HighlightGroupSet {
+        name: parse_string(name)?,
+        id: parse_u64(id)?,
+    })
+}
+
 fn parse_grid_line_cell(grid_line_cell: Value) -> Result<GridLineCell> {
     fn take_value(val: &mut Value) -> Value {
         std::mem::replace(val, Value::Nil)
@@ -616,11 +733,11 @@ fn parse_grid_scroll(grid_scroll_arguments: Vec<Value>) -> Result<RedrawEvent> {
 }
 
 fn parse_win_pos(win_pos_arguments: Vec<Value>) -> Result<RedrawEvent> {
-    let [grid, _window, start_row, start_column, width, height] =
-        extract_values(win_pos_arguments)?;
+    let [grid, window, start_row, start_column, width, height] = extract_values(win_pos_arguments)?;
 
     Ok(RedrawEvent::WindowPosition {
         grid: parse_u64(grid)?,
+        window: parse_handle(window)?,
         start_row: parse_u64(start_row)?,
         start_column: parse_u64(start_column)?,
         width: parse_u64(width)?,
@@ -640,11 +757,12 @@ fn parse_window_anchor(value: Value) -> Result<WindowAnchor> {
 }
 
 fn parse_win_float_pos(win_float_pos_arguments: Vec<Value>) -> Result<RedrawEvent> {
-    let ([grid, _window, anchor, anchor_grid, anchor_row, anchor_column, focusable], [sort_order]) =
+    let ([grid, window, anchor, anchor_grid, anchor_row, anchor_column, focusable], [sort_order]) =
         extract_values_with_optional(win_float_pos_arguments)?;
 
     Ok(RedrawEvent::WindowFloatPosition {
         grid: parse_u64(grid)?,
+        window: parse_handle(window)?,
         anchor: parse_window_anchor(anchor)?,
         anchor_grid: parse_u64(anchor_grid)?,
         anchor_row: parse_f64(anchor_row)?,
@@ -823,6 +941,164 @@ fn parse_msg_history_show(msg_history_show_arguments: Vec<Value>) -> Result<Redr
     })
 }
 
+fn parse_popupmenu_item(item: Value) -> Result<PopupMenuItem> {
+    let [word, kind, menu, info] = extract_values(parse_array(item)?)?;
+
+    Ok(PopupMenuItem {
+        word: parse_string(word)?,
+        kind: parse_string(kind)?,
+        menu: parse_string(menu)?,
+        info: parse_string(info)?,
+    })
+}
+
+// Nothing is selected when the index is -1
+fn parse_popupmenu_selected(selected: Value) -> Result<Option<u64>> {
+    Ok(u64::try_from(parse_i64(selected)?).ok())
+}
+
+fn parse_popupmenu_show(popupmenu_show_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let [items, selected, row, column, grid] = extract_values(popupmenu_show_arguments)?;
+
+    Ok(RedrawEvent::PopupMenuShow {
+        items: parse_array(items)?
+            .into_iter()
+            .map(parse_popupmenu_item)
+            .collect::<Result<_>>()?,
+        selected: parse_popupmenu_selected(selected)?,
+        row: parse_u64(row)?,
+        column: parse_u64(column)?,
+        grid: parse_u64(grid)?,
+    })
+}
+
+fn parse_popupmenu_select(popupmenu_select_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let [selected] = extract_values(popupmenu_select_arguments)?;
+
+    Ok(RedrawEvent::PopupMenuSelect {
+        selected: parse_popupmenu_selected(selected)?,
+    })
+}
+
+pub fn parse_window_backgrounds(backgrounds_value: Value) -> Result<Vec<WindowBackground>> {
+    parse_array(backgrounds_value)?
+        .into_iter()
+        .map(|background_value| {
+            let mut window = None;
+            let mut color = None;
+            let mut is_current = false;
+
+            for (name, value) in parse_map(background_value)? {
+                match (parse_string(name)?.as_str(), value) {
+                    ("window", value) => window = Some(parse_u64(value)?),
+                    ("background", value) => color = Some(unpack_color(parse_u64(value)?)),
+                    ("current", value) => is_current = parse_bool(value)?,
+                    _ => {}
+                }
+            }
+
+            Ok(WindowBackground {
+                window: window.ok_or_else(|| ParseError::Format("missing window".to_owned()))?,
+                color,
+                is_current,
+            })
+        })
+        .collect()
+}
+
+fn parse_tabline_tab(tab_value: Value) -> Result<TabLineTab> {
+    let mut tab = None;
+    let mut name = String::new();
+
+    for (key, value) in parse_map(tab_value)? {
+        match (parse_string(key)?.as_str(), value) {
+            ("tab", value) => tab = Some(parse_handle(value)?),
+            ("name", value) => name = parse_string(value)?,
+            _ => {}
+        }
+    }
+
+    Ok(TabLineTab {
+        tab: tab.ok_or_else(|| ParseError::Format("missing tab".to_owned()))?,
+        name,
+    })
+}
+
+// Newer versions of Neovim also send the current buffer and the buffers, which aren't shown
+fn parse_tabline_update(tabline_update_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let [current, tabs] = extract_values(tabline_update_arguments)?;
+
+    Ok(RedrawEvent::TabLineUpdate {
+        current: parse_handle(current)?,
+        tabs: parse_array(tabs)?
+            .into_iter()
+            .map(parse_tabline_tab)
+            .collect::<Result<_>>()?,
+    })
+}
+
+pub fn parse_search_count(count_value: Value) -> Result<Option<SearchCount>> {
+    if count_value.is_nil() {
+        return Ok(None);
+    }
+
+    let mut count = SearchCount {
+        pattern: String::new(),
+        current: 0,
+        total: 0,
+        incomplete: false,
+    };
+
+    for (name, value) in parse_map(count_value)? {
+        match (parse_string(name)?.as_str(), value) {
+            ("pattern", value) => count.pattern = parse_string(value)?,
+            ("current", value) => count.current = parse_u64(value)?,
+            ("total", value) => count.total = parse_u64(value)?,
+            ("incomplete", value) => count.incomplete = parse_bool(value)?,
+            _ => {}
+        }
+    }
+
+    Ok(Some(count))
+}
+
+// Lua sends empty tables as empty maps, and nil shows the tabs of Neovim again
+pub fn parse_tab_contents(contents_value: Value) -> Result<Option<Vec<TabContents>>> {
+    match contents_value {
+        Value::Nil => return Ok(None),
+        Value::Map(map) if map.is_empty() => return Ok(Some(Vec::new())),
+        _ => {}
+    }
+
+    parse_array(contents_value)?
+        .into_iter()
+        .map(|tab_value| {
+            let mut tab = None;
+            let mut title = None;
+            let mut icon = String::new();
+            let mut modified = false;
+
+            for (name, value) in parse_map(tab_value)? {
+                match (parse_string(name)?.as_str(), value) {
+                    ("tab", value) => tab = Some(parse_handle(value)?),
+                    ("title", value) => title = Some(parse_string(value)?),
+                    ("icon", value) => icon = parse_string(value)?,
+                    ("modified", value) => modified = parse_bool(value)?,
+                    _ => {}
+                }
+            }
+
+            Ok(TabContents {
+                tab: tab.ok_or_else(|| ParseError::Format("missing tab".to_owned()))?,
+                title,
+                icon,
+                modified,
+            })
+        })
+        .collect::<Result<_>>()
+        .map(Some)
+}
+
 pub fn parse_redraw_event(event_value: Value) -> Result<Vec<RedrawEvent>> {
     let mut event_contents = parse_array(event_value)?.into_iter();
     let event_name = event_contents
@@ -838,7 +1114,7 @@ pub fn parse_redraw_event(event_value: Value) -> Result<Vec<RedrawEvent>> {
         let event_parameters_copy = event_parameters.clone();
         let possible_parsed_event = match event_name.as_str() {
             "set_title" => Some(parse_set_title(event_parameters)),
-            "set_icon" => None, // Ignore set icon for now
+            "set_icon" => Some(parse_set_icon(event_parameters)),
             "mode_info_set" => Some(parse_mode_info_set(event_parameters)),
             "option_set" => Some(parse_option_set(event_parameters)),
             "mode_change" => Some(parse_mode_change(event_parameters)),
@@ -850,6 +1126,7 @@ pub fn parse_redraw_event(event_value: Value) -> Result<Vec<RedrawEvent>> {
             "grid_resize" => Some(parse_grid_resize(event_parameters)),
             "default_colors_set" => Some(parse_default_colors(event_parameters)),
             "hl_attr_define" => Some(parse_hl_attr_define(event_parameters)),
+            "hl_group_set" => Some(parse_hl_group_set(event_parameters)),
             "grid_line" => Some(parse_grid_line(event_parameters)),
             "grid_clear" => Some(parse_grid_clear(event_parameters)),
             "grid_destroy" => Some(parse_grid_destroy(event_parameters)),
@@ -875,6 +1152,10 @@ pub fn parse_redraw_event(event_value: Value) -> Result<Vec<RedrawEvent>> {
             "msg_showcmd" => Some(parse_msg_showcmd(event_parameters)),
             "msg_ruler" => Some(parse_msg_ruler(event_parameters)),
             "msg_history_show" => Some(parse_msg_history_show(event_parameters)),
+            "popupmenu_show" => Some(parse_popupmenu_show(event_parameters)),
+            "popupmenu_select" => Some(parse_popupmenu_select(event_parameters)),
+            "popupmenu_hide" => Some(Ok(RedrawEvent::PopupMenuHide)),
+            "tabline_update" => Some(parse_tabline_update(event_parameters)),
             _ => None,
         };
 
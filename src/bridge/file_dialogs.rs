@@ -0,0 +1,100 @@
+use std::path::PathBuf;
+
+use rfd::AsyncFileDialog;
+use rmpv::Value;
+
+use crate::bridge::lua_options::option;
+
+fn option_str<'a>(options: &'a Value, key: &str) -> Option<&'a str> {
+    option(options, key).and_then(|value| value.as_str())
+}
+
+fn option_bool(options: &Value, key: &str) -> bool {
+    option(options, key)
+        .and_then(|value| value.as_bool())
+        .unwrap_or(false)
+}
+
+fn build_dialog(options: &Value) -> AsyncFileDialog {
+    let mut dialog = AsyncFileDialog::new();
+
+    if let Some(title) = option_str(options, "title") {
+        dialog = dialog.set_title(title);
+    }
+    if let Some(directory) = option_str(options, "directory") {
+        dialog = dialog.set_directory(directory);
+    }
+    if let Some(file_name) = option_str(options, "file_name") {
+        dialog = dialog.set_file_name(file_name);
+    }
+
+    // filters = { { name = "Rust", extensions = { "rs", "toml" } } }
+    for filter in option(options, "filters")
+        .and_then(|filters| filters.as_array())
+        .into_iter()
+        .flatten()
+    {
+        let name = option_str(filter, "name").unwrap_or_default();
+        let extensions: Vec<&str> = option(filter, "extensions")
+            .and_then(|extensions| extensions.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|extension| extension.as_str())
+            .collect();
+        dialog = dialog.add_filter(name, &extensions);
+    }
+
+    dialog
+}
+
+fn path_value(path: PathBuf) -> Value {
+    Value::from(path.to_string_lossy().to_string())
+}
+
+/// Shows a native dialog for picking files or folders and returns the selected paths, which is
+/// an empty list when it was cancelled.
+pub async fn open_file_dialog(options: &Value) -> Value {
+    let dialog = build_dialog(options);
+    let folder = option_bool(options, "folder");
+    let multiple = option_bool(options, "multiple");
+
+    let paths: Vec<PathBuf> = match (folder, multiple) {
+        (true, true) => dialog
+            .pick_folders()
+            .await
+            .into_iter()
+            .flatten()
+            .map(|handle| handle.path().to_path_buf())
+            .collect(),
+        (true, false) => dialog
+            .pick_folder()
+            .await
+            .map(|handle| handle.path().to_path_buf())
+            .into_iter()
+            .collect(),
+        (false, true) => dialog
+            .pick_files()
+            .await
+            .into_iter()
+            .flatten()
+            .map(|handle| handle.path().to_path_buf())
+            .collect(),
+        (false, false) => dialog
+            .pick_file()
+            .await
+            .map(|handle| handle.path().to_path_buf())
+            .into_iter()
+            .collect(),
+    };
+
+    Value::from(paths.into_iter().map(path_value).collect::<Vec<_>>())
+}
+
+/// Shows a native save dialog and returns the chosen path, or nil when it was cancelled.
+pub async fn save_file_dialog(options: &Value) -> Value {
+    build_dialog(options)
+        .save_file()
+        .await
+        .map(|handle| path_value(handle.path().to_path_buf()))
+        .unwrap_or(Value::Nil)
+}
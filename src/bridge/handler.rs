@@ -1,26 +1,52 @@
+use std::sync::Arc;
+
 use async_trait::async_trait;
-use log::trace;
+use log::{error, trace};
 use nvim_rs::{Handler, Neovim};
 use rmpv::Value;
 
-use crate::bridge::clipboard::{get_clipboard_contents, set_clipboard_contents};
-#[cfg(windows)]
+use crate::bridge::clipboard::{
+    get_clipboard_chunk, get_clipboard_contents, set_clipboard_contents,
+};
+use crate::bridge::color_picker::pick_color;
+use crate::bridge::event_recording::EventRecorder;
+use crate::bridge::file_dialogs::{open_file_dialog, save_file_dialog};
+use crate::bridge::images::{place_image, remove_image};
+use crate::bridge::notifications::show_notification;
+use crate::bridge::setup_wizard::save_setup_wizard_choices;
 use crate::bridge::ui_commands::{ParallelCommand, UiCommand};
 use crate::{
-    bridge::{events::parse_redraw_event, NeovimWriter},
+    bridge::{
+        events::{
+            parse_redraw_event, parse_search_count, parse_tab_contents, parse_window_backgrounds,
+        },
+        NeovimWriter,
+    },
+    cmd_line::CmdLineSettings,
     editor::EditorCommand,
     error_handling::ResultPanicExplanation,
     event_aggregator::EVENT_AGGREGATOR,
+    instances::{cycle_instances, spawn_instance},
+    latency_tracker::LATENCY_TRACKER,
+    redraw_scheduler::REDRAW_SCHEDULER,
     running_tracker::*,
     settings::SETTINGS,
+    window::WindowCommand,
 };
 
 #[derive(Clone)]
-pub struct NeovimHandler {}
+pub struct NeovimHandler {
+    event_recorder: Option<Arc<EventRecorder>>,
+}
 
 impl NeovimHandler {
     pub fn new() -> Self {
-        Self {}
+        let event_recorder = SETTINGS
+            .get::<CmdLineSettings>()
+            .record_events
+            .and_then(|path| EventRecorder::create(&path))
+            .map(Arc::new);
+        Self { event_recorder }
     }
 }
 
@@ -51,8 +77,23 @@ impl Handler for NeovimHandler {
                 get_clipboard_contents(endline_type.as_deref())
                     .map_err(|_| Value::from("cannot get clipboard contents"))
             }
-            "neovide.set_clipboard" => set_clipboard_contents(&arguments[0])
-                .map_err(|_| Value::from("cannot set clipboard contents")),
+            "neovide.get_clipboard_chunk" => {
+                get_clipboard_chunk(arguments.first().unwrap_or(&Value::Nil))
+                    .map_err(|_| Value::from("cannot get clipboard contents"))
+            }
+            "neovide.set_clipboard" => {
+                let more = arguments.get(1).and_then(Value::as_bool).unwrap_or(false);
+                set_clipboard_contents(arguments.first().unwrap_or(&Value::Nil), more)
+                    .map_err(|_| Value::from("cannot set clipboard contents"))
+            }
+            "neovide.open_file_dialog" => {
+                Ok(open_file_dialog(arguments.first().unwrap_or(&Value::Nil)).await)
+            }
+            "neovide.save_file_dialog" => {
+                Ok(save_file_dialog(arguments.first().unwrap_or(&Value::Nil)).await)
+            }
+            "neovide.pick_color" => Ok(pick_color(arguments.first().unwrap_or(&Value::Nil)).await),
+            "neovide.place_image" => place_image(arguments.first().unwrap_or(&Value::Nil)).await,
             _ => Ok(Value::from("rpcrequest not handled")),
         }
     }
@@ -67,6 +108,9 @@ impl Handler for NeovimHandler {
 
         match event_name.as_ref() {
             "redraw" => {
+                if let Some(event_recorder) = &self.event_recorder {
+                    event_recorder.record(&arguments);
+                }
                 for events in arguments {
                     let parsed_events = parse_redraw_event(events)
                         .unwrap_or_explained_panic("Could not parse event from neovim");
@@ -76,6 +120,126 @@ impl Handler for NeovimHandler {
                     }
                 }
             }
+            "neovide.window_backgrounds" => {
+                match parse_window_backgrounds(arguments.first().cloned().unwrap_or(Value::Nil)) {
+                    Ok(backgrounds) => {
+                        EVENT_AGGREGATOR.send(EditorCommand::SetWindowBackgrounds(backgrounds))
+                    }
+                    Err(error) => error!("Could not parse window backgrounds: {}", error),
+                }
+            }
+            "neovide.search_count" => {
+                match parse_search_count(arguments.first().cloned().unwrap_or(Value::Nil)) {
+                    Ok(count) => EVENT_AGGREGATOR.send(EditorCommand::SetSearchCount(count)),
+                    Err(error) => error!("Could not parse search count: {}", error),
+                }
+            }
+            "neovide.set_tabline" => {
+                match parse_tab_contents(arguments.first().cloned().unwrap_or(Value::Nil)) {
+                    Ok(contents) => EVENT_AGGREGATOR.send(EditorCommand::SetTabContents(contents)),
+                    Err(error) => error!("Could not parse the tabline contents: {}", error),
+                }
+            }
+            "neovide.mode_badge" => {
+                // Empty strings mean no recording or operator
+                let state = |index: usize| {
+                    arguments
+                        .get(index)
+                        .and_then(|value| value.as_str())
+                        .filter(|value| !value.is_empty())
+                        .map(String::from)
+                };
+                EVENT_AGGREGATOR.send(EditorCommand::SetModeBadgeState {
+                    recording: state(0),
+                    operator: state(1),
+                });
+            }
+            "neovide.notify" => {
+                let text = |index: usize| {
+                    arguments
+                        .get(index)
+                        .and_then(|text| text.as_str())
+                        .unwrap_or_default()
+                        .to_string()
+                };
+                show_notification(
+                    text(0),
+                    text(1),
+                    arguments.get(2).cloned().unwrap_or(Value::Nil),
+                );
+            }
+            "neovide.remove_image" => remove_image(arguments.first()),
+            "neovide.set_progress" => {
+                // Anything that isn't a number, like nil, removes the progress bar
+                let progress = arguments
+                    .first()
+                    .and_then(|progress| {
+                        progress
+                            .as_f64()
+                            .or_else(|| progress.as_i64().map(|progress| progress as f64))
+                    })
+                    .filter(|progress| *progress >= 0.0);
+                EVENT_AGGREGATOR.send(WindowCommand::SetProgress(progress));
+            }
+            "neovide.set_context_menu_items" => {
+                // A list of { label = ..., command = ... } tables
+                let items = arguments
+                    .first()
+                    .and_then(|items| items.as_array())
+                    .map(|items| {
+                        items
+                            .iter()
+                            .filter_map(|item| {
+                                let item = item.as_map()?;
+                                let field = |key: &str| {
+                                    item.iter()
+                                        .find(|(name, _)| name.as_str() == Some(key))
+                                        .and_then(|(_, value)| value.as_str())
+                                        .map(String::from)
+                                };
+                                Some((field("label")?, field("command")?))
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                EVENT_AGGREGATOR.send(WindowCommand::SetContextMenuItems(items));
+            }
+            "neovide.buffer_info" => {
+                if let [filename, cwd, modified] = arguments.as_slice() {
+                    EVENT_AGGREGATOR.send(WindowCommand::BufferInfoChanged {
+                        filename: filename.as_str().unwrap_or_default().to_string(),
+                        cwd: cwd.as_str().unwrap_or_default().to_string(),
+                        modified: modified.as_bool().unwrap_or(false),
+                    });
+                }
+            }
+            "neovide.preview" => {
+                if let [filetype, lines] = arguments.as_slice() {
+                    let lines = lines
+                        .as_array()
+                        .map(|lines| {
+                            lines
+                                .iter()
+                                .map(|line| line.as_str().unwrap_or_default().to_string())
+                                .collect()
+                        })
+                        .unwrap_or_default();
+                    EVENT_AGGREGATOR.send(WindowCommand::PreviewContent {
+                        filetype: filetype.as_str().unwrap_or_default().to_string(),
+                        lines,
+                    });
+                }
+            }
+            "neovide.preview_top_line" => {
+                if let Some(line) = arguments.first().and_then(|line| line.as_u64()) {
+                    EVENT_AGGREGATOR.send(WindowCommand::PreviewTopLine(line));
+                }
+            }
+            "neovide.background" => {
+                if let Some(background) = arguments.first().and_then(|value| value.as_str()) {
+                    EVENT_AGGREGATOR.send(WindowCommand::BackgroundChanged(background.to_string()));
+                }
+            }
             "setting_changed" => {
                 SETTINGS.handle_changed_notification(arguments);
             }
@@ -83,7 +247,68 @@ impl Handler for NeovimHandler {
                 let error_code = arguments[0]
                     .as_i64()
                     .expect("Could not parse error code from neovim");
-                RUNNING_TRACKER.quit_with_code(error_code as i32, "Quit from neovim");
+                // The bridge quits once the process has actually exited
+                RUNNING_TRACKER.set_exit_code(error_code as i32);
+            }
+            "neovide.record_start" => {
+                let path = arguments
+                    .first()
+                    .and_then(|path| path.as_str())
+                    .unwrap_or_default();
+                EVENT_AGGREGATOR.send(WindowCommand::StartRecording(path.to_string()));
+            }
+            "neovide.dump_frame" => {
+                let path = arguments
+                    .first()
+                    .and_then(|path| path.as_str())
+                    .unwrap_or_default();
+                EVENT_AGGREGATOR.send(EditorCommand::DumpFrame(path.to_string()));
+            }
+            "neovide.setup_wizard_done" => {
+                save_setup_wizard_choices(arguments.first().unwrap_or(&Value::Nil));
+            }
+            "neovide.new_window" => {
+                let argument = |index: usize| {
+                    arguments
+                        .get(index)
+                        .and_then(|argument| argument.as_str())
+                        .unwrap_or_default()
+                };
+                let path = Some(argument(1)).filter(|path| !path.is_empty());
+                spawn_instance(argument(0), path);
+            }
+            "neovide.cycle_window" => {
+                let direction = arguments.first().and_then(|direction| direction.as_i64());
+                cycle_instances(direction.unwrap_or(1) as i32);
+            }
+            "neovide.settings" => {
+                EVENT_AGGREGATOR.send(WindowCommand::ShowSettings);
+            }
+            "neovide.command_palette" => {
+                EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::ShowCommandPalette));
+            }
+            "neovide.quick_open" => {
+                EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::ShowQuickOpen));
+            }
+            "neovide.terminal" => {
+                EVENT_AGGREGATOR.send(WindowCommand::ToggleTerminal);
+            }
+            "neovide.stats" => {
+                let action = arguments
+                    .first()
+                    .and_then(|action| action.as_str())
+                    .unwrap_or_default();
+                EVENT_AGGREGATOR.send(WindowCommand::MemoryStats(action.trim().to_string()));
+            }
+            "neovide.record_stop" => {
+                EVENT_AGGREGATOR.send(WindowCommand::StopRecording);
+            }
+            "neovide.measure_latency" => {
+                LATENCY_TRACKER.toggle();
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
+            "neovide.detach" => {
+                EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::Detach));
             }
             #[cfg(windows)]
             "neovide.register_right_click" => {
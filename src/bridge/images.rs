@@ -0,0 +1,85 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use rmpv::Value;
+
+use crate::{
+    bridge::lua_options::option,
+    event_aggregator::EVENT_AGGREGATOR,
+    renderer::{decode_image, ImageCommand, ImagePlacement},
+    window::WindowCommand,
+};
+
+// Images placed without an id get one from this up, plugins pick theirs below it
+const AUTOMATIC_IDS_START: u64 = 1 << 32;
+static NEXT_IMAGE_ID: AtomicU64 = AtomicU64::new(AUTOMATIC_IDS_START);
+
+fn option_number(options: &Value, key: &str) -> Option<f32> {
+    option(options, key)
+        .and_then(|value| {
+            value
+                .as_f64()
+                .or_else(|| value.as_i64().map(|value| value as f64))
+        })
+        .map(|value| value as f32)
+}
+
+/// Places an image for `neovide.place_image`, read from the path or taken from the data in the
+/// options. Returns the id of the image, which replaces the image with the same id.
+pub async fn place_image(options: &Value) -> Result<Value, Value> {
+    let data = match (option(options, "path"), option(options, "data")) {
+        (Some(path), _) => {
+            let path = path.as_str().unwrap_or_default();
+            tokio::fs::read(path)
+                .await
+                .map_err(|error| Value::from(format!("cannot read {path}: {error}")))?
+        }
+        // Lua strings can hold any bytes, they aren't always valid UTF-8
+        (None, Some(Value::String(data))) => data.as_bytes().to_vec(),
+        (None, Some(Value::Binary(data))) => data.clone(),
+        _ => return Err(Value::from("an image needs a path or data")),
+    };
+
+    let id = match option(options, "id") {
+        None | Some(Value::Nil) => NEXT_IMAGE_ID.fetch_add(1, Ordering::Relaxed),
+        Some(id) => match id.as_u64() {
+            Some(id) if id < AUTOMATIC_IDS_START => id,
+            _ => {
+                return Err(Value::from(format!(
+                    "image ids have to be below {AUTOMATIC_IDS_START}"
+                )))
+            }
+        },
+    };
+    // Big images take a while to decode, which would stall the frames on the render thread
+    let image = tokio::task::spawn_blocking(move || decode_image(data))
+        .await
+        .ok()
+        .flatten()
+        .ok_or_else(|| Value::from("cannot decode the image, expected a PNG, JPEG or SVG"))?;
+    let positive = |size: f32| size > 0.0;
+    EVENT_AGGREGATOR.send(WindowCommand::Image(ImageCommand::Place(ImagePlacement {
+        id,
+        image,
+        row: option_number(options, "row").unwrap_or(0.0),
+        col: option_number(options, "col").unwrap_or(0.0),
+        width: option_number(options, "width").filter(|size| positive(*size)),
+        height: option_number(options, "height").filter(|size| positive(*size)),
+        z: option(options, "z")
+            .and_then(|z| z.as_i64())
+            .unwrap_or_default(),
+    })));
+    Ok(Value::from(id))
+}
+
+/// Removes the image with the id for `neovide.remove_image`, or all of them without one. Ids which
+/// aren't one are ignored, instead of removing everything.
+pub fn remove_image(id: Option<&Value>) {
+    let command = match id {
+        None | Some(Value::Nil) => ImageCommand::Clear,
+        Some(id) => match id.as_u64() {
+            Some(id) => ImageCommand::Remove(id),
+            None => return,
+        },
+    };
+    EVENT_AGGREGATOR.send(WindowCommand::Image(command));
+}
@@ -0,0 +1,10 @@
+use rmpv::Value;
+
+/// Looks up a key in the options table passed from Lua, like the `opts` of `neovide.notify`.
+pub fn option<'a>(options: &'a Value, key: &str) -> Option<&'a Value> {
+    options
+        .as_map()?
+        .iter()
+        .find(|(name, _)| name.as_str() == Some(key))
+        .map(|(_, value)| value)
+}
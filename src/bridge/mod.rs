@@ -1,29 +1,71 @@
+mod api_info;
 mod clipboard;
+mod color_picker;
 mod command;
+mod event_recording;
 mod events;
+mod file_dialogs;
 mod handler;
+mod images;
+mod lua_options;
+mod notifications;
+mod nvim_stderr;
+mod project_files;
 pub mod session;
+mod session_autosave;
 mod setup;
+mod setup_wizard;
 mod ui_commands;
+mod wsl;
 
-use std::{process::exit, sync::Arc, thread};
+use std::{process::exit, sync::Arc, thread, time::Duration};
 
-use log::{error, info};
-use nvim_rs::UiAttachOptions;
+use log::{error, info, warn};
+use nvim_rs::{Neovim, UiAttachOptions};
+use tokio::{process::Child, time::timeout};
 
 use crate::{
-    cmd_line::CmdLineSettings, error_handling::ResultPanicExplanation, running_tracker::*,
+    bridge::api_info::ApiInfo,
+    cmd_line::CmdLineSettings,
+    dimensions::Dimensions,
+    error_handling::ResultPanicExplanation,
+    event_aggregator::EVENT_AGGREGATOR,
+    i18n::{tr, tr_with},
+    running_tracker::*,
     settings::*,
+    startup_time::STARTUP_TIME,
+    window::WindowSettings,
 };
 
 pub use command::create_nvim_command;
+use event_recording::start_event_replay;
 pub use events::*;
 use handler::NeovimHandler;
+use nvim_stderr::exit_with_startup_error;
 pub use session::NeovimWriter;
 use session::{NeovimInstance, NeovimSession};
+use session_autosave::autosave_session;
 use setup::setup_neovide_specific_state;
 pub use ui_commands::{start_ui_command_handler, ParallelCommand, SerialCommand, UiCommand};
 
+// How long Neovim gets to flush swap files and run its exit autocommands after closing the
+// connection, before it's killed
+const NEOVIM_EXIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// How long Neovim gets from being started until the UI is attached, before it's considered stuck,
+// for example on a shell waiting for input
+const NEOVIM_ATTACH_TIMEOUT: Duration = Duration::from_secs(30);
+
+// How long attaching waits for the window to know its grid size, before starting with the default
+// one and resizing later
+const INITIAL_GRID_SIZE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The grid size the window is going to have, sent by the window once the font is loaded. Neovim
+/// is attached with it, so that plugins laying out on VimEnter, like dashboards, see the final
+/// size instead of being resized right after.
+#[derive(Clone, Debug)]
+pub struct InitialGridSize(pub Dimensions);
+
 fn neovim_instance() -> NeovimInstance {
     if let Some(address) = SETTINGS.get::<CmdLineSettings>().server {
         NeovimInstance::Server { address }
@@ -33,6 +75,10 @@ fn neovim_instance() -> NeovimInstance {
 }
 
 pub fn start_bridge() {
+    if let Some(path) = SETTINGS.get::<CmdLineSettings>().replay_events {
+        start_event_replay(path);
+        return;
+    }
     thread::spawn(|| {
         start_neovim_runtime();
     });
@@ -43,50 +89,110 @@ async fn start_neovim_runtime() {
     let handler = NeovimHandler::new();
     let session = NeovimSession::new(neovim_instance(), handler)
         .await
-        .unwrap_or_explained_panic("Could not locate or start neovim process");
+        .unwrap_or_explained_panic(&tr("error.start-neovim"));
+    STARTUP_TIME.record("nvim spawned");
 
     let nvim = Arc::new(session.neovim);
 
+    match timeout(NEOVIM_ATTACH_TIMEOUT, attach_ui(&nvim)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(error)) => exit_with_startup_error(&error, &session.stderr),
+        Err(_) => exit_with_startup_error(
+            &tr_with(
+                "error.attach-timeout",
+                &[("seconds", NEOVIM_ATTACH_TIMEOUT.as_secs().to_string())],
+            ),
+            &session.stderr,
+        ),
+    }
+
+    info!("Neovim process attached");
+    STARTUP_TIME.record("nvim attached");
+
+    start_ui_command_handler(nvim.clone());
+    SETTINGS.read_initial_values(&nvim).await;
+    SETTINGS.setup_changed_listeners(&nvim).await;
+    tokio::spawn(autosave_session(nvim.clone()));
+
+    match session.io_handle.await {
+        Err(join_error) => error!("Error joining IO loop: '{}'", join_error),
+        Ok(Err(error)) => {
+            if !error.is_channel_closed() {
+                error!("Error: '{}'", error);
+            }
+        }
+        Ok(Ok(())) => {}
+    };
+
+    if let Some(neovim_process) = session.neovim_process {
+        wait_for_neovim_exit(neovim_process).await;
+    }
+
+    RUNNING_TRACKER.quit("neovim processed failed");
+}
+
+async fn attach_ui(nvim: &Neovim<NeovimWriter>) -> Result<(), String> {
     // Check the neovim version to ensure its high enough
     match nvim.command_output("echo has('nvim-0.4')").await.as_deref() {
         Ok("1") => {} // This is just a guard
-        _ => {
+        Ok(_) => {
             error!("Neovide requires nvim version 0.4 or higher. Download the latest version here https://github.com/neovim/neovim/wiki/Installing-Neovim");
             exit(0);
         }
+        Err(error) => return Err(format!("{}: {}", tr("error.communicate-neovim"), error)),
     }
 
-    let settings = SETTINGS.get::<CmdLineSettings>();
+    let api_info = ApiInfo::query(nvim).await;
+    let mut settings = SETTINGS.get::<CmdLineSettings>();
+    if settings.multi_grid && !api_info.supports_ui_option("ext_multigrid") {
+        warn!("This Neovim doesn't support multigrid, falling back to a single grid");
+        settings.multi_grid = false;
+        SETTINGS.set(&settings);
+    }
 
     let should_handle_clipboard = settings.wsl || settings.server.is_some();
-    setup_neovide_specific_state(&nvim, should_handle_clipboard).await;
+    setup_neovide_specific_state(nvim, should_handle_clipboard, &api_info).await;
 
     let mut options = UiAttachOptions::new();
     options.set_linegrid_external(true);
     options.set_multigrid_external(settings.multi_grid);
     options.set_rgb(true);
+    // Handed over as requested, and otherwise drawn into the grid by Neovim as usual
+    let window_settings = SETTINGS.get::<WindowSettings>();
+    let supported = |name, enabled| enabled && api_info.supports_ui_option(name);
+    options.set_messages_external(supported("ext_messages", window_settings.ext_messages));
+    options.set_cmdline_external(supported("ext_cmdline", window_settings.ext_cmdline));
+    options.set_popupmenu_external(supported("ext_popupmenu", window_settings.ext_popupmenu));
+    options.set_tabline_external(supported("ext_tabline", window_settings.ext_tabline));
 
     // Triggers loading the user's config
-    // Set to DEFAULT_WINDOW_GEOMETRY first, draw_frame will resize it later
-    let geometry = DEFAULT_WINDOW_GEOMETRY;
+    let geometry = initial_grid_size().await;
     nvim.ui_attach(geometry.width as i64, geometry.height as i64, &options)
         .await
-        .unwrap_or_explained_panic("Could not attach ui to neovim process");
-
-    info!("Neovim process attached");
+        .map_err(|error| format!("{}: {}", tr("error.attach-neovim"), error))
+}
 
-    start_ui_command_handler(nvim.clone());
-    SETTINGS.read_initial_values(&nvim).await;
-    SETTINGS.setup_changed_listeners(&nvim).await;
+async fn initial_grid_size() -> Dimensions {
+    let mut receiver = EVENT_AGGREGATOR.register_event::<InitialGridSize>();
+    match timeout(INITIAL_GRID_SIZE_TIMEOUT, receiver.recv()).await {
+        Ok(Some(InitialGridSize(grid_size))) => grid_size,
+        _ => {
+            warn!("The window didn't report its grid size in time, starting with the default");
+            DEFAULT_WINDOW_GEOMETRY
+        }
+    }
+}
 
-    match session.io_handle.await {
-        Err(join_error) => error!("Error joining IO loop: '{}'", join_error),
-        Ok(Err(error)) => {
-            if !error.is_channel_closed() {
-                error!("Error: '{}'", error);
-            }
+async fn wait_for_neovim_exit(mut neovim_process: Child) {
+    match timeout(NEOVIM_EXIT_TIMEOUT, neovim_process.wait()).await {
+        Ok(Ok(status)) => info!("Neovim process exited with {}", status),
+        Ok(Err(error)) => error!("Error waiting for the neovim process: '{}'", error),
+        Err(_) => {
+            warn!(
+                "Neovim process did not exit within {:?}, killing it",
+                NEOVIM_EXIT_TIMEOUT
+            );
+            neovim_process.kill().await.ok();
         }
-        Ok(Ok(())) => {}
-    };
-    RUNNING_TRACKER.quit("neovim processed failed");
+    }
 }
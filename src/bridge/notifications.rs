@@ -0,0 +1,61 @@
+use log::error;
+use notify_rust::{Notification, Timeout};
+use rmpv::Value;
+
+use crate::bridge::lua_options::option;
+#[cfg(all(unix, not(target_os = "macos")))]
+use crate::{event_aggregator::EVENT_AGGREGATOR, window::WindowCommand};
+
+/// Posts a notification of the OS. Clicking it brings the Neovide window to the front on Linux,
+/// and on macOS when running from the app bundle, which the notifications are attributed to. The
+/// toasts of Windows are shown for PowerShell by notify-rust, so clicking them can't focus Neovide.
+pub fn show_notification(title: String, body: String, options: Value) {
+    let mut notification = Notification::new();
+    notification.appname("Neovide").summary(&title).body(&body);
+
+    if let Some(timeout) = option(&options, "timeout").and_then(|timeout| timeout.as_u64()) {
+        notification.timeout(Timeout::Milliseconds(timeout as u32));
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    {
+        use notify_rust::Urgency;
+
+        match option(&options, "urgency").and_then(|urgency| urgency.as_str()) {
+            Some("low") => {
+                notification.urgency(Urgency::Low);
+            }
+            Some("critical") => {
+                notification.urgency(Urgency::Critical);
+            }
+            _ => {}
+        }
+        notification.action("default", "Show");
+    }
+
+    // Waiting for the click blocks until the notification is closed
+    tokio::task::spawn_blocking(move || {
+        #[cfg(target_os = "macos")]
+        {
+            // Notifications are attributed to this app, which macOS activates on click
+            let _ = notify_rust::set_application("com.neovide.neovide");
+        }
+
+        let handle = match notification.show() {
+            Ok(handle) => handle,
+            Err(error) => {
+                error!("Could not show notification {:?}: {}", title, error);
+                return;
+            }
+        };
+
+        #[cfg(all(unix, not(target_os = "macos")))]
+        handle.wait_for_action(|action| {
+            if action == "default" {
+                EVENT_AGGREGATOR.send(WindowCommand::FocusWindow);
+            }
+        });
+        #[cfg(not(all(unix, not(target_os = "macos"))))]
+        let _ = handle;
+    });
+}
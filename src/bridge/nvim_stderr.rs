@@ -0,0 +1,117 @@
+use std::{collections::VecDeque, process::exit, sync::Arc};
+
+use log::{error, info};
+use parking_lot::Mutex;
+use rfd::{MessageButtons, MessageDialog, MessageDialogResult, MessageLevel};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    process::ChildStderr,
+};
+
+use crate::{clipboard, i18n::tr};
+
+// Enough for the errors of a broken config, without growing forever while Neovim runs
+const MAX_STDERR_LINES: usize = 200;
+
+/// The stderr output of the embedded Neovim. It's logged line by line as it comes, and the last
+/// lines are kept to explain why Neovim didn't start.
+#[derive(Clone, Default)]
+pub struct NeovimStderr {
+    lines: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl NeovimStderr {
+    pub fn spawn_reader(stderr: ChildStderr) -> Self {
+        let neovim_stderr = Self::default();
+        let reader_stderr = neovim_stderr.clone();
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                info!("Neovim stderr: {}", line);
+                reader_stderr.push(line);
+            }
+        });
+        neovim_stderr
+    }
+
+    fn push(&self, line: String) {
+        let mut lines = self.lines.lock();
+        if lines.len() == MAX_STDERR_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    pub fn contents(&self) -> String {
+        self.lines
+            .lock()
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Shows why Neovim couldn't be started along with what it wrote to stderr, then quits. The dialog
+/// stays open after copying, since the clipboard is gone together with Neovide on some platforms.
+pub fn exit_with_startup_error(message: &str, stderr: &NeovimStderr) -> ! {
+    let description = startup_error_description(message, &stderr.contents());
+    error!("{}", description);
+
+    let copy = tr("error.copy");
+    loop {
+        let result = MessageDialog::new()
+            .set_level(MessageLevel::Error)
+            .set_title(&tr("error.title"))
+            .set_description(&description)
+            .set_buttons(MessageButtons::OkCancelCustom(
+                copy.clone(),
+                tr("error.close"),
+            ))
+            .show();
+        let copy_clicked = match result {
+            MessageDialogResult::Ok => true,
+            MessageDialogResult::Custom(button) => button == copy,
+            _ => false,
+        };
+        if !copy_clicked {
+            break;
+        }
+        if let Err(error) = clipboard::set_contents(description.clone()) {
+            error!("Could not copy the error: {}", error);
+        }
+    }
+    exit(1);
+}
+
+fn startup_error_description(message: &str, stderr: &str) -> String {
+    if stderr.trim().is_empty() {
+        message.to_string()
+    } else {
+        format!("{}\n\n{}\n{}", message, tr("error.neovim-stderr"), stderr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_last_lines() {
+        let stderr = NeovimStderr::default();
+        for line in 0..MAX_STDERR_LINES + 2 {
+            stderr.push(line.to_string());
+        }
+        let contents = stderr.contents();
+        assert!(contents.starts_with("2\n3\n"));
+        assert!(contents.ends_with(&(MAX_STDERR_LINES + 1).to_string()));
+    }
+
+    #[test]
+    fn empty_stderr_is_left_out() {
+        assert_eq!(
+            startup_error_description("Could not attach", " \n"),
+            "Could not attach"
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use log::warn;
+
+// Walking stops here, so that opening the quick open in the home directory stays quick
+const MAX_FILES: usize = 50_000;
+
+/// Lists the files of the project in `root`, relative to it. Git repositories list the files git
+/// knows about, with the ignored ones left out, other directories are walked.
+pub async fn project_files(root: String) -> Vec<String> {
+    // Both block while the files are listed
+    tokio::task::spawn_blocking(move || {
+        let root = PathBuf::from(root);
+        let mut files = git_files(&root).unwrap_or_else(|| walk_files(&root));
+        files.truncate(MAX_FILES);
+        files.sort();
+        files
+    })
+    .await
+    .unwrap_or_default()
+}
+
+fn git_files(root: &Path) -> Option<Vec<String>> {
+    let mut command = Command::new("git");
+    command
+        .args([
+            "ls-files",
+            "--cached",
+            "--others",
+            "--exclude-standard",
+            "-z",
+        ])
+        .current_dir(root);
+    #[cfg(windows)]
+    std::os::windows::process::CommandExt::creation_flags(
+        &mut command,
+        winapi::um::winbase::CREATE_NO_WINDOW,
+    );
+    let output = command.output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(parse_git_files(&output.stdout))
+}
+
+fn parse_git_files(output: &[u8]) -> Vec<String> {
+    let mut files: Vec<String> = output
+        .split(|byte| *byte == 0)
+        .filter(|file| !file.is_empty())
+        .map(|file| String::from_utf8_lossy(file).into_owned())
+        .collect();
+    // Files with merge conflicts are listed once per stage
+    files.dedup();
+    files
+}
+
+fn walk_files(root: &Path) -> Vec<String> {
+    let mut files = Vec::new();
+    let mut directories = vec![root.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        let entries = match fs::read_dir(&directory) {
+            Ok(entries) => entries,
+            Err(error) => {
+                warn!("Could not list {}: {}", directory.display(), error);
+                continue;
+            }
+        };
+        for entry in entries.flatten() {
+            // Hidden files and directories like .git are left out
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let path = entry.path();
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => directories.push(path),
+                Ok(file_type) if file_type.is_file() => {
+                    if let Ok(relative) = path.strip_prefix(root) {
+                        files.push(relative.to_string_lossy().into_owned());
+                    }
+                    if files.len() >= MAX_FILES {
+                        return files;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn git_files_are_split_at_nul() {
+        let output = b"src/main.rs\0README.md\0README.md\0with space.txt\0";
+        assert_eq!(
+            parse_git_files(output),
+            vec!["src/main.rs", "README.md", "with space.txt"]
+        );
+        assert!(parse_git_files(b"").is_empty());
+    }
+}
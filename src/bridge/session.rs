@@ -10,12 +10,14 @@ use nvim_rs::{error::LoopError, neovim::Neovim, Handler};
 use tokio::{
     io::{split, AsyncRead, AsyncWrite},
     net::TcpStream,
-    process::Command,
+    process::{Child, Command},
     spawn,
     task::JoinHandle,
 };
 use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
 
+use crate::bridge::nvim_stderr::NeovimStderr;
+
 pub type NeovimWriter = Box<dyn futures::AsyncWrite + Send + Unpin + 'static>;
 
 type BoxedReader = Box<dyn AsyncRead + Send + Unpin + 'static>;
@@ -24,6 +26,10 @@ type BoxedWriter = Box<dyn AsyncWrite + Send + Unpin + 'static>;
 pub struct NeovimSession {
     pub neovim: Neovim<NeovimWriter>,
     pub io_handle: JoinHandle<std::result::Result<(), Box<LoopError>>>,
+    /// The spawned Neovim process, if the instance is embedded
+    pub neovim_process: Option<Child>,
+    /// What the spawned Neovim process writes to stderr, empty if the instance isn't embedded
+    pub stderr: NeovimStderr,
 }
 
 impl NeovimSession {
@@ -31,12 +37,22 @@ impl NeovimSession {
         instance: NeovimInstance,
         handler: impl Handler<Writer = NeovimWriter>,
     ) -> Result<Self> {
-        let (reader, writer) = instance.connect().await?;
+        let (reader, writer, mut neovim_process) = instance.connect().await?;
+        let stderr = neovim_process
+            .as_mut()
+            .and_then(|process| process.stderr.take())
+            .map(NeovimStderr::spawn_reader)
+            .unwrap_or_default();
         let (neovim, io) =
             Neovim::<NeovimWriter>::new(reader.compat(), Box::new(writer.compat_write()), handler);
         let io_handle = spawn(io);
 
-        Ok(Self { neovim, io_handle })
+        Ok(Self {
+            neovim,
+            io_handle,
+            neovim_process,
+            stderr,
+        })
     }
 }
 
@@ -55,14 +71,17 @@ pub enum NeovimInstance {
 }
 
 impl NeovimInstance {
-    async fn connect(self) -> Result<(BoxedReader, BoxedWriter)> {
+    async fn connect(self) -> Result<(BoxedReader, BoxedWriter, Option<Child>)> {
         match self {
             NeovimInstance::Embedded(cmd) => Self::spawn_process(cmd).await,
-            NeovimInstance::Server { address } => Self::connect_to_server(address).await,
+            NeovimInstance::Server { address } => {
+                let (reader, writer) = Self::connect_to_server(address).await?;
+                Ok((reader, writer, None))
+            }
         }
     }
 
-    async fn spawn_process(mut cmd: Command) -> Result<(BoxedReader, BoxedWriter)> {
+    async fn spawn_process(mut cmd: Command) -> Result<(BoxedReader, BoxedWriter, Option<Child>)> {
         let mut child = cmd.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
         let reader = Box::new(
             child
@@ -77,7 +96,7 @@ impl NeovimInstance {
                 .ok_or_else(|| Error::new(ErrorKind::Other, "Can't open stdin"))?,
         );
 
-        Ok((reader, writer))
+        Ok((reader, writer, Some(child)))
     }
 
     async fn connect_to_server(address: String) -> Result<(BoxedReader, BoxedWriter)> {
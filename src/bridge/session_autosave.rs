@@ -0,0 +1,90 @@
+use std::{sync::Arc, time::Duration};
+
+use log::{info, warn};
+use nvim_rs::Neovim;
+use rmpv::Value;
+use tokio::time::sleep;
+
+use crate::{
+    bridge::NeovimWriter,
+    cmd_line::CmdLineSettings,
+    running_tracker::RUNNING_TRACKER,
+    settings::{last_session_path, SETTINGS},
+    window::WindowSettings,
+};
+
+// How often the interval setting is checked again while autosaving is disabled
+const DISABLED_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+const REGISTER_SESSION_AUTOSAVE_LUA: &str = r"
+    local path = ...
+
+    -- Without any file open there's nothing worth restoring, and the last useful session is kept
+    local function save_session()
+        for _, buffer in ipairs(vim.api.nvim_list_bufs()) do
+            if vim.bo[buffer].buflisted and vim.api.nvim_buf_get_name(buffer) ~= '' then
+                vim.cmd('mksession! ' .. vim.fn.fnameescape(path))
+                return
+            end
+        end
+    end
+
+    local group = vim.api.nvim_create_augroup('NeovideSessionAutosave', {})
+    vim.api.nvim_create_autocmd('VimLeavePre', {
+        group = group,
+        callback = function()
+            if (vim.g.neovide_session_autosave_interval or 0) > 0 then
+                save_session()
+            end
+        end,
+    })
+    -- Triggered by Neovide every few seconds
+    vim.api.nvim_create_autocmd('User', {
+        group = group,
+        pattern = 'NeovideSessionAutosave',
+        callback = save_session,
+    })";
+
+/// The arguments for Neovim that load the last session, when `--restore-last-session` was given.
+pub fn restore_session_args() -> Vec<String> {
+    if !SETTINGS.get::<CmdLineSettings>().restore_last_session {
+        return Vec::new();
+    }
+    let path = last_session_path();
+    if path.exists() {
+        info!("Restoring the session {}", path.to_string_lossy());
+        vec!["-S".to_string(), path.to_string_lossy().to_string()]
+    } else {
+        warn!("There is no session to restore yet");
+        Vec::new()
+    }
+}
+
+/// Saves the session when Neovim exits, and every `g:neovide_session_autosave_interval` seconds
+/// while it runs, so that it survives crashes too.
+pub async fn autosave_session(nvim: Arc<Neovim<NeovimWriter>>) {
+    let path = Value::from(last_session_path().to_string_lossy().to_string());
+    if let Err(error) = nvim
+        .execute_lua(REGISTER_SESSION_AUTOSAVE_LUA, vec![path])
+        .await
+    {
+        warn!("Could not set up the session autosave: {}", error);
+        return;
+    }
+
+    while RUNNING_TRACKER.is_running() {
+        let interval = SETTINGS.get::<WindowSettings>().session_autosave_interval;
+        if interval <= 0.0 {
+            sleep(DISABLED_POLL_INTERVAL).await;
+            continue;
+        }
+        sleep(Duration::from_secs_f32(interval)).await;
+
+        if let Err(error) = nvim
+            .command("doautocmd <nomodeline> User NeovideSessionAutosave")
+            .await
+        {
+            warn!("Could not save the session: {}", error);
+        }
+    }
+}
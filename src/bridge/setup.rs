@@ -2,18 +2,41 @@ use log::{info, warn};
 use nvim_rs::Neovim;
 use rmpv::Value;
 
-use crate::{bridge::NeovimWriter, error_handling::ResultPanicExplanation};
+use crate::{
+    bridge::{
+        api_info::ApiInfo, clipboard::CLIPBOARD_CHUNK_LINES, setup_wizard::is_first_run,
+        NeovimWriter,
+    },
+    cmd_line::CmdLineSettings,
+    error_handling::ResultPanicExplanation,
+    i18n::tr,
+    settings::{toml_to_value, EditorConfig, SavedSettings, SETTINGS},
+};
 
 const REGISTER_CLIPBOARD_PROVIDER_LUA: &str = r"
+    local chunk_lines = ...
+
+    -- Big clipboards are sent in chunks, with the more flag on all but the last one
     local function set_clipboard(register)
         return function(lines, regtype)
-            vim.rpcrequest(vim.g.neovide_channel_id, 'neovide.set_clipboard', lines)
+            local channel = vim.g.neovide_channel_id
+            for first = 1, math.max(#lines, 1), chunk_lines do
+                local last = first + chunk_lines - 1
+                local more = last < #lines
+                vim.rpcrequest(channel, 'neovide.set_clipboard', vim.list_slice(lines, first, last), more)
+            end
         end
     end
 
     local function get_clipboard(register)
         return function()
-            return vim.rpcrequest(vim.g.neovide_channel_id, 'neovide.get_clipboard', register)
+            local channel = vim.g.neovide_channel_id
+            local lines, regtype, line_count =
+                unpack(vim.rpcrequest(channel, 'neovide.get_clipboard', register))
+            while #lines < line_count do
+                vim.list_extend(lines, vim.rpcrequest(channel, 'neovide.get_clipboard_chunk', #lines))
+            end
+            return { lines, regtype }
         end
     end
 
@@ -30,6 +53,458 @@ const REGISTER_CLIPBOARD_PROVIDER_LUA: &str = r"
         cache_enabled = 0
     }";
 
+const REGISTER_WINDOW_BACKGROUNDS_LUA: &str = r"
+    local channel = ...
+
+    local function group_background(group)
+        -- nvim_get_hl_by_name is deprecated since Neovim 0.9, which added nvim_get_hl
+        if vim.api.nvim_get_hl then
+            local ok, highlight = pcall(vim.api.nvim_get_hl, 0, { name = group, link = false })
+            return ok and highlight.bg or nil
+        end
+        local ok, highlight = pcall(vim.api.nvim_get_hl_by_name, group, true)
+        return ok and highlight.background or nil
+    end
+
+    local function window_background(window, current)
+        local overrides = {}
+        for from, to in string.gmatch(vim.wo[window].winhighlight, '([^:,]+):([^,]+)') do
+            overrides[from] = to
+        end
+
+        local normal = group_background(overrides.Normal or 'Normal')
+        if current then
+            return normal
+        end
+        return group_background(overrides.NormalNC or overrides.Normal or 'NormalNC') or normal
+    end
+
+    local function send_window_backgrounds()
+        local current_window = vim.api.nvim_get_current_win()
+        local backgrounds = {}
+        for _, window in ipairs(vim.api.nvim_list_wins()) do
+            table.insert(backgrounds, {
+                window = window,
+                background = window_background(window, window == current_window),
+                current = window == current_window,
+            })
+        end
+        vim.rpcnotify(channel, 'neovide.window_backgrounds', backgrounds)
+    end
+
+    vim.api.nvim_create_autocmd({ 'WinEnter', 'WinNew', 'BufWinEnter', 'ColorScheme' }, {
+        group = vim.api.nvim_create_augroup('NeovideWindowBackgrounds', {}),
+        callback = send_window_backgrounds,
+    })
+    vim.api.nvim_create_autocmd('OptionSet', {
+        group = 'NeovideWindowBackgrounds',
+        pattern = 'winhighlight',
+        callback = send_window_backgrounds,
+    })";
+
+const REGISTER_BUFFER_INFO_LUA: &str = r"
+    local channel = ...
+
+    local function send_buffer_info()
+        vim.rpcnotify(
+            channel,
+            'neovide.buffer_info',
+            vim.fn.expand('%:t'),
+            vim.fn.fnamemodify(vim.fn.getcwd(), ':~'),
+            vim.bo.modified
+        )
+    end
+
+    vim.api.nvim_create_autocmd(
+        { 'BufEnter', 'BufFilePost', 'BufModifiedSet', 'DirChanged', 'WinEnter' },
+        {
+            group = vim.api.nvim_create_augroup('NeovideBufferInfo', {}),
+            callback = send_buffer_info,
+        }
+    )
+    send_buffer_info()";
+
+const REGISTER_BACKGROUND_LUA: &str = r"
+    local channel = ...
+
+    local function send_background()
+        vim.rpcnotify(channel, 'neovide.background', vim.o.background)
+    end
+
+    vim.api.nvim_create_autocmd('OptionSet', {
+        group = vim.api.nvim_create_augroup('NeovideBackground', {}),
+        pattern = 'background',
+        callback = send_background,
+    })
+    -- Colorschemes usually set the background without triggering OptionSet
+    vim.api.nvim_create_autocmd('ColorScheme', {
+        group = 'NeovideBackground',
+        callback = send_background,
+    })
+    send_background()";
+
+const REGISTER_LUA_API_LUA: &str = r"
+    local channel = ...
+
+    -- Functions for plugins, like require calls they wait for the result
+    _G.neovide = _G.neovide or {}
+
+    -- Returns the chosen color as '#rrggbb', or nil when the picker was cancelled
+    function neovide.pick_color(initial)
+        return vim.rpcrequest(channel, 'neovide.pick_color', initial)
+    end
+
+    -- Posts a notification of the OS, options are timeout (in milliseconds) and urgency
+    function neovide.notify(title, body, opts)
+        vim.rpcnotify(channel, 'neovide.notify', title, body or '', opts or vim.empty_dict())
+    end
+
+    -- Shows progress from 0 to 100 on the dock icon or the taskbar button, nil removes it again
+    function neovide.set_progress(progress)
+        vim.rpcnotify(channel, 'neovide.set_progress', progress)
+    end
+
+    -- Returns the selected paths, which are empty when the dialog was cancelled
+    function neovide.open_file_dialog(opts)
+        return vim.rpcrequest(channel, 'neovide.open_file_dialog', opts or vim.empty_dict())
+    end
+
+    -- Returns the chosen path, or nil when the dialog was cancelled
+    function neovide.save_file_dialog(opts)
+        return vim.rpcrequest(channel, 'neovide.save_file_dialog', opts or vim.empty_dict())
+    end
+
+    -- Starts another Neovide in the current directory, optionally opening path
+    function neovide.new_window(path)
+        path = path and vim.fn.fnamemodify(vim.fn.expand(path), ':p') or ''
+        vim.rpcnotify(channel, 'neovide.new_window', vim.fn.getcwd(), path)
+    end
+
+    -- Focuses the next Neovide, or the previous one when direction is -1
+    function neovide.cycle_window(direction)
+        vim.rpcnotify(channel, 'neovide.cycle_window', direction or 1)
+    end
+
+    -- Places a PNG, JPEG or SVG over the grid and returns its id. Options are path or data, row and
+    -- col (relative to the window win when given), width and height in cells, z, and id to
+    -- replace an image
+    function neovide.place_image(opts)
+        opts = vim.deepcopy(opts)
+        if opts.win then
+            local position = vim.fn.win_screenpos(opts.win)
+            opts.row = (opts.row or 0) + position[1] - 1
+            opts.col = (opts.col or 0) + position[2] - 1
+            opts.win = nil
+        end
+        if opts.path then
+            opts.path = vim.fn.fnamemodify(vim.fn.expand(opts.path), ':p')
+        end
+        return vim.rpcrequest(channel, 'neovide.place_image', opts)
+    end
+
+    -- Removes the image with the id, or all of them without one
+    function neovide.remove_image(id)
+        vim.rpcnotify(channel, 'neovide.remove_image', id)
+    end
+
+    -- Sets the tabs of the tabline Neovide draws, in the order to show them. Each is a table of tab,
+    -- title, icon and modified, and nil shows the tabs of Neovim again
+    function neovide.set_tabline(tabs)
+        vim.rpcnotify(channel, 'neovide.set_tabline', tabs)
+    end";
+
+const REGISTER_SETUP_WIZARD_LUA: &str = r#"
+    local channel, first_run = ...
+
+    local fonts = { 'Default', 'JetBrains Mono', 'Fira Code', 'Cascadia Code', 'Hack',
+        'Source Code Pro', 'Other...' }
+    local transparencies = { '1.0', '0.95', '0.9', '0.8' }
+    local cursor_effects = { 'none', 'railgun', 'torpedo', 'pixiedust', 'sonicboom', 'ripple',
+        'wireframe' }
+
+    local function snippet(choices)
+        local lines = { 'if vim.g.neovide then' }
+        if choices.font then
+            table.insert(lines, string.format('    vim.o.guifont = %q', choices.font))
+        end
+        if choices.transparency then
+            table.insert(lines,
+                string.format('    vim.g.neovide_transparency = %s', choices.transparency))
+        end
+        if choices.cursor_vfx_mode then
+            table.insert(lines,
+                string.format('    vim.g.neovide_cursor_vfx_mode = %q', choices.cursor_vfx_mode))
+        end
+        table.insert(lines, 'end')
+        return lines
+    end
+
+    local function finish(choices, write_snippet)
+        if choices.font then
+            vim.o.guifont = choices.font
+        end
+        vim.g.neovide_transparency = choices.transparency
+        vim.g.neovide_cursor_vfx_mode = choices.cursor_vfx_mode
+        vim.rpcnotify(channel, 'neovide.setup_wizard_done', choices)
+
+        if write_snippet then
+            local config_dir = vim.fn.stdpath('config')
+            local path = config_dir .. '/neovide.lua'
+            vim.fn.mkdir(config_dir, 'p')
+            vim.fn.writefile(snippet(choices), path)
+            vim.notify('Wrote ' .. path .. ', load it in init.lua with '
+                .. 'dofile(vim.fn.stdpath("config") .. "/neovide.lua")')
+        else
+            vim.notify('Saved the Neovide settings, run :NeovideSetupWizard to change them')
+        end
+    end
+
+    -- Every step ends the wizard without saving anything when cancelled
+    local function run()
+        local choices = {}
+
+        local function ask_snippet()
+            vim.ui.select({ 'Yes', 'No' }, {
+                prompt = 'Neovide setup: Also write a neovide.lua snippet for init.lua?',
+            }, function(answer)
+                if answer then
+                    finish(choices, answer == 'Yes')
+                end
+            end)
+        end
+
+        local function ask_cursor_effect()
+            vim.ui.select(cursor_effects, { prompt = 'Neovide setup: Cursor effect' },
+                function(effect)
+                    if effect then
+                        choices.cursor_vfx_mode = effect == 'none' and '' or effect
+                        ask_snippet()
+                    end
+                end)
+        end
+
+        local function ask_transparency()
+            vim.ui.select(transparencies, { prompt = 'Neovide setup: Transparency' },
+                function(transparency)
+                    if transparency then
+                        choices.transparency = tonumber(transparency)
+                        ask_cursor_effect()
+                    end
+                end)
+        end
+
+        local function ask_size(font)
+            vim.ui.input({ prompt = 'Neovide setup: Font size ', default = '14' }, function(size)
+                if size and tonumber(size) then
+                    choices.font = font .. ':h' .. size
+                    ask_transparency()
+                end
+            end)
+        end
+
+        vim.ui.select(fonts, { prompt = 'Neovide setup: Font' }, function(font)
+            if font == 'Other...' then
+                vim.ui.input({ prompt = 'Neovide setup: Font name ' }, function(name)
+                    if name and name ~= '' then
+                        ask_size(name)
+                    end
+                end)
+            elseif font == 'Default' then
+                ask_transparency()
+            elseif font then
+                ask_size(font)
+            end
+        end)
+    end
+
+    vim.api.nvim_create_user_command('NeovideSetupWizard', run, {})
+    if first_run then
+        -- After the user's config, so that plugins replacing vim.ui are used
+        vim.api.nvim_create_autocmd('VimEnter', { once = true, callback = vim.schedule_wrap(run) })
+    end"#;
+
+const REGISTER_MODE_BADGE_LUA: &str = r"
+    local channel = ...
+
+    local function send_state(recording)
+        if not vim.g.neovide_mode_badge then
+            return
+        end
+        -- The operator is only interesting while it waits for a motion
+        local operator = vim.api.nvim_get_mode().mode:sub(1, 2) == 'no' and vim.v.operator or ''
+        vim.rpcnotify(channel, 'neovide.mode_badge', recording, operator)
+    end
+
+    local group = vim.api.nvim_create_augroup('NeovideModeBadge', {})
+    vim.api.nvim_create_autocmd({ 'RecordingEnter', 'ModeChanged' }, {
+        group = group,
+        callback = function()
+            send_state(vim.fn.reg_recording())
+        end,
+    })
+    -- The register is still reported while leaving
+    vim.api.nvim_create_autocmd('RecordingLeave', {
+        group = group,
+        callback = function()
+            send_state('')
+        end,
+    })";
+
+// Sends the current buffer to the preview pane while it's shown. Buffers like the quickfix list
+// keep the last file in the preview.
+const REGISTER_PREVIEW_LUA: &str = r"
+    local channel = ...
+    local group = vim.api.nvim_create_augroup('NeovidePreview', {})
+    local pending = false
+
+    local function is_previewed()
+        return vim.g.neovide_preview and vim.bo.buftype == ''
+    end
+
+    local function send_top_line()
+        if is_previewed() then
+            vim.rpcnotify(channel, 'neovide.preview_top_line', vim.fn.line('w0') - 1)
+        end
+    end
+
+    local sent_buffer, sent_tick
+
+    -- Only sends the buffer when it changed since it was last sent, unless forced
+    local function send_buffer(force)
+        pending = false
+        if not is_previewed() then
+            return
+        end
+        local buffer = vim.api.nvim_get_current_buf()
+        local tick = vim.b.changedtick
+        if not force and buffer == sent_buffer and tick == sent_tick then
+            return
+        end
+        sent_buffer, sent_tick = buffer, tick
+        -- Other filetypes only show that they aren't previewed, so their lines aren't needed
+        local lines = {}
+        if vim.bo.filetype == 'markdown' then
+            lines = vim.api.nvim_buf_get_lines(0, 0, -1, false)
+        end
+        vim.rpcnotify(channel, 'neovide.preview', vim.bo.filetype, lines)
+        send_top_line()
+    end
+
+    -- Typing sends the buffer at most every 50 milliseconds
+    local function schedule_buffer()
+        if not pending and is_previewed() then
+            pending = true
+            vim.defer_fn(send_buffer, 50)
+        end
+    end
+
+    vim.api.nvim_create_autocmd('BufEnter', {
+        group = group,
+        callback = schedule_buffer,
+    })
+    -- The changedtick stays the same when the filetype changes
+    vim.api.nvim_create_autocmd('FileType', {
+        group = group,
+        callback = function()
+            sent_buffer = nil
+            schedule_buffer()
+        end,
+    })
+    -- Only markdown buffers are sent while typing
+    vim.api.nvim_create_autocmd('FileType', {
+        group = group,
+        pattern = 'markdown',
+        callback = function(args)
+            vim.api.nvim_clear_autocmds({
+                group = group,
+                buffer = args.buf,
+                event = { 'TextChanged', 'TextChangedI' },
+            })
+            vim.api.nvim_create_autocmd({ 'TextChanged', 'TextChangedI' }, {
+                group = group,
+                buffer = args.buf,
+                callback = schedule_buffer,
+            })
+        end,
+    })
+    vim.api.nvim_create_autocmd({ 'WinScrolled', 'CursorMoved', 'CursorMovedI' }, {
+        group = group,
+        callback = send_top_line,
+    })
+    -- Sends the buffer right away, setting g:neovide_preview only sends it with the next change
+    vim.api.nvim_create_user_command('NeovidePreview', function()
+        vim.g.neovide_preview = not vim.g.neovide_preview
+        send_buffer(true)
+    end, {})";
+
+const REGISTER_SEARCH_COUNT_LUA: &str = r"
+    local channel = ...
+    local group = vim.api.nvim_create_augroup('NeovideSearchCount', {})
+    local searching = false
+
+    local function send_search_count(pattern)
+        if not vim.g.neovide_search_count or pattern == '' then
+            return
+        end
+        local ok, count = pcall(vim.fn.searchcount, {
+            pattern = pattern,
+            recompute = true,
+            maxcount = 999,
+            timeout = 50,
+        })
+        if not ok or count.total == nil then
+            return
+        end
+        vim.rpcnotify(channel, 'neovide.search_count', {
+            pattern = pattern,
+            current = count.current,
+            total = count.total,
+            incomplete = count.incomplete ~= 0,
+        })
+    end
+
+    vim.api.nvim_create_autocmd('CmdlineChanged', {
+        group = group,
+        pattern = { '/', '?' },
+        callback = function()
+            send_search_count(vim.fn.getcmdline())
+        end,
+    })
+
+    local function clear_search_count()
+        searching = false
+        vim.rpcnotify(channel, 'neovide.search_count', vim.NIL)
+    end
+
+    vim.api.nvim_create_autocmd('CmdlineLeave', {
+        group = group,
+        callback = function()
+            local cmdtype = vim.v.event.cmdtype
+            local is_search = cmdtype == '/' or cmdtype == '?'
+            if is_search and not vim.v.event.abort then
+                searching = true
+            elseif is_search or searching then
+                -- Cancelled, or any other command line like :nohlsearch ends the search
+                clear_search_count()
+            end
+        end,
+    })
+    -- Keeps the count up to date while jumping between the matches with n and N, until the
+    -- highlighting is turned off, like with :nohlsearch from a mapping
+    vim.api.nvim_create_autocmd('CursorMoved', {
+        group = group,
+        callback = function()
+            if not searching then
+                return
+            end
+            if vim.v.hlsearch == 1 then
+                send_search_count(vim.fn.getreg('/'))
+            else
+                clear_search_count()
+            end
+        end,
+    })";
+
 pub async fn setup_neovide_remote_clipboard(nvim: &Neovim<NeovimWriter>, neovide_channel: u64) {
     // Users can opt-out with
     // vim: `let g:neovide_no_custom_clipboard = v:true`
@@ -44,22 +519,78 @@ pub async fn setup_neovide_remote_clipboard(nvim: &Neovim<NeovimWriter>, neovide
         return;
     }
 
-    nvim.set_var("neovide_channel_id", Value::from(neovide_channel))
+    nvim.execute_lua(
+        REGISTER_CLIPBOARD_PROVIDER_LUA,
+        vec![Value::from(CLIPBOARD_CHUNK_LINES)],
+    )
+    .await
+    .ok();
+}
+
+// The integrations built on Lua autocommands, like the buffer info for the title and the search
+// count
+async fn register_lua_integrations(nvim: &Neovim<NeovimWriter>, neovide_channel: u64) {
+    // Lua functions exposed to plugins in the global neovide table
+    nvim.execute_lua(REGISTER_LUA_API_LUA, vec![Value::from(neovide_channel)])
         .await
         .ok();
-    nvim.execute_lua(REGISTER_CLIPBOARD_PROVIDER_LUA, vec![])
+
+    // Keep the filename, working directory and modified state available for the title
+    nvim.execute_lua(REGISTER_BUFFER_INFO_LUA, vec![Value::from(neovide_channel)])
         .await
         .ok();
+
+    // The window decorations follow the background
+    nvim.execute_lua(REGISTER_BACKGROUND_LUA, vec![Value::from(neovide_channel)])
+        .await
+        .ok();
+
+    // Picking the font, transparency and cursor effect, by itself on the first run
+    nvim.execute_lua(
+        REGISTER_SETUP_WIZARD_LUA,
+        vec![Value::from(neovide_channel), Value::from(is_first_run())],
+    )
+    .await
+    .ok();
+
+    // The current and total matches of the search, shown near the command line
+    nvim.execute_lua(
+        REGISTER_SEARCH_COUNT_LUA,
+        vec![Value::from(neovide_channel)],
+    )
+    .await
+    .ok();
+
+    // Macro recording and pending operator, shown in the mode badge
+    nvim.execute_lua(REGISTER_MODE_BADGE_LUA, vec![Value::from(neovide_channel)])
+        .await
+        .ok();
+
+    // The current buffer, shown in the preview pane
+    nvim.execute_lua(REGISTER_PREVIEW_LUA, vec![Value::from(neovide_channel)])
+        .await
+        .ok();
+
+    // Per window backgrounds need each window on its own grid
+    if SETTINGS.get::<CmdLineSettings>().multi_grid {
+        nvim.execute_lua(
+            REGISTER_WINDOW_BACKGROUNDS_LUA,
+            vec![Value::from(neovide_channel)],
+        )
+        .await
+        .ok();
+    }
 }
 
 pub async fn setup_neovide_specific_state(
     nvim: &Neovim<NeovimWriter>,
     should_handle_clipboard: bool,
+    api_info: &ApiInfo,
 ) {
     // Set variable indicating to user config that neovide is being used.
     nvim.set_var("neovide", Value::Boolean(true))
         .await
-        .unwrap_or_explained_panic("Could not communicate with neovim process");
+        .unwrap_or_explained_panic(&tr("error.communicate-neovim"));
 
     if let Err(command_error) = nvim.command("runtime! ginit.vim").await {
         nvim.command(&format!(
@@ -89,20 +620,117 @@ pub async fn setup_neovide_specific_state(
     .await
     .ok();
 
-    // Retrieve the channel number for communicating with neovide.
-    let neovide_channel: Option<u64> = nvim
-        .get_api_info()
-        .await
-        .ok()
-        .and_then(|info| info[0].as_u64());
-
-    if let Some(neovide_channel) = neovide_channel {
+    // The channel number for communicating with neovide.
+    if let Some(neovide_channel) = api_info.channel {
         // Record the channel to the log.
         info!(
             "Neovide registered to nvim with channel id {}",
             neovide_channel
         );
 
+        // Let plugins send notifications like neovide.set_progress
+        nvim.set_var("neovide_channel_id", Value::from(neovide_channel))
+            .await
+            .ok();
+
+        // Create a command for detaching from a server without quitting it.
+        nvim.command(&build_neovide_command(
+            neovide_channel,
+            0,
+            "NeovideDetach",
+            "detach",
+        ))
+        .await
+        .ok();
+
+        // Create commands for recording the window, relative paths are resolved by Neovim since its
+        // working directory is the one the user sees.
+        nvim.command(&format!(
+            "command! -nargs=? -complete=file NeovideRecordStart call rpcnotify({}, \
+             'neovide.record_start', empty(<q-args>) ? '' : fnamemodify(expand(<q-args>), ':p'))",
+            neovide_channel
+        ))
+        .await
+        .ok();
+        nvim.command(&build_neovide_command(
+            neovide_channel,
+            0,
+            "NeovideRecordStop",
+            "record_stop",
+        ))
+        .await
+        .ok();
+        nvim.command(&format!(
+            "command! -nargs=? NeovideStats call rpcnotify({}, 'neovide.stats', <q-args>)",
+            neovide_channel
+        ))
+        .await
+        .ok();
+        nvim.command(&format!(
+            "command! -nargs=? -complete=file NeovideDumpFrame call rpcnotify({}, \
+             'neovide.dump_frame', empty(<q-args>) ? '' : fnamemodify(expand(<q-args>), ':p'))",
+            neovide_channel
+        ))
+        .await
+        .ok();
+
+        // Create a command for measuring the typing latency.
+        nvim.command(&build_neovide_command(
+            neovide_channel,
+            0,
+            "NeovideMeasureLatency",
+            "measure_latency",
+        ))
+        .await
+        .ok();
+
+        // Create a command for opening another Neovide, in the working directory of this one.
+        nvim.command(&format!(
+            "command! -nargs=? -complete=file NeovideNewWindow call rpcnotify({}, \
+             'neovide.new_window', getcwd(), empty(<q-args>) ? '' : fnamemodify(expand(<q-args>), ':p'))",
+            neovide_channel
+        ))
+        .await
+        .ok();
+
+        // Create a command for toggling zen mode, which is a setting so that it can be set up front
+        // too.
+        nvim.command(
+            "command! NeovideZenMode let g:neovide_zen_mode = !get(g:, 'neovide_zen_mode')",
+        )
+        .await
+        .ok();
+
+        // Create a command for opening the settings panel.
+        nvim.command(&build_neovide_command(
+            neovide_channel,
+            0,
+            "NeovideSettings",
+            "settings",
+        ))
+        .await
+        .ok();
+
+        // Create a command for opening the command palette.
+        nvim.command(&build_neovide_command(
+            neovide_channel,
+            0,
+            "NeovideCommandPalette",
+            "command_palette",
+        ))
+        .await
+        .ok();
+
+        // Create a command for opening the quick open.
+        nvim.command(&build_neovide_command(
+            neovide_channel,
+            0,
+            "NeovideQuickOpen",
+            "quick_open",
+        ))
+        .await
+        .ok();
+
         // Create a command for registering right click context hooking.
         #[cfg(windows)]
         nvim.command(&build_neovide_command(
@@ -114,6 +742,16 @@ pub async fn setup_neovide_specific_state(
         .await
         .ok();
 
+        // Create a command for toggling the terminal drawer.
+        nvim.command(&build_neovide_command(
+            neovide_channel,
+            0,
+            "NeovideTerminal",
+            "terminal",
+        ))
+        .await
+        .ok();
+
         // Create a command for unregistering the right click context hooking.
         #[cfg(windows)]
         nvim.command(&build_neovide_command(
@@ -128,10 +766,42 @@ pub async fn setup_neovide_specific_state(
         if should_handle_clipboard {
             setup_neovide_remote_clipboard(nvim, neovide_channel).await;
         }
+
+        if api_info.supports_lua_integrations() {
+            register_lua_integrations(nvim, neovide_channel).await;
+        } else {
+            warn!(
+                "Neovim api level {} is too old for the Lua integrations, some functionality is \
+                 disabled",
+                api_info.api_level
+            );
+        }
     } else {
         warn!("Neovide could not find the correct channel id. Some functionality may be disabled.");
     }
 
+    // Settings from the config file, applied before the user's init so that it can override them
+    let editor_config = SETTINGS.get::<EditorConfig>();
+    if let Some(font) = editor_config.font {
+        nvim.set_option("guifont", Value::from(font)).await.ok();
+    }
+    if let Some(transparency) = editor_config.transparency {
+        nvim.set_var("neovide_transparency", Value::from(transparency as f64))
+            .await
+            .ok();
+    }
+    if let Some(cursor_vfx_mode) = editor_config.cursor_vfx_mode {
+        nvim.set_var("neovide_cursor_vfx_mode", Value::from(cursor_vfx_mode))
+            .await
+            .ok();
+    }
+
+    for (name, value) in SETTINGS.get::<SavedSettings>().0 {
+        if let Some(value) = toml_to_value(&value) {
+            nvim.set_var(&format!("neovide_{name}"), value).await.ok();
+        }
+    }
+
     // Set some basic rendering options.
     nvim.set_option("lazyredraw", Value::Boolean(false))
         .await
@@ -146,7 +816,6 @@ pub async fn setup_neovide_specific_state(
         .ok();
 }
 
-#[cfg(windows)]
 pub fn build_neovide_command(channel: u64, num_args: u64, command: &str, event: &str) -> String {
     let nargs: String = if num_args > 1 {
         "+".to_string()
@@ -0,0 +1,66 @@
+use log::{error, info};
+use rmpv::Value;
+
+use crate::settings::{config_path, has_persistent_settings, Config, EditorConfig};
+
+/// The wizard is shown when Neovide neither has a config file nor ran before.
+pub fn is_first_run() -> bool {
+    !config_path().exists() && !has_persistent_settings()
+}
+
+/// Reads the table the wizard sent, like `{ font = 'Hack:h14', transparency = 0.9 }`.
+fn parse_choices(choices: &Value) -> EditorConfig {
+    let field = |key: &str| {
+        choices.as_map().and_then(|map| {
+            map.iter()
+                .find(|(name, _)| name.as_str() == Some(key))
+                .map(|(_, value)| value)
+        })
+    };
+    let string = |key: &str| {
+        field(key)
+            .and_then(|value| value.as_str())
+            .map(String::from)
+    };
+    EditorConfig {
+        font: string("font"),
+        transparency: field("transparency")
+            .and_then(|value| value.as_f64().or_else(|| value.as_i64().map(|i| i as f64)))
+            .map(|transparency| transparency as f32),
+        cursor_vfx_mode: string("cursor_vfx_mode"),
+    }
+}
+
+pub fn save_setup_wizard_choices(choices: &Value) {
+    let editor = parse_choices(choices);
+    match Config::save_editor_config(&editor) {
+        Ok(()) => info!(
+            "Saved the setup wizard choices to {}",
+            config_path().to_string_lossy()
+        ),
+        Err(error) => error!("Could not save the setup wizard choices: {}", error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn choices_are_parsed() {
+        let choices = Value::Map(vec![
+            (Value::from("font"), Value::from("Hack:h14")),
+            (Value::from("transparency"), Value::from(1)),
+            (Value::from("cursor_vfx_mode"), Value::from("railgun")),
+        ]);
+        assert_eq!(
+            parse_choices(&choices),
+            EditorConfig {
+                font: Some("Hack:h14".to_string()),
+                transparency: Some(1.0),
+                cursor_vfx_mode: Some("railgun".to_string()),
+            }
+        );
+        assert_eq!(parse_choices(&Value::Map(vec![])), EditorConfig::default());
+    }
+}
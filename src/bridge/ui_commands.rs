@@ -1,18 +1,41 @@
-use std::sync::Arc;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
-#[cfg(windows)]
-use log::error;
-use log::trace;
+use log::{error, trace};
 
 use nvim_rs::{call_args, rpc::model::IntoVal, Neovim};
-use tokio::sync::mpsc::unbounded_channel;
+use rfd::{AsyncMessageDialog, MessageButtons, MessageDialogResult, MessageLevel};
+use rmpv::Value;
+use tokio::{sync::mpsc::unbounded_channel, time::sleep};
 
+#[cfg(target_os = "macos")]
+use crate::bridge::clipboard::warn_about_secure_input;
 #[cfg(windows)]
 use crate::windows_utils::{
     register_rightclick_directory, register_rightclick_file, unregister_rightclick,
 };
 use crate::{
-    bridge::NeovimWriter, event_aggregator::EVENT_AGGREGATOR, running_tracker::RUNNING_TRACKER,
+    bridge::{
+        clipboard::{paste_chunks, paste_phase, paste_preview, PASTE_CHUNK_BYTES},
+        project_files::project_files,
+        NeovimWriter,
+    },
+    clipboard,
+    cmd_line::CmdLineSettings,
+    event_aggregator::EVENT_AGGREGATOR,
+    file_position::FileArgument,
+    i18n::{tr, tr_with},
+    instances,
+    latency_tracker::LATENCY_TRACKER,
+    renderer::user_commands,
+    running_tracker::RUNNING_TRACKER,
+    settings::SETTINGS,
+    window::{WindowCommand, WindowSettings},
 };
 
 // Serial commands are any commands which must complete before the next value is sent. This
@@ -41,6 +64,20 @@ pub enum SerialCommand {
         position: (u32, u32),
         modifier_string: String,
     },
+    YankSelection {
+        cut: bool,
+        fallback: Option<String>,
+    },
+    PasteClipboard,
+    /// Text to insert as a whole, like multiline IME commits, which would trigger mappings and
+    /// autoindent when typed as keys
+    Paste(String),
+    Command(String),
+    ResizeSplit {
+        vertical: bool,
+        separator: (u32, u32),
+        target: u32,
+    },
 }
 
 impl SerialCommand {
@@ -48,7 +85,24 @@ impl SerialCommand {
         match self {
             SerialCommand::Keyboard(input_command) => {
                 trace!("Keyboard Input Sent: {}", input_command);
+                let start = Instant::now();
                 nvim.input(&input_command).await.expect("Input failed");
+                LATENCY_TRACKER.input_sent(start.elapsed());
+            }
+            SerialCommand::ResizeSplit {
+                vertical,
+                separator: (column, row),
+                target,
+            } => {
+                let args = vec![
+                    Value::from(vertical),
+                    Value::from(row),
+                    Value::from(column),
+                    Value::from(target),
+                ];
+                if let Err(error) = nvim.execute_lua(RESIZE_SPLIT_LUA, args).await {
+                    error!("Could not resize the window: {}", error);
+                }
             }
             SerialCommand::MouseButton {
                 button,
@@ -102,13 +156,125 @@ impl SerialCommand {
                 .await
                 .expect("Mouse Drag Failed");
             }
+            SerialCommand::YankSelection { cut, fallback } => {
+                if is_visual_mode(nvim).await {
+                    let keys = if cut { "\"+d" } else { "\"+y" };
+                    nvim.input(keys).await.expect("Yank Selection Failed");
+                } else if let Some(fallback) = fallback {
+                    nvim.input(&fallback).await.expect("Input failed");
+                }
+            }
+            SerialCommand::PasteClipboard => {
+                #[cfg(target_os = "macos")]
+                warn_about_secure_input();
+                match clipboard::get_contents() {
+                    Ok(text) => paste(nvim, &text).await,
+                    Err(error) => {
+                        // Neovim may still have a clipboard provider that works
+                        error!("Could not read the clipboard: {}", error);
+                        let register = nvim
+                            .call_function("getreg", vec![Value::from("+")])
+                            .await
+                            .ok()
+                            .and_then(|text| text.as_str().map(String::from))
+                            .unwrap_or_default();
+                        if !register.is_empty() {
+                            paste(nvim, &register).await;
+                        }
+                    }
+                }
+            }
+            SerialCommand::Paste(text) => paste(nvim, &text).await,
+            SerialCommand::Command(command) => {
+                if let Err(error) = nvim.command(&command).await {
+                    error!("Could not run command {}: {:?}", command, error);
+                }
+            }
         }
     }
 }
 
+/// Sends text to nvim_paste in pieces, so that big pastes show their progress and can be cancelled,
+/// and are undone at once.
+async fn paste(nvim: &Neovim<NeovimWriter>, text: &str) {
+    if !confirm_paste(nvim, text).await {
+        trace!("Paste declined");
+        return;
+    }
+    let chunks = paste_chunks(text, PASTE_CHUNK_BYTES);
+    let count = chunks.len();
+    for (index, chunk) in chunks.into_iter().enumerate() {
+        match nvim.paste(chunk, true, paste_phase(index, count)).await {
+            Ok(true) => {}
+            Ok(false) => {
+                trace!("Paste cancelled after {} of {} pieces", index + 1, count);
+                break;
+            }
+            Err(error) => {
+                error!("Could not paste: {}", error);
+                break;
+            }
+        }
+    }
+}
+
+// Asks before pasting more than `g:neovide_confirm_paste_size` or into the command line, where a
+// newline runs whatever was pasted, using a native dialog. Returns whether to paste.
+async fn confirm_paste(nvim: &Neovim<NeovimWriter>, text: &str) -> bool {
+    let settings = SETTINGS.get::<WindowSettings>();
+    let too_big =
+        settings.confirm_paste_size > 0 && text.len() as u64 > settings.confirm_paste_size * 1024;
+    let into_command_line = settings.confirm_paste_cmdline
+        && current_mode(nvim)
+            .await
+            .map_or(false, |mode| mode.starts_with('c'));
+    if !too_big && !into_command_line {
+        return true;
+    }
+
+    let description = tr_with(
+        "paste-dialog.description",
+        &[
+            ("lines", text.lines().count().to_string()),
+            ("characters", text.chars().count().to_string()),
+        ],
+    );
+    let paste = tr("paste-dialog.paste");
+    let result = AsyncMessageDialog::new()
+        .set_level(MessageLevel::Warning)
+        .set_title(&tr("paste-dialog.title"))
+        .set_description(&format!("{}\n\n{}", description, paste_preview(text)))
+        .set_buttons(MessageButtons::OkCancelCustom(
+            paste.clone(),
+            tr("paste-dialog.cancel"),
+        ))
+        .show()
+        .await;
+
+    match result {
+        MessageDialogResult::Custom(button) => button == paste,
+        MessageDialogResult::Ok => true,
+        _ => false,
+    }
+}
+
+async fn current_mode(nvim: &Neovim<NeovimWriter>) -> Option<String> {
+    nvim.get_mode().await.ok().and_then(|mode| {
+        mode.into_iter()
+            .find(|(key, _)| key.as_str() == Some("mode"))
+            .and_then(|(_, value)| value.as_str().map(String::from))
+    })
+}
+
+async fn is_visual_mode(nvim: &Neovim<NeovimWriter>) -> bool {
+    let mode = current_mode(nvim).await;
+    matches!(mode.as_deref(), Some("v" | "V" | "\u{16}"))
+}
+
 #[derive(Debug, Clone)]
 pub enum ParallelCommand {
     Quit,
+    Detach,
     Resize {
         width: u64,
         height: u64,
@@ -116,8 +282,48 @@ pub enum ParallelCommand {
     FileDrop(String),
     FocusLost,
     FocusGained,
+    MouseHover {
+        grid_id: u64,
+        position: (u32, u32),
+        modifier_string: String,
+    },
+    KeyReleased(String),
     DisplayAvailableFonts(Vec<String>),
     SetBackground(String),
+    SetOsTheme(String),
+    ShowMessage {
+        text: String,
+        is_error: bool,
+    },
+    Save,
+    Zoom(f32),
+    ResetZoom,
+    /// Switches a UI element Neovim can hand over, like `ext_tabline`, without attaching again
+    SetUiOption(String, bool),
+    /// Switches to the tab clicked in the tabline Neovide draws
+    SelectTab(u64),
+    /// Closes the tab with the close button or a middle click
+    CloseTab(u64),
+    /// Moves the dragged tab to where the `target` tab is
+    MoveTab {
+        tab: u64,
+        target: u64,
+    },
+    /// Sets `g:neovide_{name}`, which updates the setting like any other change of the variable
+    SetSetting {
+        name: String,
+        value: Value,
+    },
+    /// Starts another Neovide in the working directory of Neovim
+    NewWindow,
+    SetTypewriterMode(bool),
+    /// Lists the user commands for the command palette, which opens once they're known
+    ShowCommandPalette,
+    /// Lists the files in the working directory of Neovim for the quick open, which opens once
+    /// they're known
+    ShowQuickOpen,
+    /// Edits the file, with the special characters of the path escaped
+    OpenFile(String),
     #[cfg(windows)]
     RegisterRightClick,
     #[cfg(windows)]
@@ -128,11 +334,29 @@ impl ParallelCommand {
     async fn execute(self, nvim: &Neovim<NeovimWriter>) {
         match self {
             ParallelCommand::Quit => {
-                nvim.command(
-                    "if get(g:, 'neovide_confirm_quit', 0) == 1 | confirm qa | else | qa! | endif",
-                )
-                .await
-                .ok();
+                let quit_command = if SETTINGS.get::<WindowSettings>().confirm_quit {
+                    confirm_quit(nvim).await
+                } else {
+                    Some("qa!")
+                };
+
+                if let Some(quit_command) = quit_command {
+                    nvim.command(quit_command).await.ok();
+                }
+            }
+            ParallelCommand::Detach => {
+                // An embedded Neovim can't outlive Neovide, since it's connected through stdio
+                if SETTINGS.get::<CmdLineSettings>().server.is_none() {
+                    nvim.err_writeln(
+                        "NeovideDetach is only available when connected with --server",
+                    )
+                    .await
+                    .ok();
+                    return;
+                }
+
+                nvim.ui_detach().await.ok();
+                RUNNING_TRACKER.quit("detached from neovim");
             }
             ParallelCommand::Resize { width, height } => nvim
                 .ui_try_resize(width.max(10) as i64, height.max(3) as i64)
@@ -147,13 +371,121 @@ impl ParallelCommand {
                 .await
                 .expect("Focus Gained Failed"),
             ParallelCommand::FileDrop(path) => {
-                nvim.command(format!("e {path}").as_str()).await.ok();
+                let file = FileArgument::parse(&path);
+                nvim.command(format!("e {}", file.path).as_str()).await.ok();
+                if let Some(command) = file.cursor_command() {
+                    nvim.command(&command).await.ok();
+                }
             }
             ParallelCommand::SetBackground(background) => {
                 nvim.command(format!("set background={}", background).as_str())
                     .await
                     .ok();
             }
+            ParallelCommand::SetOsTheme(theme) => {
+                nvim.execute_lua(OS_THEME_CHANGED_LUA, vec![Value::from(theme)])
+                    .await
+                    .ok();
+            }
+            ParallelCommand::ShowMessage { text, is_error } => {
+                if is_error {
+                    nvim.err_writeln(&text).await.ok();
+                } else {
+                    nvim.out_write(&format!("{text}\n")).await.ok();
+                }
+            }
+            ParallelCommand::Save => {
+                if let Err(error) = nvim.command("update").await {
+                    nvim.err_writeln(&format!("Could not save: {error}"))
+                        .await
+                        .ok();
+                }
+            }
+            ParallelCommand::Zoom(factor) => {
+                nvim.command(&format!(
+                    "let g:neovide_scale_factor = g:neovide_scale_factor * {factor}"
+                ))
+                .await
+                .ok();
+            }
+            ParallelCommand::ResetZoom => {
+                nvim.command("let g:neovide_scale_factor = 1.0").await.ok();
+            }
+            ParallelCommand::SetUiOption(name, enabled) => {
+                if let Err(error) = nvim.ui_set_option(&name, Value::from(enabled)).await {
+                    error!(
+                        "Could not set the ui option {} to {}: {}",
+                        name, enabled, error
+                    );
+                }
+            }
+            ParallelCommand::SelectTab(tab) => {
+                tab_line_event(nvim, "Clicked", tab, Value::Nil).await;
+            }
+            ParallelCommand::CloseTab(tab) => {
+                tab_line_event(nvim, "Closed", tab, Value::Nil).await;
+            }
+            ParallelCommand::MoveTab { tab, target } => {
+                tab_line_event(nvim, "Moved", tab, Value::from(target)).await;
+            }
+            ParallelCommand::SetTypewriterMode(enabled) => {
+                nvim.execute_lua(TYPEWRITER_MODE_LUA, vec![Value::from(enabled)])
+                    .await
+                    .ok();
+            }
+            ParallelCommand::NewWindow => {
+                let cwd = nvim
+                    .call_function("getcwd", vec![])
+                    .await
+                    .ok()
+                    .and_then(|cwd| cwd.as_str().map(String::from))
+                    .unwrap_or_default();
+                instances::spawn_instance(&cwd, None);
+            }
+            ParallelCommand::ShowCommandPalette => {
+                match nvim.execute_lua(USER_COMMANDS_LUA, vec![]).await {
+                    Ok(commands) => EVENT_AGGREGATOR
+                        .send(WindowCommand::ShowCommandPalette(user_commands(commands))),
+                    Err(error) => error!("Could not list the user commands: {}", error),
+                }
+            }
+            ParallelCommand::ShowQuickOpen => {
+                let cwd = nvim
+                    .call_function("getcwd", vec![])
+                    .await
+                    .ok()
+                    .and_then(|cwd| cwd.as_str().map(String::from))
+                    .unwrap_or_default();
+                let files = project_files(cwd.clone()).await;
+                EVENT_AGGREGATOR.send(WindowCommand::ShowQuickOpen { cwd, files });
+            }
+            ParallelCommand::OpenFile(path) => {
+                if let Err(error) = nvim
+                    .execute_lua(OPEN_FILE_LUA, vec![Value::from(path)])
+                    .await
+                {
+                    nvim.err_writeln(&format!("Could not open the file: {error}"))
+                        .await
+                        .ok();
+                }
+            }
+            ParallelCommand::SetSetting { name, value } => {
+                if let Err(error) = nvim.set_var(&format!("neovide_{name}"), value).await {
+                    error!("Could not set neovide_{}: {}", name, error);
+                }
+            }
+            ParallelCommand::MouseHover {
+                grid_id,
+                position,
+                modifier_string,
+            } => {
+                handle_mouse_hover(nvim, grid_id, position, modifier_string).await;
+            }
+            ParallelCommand::KeyReleased(key) => {
+                nvim.execute_lua(KEY_RELEASED_LUA, vec![Value::from(key)])
+                    .await
+                    .ok();
+            }
             ParallelCommand::DisplayAvailableFonts(fonts) => {
                 let mut content: Vec<String> = vec![
                     "What follows are the font names available for guifont. You can try any of them with <CR> in normal mode.",
@@ -229,6 +561,225 @@ impl ParallelCommand {
     }
 }
 
+// :drop splits the window instead of failing when the current buffer has unsaved changes, and
+// switches to the window the file is already shown in
+const OPEN_FILE_LUA: &str = r"
+    vim.cmd.drop(vim.fn.fnameescape(...))
+";
+
+// The global and the buffer local user commands, as { name, nargs, definition }
+const USER_COMMANDS_LUA: &str = r"
+    local commands = {}
+    local all = vim.tbl_extend(
+        'force', vim.api.nvim_get_commands({}), vim.api.nvim_buf_get_commands(0, {}))
+    for name, command in pairs(all) do
+        table.insert(commands, { name, command.nargs, command.definition or '' })
+    end
+    return commands";
+
+// Minimum time the mouse has to rest on a cell before the move event is sent to Neovim
+const MOUSE_MOVE_THROTTLE: Duration = Duration::from_millis(30);
+
+// Incremented for every mouse hover command, so that the pending events of older ones are dropped
+static MOUSE_HOVER_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+const MOUSE_HOVER_LUA: &str = r"
+    local grid, row, col, modifiers, pattern = ...
+    vim.g.neovide_mouse_position = { grid = grid, row = row, col = col, modifiers = modifiers }
+    vim.api.nvim_exec_autocmds('User', {
+        pattern = pattern,
+        modeline = false,
+        data = vim.g.neovide_mouse_position,
+    })";
+
+// Fires the NeovideMouseMove user autocommand once the mouse stopped moving for a moment, and
+// NeovideMouseHover after it rested on the same cell for the configured hover delay.
+async fn handle_mouse_hover(
+    nvim: &Neovim<NeovimWriter>,
+    grid_id: u64,
+    (grid_x, grid_y): (u32, u32),
+    modifier_string: String,
+) {
+    let generation = MOUSE_HOVER_GENERATION.fetch_add(1, Ordering::Relaxed) + 1;
+    let is_current = || MOUSE_HOVER_GENERATION.load(Ordering::Relaxed) == generation;
+
+    let hover_event = |pattern: &str| {
+        vec![
+            Value::from(grid_id),
+            Value::from(grid_y),
+            Value::from(grid_x),
+            Value::from(modifier_string.as_str()),
+            Value::from(pattern),
+        ]
+    };
+
+    sleep(MOUSE_MOVE_THROTTLE).await;
+    if !is_current() {
+        return;
+    }
+    nvim.execute_lua(MOUSE_HOVER_LUA, hover_event("NeovideMouseMove"))
+        .await
+        .ok();
+
+    let hover_delay =
+        Duration::from_secs_f32(SETTINGS.get::<WindowSettings>().mouse_hover_delay.max(0.0));
+    sleep(hover_delay.saturating_sub(MOUSE_MOVE_THROTTLE)).await;
+    if !is_current() {
+        return;
+    }
+    nvim.execute_lua(MOUSE_HOVER_LUA, hover_event("NeovideMouseHover"))
+        .await
+        .ok();
+}
+
+async fn tab_line_event(nvim: &Neovim<NeovimWriter>, event: &str, tab: u64, target: Value) {
+    let args = vec![Value::from(event), Value::from(tab), target];
+    if let Err(error) = nvim.execute_lua(TAB_LINE_EVENT_LUA, args).await {
+        error!("Could not handle the tabline {}: {}", event, error);
+    }
+}
+
+// Fires the NeovideTabClicked, NeovideTabClosed or NeovideTabMoved user autocommand, for plugins
+// which set the tabline contents, and then does what clicking the tabline of Neovim would
+const TAB_LINE_EVENT_LUA: &str = r"
+    local event, tab, target = ...
+    if not vim.api.nvim_tabpage_is_valid(tab) then
+        return
+    end
+    vim.api.nvim_exec_autocmds('User', {
+        pattern = 'NeovideTab' .. event,
+        modeline = false,
+        data = { tab = tab, target = target },
+    })
+
+    if not vim.api.nvim_tabpage_is_valid(tab) then
+        return
+    elseif event == 'Clicked' then
+        vim.api.nvim_set_current_tabpage(tab)
+    elseif event == 'Closed' then
+        vim.cmd.tabclose(vim.api.nvim_tabpage_get_number(tab))
+    elseif event == 'Moved' and vim.api.nvim_tabpage_is_valid(target) then
+        vim.api.nvim_set_current_tabpage(tab)
+        local number = vim.api.nvim_tabpage_get_number(tab)
+        local target_number = vim.api.nvim_tabpage_get_number(target)
+        -- tabmove puts the current tab after the one with the number
+        vim.cmd.tabmove(target_number > number and target_number or target_number - 1)
+    end";
+
+// Finds the window left of or above the separator by its position, and resizes it so that the
+// separator ends up at the target column or row
+const RESIZE_SPLIT_LUA: &str = r"
+    local vertical, row, col, target = ...
+    for _, win in ipairs(vim.api.nvim_tabpage_list_wins(0)) do
+        if vim.api.nvim_win_get_config(win).relative == '' then
+            local top, left = unpack(vim.api.nvim_win_get_position(win))
+            local height = vim.api.nvim_win_get_height(win)
+            local width = vim.api.nvim_win_get_width(win)
+            if vertical and left + width == col and row >= top and row < top + height then
+                vim.api.nvim_win_set_width(win, math.max(target - left, 1))
+                return
+            elseif not vertical and top + height == row and col >= left and col < left + width then
+                vim.api.nvim_win_set_height(win, math.max(target - top, 1))
+                return
+            end
+        end
+    end";
+
+const KEY_RELEASED_LUA: &str = r"
+    local key = ...
+    vim.api.nvim_exec_autocmds('User', {
+        pattern = 'NeovideKeyReleased',
+        modeline = false,
+        data = { key = key },
+    })";
+
+const OS_THEME_CHANGED_LUA: &str = r"
+    local theme = ...
+    vim.g.neovide_os_theme = theme
+    vim.api.nvim_exec_autocmds('User', {
+        pattern = 'NeovideOsThemeChanged',
+        modeline = false,
+        data = { theme = theme },
+    })";
+
+// Neovim scrolls the cursor line into the middle itself, so nothing is hidden by moving the text.
+// Unlike scrolloff, zz also works at the end of the buffer.
+const TYPEWRITER_MODE_LUA: &str = r"
+    local enabled = ...
+    local group = vim.api.nvim_create_augroup('NeovideTypewriterMode', {})
+    if not enabled then
+        return
+    end
+
+    -- Neovide pads the top of the window where Neovim can't scroll the cursor line to the middle
+    local function center()
+        if vim.api.nvim_win_get_config(0).relative ~= '' then
+            return
+        end
+        -- The middle moves with the height of the window
+        local position = vim.api.nvim_win_get_cursor(0)[1] .. ':' .. vim.api.nvim_win_get_height(0)
+        if vim.w.neovide_typewriter_position ~= position then
+            vim.w.neovide_typewriter_position = position
+            vim.cmd('normal! zz')
+        end
+    end
+
+    local events = { 'CursorMoved', 'CursorMovedI', 'WinEnter', 'VimResized' }
+    if vim.fn.exists('##WinResized') == 1 then
+        table.insert(events, 'WinResized')
+    end
+    vim.api.nvim_create_autocmd(events, {
+        group = group,
+        callback = center,
+    })
+    center()";
+
+// Asks whether modified buffers should be saved before quitting, using a native dialog. Returns the
+// command to run, or None if quitting was cancelled.
+async fn confirm_quit(nvim: &Neovim<NeovimWriter>) -> Option<&'static str> {
+    let modified_buffers = nvim
+        .eval("len(filter(getbufinfo({'bufmodified': 1}), 'v:val.listed'))")
+        .await
+        .ok()
+        .and_then(|count| count.as_u64())
+        .unwrap_or(0);
+
+    if modified_buffers == 0 {
+        return Some("qa");
+    }
+
+    let description = if modified_buffers == 1 {
+        tr("quit-dialog.unsaved-one")
+    } else {
+        tr_with(
+            "quit-dialog.unsaved-other",
+            &[("count", modified_buffers.to_string())],
+        )
+    };
+
+    let save_all = tr("quit-dialog.save-all");
+    let discard = tr("quit-dialog.discard");
+    let result = AsyncMessageDialog::new()
+        .set_level(MessageLevel::Warning)
+        .set_title(&tr("quit-dialog.title"))
+        .set_description(&description)
+        .set_buttons(MessageButtons::YesNoCancelCustom(
+            save_all.clone(),
+            discard.clone(),
+            tr("quit-dialog.cancel"),
+        ))
+        .show()
+        .await;
+
+    match result {
+        MessageDialogResult::Custom(button) if button == save_all => Some("wa | qa"),
+        MessageDialogResult::Custom(button) if button == discard => Some("qa!"),
+        MessageDialogResult::Yes => Some("wa | qa"),
+        MessageDialogResult::No => Some("qa!"),
+        _ => None,
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum UiCommand {
     Serial(SerialCommand),
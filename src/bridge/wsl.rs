@@ -0,0 +1,132 @@
+use std::process::Command as StdCommand;
+
+use crate::i18n::{tr, tr_with};
+
+/// Checks that WSL can be started and has `distro`, or any distribution when none is asked for.
+/// The error is a message for the user, saying how to fix it.
+pub fn check_wsl_distro(distro: Option<&str>) -> Result<(), String> {
+    let mut list_command = StdCommand::new("wsl");
+    list_command.args(["--list", "--quiet"]);
+    // Newer versions of WSL write UTF-8 with this, older ones ignore it
+    list_command.env("WSL_UTF8", "1");
+    #[cfg(windows)]
+    std::os::windows::process::CommandExt::creation_flags(
+        &mut list_command,
+        winapi::um::winbase::CREATE_NO_WINDOW,
+    );
+
+    let output = list_command
+        .output()
+        .map_err(|error| tr_with("error.wsl-unavailable", &[("error", error.to_string())]))?;
+    let stdout = decode_wsl_output(&output.stdout);
+
+    if !output.status.success() {
+        let message = format!(
+            "{} {}",
+            stdout.trim(),
+            decode_wsl_output(&output.stderr).trim()
+        );
+        let message = match message.trim() {
+            "" => output.status.to_string(),
+            message => message.to_owned(),
+        };
+        return Err(tr_with("error.wsl-unavailable", &[("error", message)]));
+    }
+
+    let distros = parse_wsl_distros(&stdout);
+    if distros.is_empty() {
+        return Err(tr("error.wsl-no-distros"));
+    }
+    match distro {
+        Some(distro) if !has_distro(&distros, distro) => Err(tr_with(
+            "error.wsl-unknown-distro",
+            &[
+                ("distro", distro.to_owned()),
+                ("distros", distros.join(", ")),
+            ],
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Decodes the output of wsl.exe. It writes UTF-16 regardless of the code page of the console,
+/// unless `WSL_UTF8` is set or it failed before getting that far, so the encoding is guessed from
+/// the bytes. A trailing odd byte of UTF-16 output is dropped.
+fn decode_wsl_output(bytes: &[u8]) -> String {
+    let text = if is_utf16(bytes) {
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        String::from_utf8_lossy(bytes).into_owned()
+    };
+    text.trim_start_matches('\u{feff}').replace('\0', "")
+}
+
+fn is_utf16(bytes: &[u8]) -> bool {
+    if bytes.starts_with(&[0xff, 0xfe]) {
+        return true;
+    }
+    // ASCII text in UTF-16 has a zero in every second byte, which UTF-8 text never has
+    let pairs = bytes.len() / 2;
+    let zeros = bytes.iter().skip(1).step_by(2).filter(|byte| **byte == 0);
+    pairs > 0 && zeros.count() * 2 >= pairs
+}
+
+fn parse_wsl_distros(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+// WSL doesn't care about the case of the names
+fn has_distro(distros: &[String], distro: &str) -> bool {
+    distros
+        .iter()
+        .any(|installed| installed.eq_ignore_ascii_case(distro))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn decodes_utf16_and_utf8_output() {
+        assert_eq!(
+            decode_wsl_output(&utf16("Ubuntu\r\nDebian\r\n")),
+            "Ubuntu\r\nDebian\r\n"
+        );
+        assert_eq!(decode_wsl_output(&utf16("\u{feff}Ubuntu")), "Ubuntu");
+        assert_eq!(
+            decode_wsl_output("Ubuntu\nDebian\n".as_bytes()),
+            "Ubuntu\nDebian\n"
+        );
+        assert_eq!(decode_wsl_output(&[]), "");
+    }
+
+    #[test]
+    fn odd_length_output_does_not_panic() {
+        let mut bytes = utf16("Ubuntu");
+        bytes.push(b'\n');
+        assert_eq!(decode_wsl_output(&bytes), "Ubuntu");
+        assert_eq!(decode_wsl_output(&[0xff]), "\u{fffd}");
+        assert_eq!(decode_wsl_output(b"a"), "a");
+    }
+
+    #[test]
+    fn finds_installed_distros() {
+        let distros = parse_wsl_distros(&decode_wsl_output(&utf16("Ubuntu-22.04\r\n\r\nArch\r\n")));
+        assert_eq!(distros, vec!["Ubuntu-22.04", "Arch"]);
+        assert!(has_distro(&distros, "ubuntu-22.04"));
+        assert!(!has_distro(&distros, "Ubuntu"));
+    }
+}
@@ -1,18 +1,26 @@
 use std::{iter, mem};
 
-use crate::{dimensions::Dimensions, frame::Frame, settings::*};
+use crate::{
+    benchmark::Workload, dimensions::Dimensions, file_position::neovim_file_args, frame::Frame,
+    settings::*,
+};
 
 use clap::{builder::FalseyValueParser, ArgAction, Parser};
+use winit::dpi::PhysicalPosition;
 
 #[cfg(target_os = "windows")]
 const SRGB_DEFAULT: &str = "1";
 #[cfg(not(target_os = "windows"))]
 const SRGB_DEFAULT: &str = "0";
 
+// Debug builds stay attached to the terminal, so that logs and panics remain visible
+const FORK_DEFAULT: &str = if cfg!(debug_assertions) { "0" } else { "1" };
+
 #[derive(Clone, Debug, Parser)]
 #[command(version, about, long_about = None)]
 pub struct CmdLineSettings {
-    /// Files to open (plainly appended to NeoVim args)
+    /// Files to open (appended to NeoVim args), optionally as `path:line:column`, along with
+    /// `+line` or `+/pattern` to position the cursor in the first one
     #[arg(
         num_args = ..,
         action = ArgAction::Append,
@@ -28,14 +36,18 @@ pub struct CmdLineSettings {
     )]
     pub neovim_args: Vec<String>,
 
-    /// The geometry of the window
-    #[arg(long)]
-    pub geometry: Option<Dimensions>,
+    /// The initial size of the window in grid cells, computed from the font metrics
+    #[arg(long, alias = "geometry", value_name = "COLSxROWS")]
+    pub grid: Option<Dimensions>,
 
-    /// The size of the window in pixel
-    #[arg(long)]
+    /// The initial size of the window in pixels
+    #[arg(long, value_name = "WIDTHxHEIGHT")]
     pub size: Option<Dimensions>,
 
+    /// The initial position of the window in pixels, relative to the top left of the desktop
+    #[arg(long, value_name = "X,Y", value_parser = parse_position, allow_hyphen_values = true)]
+    pub position: Option<PhysicalPosition<i32>>,
+
     /// If to enable logging to a file in the current directory
     #[arg(long = "log")]
     pub log_to_file: bool,
@@ -44,10 +56,94 @@ pub struct CmdLineSettings {
     #[arg(long, alias = "remote-tcp", value_name = "ADDRESS")]
     pub server: Option<String>,
 
+    /// Only detach from the server given with --server when closing the window, leaving it running
+    #[arg(long = "no-kill-on-exit", env = "NEOVIDE_NO_KILL_ON_EXIT", value_parser = FalseyValueParser::new())]
+    pub no_kill_on_exit: bool,
+
     /// Run NeoVim in WSL rather than on the host
     #[arg(long, env = "NEOVIDE_WSL")]
     pub wsl: bool,
 
+    /// Don't start NeoVim on the host with `flatpak-spawn --host` when running inside a Flatpak
+    /// sandbox, but use the one inside the sandbox
+    #[arg(long = "no-host-spawn", env = "NEOVIDE_NO_HOST_SPAWN", value_parser = FalseyValueParser::new())]
+    pub no_host_spawn: bool,
+
+    /// Which WSL distribution to run NeoVim in, instead of the default one
+    #[arg(long = "wsl-distro", env = "NEOVIDE_WSL_DISTRO", value_name = "DISTRO")]
+    pub wsl_distro: Option<String>,
+
+    /// The shell NeoVim is started through in WSL and on macOS, like `fish`, instead of `$SHELL`
+    #[arg(long = "wsl-shell", env = "NEOVIDE_WSL_SHELL", value_name = "SHELL")]
+    pub wsl_shell: Option<String>,
+
+    /// Start NeoVim through a regular shell instead of a login shell in WSL and on macOS, which
+    /// skips the profile scripts
+    #[arg(long = "no-login-shell", env = "NEOVIDE_NO_LOGIN_SHELL", value_parser = FalseyValueParser::new())]
+    pub no_login_shell: bool,
+
+    /// Start NeoVim directly instead of through a shell, for shells that misbehave when started
+    /// that way. NeoVim is looked up on the PATH of Neovide, and gets the environment of Neovide.
+    #[arg(long = "no-shell", env = "NEOVIDE_NO_SHELL", value_parser = FalseyValueParser::new())]
+    pub no_shell: bool,
+
+    /// Which launch profile from the config file to use
+    #[arg(long, env = "NEOVIDE_PROFILE", value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// The background color of the window until NeoVim draws, like `#1e1e2e`, instead of the one
+    /// of the last session
+    #[arg(long, env = "NEOVIDE_BACKGROUND", value_name = "COLOR")]
+    pub background: Option<String>,
+
+    /// Write the time the phases of starting Neovide took to FILE, along with the startup time of
+    /// NeoVim
+    #[arg(long, value_name = "FILE")]
+    pub startuptime: Option<String>,
+
+    /// Render the frame dump or redraw event stream in FILE without a window or NeoVim, write the
+    /// image to a PNG and exit, for regression tests of the renderer
+    #[arg(long = "headless-replay", value_name = "FILE")]
+    pub headless_replay: Option<String>,
+
+    /// Where the image of --headless-replay is written, next to the dump by default
+    #[arg(
+        long = "replay-output",
+        value_name = "FILE",
+        requires = "headless_replay"
+    )]
+    pub replay_output: Option<String>,
+
+    /// Compare the image of --headless-replay with FILE and exit with an error when they differ
+    #[arg(
+        long = "replay-golden",
+        value_name = "FILE",
+        requires = "headless_replay"
+    )]
+    pub replay_golden: Option<String>,
+
+    /// Record the redraw events of NeoVim to FILE along with their timing, for --replay-events
+    #[arg(long = "record-events", value_name = "FILE")]
+    pub record_events: Option<String>,
+
+    /// Play back the redraw events recorded in FILE at their original pace instead of starting
+    /// NeoVim, to benchmark the renderer
+    #[arg(
+        long = "replay-events",
+        value_name = "FILE",
+        conflicts_with = "record_events"
+    )]
+    pub replay_events: Option<String>,
+
+    /// Measure the frame rate of the editor and the renderer for a standard workload without a
+    /// window or NeoVim, then exit
+    #[arg(long, value_name = "WORKLOAD")]
+    pub benchmark: Option<Workload>,
+
+    /// Append the results of --benchmark to FILE as JSON lines
+    #[arg(long = "benchmark-output", value_name = "FILE", requires = "benchmark")]
+    pub benchmark_output: Option<String>,
+
     /// Which window decorations to use (do note that the window might not be resizable
     /// if this is "none")
     #[arg(long, env = "NEOVIDE_FRAME", default_value_t)]
@@ -57,14 +153,48 @@ pub struct CmdLineSettings {
     #[arg(long, env = "NEOVIDE_MAXIMIZED", value_parser = FalseyValueParser::new())]
     pub maximized: bool,
 
+    /// Start the window in fullscreen, same as setting `g:neovide_fullscreen` right away
+    #[arg(long, env = "NEOVIDE_FULLSCREEN", value_parser = FalseyValueParser::new())]
+    pub fullscreen: bool,
+
+    /// Open the session Neovide saved during the last run again, with its working directory and
+    /// files
+    #[arg(long, env = "NEOVIDE_RESTORE_LAST_SESSION", value_parser = FalseyValueParser::new())]
+    pub restore_last_session: bool,
+
     /// Enable the Multigrid extension (enables smooth scrolling and floating blur)
     #[arg(long = "multigrid", env = "NEOVIDE_MULTIGRID", value_parser = FalseyValueParser::new())]
     pub multi_grid: bool,
 
+    /// Disable the Multigrid extension, even when enabled by the environment or the config file
+    #[arg(long = "no-multigrid", action = ArgAction::SetTrue, value_parser = FalseyValueParser::new())]
+    _no_multigrid: bool,
+
+    /// Let Neovide handle the messages instead of Neovim drawing them into the grid, same as
+    /// setting `g:neovide_ext_messages` right away
+    #[arg(long = "ext-messages", env = "NEOVIDE_EXT_MESSAGES", value_parser = FalseyValueParser::new())]
+    pub ext_messages: bool,
+
+    /// Let Neovide handle the command line, same as setting `g:neovide_ext_cmdline` right away
+    #[arg(long = "ext-cmdline", env = "NEOVIDE_EXT_CMDLINE", value_parser = FalseyValueParser::new())]
+    pub ext_cmdline: bool,
+
+    /// Let Neovide handle the popup menu, same as setting `g:neovide_ext_popupmenu` right away
+    #[arg(long = "ext-popupmenu", env = "NEOVIDE_EXT_POPUPMENU", value_parser = FalseyValueParser::new())]
+    pub ext_popupmenu: bool,
+
+    /// Let Neovide handle the tabline, same as setting `g:neovide_ext_tabline` right away
+    #[arg(long = "ext-tabline", env = "NEOVIDE_EXT_TABLINE", value_parser = FalseyValueParser::new())]
+    pub ext_tabline: bool,
+
+    /// Detach from the launching terminal, so that it can be used right away [DEFAULT]
+    #[arg(long = "fork", env = "NEOVIDE_FORK", action = ArgAction::SetTrue, default_value = FORK_DEFAULT, value_parser = FalseyValueParser::new())]
+    pub fork: bool,
+
     /// Instead of spawning a child process and leaking it, be "blocking" and let the shell persist
-    /// as parent process
-    #[arg(long = "nofork")]
-    pub no_fork: bool,
+    /// as parent process until Neovide exits
+    #[arg(long = "no-fork", alias = "nofork", action = ArgAction::SetTrue, value_parser = FalseyValueParser::new())]
+    _no_fork: bool,
 
     /// Render every frame, takes more power and CPU time but possibly helps with frame timing
     /// issues
@@ -72,9 +202,21 @@ pub struct CmdLineSettings {
     pub idle: bool,
 
     /// Disable opening multiple files supplied in tabs (they're still buffers)
-    #[arg(long = "notabs")]
+    #[arg(long = "notabs", group = "layout")]
     pub no_tabs: bool,
 
+    /// Open the files supplied in horizontal splits
+    #[arg(short = 'o', group = "layout")]
+    pub horizontal_splits: bool,
+
+    /// Open the files supplied in vertical splits
+    #[arg(short = 'O', group = "layout")]
+    pub vertical_splits: bool,
+
+    /// Open the files supplied in tabs [DEFAULT]
+    #[arg(short = 'p', group = "layout")]
+    pub tabs: bool,
+
     /// Request sRGB when initializing the window, may help with GPUs with weird pixel
     /// formats. Default on Windows.
     #[arg(long = "srgb", env = "NEOVIDE_SRGB", action = ArgAction::SetTrue, default_value = SRGB_DEFAULT, value_parser = FalseyValueParser::new())]
@@ -128,18 +270,51 @@ impl Default for CmdLineSettings {
     }
 }
 
+fn parse_position(s: &str) -> Result<PhysicalPosition<i32>, String> {
+    let invalid_parse_err = format!("Invalid position: {s}\nValid format: <x>,<y>");
+
+    match s.split_once(',') {
+        Some((x, y)) => match (x.trim().parse(), y.trim().parse()) {
+            (Ok(x), Ok(y)) => Ok(PhysicalPosition::new(x, y)),
+            _ => Err(invalid_parse_err),
+        },
+        None => Err(invalid_parse_err),
+    }
+}
+
 pub fn handle_command_line_arguments(args: Vec<String>) -> Result<(), String> {
     let mut cmdline = CmdLineSettings::parse_from(args);
 
     // The neovim_args in cmdline are unprocessed, actually add options to it
-    let maybe_tab_flag = (!cmdline.no_tabs).then(|| "-p".to_string());
+    let maybe_layout_flag = if cmdline.horizontal_splits {
+        Some("-o".to_string())
+    } else if cmdline.vertical_splits {
+        Some("-O".to_string())
+    } else if cmdline.tabs || !cmdline.no_tabs {
+        Some("-p".to_string())
+    } else {
+        None
+    };
+
+    let startuptime_args = cmdline
+        .startuptime
+        .iter()
+        .flat_map(|path| ["--startuptime".to_string(), path.clone()]);
 
-    cmdline.neovim_args = maybe_tab_flag
-        .into_iter()
-        .chain(mem::take(&mut cmdline.files_to_open))
+    cmdline.neovim_args = startuptime_args
+        .chain(maybe_layout_flag)
+        .chain(neovim_file_args(mem::take(&mut cmdline.files_to_open)))
         .chain(cmdline.neovim_args)
         .collect();
 
+    if cmdline._no_fork {
+        cmdline.fork = false;
+    }
+
+    if cmdline._no_multigrid {
+        cmdline.multi_grid = false;
+    }
+
     if cmdline._novsync {
         cmdline.vsync = false;
     }
@@ -174,6 +349,40 @@ mod tests {
         );
     }
 
+    #[test]
+    #[serial]
+    fn test_neovim_passthrough_is_verbatim() {
+        let args: Vec<String> = vec![
+            "neovide",
+            "--notabs",
+            "--",
+            "-u",
+            "NONE",
+            "--clean",
+            "-c",
+            "echo 'hi there'",
+            "--size=10x10",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        let settings = SETTINGS.get::<CmdLineSettings>();
+        assert_eq!(
+            settings.neovim_args,
+            vec![
+                "-u",
+                "NONE",
+                "--clean",
+                "-c",
+                "echo 'hi there'",
+                "--size=10x10"
+            ]
+        );
+        assert_eq!(settings.size, None);
+    }
+
     #[test]
     #[serial]
     fn test_files_to_open() {
@@ -226,7 +435,7 @@ mod tests {
         );
 
         assert_eq!(
-            SETTINGS.get::<CmdLineSettings>().geometry,
+            SETTINGS.get::<CmdLineSettings>().grid,
             Some(Dimensions {
                 width: 42,
                 height: 24
@@ -234,6 +443,119 @@ mod tests {
         );
     }
 
+    #[test]
+    #[serial]
+    fn test_startuptime() {
+        let args: Vec<String> = vec!["neovide", "--startuptime", "startup.log", "./foo.txt"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert_eq!(
+            SETTINGS.get::<CmdLineSettings>().neovim_args,
+            vec!["--startuptime", "startup.log", "-p", "./foo.txt"]
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_headless_replay() {
+        let args: Vec<String> = vec![
+            "neovide",
+            "--headless-replay",
+            "frame.json",
+            "--replay-golden",
+            "golden.png",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        let settings = SETTINGS.get::<CmdLineSettings>();
+        assert_eq!(settings.headless_replay, Some("frame.json".to_string()));
+        assert_eq!(settings.replay_golden, Some("golden.png".to_string()));
+        assert_eq!(settings.replay_output, None);
+    }
+
+    #[test]
+    fn test_replay_output_needs_headless_replay() {
+        let args: Vec<String> = vec!["neovide", "--replay-output", "frame.png"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        assert!(CmdLineSettings::try_parse_from(args).is_err());
+    }
+
+    #[test]
+    fn test_record_and_replay_events_conflict() {
+        let args: Vec<String> = vec![
+            "neovide",
+            "--record-events",
+            "events.msgpack",
+            "--replay-events",
+            "events.msgpack",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+
+        assert!(CmdLineSettings::try_parse_from(args).is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn test_benchmark() {
+        let args: Vec<String> = vec!["neovide", "--benchmark", "terminal-cat"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert_eq!(
+            SETTINGS.get::<CmdLineSettings>().benchmark,
+            Some(Workload::TerminalCat)
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_files_to_open_in_splits() {
+        let args: Vec<String> = vec!["neovide", "-O", "./foo.txt", "+42", "./bar.md"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert_eq!(
+            SETTINGS.get::<CmdLineSettings>().neovim_args,
+            vec!["-O", "./foo.txt", "+42", "./bar.md"]
+        );
+
+        let args: Vec<String> = vec!["neovide", "-o", "+/pattern", "./foo.txt"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert_eq!(
+            SETTINGS.get::<CmdLineSettings>().neovim_args,
+            vec!["-o", "+/pattern", "./foo.txt"]
+        );
+    }
+
+    #[test]
+    fn test_conflicting_layouts() {
+        let args: Vec<String> = vec!["neovide", "-o", "--notabs", "./foo.txt"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        assert!(CmdLineSettings::try_parse_from(args).is_err());
+    }
+
     #[test]
     #[serial]
     fn test_geometry() {
@@ -244,7 +566,7 @@ mod tests {
 
         handle_command_line_arguments(args).expect("Could not parse arguments");
         assert_eq!(
-            SETTINGS.get::<CmdLineSettings>().geometry,
+            SETTINGS.get::<CmdLineSettings>().grid,
             Some(Dimensions {
                 width: 42,
                 height: 24
@@ -252,6 +574,24 @@ mod tests {
         );
     }
 
+    #[test]
+    #[serial]
+    fn test_grid() {
+        let args: Vec<String> = vec!["neovide", "--grid=80x25"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert_eq!(
+            SETTINGS.get::<CmdLineSettings>().grid,
+            Some(Dimensions {
+                width: 80,
+                height: 25
+            }),
+        );
+    }
+
     #[test]
     #[serial]
     fn test_size() {
@@ -270,6 +610,105 @@ mod tests {
         );
     }
 
+    #[test]
+    #[serial]
+    fn test_position() {
+        let args: Vec<String> = vec!["neovide", "--position", "-10,200"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert_eq!(
+            SETTINGS.get::<CmdLineSettings>().position,
+            Some(PhysicalPosition::new(-10, 200)),
+        );
+    }
+
+    #[test]
+    fn test_invalid_position() {
+        assert!(parse_position("10x200").is_err());
+        assert!(parse_position("10,").is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn test_fullscreen() {
+        let args: Vec<String> = vec!["neovide", "--fullscreen"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert!(SETTINGS.get::<CmdLineSettings>().fullscreen);
+    }
+
+    #[test]
+    #[serial]
+    fn test_restore_last_session() {
+        let args: Vec<String> = vec!["neovide", "--restore-last-session"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert!(SETTINGS.get::<CmdLineSettings>().restore_last_session);
+    }
+
+    #[test]
+    #[serial]
+    fn test_fork() {
+        let args: Vec<String> = vec!["neovide", "--fork"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert!(SETTINGS.get::<CmdLineSettings>().fork);
+    }
+
+    #[test]
+    #[serial]
+    fn test_no_fork() {
+        for flag in ["--no-fork", "--nofork"] {
+            let args: Vec<String> = vec!["neovide", flag]
+                .iter()
+                .map(|s| s.to_string())
+                .collect();
+
+            handle_command_line_arguments(args).expect("Could not parse arguments");
+            assert!(!SETTINGS.get::<CmdLineSettings>().fork);
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn test_override_fork_environment() {
+        let args: Vec<String> = vec!["neovide", "--no-fork"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let _env = ScopedEnv::set("NEOVIDE_FORK", "1");
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert!(!SETTINGS.get::<CmdLineSettings>().fork);
+    }
+
+    #[test]
+    #[serial]
+    fn test_profile() {
+        let args: Vec<String> = vec!["neovide", "--profile", "work"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert_eq!(
+            SETTINGS.get::<CmdLineSettings>().profile,
+            Some("work".to_owned())
+        );
+    }
+
     #[test]
     #[serial]
     fn test_log_to_file() {
@@ -282,6 +721,18 @@ mod tests {
         assert!(SETTINGS.get::<CmdLineSettings>().log_to_file);
     }
 
+    #[test]
+    #[serial]
+    fn test_no_kill_on_exit() {
+        let args: Vec<String> = vec!["neovide", "--server=127.0.0.1:7777", "--no-kill-on-exit"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert!(SETTINGS.get::<CmdLineSettings>().no_kill_on_exit);
+    }
+
     #[test]
     #[serial]
     fn test_frameless_flag() {
@@ -405,6 +856,19 @@ mod tests {
         assert_eq!(SETTINGS.get::<CmdLineSettings>().srgb, true,);
     }
 
+    #[test]
+    #[serial]
+    fn test_override_multigrid_environment() {
+        let args: Vec<String> = vec!["neovide", "--no-multigrid"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let _env = ScopedEnv::set("NEOVIDE_MULTIGRID", "1");
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert!(!SETTINGS.get::<CmdLineSettings>().multi_grid);
+    }
+
     #[test]
     #[serial]
     fn test_vsync_default() {
@@ -24,7 +24,10 @@ impl DrawCommandBatcher {
     }
 
     pub fn send_batch(&self) {
-        let batch: Vec<DrawCommand> = self.window_draw_command_receiver.try_iter().collect();
-        EVENT_AGGREGATOR.send(batch);
+        EVENT_AGGREGATOR.send(self.take_batch());
+    }
+
+    pub fn take_batch(&self) -> Vec<DrawCommand> {
+        self.window_draw_command_receiver.try_iter().collect()
     }
 }
@@ -0,0 +1,462 @@
+//! Writes what the editor shows to a JSON file for bug reports. Besides a readable description of
+//! the grids and highlights, the dump contains redraw events in the format Neovim sends them,
+//! which reproduce the frame when fed to the editor again, without Neovim.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use rmpv::Value;
+use serde::Serialize;
+use serde_json::json;
+use skia_safe::Color4f;
+
+use crate::{
+    bridge::{parse_redraw_event, RedrawEvent, WindowAnchor},
+    editor::{grid::GridCell, Colors, Editor, Style, UnderlineStyle},
+    renderer::DrawCommand,
+};
+
+#[derive(Serialize)]
+struct FrameDump {
+    version: &'static str,
+    default_colors: Option<ColorsDump>,
+    cursor: CursorDump,
+    highlights: Vec<HighlightDump>,
+    windows: Vec<WindowDump>,
+    /// Neovim redraw events reproducing the frame, see `replay_events`
+    redraw: Vec<serde_json::Value>,
+    /// The draw commands the renderer gets for the frame, for reading only
+    draw_commands: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct ColorsDump {
+    foreground: Option<String>,
+    background: Option<String>,
+    special: Option<String>,
+}
+
+#[derive(Serialize)]
+struct CursorDump {
+    grid: u64,
+    row: u64,
+    column: u64,
+}
+
+#[derive(Serialize)]
+struct HighlightDump {
+    id: u64,
+    colors: ColorsDump,
+    reverse: bool,
+    italic: bool,
+    bold: bool,
+    strikethrough: bool,
+    blend: u8,
+    underline: Option<String>,
+    groups: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct WindowDump {
+    grid: u64,
+    position: (f64, f64),
+    size: (u64, u64),
+    floating: bool,
+    lines: Vec<String>,
+}
+
+/// Writes the dump of the current frame to `path`, or to a new file in the working directory when
+/// it's empty. The draw commands come from redrawing every window, so they are the complete ones
+/// and not just what changed last.
+pub fn dump_frame(
+    editor: &Editor,
+    draw_commands: &[DrawCommand],
+    path: &str,
+) -> Result<String, String> {
+    let path = if path.is_empty() {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        PathBuf::from(format!("neovide-frame-{timestamp}.json"))
+    } else {
+        PathBuf::from(path)
+    };
+
+    let dump = FrameDump {
+        version: env!("CARGO_PKG_VERSION"),
+        default_colors: editor.default_colors.as_ref().map(colors_dump),
+        cursor: CursorDump {
+            grid: editor.cursor.parent_window_id,
+            row: editor.cursor.grid_position.1,
+            column: editor.cursor.grid_position.0,
+        },
+        highlights: sorted_styles(editor)
+            .into_iter()
+            .map(|(id, style)| highlight_dump(id, style))
+            .collect(),
+        windows: sorted_grids(editor)
+            .into_iter()
+            .map(|grid| {
+                let window = &editor.windows[&grid];
+                WindowDump {
+                    grid,
+                    position: window.get_grid_position(),
+                    size: (window.get_width(), window.get_height()),
+                    floating: window.anchor_info.is_some(),
+                    lines: window
+                        .get_text_lines()
+                        .into_iter()
+                        .map(|line| line.concat())
+                        .collect(),
+                }
+            })
+            .collect(),
+        redraw: redraw_events(editor),
+        draw_commands: draw_commands
+            .iter()
+            .map(|command| format!("{command:?}"))
+            .collect(),
+    };
+
+    let json = serde_json::to_string_pretty(&dump).map_err(|error| error.to_string())?;
+    fs::write(&path, json)
+        .map_err(|error| format!("Could not write {}: {}", path.display(), error))?;
+    Ok(format!("Frame written to {}", path.display()))
+}
+
+/// Parses the redraw events of a dump, to feed them to an editor as `NeovimRedrawEvent`s when
+/// reproducing a rendering bug. A plain list of redraw events, as Neovim sends them, works too.
+pub fn replay_events(dump: &str) -> Result<Vec<RedrawEvent>, String> {
+    let dump: serde_json::Value = serde_json::from_str(dump).map_err(|error| error.to_string())?;
+    let events = dump
+        .as_array()
+        .or_else(|| dump["redraw"].as_array())
+        .ok_or_else(|| "The dump has no redraw events".to_string())?;
+    let mut parsed_events = Vec::new();
+    for event in events {
+        let parsed = parse_redraw_event(json_to_value(event)).map_err(|error| error.to_string())?;
+        parsed_events.extend(parsed);
+    }
+    Ok(parsed_events)
+}
+
+fn sorted_styles(editor: &Editor) -> Vec<(u64, &Arc<Style>)> {
+    let mut styles: Vec<_> = editor
+        .defined_styles
+        .iter()
+        .map(|(id, style)| (*id, style))
+        .collect();
+    styles.sort_by_key(|(id, _)| *id);
+    styles
+}
+
+// The root grid first, so that the windows are positioned on it when replaying
+fn sorted_grids(editor: &Editor) -> Vec<u64> {
+    let mut grids: Vec<u64> = editor.windows.keys().copied().collect();
+    grids.sort_unstable();
+    grids
+}
+
+fn redraw_events(editor: &Editor) -> Vec<serde_json::Value> {
+    let mut events = Vec::new();
+
+    // The font first, since the size of the cells depends on it
+    if let Some(guifont) = &editor.guifont {
+        events.push(json!(["option_set", ["guifont", guifont]]));
+    }
+    if let Some(linespace) = editor.linespace {
+        events.push(json!(["option_set", ["linespace", linespace]]));
+    }
+
+    if let Some(colors) = &editor.default_colors {
+        let packed = |color: Option<Color4f>| color.map(pack_color).unwrap_or(-1);
+        events.push(json!([
+            "default_colors_set",
+            [
+                packed(colors.foreground),
+                packed(colors.background),
+                packed(colors.special),
+                -1,
+                -1
+            ]
+        ]));
+    }
+
+    // Cells keep the style they were drawn with, which is looked up again by its id
+    let mut style_ids = HashMap::new();
+    for (id, style) in sorted_styles(editor) {
+        style_ids.insert(Arc::as_ptr(style), id);
+        events.push(hl_attr_define(id, style));
+    }
+    let style_id = |cell: &GridCell| match &cell.1 {
+        None => 0,
+        Some(style) => style_ids
+            .get(&Arc::as_ptr(style))
+            .copied()
+            .or_else(|| {
+                sorted_styles(editor)
+                    .into_iter()
+                    .find(|(_, defined)| *defined == style)
+                    .map(|(id, _)| id)
+            })
+            .unwrap_or(0),
+    };
+
+    let window_handles: HashMap<u64, u64> = editor
+        .window_grids
+        .iter()
+        .map(|(window, grid)| (*grid, *window))
+        .collect();
+
+    for grid in sorted_grids(editor) {
+        let window = &editor.windows[&grid];
+        let (width, height) = (window.get_width(), window.get_height());
+        events.push(json!(["grid_resize", [grid, width, height]]));
+
+        let handle = window_handles.get(&grid).copied().unwrap_or(grid);
+        let (column, row) = window.get_grid_position();
+        match &window.anchor_info {
+            Some(anchor) => events.push(json!([
+                "win_float_pos",
+                [
+                    grid,
+                    handle,
+                    anchor_name(&anchor.anchor_type),
+                    anchor.anchor_grid_id,
+                    anchor.anchor_top,
+                    anchor.anchor_left,
+                    true,
+                    anchor.sort_order
+                ]
+            ])),
+            None if grid != 1 => events.push(json!([
+                "win_pos",
+                [grid, handle, row as u64, column as u64, width, height]
+            ])),
+            None => {}
+        }
+
+        for row in 0..height {
+            if let Some(cells) = window.row(row) {
+                let cells: Vec<serde_json::Value> = cells
+                    .iter()
+                    .map(|cell| json!([cell.0, style_id(cell)]))
+                    .collect();
+                events.push(json!(["grid_line", [grid, row, 0, cells]]));
+            }
+        }
+    }
+
+    let (column, row) = editor.cursor.grid_position;
+    events.push(json!([
+        "grid_cursor_goto",
+        [editor.cursor.parent_window_id, row, column]
+    ]));
+    events.push(json!(["flush", []]));
+    events
+}
+
+// The highlight groups are given as the info of ext_hlstate
+fn hl_attr_define(id: u64, style: &Style) -> serde_json::Value {
+    let info: Vec<serde_json::Value> = style
+        .highlight_groups
+        .iter()
+        .map(|group| json!({ "hi_name": group }))
+        .collect();
+    json!(["hl_attr_define", [id, style_attributes(style), {}, info]])
+}
+
+fn style_attributes(style: &Style) -> serde_json::Value {
+    let mut attributes = serde_json::Map::new();
+    let colors = [
+        ("foreground", style.colors.foreground),
+        ("background", style.colors.background),
+        ("special", style.colors.special),
+    ];
+    for (name, color) in colors {
+        if let Some(color) = color {
+            attributes.insert(name.to_string(), json!(pack_color(color)));
+        }
+    }
+    let flags = [
+        ("reverse", style.reverse),
+        ("italic", style.italic),
+        ("bold", style.bold),
+        ("strikethrough", style.strikethrough),
+    ];
+    for (name, enabled) in flags {
+        if enabled {
+            attributes.insert(name.to_string(), json!(true));
+        }
+    }
+    if style.blend > 0 {
+        attributes.insert("blend".to_string(), json!(style.blend));
+    }
+    if let Some(underline) = style.underline {
+        attributes.insert(underline_name(underline).to_string(), json!(true));
+    }
+    serde_json::Value::Object(attributes)
+}
+
+fn highlight_dump(id: u64, style: &Style) -> HighlightDump {
+    HighlightDump {
+        id,
+        colors: colors_dump(&style.colors),
+        reverse: style.reverse,
+        italic: style.italic,
+        bold: style.bold,
+        strikethrough: style.strikethrough,
+        blend: style.blend,
+        underline: style
+            .underline
+            .map(|underline| underline_name(underline).to_string()),
+        groups: style.highlight_groups.clone(),
+    }
+}
+
+fn colors_dump(colors: &Colors) -> ColorsDump {
+    let hex = |color: Option<Color4f>| color.map(|color| format!("#{:06x}", pack_color(color)));
+    ColorsDump {
+        foreground: hex(colors.foreground),
+        background: hex(colors.background),
+        special: hex(colors.special),
+    }
+}
+
+// The reverse of how Neovim's packed 0xRRGGBB colors are unpacked
+fn pack_color(color: Color4f) -> i64 {
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as i64;
+    (channel(color.r) << 16) | (channel(color.g) << 8) | channel(color.b)
+}
+
+fn underline_name(underline: UnderlineStyle) -> &'static str {
+    match underline {
+        UnderlineStyle::Underline => "underline",
+        UnderlineStyle::UnderDouble => "underdouble",
+        UnderlineStyle::UnderDash => "underdashed",
+        UnderlineStyle::UnderDot => "underdotted",
+        UnderlineStyle::UnderCurl => "undercurl",
+    }
+}
+
+fn anchor_name(anchor: &WindowAnchor) -> &'static str {
+    match anchor {
+        WindowAnchor::NorthWest => "NW",
+        WindowAnchor::NorthEast => "NE",
+        WindowAnchor::SouthWest => "SW",
+        WindowAnchor::SouthEast => "SE",
+    }
+}
+
+fn json_to_value(json: &serde_json::Value) -> Value {
+    match json {
+        serde_json::Value::Null => Value::Nil,
+        serde_json::Value::Bool(value) => Value::from(*value),
+        serde_json::Value::Number(number) => {
+            if let Some(value) = number.as_u64() {
+                Value::from(value)
+            } else if let Some(value) = number.as_i64() {
+                Value::from(value)
+            } else {
+                Value::from(number.as_f64().unwrap_or_default())
+            }
+        }
+        serde_json::Value::String(value) => Value::from(value.as_str()),
+        serde_json::Value::Array(values) => {
+            Value::Array(values.iter().map(json_to_value).collect())
+        }
+        serde_json::Value::Object(map) => Value::Map(
+            map.iter()
+                .map(|(key, value)| (Value::from(key.as_str()), json_to_value(value)))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bridge::GuiOption;
+
+    #[test]
+    fn styles_survive_the_round_trip() {
+        let mut style = Style::new(Colors::new(
+            Some(Color4f::new(1.0, 0.0, 0.0, 1.0)),
+            None,
+            Some(Color4f::new(0.0, 0.0, 1.0, 1.0)),
+        ));
+        style.bold = true;
+        style.underline = Some(UnderlineStyle::UnderCurl);
+        style.highlight_groups = vec!["Error".to_string()];
+
+        let dump = json!({ "redraw": [hl_attr_define(7, &style)] }).to_string();
+        match replay_events(&dump).unwrap().as_slice() {
+            [RedrawEvent::HighlightAttributesDefine { id, style: parsed }] => {
+                assert_eq!(*id, 7);
+                assert_eq!(parsed, &style);
+            }
+            events => panic!("Unexpected events {events:?}"),
+        }
+    }
+
+    #[test]
+    fn grid_lines_keep_their_highlights() {
+        let cells = vec![json!(["a", 3]), json!(["", 0])];
+        let event = json!(["grid_line", [2, 5, 0, cells]]);
+        let dump = json!({ "redraw": [event, ["flush", []]] }).to_string();
+        match replay_events(&dump).unwrap().as_slice() {
+            [RedrawEvent::GridLine {
+                grid: 2,
+                row: 5,
+                column_start: 0,
+                cells,
+            }, RedrawEvent::Flush] => {
+                assert_eq!(cells[0].text, "a");
+                assert_eq!(cells[0].highlight_id, Some(3));
+                assert_eq!(cells[1].highlight_id, Some(0));
+            }
+            events => panic!("Unexpected events {events:?}"),
+        }
+    }
+
+    #[test]
+    fn event_streams_are_replayed_too() {
+        let stream = json!([["grid_cursor_goto", [1, 2, 3]], ["flush", []]]).to_string();
+        match replay_events(&stream).unwrap().as_slice() {
+            [RedrawEvent::CursorGoto {
+                grid: 1,
+                row: 2,
+                column: 3,
+            }, RedrawEvent::Flush] => {}
+            events => panic!("Unexpected events {events:?}"),
+        }
+    }
+
+    #[test]
+    fn font_options_are_replayed() {
+        let mut editor = Editor::new();
+        editor.guifont = Some("Fira Code:h14".to_string());
+        editor.linespace = Some(2);
+
+        let dump = json!({ "redraw": redraw_events(&editor) }).to_string();
+        match replay_events(&dump).unwrap().as_slice() {
+            [RedrawEvent::OptionSet {
+                gui_option: GuiOption::GuiFont(guifont),
+            }, RedrawEvent::OptionSet {
+                gui_option: GuiOption::LineSpace(2),
+            }, ..] => assert_eq!(guifont, "Fira Code:h14"),
+            events => panic!("Unexpected events {events:?}"),
+        }
+    }
+
+    #[test]
+    fn colors_are_packed_like_neovim() {
+        assert_eq!(pack_color(Color4f::new(1.0, 0.5, 0.0, 1.0)), 0xff8000);
+    }
+}
@@ -1,5 +1,6 @@
 mod cursor;
 mod draw_command_batcher;
+mod frame_dump;
 mod grid;
 mod style;
 mod window;
@@ -9,22 +10,34 @@ use std::{collections::HashMap, sync::Arc, thread};
 use log::{error, trace};
 
 use crate::{
-    bridge::{GuiOption, RedrawEvent, WindowAnchor},
+    bridge::{
+        EditorMode, GuiOption, ParallelCommand, PopupMenuItem, RedrawEvent, SearchCount,
+        TabContents, TabLineTab, UiCommand, WindowAnchor, WindowBackground,
+    },
     event_aggregator::EVENT_AGGREGATOR,
+    latency_tracker::LATENCY_TRACKER,
     profiling::tracy_zone,
     redraw_scheduler::REDRAW_SCHEDULER,
-    renderer::DrawCommand,
-    window::WindowCommand,
+    renderer::{DrawCommand, PopupMenu, PopupMenuStyles, RendererSettings, TabLineStyles},
+    settings::SETTINGS,
+    startup_time::STARTUP_TIME,
+    window::{AccessibilityUpdate, AccessibleGrid, WindowCommand, WindowSettings},
 };
 
 pub use cursor::{Cursor, CursorMode, CursorShape};
 pub use draw_command_batcher::DrawCommandBatcher;
+pub use frame_dump::replay_events;
 pub use grid::CharacterGrid;
 pub use style::{Colors, Style, UnderlineStyle};
 pub use window::*;
 
 const MODE_CMDLINE: u64 = 4;
 
+// The builtin highlight groups of the rows drawn as fancy status lines
+const STATUS_LINE_GROUPS: [&str; 4] = ["StatusLine", "StatusLineNC", "WinBar", "WinBarNC"];
+const POPUP_MENU_GROUPS: [&str; 4] = ["Pmenu", "PmenuSel", "PmenuSbar", "PmenuThumb"];
+const TAB_LINE_GROUPS: [&str; 3] = ["TabLine", "TabLineSel", "TabLineFill"];
+
 #[derive(Clone, Debug)]
 pub struct AnchorInfo {
     pub anchor_grid_id: u64,
@@ -54,27 +67,57 @@ impl WindowAnchor {
 #[derive(Clone, Debug)]
 pub enum EditorCommand {
     NeovimRedrawEvent(RedrawEvent),
+    SetWindowBackgrounds(Vec<WindowBackground>),
+    SetSearchCount(Option<SearchCount>),
+    SetTabContents(Option<Vec<TabContents>>),
+    SetModeBadgeState {
+        recording: Option<String>,
+        operator: Option<String>,
+    },
     RedrawScreen,
+    DumpFrame(String),
 }
 
 pub struct Editor {
     pub windows: HashMap<u64, Window>,
+    pub window_grids: HashMap<u64, u64>,
     pub cursor: Cursor,
     pub defined_styles: HashMap<u64, Arc<Style>>,
+    pub default_colors: Option<Colors>,
+    status_line_groups: HashMap<String, u64>,
+    popup_menu_groups: HashMap<String, u64>,
+    tab_line_groups: HashMap<String, u64>,
     pub mode_list: Vec<CursorMode>,
     pub draw_command_batcher: Arc<DrawCommandBatcher>,
     pub current_mode_index: Option<u64>,
+    pub current_mode: EditorMode,
+    // The last font options Neovim set, which the frame dumps reproduce
+    guifont: Option<String>,
+    linespace: Option<i64>,
+    // The text lines of each grid, only rebuilt for the grids which changed
+    accessible_lines: HashMap<u64, Arc<Vec<Vec<String>>>>,
+    last_accessibility_update: Option<AccessibilityUpdate>,
 }
 
 impl Editor {
     pub fn new() -> Editor {
         Editor {
             windows: HashMap::new(),
+            window_grids: HashMap::new(),
             cursor: Cursor::new(),
             defined_styles: HashMap::new(),
+            default_colors: None,
+            status_line_groups: HashMap::new(),
+            popup_menu_groups: HashMap::new(),
+            tab_line_groups: HashMap::new(),
             mode_list: Vec::new(),
             draw_command_batcher: Arc::new(DrawCommandBatcher::new()),
             current_mode_index: None,
+            current_mode: EditorMode::Unknown(String::from("")),
+            guifont: None,
+            linespace: None,
+            accessible_lines: HashMap::new(),
+            last_accessibility_update: None,
         }
     }
 
@@ -85,6 +128,10 @@ impl Editor {
                     tracy_zone!("EditorSetTitle");
                     EVENT_AGGREGATOR.send(WindowCommand::TitleChanged(title));
                 }
+                RedrawEvent::SetIcon { icon } => {
+                    tracy_zone!("EditorSetIcon");
+                    EVENT_AGGREGATOR.send(WindowCommand::IconChanged(icon));
+                }
                 RedrawEvent::ModeInfoSet { cursor_modes } => {
                     tracy_zone!("EditorModeInfoSet");
                     self.mode_list = cursor_modes;
@@ -106,6 +153,7 @@ impl Editor {
                     } else {
                         self.current_mode_index = None
                     }
+                    self.current_mode = mode.clone();
                     self.draw_command_batcher
                         .queue(DrawCommand::ModeChanged(mode))
                         .ok();
@@ -130,19 +178,32 @@ impl Editor {
                 }
                 RedrawEvent::Flush => {
                     tracy_zone!("EditorFlush");
+                    STARTUP_TIME.record("first flush");
                     trace!("Image flushed");
+                    self.update_ligatures();
+                    let extend_highlights =
+                        SETTINGS.get::<WindowSettings>().padding_extend_highlights;
+                    for window in self.windows.values_mut() {
+                        window.flush();
+                        if extend_highlights {
+                            window.send_edges();
+                        }
+                    }
                     self.send_cursor_info();
+                    self.send_accessibility_update();
                     {
                         trace!("send_batch");
                         self.draw_command_batcher.send_batch();
                     }
                     {
                         trace!("queue_next_frame");
-                        REDRAW_SCHEDULER.queue_next_frame();
+                        LATENCY_TRACKER.redraw_flushed();
+                        REDRAW_SCHEDULER.redraw_flushed();
                     }
                 }
                 RedrawEvent::DefaultColorsSet { colors } => {
                     tracy_zone!("EditorDefaultColorsSet");
+                    self.default_colors = Some(colors.clone());
                     self.draw_command_batcher
                         .queue(DrawCommand::DefaultStyleChanged(Style::new(colors)))
                         .ok();
@@ -152,8 +213,25 @@ impl Editor {
                 }
                 RedrawEvent::HighlightAttributesDefine { id, style } => {
                     tracy_zone!("EditorHighlightAttributesDefine");
+                    let style = Style {
+                        is_status_line: self.status_line_groups.values().any(|&group| group == id),
+                        is_tab_line: self.tab_line_groups.values().any(|&group| group == id),
+                        ..style
+                    };
                     self.defined_styles.insert(id, Arc::new(style));
                 }
+                RedrawEvent::HighlightGroupSet { name, id } => {
+                    tracy_zone!("EditorHighlightGroupSet");
+                    if STATUS_LINE_GROUPS.contains(&name.as_str()) {
+                        self.status_line_groups.insert(name, id);
+                        self.mark_line_styles();
+                    } else if POPUP_MENU_GROUPS.contains(&name.as_str()) {
+                        self.popup_menu_groups.insert(name, id);
+                    } else if TAB_LINE_GROUPS.contains(&name.as_str()) {
+                        self.tab_line_groups.insert(name, id);
+                        self.mark_line_styles();
+                    }
+                }
                 RedrawEvent::CursorGoto {
                     grid,
                     column: left,
@@ -204,23 +282,27 @@ impl Editor {
                     columns,
                 } => {
                     tracy_zone!("EditorScroll");
+                    let fast_path = self.use_terminal_fast_path();
                     let window = self.windows.get_mut(&grid);
                     if let Some(window) = window {
-                        window.scroll_region(top, bottom, left, right, rows, columns);
+                        window.scroll_region(top, bottom, left, right, rows, columns, fast_path);
                     }
                 }
                 RedrawEvent::WindowPosition {
                     grid,
+                    window,
                     start_row,
                     start_column,
                     width,
                     height,
                 } => {
                     tracy_zone!("EditorWindowPosition");
+                    self.window_grids.insert(window, grid);
                     self.set_window_position(grid, start_column, start_row, width, height)
                 }
                 RedrawEvent::WindowFloatPosition {
                     grid,
+                    window,
                     anchor,
                     anchor_grid,
                     anchor_column: anchor_left,
@@ -229,6 +311,7 @@ impl Editor {
                     ..
                 } => {
                     tracy_zone!("EditorWindowFloatPosition");
+                    self.window_grids.insert(window, grid);
                     self.set_window_float_position(
                         grid,
                         anchor_grid,
@@ -255,23 +338,90 @@ impl Editor {
                 }
                 RedrawEvent::WindowViewport {
                     grid,
+                    top_line,
                     // Don't send viewport events if they don't have a scroll delta
                     scroll_delta: Some(scroll_delta),
                     ..
                 } => {
                     tracy_zone!("EditorWindowViewport");
-                    self.send_updated_viewport(grid, scroll_delta)
+                    self.send_updated_viewport(grid, top_line, scroll_delta)
+                }
+                RedrawEvent::PopupMenuShow {
+                    items,
+                    selected,
+                    row,
+                    column,
+                    grid,
+                } => {
+                    tracy_zone!("EditorPopupMenuShow");
+                    self.show_popup_menu(items, selected, (column, row), grid)
+                }
+                RedrawEvent::PopupMenuSelect { selected } => {
+                    tracy_zone!("EditorPopupMenuSelect");
+                    self.draw_command_batcher
+                        .queue(DrawCommand::PopupMenuSelect(selected))
+                        .ok();
+                }
+                RedrawEvent::PopupMenuHide => {
+                    tracy_zone!("EditorPopupMenuHide");
+                    self.draw_command_batcher
+                        .queue(DrawCommand::PopupMenuHide)
+                        .ok();
+                }
+                RedrawEvent::TabLineUpdate { current, tabs } => {
+                    tracy_zone!("EditorTabLineUpdate");
+                    self.update_tab_line(current, tabs)
                 }
                 _ => {}
             },
+            EditorCommand::SetWindowBackgrounds(backgrounds) => {
+                tracy_zone!("EditorSetWindowBackgrounds");
+                self.set_window_backgrounds(backgrounds);
+            }
+            EditorCommand::SetSearchCount(count) => {
+                tracy_zone!("EditorSetSearchCount");
+                self.draw_command_batcher
+                    .queue(DrawCommand::SearchCount(count))
+                    .ok();
+                self.draw_command_batcher.send_batch();
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
+            EditorCommand::SetTabContents(contents) => {
+                tracy_zone!("EditorSetTabContents");
+                self.draw_command_batcher
+                    .queue(DrawCommand::TabContents(contents))
+                    .ok();
+                self.draw_command_batcher.send_batch();
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
+            EditorCommand::SetModeBadgeState {
+                recording,
+                operator,
+            } => {
+                tracy_zone!("EditorSetModeBadgeState");
+                self.draw_command_batcher
+                    .queue(DrawCommand::ModeBadgeState {
+                        recording,
+                        operator,
+                    })
+                    .ok();
+                self.draw_command_batcher.send_batch();
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
             EditorCommand::RedrawScreen => {
                 tracy_zone!("EditorRedrawScreen");
                 self.redraw_screen();
             }
+            EditorCommand::DumpFrame(path) => {
+                tracy_zone!("EditorDumpFrame");
+                self.dump_frame(&path);
+            }
         };
     }
 
     fn close_window(&mut self, grid: u64) {
+        self.window_grids
+            .retain(|_, window_grid| *window_grid != grid);
         if let Some(window) = self.windows.remove(&grid) {
             window.close();
             self.draw_command_batcher
@@ -485,6 +635,80 @@ impl Editor {
             .ok();
     }
 
+    fn send_accessibility_update(&mut self) {
+        tracy_zone!("send_accessibility_update");
+        let windows = &mut self.windows;
+        let accessible_lines = &mut self.accessible_lines;
+        let mut text_changed = accessible_lines.len() != windows.len();
+        accessible_lines.retain(|id, _| windows.contains_key(id));
+        for (id, window) in windows.iter_mut() {
+            if window.take_text_changed() || !accessible_lines.contains_key(id) {
+                accessible_lines.insert(*id, Arc::new(window.get_text_lines()));
+                text_changed = true;
+            }
+        }
+
+        let (grid_left, grid_top) = self.cursor.grid_position;
+        let cursor_character = self
+            .windows
+            .get(&self.cursor.parent_window_id)
+            .map(|window| window.get_character_index(grid_left, grid_top))
+            .unwrap_or(0);
+
+        let cursor_grid = self.cursor.parent_window_id;
+        let cursor_line = grid_top as usize;
+        let cursor_unchanged = self
+            .last_accessibility_update
+            .as_ref()
+            .map_or(false, |last| {
+                last.cursor_grid == cursor_grid
+                    && last.cursor_line == cursor_line
+                    && last.cursor_character == cursor_character
+                    && last.mode == self.current_mode
+            });
+        if !text_changed && cursor_unchanged {
+            return;
+        }
+
+        // The lines are shared, so this doesn't copy the text
+        let mut grids: Vec<AccessibleGrid> = self
+            .accessible_lines
+            .iter()
+            .map(|(id, lines)| AccessibleGrid {
+                id: *id,
+                lines: lines.clone(),
+            })
+            .collect();
+        grids.sort_by_key(|grid| grid.id);
+
+        let update = AccessibilityUpdate {
+            grids,
+            cursor_grid,
+            cursor_line,
+            cursor_character,
+            mode: self.current_mode.clone(),
+        };
+        self.last_accessibility_update = Some(update.clone());
+        EVENT_AGGREGATOR.send(WindowCommand::AccessibilityUpdate(update));
+    }
+
+    fn set_window_backgrounds(&mut self, backgrounds: Vec<WindowBackground>) {
+        for background in backgrounds {
+            let window = self
+                .window_grids
+                .get(&background.window)
+                .and_then(|grid| self.windows.get_mut(grid));
+
+            if let Some(window) = window {
+                window.set_background(background.color, background.is_current);
+                // Cells which match the new background have to be redrawn transparent
+                window.redraw();
+            }
+        }
+        self.draw_command_batcher.send_batch();
+        REDRAW_SCHEDULER.queue_next_frame();
+    }
+
     fn set_option(&mut self, gui_option: GuiOption) {
         trace!("Option set {:?}", &gui_option);
 
@@ -494,6 +718,7 @@ impl Editor {
                     EVENT_AGGREGATOR.send(WindowCommand::ListAvailableFonts);
                 }
 
+                self.guifont = Some(guifont.clone());
                 self.draw_command_batcher
                     .queue(DrawCommand::FontChanged(guifont))
                     .ok();
@@ -501,29 +726,145 @@ impl Editor {
                 self.redraw_screen();
             }
             GuiOption::LineSpace(linespace) => {
+                self.linespace = Some(linespace);
                 self.draw_command_batcher
                     .queue(DrawCommand::LineSpaceChanged(linespace))
                     .ok();
 
                 self.redraw_screen();
             }
+            GuiOption::ShowTabLine(show_tabline) => {
+                self.draw_command_batcher
+                    .queue(DrawCommand::ShowTabLine(show_tabline))
+                    .ok();
+            }
             _ => (),
         }
     }
 
-    fn send_updated_viewport(&mut self, grid: u64, scroll_delta: f64) {
+    fn update_ligatures(&mut self) {
+        let ligatures = SETTINGS.get::<RendererSettings>().ligatures;
+        let (_, cursor_row) = self.cursor.grid_position;
+        for (grid, window) in self.windows.iter_mut() {
+            let cursor_row = (*grid == self.cursor.parent_window_id).then_some(cursor_row);
+            window.set_ligatures(ligatures, cursor_row);
+        }
+    }
+
+    // Scrolling output in the terminal is redrawn instead of animated, which is a lot cheaper when
+    // a command prints many lines at once
+    fn use_terminal_fast_path(&self) -> bool {
+        matches!(&self.current_mode, EditorMode::Unknown(mode) if mode == "terminal")
+            && SETTINGS.with::<RendererSettings, _>(|settings| settings.terminal_fast_path)
+    }
+
+    fn send_updated_viewport(&mut self, grid: u64, top_line: f64, scroll_delta: f64) {
         if let Some(window) = self.windows.get_mut(&grid) {
-            window.update_viewport(scroll_delta);
+            window.update_viewport(top_line, scroll_delta);
         } else {
             trace!("viewport event received before window initialized");
         }
     }
 
+    // Redraws everything to capture the complete draw commands of the frame, which are then sent
+    // on to the renderer as usual
+    fn dump_frame(&mut self, path: &str) {
+        self.redraw_screen();
+        let draw_commands = self.draw_command_batcher.take_batch();
+        let result = frame_dump::dump_frame(self, &draw_commands, path);
+        EVENT_AGGREGATOR.send(draw_commands);
+        REDRAW_SCHEDULER.queue_next_frame();
+
+        let (text, is_error) = match result {
+            Ok(text) => (text, false),
+            Err(text) => {
+                error!("{}", text);
+                (text, true)
+            }
+        };
+        EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::ShowMessage {
+            text,
+            is_error,
+        }));
+    }
+
     fn redraw_screen(&mut self) {
-        for window in self.windows.values() {
+        for window in self.windows.values_mut() {
             window.redraw();
         }
     }
+
+    // The menu is positioned on the outer grid, since it's drawn over all the windows
+    fn show_popup_menu(
+        &mut self,
+        items: Vec<PopupMenuItem>,
+        selected: Option<u64>,
+        (column, row): (u64, u64),
+        grid: u64,
+    ) {
+        let (grid_left, grid_top) = self
+            .windows
+            .get(&grid)
+            .map(|window| window.get_grid_position())
+            .unwrap_or_default();
+        let style = |name: &str| {
+            self.popup_menu_groups
+                .get(name)
+                .and_then(|id| self.defined_styles.get(id))
+                .cloned()
+        };
+        let menu = PopupMenu {
+            items,
+            selected,
+            row: grid_top as u64 + row,
+            column: grid_left as u64 + column,
+            styles: PopupMenuStyles {
+                normal: style("Pmenu"),
+                selected: style("PmenuSel"),
+                scrollbar: style("PmenuSbar"),
+                thumb: style("PmenuThumb"),
+            },
+        };
+        self.draw_command_batcher
+            .queue(DrawCommand::PopupMenuShow(menu))
+            .ok();
+    }
+
+    // The tabs are drawn with the TabLine groups, like the tabline Neovim draws itself
+    fn update_tab_line(&mut self, current: u64, tabs: Vec<TabLineTab>) {
+        let style = |name: &str| {
+            self.tab_line_groups
+                .get(name)
+                .and_then(|id| self.defined_styles.get(id))
+                .cloned()
+        };
+        let styles = TabLineStyles {
+            normal: style("TabLine"),
+            selected: style("TabLineSel"),
+            fill: style("TabLineFill"),
+        };
+        self.draw_command_batcher
+            .queue(DrawCommand::TabLineUpdate {
+                current,
+                tabs,
+                styles,
+            })
+            .ok();
+    }
+
+    /// Updates the styles already defined when the status line or tabline groups changed. Cells
+    /// drawn with them keep the old style until Neovim draws them again.
+    fn mark_line_styles(&mut self) {
+        for (id, style) in self.defined_styles.iter_mut() {
+            let is_status_line = self.status_line_groups.values().any(|group| group == id);
+            let is_tab_line = self.tab_line_groups.values().any(|group| group == id);
+            if style.is_status_line != is_status_line || style.is_tab_line != is_tab_line {
+                let style = Arc::make_mut(style);
+                style.is_status_line = is_status_line;
+                style.is_tab_line = is_tab_line;
+            }
+        }
+    }
 }
 
 pub fn start_editor() {
@@ -31,6 +31,15 @@ pub struct Style {
     pub blend: u8,
     #[new(default)]
     pub underline: Option<UnderlineStyle>,
+    /// Whether this is the highlight of status lines or window bars
+    #[new(default)]
+    pub is_status_line: bool,
+    /// Whether this is the highlight of the tabline
+    #[new(default)]
+    pub is_tab_line: bool,
+    /// The highlight groups the style was combined from, like `DiagnosticVirtualTextError`
+    #[new(default)]
+    pub highlight_groups: Vec<String>,
 }
 
 impl Style {
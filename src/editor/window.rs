@@ -1,12 +1,20 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+};
 
 use log::warn;
+use skia_safe::Color4f;
 use unicode_segmentation::UnicodeSegmentation;
 
 use crate::{
     bridge::GridLineCell,
-    editor::{grid::CharacterGrid, style::Style, AnchorInfo, DrawCommand, DrawCommandBatcher},
-    renderer::{LineFragment, WindowDrawCommand},
+    editor::{
+        grid::{CharacterGrid, GridCell},
+        style::Style,
+        AnchorInfo, DrawCommand, DrawCommandBatcher,
+    },
+    renderer::{Ligatures, LineFragment, WindowDrawCommand, WindowEdges},
 };
 
 pub enum WindowType {
@@ -21,6 +29,14 @@ pub struct Window {
 
     pub anchor_info: Option<AnchorInfo>,
     grid_position: (f64, f64),
+    // Rows which changed since the last flush, so that a row touched by several grid_line events
+    // is only redrawn once
+    dirty_rows: BTreeSet<u64>,
+    ligatures: Ligatures,
+    cursor_row: Option<u64>,
+    sent_edges: Option<WindowEdges>,
+    // Whether the text changed since the accessibility tree last got the lines
+    text_changed: bool,
 
     draw_command_batcher: Arc<DrawCommandBatcher>,
 }
@@ -40,6 +56,11 @@ impl Window {
             window_type,
             anchor_info,
             grid_position,
+            dirty_rows: BTreeSet::new(),
+            ligatures: Ligatures::On,
+            cursor_row: None,
+            sent_edges: None,
+            text_changed: true,
             draw_command_batcher,
         };
         window.send_updated_position();
@@ -81,6 +102,50 @@ impl Window {
         (grid_cell.0, grid_cell.1, double_width)
     }
 
+    pub fn get_text_lines(&self) -> Vec<Vec<String>> {
+        (0..self.grid.height)
+            .filter_map(|row| self.grid.row(row))
+            .map(|cells| {
+                // The cell after a double width character is empty and isn't a character of its own
+                let mut characters: Vec<String> = cells
+                    .iter()
+                    .filter(|(character, _)| !character.is_empty())
+                    .map(|(character, _)| character.clone())
+                    .collect();
+                while characters
+                    .last()
+                    .map_or(false, |character| character.trim().is_empty())
+                {
+                    characters.pop();
+                }
+                characters
+            })
+            .collect()
+    }
+
+    /// Returns whether the text changed since the last call, so that the text lines are only
+    /// rebuilt when needed.
+    pub fn take_text_changed(&mut self) -> bool {
+        std::mem::take(&mut self.text_changed)
+    }
+
+    pub fn row(&self, row: u64) -> Option<&[GridCell]> {
+        self.grid.row(row)
+    }
+
+    pub fn get_character_index(&self, window_left: u64, window_top: u64) -> usize {
+        self.grid
+            .row(window_top)
+            .map(|cells| {
+                cells
+                    .iter()
+                    .take(window_left as usize)
+                    .filter(|(character, _)| !character.is_empty())
+                    .count()
+            })
+            .unwrap_or(0)
+    }
+
     pub fn get_width(&self) -> u64 {
         self.grid.width
     }
@@ -100,6 +165,7 @@ impl Window {
         grid_position: (f64, f64),
     ) {
         self.grid.resize(grid_size);
+        self.text_changed = true;
         self.anchor_info = anchor_info;
         self.grid_position = grid_position;
         self.send_updated_position();
@@ -108,6 +174,7 @@ impl Window {
 
     pub fn resize(&mut self, new_size: (u64, u64)) {
         self.grid.resize(new_size);
+        self.text_changed = true;
         self.send_updated_position();
         self.redraw();
     }
@@ -178,12 +245,19 @@ impl Window {
             text.push_str(character);
         }
 
+        let ligatures = match self.ligatures {
+            Ligatures::On => true,
+            Ligatures::Off => false,
+            Ligatures::CursorLineOff => self.cursor_row != Some(row_index),
+        };
+
         let line_fragment = LineFragment {
             text,
             window_left: start,
             window_top: row_index,
             width,
             style: style.clone(),
+            ligatures,
         };
 
         (start + width, line_fragment)
@@ -212,6 +286,7 @@ impl Window {
     ) {
         let mut previous_style = None;
         if row < self.grid.height {
+            self.text_changed = true;
             let mut column_pos = column_start;
             for cell in cells {
                 self.modify_grid(
@@ -228,17 +303,73 @@ impl Window {
             // an individual line is redrawn. Unfortunately, some clipping still happens.
             // TODO: figure out how to solve this
             if row < self.grid.height - 1 {
-                self.redraw_line(row + 1);
+                self.dirty_rows.insert(row + 1);
             }
-            self.redraw_line(row);
+            self.dirty_rows.insert(row);
             if row > 0 {
-                self.redraw_line(row - 1);
+                self.dirty_rows.insert(row - 1);
             }
         } else {
             warn!("Draw command out of bounds");
         }
     }
 
+    // Changing the ligatures mode redraws everything, moving the cursor only redraws the rows it
+    // moved between, and only when the cursor line is shaped differently.
+    pub fn set_ligatures(&mut self, ligatures: Ligatures, cursor_row: Option<u64>) {
+        if self.ligatures != ligatures {
+            self.dirty_rows.extend(0..self.grid.height);
+        } else if ligatures == Ligatures::CursorLineOff && self.cursor_row != cursor_row {
+            self.dirty_rows.extend(self.cursor_row);
+            self.dirty_rows.extend(cursor_row);
+        }
+        self.ligatures = ligatures;
+        self.cursor_row = cursor_row;
+    }
+
+    // Redraws the rows changed since the last flush. Like in redraw, from the bottom up so that
+    // underlines don't get overwritten by the line below.
+    pub fn flush(&mut self) {
+        for row in self.dirty_rows.iter().rev() {
+            if *row < self.grid.height {
+                self.redraw_line(*row);
+            }
+        }
+        self.dirty_rows.clear();
+    }
+
+    // Lets the renderer extend the backgrounds of the outermost cells into the window padding.
+    // Only sent when they changed, which mostly happens when the cursor line moves.
+    pub fn send_edges(&mut self) {
+        let (width, height) = (self.grid.width, self.grid.height);
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        let cell_style = |column: u64, row: u64| {
+            self.grid
+                .get_cell(column, row)
+                .and_then(|(_, style)| style.clone())
+        };
+        let edges = WindowEdges {
+            left: (0..height).map(|row| cell_style(0, row)).collect(),
+            right: (0..height).map(|row| cell_style(width - 1, row)).collect(),
+            top: (0..width).map(|column| cell_style(column, 0)).collect(),
+            bottom: (0..width)
+                .map(|column| cell_style(column, height - 1))
+                .collect(),
+        };
+
+        if self.sent_edges.as_ref() != Some(&edges) {
+            self.send_command(WindowDrawCommand::Edges(edges.clone()));
+            self.sent_edges = Some(edges);
+        }
+    }
+
+    // With the fast path, the rendered texture isn't scrolled, but the whole region is redrawn on
+    // the next flush instead. Many scrolls within one flush, like when a terminal prints a lot of
+    // output, then cost a single redraw of the region instead of a scroll and a line each.
+    #[allow(clippy::too_many_arguments)]
     pub fn scroll_region(
         &mut self,
         top: u64,
@@ -247,6 +378,7 @@ impl Window {
         right: u64,
         rows: i64,
         cols: i64,
+        fast_path: bool,
     ) {
         let mut top_to_bottom;
         let mut bottom_to_top;
@@ -258,14 +390,21 @@ impl Window {
             &mut bottom_to_top
         };
 
-        self.send_command(WindowDrawCommand::Scroll {
-            top,
-            bottom,
-            left,
-            right,
-            rows,
-            cols,
-        });
+        self.text_changed = true;
+        if fast_path {
+            self.dirty_rows.extend(top..bottom.min(self.grid.height));
+        } else {
+            // The pending lines have to be drawn before the texture gets scrolled
+            self.flush();
+            self.send_command(WindowDrawCommand::Scroll {
+                top,
+                bottom,
+                left,
+                right,
+                rows,
+                cols,
+            });
+        }
 
         // Scrolls must not only translate the rendered texture, but also must move the grid data
         // accordingly so that future renders work correctly.
@@ -300,10 +439,13 @@ impl Window {
 
     pub fn clear(&mut self) {
         self.grid.clear();
+        self.text_changed = true;
+        self.dirty_rows.clear();
         self.send_command(WindowDrawCommand::Clear);
     }
 
-    pub fn redraw(&self) {
+    pub fn redraw(&mut self) {
+        self.dirty_rows.clear();
         self.send_command(WindowDrawCommand::Clear);
         // Draw the lines from the bottom up so that underlines don't get overwritten by the line
         // below.
@@ -312,6 +454,10 @@ impl Window {
         }
     }
 
+    pub fn set_background(&self, color: Option<Color4f>, is_current: bool) {
+        self.send_command(WindowDrawCommand::Background { color, is_current });
+    }
+
     pub fn hide(&self) {
         self.send_command(WindowDrawCommand::Hide);
     }
@@ -324,8 +470,11 @@ impl Window {
         self.send_command(WindowDrawCommand::Close);
     }
 
-    pub fn update_viewport(&self, scroll_delta: f64) {
-        self.send_command(WindowDrawCommand::Viewport { scroll_delta });
+    pub fn update_viewport(&self, top_line: f64, scroll_delta: f64) {
+        self.send_command(WindowDrawCommand::Viewport {
+            top_line,
+            scroll_delta,
+        });
     }
 }
 
@@ -334,7 +483,7 @@ mod tests {
     use std::collections::HashMap;
 
     use super::*;
-    use crate::event_aggregator::EVENT_AGGREGATOR;
+    use crate::{editor::Colors, event_aggregator::EVENT_AGGREGATOR};
 
     #[test]
     fn window_separator_modifies_grid_and_sends_draw_command() {
@@ -369,6 +518,7 @@ mod tests {
 
         assert_eq!(window.grid.get_cell(70, 1), Some(&("|".to_owned(), None)));
 
+        window.flush();
         draw_command_batcher.send_batch();
 
         let sent_commands = draw_command_receiver
@@ -376,4 +526,162 @@ mod tests {
             .expect("Could not receive commands");
         assert!(!sent_commands.is_empty());
     }
+
+    fn count_commands(commands: &[DrawCommand]) -> (usize, usize) {
+        let draw_lines = commands
+            .iter()
+            .filter(|command| {
+                matches!(
+                    command,
+                    DrawCommand::Window {
+                        command: WindowDrawCommand::DrawLine(_),
+                        ..
+                    }
+                )
+            })
+            .count();
+        let scrolls = commands
+            .iter()
+            .filter(|command| {
+                matches!(
+                    command,
+                    DrawCommand::Window {
+                        command: WindowDrawCommand::Scroll { .. },
+                        ..
+                    }
+                )
+            })
+            .count();
+        (draw_lines, scrolls)
+    }
+
+    #[test]
+    fn grid_lines_are_coalesced_until_flush() {
+        let draw_command_batcher = Arc::new(DrawCommandBatcher::new());
+        let mut window = Window::new(
+            1,
+            WindowType::Editor,
+            None,
+            (0.0, 0.0),
+            (20, 10),
+            draw_command_batcher.clone(),
+        );
+        draw_command_batcher.take_batch();
+
+        for _ in 0..5 {
+            window.draw_grid_line(
+                4,
+                0,
+                vec![GridLineCell {
+                    text: "a".to_owned(),
+                    highlight_id: None,
+                    repeat: Some(20),
+                }],
+                &HashMap::new(),
+            );
+        }
+        assert!(draw_command_batcher.take_batch().is_empty());
+
+        window.flush();
+        assert_eq!(count_commands(&draw_command_batcher.take_batch()), (3, 0));
+    }
+
+    #[test]
+    fn fast_path_scroll_redraws_instead_of_scrolling() {
+        let draw_command_batcher = Arc::new(DrawCommandBatcher::new());
+        let mut window = Window::new(
+            1,
+            WindowType::Editor,
+            None,
+            (0.0, 0.0),
+            (20, 10),
+            draw_command_batcher.clone(),
+        );
+        draw_command_batcher.take_batch();
+
+        for _ in 0..100 {
+            window.scroll_region(0, 10, 0, 20, 1, 0, true);
+        }
+        window.flush();
+        assert_eq!(count_commands(&draw_command_batcher.take_batch()), (10, 0));
+
+        window.scroll_region(0, 10, 0, 20, 1, 0, false);
+        window.flush();
+        assert_eq!(count_commands(&draw_command_batcher.take_batch()), (0, 1));
+    }
+
+    #[test]
+    fn ligatures_are_disabled_on_the_cursor_line() {
+        let draw_command_batcher = Arc::new(DrawCommandBatcher::new());
+        let mut window = Window::new(
+            1,
+            WindowType::Editor,
+            None,
+            (0.0, 0.0),
+            (20, 10),
+            draw_command_batcher.clone(),
+        );
+        draw_command_batcher.take_batch();
+
+        window.set_ligatures(Ligatures::CursorLineOff, Some(2));
+        window.flush();
+        draw_command_batcher.take_batch();
+
+        window.set_ligatures(Ligatures::CursorLineOff, Some(5));
+        window.flush();
+        let redrawn_rows: Vec<(u64, bool)> = draw_command_batcher
+            .take_batch()
+            .into_iter()
+            .filter_map(|command| match command {
+                DrawCommand::Window {
+                    command: WindowDrawCommand::DrawLine(fragments),
+                    ..
+                } => Some((fragments[0].window_top, fragments[0].ligatures)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(redrawn_rows, vec![(5, false), (2, true)]);
+    }
+
+    #[test]
+    fn edges_are_only_sent_when_changed() {
+        let draw_command_batcher = Arc::new(DrawCommandBatcher::new());
+        let mut window = Window::new(
+            1,
+            WindowType::Editor,
+            None,
+            (0.0, 0.0),
+            (20, 10),
+            draw_command_batcher.clone(),
+        );
+        draw_command_batcher.take_batch();
+
+        window.send_edges();
+        window.send_edges();
+        assert_eq!(draw_command_batcher.take_batch().len(), 1);
+
+        let mut styles = HashMap::new();
+        styles.insert(1, Arc::new(Style::new(Colors::new(None, None, None))));
+        window.draw_grid_line(
+            3,
+            19,
+            vec![GridLineCell {
+                text: " ".to_owned(),
+                highlight_id: Some(1),
+                repeat: None,
+            }],
+            &styles,
+        );
+        window.send_edges();
+        match draw_command_batcher.take_batch().pop() {
+            Some(DrawCommand::Window {
+                command: WindowDrawCommand::Edges(edges),
+                ..
+            }) => {
+                assert!(edges.right[3].is_some());
+                assert!(edges.left[3].is_none());
+            }
+            command => panic!("Expected edges, got {:?}", command),
+        }
+    }
 }
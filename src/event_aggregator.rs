@@ -2,6 +2,7 @@ use std::{
     any::{type_name, Any, TypeId},
     collections::{hash_map::Entry, HashMap},
     fmt::Debug,
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
 use parking_lot::RwLock;
@@ -13,9 +14,15 @@ lazy_static! {
     pub static ref EVENT_AGGREGATOR: EventAggregator = EventAggregator::default();
 }
 
+// The events sent before anything registered for them, kept until something does
+struct UnclaimedReceiver {
+    receiver: Box<dyn Any + Send + Sync>,
+    pending: AtomicUsize,
+}
+
 pub struct EventAggregator {
     parent_senders: RwLock<HashMap<TypeId, Box<dyn Any + Send + Sync>>>,
-    unclaimed_receivers: RwLock<HashMap<TypeId, Box<dyn Any + Send + Sync>>>,
+    unclaimed_receivers: RwLock<HashMap<TypeId, UnclaimedReceiver>>,
 }
 
 impl Default for EventAggregator {
@@ -38,9 +45,13 @@ impl EventAggregator {
                 let (sender, receiver) = unbounded_channel();
                 let logging_tx = LoggingTx::attach(sender, type_name::<T>().to_owned());
                 entry.insert(Box::new(logging_tx.clone()));
-                self.unclaimed_receivers
-                    .write()
-                    .insert(TypeId::of::<T>(), Box::new(receiver));
+                self.unclaimed_receivers.write().insert(
+                    TypeId::of::<T>(),
+                    UnclaimedReceiver {
+                        receiver: Box::new(receiver),
+                        pending: AtomicUsize::new(0),
+                    },
+                );
                 logging_tx
             }
         }
@@ -49,13 +60,19 @@ impl EventAggregator {
     pub fn send<T: Any + Clone + Debug + Send>(&self, event: T) {
         let sender = self.get_sender::<T>();
         sender.send(event).unwrap();
+        if let Some(unclaimed) = self.unclaimed_receivers.read().get(&TypeId::of::<T>()) {
+            unclaimed.pending.fetch_add(1, Ordering::Relaxed);
+        }
     }
 
     pub fn register_event<T: Any + Clone + Debug + Send>(&self) -> UnboundedReceiver<T> {
         let type_id = TypeId::of::<T>();
 
-        if let Some(receiver) = self.unclaimed_receivers.write().remove(&type_id) {
-            *receiver.downcast::<UnboundedReceiver<T>>().unwrap()
+        if let Some(unclaimed) = self.unclaimed_receivers.write().remove(&type_id) {
+            *unclaimed
+                .receiver
+                .downcast::<UnboundedReceiver<T>>()
+                .unwrap()
         } else {
             let (sender, receiver) = unbounded_channel();
             let logging_sender = LoggingTx::attach(sender, type_name::<T>().to_owned());
@@ -70,4 +87,35 @@ impl EventAggregator {
             receiver
         }
     }
+
+    /// How many events are waiting for something to register for them. They're left alone, so
+    /// that it still gets them.
+    pub fn pending_unclaimed_events(&self) -> usize {
+        self.unclaimed_receivers
+            .read()
+            .values()
+            .map(|unclaimed| unclaimed.pending.load(Ordering::Relaxed))
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct UnclaimedEvent(u32);
+
+    #[test]
+    fn counts_unclaimed_events_without_dropping_them() {
+        let aggregator = EventAggregator::default();
+        aggregator.send(UnclaimedEvent(1));
+        aggregator.send(UnclaimedEvent(2));
+        assert_eq!(aggregator.pending_unclaimed_events(), 2);
+
+        let mut receiver = aggregator.register_event::<UnclaimedEvent>();
+        assert_eq!(aggregator.pending_unclaimed_events(), 0);
+        assert_eq!(receiver.try_recv().ok(), Some(UnclaimedEvent(1)));
+        assert_eq!(receiver.try_recv().ok(), Some(UnclaimedEvent(2)));
+    }
 }
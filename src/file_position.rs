@@ -0,0 +1,158 @@
+use std::{env, path::Path};
+
+/// A file to open, with the cursor position from a `path:line:column` suffix, like compilers
+/// print them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileArgument {
+    pub path: String,
+    /// The line and column, both starting at 1
+    pub cursor: Option<(u64, u64)>,
+}
+
+impl FileArgument {
+    pub fn parse(argument: &str) -> FileArgument {
+        parse_with(argument, |path| Path::new(path).exists())
+    }
+
+    /// The Vim command that moves the cursor to the position, if there is one.
+    pub fn cursor_command(&self) -> Option<String> {
+        self.cursor
+            .map(|(line, column)| format!("call cursor({line}, {column})"))
+    }
+}
+
+fn parse_with(argument: &str, exists: impl Fn(&str) -> bool) -> FileArgument {
+    let unchanged = FileArgument {
+        path: argument.to_string(),
+        cursor: None,
+    };
+    // Files whose name really ends like a position, and Neovim commands like +42, stay as they are
+    if argument.starts_with('+') || exists(argument) {
+        return unchanged;
+    }
+
+    // Compiler messages continue with a colon after the column, like `main.rs:42:7: error`
+    let trimmed = argument.strip_suffix(':').unwrap_or(argument);
+    let split_number = |text: &str| {
+        let (rest, number) = text.rsplit_once(':')?;
+        Some((rest.to_string(), number.parse::<u64>().ok()?))
+    };
+    let (path, cursor) = match split_number(trimmed) {
+        Some((rest, last)) => match split_number(&rest) {
+            Some((path, line)) => (path, (line, last)),
+            None => (rest, (last, 1)),
+        },
+        None => return unchanged,
+    };
+    if path.is_empty() {
+        return unchanged;
+    }
+    FileArgument {
+        path,
+        cursor: Some(cursor),
+    }
+}
+
+/// Escapes a path for the pattern of an autocommand, which treats wildcards and commas
+/// specially.
+fn autocmd_pattern(path: &str) -> String {
+    let absolute = env::current_dir()
+        .map(|directory| directory.join(path))
+        .unwrap_or_else(|_| path.into());
+    // Autocommand patterns use forward slashes on all platforms
+    let absolute = absolute.to_string_lossy().replace('\\', "/");
+    let mut pattern = String::with_capacity(absolute.len());
+    for character in absolute.chars() {
+        if " *?[]{},".contains(character) {
+            pattern.push('\\');
+        }
+        pattern.push(character);
+    }
+    pattern
+}
+
+/// Turns the file arguments with positions into arguments for Neovim. Its `+command` only applies
+/// to the first file, so the cursor of the other ones is moved by an autocommand once they are
+/// shown.
+pub fn neovim_file_args(arguments: Vec<String>) -> Vec<String> {
+    let mut commands = Vec::new();
+    let mut files = Vec::new();
+    let mut is_first_file = true;
+    for argument in arguments {
+        let file = FileArgument::parse(&argument);
+        let is_file = !file.path.starts_with('+');
+        match file.cursor_command() {
+            Some(command) if is_first_file => files.push(format!("+{command}")),
+            Some(command) => commands.extend([
+                "--cmd".to_string(),
+                format!(
+                    "autocmd BufWinEnter {} ++once {command}",
+                    autocmd_pattern(&file.path)
+                ),
+            ]),
+            None => {}
+        }
+        files.push(file.path);
+        is_first_file &= !is_file;
+    }
+    commands.into_iter().chain(files).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(argument: &str) -> FileArgument {
+        parse_with(argument, |path| path == "exists:12")
+    }
+
+    fn file(path: &str, cursor: Option<(u64, u64)>) -> FileArgument {
+        FileArgument {
+            path: path.to_string(),
+            cursor,
+        }
+    }
+
+    #[test]
+    fn positions_are_parsed() {
+        assert_eq!(parse("src/main.rs"), file("src/main.rs", None));
+        assert_eq!(parse("src/main.rs:42"), file("src/main.rs", Some((42, 1))));
+        assert_eq!(
+            parse("src/main.rs:42:7"),
+            file("src/main.rs", Some((42, 7)))
+        );
+        assert_eq!(
+            parse("src/main.rs:42:7:"),
+            file("src/main.rs", Some((42, 7)))
+        );
+    }
+
+    #[test]
+    fn windows_paths_keep_their_drive() {
+        assert_eq!(
+            parse(r"C:\src\main.rs:42:7"),
+            file(r"C:\src\main.rs", Some((42, 7)))
+        );
+        assert_eq!(parse(r"C:\src\main.rs"), file(r"C:\src\main.rs", None));
+    }
+
+    #[test]
+    fn other_arguments_are_unchanged() {
+        assert_eq!(parse("exists:12"), file("exists:12", None));
+        assert_eq!(parse("+42"), file("+42", None));
+        assert_eq!(parse(":42"), file(":42", None));
+        assert_eq!(parse("notes:todo"), file("notes:todo", None));
+    }
+
+    #[test]
+    fn only_the_first_file_uses_a_plus_command() {
+        let args = neovim_file_args(vec!["+set list".to_string(), "a.rs:3:4".to_string()]);
+        assert_eq!(args, vec!["+set list", "+call cursor(3, 4)", "a.rs"]);
+
+        let args = neovim_file_args(vec!["a.rs".to_string(), "b.rs:3".to_string()]);
+        assert_eq!(args[0], "--cmd");
+        assert!(args[1].starts_with("autocmd BufWinEnter "));
+        assert!(args[1].ends_with("b.rs ++once call cursor(3, 1)"));
+        assert_eq!(&args[2..], ["a.rs", "b.rs"]);
+    }
+}
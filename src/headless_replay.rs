@@ -0,0 +1,154 @@
+//! Renders a frame dump or a stream of redraw events into an image, without a window or Neovim, so
+//! that the renderer can be tested against golden images in CI.
+
+use std::{
+    fs,
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+use image::RgbaImage;
+use log::info;
+use skia_safe::{AlphaType, ColorType, ImageInfo, Surface};
+
+use crate::{
+    cmd_line::CmdLineSettings,
+    editor::{replay_events, Editor, EditorCommand},
+    renderer::Renderer,
+};
+
+// Drawn with a whole second each, so that animations like the cursor's have settled
+const SETTLE_FRAMES: usize = 10;
+const SETTLE_FRAME_LENGTH: f32 = 1.0;
+const FALLBACK_FONT_TIMEOUT: Duration = Duration::from_secs(10);
+const FALLBACK_FONT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Replays the dump given with `--headless-replay` and writes the image, comparing it with the
+/// golden image when one is given.
+pub fn run(settings: &CmdLineSettings) -> Result<String, String> {
+    let dump_path = settings.headless_replay.as_deref().unwrap_or_default();
+    let dump = fs::read_to_string(dump_path)
+        .map_err(|error| format!("Could not read {dump_path}: {error}"))?;
+    let image = render(&dump)?;
+
+    let output = match &settings.replay_output {
+        Some(output) => output.clone(),
+        None => Path::new(dump_path)
+            .with_extension("png")
+            .to_string_lossy()
+            .into_owned(),
+    };
+    image
+        .save(&output)
+        .map_err(|error| format!("Could not write {output}: {error}"))?;
+    info!("Replayed {} into {}", dump_path, output);
+
+    match &settings.replay_golden {
+        Some(golden) => {
+            let golden_image = image::open(golden)
+                .map_err(|error| format!("Could not read {golden}: {error}"))?
+                .to_rgba8();
+            match different_pixels(&image, &golden_image) {
+                0 => Ok(format!("{output} matches {golden}")),
+                count => Err(format!("{output} differs from {golden} in {count} pixels")),
+            }
+        }
+        None => Ok(format!("Rendered {output}")),
+    }
+}
+
+fn render(dump: &str) -> Result<RgbaImage, String> {
+    // The renderer takes the draw commands the editor sends, so it has to exist first
+    let mut renderer = Renderer::new(1.0);
+    let mut editor = Editor::new();
+    for event in replay_events(dump)? {
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(event));
+    }
+
+    // The font of the dump is taken in first, since the size of the surface depends on it
+    let mut font_surface = Surface::new_raster_n32_premul((1, 1))
+        .ok_or_else(|| "Could not create a surface".to_string())?;
+    renderer.draw_frame(font_surface.canvas(), 0.0);
+
+    let mut surface = root_grid_surface(&renderer, &editor)?;
+    renderer.draw_frame(surface.canvas(), SETTLE_FRAME_LENGTH);
+    // The fallback fonts are loaded on a thread, without them the image would depend on its timing
+    let started = Instant::now();
+    while renderer.is_loading_fonts() {
+        if started.elapsed() > FALLBACK_FONT_TIMEOUT {
+            return Err("Timed out loading the fallback fonts".to_string());
+        }
+        thread::sleep(FALLBACK_FONT_POLL_INTERVAL);
+        renderer.draw_frame(surface.canvas(), 0.0);
+    }
+    for _ in 1..SETTLE_FRAMES {
+        renderer.draw_frame(surface.canvas(), SETTLE_FRAME_LENGTH);
+    }
+
+    let info = surface.image_info();
+    let row_bytes = info.min_row_bytes();
+    let mut pixels = vec![0; row_bytes * info.height() as usize];
+    if !surface.read_pixels(&info, &mut pixels, row_bytes, (0, 0)) {
+        return Err("Could not read the rendered frame".to_string());
+    }
+    RgbaImage::from_raw(info.width() as u32, info.height() as u32, pixels)
+        .ok_or_else(|| "Could not read the rendered frame".to_string())
+}
+
+/// A surface in memory with the size the window would have for the root grid of the editor.
+pub fn root_grid_surface(renderer: &Renderer, editor: &Editor) -> Result<Surface, String> {
+    let root_window = editor
+        .windows
+        .get(&1)
+        .ok_or_else(|| "There is no root grid".to_string())?;
+    let grid_size = (root_window.get_width(), root_window.get_height()).into();
+    let padding = renderer.window_padding;
+    let mut size = renderer.grid_renderer.convert_grid_to_physical(grid_size);
+    size.width += padding.left + padding.right;
+    size.height += padding.top + padding.bottom;
+
+    let info = ImageInfo::new(
+        (size.width as i32, size.height as i32),
+        ColorType::RGBA8888,
+        AlphaType::Unpremul,
+        None,
+    );
+    Surface::new_raster(&info, None, None)
+        .ok_or_else(|| format!("Could not create a {}x{} surface", size.width, size.height))
+}
+
+fn different_pixels(image: &RgbaImage, golden: &RgbaImage) -> usize {
+    if image.dimensions() != golden.dimensions() {
+        return (image.width() * image.height()).max(golden.width() * golden.height()) as usize;
+    }
+    image
+        .pixels()
+        .zip(golden.pixels())
+        .filter(|(pixel, golden_pixel)| pixel != golden_pixel)
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    #[test]
+    fn counts_different_pixels() {
+        let golden = RgbaImage::from_pixel(4, 2, Rgba([0, 0, 0, 255]));
+        let mut image = golden.clone();
+        assert_eq!(different_pixels(&image, &golden), 0);
+
+        image.put_pixel(1, 1, Rgba([255, 0, 0, 255]));
+        assert_eq!(different_pixels(&image, &golden), 1);
+    }
+
+    #[test]
+    fn other_sizes_differ_everywhere() {
+        let golden = RgbaImage::new(4, 2);
+        let image = RgbaImage::new(2, 2);
+        assert_eq!(different_pixels(&image, &golden), 8);
+    }
+}
@@ -0,0 +1,187 @@
+//! Translations of the strings Neovide shows itself, like menus, dialogs and errors.
+
+use std::{collections::HashMap, env};
+
+use log::{info, warn};
+
+const FALLBACK_LOCALE: &str = "en";
+
+// Embedded, so that a translation can't go missing
+const LOCALES: &[(&str, &str)] = &[
+    ("en", include_str!("../assets/locales/en.toml")),
+    ("de", include_str!("../assets/locales/de.toml")),
+    ("es", include_str!("../assets/locales/es.toml")),
+    ("fr", include_str!("../assets/locales/fr.toml")),
+];
+
+lazy_static! {
+    static ref TRANSLATIONS: Translations = Translations::new(detect_locale().as_deref());
+}
+
+struct Translations {
+    strings: HashMap<String, String>,
+    fallback: HashMap<String, String>,
+}
+
+impl Translations {
+    fn new(locale: Option<&str>) -> Self {
+        let strings = locale
+            .map(locale_candidates)
+            .unwrap_or_default()
+            .iter()
+            .find_map(|candidate| load_locale(candidate))
+            .unwrap_or_default();
+        Self {
+            strings,
+            fallback: load_locale(FALLBACK_LOCALE).unwrap_or_default(),
+        }
+    }
+
+    fn get(&self, key: &str) -> String {
+        match self.strings.get(key).or_else(|| self.fallback.get(key)) {
+            Some(text) => text.clone(),
+            None => {
+                warn!("Missing translation for {}", key);
+                key.to_string()
+            }
+        }
+    }
+}
+
+/// The text for `key` in the language of the user, or in English if it isn't translated.
+pub fn tr(key: &str) -> String {
+    TRANSLATIONS.get(key)
+}
+
+/// Like `tr`, replacing `{name}` placeholders in the text with the given values.
+pub fn tr_with(key: &str, args: &[(&str, String)]) -> String {
+    args.iter().fold(tr(key), |text, (name, value)| {
+        text.replace(&format!("{{{name}}}"), value)
+    })
+}
+
+fn load_locale(name: &str) -> Option<HashMap<String, String>> {
+    let (_, source) = LOCALES.iter().find(|(locale, _)| *locale == name)?;
+    match source.parse::<toml::Value>() {
+        Ok(value) => {
+            let mut strings = HashMap::new();
+            flatten("", &value, &mut strings);
+            Some(strings)
+        }
+        Err(error) => {
+            warn!("Could not parse the {} translation: {}", name, error);
+            None
+        }
+    }
+}
+
+// Tables become dotted keys, like `quit-dialog.title`
+fn flatten(prefix: &str, value: &toml::Value, strings: &mut HashMap<String, String>) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                let key = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{prefix}.{key}")
+                };
+                flatten(&key, value, strings);
+            }
+        }
+        toml::Value::String(text) => {
+            strings.insert(prefix.to_string(), text.clone());
+        }
+        _ => {}
+    }
+}
+
+/// The names to look the translation up by, most specific first. `pt_BR.UTF-8` is looked up
+/// as `pt-BR` and then as `pt`.
+fn locale_candidates(locale: &str) -> Vec<String> {
+    let locale = locale
+        .split(['.', '@'])
+        .next()
+        .unwrap_or_default()
+        .replace('_', "-");
+    if locale.is_empty() || locale == "C" || locale == "POSIX" {
+        return Vec::new();
+    }
+    let mut candidates = vec![locale.clone()];
+    if let Some((language, _)) = locale.split_once('-') {
+        candidates.push(language.to_string());
+    }
+    candidates
+}
+
+fn detect_locale() -> Option<String> {
+    // The same order gettext uses. LANGUAGE can list several, the first one is used.
+    let locale = ["LANGUAGE", "LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .map(|value| value.split(':').next().unwrap_or_default().to_string())
+        .find(|value| !value.is_empty())
+        .or_else(system_locale);
+    info!("Using translations for locale {:?}", locale);
+    locale
+}
+
+#[cfg(target_os = "windows")]
+fn system_locale() -> Option<String> {
+    use winapi::um::winnls::GetUserDefaultLocaleName;
+    use winapi::um::winnt::LOCALE_NAME_MAX_LENGTH;
+
+    let mut buffer = [0u16; LOCALE_NAME_MAX_LENGTH];
+    let length =
+        unsafe { GetUserDefaultLocaleName(buffer.as_mut_ptr(), LOCALE_NAME_MAX_LENGTH as i32) };
+    // The length includes the terminating null
+    (length > 1).then(|| String::from_utf16_lossy(&buffer[..length as usize - 1]))
+}
+
+#[cfg(target_os = "macos")]
+fn system_locale() -> Option<String> {
+    // Apps started from the Finder don't get LANG
+    let output = std::process::Command::new("defaults")
+        .args(["read", "-g", "AppleLocale"])
+        .output()
+        .ok()?;
+    let locale = String::from_utf8(output.stdout).ok()?;
+    Some(locale.trim().to_string()).filter(|locale| !locale.is_empty())
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn system_locale() -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locale_names_are_normalized() {
+        assert_eq!(locale_candidates("pt_BR.UTF-8"), vec!["pt-BR", "pt"]);
+        assert_eq!(locale_candidates("de_DE@euro"), vec!["de-DE", "de"]);
+        assert_eq!(locale_candidates("fr"), vec!["fr"]);
+        assert!(locale_candidates("C.UTF-8").is_empty());
+        assert!(locale_candidates("POSIX").is_empty());
+    }
+
+    #[test]
+    fn every_translation_has_the_english_keys() {
+        let english = load_locale(FALLBACK_LOCALE).unwrap();
+        for (name, _) in LOCALES {
+            let translation = load_locale(name).unwrap();
+            for key in english.keys() {
+                assert!(translation.contains_key(key), "{name} is missing {key}");
+            }
+        }
+    }
+
+    #[test]
+    fn missing_translations_fall_back_to_english() {
+        let translations = Translations::new(Some("de_AT.UTF-8"));
+        assert_eq!(translations.get("context-menu.copy"), "Kopieren");
+        let translations = Translations::new(Some("xx_XX"));
+        assert_eq!(translations.get("context-menu.copy"), "Copy");
+    }
+}
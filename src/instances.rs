@@ -0,0 +1,196 @@
+// Lets the running Neovide instances find each other, for opening new windows and cycling the focus
+// through them. Every instance listens on a localhost port, which it writes into a file named after
+// its process id.
+
+use std::{
+    env, fs,
+    io::{BufRead, BufReader, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+use log::{error, trace, warn};
+
+use crate::{
+    cmd_line::CmdLineSettings, event_aggregator::EVENT_AGGREGATOR, settings::SETTINGS,
+    window::WindowCommand,
+};
+
+const FOCUS_MESSAGE: &str = "focus";
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(200);
+
+fn instances_dir() -> PathBuf {
+    dirs::runtime_dir()
+        .unwrap_or_else(env::temp_dir)
+        .join("neovide-instances")
+}
+
+fn instance_file(pid: u32) -> PathBuf {
+    instances_dir().join(pid.to_string())
+}
+
+/// Starts listening for the other instances and announces this one.
+pub fn register_instance() {
+    let listener = match TcpListener::bind((Ipv4Addr::LOCALHOST, 0)) {
+        Ok(listener) => listener,
+        Err(error) => {
+            warn!("Could not listen for other Neovide instances: {}", error);
+            return;
+        }
+    };
+    let port = match listener.local_addr() {
+        Ok(address) => address.port(),
+        Err(error) => {
+            warn!("Could not listen for other Neovide instances: {}", error);
+            return;
+        }
+    };
+    let result = fs::create_dir_all(instances_dir())
+        .and_then(|()| fs::write(instance_file(process::id()), port.to_string()));
+    if let Err(error) = result {
+        warn!("Could not register the Neovide instance: {}", error);
+        return;
+    }
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let mut message = String::new();
+            if BufReader::new(stream).read_line(&mut message).is_ok()
+                && message.trim() == FOCUS_MESSAGE
+            {
+                trace!("Focus requested by another instance");
+                EVENT_AGGREGATOR.send(WindowCommand::FocusWindow);
+            }
+        }
+    });
+}
+
+pub fn unregister_instance() {
+    fs::remove_file(instance_file(process::id())).ok();
+}
+
+/// The process ids of the registered instances, in the order they're cycled through.
+fn instance_ids(dir: &Path) -> Vec<u32> {
+    let mut ids: Vec<u32> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+                .collect()
+        })
+        .unwrap_or_default();
+    ids.sort_unstable();
+    ids
+}
+
+/// The instances after `current` in the cycle, wrapping around, `direction` is 1 or -1.
+fn cycle_order(ids: &[u32], current: u32, direction: i32) -> Vec<u32> {
+    let others = ids.iter().copied().filter(|id| *id != current);
+    let (mut after, mut before): (Vec<u32>, Vec<u32>) = others.partition(|id| *id > current);
+    after.append(&mut before);
+    if direction < 0 {
+        after.reverse();
+    }
+    after
+}
+
+fn send_focus(pid: u32) -> bool {
+    let port: u16 = match fs::read_to_string(instance_file(pid))
+        .ok()
+        .and_then(|port| port.trim().parse().ok())
+    {
+        Some(port) => port,
+        None => return false,
+    };
+    match TcpStream::connect_timeout(&(Ipv4Addr::LOCALHOST, port).into(), CONNECT_TIMEOUT) {
+        Ok(mut stream) => writeln!(stream, "{FOCUS_MESSAGE}").is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Focuses the next instance, or the previous one when `direction` is negative. Instances that
+/// don't answer anymore crashed without unregistering, so they are removed on the way.
+pub fn cycle_instances(direction: i32) {
+    let ids = instance_ids(&instances_dir());
+    for pid in cycle_order(&ids, process::id(), direction) {
+        if send_focus(pid) {
+            return;
+        }
+        fs::remove_file(instance_file(pid)).ok();
+    }
+}
+
+/// Moves the process out of the terminal's process group (or console), so that closing the
+/// terminal or pressing Ctrl-C in it doesn't take Neovide down as well.
+pub fn detach(command: &mut Command) {
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::process::CommandExt;
+        use winapi::um::winbase::{CREATE_NEW_PROCESS_GROUP, DETACHED_PROCESS};
+        command.creation_flags(DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP);
+    }
+}
+
+/// Starts another Neovide in `cwd`, with the same profile as this one, optionally opening `path`.
+pub fn spawn_instance(cwd: &str, path: Option<&str>) {
+    let executable = match env::current_exe() {
+        Ok(executable) => executable,
+        Err(error) => {
+            error!("Could not open a new window: {}", error);
+            return;
+        }
+    };
+    let mut command = Command::new(executable);
+    command.arg("--no-fork");
+    if let Some(profile) = &SETTINGS.get::<CmdLineSettings>().profile {
+        command.args(["--profile", profile]);
+    }
+    if let Some(path) = path {
+        command.arg(path);
+    }
+    if !cwd.is_empty() {
+        command.current_dir(cwd);
+    }
+    detach(&mut command);
+
+    if let Err(error) = command.spawn() {
+        error!("Could not open a new window: {}", error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instances_are_cycled_in_order() {
+        let ids = [10, 20, 30, 40];
+        assert_eq!(cycle_order(&ids, 20, 1), vec![30, 40, 10]);
+        assert_eq!(cycle_order(&ids, 20, -1), vec![10, 40, 30]);
+        assert_eq!(cycle_order(&ids, 40, 1), vec![10, 20, 30]);
+        assert_eq!(cycle_order(&[20], 20, 1), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn instance_ids_are_read_from_the_file_names() {
+        let dir = env::temp_dir().join(format!("neovide-instances-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["30", "4", "not-an-instance"] {
+            fs::write(dir.join(name), "1234").unwrap();
+        }
+        assert_eq!(instance_ids(&dir), vec![4, 30]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
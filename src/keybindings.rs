@@ -0,0 +1,307 @@
+use log::warn;
+use rmpv::Value;
+#[allow(unused_imports)]
+use winit::platform::modifier_supplement::KeyEventExtModifierSupplement;
+use winit::{
+    event::KeyEvent,
+    keyboard::{Key, ModifiersState},
+};
+
+use crate::{
+    bridge::{ParallelCommand, SerialCommand, UiCommand},
+    event_aggregator::EVENT_AGGREGATOR,
+    instances,
+    settings::{KeyBindings, SETTINGS},
+    window::{get_special_key, WindowCommand, WindowSettings, ZOOM_STEP},
+};
+
+/// Actions handled by Neovide itself, which can be bound to key chords in the config file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GuiAction {
+    ToggleFullscreen,
+    ZoomIn,
+    ZoomOut,
+    ResetZoom,
+    NewWindow,
+    NextWindow,
+    PreviousWindow,
+    Copy,
+    Cut,
+    Paste,
+    Save,
+    Quit,
+    Settings,
+    CommandPalette,
+    QuickOpen,
+    ToggleTerminal,
+}
+
+impl GuiAction {
+    pub const ALL: [GuiAction; 16] = [
+        GuiAction::ToggleFullscreen,
+        GuiAction::ZoomIn,
+        GuiAction::ZoomOut,
+        GuiAction::ResetZoom,
+        GuiAction::NewWindow,
+        GuiAction::NextWindow,
+        GuiAction::PreviousWindow,
+        GuiAction::Copy,
+        GuiAction::Cut,
+        GuiAction::Paste,
+        GuiAction::Save,
+        GuiAction::Quit,
+        GuiAction::Settings,
+        GuiAction::CommandPalette,
+        GuiAction::QuickOpen,
+        GuiAction::ToggleTerminal,
+    ];
+
+    /// The name used in the `keybindings` table of the config file.
+    pub fn name(self) -> &'static str {
+        match self {
+            GuiAction::ToggleFullscreen => "toggle_fullscreen",
+            GuiAction::ZoomIn => "zoom_in",
+            GuiAction::ZoomOut => "zoom_out",
+            GuiAction::ResetZoom => "reset_zoom",
+            GuiAction::NewWindow => "new_window",
+            GuiAction::NextWindow => "next_window",
+            GuiAction::PreviousWindow => "previous_window",
+            GuiAction::Copy => "copy",
+            GuiAction::Cut => "cut",
+            GuiAction::Paste => "paste",
+            GuiAction::Save => "save",
+            GuiAction::Quit => "quit",
+            GuiAction::Settings => "settings",
+            GuiAction::CommandPalette => "command_palette",
+            GuiAction::QuickOpen => "quick_open",
+            GuiAction::ToggleTerminal => "toggle_terminal",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<GuiAction> {
+        GuiAction::ALL
+            .into_iter()
+            .find(|action| action.name() == name)
+    }
+
+    /// Runs the action. The fallback is the formatted key, which is sent to Neovim instead when
+    /// the action doesn't apply, like copying without a selection.
+    pub fn run(self, fallback: Option<String>) {
+        let command: UiCommand = match self {
+            GuiAction::ToggleFullscreen => {
+                // Goes through g:neovide_fullscreen, so that it stays in sync with the window
+                let fullscreen = SETTINGS.get::<WindowSettings>().fullscreen;
+                ParallelCommand::SetSetting {
+                    name: "fullscreen".to_string(),
+                    value: Value::from(!fullscreen),
+                }
+                .into()
+            }
+            GuiAction::ZoomIn => ParallelCommand::Zoom(ZOOM_STEP).into(),
+            GuiAction::ZoomOut => ParallelCommand::Zoom(1.0 / ZOOM_STEP).into(),
+            GuiAction::ResetZoom => ParallelCommand::ResetZoom.into(),
+            GuiAction::NewWindow => ParallelCommand::NewWindow.into(),
+            GuiAction::NextWindow => {
+                instances::cycle_instances(1);
+                return;
+            }
+            GuiAction::PreviousWindow => {
+                instances::cycle_instances(-1);
+                return;
+            }
+            GuiAction::Copy => SerialCommand::YankSelection {
+                cut: false,
+                fallback,
+            }
+            .into(),
+            GuiAction::Cut => SerialCommand::YankSelection {
+                cut: true,
+                fallback,
+            }
+            .into(),
+            GuiAction::Paste => SerialCommand::PasteClipboard.into(),
+            GuiAction::Save => ParallelCommand::Save.into(),
+            GuiAction::Quit => ParallelCommand::Quit.into(),
+            GuiAction::Settings => {
+                EVENT_AGGREGATOR.send(WindowCommand::ShowSettings);
+                return;
+            }
+            // Neovim is asked for its user commands first
+            GuiAction::CommandPalette => ParallelCommand::ShowCommandPalette.into(),
+            // And for the files in its working directory
+            GuiAction::QuickOpen => ParallelCommand::ShowQuickOpen.into(),
+            GuiAction::ToggleTerminal => {
+                EVENT_AGGREGATOR.send(WindowCommand::ToggleTerminal);
+                return;
+            }
+        };
+        EVENT_AGGREGATOR.send(command);
+    }
+}
+
+/// Formats the key event as a chord like `<C-S-f>`, the way chords are written in the config file.
+pub fn chord(key_event: &KeyEvent, modifiers: ModifiersState) -> Option<String> {
+    let key = match key_event.key_without_modifiers() {
+        Key::Character(text) => text.to_lowercase(),
+        Key::Enter => "cr".to_string(),
+        key => get_special_key(&key)?.to_lowercase(),
+    };
+    let modifiers = [
+        (modifiers.control_key(), 'C'),
+        (modifiers.shift_key(), 'S'),
+        (modifiers.alt_key(), 'M'),
+        (modifiers.super_key(), 'D'),
+    ];
+    Some(format_chord(
+        modifiers
+            .into_iter()
+            .filter(|(pressed, _)| *pressed)
+            .map(|(_, modifier)| modifier),
+        &key,
+    ))
+}
+
+fn format_chord(modifiers: impl Iterator<Item = char>, key: &str) -> String {
+    let modifiers: String = modifiers.map(|modifier| format!("{modifier}-")).collect();
+    format!("<{modifiers}{key}>")
+}
+
+/// Brings a chord from the config file into the form `chord` returns, so that `<c-F>` and `<C-f>`
+/// are the same. `A-` is accepted for Alt and `T-` for Cmd too.
+pub fn normalize_chord(chord: &str) -> Option<String> {
+    let mut rest = chord.strip_prefix('<')?.strip_suffix('>')?;
+    let mut pressed = [false; 4];
+    // The key itself can be a minus, as in <C-->
+    while rest.len() > 2 && rest.as_bytes()[1] == b'-' {
+        let index = match rest.as_bytes()[0].to_ascii_uppercase() {
+            b'C' => 0,
+            b'S' => 1,
+            b'M' | b'A' => 2,
+            b'D' | b'T' => 3,
+            _ => break,
+        };
+        pressed[index] = true;
+        rest = &rest[2..];
+    }
+    if rest.is_empty() {
+        return None;
+    }
+    let modifiers = pressed
+        .into_iter()
+        .zip(['C', 'S', 'M', 'D'])
+        .filter(|(pressed, _)| *pressed)
+        .map(|(_, modifier)| modifier);
+    Some(format_chord(modifiers, &rest.to_lowercase()))
+}
+
+/// The action bound to the key event in the config file, if any.
+pub fn bound_action_of(key_event: &KeyEvent, modifiers: ModifiersState) -> Option<GuiAction> {
+    let chord = chord(key_event, modifiers)?;
+    SETTINGS.with::<KeyBindings, _>(|keybindings| bound_action(keybindings, &chord))
+}
+
+/// The action bound to the chord, if any.
+fn bound_action(keybindings: &KeyBindings, chord: &str) -> Option<GuiAction> {
+    keybindings
+        .0
+        .iter()
+        .find(|(bound_chord, _)| normalize_chord(bound_chord).as_deref() == Some(chord))
+        .and_then(|(bound_chord, name)| {
+            let action = GuiAction::parse(name);
+            if action.is_none() {
+                warn!("Unknown action {} bound to {}", name, bound_chord);
+            }
+            action
+        })
+}
+
+/// The chords bound to the action, as written in the config file.
+pub fn chords_of(keybindings: &KeyBindings, action: GuiAction) -> Vec<String> {
+    keybindings
+        .0
+        .iter()
+        .filter(|(_, name)| *name == action.name())
+        .map(|(chord, _)| chord.clone())
+        .collect()
+}
+
+/// Binds the action to the chord only, replacing its older chords and whatever used the chord.
+pub fn rebind(keybindings: &mut KeyBindings, action: GuiAction, chord: Option<String>) {
+    let normalized = chord.as_deref().and_then(normalize_chord);
+    keybindings.0.retain(|bound_chord, name| {
+        *name != action.name()
+            && (normalized.is_none() || normalize_chord(bound_chord) != normalized)
+    });
+    if let Some(chord) = chord {
+        keybindings.0.insert(chord, action.name().to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bindings(bindings: &[(&str, &str)]) -> KeyBindings {
+        KeyBindings(
+            bindings
+                .iter()
+                .map(|(chord, action)| (chord.to_string(), action.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn chords_are_normalized() {
+        assert_eq!(normalize_chord("<C-S-f>").as_deref(), Some("<C-S-f>"));
+        assert_eq!(normalize_chord("<s-c-F>").as_deref(), Some("<C-S-f>"));
+        assert_eq!(normalize_chord("<A-T-CR>").as_deref(), Some("<M-D-cr>"));
+        assert_eq!(normalize_chord("<C-->").as_deref(), Some("<C-->"));
+        assert_eq!(normalize_chord("<F11>").as_deref(), Some("<f11>"));
+        assert_eq!(normalize_chord("C-f"), None);
+        assert_eq!(normalize_chord("<>"), None);
+    }
+
+    #[test]
+    fn actions_are_found_by_chord() {
+        let keybindings = bindings(&[
+            ("<C-S-F>", "toggle_fullscreen"),
+            ("<D-n>", "new_window"),
+            ("<C-q>", "launch_rockets"),
+        ]);
+        assert_eq!(
+            bound_action(&keybindings, "<C-S-f>"),
+            Some(GuiAction::ToggleFullscreen)
+        );
+        assert_eq!(
+            bound_action(&keybindings, "<D-n>"),
+            Some(GuiAction::NewWindow)
+        );
+        assert_eq!(bound_action(&keybindings, "<C-q>"), None);
+        assert_eq!(bound_action(&keybindings, "<C-n>"), None);
+    }
+
+    #[test]
+    fn rebinding_replaces_old_chords() {
+        let mut keybindings = bindings(&[
+            ("<C-S-f>", "toggle_fullscreen"),
+            ("<F11>", "toggle_fullscreen"),
+            ("<C-v>", "paste"),
+        ]);
+        rebind(
+            &mut keybindings,
+            GuiAction::ToggleFullscreen,
+            Some("<c-v>".to_string()),
+        );
+        assert_eq!(keybindings, bindings(&[("<c-v>", "toggle_fullscreen")]));
+
+        rebind(&mut keybindings, GuiAction::ToggleFullscreen, None);
+        assert_eq!(keybindings, KeyBindings::default());
+    }
+
+    #[test]
+    fn action_names_round_trip() {
+        for action in GuiAction::ALL {
+            assert_eq!(GuiAction::parse(action.name()), Some(action));
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use log::info;
+
+lazy_static! {
+    pub static ref LATENCY_TRACKER: LatencyTracker = LatencyTracker::new();
+}
+
+// Only the most recent samples are kept, so that the numbers follow changed settings
+const MAX_SAMPLES: usize = 1000;
+// A summary is logged every this many samples
+const LOG_INTERVAL: usize = 100;
+
+struct PendingInput {
+    pressed: Instant,
+    round_trip: Option<Duration>,
+    flushed: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Sample {
+    round_trip: Duration,
+    end_to_end: Duration,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LatencyStats {
+    pub samples: usize,
+    pub end_to_end_p50: Duration,
+    pub end_to_end_p99: Duration,
+    pub round_trip_p50: Duration,
+    pub round_trip_p99: Duration,
+}
+
+#[derive(Default)]
+struct TrackerState {
+    pending: VecDeque<PendingInput>,
+    samples: VecDeque<Sample>,
+    samples_since_log: usize,
+}
+
+/// Measures how long it takes from a key press until its effect is presented on screen, split
+/// into the RPC round trip to Neovim and the whole way to the presented frame.
+pub struct LatencyTracker {
+    enabled: AtomicBool,
+    state: Mutex<TrackerState>,
+}
+
+impl LatencyTracker {
+    fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            state: Mutex::new(TrackerState::default()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Starts or stops measuring. The results so far are logged when stopping.
+    pub fn toggle(&self) {
+        let enabled = !self.enabled.fetch_xor(true, Ordering::Relaxed);
+        let mut state = self.state.lock().unwrap();
+        if enabled {
+            info!("Measuring latency");
+            *state = TrackerState::default();
+        } else {
+            state.pending.clear();
+            log_stats("Final latency", stats(&state.samples));
+        }
+    }
+
+    pub fn key_pressed(&self) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        self.state.lock().unwrap().pending.push_back(PendingInput {
+            pressed: Instant::now(),
+            round_trip: None,
+            flushed: false,
+        });
+    }
+
+    /// Records the time Neovim took to accept the oldest key that wasn't accepted yet.
+    pub fn input_sent(&self, round_trip: Duration) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        if let Some(input) = state
+            .pending
+            .iter_mut()
+            .find(|input| input.round_trip.is_none())
+        {
+            input.round_trip = Some(round_trip);
+        }
+    }
+
+    pub fn redraw_flushed(&self) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        for input in state
+            .pending
+            .iter_mut()
+            .filter(|input| input.round_trip.is_some())
+        {
+            input.flushed = true;
+        }
+    }
+
+    /// Completes the measurement of every key whose redraw made it into the presented frame.
+    pub fn frame_presented(&self) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        while state.pending.front().map_or(false, |input| input.flushed) {
+            let input = state.pending.pop_front().unwrap();
+            state.samples.push_back(Sample {
+                round_trip: input.round_trip.unwrap_or_default(),
+                end_to_end: now - input.pressed,
+            });
+            state.samples_since_log += 1;
+        }
+
+        while state.samples.len() > MAX_SAMPLES {
+            state.samples.pop_front();
+        }
+
+        if state.samples_since_log >= LOG_INTERVAL {
+            state.samples_since_log = 0;
+            log_stats("Latency", stats(&state.samples));
+        }
+    }
+
+    pub fn stats(&self) -> Option<LatencyStats> {
+        stats(&self.state.lock().unwrap().samples)
+    }
+}
+
+fn stats(samples: &VecDeque<Sample>) -> Option<LatencyStats> {
+    if samples.is_empty() {
+        return None;
+    }
+
+    let mut end_to_end: Vec<Duration> = samples.iter().map(|sample| sample.end_to_end).collect();
+    let mut round_trip: Vec<Duration> = samples.iter().map(|sample| sample.round_trip).collect();
+    end_to_end.sort_unstable();
+    round_trip.sort_unstable();
+
+    Some(LatencyStats {
+        samples: samples.len(),
+        end_to_end_p50: percentile(&end_to_end, 50),
+        end_to_end_p99: percentile(&end_to_end, 99),
+        round_trip_p50: percentile(&round_trip, 50),
+        round_trip_p99: percentile(&round_trip, 99),
+    })
+}
+
+/// The nearest rank percentile of already sorted values.
+fn percentile(sorted: &[Duration], percent: usize) -> Duration {
+    let rank = (sorted.len() * percent + 99) / 100;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn log_stats(label: &str, stats: Option<LatencyStats>) {
+    match stats {
+        Some(stats) => info!(
+            "{} over {} keys: end to end p50 {:.1}ms p99 {:.1}ms, rpc p50 {:.1}ms p99 {:.1}ms",
+            label,
+            stats.samples,
+            stats.end_to_end_p50.as_secs_f64() * 1000.0,
+            stats.end_to_end_p99.as_secs_f64() * 1000.0,
+            stats.round_trip_p50.as_secs_f64() * 1000.0,
+            stats.round_trip_p99.as_secs_f64() * 1000.0,
+        ),
+        None => info!("{}: no keys were measured", label),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(values: &[u64]) -> Vec<Duration> {
+        values
+            .iter()
+            .map(|value| Duration::from_millis(*value))
+            .collect()
+    }
+
+    #[test]
+    fn percentile_uses_nearest_rank() {
+        let sorted = millis(&(1..=100).collect::<Vec<u64>>());
+        assert_eq!(percentile(&sorted, 50), Duration::from_millis(50));
+        assert_eq!(percentile(&sorted, 99), Duration::from_millis(99));
+        assert_eq!(percentile(&millis(&[7]), 99), Duration::from_millis(7));
+    }
+
+    #[test]
+    fn stats_of_samples() {
+        let samples: VecDeque<Sample> = [10, 20, 30, 40]
+            .iter()
+            .map(|value| Sample {
+                round_trip: Duration::from_millis(value / 10),
+                end_to_end: Duration::from_millis(*value),
+            })
+            .collect();
+
+        let result = stats(&samples).unwrap();
+        assert_eq!(result.samples, 4);
+        assert_eq!(result.end_to_end_p50, Duration::from_millis(20));
+        assert_eq!(result.end_to_end_p99, Duration::from_millis(40));
+        assert_eq!(result.round_trip_p50, Duration::from_millis(2));
+        assert_eq!(result.round_trip_p99, Duration::from_millis(4));
+        assert_eq!(stats(&VecDeque::new()), None);
+    }
+}
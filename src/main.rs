@@ -8,6 +8,7 @@ extern crate neovide_derive;
 #[macro_use]
 extern crate clap;
 
+mod benchmark;
 mod bridge;
 mod channel_utils;
 mod clipboard;
@@ -16,12 +17,19 @@ mod dimensions;
 mod editor;
 mod error_handling;
 mod event_aggregator;
+mod file_position;
 mod frame;
+mod headless_replay;
+mod i18n;
+mod instances;
+mod keybindings;
+mod latency_tracker;
 mod profiling;
 mod redraw_scheduler;
 mod renderer;
 mod running_tracker;
 mod settings;
+mod startup_time;
 mod window;
 
 #[cfg(target_os = "windows")]
@@ -43,15 +51,17 @@ use backtrace::Backtrace;
 use bridge::start_bridge;
 use cmd_line::CmdLineSettings;
 use editor::start_editor;
-use renderer::{cursor_renderer::CursorSettings, RendererSettings};
+use renderer::{animation_utils, cursor_renderer::CursorSettings, RendererSettings};
 use settings::SETTINGS;
+use startup_time::STARTUP_TIME;
 use std::fs::{File, OpenOptions};
 use std::io::Write;
 use std::panic::{set_hook, PanicInfo};
+use std::process::exit;
 use std::time::SystemTime;
 use time::macros::format_description;
 use time::OffsetDateTime;
-use window::{create_window, KeyboardSettings, WindowSettings};
+use window::{create_window, KeyboardSettings, ShortcutSettings, WindowSettings};
 
 pub use channel_utils::*;
 pub use event_aggregator::*;
@@ -146,11 +156,12 @@ fn protected_main() {
     //   properly or updates to the graphics are pushed to the screen.
 
     startup_profiler();
+    lazy_static::initialize(&STARTUP_TIME);
 
     #[cfg(target_os = "windows")]
     windows_attach_to_console();
 
-    Config::init();
+    let config = Config::init();
 
     //Will exit if -h or -v
     if let Err(err) = cmd_line::handle_command_line_arguments(args().collect()) {
@@ -158,20 +169,46 @@ fn protected_main() {
         return;
     }
 
+    if let Err(err) = config.apply_profile() {
+        eprintln!("{err}");
+        return;
+    }
+
     #[cfg(not(test))]
     init_logger();
 
     trace!("Neovide version: {}", crate_version!());
 
-    maybe_disown();
-
-    #[cfg(target_os = "windows")]
-    windows_fix_dpi();
-
     WindowSettings::register();
     RendererSettings::register();
     CursorSettings::register();
     KeyboardSettings::register();
+    ShortcutSettings::register();
+
+    // Replaying and benchmarking stay in the foreground, so that CI gets the exit code
+    let cmdline = SETTINGS.get::<CmdLineSettings>();
+    if cmdline.headless_replay.is_some() || cmdline.benchmark.is_some() {
+        let result = if cmdline.benchmark.is_some() {
+            benchmark::run(&cmdline)
+        } else {
+            headless_replay::run(&cmdline)
+        };
+        match result {
+            Ok(message) => println!("{message}"),
+            Err(error) => {
+                eprintln!("{error}");
+                exit(1);
+            }
+        }
+        return;
+    }
+
+    maybe_disown();
+    instances::register_instance();
+    animation_utils::query_os_reduced_motion();
+
+    #[cfg(target_os = "windows")]
+    windows_fix_dpi();
 
     start_bridge();
     start_editor();
@@ -204,7 +241,7 @@ fn maybe_disown() {
 
     let settings = SETTINGS.get::<CmdLineSettings>();
 
-    if cfg!(debug_assertions) || settings.no_fork {
+    if !settings.fork {
         return;
     }
 
@@ -212,14 +249,11 @@ fn maybe_disown() {
     windows_detach_from_console();
 
     if let Ok(current_exe) = env::current_exe() {
-        assert!(process::Command::new(current_exe)
-            .stdin(process::Stdio::null())
-            .stdout(process::Stdio::null())
-            .stderr(process::Stdio::null())
-            .arg("--nofork")
-            .args(env::args().skip(1))
-            .spawn()
-            .is_ok());
+        let mut command = process::Command::new(current_exe);
+        command.arg("--no-fork").args(env::args().skip(1));
+        instances::detach(&mut command);
+
+        assert!(command.spawn().is_ok());
         process::exit(0);
     } else {
         eprintln!("error in disowning process, cannot obtain the path for the current executable, continuing without disowning...");
@@ -15,6 +15,9 @@ lazy_static! {
 pub struct RedrawScheduler {
     scheduled_frame: Mutex<Option<Instant>>,
     frame_queued: AtomicBool,
+    input_pending: AtomicBool,
+    immediate_frame: AtomicBool,
+    render_waker: Mutex<Option<Box<dyn Fn() + Send>>>,
 }
 
 impl RedrawScheduler {
@@ -22,6 +25,9 @@ impl RedrawScheduler {
         RedrawScheduler {
             scheduled_frame: Mutex::new(None),
             frame_queued: AtomicBool::new(true),
+            input_pending: AtomicBool::new(false),
+            immediate_frame: AtomicBool::new(false),
+            render_waker: Mutex::new(None),
         }
     }
 
@@ -43,6 +49,35 @@ impl RedrawScheduler {
         self.frame_queued.store(true, Ordering::Relaxed);
     }
 
+    /// Sets how the render loop is woken up for immediate frames.
+    pub fn set_render_waker(&self, waker: Box<dyn Fn() + Send>) {
+        *self.render_waker.lock().unwrap() = Some(waker);
+    }
+
+    /// Marks that a key was sent to Neovim, so that the redraw answering it is presented right
+    /// away instead of on the next frame tick.
+    pub fn input_sent(&self) {
+        self.input_pending.store(true, Ordering::Relaxed);
+    }
+
+    /// Called when Neovim finished a redraw. Wakes up the render loop if that redraw is the answer
+    /// to a key press.
+    pub fn redraw_flushed(&self) {
+        self.queue_next_frame();
+
+        if self.input_pending.swap(false, Ordering::Relaxed) {
+            trace!("Immediate frame requested");
+            self.immediate_frame.store(true, Ordering::Relaxed);
+            if let Some(wake) = self.render_waker.lock().unwrap().as_ref() {
+                wake();
+            }
+        }
+    }
+
+    pub fn take_immediate_frame(&self) -> bool {
+        self.immediate_frame.swap(false, Ordering::Relaxed)
+    }
+
     pub fn should_draw(&self) -> bool {
         if self.frame_queued.load(Ordering::Relaxed) {
             self.frame_queued.store(false, Ordering::Relaxed);
@@ -1,5 +1,102 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
 use skia_safe::Point;
 
+use crate::{redraw_scheduler::REDRAW_SCHEDULER, renderer::RendererSettings, settings::SETTINGS};
+
+static OS_PREFERS_REDUCED_MOTION: AtomicBool = AtomicBool::new(false);
+
+/// Looks up whether the OS asks for reduced motion, on a thread of its own since it can mean
+/// spawning a process. Animations run as usual until the answer is there.
+pub fn query_os_reduced_motion() {
+    thread::spawn(|| {
+        OS_PREFERS_REDUCED_MOTION.store(os_prefers_reduced_motion(), Ordering::Relaxed);
+    });
+}
+
+/// Whether animations should be skipped, either because `g:neovide_reduce_motion` is set or
+/// because the OS asks for reduced motion.
+pub fn reduce_motion() -> bool {
+    SETTINGS.get::<RendererSettings>().reduce_motion
+        || OS_PREFERS_REDUCED_MOTION.load(Ordering::Relaxed)
+}
+
+/// The time animations should advance by this frame. With reduced motion every animation jumps
+/// straight to its end.
+pub fn animation_dt(dt: f32) -> f32 {
+    if reduce_motion() {
+        f32::INFINITY
+    } else {
+        dt
+    }
+}
+
+/// The opacity of something that is fully visible for `delay` seconds after `since` and then fades
+/// out over `length` seconds. Redraws are scheduled until it's gone.
+pub fn fade_out_opacity(since: Instant, delay: f32, length: f32) -> f32 {
+    let elapsed = since.elapsed().as_secs_f32();
+    if elapsed < delay {
+        REDRAW_SCHEDULER.schedule(since + Duration::from_secs_f32(delay));
+        return 1.0;
+    }
+
+    if reduce_motion() {
+        return 0.0;
+    }
+
+    let opacity = 1.0 - (elapsed - delay) / length;
+    if opacity > 0.0 {
+        REDRAW_SCHEDULER.queue_next_frame();
+    }
+    opacity.max(0.0)
+}
+
+#[cfg(target_os = "windows")]
+fn os_prefers_reduced_motion() -> bool {
+    use winapi::{
+        shared::minwindef::{BOOL, TRUE},
+        um::winuser::{SystemParametersInfoW, SPI_GETCLIENTAREAANIMATION},
+    };
+
+    let mut animations_enabled: BOOL = TRUE;
+    unsafe {
+        SystemParametersInfoW(
+            SPI_GETCLIENTAREAANIMATION,
+            0,
+            &mut animations_enabled as *mut BOOL as *mut _,
+            0,
+        );
+    }
+    animations_enabled == 0
+}
+
+#[cfg(target_os = "macos")]
+fn os_prefers_reduced_motion() -> bool {
+    use cocoa::base::{id, NO};
+    use objc::{class, msg_send, runtime::BOOL, sel, sel_impl};
+
+    unsafe {
+        let workspace: id = msg_send![class!(NSWorkspace), sharedWorkspace];
+        let reduce_motion: BOOL = msg_send![workspace, accessibilityDisplayShouldReduceMotion];
+        reduce_motion != NO
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn os_prefers_reduced_motion() -> bool {
+    // GNOME based desktops expose the setting through gsettings, other desktops don't have a
+    // common one
+    std::process::Command::new("gsettings")
+        .args(["get", "org.gnome.desktop.interface", "enable-animations"])
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim() == "false")
+        .unwrap_or(false)
+}
+
 #[allow(dead_code)]
 pub fn ease_linear(t: f32) -> f32 {
     t
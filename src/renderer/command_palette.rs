@@ -0,0 +1,447 @@
+use std::{borrow::Cow, cmp::Reverse, path::Path};
+
+use rmpv::Value;
+use skia_safe::{Canvas, Color, Paint, Rect};
+use winit::{dpi::PhysicalPosition, event::KeyEvent, keyboard::Key};
+
+use crate::{
+    bridge::{ParallelCommand, SerialCommand, UiCommand},
+    event_aggregator::EVENT_AGGREGATOR,
+    i18n::{tr, tr_with},
+    keybindings::GuiAction,
+    redraw_scheduler::REDRAW_SCHEDULER,
+    renderer::{
+        overlay::{contains, draw_panel, Colors, Layout, Modal, MARGIN},
+        GridRenderer,
+    },
+};
+
+const MAX_VISIBLE_ITEMS: usize = 12;
+
+/// A user command defined in Neovim, listed in the palette next to the GUI actions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UserCommand {
+    pub name: String,
+    pub definition: String,
+    /// Commands with arguments are put on the command line instead of being run
+    pub takes_arguments: bool,
+}
+
+/// Reads the `{ name, nargs, definition }` lists sent by Neovim.
+pub fn user_commands(value: Value) -> Vec<UserCommand> {
+    let commands = match value {
+        Value::Array(commands) => commands,
+        _ => return Vec::new(),
+    };
+    commands
+        .into_iter()
+        .filter_map(|command| {
+            let fields = match command {
+                Value::Array(fields) => fields,
+                _ => return None,
+            };
+            let field = |index: usize| {
+                fields
+                    .get(index)
+                    .and_then(|field| field.as_str())
+                    .unwrap_or_default()
+                    .to_string()
+            };
+            let name = field(0);
+            if name.is_empty() {
+                return None;
+            }
+            Some(UserCommand {
+                takes_arguments: field(1) != "0",
+                definition: field(2),
+                name,
+            })
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Item {
+    Action(GuiAction),
+    Command(usize),
+    File(usize),
+}
+
+/// Lists the GUI actions and the user commands of Neovim, filtered by what's typed. Opened with
+/// Ctrl+Shift+P (Cmd+Shift+P on macOS) or `:NeovideCommandPalette`. For quick open, it lists the
+/// files of the project instead.
+#[derive(Default)]
+pub struct CommandPalette {
+    open: bool,
+    quick_open: bool,
+    query: String,
+    commands: Vec<UserCommand>,
+    // The files of quick open, relative to cwd
+    cwd: String,
+    files: Vec<String>,
+    matches: Vec<Item>,
+    selected: usize,
+    scroll: usize,
+    mouse_position: PhysicalPosition<f64>,
+    // Where the items were drawn, to find what was clicked
+    item_regions: Vec<(usize, Rect)>,
+}
+
+impl CommandPalette {
+    pub fn open(&mut self, mut commands: Vec<UserCommand>) {
+        commands.sort_by(|a, b| a.name.cmp(&b.name));
+        commands.dedup_by(|a, b| a.name == b.name);
+        self.commands = commands;
+        self.files.clear();
+        self.quick_open = false;
+        self.show();
+    }
+
+    pub fn open_files(&mut self, cwd: String, files: Vec<String>) {
+        self.cwd = cwd;
+        self.files = files;
+        self.commands.clear();
+        self.quick_open = true;
+        self.show();
+    }
+
+    fn show(&mut self) {
+        self.query.clear();
+        self.open = true;
+        self.update_matches();
+        REDRAW_SCHEDULER.queue_next_frame();
+    }
+
+    pub fn close(&mut self) {
+        self.open = false;
+        self.item_regions.clear();
+        REDRAW_SCHEDULER.queue_next_frame();
+    }
+
+    // Files are borrowed, since there can be many of them to match on every key press
+    fn label(&self, item: Item) -> Cow<str> {
+        match item {
+            Item::Action(action) => tr_with(
+                "command-palette.action",
+                &[("action", action.name().replace('_', " "))],
+            )
+            .into(),
+            Item::Command(index) => format!(":{}", self.commands[index].name).into(),
+            Item::File(index) => self.files[index].as_str().into(),
+        }
+    }
+
+    fn update_matches(&mut self) {
+        let items: Vec<Item> = if self.quick_open {
+            (0..self.files.len()).map(Item::File).collect()
+        } else {
+            GuiAction::ALL
+                .into_iter()
+                .filter(|action| *action != GuiAction::CommandPalette)
+                .map(Item::Action)
+                .chain((0..self.commands.len()).map(Item::Command))
+                .collect()
+        };
+        let mut scored: Vec<(i64, usize, Item)> = items
+            .into_iter()
+            .filter_map(|item| {
+                let label = self.label(item);
+                let score = fuzzy_score(&self.query, &label)?;
+                Some((score, label.len(), item))
+            })
+            .collect();
+        // The best matches first, shorter labels first between equally good ones
+        scored.sort_by_key(|(score, length, _)| (Reverse(*score), *length));
+        self.matches = scored.into_iter().map(|(_, _, item)| item).collect();
+        self.selected = 0;
+        self.scroll = 0;
+    }
+
+    fn handle_key(&mut self, key: &Key) {
+        match key {
+            Key::Escape => self.close(),
+            Key::Enter => self.run_selected(),
+            Key::ArrowUp => self.move_selection(-1),
+            Key::ArrowDown | Key::Tab => self.move_selection(1),
+            Key::Backspace => {
+                self.query.pop();
+                self.update_matches();
+            }
+            Key::Space => {
+                self.query.push(' ');
+                self.update_matches();
+            }
+            Key::Character(text) => {
+                self.query.push_str(text);
+                self.update_matches();
+            }
+            _ => {}
+        }
+        REDRAW_SCHEDULER.queue_next_frame();
+    }
+
+    fn move_selection(&mut self, direction: i32) {
+        let last = self.matches.len() as i32 - 1;
+        self.selected = (self.selected as i32 + direction).clamp(0, last.max(0)) as usize;
+    }
+
+    fn run_selected(&mut self) {
+        let item = match self.matches.get(self.selected) {
+            Some(item) => *item,
+            None => return,
+        };
+        self.close();
+        match item {
+            Item::Action(action) => action.run(None),
+            Item::Command(index) => {
+                // Goes through the command line, so it ends up in the history like typed commands.
+                // Normal mode first, the keys would be inserted as text otherwise.
+                let command = &self.commands[index];
+                let keys = if command.takes_arguments {
+                    format!("<C-\\><C-n>:{} ", command.name)
+                } else {
+                    format!("<C-\\><C-n>:{}<CR>", command.name)
+                };
+                EVENT_AGGREGATOR.send(UiCommand::Serial(SerialCommand::Keyboard(keys)));
+            }
+            Item::File(index) => {
+                // Absolute, in case the working directory changed since the files were listed
+                let path = Path::new(&self.cwd).join(&self.files[index]);
+                EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::OpenFile(
+                    path.to_string_lossy().into_owned(),
+                )));
+            }
+        }
+    }
+
+    pub fn draw(&mut self, canvas: &mut Canvas, grid_renderer: &mut GridRenderer, region: Rect) {
+        self.item_regions.clear();
+        if !self.open {
+            return;
+        }
+
+        let layout = Layout::new(grid_renderer);
+        let Layout {
+            char_width,
+            line_height,
+            row_height,
+            text_offset,
+        } = layout;
+        let visible_items = MAX_VISIBLE_ITEMS
+            .min(((region.height() - 2.0 * MARGIN) / row_height) as usize)
+            .saturating_sub(1);
+        let shown_items = self.matches.len().clamp(1, visible_items.max(1));
+        let panel = layout.panel(
+            region,
+            (shown_items + 1) as f32 * row_height + row_height / 2.0,
+        );
+        if panel.width() <= 0.0 || visible_items == 0 {
+            return;
+        }
+
+        let colors = Colors::new(grid_renderer);
+        let Colors {
+            foreground,
+            dimmed,
+            accent,
+            ..
+        } = colors;
+        draw_panel(canvas, panel, char_width, &colors);
+        let mut paint = Paint::default();
+        paint.set_anti_alias(true);
+
+        let left = panel.left + 2.0 * char_width;
+        let right = panel.right - 2.0 * char_width;
+        let mut draw_text = |canvas: &mut Canvas, text: &str, x: f32, top: f32, color: Color| {
+            let mut paint = Paint::default();
+            paint.set_anti_alias(true).set_color(color);
+            for blob in grid_renderer
+                .shaper
+                .shape_cached(text.to_string(), false, false, false)
+                .iter()
+            {
+                canvas.draw_text_blob(blob, (x, top + text_offset), &paint);
+            }
+        };
+
+        let mut top = panel.top + row_height / 4.0;
+        let prompt = format!("> {}", self.query);
+        draw_text(canvas, &prompt, left, top, foreground);
+        // The text cursor after the query
+        let caret_x = left + prompt.chars().count() as f32 * char_width;
+        paint.set_color(accent);
+        canvas.draw_rect(
+            Rect::from_xywh(
+                caret_x,
+                top + (row_height - line_height) / 2.0,
+                2.0,
+                line_height,
+            ),
+            &paint,
+        );
+        top += row_height;
+
+        if self.matches.is_empty() {
+            let text = if self.quick_open && self.files.is_empty() {
+                tr("command-palette.no-files")
+            } else {
+                tr("command-palette.no-matches")
+            };
+            draw_text(canvas, &text, left, top, dimmed);
+            return;
+        }
+
+        // Keep the selected item visible
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + visible_items {
+            self.scroll = self.selected + 1 - visible_items;
+        }
+
+        let items: Vec<(usize, Item)> = self
+            .matches
+            .iter()
+            .copied()
+            .enumerate()
+            .skip(self.scroll)
+            .take(visible_items)
+            .collect();
+        for (index, item) in items {
+            let item_region = Rect::new(panel.left, top, panel.right, top + row_height);
+            if index == self.selected {
+                paint.set_color(foreground.with_a(30));
+                canvas.draw_rect(item_region, &paint);
+            }
+            let label = self.label(item);
+            draw_text(canvas, &label, left, top, foreground);
+
+            // The definition of user commands, cut off where it doesn't fit
+            if let Item::Command(command) = item {
+                let label_end = left + (label.chars().count() + 2) as f32 * char_width;
+                let columns = ((right - label_end) / char_width).max(0.0) as usize;
+                let definition: String = self.commands[command]
+                    .definition
+                    .chars()
+                    .take(columns)
+                    .collect();
+                let width = definition.chars().count() as f32 * char_width;
+                draw_text(canvas, &definition, right - width, top, dimmed);
+            }
+            self.item_regions.push((index, item_region));
+            top += row_height;
+        }
+    }
+}
+
+impl Modal for CommandPalette {
+    fn is_open(&self) -> bool {
+        self.open
+    }
+
+    fn key_pressed(&mut self, key_event: &KeyEvent) {
+        self.handle_key(&key_event.logical_key);
+    }
+
+    fn cursor_moved(&mut self, position: PhysicalPosition<f64>) {
+        self.mouse_position = position;
+    }
+
+    fn clicked(&mut self) {
+        let clicked = self
+            .item_regions
+            .iter()
+            .find(|(_, region)| contains(region, self.mouse_position));
+        if let Some((index, _)) = clicked {
+            self.selected = *index;
+            self.run_selected();
+        }
+    }
+}
+
+fn same_letter(a: char, b: char) -> bool {
+    a.to_lowercase().eq(b.to_lowercase())
+}
+
+/// How well the query matches the text, when all of its letters appear in the text in order.
+/// Letters right after each other and at the start of words score higher.
+fn fuzzy_score(query: &str, text: &str) -> Option<i64> {
+    let text: Vec<char> = text.chars().collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous: Option<usize> = None;
+    for query_char in query.chars().filter(|char| !char.is_whitespace()) {
+        let index = (position..text.len()).find(|&index| same_letter(text[index], query_char))?;
+        score += 1;
+        if index > 0 && previous == Some(index - 1) {
+            score += 5;
+        }
+        let word_start = index == 0
+            || !text[index - 1].is_alphanumeric()
+            || (text[index].is_uppercase() && text[index - 1].is_lowercase());
+        if word_start {
+            score += 3;
+        }
+        previous = Some(index);
+        position = index + 1;
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn letters_have_to_appear_in_order() {
+        assert!(fuzzy_score("nw", "Neovide: new window").is_some());
+        assert!(fuzzy_score("WN", "Neovide: new window").is_some());
+        assert_eq!(fuzzy_score("wn", "new"), None);
+        assert_eq!(fuzzy_score("", "anything"), Some(0));
+    }
+
+    #[test]
+    fn word_starts_and_runs_score_higher() {
+        let word_starts = fuzzy_score("nw", "new window").unwrap();
+        let scattered = fuzzy_score("nw", "unknown").unwrap();
+        assert!(word_starts > scattered);
+
+        let run = fuzzy_score("zoom", "zoom in").unwrap();
+        let gaps = fuzzy_score("zoom", "z o o m").unwrap();
+        assert!(run > gaps);
+
+        let camel_case = fuzzy_score("gs", ":GitStatus").unwrap();
+        let inside = fuzzy_score("gs", ":Ignores").unwrap();
+        assert!(camel_case > inside);
+    }
+
+    #[test]
+    fn user_commands_are_read() {
+        let command = |name: &str, nargs: &str, definition: &str| {
+            Value::Array(vec![
+                Value::from(name),
+                Value::from(nargs),
+                Value::from(definition),
+            ])
+        };
+        let value = Value::Array(vec![
+            command("Format", "0", "lua vim.lsp.buf.format()"),
+            command("Grep", "+", "silent grep <args>"),
+            Value::from("not a command"),
+        ]);
+        assert_eq!(
+            user_commands(value),
+            vec![
+                UserCommand {
+                    name: "Format".to_string(),
+                    definition: "lua vim.lsp.buf.format()".to_string(),
+                    takes_arguments: false,
+                },
+                UserCommand {
+                    name: "Grep".to_string(),
+                    definition: "silent grep <args>".to_string(),
+                    takes_arguments: true,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,246 @@
+use std::sync::Arc;
+
+use skia_safe::{Canvas, Paint, Rect};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    dimensions::Dimensions,
+    editor::Style,
+    renderer::{popup_menu::MenuLayout, GridRenderer},
+};
+
+// The panel is only shown when at least this many columns fit next to the popup menu
+const MIN_PANEL_WIDTH: u64 = 20;
+const MAX_PANEL_WIDTH: u64 = 60;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DocumentationLineKind {
+    Heading,
+    Code,
+    Text,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DocumentationLine {
+    pub text: String,
+    pub kind: DocumentationLineKind,
+}
+
+/// Draws the documentation of the selected completion item next to the popup menu at `menu`.
+/// `screen_size` is the size of the outer grid the canvas starts at.
+pub fn draw_completion_panel(
+    canvas: &mut Canvas,
+    grid_renderer: &mut GridRenderer,
+    screen_size: Dimensions,
+    menu: &MenuLayout,
+    text: &str,
+    style: &Option<Arc<Style>>,
+) {
+    // Prefer the right side of the popup menu, like the preview popup does
+    let menu_right = menu.column + menu.width;
+    let space_right = screen_size.width.saturating_sub(menu_right);
+    let (column, width) = if space_right >= MIN_PANEL_WIDTH {
+        (menu_right, space_right.min(MAX_PANEL_WIDTH))
+    } else if menu.column >= MIN_PANEL_WIDTH {
+        let width = menu.column.min(MAX_PANEL_WIDTH);
+        (menu.column - width, width)
+    } else {
+        return;
+    };
+
+    // One cell of padding on either side
+    let lines = format_documentation(text, (width - 2) as usize);
+    if lines.is_empty() {
+        return;
+    }
+    let height = (lines.len() as u64).min(screen_size.height);
+    let row = menu.row.min(screen_size.height - height);
+
+    let font_dimensions = grid_renderer.font_dimensions;
+    let default_colors = &grid_renderer.default_style.colors;
+    let style = style.as_ref().unwrap_or(&grid_renderer.default_style);
+    let foreground = style.foreground(default_colors).to_color();
+    let background = style.background(default_colors).to_color();
+
+    let mut paint = Paint::default();
+    paint.set_anti_alias(false);
+    paint.set_color(background);
+    let panel_region = Rect::from_xywh(
+        (column * font_dimensions.width) as f32,
+        (row * font_dimensions.height) as f32,
+        (width * font_dimensions.width) as f32,
+        (height * font_dimensions.height) as f32,
+    );
+    canvas.save();
+    canvas.clip_rect(panel_region, None, Some(false));
+    canvas.draw_rect(panel_region, &paint);
+
+    let y_adjustment = grid_renderer.shaper.y_adjustment() as f32;
+    let x = panel_region.left + font_dimensions.width as f32;
+    for (index, line) in lines.into_iter().take(height as usize).enumerate() {
+        // Code is dimmed a bit to set it apart from the prose around it
+        let color = match line.kind {
+            DocumentationLineKind::Code => foreground.with_a(200),
+            _ => foreground,
+        };
+        paint.set_color(color);
+
+        let bold = line.kind == DocumentationLineKind::Heading;
+        let y = panel_region.top + (index as u64 * font_dimensions.height) as f32;
+        for blob in grid_renderer
+            .shaper
+            .shape_cached(line.text, bold, false, true)
+            .iter()
+        {
+            canvas.draw_text_blob(blob, (x, y + y_adjustment), &paint);
+        }
+    }
+    canvas.restore();
+}
+
+/// Turns the markdown-ish documentation into lines no wider than `width`. Headings and code
+/// blocks are marked so they can be styled, and the emphasis markers are dropped.
+pub fn format_documentation(text: &str, width: usize) -> Vec<DocumentationLine> {
+    let mut lines = Vec::new();
+    let mut in_code_block = false;
+
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+
+        if in_code_block {
+            lines.push(DocumentationLine {
+                text: line.graphemes(true).take(width).collect(),
+                kind: DocumentationLineKind::Code,
+            });
+        } else if trimmed.starts_with('#') {
+            let heading = strip_emphasis(trimmed.trim_start_matches('#').trim());
+            push_wrapped(&mut lines, &heading, width, DocumentationLineKind::Heading);
+        } else if trimmed.is_empty() {
+            // Consecutive blank lines collapse into one
+            if lines
+                .last()
+                .map_or(false, |last: &DocumentationLine| !last.text.is_empty())
+            {
+                lines.push(DocumentationLine {
+                    text: String::new(),
+                    kind: DocumentationLineKind::Text,
+                });
+            }
+        } else {
+            let text = strip_emphasis(line.trim_end());
+            push_wrapped(&mut lines, &text, width, DocumentationLineKind::Text);
+        }
+    }
+
+    while lines.last().map_or(false, |last| last.text.is_empty()) {
+        lines.pop();
+    }
+    lines
+}
+
+fn strip_emphasis(text: &str) -> String {
+    text.replace("**", "").replace("__", "").replace('`', "")
+}
+
+fn push_wrapped(
+    lines: &mut Vec<DocumentationLine>,
+    text: &str,
+    width: usize,
+    kind: DocumentationLineKind,
+) {
+    let mut current = String::new();
+    let mut current_width = 0;
+
+    for word in text.split_whitespace() {
+        let mut word: Vec<&str> = word.graphemes(true).collect();
+
+        if current_width > 0 && current_width + 1 + word.len() > width {
+            lines.push(DocumentationLine {
+                text: std::mem::take(&mut current),
+                kind,
+            });
+            current_width = 0;
+        }
+
+        // Words longer than a whole line get split
+        while word.len() > width {
+            let rest = word.split_off(width);
+            lines.push(DocumentationLine {
+                text: word.concat(),
+                kind,
+            });
+            word = rest;
+        }
+
+        if current_width > 0 {
+            current.push(' ');
+            current_width += 1;
+        }
+        current.push_str(&word.concat());
+        current_width += word.len();
+    }
+
+    if current_width > 0 {
+        lines.push(DocumentationLine {
+            text: current,
+            kind,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(lines: &[DocumentationLine]) -> Vec<&str> {
+        lines.iter().map(|line| line.text.as_str()).collect()
+    }
+
+    #[test]
+    fn wraps_words_at_the_width() {
+        let lines = format_documentation("The quick brown fox jumps over the lazy dog", 15);
+        assert_eq!(
+            texts(&lines),
+            vec!["The quick brown", "fox jumps over", "the lazy dog"]
+        );
+    }
+
+    #[test]
+    fn splits_words_longer_than_the_width() {
+        let lines = format_documentation("abcdefghij", 4);
+        assert_eq!(texts(&lines), vec!["abcd", "efgh", "ij"]);
+    }
+
+    #[test]
+    fn styles_headings_and_code_blocks() {
+        let lines = format_documentation(
+            "# **Vec**\n\n\n\nA `contiguous` array\n```rust\nlet v = Vec::new();\n```\n\n",
+            40,
+        );
+        assert_eq!(
+            lines,
+            vec![
+                DocumentationLine {
+                    text: "Vec".to_string(),
+                    kind: DocumentationLineKind::Heading,
+                },
+                DocumentationLine {
+                    text: String::new(),
+                    kind: DocumentationLineKind::Text,
+                },
+                DocumentationLine {
+                    text: "A contiguous array".to_string(),
+                    kind: DocumentationLineKind::Text,
+                },
+                DocumentationLine {
+                    text: "let v = Vec::new();".to_string(),
+                    kind: DocumentationLineKind::Code,
+                },
+            ]
+        );
+    }
+}
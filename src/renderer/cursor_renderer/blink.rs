@@ -1,6 +1,13 @@
 use std::time::{Duration, Instant};
 
-use crate::{editor::Cursor, redraw_scheduler::REDRAW_SCHEDULER};
+use crate::{
+    editor::Cursor,
+    redraw_scheduler::REDRAW_SCHEDULER,
+    renderer::animation_utils::{ease, ease_in_out_quad},
+};
+
+// Portion of the on and off phases spent fading when smooth blinking is enabled
+const SMOOTH_BLINK_FADE_RATIO: f32 = 0.5;
 
 #[derive(Debug)]
 pub enum BlinkState {
@@ -24,7 +31,17 @@ impl BlinkStatus {
         }
     }
 
-    pub fn update_status(&mut self, new_cursor: &Cursor) -> bool {
+    /// Restarts the blink cycle from the waiting phase, keeping the cursor visible. Used to pause
+    /// blinking while the user is typing.
+    pub fn reset(&mut self) {
+        self.state = BlinkState::Waiting;
+        self.last_transition = Instant::now();
+        REDRAW_SCHEDULER.queue_next_frame();
+    }
+
+    /// Advances the blink state machine and returns the opacity the cursor should be drawn with,
+    /// between 0.0 (hidden) and 1.0 (fully visible).
+    pub fn update_status(&mut self, new_cursor: &Cursor, smooth: bool) -> f32 {
         if self.previous_cursor.is_none() || new_cursor != self.previous_cursor.as_ref().unwrap() {
             self.previous_cursor = Some(new_cursor.clone());
             self.last_transition = Instant::now();
@@ -39,16 +56,13 @@ impl BlinkStatus {
             || new_cursor.blinkoff == Some(0)
             || new_cursor.blinkon == Some(0)
         {
-            return true;
+            return 1.0;
         }
 
-        let delay = match self.state {
-            BlinkState::Waiting => new_cursor.blinkwait,
-            BlinkState::Off => new_cursor.blinkoff,
-            BlinkState::On => new_cursor.blinkon,
-        }
-        .filter(|millis| *millis > 0)
-        .map(Duration::from_millis);
+        let delay = self
+            .current_delay(new_cursor)
+            .filter(|millis| *millis > 0)
+            .map(Duration::from_millis);
 
         if delay
             .map(|delay| self.last_transition + delay < Instant::now())
@@ -62,20 +76,43 @@ impl BlinkStatus {
             self.last_transition = Instant::now();
         }
 
-        let scheduled_frame = (match self.state {
-            BlinkState::Waiting => new_cursor.blinkwait,
-            BlinkState::Off => new_cursor.blinkoff,
-            BlinkState::On => new_cursor.blinkon,
-        })
-        .map(|delay| self.last_transition + Duration::from_millis(delay));
+        let delay = self.current_delay(new_cursor).map(Duration::from_millis);
 
-        if let Some(scheduled_frame) = scheduled_frame {
-            REDRAW_SCHEDULER.schedule(scheduled_frame);
+        if smooth && !matches!(self.state, BlinkState::Waiting) && delay.is_some() {
+            // The fade is continuous, so every frame has to be drawn until the next transition
+            REDRAW_SCHEDULER.queue_next_frame();
+        } else if let Some(delay) = delay {
+            REDRAW_SCHEDULER.schedule(self.last_transition + delay);
         }
 
+        match (&self.state, delay) {
+            (BlinkState::Waiting, _) => 1.0,
+            (BlinkState::On, Some(delay)) if smooth => self.fade(delay, 1.0, 0.0),
+            (BlinkState::Off, Some(delay)) if smooth => self.fade(delay, 0.0, 1.0),
+            (BlinkState::On, _) => 1.0,
+            (BlinkState::Off, _) => 0.0,
+        }
+    }
+
+    fn current_delay(&self, cursor: &Cursor) -> Option<u64> {
         match self.state {
-            BlinkState::Off => false,
-            BlinkState::On | BlinkState::Waiting => true,
+            BlinkState::Waiting => cursor.blinkwait,
+            BlinkState::Off => cursor.blinkoff,
+            BlinkState::On => cursor.blinkon,
         }
     }
+
+    // Holds the start value for the first part of the phase, then eases towards the end value so
+    // that the transition to the next phase is seamless.
+    fn fade(&self, delay: Duration, start: f32, end: f32) -> f32 {
+        let fade_length = delay.as_secs_f32() * SMOOTH_BLINK_FADE_RATIO;
+        if fade_length <= 0.0 {
+            return end;
+        }
+
+        let fade_start = delay.as_secs_f32() - fade_length;
+        let elapsed = self.last_transition.elapsed().as_secs_f32();
+        let t = ((elapsed - fade_start) / fade_length).clamp(0.0, 1.0);
+        ease(ease_in_out_quad, start, end, t)
+    }
 }
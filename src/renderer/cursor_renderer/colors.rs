@@ -0,0 +1,111 @@
+use skia_safe::{Color, Point};
+
+use crate::{bridge::EditorMode, renderer::animation_utils::lerp};
+
+use super::CursorSettings;
+
+/// The cursor color set for the mode, or for all modes. Colors that can't be parsed are ignored,
+/// like unset ones.
+pub fn override_color(settings: &CursorSettings, mode: &EditorMode) -> Option<Color> {
+    let mode_color = match mode {
+        EditorMode::Normal => &settings.color_normal,
+        EditorMode::Insert => &settings.color_insert,
+        EditorMode::Visual => &settings.color_visual,
+        EditorMode::Replace => &settings.color_replace,
+        EditorMode::CmdLine => &settings.color_cmdline,
+        EditorMode::Unknown(_) => &settings.color,
+    };
+    [mode_color, &settings.color]
+        .into_iter()
+        .find(|color| !color.is_empty())
+        .and_then(|color| color.parse::<csscolorparser::Color>().ok())
+        .map(|color| {
+            let [red, green, blue, _] = color.to_rgba8();
+            Color::from_rgb(red, green, blue)
+        })
+}
+
+/// Mixes the color from 'guicursor' with the override, `amount` 1.0 uses the override only.
+pub fn blend_color(guicursor: Color, override_color: Color, amount: f32) -> Color {
+    let amount = amount.clamp(0.0, 1.0);
+    let channel = |from: u8, to: u8| {
+        lerp(from as f32, to as f32, amount)
+            .round()
+            .clamp(0.0, 255.0) as u8
+    };
+    Color::from_argb(
+        guicursor.a(),
+        channel(guicursor.r(), override_color.r()),
+        channel(guicursor.g(), override_color.g()),
+        channel(guicursor.b(), override_color.b()),
+    )
+}
+
+/// How far the cursor moved, in cells.
+pub fn jump_distance(from: Point, to: Point, font_dimensions: Point) -> f32 {
+    let columns = (to.x - from.x) / font_dimensions.x;
+    let rows = (to.y - from.y) / font_dimensions.y;
+    (columns * columns + rows * rows).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(color: &str, color_insert: &str) -> CursorSettings {
+        CursorSettings {
+            color: color.to_string(),
+            color_insert: color_insert.to_string(),
+            ..CursorSettings::default()
+        }
+    }
+
+    #[test]
+    fn mode_colors_win_over_the_general_one() {
+        let settings = settings("#ff0000", "#00ff00");
+        assert_eq!(
+            override_color(&settings, &EditorMode::Insert),
+            Some(Color::from_rgb(0, 255, 0))
+        );
+        assert_eq!(
+            override_color(&settings, &EditorMode::Normal),
+            Some(Color::from_rgb(255, 0, 0))
+        );
+        assert_eq!(
+            override_color(&CursorSettings::default(), &EditorMode::Normal),
+            None
+        );
+        assert_eq!(
+            override_color(&self::settings("not a color", ""), &EditorMode::Normal),
+            None
+        );
+    }
+
+    #[test]
+    fn colors_are_blended() {
+        let black = Color::from_argb(128, 0, 0, 0);
+        let white = Color::from_rgb(255, 255, 255);
+        assert_eq!(
+            blend_color(black, white, 1.0),
+            Color::from_argb(128, 255, 255, 255)
+        );
+        assert_eq!(blend_color(black, white, 0.0), black);
+        assert_eq!(
+            blend_color(black, white, 0.5),
+            Color::from_argb(128, 128, 128, 128)
+        );
+    }
+
+    #[test]
+    fn jumps_are_measured_in_cells() {
+        let font_dimensions = Point::new(10.0, 20.0);
+        assert_eq!(
+            jump_distance(
+                Point::new(0.0, 0.0),
+                Point::new(30.0, 80.0),
+                font_dimensions
+            ),
+            5.0
+        );
+    }
+}
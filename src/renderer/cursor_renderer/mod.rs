@@ -1,10 +1,11 @@
 mod blink;
+mod colors;
 mod cursor_vfx;
 
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Instant};
 
-use skia_safe::{op, Canvas, Paint, Path, Point};
-use winit::event::{Event, WindowEvent};
+use skia_safe::{op, paint::Style, Canvas, Color, Paint, Path, Point};
+use winit::event::{ElementState, Event, WindowEvent};
 
 use crate::{
     bridge::EditorMode,
@@ -17,9 +18,13 @@ use crate::{
 };
 
 use blink::*;
+use colors::{blend_color, jump_distance, override_color};
 
 const DEFAULT_CELL_PERCENTAGE: f32 = 1.0 / 8.0;
 
+// The ring shown after a jump starts this many lines around the cursor
+const FLASH_RADIUS: f32 = 6.0;
+
 const STANDARD_CORNERS: &[(f32, f32); 4] = &[(-0.5, -0.5), (0.5, -0.5), (0.5, 0.5), (-0.5, 0.5)];
 
 #[derive(SettingGroup)]
@@ -33,6 +38,17 @@ pub struct CursorSettings {
     animate_command_line: bool,
     trail_size: f32,
     unfocused_outline_width: f32,
+    smooth_blink: bool,
+    color: String,
+    color_normal: String,
+    color_insert: String,
+    color_visual: String,
+    color_replace: String,
+    color_cmdline: String,
+    color_blend: f32,
+    jump_flash_distance: f32,
+    jump_flash_length: f32,
+    window_jump_animation_length: f32,
 
     vfx_mode: cursor_vfx::VfxMode,
     vfx_opacity: f32,
@@ -53,6 +69,17 @@ impl Default for CursorSettings {
             animate_command_line: true,
             trail_size: 0.7,
             unfocused_outline_width: 1.0 / 8.0,
+            smooth_blink: false,
+            color: "".to_string(),
+            color_normal: "".to_string(),
+            color_insert: "".to_string(),
+            color_visual: "".to_string(),
+            color_replace: "".to_string(),
+            color_cmdline: "".to_string(),
+            color_blend: 1.0,
+            jump_flash_distance: 0.0,
+            jump_flash_length: 0.5,
+            window_jump_animation_length: 0.25,
             vfx_mode: cursor_vfx::VfxMode::Disabled,
             vfx_opacity: 200.0,
             vfx_particle_lifetime: 1.2,
@@ -71,6 +98,8 @@ pub struct Corner {
     relative_position: Point,
     previous_destination: Point,
     length_multiplier: f32,
+    /// Whether the cursor moved to another window, which is animated slower
+    window_jump: bool,
     t: f32,
 }
 
@@ -82,6 +111,7 @@ impl Corner {
             relative_position: Point::new(0.0, 0.0),
             previous_destination: Point::new(-1000.0, -1000.0),
             length_multiplier: 1.0,
+            window_jump: false,
             t: 0.0,
         }
     }
@@ -93,11 +123,13 @@ impl Corner {
         destination: Point,
         dt: f32,
         immediate_movement: bool,
+        window_jump: bool,
     ) -> bool {
         if destination != self.previous_destination {
             self.t = 0.0;
             self.start_position = self.current_position;
             self.previous_destination = destination;
+            self.window_jump = window_jump;
             self.length_multiplier = if settings.distance_length_adjust {
                 (destination - self.current_position)
                     .length()
@@ -155,16 +187,22 @@ impl Corner {
                     (1.0 - settings.trail_size).max(0.0).min(1.0),
                     -direction_alignment,
                 );
-            self.t =
-                (self.t + corner_dt / (settings.animation_length * self.length_multiplier)).min(1.0)
+            let animation_length = if self.window_jump {
+                settings.window_jump_animation_length
+            } else {
+                settings.animation_length * self.length_multiplier
+            };
+            self.t = (self.t + corner_dt / animation_length).min(1.0)
         }
 
-        self.current_position = ease_point(
-            ease_out_expo,
-            self.start_position,
-            corner_destination,
-            self.t,
-        );
+        // Jumps to other windows travel across the screen, instead of darting off right away
+        let ease_func = if self.window_jump {
+            ease_in_out_cubic
+        } else {
+            ease_out_expo
+        };
+        self.current_position =
+            ease_point(ease_func, self.start_position, corner_destination, self.t);
 
         true
     }
@@ -180,6 +218,8 @@ pub struct CursorRenderer {
     cursor_vfx: Option<Box<dyn cursor_vfx::CursorVfx>>,
     previous_vfx_mode: cursor_vfx::VfxMode,
     window_has_focus: bool,
+    flash_started_at: Option<Instant>,
+    previous_window_id: u64,
 }
 
 impl CursorRenderer {
@@ -194,18 +234,32 @@ impl CursorRenderer {
             cursor_vfx: None,
             previous_vfx_mode: cursor_vfx::VfxMode::Disabled,
             window_has_focus: true,
+            flash_started_at: None,
+            previous_window_id: 0,
         };
         renderer.set_cursor_shape(&CursorShape::Block, DEFAULT_CELL_PERCENTAGE);
         renderer
     }
 
     pub fn handle_event(&mut self, event: &Event<()>) {
-        if let Event::WindowEvent {
-            event: WindowEvent::Focused(is_focused),
-            ..
-        } = event
-        {
-            self.window_has_focus = *is_focused
+        match event {
+            Event::WindowEvent {
+                event: WindowEvent::Focused(is_focused),
+                ..
+            } => {
+                self.window_has_focus = *is_focused;
+                if *is_focused {
+                    self.blink_status.reset();
+                }
+            }
+            // Keep the cursor solid while typing, like most terminals do
+            Event::WindowEvent {
+                event: WindowEvent::KeyboardInput { event, .. },
+                ..
+            } if event.state == ElementState::Pressed => {
+                self.blink_status.reset();
+            }
+            _ => {}
         }
     }
 
@@ -213,6 +267,10 @@ impl CursorRenderer {
         self.cursor = new_cursor;
     }
 
+    pub fn cursor(&self) -> &Cursor {
+        &self.cursor
+    }
+
     fn set_cursor_shape(&mut self, cursor_shape: &CursorShape, cell_percentage: f32) {
         self.corners = self
             .corners
@@ -253,7 +311,8 @@ impl CursorRenderer {
         if let Some(window) = windows.get(&self.cursor.parent_window_id) {
             let grid_x = cursor_grid_x as f32 + window.grid_current_position.x;
             let mut grid_y = cursor_grid_y as f32 + window.grid_current_position.y
-                - (window.current_scroll - window.current_surface.vertical_position);
+                - (window.current_scroll - window.current_surface.vertical_position)
+                + window.typewriter_offset;
 
             // Prevent the cursor from targeting a position outside its current window. Since only
             // the vertical direction is effected by scrolling, we only have to clamp the vertical
@@ -262,7 +321,19 @@ impl CursorRenderer {
                 .max(window.grid_current_position.y)
                 .min(window.grid_current_position.y + window.grid_size.height as f32 - 1.0);
 
-            self.destination = (grid_x * font_width as f32, grid_y * font_height as f32).into();
+            let destination = (grid_x * font_width as f32, grid_y * font_height as f32).into();
+            let flash_distance = SETTINGS.get::<CursorSettings>().jump_flash_distance;
+            if flash_distance > 0.0
+                && !self.destination.is_zero()
+                && jump_distance(
+                    self.destination,
+                    destination,
+                    (font_width as f32, font_height as f32).into(),
+                ) >= flash_distance
+            {
+                self.flash_started_at = Some(Instant::now());
+            }
+            self.destination = destination;
         } else {
             self.destination = (
                 (cursor_grid_x * font_width) as f32,
@@ -280,8 +351,15 @@ impl CursorRenderer {
         dt: f32,
     ) {
         tracy_zone!("cursor_draw");
-        let render = self.blink_status.update_status(&self.cursor);
         let settings = SETTINGS.get::<CursorSettings>();
+        let reduce_motion = reduce_motion();
+        // Blinking is paused while the window is unfocused, the hollow cursor is shown instead
+        let blink_opacity = if self.window_has_focus {
+            self.blink_status
+                .update_status(&self.cursor, settings.smooth_blink && !reduce_motion)
+        } else {
+            1.0
+        };
 
         if settings.vfx_mode != self.previous_vfx_mode {
             self.cursor_vfx = cursor_vfx::new_cursor_vfx(&settings.vfx_mode);
@@ -326,11 +404,19 @@ impl CursorRenderer {
             }
         }
 
+        // The command line has its own setting for animating
+        let window_jump = self.cursor.parent_window_id != self.previous_window_id
+            && settings.window_jump_animation_length > 0.0
+            && !matches!(current_mode, EditorMode::CmdLine)
+            && !matches!(self.previous_editor_mode, EditorMode::CmdLine);
+        self.previous_window_id = self.cursor.parent_window_id;
+
         let mut animating = false;
 
         if !center_destination.is_zero() {
             for corner in self.corners.iter_mut() {
-                let immediate_movement = !settings.animate_in_insert_mode && in_insert_mode
+                let immediate_movement = reduce_motion
+                    || !settings.animate_in_insert_mode && in_insert_mode
                     || !settings.animate_command_line && !changed_to_from_cmdline;
 
                 let corner_animating = corner.update(
@@ -339,15 +425,19 @@ impl CursorRenderer {
                     center_destination,
                     dt,
                     immediate_movement,
+                    window_jump,
                 );
 
                 animating |= corner_animating;
             }
 
-            let vfx_animating = if let Some(vfx) = self.cursor_vfx.as_mut() {
-                vfx.update(&settings, center_destination, cursor_dimensions, dt)
-            } else {
-                false
+            // Particles are frozen while the window is unfocused so they don't keep the renderer
+            // busy in the background
+            let vfx_animating = match self.cursor_vfx.as_mut() {
+                Some(vfx) if self.window_has_focus && !reduce_motion => {
+                    vfx.update(&settings, center_destination, cursor_dimensions, dt)
+                }
+                _ => false,
             };
 
             animating |= vfx_animating;
@@ -358,16 +448,31 @@ impl CursorRenderer {
         } else {
             self.previous_editor_mode = current_mode.clone();
         }
-        if !(self.cursor.enabled && render) {
+        let mut cursor_color = self
+            .cursor
+            .background(&grid_renderer.default_style.colors)
+            .to_color();
+        if let Some(override_color) = override_color(&settings, current_mode) {
+            cursor_color = blend_color(cursor_color, override_color, settings.color_blend);
+        }
+
+        if self.cursor.enabled {
+            self.draw_flash(
+                canvas,
+                &settings,
+                cursor_color,
+                center_destination,
+                grid_renderer,
+            );
+        }
+
+        if !self.cursor.enabled || blink_opacity <= 0.0 {
             return;
         }
+        let alpha = (self.cursor.alpha() as f32 * blink_opacity) as u8;
+
         // Draw Background
-        let background_color = self
-            .cursor
-            .background(&grid_renderer.default_style.colors)
-            .to_color()
-            .with_a(self.cursor.alpha());
-        paint.set_color(background_color);
+        paint.set_color(cursor_color.with_a(alpha));
 
         let path = if self.window_has_focus || self.cursor.shape != CursorShape::Block {
             self.draw_rectangle(canvas, &paint)
@@ -381,7 +486,7 @@ impl CursorRenderer {
             .cursor
             .foreground(&grid_renderer.default_style.colors)
             .to_color()
-            .with_a(self.cursor.alpha());
+            .with_a(alpha);
         paint.set_color(foreground_color);
 
         canvas.save();
@@ -393,7 +498,9 @@ impl CursorRenderer {
         let bold = style.as_ref().map(|x| x.bold).unwrap_or(false);
         let italic = style.as_ref().map(|x| x.italic).unwrap_or(false);
 
-        let blobs = &grid_renderer.shaper.shape_cached(character, bold, italic);
+        let blobs = &grid_renderer
+            .shaper
+            .shape_cached(character, bold, italic, true);
 
         for blob in blobs.iter() {
             canvas.draw_text_blob(
@@ -405,11 +512,39 @@ impl CursorRenderer {
 
         canvas.restore();
 
-        if let Some(vfx) = self.cursor_vfx.as_ref() {
+        if let Some(vfx) = self.cursor_vfx.as_ref().filter(|_| !reduce_motion) {
             vfx.render(&settings, canvas, grid_renderer, &self.cursor);
         }
     }
 
+    /// A ring closing in on the cursor after a long jump, to find it again on big screens.
+    fn draw_flash(
+        &mut self,
+        canvas: &mut Canvas,
+        settings: &CursorSettings,
+        color: Color,
+        center: Point,
+        grid_renderer: &GridRenderer,
+    ) {
+        let started_at = match self.flash_started_at {
+            Some(started_at) => started_at,
+            None => return,
+        };
+        let opacity = fade_out_opacity(started_at, 0.0, settings.jump_flash_length);
+        if opacity <= 0.0 {
+            self.flash_started_at = None;
+            return;
+        }
+
+        let line_height = grid_renderer.font_dimensions.height as f32;
+        let radius = lerp(line_height, FLASH_RADIUS * line_height, opacity);
+        let mut paint = Paint::new(color.with_a((255.0 * opacity) as u8), None);
+        paint.set_anti_alias(true);
+        paint.set_style(Style::Stroke);
+        paint.set_stroke_width(grid_renderer.em_size / 4.0);
+        canvas.draw_circle(center, radius, &paint);
+    }
+
     fn draw_rectangle(&self, canvas: &mut Canvas, paint: &Paint) -> Path {
         // The cursor is made up of four points, so I create a path with each of the four
         // corners.
@@ -460,7 +595,51 @@ impl CursorRenderer {
         path
     }
 
+    /// Moves the destination horizontally, for text that is shown before Neovim reports it.
+    pub fn shift_destination(&mut self, x: f32) {
+        self.destination.x += x;
+    }
+
     pub fn get_current_position(&self) -> Point {
         self.destination
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn move_corner(window_jump: bool) -> Corner {
+        let settings = CursorSettings {
+            distance_length_adjust: false,
+            ..CursorSettings::default()
+        };
+        let mut corner = Corner::new();
+        let font_dimensions = Point::new(10.0, 20.0);
+        let destination = Point::new(500.0, 400.0);
+        corner.update(
+            &settings,
+            font_dimensions,
+            destination,
+            0.0,
+            false,
+            window_jump,
+        );
+        corner.update(
+            &settings,
+            font_dimensions,
+            destination,
+            0.1,
+            false,
+            window_jump,
+        );
+        corner
+    }
+
+    #[test]
+    fn window_jumps_take_longer() {
+        assert_eq!(move_corner(false).t, 1.0);
+        let corner = move_corner(true);
+        assert!(corner.t > 0.0 && corner.t < 1.0);
+    }
+}
@@ -0,0 +1,127 @@
+use crate::{editor::Style, renderer::RendererSettings};
+
+/// How the highlight groups of a style are drawn on top of their attributes, chosen with the
+/// `highlight_*` settings.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Decorations {
+    /// Undercurls instead of the underline, in the special color
+    pub squiggle: bool,
+    /// A background with rounded ends, for inline hints
+    pub pill: bool,
+    /// Text drawn with `highlight_fade_opacity`, for virtual text
+    pub fade: bool,
+}
+
+/// The `highlight_*` settings, parsed once whenever they change.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DecorationPatterns {
+    settings: [String; 3],
+    squiggle: Vec<Pattern>,
+    pill: Vec<Pattern>,
+    fade: Vec<Pattern>,
+}
+
+/// A highlight group name, where a trailing `*` matches any group starting with the rest.
+#[derive(Clone, Debug, PartialEq)]
+enum Pattern {
+    Name(String),
+    Prefix(String),
+}
+
+impl DecorationPatterns {
+    /// Parses the patterns again when the settings changed, and returns whether they did.
+    pub fn update(&mut self, settings: &RendererSettings) -> bool {
+        let new_settings = [
+            settings.highlight_squiggle.clone(),
+            settings.highlight_pill.clone(),
+            settings.highlight_fade.clone(),
+        ];
+        if new_settings == self.settings {
+            return false;
+        }
+        self.squiggle = parse_patterns(&new_settings[0]);
+        self.pill = parse_patterns(&new_settings[1]);
+        self.fade = parse_patterns(&new_settings[2]);
+        self.settings = new_settings;
+        true
+    }
+
+    /// Without any pattern the highlight groups aren't needed.
+    pub fn is_empty(&self) -> bool {
+        self.squiggle.is_empty() && self.pill.is_empty() && self.fade.is_empty()
+    }
+
+    pub fn of(&self, style: &Style) -> Decorations {
+        let groups = &style.highlight_groups;
+        if groups.is_empty() {
+            return Decorations::default();
+        }
+        Decorations {
+            squiggle: matches_any(&self.squiggle, groups),
+            pill: matches_any(&self.pill, groups),
+            fade: matches_any(&self.fade, groups),
+        }
+    }
+}
+
+/// Splits the comma separated patterns.
+fn parse_patterns(patterns: &str) -> Vec<Pattern> {
+    patterns
+        .split(',')
+        .map(str::trim)
+        .filter(|pattern| !pattern.is_empty())
+        .map(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => Pattern::Prefix(prefix.to_string()),
+            None => Pattern::Name(pattern.to_string()),
+        })
+        .collect()
+}
+
+/// Whether one of the groups matches one of the patterns.
+fn matches_any(patterns: &[Pattern], groups: &[String]) -> bool {
+    patterns.iter().any(|pattern| {
+        groups.iter().any(|group| match pattern {
+            Pattern::Name(name) => group == name,
+            Pattern::Prefix(prefix) => group.starts_with(prefix.as_str()),
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn groups(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn matches(patterns: &str, groups: &[String]) -> bool {
+        matches_any(&parse_patterns(patterns), groups)
+    }
+
+    #[test]
+    fn patterns_match_whole_names_or_prefixes() {
+        let hints = groups(&["LspInlayHint"]);
+        assert!(matches("LspInlayHint", &hints));
+        assert!(matches("Comment, LspInlay*", &hints));
+        assert!(!matches("LspInlay", &hints));
+        assert!(!matches("", &hints));
+
+        let combined = groups(&["CursorLine", "DiagnosticVirtualTextWarn"]);
+        assert!(matches("DiagnosticVirtualText*", &combined));
+        assert!(!matches("DiagnosticUnderline*", &combined));
+    }
+
+    #[test]
+    fn patterns_are_only_parsed_when_changed() {
+        let mut patterns = DecorationPatterns::default();
+        let mut settings = RendererSettings::default();
+        assert!(!patterns.update(&settings));
+        assert!(patterns.is_empty());
+
+        settings.highlight_fade = "LspInlayHint".to_string();
+        assert!(patterns.update(&settings));
+        assert!(!patterns.update(&settings));
+        assert!(!patterns.is_empty());
+    }
+}
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{collections::HashSet, sync::Arc};
 
 use log::{debug, error, trace, warn};
 use lru::LruCache;
@@ -24,12 +24,29 @@ struct ShapeKey {
     pub text: String,
     pub bold: bool,
     pub italic: bool,
+    pub ligatures: bool,
 }
 
+const BYTES_PER_MEGABYTE: usize = 1024 * 1024;
+// Rough sizes of what's kept for a shaped piece of text, the glyph id and position of every
+// character and the bookkeeping of every blob
+const BYTES_PER_SHAPED_CHARACTER: usize = 12;
+const BYTES_PER_BLOB: usize = 64;
+
+// Turned off in addition to the defaults when shaping without ligatures
+const NO_LIGATURE_FEATURES: [(&str, u16); 3] = [("liga", 0), ("calt", 0), ("dlig", 0)];
+
 pub struct CachingShaper {
     options: FontOptions,
     font_loader: FontLoader,
     blob_cache: LruCache<ShapeKey, Vec<TextBlob>>,
+    blob_cache_bytes: usize,
+    blob_cache_limit: usize,
+    // Set by `g:neovide_glyph_cache_size`, otherwise the cache grows as needed
+    glyph_cache_limit: Option<usize>,
+    // Text shaped with placeholder glyphs while its fallback fonts are being loaded
+    placeholder_keys: HashSet<ShapeKey>,
+    placeholder_shaped: bool,
     shape_context: ShapeContext,
     scale_factor: f32,
     fudge_factor: f32,
@@ -43,7 +60,12 @@ impl CachingShaper {
         let mut shaper = CachingShaper {
             options,
             font_loader: FontLoader::new(font_size),
-            blob_cache: LruCache::new(10000),
+            blob_cache: LruCache::unbounded(),
+            blob_cache_bytes: 0,
+            blob_cache_limit: 16 * BYTES_PER_MEGABYTE,
+            glyph_cache_limit: None,
+            placeholder_keys: HashSet::new(),
+            placeholder_shaped: false,
             shape_context: ShapeContext::new(),
             scale_factor,
             fudge_factor: 1.0,
@@ -143,7 +165,8 @@ impl CachingShaper {
             debug!("Fudged font width: {:.2}px", self.info().1);
             self.font_loader = FontLoader::new(font_size);
         }
-        self.blob_cache.clear();
+        self.clear_shape_cache();
+        self.placeholder_keys.clear();
     }
 
     pub fn font_names(&self) -> Vec<String> {
@@ -202,8 +225,9 @@ impl CachingShaper {
         text: &str,
         bold: bool,
         italic: bool,
-    ) -> Vec<(Vec<CharCluster>, Arc<FontPair>)> {
+    ) -> (Vec<(Vec<CharCluster>, Arc<FontPair>)>, bool) {
         let mut cluster = CharCluster::new();
+        let mut uses_placeholders = false;
 
         // Enumerate the characters storing the glyph index in the user data so that we can position
         // glyphs according to Neovim's grid rules
@@ -287,17 +311,22 @@ impl CachingShaper {
                 results.push((cluster.to_owned(), best.clone()));
             } else {
                 let fallback_character = cluster.chars()[0].ch;
-                if let Some(fallback_font) =
-                    self.font_loader
-                        .load_font_for_character(bold, italic, fallback_character)
+                match self
+                    .font_loader
+                    .fallback_font_for_character(bold, italic, fallback_character)
                 {
-                    results.push((cluster.to_owned(), fallback_font));
-                } else {
-                    // Last Resort covers all of the unicode space so we will always have a fallback
-                    results.push((
-                        cluster.to_owned(),
-                        self.font_loader.get_or_load_last_resort(),
-                    ));
+                    FallbackFont::Loaded(fallback_font) => {
+                        results.push((cluster.to_owned(), fallback_font));
+                    }
+                    fallback => {
+                        // Last Resort covers all of the unicode space so we will always have a
+                        // fallback, which also stands in while the real one is loading
+                        uses_placeholders |= matches!(fallback, FallbackFont::Pending);
+                        results.push((
+                            cluster.to_owned(),
+                            self.font_loader.get_or_load_last_resort(),
+                        ));
+                    }
                 }
             }
         }
@@ -326,10 +355,13 @@ impl CachingShaper {
             grouped_results.push((current_group, current_font_option.unwrap()));
         }
 
-        grouped_results
+        (grouped_results, uses_placeholders)
     }
 
     pub fn adjust_font_cache_size(&self) {
+        if self.glyph_cache_limit.is_some() {
+            return;
+        }
         let current_font_cache_size = font_cache_limit() as f32;
         let percent_font_cache_used = font_cache_used() as f32 / current_font_cache_size;
         if percent_font_cache_used > 0.9 {
@@ -337,11 +369,49 @@ impl CachingShaper {
                 "Font cache is {}% full, increasing cache size",
                 percent_font_cache_used * 100.0
             );
-            set_font_cache_limit((percent_font_cache_used * 1.5) as usize);
+            set_font_cache_limit((current_font_cache_size * 1.5) as usize);
+        }
+    }
+
+    /// Applies the sizes in megabytes the glyph and shaping caches may grow to, evicting what was
+    /// used least recently when they're lowered. A glyph cache size of 0 lets it grow as needed.
+    pub fn set_cache_limits(&mut self, glyph_cache_size: u32, shaping_cache_size: u32) {
+        let glyph_cache_limit =
+            (glyph_cache_size > 0).then(|| glyph_cache_size as usize * BYTES_PER_MEGABYTE);
+        if glyph_cache_limit != self.glyph_cache_limit {
+            self.glyph_cache_limit = glyph_cache_limit;
+            // Skia evicts the glyphs itself once the cache is over its limit
+            if let Some(limit) = glyph_cache_limit {
+                debug!("Glyph cache limited to {} bytes", limit);
+                set_font_cache_limit(limit);
+            }
+        }
+
+        let blob_cache_limit = shaping_cache_size as usize * BYTES_PER_MEGABYTE;
+        if blob_cache_limit != self.blob_cache_limit {
+            debug!("Shaping cache limited to {} bytes", blob_cache_limit);
+            self.blob_cache_limit = blob_cache_limit;
+            self.evict_shapes();
         }
     }
 
-    pub fn shape(&mut self, text: String, bold: bool, italic: bool) -> Vec<TextBlob> {
+    fn evict_shapes(&mut self) {
+        evict_shapes(
+            &mut self.blob_cache,
+            &mut self.blob_cache_bytes,
+            self.blob_cache_limit,
+        );
+    }
+
+    /// Shapes the text into blobs, and tells whether some of its glyphs are placeholders for
+    /// fonts which are still loading.
+    pub fn shape(
+        &mut self,
+        text: String,
+        bold: bool,
+        italic: bool,
+        ligatures: bool,
+    ) -> (Vec<TextBlob>, bool) {
         let current_size = self.current_size();
         let (glyph_width, ..) = self.font_base_dimensions();
 
@@ -349,12 +419,16 @@ impl CachingShaper {
 
         trace!("Shaping text: {}", text);
 
-        for (cluster_group, font_pair) in self.build_clusters(&text, bold, italic) {
-            let mut shaper = self
+        let (cluster_groups, uses_placeholders) = self.build_clusters(&text, bold, italic);
+        for (cluster_group, font_pair) in cluster_groups {
+            let mut shaper_builder = self
                 .shape_context
                 .builder(font_pair.swash_font.as_ref())
-                .size(current_size)
-                .build();
+                .size(current_size);
+            if !ligatures {
+                shaper_builder = shaper_builder.features(NO_LIGATURE_FEATURES);
+            }
+            let mut shaper = shaper_builder.build();
 
             let charmap = font_pair.swash_font.as_ref().charmap();
             for mut cluster in cluster_group {
@@ -389,18 +463,150 @@ impl CachingShaper {
 
         self.adjust_font_cache_size();
 
-        resulting_blobs
+        (resulting_blobs, uses_placeholders)
     }
 
-    pub fn shape_cached(&mut self, text: String, bold: bool, italic: bool) -> &Vec<TextBlob> {
+    pub fn shape_cached(
+        &mut self,
+        text: String,
+        bold: bool,
+        italic: bool,
+        ligatures: bool,
+    ) -> &Vec<TextBlob> {
         tracy_zone!("shape_cached");
-        let key = ShapeKey::new(text.clone(), bold, italic);
+        let key = ShapeKey::new(text.clone(), bold, italic, ligatures);
 
         if !self.blob_cache.contains(&key) {
-            let blobs = self.shape(text, bold, italic);
+            let (blobs, uses_placeholders) = self.shape(text, bold, italic, ligatures);
+            if uses_placeholders {
+                self.placeholder_keys.insert(key.clone());
+            }
+            self.blob_cache_bytes += shape_bytes(&key, &blobs);
             self.blob_cache.put(key.clone(), blobs);
+            self.evict_shapes();
+        }
+        if self.placeholder_keys.contains(&key) {
+            self.placeholder_shaped = true;
         }
 
         self.blob_cache.get(&key).unwrap()
     }
+
+    /// How many shaped pieces of text are cached.
+    pub fn cached_shapes(&self) -> usize {
+        self.blob_cache.len()
+    }
+
+    /// The estimated bytes of the cached shapes.
+    pub fn cached_shape_bytes(&self) -> usize {
+        self.blob_cache_bytes
+    }
+
+    pub fn clear_shape_cache(&mut self) {
+        self.blob_cache.clear();
+        self.blob_cache_bytes = 0;
+    }
+
+    /// Whether text with placeholder glyphs was shaped since the last call.
+    pub fn take_placeholder_shaped(&mut self) -> bool {
+        std::mem::take(&mut self.placeholder_shaped)
+    }
+
+    pub fn is_loading_fallback_fonts(&self) -> bool {
+        self.font_loader.is_loading_fallback_fonts()
+    }
+
+    /// Takes the fallback fonts loaded in the background. Returns true when some arrived, after
+    /// dropping the text shaped with placeholders so that it's shaped again with them.
+    pub fn receive_fallback_fonts(&mut self) -> bool {
+        if !self.font_loader.receive_fallback_fonts() {
+            return false;
+        }
+        for key in self.placeholder_keys.drain() {
+            if let Some(blobs) = self.blob_cache.pop(&key) {
+                self.blob_cache_bytes -= shape_bytes(&key, &blobs);
+            }
+        }
+        true
+    }
+}
+
+fn shape_bytes(key: &ShapeKey, blobs: &[TextBlob]) -> usize {
+    key.text.len()
+        + key.text.chars().count() * BYTES_PER_SHAPED_CHARACTER
+        + blobs.len() * BYTES_PER_BLOB
+}
+
+// The text shaped last is always kept, since it's about to be drawn
+fn evict_shapes(cache: &mut LruCache<ShapeKey, Vec<TextBlob>>, bytes: &mut usize, limit: usize) {
+    while *bytes > limit && cache.len() > 1 {
+        match cache.pop_lru() {
+            Some((key, blobs)) => *bytes -= shape_bytes(&key, &blobs),
+            None => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache_shape(cache: &mut LruCache<ShapeKey, Vec<TextBlob>>, bytes: &mut usize, text: &str) {
+        let key = ShapeKey::new(text.to_string(), false, false, false);
+        *bytes += shape_bytes(&key, &[]);
+        cache.put(key, Vec::new());
+    }
+
+    fn cached_texts(cache: &LruCache<ShapeKey, Vec<TextBlob>>) -> Vec<&str> {
+        let mut texts: Vec<&str> = cache.iter().map(|(key, _)| key.text.as_str()).collect();
+        texts.sort();
+        texts
+    }
+
+    #[test]
+    fn shapes_are_measured_by_their_characters() {
+        let key = ShapeKey::new("ab".to_string(), false, false, false);
+        assert_eq!(shape_bytes(&key, &[]), 2 + 2 * BYTES_PER_SHAPED_CHARACTER);
+        // Multibyte characters are counted once as a shaped character
+        let key = ShapeKey::new("é".to_string(), true, false, false);
+        assert_eq!(shape_bytes(&key, &[]), 2 + BYTES_PER_SHAPED_CHARACTER);
+    }
+
+    #[test]
+    fn the_least_recently_used_shapes_are_evicted_over_the_limit() {
+        let mut cache = LruCache::unbounded();
+        let mut bytes = 0;
+        for text in ["a", "b", "c", "d"] {
+            cache_shape(&mut cache, &mut bytes, text);
+        }
+        let shape_size = bytes / 4;
+        // Using it keeps it around
+        cache.get(&ShapeKey::new("a".to_string(), false, false, false));
+
+        evict_shapes(&mut cache, &mut bytes, 2 * shape_size);
+        assert_eq!(cached_texts(&cache), vec!["a", "d"]);
+        assert_eq!(bytes, 2 * shape_size);
+
+        // Nothing is evicted under the limit
+        evict_shapes(&mut cache, &mut bytes, 2 * shape_size);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn the_last_shape_is_kept_over_the_limit() {
+        let mut cache = LruCache::unbounded();
+        let mut bytes = 0;
+        cache_shape(&mut cache, &mut bytes, "a long line of text");
+        cache_shape(&mut cache, &mut bytes, "another long line of text");
+
+        evict_shapes(&mut cache, &mut bytes, 0);
+        assert_eq!(cached_texts(&cache), vec!["another long line of text"]);
+        assert_eq!(
+            bytes,
+            shape_bytes(
+                &ShapeKey::new("another long line of text".to_string(), false, false, false),
+                &[]
+            )
+        );
+    }
 }
@@ -1,4 +1,11 @@
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc,
+    },
+    thread,
+};
 
 use log::trace;
 use lru::LruCache;
@@ -7,6 +14,7 @@ use skia_safe::{
     Typeface,
 };
 
+use crate::redraw_scheduler::REDRAW_SCHEDULER;
 use crate::renderer::fonts::font_options::{FontEdging, FontHinting};
 use crate::renderer::fonts::swash_font::SwashFont;
 
@@ -54,11 +62,49 @@ pub struct FontKey {
     pub edging: FontEdging,
 }
 
+/// A character which none of the configured or loaded fonts have.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+struct FallbackRequest {
+    bold: bool,
+    italic: bool,
+    character: char,
+}
+
+enum FallbackMatch {
+    // Loaded on the fallback thread, reading the data of the font takes a while for big ones
+    Font(FontPair),
+    // The font with the character was sent for an earlier request
+    AlreadySent,
+    Missing,
+}
+
+struct FallbackResult {
+    request: FallbackRequest,
+    found: FallbackMatch,
+}
+
+enum FallbackState {
+    Pending,
+    Missing,
+}
+
+pub enum FallbackFont {
+    Loaded(Arc<FontPair>),
+    /// Still being looked up, the text is drawn with placeholder glyphs until then
+    Pending,
+    /// No font of the system has the character
+    Missing,
+}
+
 pub struct FontLoader {
     font_mgr: FontMgr,
     cache: LruCache<FontKey, Arc<FontPair>>,
     font_size: f32,
     last_resort: Option<Arc<FontPair>>,
+    // Kept for as long as the loader, so that the fallback thread sends every font only once
+    fallback_fonts: Vec<Arc<FontPair>>,
+    fallbacks: HashMap<FallbackRequest, FallbackState>,
+    fallback_thread: Option<(Sender<FallbackRequest>, Receiver<FallbackResult>)>,
 }
 
 impl FontLoader {
@@ -68,6 +114,9 @@ impl FontLoader {
             cache: LruCache::new(20),
             font_size,
             last_resort: None,
+            fallback_fonts: Vec::new(),
+            fallbacks: HashMap::new(),
+            fallback_thread: None,
         }
     }
 
@@ -99,33 +148,77 @@ impl FontLoader {
         Some(font_arc)
     }
 
-    pub fn load_font_for_character(
+    /// The font for a character which none of the configured or loaded fonts have. The system is
+    /// searched on a background thread, since matching and reading fonts with many glyphs, like
+    /// the CJK ones, takes longer than a frame.
+    pub fn fallback_font_for_character(
         &mut self,
         bold: bool,
         italic: bool,
         character: char,
-    ) -> Option<Arc<FontPair>> {
-        let font_style = font_style(bold, italic);
-        let typeface =
-            self.font_mgr
-                .match_family_style_character("", font_style, &[], character as i32)?;
-
-        let font_key = FontKey {
+    ) -> FallbackFont {
+        let request = FallbackRequest {
             bold,
             italic,
-            family_name: Some(typeface.family_name()),
-            hinting: FontHinting::default(),
-            edging: FontEdging::default(),
+            character,
         };
+        match self.fallbacks.get(&request) {
+            Some(FallbackState::Pending) => return FallbackFont::Pending,
+            Some(FallbackState::Missing) => return FallbackFont::Missing,
+            None => {}
+        }
 
-        let font_pair = Arc::new(FontPair::new(
-            font_key.clone(),
-            Font::from_typeface(typeface, self.font_size),
-        )?);
+        let font_size = self.font_size;
+        let (requests, _) = self
+            .fallback_thread
+            .get_or_insert_with(|| spawn_fallback_thread(font_size));
+        if requests.send(request).is_err() {
+            self.fallbacks.insert(request, FallbackState::Missing);
+            return FallbackFont::Missing;
+        }
+        trace!("Looking up a fallback font for {:?}", character);
+        self.fallbacks.insert(request, FallbackState::Pending);
+        FallbackFont::Pending
+    }
 
-        self.cache.put(font_key, font_pair.clone());
+    /// Takes the fonts the fallback thread found. Returns true when some lookups finished, so that
+    /// the text drawn with placeholders can be shaped again.
+    pub fn receive_fallback_fonts(&mut self) -> bool {
+        let results: Vec<FallbackResult> = match &self.fallback_thread {
+            Some((_, results)) => results.try_iter().collect(),
+            None => return false,
+        };
+        if results.is_empty() {
+            return false;
+        }
 
-        Some(font_pair)
+        for FallbackResult { request, found } in results {
+            self.fallbacks.remove(&request);
+            if let FallbackMatch::Font(font_pair) = found {
+                self.fallback_fonts.push(Arc::new(font_pair));
+            }
+
+            // Otherwise it would be looked up again with every redraw
+            let covered = self.fallback_fonts.iter().any(|font_pair| {
+                font_pair
+                    .swash_font
+                    .as_ref()
+                    .charmap()
+                    .map(request.character)
+                    != 0
+            });
+            if !covered {
+                self.fallbacks.insert(request, FallbackState::Missing);
+            }
+        }
+        true
+    }
+
+    /// Whether some fallback fonts are still being looked up.
+    pub fn is_loading_fallback_fonts(&self) -> bool {
+        self.fallbacks
+            .values()
+            .any(|state| matches!(state, FallbackState::Pending))
     }
 
     pub fn get_or_load_last_resort(&mut self) -> Arc<FontPair> {
@@ -146,7 +239,11 @@ impl FontLoader {
     }
 
     pub fn loaded_fonts(&self) -> Vec<Arc<FontPair>> {
-        self.cache.iter().map(|(_, v)| v.clone()).collect()
+        self.cache
+            .iter()
+            .map(|(_, v)| v.clone())
+            .chain(self.fallback_fonts.iter().cloned())
+            .collect()
     }
 
     pub fn refresh(&mut self, font_pair: &FontPair) {
@@ -158,6 +255,52 @@ impl FontLoader {
     }
 }
 
+fn spawn_fallback_thread(font_size: f32) -> (Sender<FallbackRequest>, Receiver<FallbackResult>) {
+    let (request_sender, requests) = channel::<FallbackRequest>();
+    let (result_sender, results) = channel();
+    thread::spawn(move || {
+        let font_mgr = FontMgr::new();
+        let mut sent_families = HashSet::new();
+        // Ends when the loader is dropped, like after the font size changed
+        for request in requests {
+            let font_style = font_style(request.bold, request.italic);
+            let typeface = font_mgr.match_family_style_character(
+                "",
+                font_style,
+                &[],
+                request.character as i32,
+            );
+            let found = match typeface {
+                Some(typeface) => {
+                    let family = (typeface.family_name(), request.bold, request.italic);
+                    if !sent_families.insert(family) {
+                        FallbackMatch::AlreadySent
+                    } else {
+                        let font_key = FontKey {
+                            bold: request.bold,
+                            italic: request.italic,
+                            family_name: Some(typeface.family_name()),
+                            hinting: FontHinting::default(),
+                            edging: FontEdging::default(),
+                        };
+                        FontPair::new(font_key, Font::from_typeface(typeface, font_size))
+                            .map_or(FallbackMatch::Missing, FallbackMatch::Font)
+                    }
+                }
+                None => FallbackMatch::Missing,
+            };
+            if result_sender
+                .send(FallbackResult { request, found })
+                .is_err()
+            {
+                break;
+            }
+            REDRAW_SCHEDULER.queue_next_frame();
+        }
+    });
+    (request_sender, results)
+}
+
 fn font_style(bold: bool, italic: bool) -> FontStyle {
     match (bold, italic) {
         (true, true) => FontStyle::bold_italic(),
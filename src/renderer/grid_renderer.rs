@@ -2,7 +2,8 @@ use std::sync::Arc;
 
 use log::trace;
 use skia_safe::{
-    colors, dash_path_effect, BlendMode, Canvas, Color, Paint, Path, Point, Rect, HSV,
+    colors, dash_path_effect, gradient_shader, BlendMode, Canvas, Color, Paint, Path, Point, RRect,
+    Rect, TileMode, HSV,
 };
 use winit::dpi::PhysicalSize;
 
@@ -10,7 +11,11 @@ use crate::{
     dimensions::Dimensions,
     editor::{Colors, Style, UnderlineStyle},
     profiling::tracy_zone,
-    renderer::{CachingShaper, RendererSettings},
+    renderer::{
+        decorations::DecorationPatterns,
+        status_line::{gradient_colors, Separator},
+        CachingShaper, RendererSettings,
+    },
     settings::*,
     window::WindowSettings,
 };
@@ -23,6 +28,7 @@ pub struct GridRenderer {
     pub font_dimensions: Dimensions,
     pub scale_factor: f64,
     pub is_ready: bool,
+    pub decoration_patterns: DecorationPatterns,
 }
 
 impl GridRenderer {
@@ -46,6 +52,7 @@ impl GridRenderer {
             font_dimensions,
             scale_factor,
             is_ready: false,
+            decoration_patterns: DecorationPatterns::default(),
         }
     }
 
@@ -96,6 +103,11 @@ impl GridRenderer {
         self.default_style.colors.background.unwrap().to_color()
     }
 
+    /// Sets the background used until Neovim sends its default colors.
+    pub fn set_default_background(&mut self, background: Color) {
+        Arc::make_mut(&mut self.default_style).colors.background = Some(background.into());
+    }
+
     pub fn draw_background(
         &mut self,
         canvas: &mut Canvas,
@@ -103,6 +115,8 @@ impl GridRenderer {
         cell_width: u64,
         style: &Option<Arc<Style>>,
         is_floating: bool,
+        window_background: Color,
+        is_fancy_status_line: bool,
     ) {
         tracy_zone!("draw_background");
         self.paint.set_blend_mode(BlendMode::Src);
@@ -122,13 +136,84 @@ impl GridRenderer {
         if is_floating {
             self.paint
                 .set_alpha((255.0 * ((100 - style.blend) as f32 / 100.0)) as u8);
-        } else if (SETTINGS.get::<WindowSettings>().transparency - 1.0).abs() > f32::EPSILON
+        } else if SETTINGS.get::<WindowSettings>().has_transparency()
             // Only make background color transparent
-            && self.paint.color() == self.get_default_background()
+            && self.paint.color() == window_background
         {
             self.paint.set_alpha(0);
         }
+
+        if is_fancy_status_line {
+            let (top, bottom) = gradient_colors(self.paint.color4f());
+            let colors = [top.to_color(), bottom.to_color()];
+            self.paint.set_shader(gradient_shader::linear(
+                ((region.left, region.top), (region.left, region.bottom)),
+                &colors[..],
+                None,
+                TileMode::Clamp,
+                None,
+                None,
+            ));
+        }
+        if self.decoration_patterns.of(style).pill && !is_fancy_status_line {
+            self.draw_pill(canvas, region, is_floating, window_background);
+            return;
+        }
         canvas.draw_rect(region, &self.paint);
+        self.paint.set_shader(None);
+    }
+
+    // Draws the background with rounded ends, over the background of the window
+    fn draw_pill(
+        &mut self,
+        canvas: &mut Canvas,
+        region: Rect,
+        is_floating: bool,
+        window_background: Color,
+    ) {
+        let mut window_paint = self.paint.clone();
+        window_paint.set_color(window_background);
+        if is_floating {
+            window_paint.set_alpha(self.paint.alpha());
+        } else if SETTINGS.get::<WindowSettings>().has_transparency() {
+            window_paint.set_alpha(0);
+        }
+        canvas.draw_rect(region, &window_paint);
+
+        let pill = region.with_inset((0.0, (region.height() * 0.1).round()));
+        let radius = pill.height() / 2.0;
+        self.paint
+            .set_blend_mode(BlendMode::SrcOver)
+            .set_anti_alias(true);
+        canvas.draw_rrect(RRect::new_rect_xy(pill, radius, radius), &self.paint);
+        self.paint.set_anti_alias(false);
+    }
+
+    /// Draws a Powerline separator as a shape instead of the glyph of the font.
+    pub fn draw_separator(
+        &self,
+        canvas: &mut Canvas,
+        separator: Separator,
+        grid_position: (u64, u64),
+        style: &Option<Arc<Style>>,
+    ) {
+        tracy_zone!("draw_separator");
+        let region = self.compute_text_region(grid_position, 1);
+        let style = style.as_ref().unwrap_or(&self.default_style);
+
+        let mut paint = self.paint.clone();
+        paint
+            .set_blend_mode(BlendMode::SrcOver)
+            .set_anti_alias(true)
+            .set_color(style.foreground(&self.default_style.colors).to_color());
+        if separator.filled {
+            paint.set_style(skia_safe::paint::Style::Fill);
+        } else {
+            paint
+                .set_style(skia_safe::paint::Style::Stroke)
+                .set_stroke_width((self.em_size / 12.0).max(1.0));
+        }
+        canvas.draw_path(&separator.path(region), &paint);
     }
 
     pub fn draw_foreground(
@@ -138,12 +223,14 @@ impl GridRenderer {
         grid_position: (u64, u64),
         cell_width: u64,
         style: &Option<Arc<Style>>,
+        ligatures: bool,
     ) {
         tracy_zone!("draw_foreground");
         let (x, y) = grid_position * self.font_dimensions;
         let width = cell_width * self.font_dimensions.width;
 
         let style = style.as_ref().unwrap_or(&self.default_style);
+        let decorations = self.decoration_patterns.of(style);
 
         // We don't want to clip text in the x position, only the y so we add a buffer of 1
         // character on either side of the region so that we clip vertically but not horizontally.
@@ -151,7 +238,12 @@ impl GridRenderer {
         let clip_position = (grid_x.saturating_sub(1), grid_y);
         let region = self.compute_text_region(clip_position, cell_width + 2);
 
-        if let Some(underline_style) = style.underline {
+        let underline = if decorations.squiggle {
+            Some(UnderlineStyle::UnderCurl)
+        } else {
+            style.underline
+        };
+        if let Some(underline_style) = underline {
             let line_position = self.shaper.underline_position();
             let p1 = (
                 x as f32,
@@ -178,11 +270,19 @@ impl GridRenderer {
             self.paint
                 .set_color(style.foreground(&self.default_style.colors).to_color());
         }
+        if decorations.fade {
+            let opacity = SETTINGS
+                .get::<RendererSettings>()
+                .highlight_fade_opacity
+                .clamp(0.0, 1.0);
+            self.paint
+                .set_alpha((self.paint.alpha() as f32 * opacity) as u8);
+        }
         self.paint.set_anti_alias(false);
 
         for blob in self
             .shaper
-            .shape_cached(text, style.bold, style.italic)
+            .shape_cached(text, style.bold, style.italic, ligatures)
             .iter()
         {
             canvas.draw_text_blob(blob, (x as f32, (y + y_adjustment) as f32), &self.paint);
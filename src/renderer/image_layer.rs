@@ -0,0 +1,321 @@
+use std::fmt;
+
+use log::error;
+use resvg::{
+    tiny_skia::{Pixmap, Transform},
+    usvg::{self, FitTo},
+};
+use skia_safe::{
+    AlphaType, Canvas, ColorType, Data, FilterMode, Image, ImageInfo, MipmapMode, Paint, Point,
+    Rect, SamplingOptions, Size,
+};
+
+use crate::dimensions::Dimensions;
+
+// SVGs are rasterized at most this big on either side, larger ones are scaled up when drawn
+const MAX_SVG_SIZE: f32 = 4096.0;
+
+/// An image decoded off the render thread, from a PNG, JPEG or SVG.
+#[derive(Clone)]
+pub enum DecodedImage {
+    /// Unpremultiplied RGBA pixels
+    Raster {
+        width: u32,
+        height: u32,
+        pixels: Vec<u8>,
+    },
+    /// SVGs are rasterized for the size they're drawn with, so only their size is known up front
+    Svg {
+        data: Vec<u8>,
+        width: f32,
+        height: f32,
+    },
+}
+
+// The pixels would flood the log of the events
+impl fmt::Debug for DecodedImage {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodedImage::Raster { width, height, .. } => {
+                write!(formatter, "Raster({width}x{height})")
+            }
+            DecodedImage::Svg { width, height, .. } => write!(formatter, "Svg({width}x{height})"),
+        }
+    }
+}
+
+/// Decodes a PNG, JPEG or SVG. Meant to be called outside of the render thread, as big images
+/// take a while.
+pub fn decode_image(data: Vec<u8>) -> Option<DecodedImage> {
+    if is_svg(&data) {
+        // The size given by the width, height or viewBox of the svg element
+        let tree = usvg::Tree::from_data(&data, &usvg::Options::default()).ok()?;
+        let (width, height) = (tree.size.width() as f32, tree.size.height() as f32);
+        return Some(DecodedImage::Svg {
+            data,
+            width,
+            height,
+        });
+    }
+
+    let image = image::load_from_memory(&data).ok()?.into_rgba8();
+    Some(DecodedImage::Raster {
+        width: image.width(),
+        height: image.height(),
+        pixels: image.into_raw(),
+    })
+}
+
+/// An image placed by a plugin, at a position of the screen in grid cells.
+#[derive(Clone, Debug)]
+pub struct ImagePlacement {
+    pub id: u64,
+    pub image: DecodedImage,
+    pub row: f32,
+    pub col: f32,
+    /// The size in cells. With only one of them, the other follows the aspect ratio of the image,
+    /// without either the image keeps its size in pixels.
+    pub width: Option<f32>,
+    pub height: Option<f32>,
+    /// Images with higher z are drawn over the ones with lower z
+    pub z: i64,
+}
+
+#[derive(Clone, Debug)]
+pub enum ImageCommand {
+    Place(ImagePlacement),
+    Remove(u64),
+    Clear,
+}
+
+enum Content {
+    Raster(Image),
+    // Rasterized again whenever it's drawn with another size, so that it stays sharp
+    Svg {
+        data: Vec<u8>,
+        rendered: Option<Image>,
+    },
+}
+
+struct PlacedImage {
+    id: u64,
+    row: f32,
+    col: f32,
+    width: Option<f32>,
+    height: Option<f32>,
+    z: i64,
+    content: Content,
+    natural_size: Size,
+}
+
+/// Draws the images placed by plugins over the windows, like markdown previews or thumbnails.
+#[derive(Default)]
+pub struct ImageLayer {
+    // Kept in drawing order
+    images: Vec<PlacedImage>,
+}
+
+impl ImageLayer {
+    pub fn handle_command(&mut self, command: ImageCommand) {
+        match command {
+            ImageCommand::Place(placement) => self.place(placement),
+            ImageCommand::Remove(id) => self.images.retain(|image| image.id != id),
+            ImageCommand::Clear => self.images.clear(),
+        }
+    }
+
+    fn place(&mut self, placement: ImagePlacement) {
+        let (content, natural_size) = match placement.image {
+            DecodedImage::Raster {
+                width,
+                height,
+                pixels,
+            } => {
+                let info = ImageInfo::new(
+                    (width as i32, height as i32),
+                    ColorType::RGBA8888,
+                    AlphaType::Unpremul,
+                    None,
+                );
+                let row_bytes = width as usize * 4;
+                match Image::from_raster_data(&info, Data::new_copy(&pixels), row_bytes) {
+                    Some(image) => (
+                        Content::Raster(image),
+                        Size::new(width as f32, height as f32),
+                    ),
+                    None => {
+                        error!("Could not create the image {}", placement.id);
+                        return;
+                    }
+                }
+            }
+            DecodedImage::Svg {
+                data,
+                width,
+                height,
+            } => (
+                Content::Svg {
+                    data,
+                    rendered: None,
+                },
+                Size::new(width, height),
+            ),
+        };
+
+        self.images.retain(|image| image.id != placement.id);
+        self.images.push(PlacedImage {
+            id: placement.id,
+            row: placement.row,
+            col: placement.col,
+            width: placement.width,
+            height: placement.height,
+            z: placement.z,
+            content,
+            natural_size,
+        });
+        self.images.sort_by_key(|image| (image.z, image.id));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+
+    /// Draws the images relative to `origin`, the top left corner of the root grid.
+    pub fn draw(&mut self, canvas: &mut Canvas, origin: Point, font_dimensions: Dimensions) {
+        let cell = Size::new(font_dimensions.width as f32, font_dimensions.height as f32);
+        for image in &mut self.images {
+            let size = image_size(image.natural_size, image.width, image.height, cell);
+            let rect = Rect::from_point_and_size(
+                (
+                    origin.x + image.col * cell.width,
+                    origin.y + image.row * cell.height,
+                ),
+                size,
+            );
+            if rect.is_empty() {
+                continue;
+            }
+
+            let raster = match &mut image.content {
+                Content::Raster(raster) => &*raster,
+                Content::Svg { data, rendered } => {
+                    let (width, height) = svg_raster_size(size);
+                    let rendered_size = rendered
+                        .as_ref()
+                        .map(|image| (image.width() as u32, image.height() as u32));
+                    if rendered_size != Some((width, height)) {
+                        *rendered = render_svg(data, width, height);
+                    }
+                    match rendered {
+                        Some(rendered) => &*rendered,
+                        None => continue,
+                    }
+                }
+            };
+            let mut paint = Paint::default();
+            paint.set_anti_alias(true);
+            canvas.draw_image_rect_with_sampling_options(
+                raster,
+                None,
+                rect,
+                SamplingOptions::new(FilterMode::Linear, MipmapMode::Linear),
+                &paint,
+            );
+        }
+    }
+}
+
+/// The size an SVG drawn with `size` is rasterized with, scaled down to fit `MAX_SVG_SIZE`.
+fn svg_raster_size(size: Size) -> (u32, u32) {
+    let scale = (MAX_SVG_SIZE / size.width.max(size.height)).min(1.0);
+    (
+        (size.width * scale).round() as u32,
+        (size.height * scale).round() as u32,
+    )
+}
+
+fn render_svg(data: &[u8], width: u32, height: u32) -> Option<Image> {
+    let tree = usvg::Tree::from_data(data, &usvg::Options::default()).ok()?;
+    let mut pixmap = Pixmap::new(width, height)?;
+    resvg::render(
+        &tree,
+        FitTo::Size(width, height),
+        Transform::default(),
+        pixmap.as_mut(),
+    )?;
+    let info = ImageInfo::new(
+        (width as i32, height as i32),
+        ColorType::RGBA8888,
+        AlphaType::Premul,
+        None,
+    );
+    Image::from_raster_data(&info, Data::new_copy(pixmap.data()), width as usize * 4)
+}
+
+// SVGs are text, starting with an XML declaration or the svg element itself
+fn is_svg(data: &[u8]) -> bool {
+    let start = &data[..data.len().min(512)];
+    let text = String::from_utf8_lossy(start);
+    let text = text.trim_start_matches('\u{feff}').trim_start();
+    text.starts_with("<?xml") || text.starts_with("<svg") || text.starts_with("<!--")
+}
+
+/// The size of the image in pixels, for the size in cells it was placed with.
+fn image_size(natural: Size, width: Option<f32>, height: Option<f32>, cell: Size) -> Size {
+    let aspect_ratio = if natural.height > 0.0 {
+        natural.width / natural.height
+    } else {
+        1.0
+    };
+    match (width, height) {
+        (Some(width), Some(height)) => Size::new(width * cell.width, height * cell.height),
+        (Some(width), None) => {
+            let width = width * cell.width;
+            Size::new(width, width / aspect_ratio)
+        }
+        (None, Some(height)) => {
+            let height = height * cell.height;
+            Size::new(height * aspect_ratio, height)
+        }
+        (None, None) => natural,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_sizes_follow_the_aspect_ratio() {
+        let natural = Size::new(200.0, 100.0);
+        let cell = Size::new(10.0, 20.0);
+        assert_eq!(
+            image_size(natural, Some(4.0), Some(3.0), cell),
+            Size::new(40.0, 60.0)
+        );
+        assert_eq!(
+            image_size(natural, Some(4.0), None, cell),
+            Size::new(40.0, 20.0)
+        );
+        assert_eq!(
+            image_size(natural, None, Some(3.0), cell),
+            Size::new(120.0, 60.0)
+        );
+        assert_eq!(image_size(natural, None, None, cell), natural);
+    }
+
+    #[test]
+    fn big_svgs_are_rasterized_smaller() {
+        assert_eq!(svg_raster_size(Size::new(300.0, 200.0)), (300, 200));
+        assert_eq!(svg_raster_size(Size::new(16384.0, 8192.0)), (4096, 2048));
+        assert_eq!(svg_raster_size(Size::new(1000.0, 100000.0)), (41, 4096));
+    }
+
+    #[test]
+    fn svgs_are_recognized() {
+        assert!(is_svg(b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>"));
+        assert!(is_svg(b"\n  <?xml version=\"1.0\"?><svg/>"));
+        assert!(!is_svg(b"\x89PNG\r\n\x1a\n"));
+        assert!(!is_svg(b""));
+    }
+}
@@ -0,0 +1,198 @@
+use std::{collections::VecDeque, time::Instant};
+
+use skia_safe::{Canvas, Color, Paint, RRect, Rect};
+
+use crate::{
+    bridge::EditorMode,
+    renderer::{animation_utils::fade_out_opacity, BadgePosition, GridRenderer, RendererSettings},
+};
+
+const MARGIN: f32 = 8.0;
+const MAX_CHIPS: usize = 8;
+// Pressing the same key again within this many seconds counts up the existing chip
+const REPEAT_INTERVAL: f32 = 1.0;
+const FADE_DELAY: f32 = 2.0;
+const FADE_OUT_LENGTH: f32 = 0.5;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct KeyChip {
+    key: String,
+    count: u32,
+    pressed_at: Instant,
+}
+
+/// Shows the recently pressed keys as chips that fade out, for screencasts.
+#[derive(Default)]
+pub struct KeyOverlay {
+    chips: VecDeque<KeyChip>,
+}
+
+impl KeyOverlay {
+    pub fn key_pressed(&mut self, key: String, mode: &EditorMode, show_typed_text: bool) {
+        // What is typed in insert mode or on the command line may be private, only the keys
+        // controlling the editor are shown
+        if !show_typed_text && is_text_entry_mode(mode) && is_typed_text(&key) {
+            return;
+        }
+
+        let now = Instant::now();
+        if let Some(last) = self.chips.back_mut() {
+            if last.key == key
+                && now.duration_since(last.pressed_at).as_secs_f32() < REPEAT_INTERVAL
+            {
+                last.count += 1;
+                last.pressed_at = now;
+                return;
+            }
+        }
+
+        self.chips.push_back(KeyChip {
+            key,
+            count: 1,
+            pressed_at: now,
+        });
+        while self.chips.len() > MAX_CHIPS {
+            self.chips.pop_front();
+        }
+    }
+
+    /// Draws the chips in a row in the configured corner of `region`, the newest last.
+    pub fn draw(
+        &mut self,
+        canvas: &mut Canvas,
+        grid_renderer: &mut GridRenderer,
+        settings: &RendererSettings,
+        region: Rect,
+    ) {
+        let chips: Vec<(String, f32)> = self
+            .chips
+            .iter()
+            .map(|chip| {
+                let opacity = fade_out_opacity(chip.pressed_at, FADE_DELAY, FADE_OUT_LENGTH);
+                (chip_text(chip), opacity)
+            })
+            .collect();
+        self.chips
+            .retain(|chip| chip.pressed_at.elapsed().as_secs_f32() < FADE_DELAY + FADE_OUT_LENGTH);
+
+        let font_dimensions = grid_renderer.font_dimensions;
+        let height = font_dimensions.height as f32;
+        let widths: Vec<f32> = chips
+            .iter()
+            .map(|(text, _)| ((text.chars().count() as u64 + 2) * font_dimensions.width) as f32)
+            .collect();
+        let total_width = widths.iter().sum::<f32>() + MARGIN * (widths.len() as f32 - 1.0);
+
+        let mut left = match settings.show_keys_position {
+            BadgePosition::TopLeft | BadgePosition::BottomLeft => region.left + MARGIN,
+            BadgePosition::TopRight | BadgePosition::BottomRight => {
+                region.right - MARGIN - total_width
+            }
+        };
+        let top = match settings.show_keys_position {
+            BadgePosition::TopLeft | BadgePosition::TopRight => region.top + MARGIN,
+            BadgePosition::BottomLeft | BadgePosition::BottomRight => {
+                region.bottom - MARGIN - height
+            }
+        };
+
+        let colors = &grid_renderer.default_style.colors;
+        let background = colors
+            .foreground
+            .map_or(Color::WHITE, |color| color.to_color());
+        let foreground = colors
+            .background
+            .map_or(Color::BLACK, |color| color.to_color());
+        let y_adjustment = grid_renderer.shaper.y_adjustment() as f32;
+
+        let mut paint = Paint::default();
+        paint.set_anti_alias(true);
+        for ((text, opacity), width) in chips.into_iter().zip(widths) {
+            if opacity > 0.0 {
+                let chip_region = Rect::from_xywh(left, top, width, height);
+                paint.set_color(background.with_a((200.0 * opacity) as u8));
+                let radius = height / 4.0;
+                canvas.draw_rrect(RRect::new_rect_xy(chip_region, radius, radius), &paint);
+
+                paint.set_color(foreground.with_a((255.0 * opacity) as u8));
+                let x = left + font_dimensions.width as f32;
+                for blob in grid_renderer
+                    .shaper
+                    .shape_cached(text, true, false, false)
+                    .iter()
+                {
+                    canvas.draw_text_blob(blob, (x, top + y_adjustment), &paint);
+                }
+            }
+            left += width + MARGIN;
+        }
+    }
+}
+
+fn is_text_entry_mode(mode: &EditorMode) -> bool {
+    match mode {
+        EditorMode::Insert | EditorMode::Replace | EditorMode::CmdLine => true,
+        // Like cmdline_insert, cmdline_replace and insert mode in a terminal
+        EditorMode::Unknown(name) => {
+            name.starts_with("cmdline") || name == "terminal" || name.starts_with("insert")
+        }
+        _ => false,
+    }
+}
+
+/// Whether the key just types a character, instead of being a special key or having modifiers.
+fn is_typed_text(key: &str) -> bool {
+    !key.starts_with('<') || key == "<lt>" || key == "<Space>"
+}
+
+fn chip_text(chip: &KeyChip) -> String {
+    let key = match chip.key.as_str() {
+        "<lt>" => "<",
+        key => key,
+    };
+    if chip.count > 1 {
+        format!("{} ×{}", key, chip.count)
+    } else {
+        key.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(overlay: &KeyOverlay) -> Vec<String> {
+        overlay.chips.iter().map(chip_text).collect()
+    }
+
+    #[test]
+    fn repeated_keys_are_counted() {
+        let mut overlay = KeyOverlay::default();
+        for key in ["j", "j", "j", "<C-w>", "<lt>"] {
+            overlay.key_pressed(key.to_string(), &EditorMode::Normal, false);
+        }
+        assert_eq!(keys(&overlay), vec!["j ×3", "<C-w>", "<"]);
+    }
+
+    #[test]
+    fn typed_text_is_hidden_in_insert_mode() {
+        let mut overlay = KeyOverlay::default();
+        for key in ["s", "e", "<Space>", "<C-w>", "<Esc>"] {
+            overlay.key_pressed(key.to_string(), &EditorMode::Insert, false);
+        }
+        assert_eq!(keys(&overlay), vec!["<C-w>", "<Esc>"]);
+
+        overlay.key_pressed("x".to_string(), &EditorMode::Insert, true);
+        assert_eq!(keys(&overlay), vec!["<C-w>", "<Esc>", "x"]);
+    }
+
+    #[test]
+    fn only_the_latest_keys_are_kept() {
+        let mut overlay = KeyOverlay::default();
+        for key in 0..10 {
+            overlay.key_pressed(key.to_string(), &EditorMode::Normal, false);
+        }
+        assert_eq!(overlay.chips.len(), MAX_CHIPS);
+        assert_eq!(overlay.chips.front().unwrap().key, "2");
+    }
+}
@@ -0,0 +1,107 @@
+use std::time::{Duration, Instant};
+
+use log::info;
+use skia_safe::{paint, Canvas, Color, Data, Image, Paint, Rect};
+
+use crate::redraw_scheduler::REDRAW_SCHEDULER;
+
+static LOGO: &[u8] = include_bytes!("../../assets/neovide-256x256.png");
+
+// Quick starts are over before the indicator could be seen, so it would only flicker
+const SHOW_DELAY: Duration = Duration::from_millis(300);
+const FADE_IN_LENGTH: f32 = 0.3;
+const LOGO_SIZE: f32 = 96.0;
+const SPINNER_SIZE: f32 = 24.0;
+const SPINNER_GAP: f32 = 24.0;
+// In turns per second
+const SPINNER_SPEED: f32 = 1.2;
+
+/// The logo and a spinner, shown until Neovim drew for the first time.
+pub struct LoadingIndicator {
+    started_at: Instant,
+    logo: Option<Image>,
+    finished: bool,
+}
+
+impl LoadingIndicator {
+    pub fn new() -> Self {
+        Self {
+            started_at: Instant::now(),
+            logo: Image::from_encoded(Data::new_copy(LOGO)),
+            finished: false,
+        }
+    }
+
+    /// Stops the indicator once Neovim drew, and logs how long that took.
+    pub fn finish(&mut self) {
+        if !self.finished {
+            self.finished = true;
+            info!(
+                "Time to first frame: {}ms",
+                self.started_at.elapsed().as_millis()
+            );
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas, background: Color, scale_factor: f32) {
+        if self.finished {
+            return;
+        }
+        // Keep spinning until Neovim drew
+        REDRAW_SCHEDULER.queue_next_frame();
+
+        let elapsed = self.started_at.elapsed();
+        let visible_for = match elapsed.checked_sub(SHOW_DELAY) {
+            Some(visible_for) => visible_for.as_secs_f32(),
+            None => return,
+        };
+        let opacity = (visible_for / FADE_IN_LENGTH).min(1.0);
+
+        let bounds = canvas.local_clip_bounds().unwrap_or_default();
+        let logo_size = LOGO_SIZE * scale_factor;
+        let spinner_size = SPINNER_SIZE * scale_factor;
+        let total_height = logo_size + SPINNER_GAP * scale_factor + spinner_size;
+        let top = bounds.center_y() - total_height / 2.0;
+
+        let mut logo_paint = Paint::default();
+        logo_paint.set_anti_alias(true).set_alpha_f(opacity);
+        if let Some(logo) = &self.logo {
+            let logo_rect = Rect::from_xywh(
+                bounds.center_x() - logo_size / 2.0,
+                top,
+                logo_size,
+                logo_size,
+            );
+            canvas.draw_image_rect(logo, None, logo_rect, &logo_paint);
+        }
+
+        // Light on dark backgrounds and dark on light ones
+        let brightness =
+            (background.r() as u32 + background.g() as u32 + background.b() as u32) / 3;
+        let color = if brightness < 128 {
+            Color::WHITE
+        } else {
+            Color::BLACK
+        };
+        let mut spinner_paint = Paint::default();
+        spinner_paint
+            .set_anti_alias(true)
+            .set_style(paint::Style::Stroke)
+            .set_stroke_width(2.5 * scale_factor)
+            .set_stroke_cap(paint::Cap::Round)
+            .set_color(color)
+            .set_alpha_f(0.7 * opacity);
+        let spinner_rect = Rect::from_xywh(
+            bounds.center_x() - spinner_size / 2.0,
+            top + logo_size + SPINNER_GAP * scale_factor,
+            spinner_size,
+            spinner_size,
+        );
+        let start_angle = elapsed.as_secs_f32() * SPINNER_SPEED * 360.0 % 360.0;
+        canvas.draw_arc(spinner_rect, start_angle, 270.0, false, &spinner_paint);
+    }
+}
@@ -1,39 +1,90 @@
 pub mod animation_utils;
+mod command_palette;
+mod completion_panel;
 pub mod cursor_renderer;
+mod decorations;
 pub mod fonts;
 pub mod grid_renderer;
+mod image_layer;
+mod key_overlay;
+mod loading_indicator;
+mod mode_badge;
 mod opengl;
+mod overlay;
+mod popup_menu;
+mod predicted_echo;
+mod present_mode;
+mod preview_pane;
 pub mod profiler;
 mod rendered_window;
+mod search_overlay;
+mod settings_panel;
+mod status_line;
+mod tab_line;
+mod terminal_drawer;
+mod theme_transition;
+mod typewriter;
 
 use std::{
     cmp::Ordering,
     collections::{hash_map::Entry, HashMap},
     sync::Arc,
+    time::Duration,
 };
 
 use log::error;
-use skia_safe::{Canvas, Point};
+use rmpv::Value;
+use skia_safe::{
+    canvas::SaveLayerRec, color_filters, image_filters, Canvas, Color, ColorMatrix, Image,
+    ImageFilter, Paint, Point, Rect,
+};
 use tokio::sync::mpsc::UnboundedReceiver;
-use winit::event::Event;
+use winit::event::{Event, WindowEvent};
 
 use crate::{
-    bridge::EditorMode,
+    bridge::{EditorMode, ParallelCommand, SearchCount, TabContents, TabLineTab, UiCommand},
+    cmd_line::CmdLineSettings,
     editor::{Cursor, Style},
     event_aggregator::EVENT_AGGREGATOR,
     profiling::tracy_zone,
+    redraw_scheduler::REDRAW_SCHEDULER,
+    renderer::animation_utils::{animation_dt, ease, ease_out_cubic},
     settings::*,
     WindowSettings,
 };
 
+use command_palette::CommandPalette;
+pub use command_palette::{user_commands, UserCommand};
 use cursor_renderer::CursorRenderer;
 pub use fonts::caching_shaper::CachingShaper;
 pub use grid_renderer::GridRenderer;
+use image_layer::ImageLayer;
+pub use image_layer::{decode_image, ImageCommand, ImagePlacement};
+use key_overlay::KeyOverlay;
+use loading_indicator::LoadingIndicator;
+pub use mode_badge::BadgePosition;
+use mode_badge::ModeBadge;
+use overlay::{handle_modal_event, Modal};
+use popup_menu::PopupMenuRenderer;
+pub use popup_menu::{PopupMenu, PopupMenuStyles};
+use predicted_echo::PredictedEcho;
+use preview_pane::PreviewPane;
 pub use rendered_window::{
-    LineFragment, RenderedWindow, WindowDrawCommand, WindowDrawDetails, WindowPadding,
+    LineFragment, RenderedWindow, WindowDrawCommand, WindowDrawDetails, WindowEdges, WindowPadding,
 };
+use search_overlay::SearchOverlay;
+use settings_panel::SettingsPanel;
+use tab_line::TabLine;
+pub use tab_line::TabLineStyles;
+use terminal_drawer::TerminalDrawer;
+use theme_transition::ThemeTransition;
+use typewriter::typewriter_padding;
 
-pub use opengl::{build_context, Context as WindowedContext};
+pub use opengl::{
+    build_context, build_window, is_context_lost_error, load_surface_size_limits,
+    Context as WindowedContext,
+};
+pub use present_mode::PresentMode;
 
 #[derive(SettingGroup, Clone)]
 pub struct RendererSettings {
@@ -46,6 +97,31 @@ pub struct RendererSettings {
     debug_renderer: bool,
     profiler: bool,
     underline_automatic_scaling: bool,
+    unfocused_dim: f32,
+    inactive_window_dim: f32,
+    inactive_window_desaturate: f32,
+    reduce_motion: bool,
+    pub terminal_fast_path: bool,
+    pub ligatures: Ligatures,
+    completion_documentation: bool,
+    mode_badge: bool,
+    mode_badge_position: BadgePosition,
+    mode_badge_fade: f32,
+    search_count: bool,
+    pub show_keys: bool,
+    show_keys_position: BadgePosition,
+    show_keys_insert_text: bool,
+    fancy_statusline: bool,
+    pub typewriter_mode: bool,
+    theme_transition_length: f32,
+    highlight_squiggle: String,
+    highlight_pill: String,
+    highlight_fade: String,
+    highlight_fade_opacity: f32,
+    pub max_render_buffer_size: u32,
+    glyph_cache_size: u32,
+    shaping_cache_size: u32,
+    scrollback_texture_size: u32,
 }
 
 impl Default for RendererSettings {
@@ -60,6 +136,76 @@ impl Default for RendererSettings {
             debug_renderer: false,
             profiler: false,
             underline_automatic_scaling: false,
+            unfocused_dim: 0.0,
+            inactive_window_dim: 0.0,
+            inactive_window_desaturate: 0.0,
+            reduce_motion: false,
+            terminal_fast_path: true,
+            ligatures: Ligatures::On,
+            completion_documentation: true,
+            mode_badge: false,
+            mode_badge_position: BadgePosition::BottomRight,
+            mode_badge_fade: 2.0,
+            search_count: false,
+            show_keys: false,
+            show_keys_position: BadgePosition::BottomLeft,
+            show_keys_insert_text: false,
+            fancy_statusline: false,
+            typewriter_mode: false,
+            theme_transition_length: 0.0,
+            highlight_squiggle: String::new(),
+            highlight_pill: String::new(),
+            highlight_fade: String::new(),
+            highlight_fade_opacity: 0.6,
+            max_render_buffer_size: 0,
+            glyph_cache_size: 0,
+            shaping_cache_size: 16,
+            scrollback_texture_size: 0,
+        }
+    }
+}
+
+/// Where the font's ligatures are shaped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ligatures {
+    On,
+    Off,
+    /// Everywhere except on the line with the cursor, so that the individual characters can be
+    /// seen while editing
+    CursorLineOff,
+}
+
+impl ParseFromValue for Ligatures {
+    fn parse_from_value(&mut self, value: Value) {
+        *self = match value {
+            Value::Boolean(true) => Ligatures::On,
+            Value::Boolean(false) => Ligatures::Off,
+            Value::String(ref name) => match name.as_str() {
+                Some("on") => Ligatures::On,
+                Some("off") => Ligatures::Off,
+                Some("cursor-line-off") => Ligatures::CursorLineOff,
+                _ => {
+                    error!("Expected a ligatures mode, but received {:?}", value);
+                    return;
+                }
+            },
+            _ => {
+                error!(
+                    "Expected a ligatures string or boolean, but received {:?}",
+                    value
+                );
+                return;
+            }
+        };
+    }
+}
+
+impl From<Ligatures> for Value {
+    fn from(ligatures: Ligatures) -> Self {
+        match ligatures {
+            Ligatures::On => Value::from("on"),
+            Ligatures::Off => Value::from("off"),
+            Ligatures::CursorLineOff => Value::from("cursor-line-off"),
         }
     }
 }
@@ -76,12 +222,51 @@ pub enum DrawCommand {
     LineSpaceChanged(i64),
     DefaultStyleChanged(Style),
     ModeChanged(EditorMode),
+    PopupMenuShow(PopupMenu),
+    PopupMenuSelect(Option<u64>),
+    PopupMenuHide,
+    /// Text typed in insert mode that Neovim hasn't drawn yet
+    PredictEcho(String),
+    ModeBadgeState {
+        recording: Option<String>,
+        operator: Option<String>,
+    },
+    SearchCount(Option<SearchCount>),
+    /// A key sent to Neovim, in its key notation
+    KeyPressed(String),
+    TabLineUpdate {
+        current: u64,
+        tabs: Vec<TabLineTab>,
+        styles: TabLineStyles,
+    },
+    ShowTabLine(u64),
+    TabContents(Option<Vec<TabContents>>),
 }
 
 pub struct Renderer {
     cursor_renderer: CursorRenderer,
+    popup_menu: PopupMenuRenderer,
+    mode_badge: ModeBadge,
+    search_overlay: SearchOverlay,
+    key_overlay: KeyOverlay,
+    tab_line: TabLine,
+    /// The height of the tabline above the grid, in pixels. It's part of the top padding.
+    pub tab_line_height: u32,
+    settings_panel: SettingsPanel,
+    command_palette: CommandPalette,
+    image_layer: ImageLayer,
+    preview_pane: PreviewPane,
+    /// The width of the preview pane on the right of the grid, in pixels. It's part of the right
+    /// padding.
+    pub preview_width: u32,
+    terminal_drawer: TerminalDrawer,
+    /// The height of the terminal drawer below the grid, in pixels. It's part of the bottom
+    /// padding.
+    pub terminal_height: u32,
+    theme_transition: ThemeTransition,
     pub grid_renderer: GridRenderer,
     current_mode: EditorMode,
+    predicted_echo: PredictedEcho,
 
     rendered_windows: HashMap<u64, RenderedWindow>,
     pub window_regions: Vec<WindowDrawDetails>,
@@ -90,14 +275,23 @@ pub struct Renderer {
     profiler: profiler::Profiler,
     os_scale_factor: f64,
     user_scale_factor: f64,
+    monitor_scale_factor: f64,
     pub window_padding: WindowPadding,
+    window_has_focus: bool,
+    loading_indicator: LoadingIndicator,
+
+    // Animates between the transparencies of the focused, unfocused and fullscreen states
+    transparency_start: f32,
+    transparency: f32,
+    transparency_destination: f32,
+    transparency_t: f32,
 }
 
 impl Renderer {
     pub fn new(os_scale_factor: f64) -> Self {
         let window_settings = SETTINGS.get::<WindowSettings>();
 
-        let user_scale_factor = window_settings.scale_factor.into();
+        let user_scale_factor = window_settings.effective_scale_factor().into();
         let scale_factor = user_scale_factor * os_scale_factor;
         let cursor_renderer = CursorRenderer::new();
         let grid_renderer = GridRenderer::new(scale_factor);
@@ -119,18 +313,47 @@ impl Renderer {
         Renderer {
             rendered_windows,
             cursor_renderer,
+            popup_menu: PopupMenuRenderer::default(),
+            mode_badge: ModeBadge::new(),
+            search_overlay: SearchOverlay::new(),
+            key_overlay: KeyOverlay::default(),
+            tab_line: TabLine::default(),
+            tab_line_height: 0,
+            settings_panel: SettingsPanel::default(),
+            command_palette: CommandPalette::default(),
+            image_layer: ImageLayer::default(),
+            preview_pane: PreviewPane::default(),
+            preview_width: 0,
+            terminal_drawer: TerminalDrawer::default(),
+            terminal_height: 0,
+            theme_transition: ThemeTransition::default(),
             grid_renderer,
             current_mode,
+            predicted_echo: PredictedEcho::default(),
             window_regions,
             batched_draw_command_receiver,
             profiler,
             os_scale_factor,
             user_scale_factor,
+            monitor_scale_factor: 1.0,
             window_padding,
+            window_has_focus: true,
+            loading_indicator: LoadingIndicator::new(),
+            transparency_start: window_settings.transparency,
+            transparency: window_settings.transparency,
+            transparency_destination: window_settings.transparency,
+            transparency_t: 2.0, // 2.0 is out of the 0.0 to 1.0 range and stops animation.
         }
     }
 
     pub fn handle_event(&mut self, event: &Event<()>) {
+        if let Event::WindowEvent {
+            event: WindowEvent::Focused(is_focused),
+            ..
+        } = event
+        {
+            self.window_has_focus = *is_focused;
+        }
         self.cursor_renderer.handle_event(event);
     }
 
@@ -138,6 +361,26 @@ impl Renderer {
         self.grid_renderer.font_names()
     }
 
+    pub fn is_tab_line_visible(&self) -> bool {
+        self.tab_line.is_visible()
+    }
+
+    /// Whether the row of the window shows text, rather than the tabline, a status line or the
+    /// command line.
+    pub fn is_text_row(&self, window: &WindowDrawDetails, row: u64) -> bool {
+        // With multigrid the root grid only has what's around the windows, and the messages have
+        // their own grid, which is sorted above all the others
+        let multi_grid = SETTINGS.get::<CmdLineSettings>().multi_grid;
+        if multi_grid && (window.id == 1 || window.floating_order == Some(u64::MAX)) {
+            return false;
+        }
+        self.rendered_windows
+            .get(&window.id)
+            .map_or(false, |rendered_window| {
+                rendered_window.is_text_row(row, window.id == 1)
+            })
+    }
+
     /// Draws frame
     ///
     /// # Returns
@@ -150,6 +393,28 @@ impl Renderer {
             draw_commands.extend(draw_command);
         }
 
+        let cache_settings = SETTINGS.get::<RendererSettings>();
+        self.grid_renderer.shaper.set_cache_limits(
+            cache_settings.glyph_cache_size,
+            cache_settings.shaping_cache_size,
+        );
+
+        let had_decorations = !self.grid_renderer.decoration_patterns.is_empty();
+        if self
+            .grid_renderer
+            .decoration_patterns
+            .update(&cache_settings)
+        {
+            // The highlight groups are only sent by Neovim while some pattern needs them
+            let has_decorations = !self.grid_renderer.decoration_patterns.is_empty();
+            if has_decorations != had_decorations {
+                EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::SetUiOption(
+                    "ext_hlstate".to_string(),
+                    has_decorations,
+                )));
+            }
+        }
+
         let mut font_changed = false;
 
         for draw_command in draw_commands.into_iter() {
@@ -159,27 +424,90 @@ impl Renderer {
             self.handle_draw_command(root_canvas, draw_command);
         }
 
+        // The lines drawn with placeholder glyphs are drawn again once their fonts are loaded
+        if self.grid_renderer.shaper.receive_fallback_fonts() {
+            for rendered_window in self.rendered_windows.values_mut() {
+                rendered_window.redraw_placeholder_lines(&mut self.grid_renderer);
+            }
+        }
+
         let default_background = self.grid_renderer.get_default_background();
         let font_dimensions = self.grid_renderer.font_dimensions;
 
-        let transparency = { SETTINGS.get::<WindowSettings>().transparency };
+        let transparency = self.update_transparency(animation_dt(dt));
         root_canvas.clear(default_background.with_a((255.0 * transparency) as u8));
         root_canvas.save();
         root_canvas.reset_matrix();
 
-        let user_scale_factor = SETTINGS.get::<WindowSettings>().scale_factor.into();
+        let user_scale_factor = SETTINGS
+            .get::<WindowSettings>()
+            .effective_scale_factor()
+            .into();
         if user_scale_factor != self.user_scale_factor {
             self.user_scale_factor = user_scale_factor;
-            self.grid_renderer
-                .handle_scale_factor_update(self.os_scale_factor * self.user_scale_factor);
+            self.update_scale_factor();
             font_changed = true;
         }
 
+        if self.preview_width > 0 {
+            let size = root_canvas.image_info().dimensions();
+            let preview_width = self.preview_width as f32;
+            let region = Rect::from_xywh(
+                size.width as f32 - preview_width,
+                0.0,
+                preview_width,
+                size.height as f32,
+            );
+            self.preview_pane.draw(
+                root_canvas,
+                &mut self.grid_renderer,
+                region,
+                animation_dt(dt),
+                SETTINGS.get::<RendererSettings>().scroll_animation_length,
+            );
+        }
+
+        if self.terminal_height > 0 {
+            let size = root_canvas.image_info().dimensions();
+            let terminal_height = self.terminal_height as f32;
+            let region = Rect::from_xywh(
+                0.0,
+                size.height as f32 - terminal_height,
+                size.width as f32 - self.preview_width as f32,
+                terminal_height,
+            );
+            self.terminal_drawer
+                .draw(root_canvas, &mut self.grid_renderer, region);
+        }
+
+        let size = root_canvas.image_info().dimensions();
+        let region = Rect::from_xywh(
+            0.0,
+            0.0,
+            size.width as f32 - self.preview_width as f32,
+            self.tab_line_height as f32,
+        );
+        self.tab_line
+            .draw(root_canvas, &mut self.grid_renderer, region);
+
         if let Some(root_window) = self.rendered_windows.get(&1) {
             let clip_rect = root_window.pixel_region(font_dimensions);
+
+            if SETTINGS.get::<WindowSettings>().padding_extend_highlights {
+                for window in self
+                    .rendered_windows
+                    .values()
+                    .filter(|window| !window.hidden && window.floating_order.is_none())
+                {
+                    window.draw_padding_backgrounds(root_canvas, &self.grid_renderer, clip_rect);
+                }
+            }
+
             root_canvas.clip_rect(clip_rect, None, Some(false));
         }
 
+        self.update_typewriter_offsets(SETTINGS.get::<RendererSettings>().typewriter_mode);
+
         let windows: Vec<&mut RenderedWindow> = {
             let (mut root_windows, mut floating_windows): (
                 Vec<&mut RenderedWindow>,
@@ -202,6 +530,15 @@ impl Renderer {
         };
 
         let settings = SETTINGS.get::<RendererSettings>();
+        let window_settings = SETTINGS.get::<WindowSettings>();
+        let dim = if window_settings.zen_mode {
+            settings
+                .inactive_window_dim
+                .max(window_settings.zen_dim_inactive)
+        } else {
+            settings.inactive_window_dim
+        };
+        let desaturate_filter = desaturate_filter(settings.inactive_window_desaturate);
         self.window_regions = windows
             .into_iter()
             .map(|window| {
@@ -209,23 +546,120 @@ impl Renderer {
                     window.padding = self.window_padding;
                 }
 
-                window.draw(
+                let details = window.draw(
                     root_canvas,
                     &settings,
                     default_background.with_a((255.0 * transparency) as u8),
                     font_dimensions,
-                    dt,
-                )
+                    animation_dt(dt),
+                );
+                // Right after drawing the split, so that the floats drawn later stay bright
+                if window.id != 1 && !window.is_current && window.floating_order.is_none() {
+                    dim_inactive_window(
+                        root_canvas,
+                        window.pixel_region(font_dimensions),
+                        dim,
+                        desaturate_filter.as_ref(),
+                    );
+                }
+                details
             })
             .collect();
 
+        if !self.image_layer.is_empty() {
+            if let Some(root_window) = self.rendered_windows.get(&1) {
+                let root_region = root_window.pixel_region(font_dimensions);
+                self.image_layer.draw(
+                    root_canvas,
+                    Point::new(root_region.left, root_region.top),
+                    font_dimensions,
+                );
+            }
+        }
+
+        if let Some(root_window) = self.rendered_windows.get(&1) {
+            let root_region = root_window.pixel_region(font_dimensions);
+            self.popup_menu.draw(
+                root_canvas,
+                &mut self.grid_renderer,
+                root_window.grid_size,
+                Point::new(root_region.left, root_region.top),
+                settings.completion_documentation,
+            );
+        }
+
         let windows = &self.rendered_windows;
         self.cursor_renderer
             .update_cursor_destination(font_dimensions.into(), windows);
 
+        if !self.predicted_echo.is_empty() && self.current_mode == EditorMode::Insert {
+            self.draw_predicted_echo(root_canvas);
+        }
+
         self.cursor_renderer
             .draw(&mut self.grid_renderer, &self.current_mode, root_canvas, dt);
 
+        // Neovim draws its own search count into the grid while it owns the command line
+        let cmdline_external = { SETTINGS.get::<WindowSettings>().cmdline_external() };
+        if settings.search_count && cmdline_external {
+            if let Some(root_window) = self.rendered_windows.get(&1) {
+                self.search_overlay.draw(
+                    root_canvas,
+                    &mut self.grid_renderer,
+                    root_window.pixel_region(font_dimensions),
+                );
+            }
+        }
+
+        if settings.show_keys {
+            if let Some(root_window) = self.rendered_windows.get(&1) {
+                self.key_overlay.draw(
+                    root_canvas,
+                    &mut self.grid_renderer,
+                    &settings,
+                    root_window.pixel_region(font_dimensions),
+                );
+            }
+        }
+
+        if settings.mode_badge {
+            if let Some(root_window) = self.rendered_windows.get(&1) {
+                self.mode_badge.draw(
+                    root_canvas,
+                    &mut self.grid_renderer,
+                    &settings,
+                    root_window.pixel_region(font_dimensions),
+                );
+            }
+        }
+
+        if !self.window_has_focus && settings.unfocused_dim > 0.0 {
+            let dim_alpha = (255.0 * settings.unfocused_dim.min(1.0)) as u8;
+            root_canvas.draw_paint(&Paint::new(Color::BLACK.with_a(dim_alpha), None));
+        }
+
+        if self.rendered_windows.contains_key(&1) {
+            self.loading_indicator.finish();
+        }
+        self.loading_indicator
+            .draw(root_canvas, default_background, self.os_scale_factor as f32);
+
+        if let Some(root_window) = self.rendered_windows.get(&1) {
+            self.settings_panel.draw(
+                root_canvas,
+                &mut self.grid_renderer,
+                root_window.pixel_region(font_dimensions),
+            );
+            self.command_palette.draw(
+                root_canvas,
+                &mut self.grid_renderer,
+                root_window.pixel_region(font_dimensions),
+            );
+        }
+
+        self.theme_transition
+            .draw(root_canvas, settings.theme_transition_length);
+
         self.profiler.draw(root_canvas, dt);
 
         root_canvas.restore();
@@ -233,10 +667,210 @@ impl Renderer {
         font_changed
     }
 
+    /// Pads the top of the window with the cursor in typewriter mode, while Neovim can't scroll the
+    /// cursor line to its middle since it's close to the start of the buffer. While the cursor is
+    /// outside of the splits, on the command line for example, the windows stay as they are.
+    fn update_typewriter_offsets(&mut self, typewriter_mode: bool) {
+        let cursor = self.cursor_renderer.cursor();
+        let cursor_window = self
+            .rendered_windows
+            .get(&cursor.parent_window_id)
+            .filter(|window| window.id != 1 && window.floating_order.is_none());
+        let destination = match cursor_window {
+            Some(window) if typewriter_mode => Some((
+                window.id,
+                typewriter_padding(
+                    window.top_line(),
+                    cursor.grid_position.1,
+                    window.grid_size.height,
+                ),
+            )),
+            _ if typewriter_mode => return,
+            _ => None,
+        };
+
+        for window in self.rendered_windows.values_mut() {
+            match destination {
+                Some((id, rows)) if id == window.id => window.set_typewriter_destination(rows),
+                _ => window.set_typewriter_destination(0.0),
+            }
+        }
+    }
+
+    /// Moves the transparency towards the one of the current state, and returns it.
+    fn update_transparency(&mut self, dt: f32) -> f32 {
+        let settings = SETTINGS.get::<WindowSettings>();
+        let destination = settings.state_transparency(self.window_has_focus);
+        if (destination - self.transparency_destination).abs() > f32::EPSILON {
+            self.transparency_start = self.transparency;
+            self.transparency_destination = destination;
+            self.transparency_t = 0.0;
+        }
+
+        if 1.0 - self.transparency_t < f32::EPSILON {
+            self.transparency_t = 2.0;
+            self.transparency = self.transparency_destination;
+        } else {
+            self.transparency_t = if settings.transparency_transition > 0.0 {
+                (self.transparency_t + dt / settings.transparency_transition).min(1.0)
+            } else {
+                1.0
+            };
+            self.transparency = ease(
+                ease_out_cubic,
+                self.transparency_start,
+                self.transparency_destination,
+                self.transparency_t,
+            );
+            REDRAW_SCHEDULER.queue_next_frame();
+        }
+        self.transparency
+    }
+
+    /// Whether the window should hand over every frame with `set_last_frame`, for crossfading
+    /// colorscheme changes.
+    pub fn wants_last_frame(&self) -> bool {
+        ThemeTransition::wants_last_frame(
+            SETTINGS.get::<RendererSettings>().theme_transition_length,
+        )
+    }
+
+    pub fn set_last_frame(&mut self, image: Image) {
+        self.theme_transition.set_last_frame(image);
+    }
+
+    /// Opens the settings panel, for `:NeovideSettings`.
+    pub fn show_settings(&mut self) {
+        self.command_palette.close();
+        self.settings_panel.open();
+    }
+
+    /// Opens the command palette with the user commands of Neovim.
+    pub fn show_command_palette(&mut self, commands: Vec<UserCommand>) {
+        self.settings_panel.close();
+        self.command_palette.open(commands);
+    }
+
+    /// Places or removes the images of plugins.
+    pub fn handle_image_command(&mut self, command: ImageCommand) {
+        self.image_layer.handle_command(command);
+        REDRAW_SCHEDULER.queue_next_frame();
+    }
+
+    /// Shows the buffer in the preview pane, with its lines and its filetype.
+    pub fn set_preview_content(&mut self, filetype: String, lines: Vec<String>) {
+        self.preview_pane.set_content(filetype, lines);
+    }
+
+    /// Scrolls the preview pane along with the source, `line` being the top line of its window.
+    pub fn set_preview_top_line(&mut self, line: u64) {
+        self.preview_pane.set_top_line(line);
+    }
+
+    pub fn is_terminal_open(&self) -> bool {
+        self.terminal_drawer.is_open()
+    }
+
+    /// Opens or closes the terminal drawer. An empty `shell` means the shell of the user.
+    pub fn toggle_terminal(&mut self, shell: &str, cwd: &str) {
+        let shell = if shell.is_empty() {
+            default_shell()
+        } else {
+            shell.to_string()
+        };
+        self.terminal_drawer.toggle(&shell, cwd);
+    }
+
+    /// Opens the command palette listing the files of the project, for quick open.
+    pub fn show_quick_open(&mut self, cwd: String, files: Vec<String>) {
+        self.settings_panel.close();
+        self.command_palette.open_files(cwd, files);
+    }
+
+    /// Gives the event to the command palette, the settings panel, the terminal drawer and the
+    /// tabline. Returns true when one of them is shown and used the event.
+    pub fn handle_overlay_event(&mut self, event: &Event<()>) -> bool {
+        handle_modal_event(&mut self.command_palette, event)
+            || handle_modal_event(&mut self.settings_panel, event)
+            || self.terminal_drawer.handle_event(event)
+            || self.tab_line.handle_event(event)
+    }
+
+    /// Whether the command palette or the settings panel is open.
+    pub fn has_open_overlay(&self) -> bool {
+        self.command_palette.is_open() || self.settings_panel.is_open()
+    }
+
+    pub fn is_profiling(&self) -> bool {
+        self.profiler.is_visible()
+    }
+
+    /// Shows how long the CPU and the GPU worked on the last frame in the profiler.
+    pub fn record_frame_timing(&mut self, cpu_time: Duration, gpu_time: Option<Duration>) {
+        self.profiler.record_frame_timing(cpu_time, gpu_time);
+    }
+
+    /// Whether Neovim didn't draw yet.
+    pub fn is_loading(&self) -> bool {
+        !self.loading_indicator.is_finished()
+    }
+
+    /// Whether text is drawn with placeholder glyphs, until its fallback fonts are loaded.
+    pub fn is_loading_fonts(&self) -> bool {
+        self.grid_renderer.shaper.is_loading_fallback_fonts()
+    }
+
+    /// The number of window surfaces and the bytes of their pixels.
+    pub fn surface_memory(&self) -> (usize, usize) {
+        self.rendered_windows
+            .values()
+            .map(RenderedWindow::surface_memory)
+            .fold((0, 0), |(count, bytes), (window_count, window_bytes)| {
+                (count + window_count, bytes + window_bytes)
+            })
+    }
+
+    /// The number of cached shapes and their estimated bytes.
+    pub fn cached_shapes(&self) -> (usize, usize) {
+        let shaper = &self.grid_renderer.shaper;
+        (shaper.cached_shapes(), shaper.cached_shape_bytes())
+    }
+
+    /// Drops what's only kept to draw faster, which is built again as needed.
+    pub fn trim_caches(&mut self) {
+        for rendered_window in self.rendered_windows.values_mut() {
+            rendered_window.drop_snapshots();
+        }
+        self.grid_renderer.shaper.clear_shape_cache();
+    }
+
+    /// Moves the windows over to a new graphics context, after the old one was lost.
+    pub fn handle_context_recreated(&mut self, root_canvas: &mut Canvas) {
+        for rendered_window in self.rendered_windows.values_mut() {
+            rendered_window.recreate_surface(root_canvas, &self.grid_renderer);
+        }
+    }
+
     pub fn handle_os_scale_factor_change(&mut self, os_scale_factor: f64) {
         self.os_scale_factor = os_scale_factor;
-        self.grid_renderer
-            .handle_scale_factor_update(self.os_scale_factor * self.user_scale_factor);
+        self.update_scale_factor();
+    }
+
+    /// Sets the scale factor from the profile of the monitor the window is on, returns whether it
+    /// changed.
+    pub fn handle_monitor_scale_factor_change(&mut self, monitor_scale_factor: f64) -> bool {
+        if monitor_scale_factor == self.monitor_scale_factor {
+            return false;
+        }
+        self.monitor_scale_factor = monitor_scale_factor;
+        self.update_scale_factor();
+        true
+    }
+
+    fn update_scale_factor(&mut self) {
+        self.grid_renderer.handle_scale_factor_update(
+            self.os_scale_factor * self.user_scale_factor * self.monitor_scale_factor,
+        );
     }
 
     fn handle_draw_command(&mut self, root_canvas: &mut Canvas, draw_command: DrawCommand) {
@@ -277,8 +911,13 @@ impl Renderer {
                 }
             }
             DrawCommand::UpdateCursor(new_cursor) => {
+                self.predicted_echo
+                    .reconcile(self.cursor_renderer.cursor(), &new_cursor);
                 self.cursor_renderer.update_cursor(new_cursor);
             }
+            DrawCommand::PredictEcho(text) => {
+                self.predicted_echo.predict(&text);
+            }
             DrawCommand::FontChanged(new_font) => {
                 self.grid_renderer.update_font(&new_font);
             }
@@ -287,19 +926,99 @@ impl Renderer {
             }
             DrawCommand::DefaultStyleChanged(new_style) => {
                 self.grid_renderer.default_style = Arc::new(new_style);
+                self.theme_transition.start();
             }
             DrawCommand::ModeChanged(new_mode) => {
+                self.mode_badge.set_mode(new_mode.clone());
                 self.current_mode = new_mode;
             }
+            DrawCommand::ModeBadgeState {
+                recording,
+                operator,
+            } => {
+                self.mode_badge.set_state(recording, operator);
+            }
+            DrawCommand::SearchCount(count) => {
+                self.search_overlay.set_count(count);
+            }
+            DrawCommand::KeyPressed(key) => {
+                let show_typed_text = SETTINGS.get::<RendererSettings>().show_keys_insert_text;
+                self.key_overlay
+                    .key_pressed(key, &self.current_mode, show_typed_text);
+            }
+            DrawCommand::PopupMenuShow(menu) => {
+                self.popup_menu.show(menu);
+            }
+            DrawCommand::PopupMenuSelect(selected) => {
+                self.popup_menu.select(selected);
+            }
+            DrawCommand::PopupMenuHide => {
+                self.popup_menu.hide();
+            }
+            DrawCommand::TabLineUpdate {
+                current,
+                tabs,
+                styles,
+            } => {
+                self.tab_line.update(current, tabs, styles);
+            }
+            DrawCommand::ShowTabLine(show_tabline) => {
+                self.tab_line.set_show_tabline(show_tabline);
+            }
+            DrawCommand::TabContents(contents) => {
+                self.tab_line.set_contents(contents);
+            }
             _ => {}
         }
     }
 
+    /// Draws the predicted text at the cursor and moves the cursor past it, until Neovim answers
+    /// with the real content.
+    fn draw_predicted_echo(&mut self, root_canvas: &mut Canvas) {
+        let width = self.predicted_echo.draw(
+            root_canvas,
+            &mut self.grid_renderer,
+            self.cursor_renderer.get_current_position(),
+            &self.cursor_renderer.cursor().grid_cell.1,
+        );
+        self.cursor_renderer.shift_destination(width);
+    }
+
     pub fn get_cursor_position(&self) -> Point {
         self.cursor_renderer.get_current_position()
     }
 }
 
+fn desaturate_filter(desaturate: f32) -> Option<ImageFilter> {
+    if desaturate <= 0.0 {
+        return None;
+    }
+    let mut saturation = ColorMatrix::default();
+    saturation.set_saturation(1.0 - desaturate.min(1.0));
+    image_filters::color_filter(color_filters::matrix(&saturation), None, None)
+}
+
+/// Darkens and desaturates a split other than the current one. The root grid is left alone, since
+/// it's below all of them.
+fn dim_inactive_window(
+    root_canvas: &mut Canvas,
+    region: Rect,
+    dim: f32,
+    desaturate_filter: Option<&ImageFilter>,
+) {
+    if let Some(desaturate_filter) = desaturate_filter {
+        let save_layer_rec = SaveLayerRec::default()
+            .backdrop(desaturate_filter)
+            .bounds(&region);
+        root_canvas.save_layer(&save_layer_rec);
+        root_canvas.restore();
+    }
+    if dim > 0.0 {
+        let paint = Paint::new(Color::BLACK.with_a((255.0 * dim.min(1.0)) as u8), None);
+        root_canvas.draw_rect(region, &paint);
+    }
+}
+
 /// Defines how floating windows are sorted.
 fn floating_sort(window_a: &&mut RenderedWindow, window_b: &&mut RenderedWindow) -> Ordering {
     // First, compare floating order
@@ -326,3 +1045,11 @@ fn floating_sort(window_a: &&mut RenderedWindow, window_b: &&mut RenderedWindow)
     }
     ord
 }
+
+fn default_shell() -> String {
+    if cfg!(windows) {
+        std::env::var("COMSPEC").unwrap_or_else(|_| "powershell.exe".to_string())
+    } else {
+        std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string())
+    }
+}
@@ -0,0 +1,218 @@
+use std::time::Instant;
+
+use log::error;
+use rmpv::Value;
+use skia_safe::{Canvas, Color, Paint, RRect, Rect};
+
+use crate::{
+    bridge::EditorMode,
+    renderer::{animation_utils::fade_out_opacity, GridRenderer, RendererSettings},
+    settings::*,
+};
+
+const MARGIN: f32 = 8.0;
+const FADE_OUT_LENGTH: f32 = 0.3;
+
+/// The corner of the editor the mode badge is drawn in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BadgePosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl ParseFromValue for BadgePosition {
+    fn parse_from_value(&mut self, value: Value) {
+        if value.is_str() {
+            *self = match value.as_str().unwrap() {
+                "top-left" => BadgePosition::TopLeft,
+                "top-right" => BadgePosition::TopRight,
+                "bottom-left" => BadgePosition::BottomLeft,
+                "bottom-right" => BadgePosition::BottomRight,
+                value => {
+                    error!("Expected a badge position, but received {:?}", value);
+                    return;
+                }
+            };
+        } else {
+            error!("Expected a badge position string, but received {:?}", value);
+        }
+    }
+}
+
+impl From<BadgePosition> for Value {
+    fn from(position: BadgePosition) -> Self {
+        match position {
+            BadgePosition::TopLeft => Value::from("top-left"),
+            BadgePosition::TopRight => Value::from("top-right"),
+            BadgePosition::BottomLeft => Value::from("bottom-left"),
+            BadgePosition::BottomRight => Value::from("bottom-right"),
+        }
+    }
+}
+
+/// Shows the current mode, the pending operator and the register a macro is recorded into.
+pub struct ModeBadge {
+    mode: EditorMode,
+    operator: Option<String>,
+    recording: Option<String>,
+    changed_at: Instant,
+}
+
+impl ModeBadge {
+    pub fn new() -> Self {
+        Self {
+            mode: EditorMode::Normal,
+            operator: None,
+            recording: None,
+            changed_at: Instant::now(),
+        }
+    }
+
+    pub fn set_mode(&mut self, mode: EditorMode) {
+        if mode != self.mode {
+            self.mode = mode;
+            self.changed_at = Instant::now();
+        }
+    }
+
+    pub fn set_state(&mut self, recording: Option<String>, operator: Option<String>) {
+        if recording != self.recording || operator != self.operator {
+            self.recording = recording;
+            self.operator = operator;
+            self.changed_at = Instant::now();
+        }
+    }
+
+    /// The opacity of the badge, which fades out once the mode didn't change for the fade delay.
+    /// It stays visible while recording so that a forgotten recording is noticed.
+    fn opacity(&self, fade_delay: f32) -> f32 {
+        if fade_delay <= 0.0 || self.recording.is_some() {
+            return 1.0;
+        }
+
+        fade_out_opacity(self.changed_at, fade_delay, FADE_OUT_LENGTH)
+    }
+
+    /// Draws the badge in a corner of `region`, the pixel region of the editor.
+    pub fn draw(
+        &self,
+        canvas: &mut Canvas,
+        grid_renderer: &mut GridRenderer,
+        settings: &RendererSettings,
+        region: Rect,
+    ) {
+        let opacity = self.opacity(settings.mode_badge_fade);
+        if opacity <= 0.0 {
+            return;
+        }
+
+        let text = badge_text(
+            &self.mode,
+            self.operator.as_deref(),
+            self.recording.as_deref(),
+        );
+        let font_dimensions = grid_renderer.font_dimensions;
+        let width = (text.chars().count() as u64 + 2) * font_dimensions.width;
+        let size = (width as f32, font_dimensions.height as f32);
+
+        let left = match settings.mode_badge_position {
+            BadgePosition::TopLeft | BadgePosition::BottomLeft => region.left + MARGIN,
+            BadgePosition::TopRight | BadgePosition::BottomRight => region.right - MARGIN - size.0,
+        };
+        let top = match settings.mode_badge_position {
+            BadgePosition::TopLeft | BadgePosition::TopRight => region.top + MARGIN,
+            BadgePosition::BottomLeft | BadgePosition::BottomRight => {
+                region.bottom - MARGIN - size.1
+            }
+        };
+        let badge_region = Rect::from_xywh(left, top, size.0, size.1);
+
+        // The colors are inverted so that the badge stands out from the text below it
+        let colors = &grid_renderer.default_style.colors;
+        let background = colors
+            .foreground
+            .map_or(Color::WHITE, |color| color.to_color());
+        let foreground = colors
+            .background
+            .map_or(Color::BLACK, |color| color.to_color());
+        let alpha = (255.0 * opacity) as u8;
+
+        let mut paint = Paint::default();
+        paint.set_anti_alias(true);
+        paint.set_color(background.with_a((200.0 * opacity) as u8));
+        let radius = size.1 / 4.0;
+        canvas.draw_rrect(RRect::new_rect_xy(badge_region, radius, radius), &paint);
+
+        paint.set_color(foreground.with_a(alpha));
+        let y_adjustment = grid_renderer.shaper.y_adjustment() as f32;
+        let x = left + font_dimensions.width as f32;
+        for blob in grid_renderer
+            .shaper
+            .shape_cached(text, true, false, true)
+            .iter()
+        {
+            canvas.draw_text_blob(blob, (x, top + y_adjustment), &paint);
+        }
+    }
+}
+
+fn mode_name(mode: &EditorMode) -> String {
+    match mode {
+        EditorMode::Normal => "NORMAL".to_string(),
+        EditorMode::Insert => "INSERT".to_string(),
+        EditorMode::Visual => "VISUAL".to_string(),
+        EditorMode::Replace => "REPLACE".to_string(),
+        EditorMode::CmdLine => "COMMAND".to_string(),
+        // Modes like cmdline_insert or visual_select
+        EditorMode::Unknown(name) => name.replace('_', " ").to_uppercase(),
+    }
+}
+
+pub fn badge_text(mode: &EditorMode, operator: Option<&str>, recording: Option<&str>) -> String {
+    let mut text = mode_name(mode);
+    if let Some(operator) = operator {
+        text.push(' ');
+        text.push_str(operator);
+    }
+    if let Some(register) = recording {
+        text.push_str(" @");
+        text.push_str(register);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn badge_text_of_modes() {
+        assert_eq!(badge_text(&EditorMode::Insert, None, None), "INSERT");
+        assert_eq!(
+            badge_text(
+                &EditorMode::Unknown("cmdline_insert".to_string()),
+                None,
+                None
+            ),
+            "CMDLINE INSERT"
+        );
+    }
+
+    #[test]
+    fn badge_text_with_operator_and_recording() {
+        assert_eq!(
+            badge_text(
+                &EditorMode::Unknown("operator".to_string()),
+                Some("d"),
+                Some("q")
+            ),
+            "OPERATOR d @q"
+        );
+        assert_eq!(
+            badge_text(&EditorMode::Normal, None, Some("a")),
+            "NORMAL @a"
+        );
+    }
+}
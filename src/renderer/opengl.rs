@@ -1,13 +1,19 @@
 use std::ffi::{c_void, CStr};
 use std::num::NonZeroU32;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
 
-use crate::cmd_line::CmdLineSettings;
+use crate::{
+    cmd_line::CmdLineSettings,
+    renderer::{PresentMode, RendererSettings},
+    settings::SETTINGS,
+};
 
-use gl::MAX_RENDERBUFFER_SIZE;
+use gl::types::GLint;
 use glutin::surface::SwapInterval;
 use glutin::{
     config::{Config, ConfigTemplateBuilder},
-    context::{ContextAttributesBuilder, GlProfile, PossiblyCurrentContext},
+    context::{ContextAttributesBuilder, GlProfile, PossiblyCurrentContext, Robustness},
     display::GetGlDisplay,
     prelude::*,
     surface::{Surface, SurfaceAttributesBuilder, WindowSurface},
@@ -23,22 +29,82 @@ use winit::{
 pub struct Context {
     surface: Surface<WindowSurface>,
     context: PossiblyCurrentContext,
-    window: Window,
+    window: Arc<Window>,
     config: Config,
+    present_mode: PresentMode,
+    srgb: bool,
+}
+
+// What every driver supports, used until the limits of the actual one are known
+const DEFAULT_MAX_SURFACE_SIZE: u32 = 4096;
+
+// The largest surface drawn into before being shown
+static MAX_OFFSCREEN_SURFACE_SIZE: AtomicU32 = AtomicU32::new(DEFAULT_MAX_SURFACE_SIZE);
+
+fn get_integers<const N: usize>(name: gl::types::GLenum) -> [u32; N] {
+    let mut values: [GLint; N] = [0; N];
+    unsafe { gl::GetIntegerv(name, values.as_mut_ptr()) };
+    values.map(|value| value.max(0) as u32)
+}
+
+/// Queries the surface sizes the driver supports, once OpenGL is loaded.
+pub fn load_surface_size_limits() {
+    let [max_texture_size] = get_integers(gl::MAX_TEXTURE_SIZE);
+    let [max_renderbuffer_size] = get_integers(gl::MAX_RENDERBUFFER_SIZE);
+    let max_offscreen_size = max_texture_size.min(max_renderbuffer_size);
+    log::info!("Offscreen surface size limit: {}", max_offscreen_size);
+    if max_offscreen_size > 0 {
+        MAX_OFFSCREEN_SURFACE_SIZE.store(max_offscreen_size, Ordering::Relaxed);
+    }
+}
+
+/// Applies `g:neovide_max_render_buffer_size` on top of the limit of the driver.
+fn offscreen_limit() -> u32 {
+    let driver_limit = MAX_OFFSCREEN_SURFACE_SIZE.load(Ordering::Relaxed);
+    match SETTINGS.get::<RendererSettings>().max_render_buffer_size {
+        0 => driver_limit,
+        configured => configured.min(driver_limit),
+    }
 }
 
+/// The framebuffer of the window always has its full size, the windows drawn into it are scaled
+/// to fit the offscreen limits instead.
 pub fn clamp_render_buffer_size(size: PhysicalSize<u32>) -> PhysicalSize<u32> {
-    PhysicalSize::new(
-        size.width.clamp(1, MAX_RENDERBUFFER_SIZE),
-        size.height.clamp(1, MAX_RENDERBUFFER_SIZE),
+    PhysicalSize::new(size.width.max(1), size.height.max(1))
+}
+
+/// The dimensions of a window surface for a window of `size`. Surfaces can't be empty, so windows
+/// without an area, like minimized ones, get a surface of a single pixel.
+pub fn surface_dimensions(size: PhysicalSize<u32>) -> (NonZeroU32, NonZeroU32) {
+    let size = clamp_render_buffer_size(size);
+    let one = NonZeroU32::new(1).unwrap();
+    (
+        NonZeroU32::new(size.width).unwrap_or(one),
+        NonZeroU32::new(size.height).unwrap_or(one),
     )
 }
 
+/// How much a surface of `size` has to be scaled down to fit the offscreen limits. Huge windows
+/// are drawn at a lower resolution and stretched, instead of being cut off.
+pub fn offscreen_surface_scale(size: PhysicalSize<u32>) -> f32 {
+    surface_scale(size, offscreen_limit())
+}
+
+fn surface_scale(size: PhysicalSize<u32>, max_size: u32) -> f32 {
+    let largest = size.width.max(size.height);
+    if largest <= max_size {
+        1.0
+    } else {
+        max_size as f32 / largest as f32
+    }
+}
+
 impl Context {
     pub fn window(&self) -> &Window {
         &self.window
     }
-    pub fn resize(&self, width: NonZeroU32, height: NonZeroU32) {
+    pub fn resize(&self, size: PhysicalSize<u32>) {
+        let (width, height) = surface_dimensions(size);
         GlSurface::resize(&self.surface, &self.context, width, height)
     }
     pub fn swap_buffers(&self) -> glutin::error::Result<()> {
@@ -54,17 +120,67 @@ impl Context {
     pub fn get_render_target_size(&self) -> PhysicalSize<u32> {
         clamp_render_buffer_size(self.window.inner_size())
     }
+
+    /// Whether the driver reset the context, after which everything drawn with it is gone.
+    pub fn is_lost(&self) -> bool {
+        // Only robust contexts can report the reset, others just stop working
+        gl::GetGraphicsResetStatus::is_loaded()
+            && unsafe { gl::GetGraphicsResetStatus() } != gl::NO_ERROR
+    }
+
+    /// Replaces the surface and the context with new ones for the same window, requesting sRGB or
+    /// not. Used after the old ones were lost and to switch sRGB. When that fails, the current ones
+    /// are kept.
+    pub fn recreate(&mut self, srgb: bool) -> glutin::error::Result<()> {
+        let (surface, context) =
+            create_surface_and_context(&self.window, &self.config, srgb, self.present_mode)?;
+        self.surface = surface;
+        self.context = context;
+        self.srgb = srgb;
+        Ok(())
+    }
+
+    pub fn srgb(&self) -> bool {
+        self.srgb
+    }
+
+    pub fn present_mode(&self) -> PresentMode {
+        self.present_mode
+    }
+
+    /// The modes there is a swap interval for.
+    pub fn supported_present_modes(&self) -> Vec<PresentMode> {
+        PresentMode::ALL
+            .into_iter()
+            .filter(|present_mode| swap_interval(*present_mode).is_some())
+            .collect()
+    }
+
+    /// Switches to one of the supported modes, also for contexts created later.
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) {
+        self.present_mode = present_mode;
+        set_swap_interval(&self.surface, &self.context, self.present_mode);
+    }
+}
+
+/// Whether an error means the context is gone and has to be recreated.
+pub fn is_context_lost_error(error: &glutin::error::Error) -> bool {
+    matches!(
+        error.error_kind(),
+        glutin::error::ErrorKind::ContextLost | glutin::error::ErrorKind::BadContext
+    )
 }
 
 fn gen_config(mut config_iterator: Box<dyn Iterator<Item = Config> + '_>) -> Config {
     config_iterator.next().unwrap()
 }
 
-pub fn build_context<TE>(
-    cmd_line_settings: &CmdLineSettings,
+/// Creates the window together with the OpenGL config for it. This has to happen on the thread of
+/// the event loop, while the context can be created on the thread drawing with it.
+pub fn build_window<TE>(
     winit_window_builder: WindowBuilder,
     event_loop: &EventLoop<TE>,
-) -> Context {
+) -> (Window, Config) {
     let template_builder = ConfigTemplateBuilder::new()
         .with_stencil_size(8)
         .with_transparency(true);
@@ -73,41 +189,110 @@ pub fn build_context<TE>(
         .build(event_loop, template_builder, gen_config)
         .expect("Failed to create Window");
     let window = window.expect("Could not create Window");
+    (window, config)
+}
+
+/// Creates the context drawing into the window, current on the calling thread.
+pub fn build_context(
+    cmd_line_settings: &CmdLineSettings,
+    window: Arc<Window>,
+    config: Config,
+) -> Context {
+    let present_mode = PresentMode::from_vsync(cmd_line_settings.vsync);
+    let srgb = cmd_line_settings.srgb;
+    let (surface, context) = create_surface_and_context(&window, &config, srgb, present_mode)
+        .expect("Failed to create OpenGL context");
+
+    Context {
+        surface,
+        context,
+        window,
+        config,
+        present_mode,
+        srgb,
+    }
+}
 
+fn create_surface_and_context(
+    window: &Window,
+    config: &Config,
+    srgb: bool,
+    present_mode: PresentMode,
+) -> glutin::error::Result<(Surface<WindowSurface>, PossiblyCurrentContext)> {
     let gl_display = config.display();
     let raw_window_handle = window.raw_window_handle();
 
-    let size = clamp_render_buffer_size(window.inner_size());
+    let (width, height) = surface_dimensions(window.inner_size());
 
     let surface_attributes = SurfaceAttributesBuilder::<WindowSurface>::new()
-        .with_srgb(Some(cmd_line_settings.srgb))
-        .build(
-            raw_window_handle,
-            NonZeroU32::new(size.width).unwrap(),
-            NonZeroU32::new(size.height).unwrap(),
-        );
-    let surface = unsafe { gl_display.create_window_surface(&config, &surface_attributes) }
-        .expect("Failed to create Windows Surface");
+        .with_srgb(Some(srgb))
+        .build(raw_window_handle, width, height);
+    let surface = unsafe { gl_display.create_window_surface(config, &surface_attributes) }?;
 
+    // A robust context reports driver resets, so that they can be recovered from. Not every
+    // driver supports it.
+    let robust_context_attributes = ContextAttributesBuilder::new()
+        .with_profile(GlProfile::Core)
+        .with_robustness(Robustness::RobustLoseContextOnReset)
+        .build(Some(raw_window_handle));
     let context_attributes = ContextAttributesBuilder::new()
         .with_profile(GlProfile::Core)
         .build(Some(raw_window_handle));
-    let context = unsafe { gl_display.create_context(&config, &context_attributes) }
-        .expect("Failed to create OpenGL context")
-        .make_current(&surface)
-        .unwrap();
+    let context = unsafe {
+        gl_display
+            .create_context(config, &robust_context_attributes)
+            .or_else(|_| gl_display.create_context(config, &context_attributes))
+    }?
+    .make_current(&surface)?;
 
-    // NOTE: We don't care if these fails, the driver can override the SwapInterval in any case, so it needs to work in all cases
-    let _ = if cmd_line_settings.vsync {
-        surface.set_swap_interval(&context, SwapInterval::Wait(NonZeroU32::new(1).unwrap()))
-    } else {
-        surface.set_swap_interval(&context, SwapInterval::DontWait)
+    set_swap_interval(&surface, &context, present_mode);
+
+    Ok((surface, context))
+}
+
+fn set_swap_interval(
+    surface: &Surface<WindowSurface>,
+    context: &PossiblyCurrentContext,
+    present_mode: PresentMode,
+) {
+    let swap_interval = match swap_interval(present_mode) {
+        Some(swap_interval) => swap_interval,
+        None => return,
     };
+    // NOTE: We don't care if these fails, the driver can override the SwapInterval in any case, so it needs to work in all cases
+    let _ = surface.set_swap_interval(context, swap_interval);
+}
 
-    Context {
-        surface,
-        context,
-        window,
-        config,
+/// OpenGL can only wait for vsync or not, replacing queued frames is up to the driver, so there is
+/// no swap interval for `mailbox`.
+fn swap_interval(present_mode: PresentMode) -> Option<SwapInterval> {
+    match present_mode {
+        PresentMode::Fifo => Some(SwapInterval::Wait(NonZeroU32::new(1).unwrap())),
+        PresentMode::Immediate => Some(SwapInterval::DontWait),
+        PresentMode::Mailbox => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serial_test::serial;
+
+    use super::*;
+
+    #[test]
+    fn huge_surfaces_are_scaled_down() {
+        assert_eq!(surface_scale(PhysicalSize::new(7680, 2160), 8192), 1.0);
+        assert_eq!(surface_scale(PhysicalSize::new(16384, 4320), 8192), 0.5);
+        assert_eq!(surface_scale(PhysicalSize::new(2000, 16384), 4096), 0.25);
+    }
+
+    #[test]
+    #[serial]
+    fn empty_windows_get_a_surface() {
+        SETTINGS.set(&RendererSettings::default());
+        let (width, height) = surface_dimensions(PhysicalSize::new(0, 0));
+        assert_eq!((width.get(), height.get()), (1, 1));
+        let (width, height) = surface_dimensions(PhysicalSize::new(800, 0));
+        assert_eq!((width.get(), height.get()), (800, 1));
     }
 }
@@ -0,0 +1,185 @@
+//! What the overlays drawn over the editor share, like the command palette, the settings panel
+//! and the terminal drawer: the layout of their panels and the routing of the input to them.
+
+use skia_safe::{paint, Canvas, Color, Paint, RRect, Rect};
+use winit::{
+    dpi::PhysicalPosition,
+    event::{ElementState, Event, Ime, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent},
+    keyboard::ModifiersState,
+};
+
+use crate::renderer::GridRenderer;
+
+/// The space between the panels and the edges of the window
+pub const MARGIN: f32 = 32.0;
+const MAX_WIDTH_COLUMNS: u64 = 80;
+// Rows are this many lines high, so that they are easy to click
+const ROW_HEIGHT_LINES: f32 = 1.4;
+
+/// The sizes of the rows of a panel, from the font of the editor.
+#[derive(Clone, Copy)]
+pub struct Layout {
+    pub char_width: f32,
+    pub line_height: f32,
+    pub row_height: f32,
+    /// Where the text starts in a row, so that it's centered vertically
+    pub text_offset: f32,
+}
+
+impl Layout {
+    pub fn new(grid_renderer: &GridRenderer) -> Self {
+        let font_dimensions = grid_renderer.font_dimensions;
+        let char_width = font_dimensions.width as f32;
+        let line_height = font_dimensions.height as f32;
+        let row_height = (line_height * ROW_HEIGHT_LINES).round();
+        let y_adjustment = grid_renderer.shaper.y_adjustment() as f32;
+        Self {
+            char_width,
+            line_height,
+            row_height,
+            text_offset: (row_height - line_height) / 2.0 + y_adjustment,
+        }
+    }
+
+    /// A panel of the given height, centered at the top of `region`.
+    pub fn panel(&self, region: Rect, height: f32) -> Rect {
+        let width = (region.width() - 2.0 * MARGIN).min(MAX_WIDTH_COLUMNS as f32 * self.char_width);
+        Rect::from_xywh(
+            region.center_x() - width / 2.0,
+            region.top + MARGIN,
+            width,
+            height,
+        )
+    }
+}
+
+/// The colors of the panels, taken from the default highlight of Neovim.
+#[derive(Clone, Copy)]
+pub struct Colors {
+    pub background: Color,
+    pub foreground: Color,
+    pub dimmed: Color,
+    pub accent: Color,
+}
+
+impl Colors {
+    pub fn new(grid_renderer: &GridRenderer) -> Self {
+        let colors = &grid_renderer.default_style.colors;
+        let foreground = colors
+            .foreground
+            .map_or(Color::WHITE, |color| color.to_color());
+        Self {
+            background: colors
+                .background
+                .map_or(Color::BLACK, |color| color.to_color()),
+            foreground,
+            dimmed: foreground.with_a(140),
+            accent: colors
+                .special
+                .map_or(Color::from_rgb(80, 140, 240), |color| color.to_color()),
+        }
+    }
+}
+
+/// Draws the rounded background and border of a panel.
+pub fn draw_panel(canvas: &mut Canvas, panel: Rect, radius: f32, colors: &Colors) {
+    let mut paint = Paint::default();
+    paint.set_anti_alias(true).set_color(colors.background);
+    canvas.draw_rrect(RRect::new_rect_xy(panel, radius, radius), &paint);
+    paint
+        .set_style(paint::Style::Stroke)
+        .set_stroke_width(1.0)
+        .set_color(colors.dimmed);
+    canvas.draw_rrect(RRect::new_rect_xy(panel, radius, radius), &paint);
+}
+
+pub fn contains(rect: &Rect, position: PhysicalPosition<f64>) -> bool {
+    let (x, y) = (position.x as f32, position.y as f32);
+    x >= rect.left && x < rect.right && y >= rect.top && y < rect.bottom
+}
+
+/// The window events the overlays take, instead of Neovim.
+pub enum Input<'a> {
+    ModifiersChanged(ModifiersState),
+    CursorMoved(PhysicalPosition<f64>),
+    /// A press of the left mouse button
+    Click,
+    /// The other mouse buttons, and releasing the left one
+    MouseButton,
+    Wheel(MouseScrollDelta),
+    KeyPressed(&'a KeyEvent),
+    /// Text committed by the input method
+    Text(&'a str),
+    /// Released keys and the rest of the input method events
+    OtherKeyboard,
+}
+
+impl<'a> Input<'a> {
+    /// The input in the event, if it's one.
+    pub fn of(event: &'a Event<()>) -> Option<Self> {
+        let window_event = match event {
+            Event::WindowEvent { event, .. } => event,
+            _ => return None,
+        };
+        Some(match window_event {
+            WindowEvent::ModifiersChanged(modifiers) => Input::ModifiersChanged(modifiers.state()),
+            WindowEvent::CursorMoved { position, .. } => Input::CursorMoved(*position),
+            WindowEvent::MouseInput {
+                button: MouseButton::Left,
+                state: ElementState::Pressed,
+                ..
+            } => Input::Click,
+            WindowEvent::MouseInput { .. } => Input::MouseButton,
+            WindowEvent::MouseWheel { delta, .. } => Input::Wheel(*delta),
+            WindowEvent::KeyboardInput {
+                event:
+                    key_event @ KeyEvent {
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => Input::KeyPressed(key_event),
+            WindowEvent::Ime(Ime::Commit(text)) => Input::Text(text),
+            WindowEvent::KeyboardInput { .. } | WindowEvent::Ime(_) => Input::OtherKeyboard,
+            _ => return None,
+        })
+    }
+}
+
+/// An overlay covering the editor, which takes all the input while it's open.
+pub trait Modal {
+    fn is_open(&self) -> bool;
+    fn key_pressed(&mut self, key_event: &KeyEvent);
+    fn cursor_moved(&mut self, position: PhysicalPosition<f64>);
+    fn clicked(&mut self);
+    fn modifiers_changed(&mut self, _modifiers: ModifiersState) {}
+}
+
+/// Gives the input to the overlay while it's open. Returns true when the event was used and
+/// shouldn't reach Neovim.
+pub fn handle_modal_event(overlay: &mut impl Modal, event: &Event<()>) -> bool {
+    if !overlay.is_open() {
+        return false;
+    }
+    match Input::of(event) {
+        Some(Input::ModifiersChanged(modifiers)) => {
+            overlay.modifiers_changed(modifiers);
+            // The keyboard manager needs them too, for when the overlay closes
+            false
+        }
+        Some(Input::CursorMoved(position)) => {
+            overlay.cursor_moved(position);
+            true
+        }
+        Some(Input::Click) => {
+            overlay.clicked();
+            true
+        }
+        Some(Input::KeyPressed(key_event)) => {
+            overlay.key_pressed(key_event);
+            true
+        }
+        Some(_) => true,
+        None => false,
+    }
+}
@@ -0,0 +1,324 @@
+use std::sync::Arc;
+
+use skia_safe::{Canvas, Point};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    bridge::PopupMenuItem,
+    dimensions::Dimensions,
+    editor::Style,
+    renderer::{completion_panel::draw_completion_panel, GridRenderer},
+};
+
+/// The highlights of the popup menu, from the Pmenu groups of Neovim.
+#[derive(Clone, Debug, Default)]
+pub struct PopupMenuStyles {
+    pub normal: Option<Arc<Style>>,
+    pub selected: Option<Arc<Style>>,
+    pub scrollbar: Option<Arc<Style>>,
+    pub thumb: Option<Arc<Style>>,
+}
+
+/// The popup menu Neovim hands over while `ext_popupmenu` is enabled. `row` and `column` are where
+/// the completed word starts on the outer grid.
+#[derive(Clone, Debug)]
+pub struct PopupMenu {
+    pub items: Vec<PopupMenuItem>,
+    pub selected: Option<u64>,
+    pub row: u64,
+    pub column: u64,
+    pub styles: PopupMenuStyles,
+}
+
+/// Where the menu is drawn on the outer grid, in cells.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MenuLayout {
+    pub row: u64,
+    pub column: u64,
+    pub width: u64,
+    pub height: u64,
+}
+
+// The widths of the word, kind and menu columns
+type ColumnWidths = (usize, usize, usize);
+
+impl PopupMenu {
+    fn column_widths(&self) -> ColumnWidths {
+        let width = |text: &str| text.graphemes(true).count();
+        self.items
+            .iter()
+            .fold((0, 0, 0), |(word, kind, menu), item| {
+                (
+                    word.max(width(&item.word)),
+                    kind.max(width(&item.kind)),
+                    menu.max(width(&item.menu)),
+                )
+            })
+    }
+
+    /// Below the line the menu belongs to when all the items fit there or there is more room than
+    /// above, otherwise above it, like Neovim places its own menu. A column of padding goes before
+    /// the words, so the menu starts one column left of them.
+    fn layout(&self, screen_size: Dimensions) -> MenuLayout {
+        let (word, kind, menu) = self.column_widths();
+        let item_count = self.items.len() as u64;
+        let rows_below = screen_size.height.saturating_sub(self.row + 1);
+        let rows_above = self.row.min(screen_size.height);
+        let (row, height) = if item_count <= rows_below || rows_below >= rows_above {
+            (self.row + 1, item_count.min(rows_below))
+        } else {
+            let height = item_count.min(rows_above);
+            (self.row - height, height)
+        };
+
+        let scrollbar = usize::from(item_count > height);
+        let width = ((1 + word + optional_width(kind) + optional_width(menu) + 1 + scrollbar)
+            as u64)
+            .min(screen_size.width);
+        let column = self.column.saturating_sub(1).min(screen_size.width - width);
+
+        MenuLayout {
+            row,
+            column,
+            width,
+            height,
+        }
+    }
+}
+
+// Columns which are empty for every item take no space
+fn optional_width(width: usize) -> usize {
+    if width > 0 {
+        width + 1
+    } else {
+        0
+    }
+}
+
+fn padded(text: &str, width: usize) -> String {
+    let padding = width.saturating_sub(text.graphemes(true).count());
+    format!("{}{}", text, " ".repeat(padding))
+}
+
+// The text of an item, with its columns lined up with the other items
+fn item_text(item: &PopupMenuItem, (word, kind, menu): ColumnWidths, width: u64) -> String {
+    let mut text = format!(" {}", padded(&item.word, word));
+    if kind > 0 {
+        text.push(' ');
+        text.push_str(&padded(&item.kind, kind));
+    }
+    if menu > 0 {
+        text.push(' ');
+        text.push_str(&item.menu);
+    }
+    text.graphemes(true).take(width as usize).collect()
+}
+
+// Scrolls just enough for the selected item to be visible
+fn scroll_to_selected(scroll: u64, selected: Option<u64>, height: u64, item_count: u64) -> u64 {
+    let scroll = match selected {
+        Some(selected) if selected < scroll => selected,
+        Some(selected) if selected >= scroll + height => selected + 1 - height,
+        _ => scroll,
+    };
+    scroll.min(item_count.saturating_sub(height))
+}
+
+/// Draws the popup menu of Neovim, along with the documentation of the selected item.
+#[derive(Default)]
+pub struct PopupMenuRenderer {
+    menu: Option<PopupMenu>,
+    // The first item shown
+    scroll: u64,
+}
+
+impl PopupMenuRenderer {
+    pub fn show(&mut self, menu: PopupMenu) {
+        self.menu = Some(menu);
+        self.scroll = 0;
+    }
+
+    pub fn select(&mut self, selected: Option<u64>) {
+        if let Some(menu) = &mut self.menu {
+            menu.selected = selected;
+        }
+    }
+
+    pub fn hide(&mut self) {
+        self.menu = None;
+    }
+
+    /// `screen_size` is the size of the outer grid in cells, and `origin` where it starts in
+    /// pixels.
+    pub fn draw(
+        &mut self,
+        canvas: &mut Canvas,
+        grid_renderer: &mut GridRenderer,
+        screen_size: Dimensions,
+        origin: Point,
+        documentation: bool,
+    ) {
+        let menu = match &self.menu {
+            Some(menu) => menu,
+            None => return,
+        };
+        let layout = menu.layout(screen_size);
+        if layout.width == 0 || layout.height == 0 {
+            return;
+        }
+        let item_count = menu.items.len() as u64;
+        self.scroll = scroll_to_selected(self.scroll, menu.selected, layout.height, item_count);
+
+        canvas.save();
+        canvas.translate(origin);
+
+        let window_background = grid_renderer.get_default_background();
+        let has_scrollbar = item_count > layout.height;
+        let text_width = layout.width - u64::from(has_scrollbar);
+        let column_widths = menu.column_widths();
+        for line in 0..layout.height {
+            let index = self.scroll + line;
+            let style = if menu.selected == Some(index) {
+                &menu.styles.selected
+            } else {
+                &menu.styles.normal
+            };
+            let position = (layout.column, layout.row + line);
+            let text = item_text(&menu.items[index as usize], column_widths, text_width);
+            grid_renderer.draw_background(
+                canvas,
+                position,
+                text_width,
+                style,
+                true,
+                window_background,
+                false,
+            );
+            grid_renderer.draw_foreground(canvas, text, position, text_width, style, false);
+        }
+
+        if has_scrollbar {
+            let thumb_height = (layout.height * layout.height / item_count).max(1);
+            let thumb_top = self.scroll * layout.height / item_count;
+            for line in 0..layout.height {
+                let style = if (thumb_top..thumb_top + thumb_height).contains(&line) {
+                    &menu.styles.thumb
+                } else {
+                    &menu.styles.scrollbar
+                };
+                grid_renderer.draw_background(
+                    canvas,
+                    (layout.column + text_width, layout.row + line),
+                    1,
+                    style,
+                    true,
+                    window_background,
+                    false,
+                );
+            }
+        }
+
+        let selected_item = menu
+            .selected
+            .and_then(|selected| menu.items.get(selected as usize));
+        if let Some(item) = selected_item.filter(|item| documentation && !item.info.is_empty()) {
+            draw_completion_panel(
+                canvas,
+                grid_renderer,
+                screen_size,
+                &layout,
+                &item.info,
+                &menu.styles.normal,
+            );
+        }
+
+        canvas.restore();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(word: &str, kind: &str, menu: &str) -> PopupMenuItem {
+        PopupMenuItem {
+            word: word.to_string(),
+            kind: kind.to_string(),
+            menu: menu.to_string(),
+            info: String::new(),
+        }
+    }
+
+    fn menu(item_count: usize, row: u64, column: u64) -> PopupMenu {
+        PopupMenu {
+            items: (0..item_count).map(|_| item("word", "", "")).collect(),
+            selected: None,
+            row,
+            column,
+            styles: PopupMenuStyles::default(),
+        }
+    }
+
+    #[test]
+    fn goes_below_the_line_when_it_fits() {
+        let screen_size = Dimensions {
+            width: 80,
+            height: 24,
+        };
+        assert_eq!(
+            menu(5, 2, 10).layout(screen_size),
+            MenuLayout {
+                row: 3,
+                column: 9,
+                width: 6,
+                height: 5,
+            }
+        );
+    }
+
+    #[test]
+    fn goes_above_the_line_when_there_is_more_room() {
+        let screen_size = Dimensions {
+            width: 80,
+            height: 24,
+        };
+        assert_eq!(
+            menu(30, 20, 10).layout(screen_size),
+            MenuLayout {
+                row: 0,
+                column: 9,
+                width: 7,
+                height: 20,
+            }
+        );
+    }
+
+    #[test]
+    fn stays_on_the_screen() {
+        let screen_size = Dimensions {
+            width: 80,
+            height: 24,
+        };
+        assert_eq!(menu(2, 0, 78).layout(screen_size).column, 74);
+    }
+
+    #[test]
+    fn lines_up_the_columns() {
+        let menu = PopupMenu {
+            items: vec![item("push", "f", "Vec"), item("len", "method", "")],
+            ..menu(0, 0, 0)
+        };
+        let widths = menu.column_widths();
+        assert_eq!(item_text(&menu.items[0], widths, 80), " push f      Vec");
+        assert_eq!(item_text(&menu.items[1], widths, 80), " len  method ");
+        assert_eq!(item_text(&menu.items[1], widths, 6), " len  ");
+    }
+
+    #[test]
+    fn scrolls_to_the_selected_item() {
+        assert_eq!(scroll_to_selected(0, Some(12), 10, 20), 3);
+        assert_eq!(scroll_to_selected(5, Some(2), 10, 20), 2);
+        assert_eq!(scroll_to_selected(5, None, 10, 20), 5);
+        assert_eq!(scroll_to_selected(15, None, 10, 20), 10);
+    }
+}
@@ -0,0 +1,130 @@
+use std::sync::Arc;
+
+use skia_safe::{Canvas, Paint, Point, Rect};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    editor::{Cursor, Style},
+    renderer::GridRenderer,
+};
+
+/// Text typed in insert mode that Neovim hasn't drawn yet, shown at the cursor in the meantime.
+#[derive(Default)]
+pub struct PredictedEcho {
+    text: String,
+}
+
+impl PredictedEcho {
+    pub fn predict(&mut self, text: &str) {
+        self.text.push_str(text);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// Drops the text Neovim drew, going by how far the cursor moved along the line. When it
+    /// didn't move or went somewhere else, like with a mapping, nothing of it is left to predict.
+    pub fn reconcile(&mut self, old_cursor: &Cursor, new_cursor: &Cursor) {
+        let (old_column, old_row) = old_cursor.grid_position;
+        let (new_column, new_row) = new_cursor.grid_position;
+        let drawn = new_column.saturating_sub(old_column) as usize;
+        if old_cursor.parent_window_id != new_cursor.parent_window_id
+            || old_row != new_row
+            || drawn == 0
+        {
+            self.text.clear();
+        } else {
+            self.text = self.text.graphemes(true).skip(drawn).collect();
+        }
+    }
+
+    /// Draws the text at `position` with the style of the cell under the cursor, so that it looks
+    /// like the text around it. Returns its width, to move the cursor past it.
+    pub fn draw(
+        &self,
+        canvas: &mut Canvas,
+        grid_renderer: &mut GridRenderer,
+        position: Point,
+        cell_style: &Option<Arc<Style>>,
+    ) -> f32 {
+        let font_dimensions = grid_renderer.font_dimensions;
+        let columns = self.text.graphemes(true).count();
+        let width = (columns as u64 * font_dimensions.width) as f32;
+
+        let default_style = grid_renderer.default_style.clone();
+        let style = cell_style.as_ref().unwrap_or(&default_style);
+        let mut paint = Paint::default();
+        paint.set_anti_alias(false);
+        paint.set_color(style.background(&default_style.colors).to_color());
+        canvas.draw_rect(
+            Rect::from_xywh(position.x, position.y, width, font_dimensions.height as f32),
+            &paint,
+        );
+        paint.set_color(style.foreground(&default_style.colors).to_color());
+
+        let y_adjustment = grid_renderer.shaper.y_adjustment() as f32;
+        for blob in grid_renderer
+            .shaper
+            .shape_cached(self.text.clone(), style.bold, style.italic, true)
+            .iter()
+        {
+            canvas.draw_text_blob(blob, (position.x, position.y + y_adjustment), &paint);
+        }
+
+        width
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cursor(window: u64, column: u64, row: u64) -> Cursor {
+        Cursor {
+            parent_window_id: window,
+            grid_position: (column, row),
+            ..Cursor::new()
+        }
+    }
+
+    fn predicted(keys: &[&str]) -> PredictedEcho {
+        let mut echo = PredictedEcho::default();
+        for key in keys {
+            echo.predict(key);
+        }
+        echo
+    }
+
+    #[test]
+    fn typed_text_is_predicted_in_order() {
+        let echo = predicted(&["a", "é", "b"]);
+        assert_eq!(echo.text, "aéb");
+        assert!(PredictedEcho::default().is_empty());
+    }
+
+    #[test]
+    fn text_is_dropped_as_neovim_draws_it() {
+        let mut echo = predicted(&["a", "é", "b"]);
+        echo.reconcile(&cursor(2, 4, 1), &cursor(2, 6, 1));
+        assert_eq!(echo.text, "b");
+        echo.reconcile(&cursor(2, 6, 1), &cursor(2, 7, 1));
+        assert!(echo.is_empty());
+    }
+
+    #[test]
+    fn text_is_dropped_when_the_cursor_goes_elsewhere() {
+        let mut echo = predicted(&["a", "b"]);
+        echo.reconcile(&cursor(2, 4, 1), &cursor(2, 5, 2));
+        assert!(echo.is_empty());
+
+        let mut echo = predicted(&["a", "b"]);
+        echo.reconcile(&cursor(2, 4, 1), &cursor(3, 5, 1));
+        assert!(echo.is_empty());
+
+        // A mapping that didn't insert anything
+        let mut echo = predicted(&["a"]);
+        echo.reconcile(&cursor(2, 4, 1), &cursor(2, 4, 1));
+        assert!(echo.is_empty());
+    }
+}
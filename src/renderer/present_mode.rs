@@ -0,0 +1,90 @@
+use std::fmt;
+
+/// How finished frames are handed to the screen, named after the Vulkan modes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PresentMode {
+    /// Waits for vsync. No tearing, but a frame may wait up to a refresh before it's shown.
+    Fifo,
+    /// Replaces the queued frame with newer ones without waiting. No tearing and low latency.
+    Mailbox,
+    /// Shows frames right away. The lowest latency, but it may tear.
+    Immediate,
+}
+
+impl PresentMode {
+    pub const ALL: [PresentMode; 3] = [Self::Fifo, Self::Mailbox, Self::Immediate];
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "fifo" => Some(Self::Fifo),
+            "mailbox" => Some(Self::Mailbox),
+            "immediate" => Some(Self::Immediate),
+            _ => None,
+        }
+    }
+
+    /// The mode `--vsync` and `--novsync` stand for.
+    pub fn from_vsync(vsync: bool) -> Self {
+        if vsync {
+            Self::Fifo
+        } else {
+            Self::Immediate
+        }
+    }
+
+    /// Fails with a message naming the modes the backend supports instead, when it can't present
+    /// this way.
+    pub fn check_supported(self, supported: &[PresentMode]) -> Result<Self, String> {
+        if supported.contains(&self) {
+            return Ok(self);
+        }
+        let supported: Vec<String> = supported.iter().map(ToString::to_string).collect();
+        Err(format!(
+            "The present mode {} isn't supported, only {} are",
+            self,
+            supported.join(" and ")
+        ))
+    }
+}
+
+impl fmt::Display for PresentMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Fifo => "fifo",
+            Self::Mailbox => "mailbox",
+            Self::Immediate => "immediate",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_names() {
+        assert_eq!(PresentMode::parse("fifo"), Some(PresentMode::Fifo));
+        assert_eq!(PresentMode::parse("mailbox"), Some(PresentMode::Mailbox));
+        assert_eq!(
+            PresentMode::parse("immediate"),
+            Some(PresentMode::Immediate)
+        );
+        assert_eq!(PresentMode::parse("vsync"), None);
+    }
+
+    #[test]
+    fn unsupported_modes_are_rejected() {
+        let supported = [PresentMode::Fifo, PresentMode::Immediate];
+        assert_eq!(
+            PresentMode::Immediate.check_supported(&supported),
+            Ok(PresentMode::Immediate)
+        );
+        assert_eq!(
+            PresentMode::Mailbox.check_supported(&supported),
+            Err(
+                "The present mode mailbox isn't supported, only fifo and immediate are".to_string()
+            )
+        );
+    }
+}
@@ -0,0 +1,522 @@
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use skia_safe::{Canvas, Color, Paint, Rect};
+
+use crate::{
+    redraw_scheduler::REDRAW_SCHEDULER,
+    renderer::{
+        animation_utils::{ease, ease_out_cubic},
+        GridRenderer,
+    },
+};
+
+// In columns and lines of the font
+const MARGIN_COLUMNS: f32 = 2.0;
+const BLOCK_SPACING_LINES: f32 = 0.6;
+
+#[derive(Clone, Debug, PartialEq)]
+enum BlockKind {
+    Heading(usize),
+    Paragraph,
+    Code,
+    Quote,
+    /// The marker, like `•` or `2.`, and how deeply the list is nested
+    ListItem {
+        marker: String,
+        depth: usize,
+    },
+    Rule,
+}
+
+/// A block of the rendered markdown, with the line of the source it starts on.
+#[derive(Clone, Debug, PartialEq)]
+struct Block {
+    kind: BlockKind,
+    text: String,
+    source_line: u64,
+}
+
+impl Block {
+    fn scale(&self) -> f32 {
+        match self.kind {
+            BlockKind::Heading(1) => 1.6,
+            BlockKind::Heading(2) => 1.35,
+            BlockKind::Heading(3) => 1.15,
+            _ => 1.0,
+        }
+    }
+
+    fn indent_columns(&self) -> usize {
+        match &self.kind {
+            BlockKind::ListItem { marker, depth } => depth * 2 + marker.chars().count() + 1,
+            BlockKind::Quote => 2,
+            BlockKind::Code => 1,
+            _ => 0,
+        }
+    }
+}
+
+struct LaidOutBlock {
+    block: usize,
+    top: f32,
+    height: f32,
+    lines: Vec<String>,
+}
+
+/// A live preview of the current buffer next to the grid, toggled with `:NeovidePreview`. Markdown
+/// is rendered, and the preview scrolls along with the source window.
+#[derive(Default)]
+pub struct PreviewPane {
+    filetype: String,
+    blocks: Vec<Block>,
+    layout: Vec<LaidOutBlock>,
+    // The columns and the line height the layout was made for
+    layout_key: Option<(usize, u64)>,
+    source_top_line: u64,
+    scroll_start: f32,
+    scroll: f32,
+    scroll_destination: f32,
+    scroll_t: f32,
+}
+
+impl PreviewPane {
+    pub fn set_content(&mut self, filetype: String, lines: Vec<String>) {
+        self.blocks = if filetype == "markdown" {
+            parse_markdown(&lines.join("\n"))
+        } else {
+            Vec::new()
+        };
+        self.filetype = filetype;
+        self.layout_key = None;
+        REDRAW_SCHEDULER.queue_next_frame();
+    }
+
+    pub fn set_top_line(&mut self, line: u64) {
+        self.source_top_line = line;
+        REDRAW_SCHEDULER.queue_next_frame();
+    }
+
+    fn update_layout(&mut self, grid_renderer: &GridRenderer, columns: usize) {
+        let line_height = grid_renderer.font_dimensions.height;
+        if self.layout_key == Some((columns, line_height)) {
+            return;
+        }
+        self.layout_key = Some((columns, line_height));
+
+        let line_height = line_height as f32;
+        let mut top = 0.0;
+        self.layout = self
+            .blocks
+            .iter()
+            .enumerate()
+            .map(|(index, block)| {
+                let scale = block.scale();
+                let width =
+                    ((columns.saturating_sub(block.indent_columns())) as f32 / scale) as usize;
+                let lines = match block.kind {
+                    // Code keeps its lines, cut off at the edge of the pane
+                    BlockKind::Code => block.text.lines().map(String::from).collect(),
+                    BlockKind::Rule => vec![String::new()],
+                    _ => wrap_text(&block.text, width.max(1)),
+                };
+                let height = lines.len() as f32 * line_height * scale;
+                let laid_out = LaidOutBlock {
+                    block: index,
+                    top,
+                    height,
+                    lines,
+                };
+                top += height + BLOCK_SPACING_LINES * line_height;
+                laid_out
+            })
+            .collect();
+    }
+
+    // Keeps the first block at or after the top line of the source at the top of the pane
+    fn update_scroll(&mut self, dt: f32, animation_length: f32) {
+        let destination = self
+            .layout
+            .iter()
+            .find(|laid_out| self.blocks[laid_out.block].source_line >= self.source_top_line)
+            .or_else(|| self.layout.last())
+            .map_or(0.0, |laid_out| laid_out.top);
+        if (destination - self.scroll_destination).abs() > f32::EPSILON {
+            self.scroll_start = self.scroll;
+            self.scroll_destination = destination;
+            self.scroll_t = 0.0;
+        }
+
+        if self.scroll_t >= 1.0 {
+            self.scroll = self.scroll_destination;
+            return;
+        }
+        self.scroll_t = if animation_length > 0.0 {
+            (self.scroll_t + dt / animation_length).min(1.0)
+        } else {
+            1.0
+        };
+        self.scroll = ease(
+            ease_out_cubic,
+            self.scroll_start,
+            self.scroll_destination,
+            self.scroll_t,
+        );
+        REDRAW_SCHEDULER.queue_next_frame();
+    }
+
+    pub fn draw(
+        &mut self,
+        canvas: &mut Canvas,
+        grid_renderer: &mut GridRenderer,
+        region: Rect,
+        dt: f32,
+        animation_length: f32,
+    ) {
+        let font_dimensions = grid_renderer.font_dimensions;
+        let char_width = font_dimensions.width as f32;
+        let line_height = font_dimensions.height as f32;
+        let y_adjustment = grid_renderer.shaper.y_adjustment() as f32;
+        let columns = ((region.width() / char_width) - 2.0 * MARGIN_COLUMNS).max(0.0) as usize;
+        if columns == 0 {
+            return;
+        }
+        self.update_layout(grid_renderer, columns);
+        self.update_scroll(dt, animation_length);
+
+        let colors = &grid_renderer.default_style.colors;
+        let background = colors
+            .background
+            .map_or(Color::BLACK, |color| color.to_color());
+        let foreground = colors
+            .foreground
+            .map_or(Color::WHITE, |color| color.to_color());
+        let accent = colors.special.map_or(foreground, |color| color.to_color());
+        let dimmed = foreground.with_a(150);
+
+        canvas.save();
+        canvas.clip_rect(region, None, Some(false));
+        let mut paint = Paint::default();
+        paint.set_color(background);
+        canvas.draw_rect(region, &paint);
+        paint.set_color(foreground.with_a(40));
+        canvas.draw_rect(
+            Rect::from_xywh(region.left, region.top, 1.0, region.height()),
+            &paint,
+        );
+
+        let left = region.left + MARGIN_COLUMNS * char_width;
+        let right = region.right - MARGIN_COLUMNS * char_width;
+        let mut draw_text =
+            |canvas: &mut Canvas, text: &str, x: f32, y: f32, color: Color, bold: bool| {
+                let mut paint = Paint::default();
+                paint.set_anti_alias(true).set_color(color);
+                for blob in grid_renderer
+                    .shaper
+                    .shape_cached(text.to_string(), bold, false, false)
+                    .iter()
+                {
+                    canvas.draw_text_blob(blob, (x, y + y_adjustment), &paint);
+                }
+            };
+
+        if self.filetype != "markdown" {
+            let message = if self.filetype.is_empty() {
+                "No preview for this buffer".to_string()
+            } else {
+                format!("No preview for {} files", self.filetype)
+            };
+            draw_text(
+                canvas,
+                &message,
+                left,
+                region.top + line_height,
+                dimmed,
+                false,
+            );
+            canvas.restore();
+            return;
+        }
+
+        let origin = region.top + line_height - self.scroll;
+        for laid_out in &self.layout {
+            let top = origin + laid_out.top;
+            if top + laid_out.height < region.top || top > region.bottom {
+                continue;
+            }
+            let block = &self.blocks[laid_out.block];
+            let indent = left + block.indent_columns() as f32 * char_width;
+            let scale = block.scale();
+
+            match &block.kind {
+                BlockKind::Code => {
+                    paint.set_color(foreground.with_a(20));
+                    canvas.draw_rect(Rect::new(left, top, right, top + laid_out.height), &paint);
+                }
+                BlockKind::Quote => {
+                    paint.set_color(accent.with_a(160));
+                    canvas.draw_rect(Rect::from_xywh(left, top, 2.0, laid_out.height), &paint);
+                }
+                BlockKind::Rule => {
+                    paint.set_color(foreground.with_a(60));
+                    canvas.draw_rect(
+                        Rect::from_xywh(left, top + laid_out.height / 2.0, right - left, 1.0),
+                        &paint,
+                    );
+                }
+                BlockKind::ListItem { marker, depth } => {
+                    let x = left + (depth * 2) as f32 * char_width;
+                    draw_text(canvas, marker, x, top, accent, false);
+                }
+                BlockKind::Heading(_) | BlockKind::Paragraph => {}
+            }
+
+            let (color, bold) = match block.kind {
+                BlockKind::Heading(_) => (foreground, true),
+                BlockKind::Quote => (dimmed, false),
+                _ => (foreground, false),
+            };
+            for (index, line) in laid_out.lines.iter().enumerate() {
+                let y = top + index as f32 * line_height * scale;
+                canvas.save();
+                canvas.translate((indent, y));
+                canvas.scale((scale, scale));
+                draw_text(canvas, line, 0.0, 0.0, color, bold);
+                canvas.restore();
+            }
+        }
+
+        canvas.restore();
+    }
+}
+
+/// Splits the markdown into the blocks of the preview, with the inline formatting dropped.
+fn parse_markdown(source: &str) -> Vec<Block> {
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(source.match_indices('\n').map(|(index, _)| index + 1))
+        .collect();
+    let source_line = |offset: usize| match line_starts.binary_search(&offset) {
+        Ok(line) => line as u64,
+        Err(line) => line as u64 - 1,
+    };
+
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut current: Option<Block> = None;
+    // The next number of the ordered lists, None for bullet lists
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    let mut quote_depth = 0;
+
+    let finish = |current: &mut Option<Block>, blocks: &mut Vec<Block>| {
+        if let Some(mut block) = current.take() {
+            let trimmed = block.text.trim_end().len();
+            block.text.truncate(trimmed);
+            if !block.text.is_empty() || block.kind == BlockKind::Rule {
+                blocks.push(block);
+            }
+        }
+    };
+
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH;
+    for (event, range) in Parser::new_ext(source, options).into_offset_iter() {
+        let line = source_line(range.start);
+        match event {
+            Event::Start(Tag::Heading(level, ..)) => {
+                finish(&mut current, &mut blocks);
+                current = Some(Block {
+                    kind: BlockKind::Heading(level as usize),
+                    text: String::new(),
+                    source_line: line,
+                });
+            }
+            Event::Start(Tag::Paragraph | Tag::TableRow | Tag::TableHead) => {
+                // Paragraphs inside list items continue the item
+                let continues_item = matches!(
+                    &current,
+                    Some(Block {
+                        kind: BlockKind::ListItem { .. },
+                        text,
+                        ..
+                    }) if text.is_empty()
+                );
+                if !continues_item {
+                    finish(&mut current, &mut blocks);
+                    let kind = if quote_depth > 0 {
+                        BlockKind::Quote
+                    } else {
+                        BlockKind::Paragraph
+                    };
+                    current = Some(Block {
+                        kind,
+                        text: String::new(),
+                        source_line: line,
+                    });
+                }
+            }
+            Event::Start(Tag::CodeBlock(_)) => {
+                finish(&mut current, &mut blocks);
+                current = Some(Block {
+                    kind: BlockKind::Code,
+                    text: String::new(),
+                    source_line: line,
+                });
+            }
+            Event::Start(Tag::BlockQuote) => quote_depth += 1,
+            Event::End(Tag::BlockQuote) => quote_depth -= 1,
+            Event::Start(Tag::List(first_number)) => {
+                finish(&mut current, &mut blocks);
+                lists.push(first_number);
+            }
+            Event::End(Tag::List(_)) => {
+                finish(&mut current, &mut blocks);
+                lists.pop();
+            }
+            Event::Start(Tag::Item) => {
+                finish(&mut current, &mut blocks);
+                let marker = match lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}.", *number - 1)
+                    }
+                    _ => "•".to_string(),
+                };
+                current = Some(Block {
+                    kind: BlockKind::ListItem {
+                        marker,
+                        depth: lists.len().saturating_sub(1),
+                    },
+                    text: String::new(),
+                    source_line: line,
+                });
+            }
+            Event::End(
+                Tag::Heading(..)
+                | Tag::Paragraph
+                | Tag::CodeBlock(_)
+                | Tag::Item
+                | Tag::TableRow
+                | Tag::TableHead,
+            ) => finish(&mut current, &mut blocks),
+            Event::End(Tag::TableCell) => {
+                if let Some(block) = &mut current {
+                    block.text.push_str(" │ ");
+                }
+            }
+            Event::Rule => {
+                finish(&mut current, &mut blocks);
+                blocks.push(Block {
+                    kind: BlockKind::Rule,
+                    text: String::new(),
+                    source_line: line,
+                });
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some(block) = &mut current {
+                    block.text.push_str(&text);
+                }
+            }
+            Event::SoftBreak => {
+                if let Some(block) = &mut current {
+                    block.text.push(' ');
+                }
+            }
+            Event::HardBreak => {
+                if let Some(block) = &mut current {
+                    block.text.push('\n');
+                }
+            }
+            _ => {}
+        }
+    }
+    finish(&mut current, &mut blocks);
+    blocks
+}
+
+/// Wraps the text at spaces to lines of at most `width` characters, breaking words longer than a
+/// line. Line breaks in the text are kept.
+fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let mut word: Vec<char> = word.chars().collect();
+            let line_length = line.chars().count();
+            if line_length > 0 && line_length + 1 + word.len() > width {
+                lines.push(std::mem::take(&mut line));
+            }
+            while word.len() > width {
+                let rest = word.split_off(width);
+                lines.push(word.into_iter().collect());
+                word = rest;
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.extend(word);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(kind: BlockKind, text: &str, source_line: u64) -> Block {
+        Block {
+            kind,
+            text: text.to_string(),
+            source_line,
+        }
+    }
+
+    #[test]
+    fn markdown_is_split_into_blocks() {
+        let source = "# Title\n\nSome *emphasized*\ntext.\n\n- one\n- two\n\n> quoted\n\n```\nlet a = 1;\n```\n\n---\n\n1. first";
+        assert_eq!(
+            parse_markdown(source),
+            vec![
+                block(BlockKind::Heading(1), "Title", 0),
+                block(BlockKind::Paragraph, "Some emphasized text.", 2),
+                block(
+                    BlockKind::ListItem {
+                        marker: "•".to_string(),
+                        depth: 0
+                    },
+                    "one",
+                    5
+                ),
+                block(
+                    BlockKind::ListItem {
+                        marker: "•".to_string(),
+                        depth: 0
+                    },
+                    "two",
+                    6
+                ),
+                block(BlockKind::Quote, "quoted", 8),
+                block(BlockKind::Code, "let a = 1;", 10),
+                block(BlockKind::Rule, "", 14),
+                block(
+                    BlockKind::ListItem {
+                        marker: "1.".to_string(),
+                        depth: 0
+                    },
+                    "first",
+                    16
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn text_wraps_at_spaces() {
+        assert_eq!(
+            wrap_text("the quick brown fox", 10),
+            vec!["the quick", "brown fox"]
+        );
+        assert_eq!(
+            wrap_text("abcdefghij klm", 4),
+            vec!["abcd", "efgh", "ij", "klm"]
+        );
+        assert_eq!(wrap_text("one\ntwo", 10), vec!["one", "two"]);
+    }
+}
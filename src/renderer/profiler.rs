@@ -2,9 +2,10 @@ use crate::renderer::animation_utils::lerp;
 use crate::settings::SETTINGS;
 use std::collections::VecDeque;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use crate::{
+    latency_tracker::LATENCY_TRACKER,
     profiling::tracy_zone,
     renderer::{fonts::font_loader::*, RendererSettings},
 };
@@ -18,6 +19,8 @@ pub struct Profiler {
     pub size: Size,
     pub last_draw: Instant,
     pub frametimes: VecDeque<f32>,
+    cpu_time: Option<Duration>,
+    gpu_time: Option<Duration>,
 }
 
 impl Profiler {
@@ -28,17 +31,32 @@ impl Profiler {
         Self {
             font,
             position: Point::new(32.0, 32.0),
-            size: Size::new(200.0, 120.0),
+            size: Size::new(200.0, 136.0),
             last_draw: Instant::now(),
             frametimes: VecDeque::with_capacity(FRAMETIMES_COUNT),
+            cpu_time: None,
+            gpu_time: None,
         }
     }
 
+    pub fn is_visible(&self) -> bool {
+        // Measuring the latency shows the overlay too, that's where the results are
+        SETTINGS.get::<RendererSettings>().profiler || LATENCY_TRACKER.is_enabled()
+    }
+
+    /// Records how long the CPU and the GPU worked on the last frame. The GPU time is missing
+    /// when the driver can't measure it.
+    pub fn record_frame_timing(&mut self, cpu_time: Duration, gpu_time: Option<Duration>) {
+        self.cpu_time = Some(cpu_time);
+        self.gpu_time = gpu_time;
+    }
+
     pub fn draw(&mut self, root_canvas: &mut Canvas, dt: f32) {
         tracy_zone!("profiler_draw");
-        if !SETTINGS.get::<RendererSettings>().profiler {
+        if !self.is_visible() {
             return;
         }
+        let measuring_latency = LATENCY_TRACKER.is_enabled();
 
         root_canvas.save();
         let rect = self.get_rect();
@@ -63,6 +81,37 @@ impl Profiler {
             &paint,
         );
 
+        if let Some(cpu_time) = self.cpu_time {
+            text_position.y += self.font.skia_font.size();
+            let gpu_time = match self.gpu_time {
+                Some(gpu_time) => format!("{:.1}ms", gpu_time.as_secs_f32() * 1000.0),
+                None => "n/a".to_string(),
+            };
+            root_canvas.draw_str(
+                format!(
+                    "cpu {:.1}ms gpu {}",
+                    cpu_time.as_secs_f32() * 1000.0,
+                    gpu_time
+                ),
+                text_position,
+                &self.font.skia_font,
+                &paint,
+            );
+        }
+
+        if measuring_latency {
+            text_position.y += self.font.skia_font.size();
+            let latency = match LATENCY_TRACKER.stats() {
+                Some(stats) => format!(
+                    "key p50 {:.1}ms p99 {:.1}ms",
+                    stats.end_to_end_p50.as_secs_f32() * 1000.0,
+                    stats.end_to_end_p99.as_secs_f32() * 1000.0,
+                ),
+                None => "key: type to measure".to_string(),
+            };
+            root_canvas.draw_str(latency, text_position, &self.font.skia_font, &paint);
+        }
+
         self.frametimes.push_back(dt * 1000.0); // to msecs
         while self.frametimes.len() > FRAMETIMES_COUNT {
             self.frametimes.pop_front();
@@ -1,11 +1,15 @@
-use std::{collections::VecDeque, sync::Arc};
+use std::{
+    collections::{HashMap, VecDeque},
+    ops::Range,
+    sync::Arc,
+};
 
 use skia_safe::{
     canvas::{SaveLayerRec, SrcRectConstraint},
     gpu::SurfaceOrigin,
     image_filters::blur,
-    BlendMode, Budgeted, Canvas, Color, Image, ImageInfo, Paint, Point, Rect, SamplingOptions,
-    Surface, SurfaceProps, SurfacePropsFlags,
+    BlendMode, Budgeted, Canvas, Color, Color4f, Image, ImageInfo, Paint, Point, Rect,
+    SamplingOptions, Surface, SurfaceProps, SurfacePropsFlags,
 };
 
 use crate::{
@@ -13,11 +17,20 @@ use crate::{
     editor::Style,
     profiling::tracy_zone,
     redraw_scheduler::REDRAW_SCHEDULER,
-    renderer::{animation_utils::*, GridRenderer, RendererSettings},
+    renderer::{
+        animation_utils::*,
+        status_line::{is_status_line_row, separator_in},
+        GridRenderer, RendererSettings,
+    },
+    settings::SETTINGS,
+    window::WindowSettings,
 };
 use winit::dpi::PhysicalSize;
 
-use super::opengl::clamp_render_buffer_size;
+use super::opengl::offscreen_surface_scale;
+
+// Kept for scroll animations when `g:neovide_scrollback_texture_size` doesn't limit their size
+const DEFAULT_MAX_SNAPSHOTS: usize = 5;
 
 #[derive(Clone, Debug)]
 pub struct LineFragment {
@@ -26,6 +39,7 @@ pub struct LineFragment {
     pub window_top: u64,
     pub width: u64,
     pub style: Option<Arc<Style>>,
+    pub ligatures: bool,
 }
 
 #[derive(Clone, Debug)]
@@ -49,8 +63,24 @@ pub enum WindowDrawCommand {
     Hide,
     Close,
     Viewport {
+        top_line: f64,
         scroll_delta: f64,
     },
+    Background {
+        color: Option<Color4f>,
+        is_current: bool,
+    },
+    Edges(WindowEdges),
+}
+
+/// The styles of the outermost cells of a window, used to extend their backgrounds into the
+/// window padding.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WindowEdges {
+    pub left: Vec<Option<Arc<Style>>>,
+    pub right: Vec<Option<Arc<Style>>>,
+    pub top: Vec<Option<Arc<Style>>>,
+    pub bottom: Vec<Option<Arc<Style>>>,
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -61,10 +91,17 @@ pub struct WindowPadding {
     pub bottom: u32,
 }
 
-fn build_window_surface(parent_canvas: &mut Canvas, pixel_size: PhysicalSize<u32>) -> Surface {
-    let pixel_size = clamp_render_buffer_size(pixel_size);
-    let mut context = parent_canvas.recording_context().unwrap();
-    let budgeted = Budgeted::Yes;
+/// Creates the surface a window is drawn into, along with the scale it is drawn at. Windows larger
+/// than the GPU supports are drawn at a lower resolution.
+fn build_window_surface(
+    parent_canvas: &mut Canvas,
+    pixel_size: PhysicalSize<u32>,
+) -> (Surface, f32) {
+    let scale = offscreen_surface_scale(pixel_size);
+    let pixel_size = PhysicalSize::new(
+        ((pixel_size.width as f32 * scale).ceil() as u32).max(1),
+        ((pixel_size.height as f32 * scale).ceil() as u32).max(1),
+    );
     let parent_image_info = parent_canvas.image_info();
     let image_info = ImageInfo::new(
         (pixel_size.width as i32, pixel_size.height as i32),
@@ -75,31 +112,38 @@ fn build_window_surface(parent_canvas: &mut Canvas, pixel_size: PhysicalSize<u32
     let surface_origin = SurfaceOrigin::TopLeft;
     // Subpixel layout (should be configurable/obtained from fontconfig).
     let props = SurfaceProps::new(SurfacePropsFlags::default(), skia_safe::PixelGeometry::RGBH);
-    Surface::new_render_target(
-        &mut context,
-        budgeted,
-        &image_info,
-        None,
-        surface_origin,
-        Some(&props),
-        None,
-    )
-    .expect("Could not create surface")
+    // Without a GPU, like when replaying headlessly, the windows are drawn in memory too
+    let surface = match parent_canvas.recording_context() {
+        Some(mut context) => Surface::new_render_target(
+            &mut context,
+            Budgeted::Yes,
+            &image_info,
+            None,
+            surface_origin,
+            Some(&props),
+            None,
+        ),
+        None => Surface::new_raster(&image_info, None, Some(&props)),
+    };
+    let mut surface = surface.expect("Could not create surface");
+    // Everything is drawn in window pixels, the surface may have fewer
+    surface.canvas().scale((scale, scale));
+    (surface, scale)
 }
 
 fn build_window_surface_with_grid_size(
     parent_canvas: &mut Canvas,
     grid_renderer: &GridRenderer,
     grid_size: Dimensions,
-) -> Surface {
-    let mut surface = build_window_surface(
+) -> (Surface, f32) {
+    let (mut surface, scale) = build_window_surface(
         parent_canvas,
         (grid_size * grid_renderer.font_dimensions).into(),
     );
 
     let canvas = surface.canvas();
     canvas.clear(grid_renderer.get_default_background());
-    surface
+    (surface, scale)
 }
 
 pub struct LocatedSnapshot {
@@ -109,6 +153,8 @@ pub struct LocatedSnapshot {
 
 pub struct LocatedSurface {
     surface: Surface,
+    /// The size of the surface relative to the window it shows
+    scale: f32,
     pub vertical_position: f32,
 }
 
@@ -119,14 +165,21 @@ impl LocatedSurface {
         grid_size: Dimensions,
         vertical_position: f32,
     ) -> LocatedSurface {
-        let surface = build_window_surface_with_grid_size(parent_canvas, grid_renderer, grid_size);
+        let (surface, scale) =
+            build_window_surface_with_grid_size(parent_canvas, grid_renderer, grid_size);
 
         LocatedSurface {
             surface,
+            scale,
             vertical_position,
         }
     }
 
+    fn clear(&mut self, grid_renderer: &GridRenderer, grid_size: Dimensions) {
+        (self.surface, self.scale) =
+            build_window_surface_with_grid_size(self.surface.canvas(), grid_renderer, grid_size);
+    }
+
     fn snapshot(&mut self) -> LocatedSnapshot {
         let image = self.surface.image_snapshot();
         LocatedSnapshot {
@@ -155,8 +208,57 @@ pub struct RenderedWindow {
     pub current_scroll: f32,
     scroll_destination: f32,
     scroll_t: f32,
+    // The first buffer line shown, counted from 0
+    top_line: f64,
+
+    // Rows of padding above the text, which keep the cursor line centered at the start of the
+    // buffer in typewriter mode
+    typewriter_start: f32,
+    pub typewriter_offset: f32,
+    typewriter_destination: f32,
+    typewriter_t: f32,
 
     pub padding: WindowPadding,
+
+    // Set from the Normal/NormalNC overrides in 'winhighlight'
+    background: Option<Color>,
+    pub is_current: bool,
+
+    edges: WindowEdges,
+
+    placeholder_lines: PlaceholderLines,
+    // The rows last drawn as a status line, a window bar or the tabline
+    chrome_rows: HashMap<u64, ChromeRow>,
+}
+
+/// The lines with placeholder glyphs for fonts which are still loading, by row and by the columns
+/// they cover, since Neovim redraws parts of rows too.
+type PlaceholderLines = HashMap<u64, HashMap<Range<u64>, Vec<LineFragment>>>;
+
+/// What a row drawn with the highlights of the UI around the text shows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChromeRow {
+    /// A status line or a window bar
+    StatusLine,
+    TabLine,
+}
+
+impl ChromeRow {
+    fn of(line_fragments: &[LineFragment]) -> Option<ChromeRow> {
+        let is_tab_line = line_fragments.iter().any(|fragment| {
+            fragment
+                .style
+                .as_ref()
+                .map_or(false, |style| style.is_tab_line)
+        });
+        if is_status_line_row(line_fragments) {
+            Some(ChromeRow::StatusLine)
+        } else if is_tab_line {
+            Some(ChromeRow::TabLine)
+        } else {
+            None
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -164,6 +266,8 @@ pub struct WindowDrawDetails {
     pub id: u64,
     pub region: Rect,
     pub floating_order: Option<u64>,
+    /// How far the text is drawn below the top of the region, in pixels
+    pub content_offset: f32,
 }
 
 impl RenderedWindow {
@@ -195,7 +299,21 @@ impl RenderedWindow {
             current_scroll: 0.0,
             scroll_destination: 0.0,
             scroll_t: 2.0, // 2.0 is out of the 0.0 to 1.0 range and stops animation.
+            top_line: 0.0,
+
+            typewriter_start: 0.0,
+            typewriter_offset: 0.0,
+            typewriter_destination: 0.0,
+            typewriter_t: 2.0,
+
             padding,
+
+            background: None,
+            is_current: true,
+
+            edges: WindowEdges::default(),
+            placeholder_lines: HashMap::new(),
+            chrome_rows: HashMap::new(),
         }
     }
 
@@ -210,6 +328,19 @@ impl RenderedWindow {
         Rect::from_point_and_size(current_pixel_position, image_size)
     }
 
+    pub fn top_line(&self) -> f64 {
+        self.top_line
+    }
+
+    /// Moves the text down by `rows`, animated like scrolling.
+    pub fn set_typewriter_destination(&mut self, rows: f32) {
+        if (self.typewriter_destination - rows).abs() > f32::EPSILON {
+            self.typewriter_start = self.typewriter_offset;
+            self.typewriter_destination = rows;
+            self.typewriter_t = 0.0;
+        }
+    }
+
     pub fn update(&mut self, settings: &RendererSettings, dt: f32) -> bool {
         let mut animating = false;
 
@@ -249,6 +380,24 @@ impl RenderedWindow {
             );
         }
 
+        {
+            if 1.0 - self.typewriter_t < std::f32::EPSILON {
+                // We are at destination, move t out of 0-1 range to stop the animation.
+                self.typewriter_t = 2.0;
+            } else {
+                animating = true;
+                self.typewriter_t =
+                    (self.typewriter_t + dt / settings.scroll_animation_length).min(1.0);
+            }
+
+            self.typewriter_offset = ease(
+                ease_out_expo,
+                self.typewriter_start,
+                self.typewriter_destination,
+                self.typewriter_t,
+            );
+        }
+
         animating
     }
 
@@ -269,9 +418,13 @@ impl RenderedWindow {
         root_canvas.save();
         root_canvas.clip_rect(pixel_region, None, Some(false));
 
-        if self.floating_order.is_none() {
-            root_canvas.clear(default_background);
-        }
+        let default_background = if self.floating_order.is_none() {
+            let background = self.window_background(default_background);
+            root_canvas.clear(background);
+            background
+        } else {
+            default_background
+        };
 
         if self.floating_order.is_some() && settings.floating_blur {
             if let Some(blur) = blur(
@@ -310,11 +463,13 @@ impl RenderedWindow {
         paint.set_color(Color::from_argb(255, 255, 255, 255));
 
         let font_height = font_dimensions.height;
+        let content_offset = self.typewriter_offset * font_height as f32;
 
         // Draw scrolling snapshots.
         for snapshot in self.snapshots.iter_mut().rev() {
-            let scroll_offset =
-                (snapshot.vertical_position - self.current_scroll) * font_height as f32;
+            let scroll_offset = (snapshot.vertical_position - self.current_scroll)
+                * font_height as f32
+                + content_offset;
             let image = &mut snapshot.image;
             root_canvas.draw_image_rect(
                 image,
@@ -325,8 +480,9 @@ impl RenderedWindow {
         }
 
         // Draw current surface.
-        let scroll_offset =
-            (self.current_surface.vertical_position - self.current_scroll) * font_height as f32;
+        let scroll_offset = (self.current_surface.vertical_position - self.current_scroll)
+            * font_height as f32
+            + content_offset;
         let snapshot = self.current_surface.surface.image_snapshot();
         root_canvas.draw_image_rect(
             snapshot,
@@ -347,9 +503,38 @@ impl RenderedWindow {
             id: self.id,
             region: pixel_region,
             floating_order: self.floating_order,
+            content_offset,
         }
     }
 
+    /// The number of surfaces and scroll snapshots the window keeps, and the bytes of their pixels.
+    pub fn surface_memory(&self) -> (usize, usize) {
+        let surface = &self.current_surface.surface;
+        let surface_bytes = surface.width() as usize
+            * surface.height() as usize
+            * surface.image_info().bytes_per_pixel();
+        (
+            1 + self.snapshots.len(),
+            surface_bytes + snapshot_bytes(&self.snapshots),
+        )
+    }
+
+    /// Drops the snapshots kept for scroll animations, which only cuts a running one short.
+    pub fn drop_snapshots(&mut self) {
+        self.snapshots.clear();
+    }
+
+    /// Creates the surface again on a new graphics context, the contents have to be redrawn.
+    pub fn recreate_surface(&mut self, parent_canvas: &mut Canvas, grid_renderer: &GridRenderer) {
+        self.snapshots.clear();
+        self.current_surface = LocatedSurface::new(
+            parent_canvas,
+            grid_renderer,
+            self.grid_size,
+            self.current_surface.vertical_position,
+        );
+    }
+
     pub fn handle_window_draw_command(
         &mut self,
         grid_renderer: &mut GridRenderer,
@@ -392,19 +577,26 @@ impl RenderedWindow {
                 }
 
                 if self.grid_size != new_grid_size {
-                    let mut new_surface = build_window_surface_with_grid_size(
+                    let (mut new_surface, new_scale) = build_window_surface_with_grid_size(
                         self.current_surface.surface.canvas(),
                         grid_renderer,
                         new_grid_size,
                     );
+                    // The old contents are in the pixels of the old surface
+                    let new_canvas = new_surface.canvas();
+                    new_canvas.save();
+                    let old_scale = self.current_surface.scale;
+                    new_canvas.scale((1.0 / old_scale, 1.0 / old_scale));
                     self.current_surface.surface.draw(
-                        new_surface.canvas(),
+                        new_canvas,
                         (0.0, 0.0),
                         SamplingOptions::default(),
                         None,
                     );
+                    new_canvas.restore();
 
                     self.current_surface.surface = new_surface;
+                    self.current_surface.scale = new_scale;
                     self.grid_size = new_grid_size;
                 }
 
@@ -420,7 +612,13 @@ impl RenderedWindow {
             }
             WindowDrawCommand::DrawLine(line_fragments) => {
                 tracy_zone!("draw_line_cmd", 0);
+                let window_background = self
+                    .background
+                    .unwrap_or_else(|| grid_renderer.get_default_background());
+                let is_fancy_status_line = SETTINGS.get::<RendererSettings>().fancy_statusline
+                    && is_status_line_row(&line_fragments);
                 let canvas = self.current_surface.surface.canvas();
+                grid_renderer.shaper.take_placeholder_shaped();
 
                 canvas.save();
                 for line_fragment in line_fragments.iter() {
@@ -438,21 +636,51 @@ impl RenderedWindow {
                         *width,
                         style,
                         self.floating_order.is_some(),
+                        window_background,
+                        is_fancy_status_line,
                     );
                 }
 
-                for line_fragment in line_fragments.into_iter() {
+                for line_fragment in line_fragments.iter() {
                     let LineFragment {
                         text,
                         window_left,
                         window_top,
                         width,
                         style,
+                        ligatures,
                     } = line_fragment;
-                    let grid_position = (window_left, window_top);
-                    grid_renderer.draw_foreground(canvas, text, grid_position, width, &style);
+                    let grid_position = (*window_left, *window_top);
+                    let separator = separator_in(text).filter(|_| is_fancy_status_line);
+                    if let Some((offset, separator)) = separator {
+                        let grid_position = (window_left + offset, *window_top);
+                        grid_renderer.draw_separator(canvas, separator, grid_position, style);
+                        continue;
+                    }
+                    grid_renderer.draw_foreground(
+                        canvas,
+                        text.clone(),
+                        grid_position,
+                        *width,
+                        style,
+                        *ligatures,
+                    );
                 }
                 canvas.restore();
+
+                if let Some(row) = line_fragments.first().map(|fragment| fragment.window_top) {
+                    match ChromeRow::of(&line_fragments) {
+                        Some(chrome_row) => self.chrome_rows.insert(row, chrome_row),
+                        None => self.chrome_rows.remove(&row),
+                    };
+                    let has_placeholders = grid_renderer.shaper.take_placeholder_shaped();
+                    update_placeholder_lines(
+                        &mut self.placeholder_lines,
+                        row,
+                        line_fragments,
+                        has_placeholders,
+                    );
+                }
             }
             WindowDrawCommand::Scroll {
                 top,
@@ -480,6 +708,15 @@ impl RenderedWindow {
                     -rows as f32 * font_height as f32,
                 ));
 
+                // The snapshot has the pixels of the surface, which may be scaled down
+                let scale = self.current_surface.scale;
+                let source_region = Rect::from_ltrb(
+                    scrolled_region.left * scale,
+                    scrolled_region.top * scale,
+                    scrolled_region.right * scale,
+                    scrolled_region.bottom * scale,
+                );
+
                 let snapshot = self.current_surface.surface.image_snapshot();
                 let canvas = self.current_surface.surface.canvas();
 
@@ -488,20 +725,36 @@ impl RenderedWindow {
                 canvas.clip_rect(scrolled_region, None, Some(false));
                 canvas.draw_image_rect(
                     snapshot,
-                    Some((&scrolled_region, SrcRectConstraint::Fast)),
+                    Some((&source_region, SrcRectConstraint::Fast)),
                     translated_region,
                     &grid_renderer.paint,
                 );
 
                 canvas.restore();
+
+                if !self.placeholder_lines.is_empty() {
+                    self.placeholder_lines = scroll_lines(
+                        std::mem::take(&mut self.placeholder_lines),
+                        top,
+                        bottom,
+                        rows,
+                    );
+                }
+                if !self.chrome_rows.is_empty() {
+                    self.chrome_rows = scroll_rows(
+                        std::mem::take(&mut self.chrome_rows),
+                        top,
+                        bottom,
+                        rows,
+                        |_, _| {},
+                    );
+                }
             }
             WindowDrawCommand::Clear => {
                 tracy_zone!("clear_cmd", 0);
-                self.current_surface.surface = build_window_surface_with_grid_size(
-                    self.current_surface.surface.canvas(),
-                    grid_renderer,
-                    self.grid_size,
-                );
+                self.current_surface.clear(grid_renderer, self.grid_size);
+                self.placeholder_lines.clear();
+                self.chrome_rows.clear();
 
                 self.snapshots.clear();
             }
@@ -518,15 +771,19 @@ impl RenderedWindow {
                 tracy_zone!("hide_cmd", 0);
                 self.hidden = true;
             }
-            WindowDrawCommand::Viewport { scroll_delta, .. } => {
+            WindowDrawCommand::Viewport {
+                top_line,
+                scroll_delta,
+            } => {
                 tracy_zone!("viewport_cmd", 0);
+                self.top_line = top_line;
                 if scroll_delta.abs() > f64::EPSILON {
                     let new_snapshot = self.current_surface.snapshot();
                     self.snapshots.push_back(new_snapshot);
-
-                    if self.snapshots.len() > 5 {
-                        self.snapshots.pop_front();
-                    }
+                    evict_snapshots(
+                        &mut self.snapshots,
+                        SETTINGS.get::<RendererSettings>().scrollback_texture_size,
+                    );
 
                     self.current_surface.vertical_position += scroll_delta as f32;
 
@@ -536,7 +793,368 @@ impl RenderedWindow {
                     self.scroll_t = 0.0;
                 }
             }
+            WindowDrawCommand::Background { color, is_current } => {
+                self.background = color.map(|color| color.to_color());
+                self.is_current = is_current;
+            }
+            WindowDrawCommand::Edges(edges) => {
+                self.edges = edges;
+            }
             _ => {}
         };
     }
+
+    /// Whether the row shows text, rather than the tabline, a status line, a window bar or the
+    /// command line, which only grids with `has_command_line` show.
+    pub fn is_text_row(&self, row: u64, has_command_line: bool) -> bool {
+        is_text_row(
+            &self.chrome_rows,
+            self.grid_size.height,
+            row,
+            has_command_line,
+        )
+    }
+
+    /// Draws the lines with placeholder glyphs again, after fallback fonts were loaded.
+    pub fn redraw_placeholder_lines(&mut self, grid_renderer: &mut GridRenderer) {
+        let lines = std::mem::take(&mut self.placeholder_lines);
+        for line_fragments in lines.into_values().flat_map(HashMap::into_values) {
+            self.handle_window_draw_command(
+                grid_renderer,
+                WindowDrawCommand::DrawLine(line_fragments),
+            );
+        }
+    }
+
+    /// Extends the backgrounds of the cells at the edges of the window, like `CursorLine` or
+    /// `ColorColumn`, through the padding when the window touches it. `content_region` is the
+    /// region inside of the padding.
+    pub fn draw_padding_backgrounds(
+        &self,
+        root_canvas: &mut Canvas,
+        grid_renderer: &GridRenderer,
+        content_region: Rect,
+    ) {
+        let region = self.pixel_region(grid_renderer.font_dimensions);
+        let font_width = grid_renderer.font_dimensions.width as f32;
+        let font_height = grid_renderer.font_dimensions.height as f32;
+        let window_background = self
+            .background
+            .unwrap_or_else(|| grid_renderer.get_default_background())
+            .with_a(255);
+
+        let mut paint = Paint::default();
+        paint.set_anti_alias(false);
+        let mut fill = |rect: Rect, style: &Option<Arc<Style>>| {
+            if let Some(style) = style {
+                let color = style
+                    .background(&grid_renderer.default_style.colors)
+                    .to_color();
+                // The padding already has the background of the window
+                if color.with_a(255) != window_background {
+                    paint.set_color(color);
+                    root_canvas.draw_rect(rect, &paint);
+                }
+            }
+        };
+
+        let touches = |a: f32, b: f32| (a - b).abs() < 0.5;
+        let padding = self.padding;
+
+        if padding.left > 0 && touches(region.left, content_region.left) {
+            for (row, style) in self.edges.left.iter().enumerate() {
+                let top = region.top + row as f32 * font_height;
+                let left = content_region.left - padding.left as f32;
+                fill(
+                    Rect::from_ltrb(left, top, region.left, top + font_height),
+                    style,
+                );
+            }
+        }
+        if padding.right > 0 && touches(region.right, content_region.right) {
+            for (row, style) in self.edges.right.iter().enumerate() {
+                let top = region.top + row as f32 * font_height;
+                let right = content_region.right + padding.right as f32;
+                fill(
+                    Rect::from_ltrb(region.right, top, right, top + font_height),
+                    style,
+                );
+            }
+        }
+        if padding.top > 0 && touches(region.top, content_region.top) {
+            for (column, style) in self.edges.top.iter().enumerate() {
+                let left = region.left + column as f32 * font_width;
+                let top = content_region.top - padding.top as f32;
+                fill(
+                    Rect::from_ltrb(left, top, left + font_width, region.top),
+                    style,
+                );
+            }
+        }
+        if padding.bottom > 0 && touches(region.bottom, content_region.bottom) {
+            for (column, style) in self.edges.bottom.iter().enumerate() {
+                let left = region.left + column as f32 * font_width;
+                let bottom = content_region.bottom + padding.bottom as f32;
+                fill(
+                    Rect::from_ltrb(left, region.bottom, left + font_width, bottom),
+                    style,
+                );
+            }
+        }
+    }
+
+    /// The color the grid is filled with, keeping the alpha of the default background unless the
+    /// window isn't the current one.
+    fn window_background(&self, default_background: Color) -> Color {
+        let background = self
+            .background
+            .map(|background| background.with_a(default_background.a()))
+            .unwrap_or(default_background);
+
+        // Without an inactive transparency, the windows follow the transparency of the state
+        let transparency_inactive = SETTINGS.get::<WindowSettings>().transparency_inactive;
+        if self.is_current || transparency_inactive < 0.0 {
+            background
+        } else {
+            background.with_a((255.0 * transparency_inactive) as u8)
+        }
+    }
+}
+
+/// Moves the lines between the rows `top` and `bottom` up by `rows`, like the scroll of that
+/// region, dropping the ones moved out of it.
+fn scroll_lines(lines: PlaceholderLines, top: u64, bottom: u64, rows: i64) -> PlaceholderLines {
+    scroll_rows(lines, top, bottom, rows, |row_lines, new_row| {
+        for fragment in row_lines.values_mut().flatten() {
+            fragment.window_top = new_row;
+        }
+    })
+}
+
+/// Keeps the line drawn at `row` when it has placeholder glyphs, replacing the lines drawn before
+/// in the columns it covers.
+fn update_placeholder_lines(
+    lines: &mut PlaceholderLines,
+    row: u64,
+    line_fragments: Vec<LineFragment>,
+    has_placeholders: bool,
+) {
+    let columns = match (line_fragments.first(), line_fragments.last()) {
+        (Some(first), Some(last)) => first.window_left..last.window_left + last.width,
+        _ => return,
+    };
+    let row_lines = lines.entry(row).or_default();
+    row_lines.retain(|drawn, _| drawn.end <= columns.start || drawn.start >= columns.end);
+    if has_placeholders {
+        row_lines.insert(columns, line_fragments);
+    }
+    if row_lines.is_empty() {
+        lines.remove(&row);
+    }
+}
+
+/// Moves what is kept by row like the scroll of the region between `top` and `bottom` by `rows`,
+/// calling `moved` with the new row of each moved value.
+fn scroll_rows<T>(
+    values: HashMap<u64, T>,
+    top: u64,
+    bottom: u64,
+    rows: i64,
+    mut moved: impl FnMut(&mut T, u64),
+) -> HashMap<u64, T> {
+    values
+        .into_iter()
+        .filter_map(|(row, mut value)| {
+            if row < top || row >= bottom {
+                return Some((row, value));
+            }
+            let new_row = row as i64 - rows;
+            if new_row < top as i64 || new_row >= bottom as i64 {
+                return None;
+            }
+            moved(&mut value, new_row as u64);
+            Some((new_row as u64, value))
+        })
+        .collect()
+}
+
+/// Whether the row of a grid with `chrome_rows` shows text. When the grid has the command line,
+/// which the root grid has without multigrid, it's below the last status line, or the last row
+/// when there is none.
+fn is_text_row(
+    chrome_rows: &HashMap<u64, ChromeRow>,
+    grid_height: u64,
+    row: u64,
+    has_command_line: bool,
+) -> bool {
+    if chrome_rows.contains_key(&row) {
+        return false;
+    }
+    if !has_command_line {
+        return true;
+    }
+    let command_line_top = chrome_rows
+        .iter()
+        .filter(|(_, chrome_row)| **chrome_row == ChromeRow::StatusLine)
+        .map(|(status_line_row, _)| status_line_row + 1)
+        .max()
+        .unwrap_or_else(|| grid_height.saturating_sub(1));
+    row < command_line_top
+}
+
+fn snapshot_bytes(snapshots: &VecDeque<LocatedSnapshot>) -> usize {
+    snapshots
+        .iter()
+        .map(|snapshot| {
+            let image = &snapshot.image;
+            image.width() as usize * image.height() as usize * image.image_info().bytes_per_pixel()
+        })
+        .sum()
+}
+
+// The oldest snapshots are dropped first, the newest one is kept so that scrolling animates.
+// `size` is the limit in megabytes, without one only a few snapshots are kept.
+fn evict_snapshots(snapshots: &mut VecDeque<LocatedSnapshot>, size: u32) {
+    if size == 0 {
+        while snapshots.len() > DEFAULT_MAX_SNAPSHOTS {
+            snapshots.pop_front();
+        }
+        return;
+    }
+    let limit = size as usize * 1024 * 1024;
+    while snapshots.len() > 1 && snapshot_bytes(snapshots) > limit {
+        snapshots.pop_front();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line_at(row: u64, left: u64) -> Vec<LineFragment> {
+        vec![LineFragment {
+            text: "字".to_owned(),
+            window_left: left,
+            window_top: row,
+            width: 2,
+            style: None,
+            ligatures: false,
+        }]
+    }
+
+    fn line(row: u64) -> Vec<LineFragment> {
+        line_at(row, 0)
+    }
+
+    fn placeholder_lines(rows: &[u64]) -> PlaceholderLines {
+        let mut lines = PlaceholderLines::new();
+        for row in rows {
+            update_placeholder_lines(&mut lines, *row, line(*row), true);
+        }
+        lines
+    }
+
+    #[test]
+    fn placeholder_lines_move_with_the_scroll() {
+        let lines = placeholder_lines(&[1, 5, 12]);
+        let scrolled = scroll_lines(lines, 2, 10, 3);
+
+        let mut rows: Vec<u64> = scrolled.keys().copied().collect();
+        rows.sort();
+        // Row 5 moves up to 2, row 1 is outside of the region and row 12 below it
+        assert_eq!(rows, vec![1, 2, 12]);
+        assert_eq!(scrolled[&2][&(0..2)][0].window_top, 2);
+
+        let scrolled = scroll_lines(scrolled, 0, 10, -9);
+        let mut rows: Vec<u64> = scrolled.keys().copied().collect();
+        rows.sort();
+        assert_eq!(rows, vec![12]);
+    }
+
+    #[test]
+    fn placeholder_lines_are_kept_for_parts_of_rows() {
+        let mut lines = placeholder_lines(&[3]);
+        update_placeholder_lines(&mut lines, 3, line_at(3, 10), true);
+        let mut columns: Vec<Range<u64>> = lines[&3].keys().cloned().collect();
+        columns.sort_by_key(|columns| columns.start);
+        assert_eq!(columns, vec![0..2, 10..12]);
+
+        // Drawn again without placeholders
+        update_placeholder_lines(&mut lines, 3, line_at(3, 1), false);
+        assert_eq!(lines[&3].keys().cloned().collect::<Vec<_>>(), vec![10..12]);
+        update_placeholder_lines(&mut lines, 3, line_at(3, 10), false);
+        assert!(lines.is_empty());
+    }
+
+    // A megabyte of pixels
+    fn snapshot(vertical_position: f32) -> LocatedSnapshot {
+        let mut surface = Surface::new_raster_n32_premul((512, 512)).unwrap();
+        LocatedSnapshot {
+            image: surface.image_snapshot(),
+            vertical_position,
+        }
+    }
+
+    fn positions(snapshots: &VecDeque<LocatedSnapshot>) -> Vec<f32> {
+        snapshots
+            .iter()
+            .map(|snapshot| snapshot.vertical_position)
+            .collect()
+    }
+
+    #[test]
+    fn the_oldest_snapshots_are_evicted_over_the_byte_limit() {
+        let mut snapshots: VecDeque<LocatedSnapshot> = (0..4).map(|i| snapshot(i as f32)).collect();
+        assert_eq!(snapshot_bytes(&snapshots), 4 * 1024 * 1024);
+
+        evict_snapshots(&mut snapshots, 2);
+        assert_eq!(positions(&snapshots), vec![2.0, 3.0]);
+        assert_eq!(snapshot_bytes(&snapshots), 2 * 1024 * 1024);
+    }
+
+    #[test]
+    fn the_newest_snapshot_is_kept_when_it_is_over_the_limit() {
+        let mut snapshots: VecDeque<LocatedSnapshot> = (0..3).map(|i| snapshot(i as f32)).collect();
+        let mut big_surface = Surface::new_raster_n32_premul((1024, 1024)).unwrap();
+        snapshots.push_back(LocatedSnapshot {
+            image: big_surface.image_snapshot(),
+            vertical_position: 3.0,
+        });
+
+        evict_snapshots(&mut snapshots, 1);
+        assert_eq!(positions(&snapshots), vec![3.0]);
+    }
+
+    #[test]
+    fn without_a_limit_a_few_snapshots_are_kept() {
+        let mut snapshots: VecDeque<LocatedSnapshot> = (0..8).map(|i| snapshot(i as f32)).collect();
+
+        evict_snapshots(&mut snapshots, 0);
+        assert_eq!(positions(&snapshots), vec![3.0, 4.0, 5.0, 6.0, 7.0]);
+    }
+
+    #[test]
+    fn root_grid_without_multigrid_has_text_between_the_tabline_and_the_command_line() {
+        let chrome_rows = HashMap::from([
+            (0, ChromeRow::TabLine),
+            (4, ChromeRow::StatusLine),
+            (8, ChromeRow::StatusLine),
+        ]);
+
+        let text_rows: Vec<u64> = (0..10)
+            .filter(|&row| is_text_row(&chrome_rows, 10, row, true))
+            .collect();
+        // Row 9 is the command line, below the last status line
+        assert_eq!(text_rows, vec![1, 2, 3, 5, 6, 7]);
+    }
+
+    #[test]
+    fn root_grid_without_status_lines_has_the_command_line_in_the_last_row() {
+        let chrome_rows = HashMap::new();
+
+        assert!(is_text_row(&chrome_rows, 10, 8, true));
+        assert!(!is_text_row(&chrome_rows, 10, 9, true));
+        // Other grids don't have the command line
+        assert!(is_text_row(&chrome_rows, 10, 9, false));
+    }
 }
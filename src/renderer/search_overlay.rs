@@ -0,0 +1,112 @@
+use std::time::Instant;
+
+use skia_safe::{Canvas, Color, Paint, RRect, Rect};
+
+use crate::{
+    bridge::SearchCount,
+    renderer::{animation_utils::fade_out_opacity, GridRenderer},
+};
+
+const MARGIN: f32 = 8.0;
+// The count stays for a moment after the last search update before fading out
+const FADE_DELAY: f32 = 2.0;
+const FADE_OUT_LENGTH: f32 = 0.5;
+
+/// Shows the search pattern and which of the matches the cursor is on, above the command line.
+pub struct SearchOverlay {
+    count: Option<SearchCount>,
+    updated_at: Instant,
+}
+
+impl SearchOverlay {
+    pub fn new() -> Self {
+        Self {
+            count: None,
+            updated_at: Instant::now(),
+        }
+    }
+
+    pub fn set_count(&mut self, count: Option<SearchCount>) {
+        self.count = count;
+        self.updated_at = Instant::now();
+    }
+
+    /// Draws the overlay in the bottom right of `region`, the pixel region of the editor, one line
+    /// above the command line.
+    pub fn draw(&self, canvas: &mut Canvas, grid_renderer: &mut GridRenderer, region: Rect) {
+        let count = match &self.count {
+            Some(count) => count,
+            None => return,
+        };
+
+        let opacity = fade_out_opacity(self.updated_at, FADE_DELAY, FADE_OUT_LENGTH);
+        if opacity <= 0.0 {
+            return;
+        }
+
+        let text = search_count_text(count);
+        let font_dimensions = grid_renderer.font_dimensions;
+        let width = (text.chars().count() as u64 + 2) * font_dimensions.width;
+        let height = font_dimensions.height as f32;
+        let overlay_region = Rect::from_xywh(
+            region.right - MARGIN - width as f32,
+            region.bottom - 2.0 * height - MARGIN,
+            width as f32,
+            height,
+        );
+
+        let colors = &grid_renderer.default_style.colors;
+        let background = colors
+            .background
+            .map_or(Color::BLACK, |color| color.to_color());
+        let foreground = colors
+            .foreground
+            .map_or(Color::WHITE, |color| color.to_color());
+
+        let mut paint = Paint::default();
+        paint.set_anti_alias(true);
+        paint.set_color(background.with_a((230.0 * opacity) as u8));
+        let radius = height / 4.0;
+        canvas.draw_rrect(RRect::new_rect_xy(overlay_region, radius, radius), &paint);
+
+        paint.set_color(foreground.with_a((255.0 * opacity) as u8));
+        let y_adjustment = grid_renderer.shaper.y_adjustment() as f32;
+        let x = overlay_region.left + font_dimensions.width as f32;
+        for blob in grid_renderer
+            .shaper
+            .shape_cached(text, false, false, true)
+            .iter()
+        {
+            canvas.draw_text_blob(blob, (x, overlay_region.top + y_adjustment), &paint);
+        }
+    }
+}
+
+pub fn search_count_text(count: &SearchCount) -> String {
+    let total = if count.incomplete {
+        format!(">{}", count.total)
+    } else {
+        count.total.to_string()
+    };
+    format!("/{} [{}/{}]", count.pattern, count.current, total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search_count_text_shows_the_position() {
+        let mut count = SearchCount {
+            pattern: "foo".to_string(),
+            current: 3,
+            total: 17,
+            incomplete: false,
+        };
+        assert_eq!(search_count_text(&count), "/foo [3/17]");
+
+        count.total = 999;
+        count.incomplete = true;
+        assert_eq!(search_count_text(&count), "/foo [3/>999]");
+    }
+}
@@ -0,0 +1,680 @@
+use log::error;
+use rmpv::Value;
+use skia_safe::{Canvas, Color, Paint, RRect, Rect};
+use winit::{
+    dpi::PhysicalPosition,
+    event::KeyEvent,
+    keyboard::{Key, ModifiersState},
+};
+
+use crate::{
+    bridge::{ParallelCommand, UiCommand},
+    event_aggregator::EVENT_AGGREGATOR,
+    i18n::{tr, tr_with},
+    keybindings::{chord, chords_of, rebind, GuiAction},
+    redraw_scheduler::REDRAW_SCHEDULER,
+    renderer::{
+        overlay::{contains, draw_panel, Colors, Layout, Modal, MARGIN},
+        GridRenderer,
+    },
+    settings::{config_path, Config, KeyBindings, SettingValue, SETTINGS},
+};
+
+const SLIDER_COLUMNS: u64 = 20;
+
+/// Settings with a known range are shown with a slider, as (min, max, step).
+const RANGES: &[(&str, f64, f64, f64)] = &[
+    ("transparency", 0.0, 1.0, 0.05),
+    ("transparency_inactive", -1.0, 1.0, 0.05),
+    ("transparency_unfocused", -1.0, 1.0, 0.05),
+    ("transparency_fullscreen", -1.0, 1.0, 0.05),
+    ("transparency_transition", 0.0, 2.0, 0.05),
+    ("floating_opacity", 0.0, 1.0, 0.05),
+    ("unfocused_dim", 0.0, 1.0, 0.05),
+    ("inactive_window_dim", 0.0, 1.0, 0.05),
+    ("inactive_window_desaturate", 0.0, 1.0, 0.05),
+    ("scale_factor", 0.5, 3.0, 0.1),
+    ("scroll_animation_length", 0.0, 2.0, 0.05),
+    ("position_animation_length", 0.0, 2.0, 0.05),
+    ("theme_transition_length", 0.0, 2.0, 0.05),
+    ("highlight_fade_opacity", 0.0, 1.0, 0.05),
+    ("cursor_animation_length", 0.0, 1.0, 0.01),
+    ("cursor_trail_size", 0.0, 1.0, 0.05),
+    ("cursor_window_jump_animation_length", 0.0, 1.0, 0.01),
+    ("cursor_color_blend", 0.0, 1.0, 0.05),
+    ("cursor_jump_flash_distance", 0.0, 100.0, 1.0),
+    ("refresh_rate", 1.0, 240.0, 1.0),
+    ("refresh_rate_idle", 1.0, 240.0, 1.0),
+    ("zen_font_scale", 0.5, 3.0, 0.1),
+    ("zen_dim_inactive", 0.0, 1.0, 0.05),
+    ("preview_width", 0.1, 0.9, 0.05),
+    ("terminal_height", 0.1, 0.9, 0.05),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+struct Entry {
+    setting: SettingValue,
+    original: Value,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Row {
+    Category(usize),
+    Setting(usize),
+    Binding(usize),
+}
+
+/// Lists every `neovide_*` setting grouped by category, opened with `:NeovideSettings`. Changes
+/// are applied right away through Neovim, and can be saved to the config file. The GUI
+/// keybindings are listed last, and rebound by pressing the new chord.
+#[derive(Default)]
+pub struct SettingsPanel {
+    open: bool,
+    entries: Vec<Entry>,
+    categories: Vec<String>,
+    rows: Vec<Row>,
+    keybindings: KeyBindings,
+    saved_keybindings: KeyBindings,
+    // Settings come first, then the keybindings
+    selected: usize,
+    scroll: usize,
+    recording: bool,
+    status: Option<String>,
+    modifiers: ModifiersState,
+    mouse_position: PhysicalPosition<f64>,
+    // Where the rows and their controls were drawn, to find what was clicked
+    row_regions: Vec<(usize, Rect, Rect)>,
+}
+
+impl SettingsPanel {
+    pub fn open(&mut self) {
+        self.set_values(SETTINGS.values());
+        self.keybindings = SETTINGS.get::<KeyBindings>();
+        self.saved_keybindings = self.keybindings.clone();
+        self.open = true;
+        self.recording = false;
+        self.status = None;
+        REDRAW_SCHEDULER.queue_next_frame();
+    }
+
+    pub fn close(&mut self) {
+        self.open = false;
+        self.row_regions.clear();
+        REDRAW_SCHEDULER.queue_next_frame();
+    }
+
+    fn set_values(&mut self, values: Vec<SettingValue>) {
+        self.entries = values
+            .into_iter()
+            .map(|setting| Entry {
+                original: setting.value.clone(),
+                setting,
+            })
+            .collect();
+        self.categories.clear();
+        self.rows.clear();
+        for (index, entry) in self.entries.iter().enumerate() {
+            if self.categories.last() != Some(&entry.setting.category) {
+                self.categories.push(entry.setting.category.clone());
+                self.rows.push(Row::Category(self.categories.len() - 1));
+            }
+            self.rows.push(Row::Setting(index));
+        }
+        self.categories.push(tr("settings-panel.keybindings"));
+        self.rows.push(Row::Category(self.categories.len() - 1));
+        self.rows
+            .extend((0..GuiAction::ALL.len()).map(Row::Binding));
+        self.selected = 0;
+        self.scroll = 0;
+    }
+
+    fn handle_key(&mut self, key: &Key) {
+        match key {
+            Key::Escape => self.close(),
+            Key::ArrowUp => self.move_selection(-1),
+            Key::ArrowDown => self.move_selection(1),
+            Key::ArrowLeft => self.change_selected(-1),
+            Key::ArrowRight => self.change_selected(1),
+            Key::Enter | Key::Space => self.activate_selected(),
+            Key::Character(text) => match text.as_str() {
+                "q" => self.close(),
+                "k" => self.move_selection(-1),
+                "j" => self.move_selection(1),
+                "h" => self.change_selected(-1),
+                "l" => self.change_selected(1),
+                "s" => self.save(),
+                _ => {}
+            },
+            _ => {}
+        }
+        REDRAW_SCHEDULER.queue_next_frame();
+    }
+
+    fn selected_row(&self) -> Row {
+        if self.selected < self.entries.len() {
+            Row::Setting(self.selected)
+        } else {
+            Row::Binding(self.selected - self.entries.len())
+        }
+    }
+
+    fn move_selection(&mut self, direction: i32) {
+        let last = (self.entries.len() + GuiAction::ALL.len()) as i32 - 1;
+        self.selected = (self.selected as i32 + direction).clamp(0, last) as usize;
+    }
+
+    /// Toggles the selected switch, or waits for the new chord of the selected keybinding.
+    fn activate_selected(&mut self) {
+        match self.selected_row() {
+            Row::Binding(_) => self.recording = true,
+            _ => {
+                if let Some(&Value::Boolean(value)) = self.selected_value() {
+                    self.set_value(self.selected, Value::Boolean(!value));
+                }
+            }
+        }
+    }
+
+    fn record_chord(&mut self, key_event: &KeyEvent) {
+        let action = match self.selected_row() {
+            Row::Binding(index) => GuiAction::ALL[index],
+            _ => return,
+        };
+        match key_event.logical_key {
+            // Wait for the actual key of the chord
+            Key::Shift | Key::Control | Key::Alt | Key::Super => return,
+            Key::Escape => {}
+            Key::Backspace => self.set_chord(action, None),
+            _ => {
+                if let Some(chord) = chord(key_event, self.modifiers) {
+                    self.set_chord(action, Some(chord));
+                }
+            }
+        }
+        self.recording = false;
+        REDRAW_SCHEDULER.queue_next_frame();
+    }
+
+    /// Keybindings are used right away, without going through Neovim.
+    fn set_chord(&mut self, action: GuiAction, chord: Option<String>) {
+        rebind(&mut self.keybindings, action, chord);
+        SETTINGS.set::<KeyBindings>(&self.keybindings);
+        self.status = None;
+    }
+
+    fn change_selected(&mut self, steps: i32) {
+        let entry = match self.entries.get(self.selected) {
+            Some(entry) => entry,
+            None => return,
+        };
+        let changed = changed_value(&entry.setting.name, &entry.setting.value, steps);
+        if let Some(value) = changed {
+            self.set_value(self.selected, value);
+        }
+    }
+
+    fn selected_value(&self) -> Option<&Value> {
+        self.entries
+            .get(self.selected)
+            .map(|entry| &entry.setting.value)
+    }
+
+    /// Shows the value right away and sends it to Neovim, which reports it back to the settings.
+    fn set_value(&mut self, index: usize, value: Value) {
+        let entry = &mut self.entries[index];
+        entry.setting.value = value.clone();
+        EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::SetSetting {
+            name: entry.setting.name.clone(),
+            value,
+        }));
+        self.status = None;
+    }
+
+    fn changed_values(&self) -> Vec<(String, Value)> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.setting.value != entry.original)
+            .map(|entry| (entry.setting.name.clone(), entry.setting.value.clone()))
+            .collect()
+    }
+
+    fn save(&mut self) {
+        let changed = self.changed_values();
+        let keybindings_changed = self.keybindings != self.saved_keybindings;
+        if changed.is_empty() && !keybindings_changed {
+            self.status = Some(tr("settings-panel.nothing-changed"));
+            return;
+        }
+        let result = if changed.is_empty() {
+            Ok(())
+        } else {
+            Config::save_settings(&changed)
+        };
+        let result = result.and_then(|()| {
+            if keybindings_changed {
+                Config::save_keybindings(&self.keybindings)
+            } else {
+                Ok(())
+            }
+        });
+        match result {
+            Ok(()) => {
+                for entry in &mut self.entries {
+                    entry.original = entry.setting.value.clone();
+                }
+                self.saved_keybindings = self.keybindings.clone();
+                self.status = Some(tr_with(
+                    "settings-panel.saved",
+                    &[("path", config_path().to_string_lossy().into_owned())],
+                ));
+            }
+            Err(message) => {
+                error!("Could not save the settings: {}", message);
+                self.status = Some(message);
+            }
+        }
+    }
+
+    pub fn draw(&mut self, canvas: &mut Canvas, grid_renderer: &mut GridRenderer, region: Rect) {
+        self.row_regions.clear();
+        if !self.open {
+            return;
+        }
+
+        let layout = Layout::new(grid_renderer);
+        let Layout {
+            char_width,
+            line_height,
+            row_height,
+            text_offset,
+        } = layout;
+        let panel = layout.panel(region, region.height() - 2.0 * MARGIN);
+        if panel.width() <= 0.0 || panel.height() <= 3.0 * row_height {
+            return;
+        }
+
+        let colors = Colors::new(grid_renderer);
+        let Colors {
+            background,
+            foreground,
+            dimmed,
+            accent,
+        } = colors;
+
+        let mut paint = Paint::default();
+        paint.set_anti_alias(true);
+
+        // Dim the editor behind the panel
+        paint.set_color(Color::BLACK.with_a(100));
+        canvas.draw_rect(region, &paint);
+        draw_panel(canvas, panel, char_width, &colors);
+
+        let left = panel.left + 2.0 * char_width;
+        let right = panel.right - 2.0 * char_width;
+        let mut draw_text =
+            |canvas: &mut Canvas, text: &str, x: f32, top: f32, color: Color, bold: bool| {
+                let mut paint = Paint::default();
+                paint.set_anti_alias(true).set_color(color);
+                for blob in grid_renderer
+                    .shaper
+                    .shape_cached(text.to_string(), bold, false, false)
+                    .iter()
+                {
+                    canvas.draw_text_blob(blob, (x, top + text_offset), &paint);
+                }
+            };
+
+        draw_text(
+            canvas,
+            &tr("settings-panel.title"),
+            left,
+            panel.top,
+            foreground,
+            true,
+        );
+        let footer = if self.recording {
+            tr("settings-panel.recording-footer")
+        } else {
+            self.status
+                .clone()
+                .unwrap_or_else(|| tr("settings-panel.footer"))
+        };
+        draw_text(
+            canvas,
+            &footer,
+            left,
+            panel.bottom - row_height,
+            dimmed,
+            false,
+        );
+
+        // Keep the selected setting visible
+        let visible_rows = ((panel.height() - 3.0 * row_height) / row_height).floor() as usize;
+        let selected_row = self
+            .rows
+            .iter()
+            .position(|row| *row == self.selected_row())
+            .unwrap_or(0);
+        if selected_row < self.scroll {
+            // Show the category header above the first setting too
+            self.scroll = selected_row.saturating_sub(1);
+        } else if selected_row >= self.scroll + visible_rows {
+            self.scroll = selected_row + 1 - visible_rows;
+        }
+
+        let slider_width = SLIDER_COLUMNS as f32 * char_width;
+        let mut top = panel.top + 1.5 * row_height;
+        for row in self.rows.iter().skip(self.scroll).take(visible_rows) {
+            match *row {
+                Row::Category(index) => {
+                    draw_text(canvas, &self.categories[index], left, top, accent, true);
+                }
+                Row::Setting(index) => {
+                    let entry = &self.entries[index];
+                    let row_region = Rect::new(panel.left, top, panel.right, top + row_height);
+                    if index == self.selected {
+                        paint.set_color(foreground.with_a(30));
+                        canvas.draw_rect(row_region, &paint);
+                    }
+                    let modified = if entry.setting.value != entry.original {
+                        "*"
+                    } else {
+                        " "
+                    };
+                    let name = format!("{}neovide_{}", modified, entry.setting.name);
+                    draw_text(canvas, &name, left, top, foreground, false);
+
+                    let value = &entry.setting.value;
+                    let value_text = value_text(value);
+                    let value_width = value_text.chars().count() as f32 * char_width;
+                    let control = match (value, range_of(&entry.setting.name)) {
+                        (Value::Boolean(enabled), _) => {
+                            let toggle = Rect::from_xywh(
+                                right - 3.0 * char_width,
+                                top + (row_height - line_height) / 2.0,
+                                3.0 * char_width,
+                                line_height,
+                            );
+                            let radius = toggle.height() / 2.0;
+                            paint.set_color(if *enabled { accent } else { dimmed });
+                            canvas.draw_rrect(RRect::new_rect_xy(toggle, radius, radius), &paint);
+                            let knob_x = if *enabled {
+                                toggle.right - radius
+                            } else {
+                                toggle.left + radius
+                            };
+                            paint.set_color(background);
+                            canvas.draw_circle((knob_x, toggle.center_y()), radius * 0.7, &paint);
+                            toggle
+                        }
+                        (value, Some((min, max, _))) if is_number(value) => {
+                            let number = as_number(value).unwrap_or_default();
+                            let slider = Rect::from_xywh(
+                                right - slider_width,
+                                top + row_height / 2.0 - 2.0,
+                                slider_width,
+                                4.0,
+                            );
+                            paint.set_color(dimmed);
+                            canvas.draw_rect(slider, &paint);
+                            let fraction = ((number - min) / (max - min)).clamp(0.0, 1.0) as f32;
+                            let knob_x = slider.left + slider.width() * fraction;
+                            paint.set_color(accent);
+                            canvas.draw_rect(
+                                Rect::new(slider.left, slider.top, knob_x, slider.bottom),
+                                &paint,
+                            );
+                            canvas.draw_circle(
+                                (knob_x, slider.center_y()),
+                                line_height / 4.0,
+                                &paint,
+                            );
+                            draw_text(
+                                canvas,
+                                &value_text,
+                                slider.left - value_width - char_width,
+                                top,
+                                foreground,
+                                false,
+                            );
+                            Rect::new(slider.left, top, slider.right, top + row_height)
+                        }
+                        (value, None) if is_number(value) => {
+                            let text = format!("< {value_text} >");
+                            let width = text.chars().count() as f32 * char_width;
+                            draw_text(canvas, &text, right - width, top, foreground, false);
+                            Rect::new(right - width, top, right, top + row_height)
+                        }
+                        _ => {
+                            // Strings and lists are set with :let, the panel only shows them
+                            draw_text(canvas, &value_text, right - value_width, top, dimmed, false);
+                            Rect::new(right - value_width, top, right, top + row_height)
+                        }
+                    };
+                    self.row_regions.push((index, row_region, control));
+                }
+                Row::Binding(index) => {
+                    let action = GuiAction::ALL[index];
+                    let selection = self.entries.len() + index;
+                    let row_region = Rect::new(panel.left, top, panel.right, top + row_height);
+                    if selection == self.selected {
+                        paint.set_color(foreground.with_a(30));
+                        canvas.draw_rect(row_region, &paint);
+                    }
+                    let chords = chords_of(&self.keybindings, action);
+                    let modified = if chords != chords_of(&self.saved_keybindings, action) {
+                        "*"
+                    } else {
+                        " "
+                    };
+                    let name = format!("{}{}", modified, action.name());
+                    draw_text(canvas, &name, left, top, foreground, false);
+
+                    let (chords_text, color) = if self.recording && selection == self.selected {
+                        ("...".to_string(), accent)
+                    } else if chords.is_empty() {
+                        (tr("settings-panel.unbound"), dimmed)
+                    } else {
+                        (chords.join(" "), foreground)
+                    };
+                    let width = chords_text.chars().count() as f32 * char_width;
+                    draw_text(canvas, &chords_text, right - width, top, color, false);
+                    let control = Rect::new(right - width, top, right, top + row_height);
+                    self.row_regions.push((selection, row_region, control));
+                }
+            }
+            top += row_height;
+        }
+    }
+}
+
+impl Modal for SettingsPanel {
+    fn is_open(&self) -> bool {
+        self.open
+    }
+
+    fn key_pressed(&mut self, key_event: &KeyEvent) {
+        if self.recording {
+            self.record_chord(key_event);
+        } else {
+            self.handle_key(&key_event.logical_key);
+        }
+    }
+
+    fn cursor_moved(&mut self, position: PhysicalPosition<f64>) {
+        self.mouse_position = position;
+    }
+
+    fn modifiers_changed(&mut self, modifiers: ModifiersState) {
+        self.modifiers = modifiers;
+    }
+
+    fn clicked(&mut self) {
+        let position = self.mouse_position;
+        let clicked = self
+            .row_regions
+            .iter()
+            .find(|(_, row, _)| contains(row, position))
+            .copied();
+        if let Some((index, _, control)) = clicked {
+            self.selected = index;
+            if contains(&control, position) {
+                match self.selected_row() {
+                    Row::Setting(index) => match range_of(&self.entries[index].setting.name) {
+                        Some((min, max, _)) if is_number(&self.entries[index].setting.value) => {
+                            let x = position.x as f32;
+                            let fraction = ((x - control.left) / control.width()) as f64;
+                            let value = number_value(min + (max - min) * fraction, min, max);
+                            self.set_value(index, value);
+                        }
+                        _ => self.activate_selected(),
+                    },
+                    _ => self.activate_selected(),
+                }
+            }
+            REDRAW_SCHEDULER.queue_next_frame();
+        }
+    }
+}
+
+fn range_of(name: &str) -> Option<(f64, f64, f64)> {
+    RANGES
+        .iter()
+        .find(|(range_name, ..)| *range_name == name)
+        .map(|(_, min, max, step)| (*min, *max, *step))
+}
+
+fn is_number(value: &Value) -> bool {
+    as_number(value).is_some()
+}
+
+fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::F32(value) => Some(*value as f64),
+        Value::F64(value) => Some(*value),
+        Value::Integer(value) => value.as_f64(),
+        _ => None,
+    }
+}
+
+// Rounded to the step, so that repeated steps don't collect floating point errors
+fn number_value(number: f64, min: f64, max: f64) -> Value {
+    Value::from((number.clamp(min, max) * 1000.0).round() / 1000.0)
+}
+
+/// The value after changing it by `steps`, keeping integers integers and inside the range.
+fn changed_value(name: &str, value: &Value, steps: i32) -> Option<Value> {
+    match value {
+        Value::Boolean(_) => Some(Value::Boolean(steps > 0)),
+        Value::Integer(integer) => {
+            let step = range_of(name).map_or(1, |(_, _, step)| step.max(1.0) as i64);
+            let changed = integer.as_i64()? + step * steps as i64;
+            let changed = match range_of(name) {
+                Some((min, max, _)) => changed.clamp(min as i64, max as i64),
+                // Unsigned settings can't go below zero
+                None if integer.is_u64() => changed.max(0),
+                None => changed,
+            };
+            Some(Value::from(changed))
+        }
+        Value::F32(_) | Value::F64(_) => {
+            let number = as_number(value)?;
+            let (min, max, step) = range_of(name).unwrap_or((f64::MIN, f64::MAX, 0.1));
+            Some(number_value(number + step * steps as f64, min, max))
+        }
+        _ => None,
+    }
+}
+
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::Boolean(value) => if *value { "on" } else { "off" }.to_string(),
+        Value::String(text) => format!("\"{}\"", text.as_str().unwrap_or_default()),
+        Value::F32(_) | Value::F64(_) => format!("{:.2}", as_number(value).unwrap_or_default()),
+        Value::Nil => "-".to_string(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setting(category: &str, name: &str, value: Value) -> SettingValue {
+        SettingValue {
+            category: category.to_string(),
+            name: name.to_string(),
+            value,
+        }
+    }
+
+    #[test]
+    fn settings_are_grouped_by_category() {
+        let mut panel = SettingsPanel::default();
+        panel.set_values(vec![
+            setting("Cursor", "cursor_trail_size", Value::from(0.7)),
+            setting("Window", "fullscreen", Value::from(false)),
+            setting("Window", "transparency", Value::from(1.0)),
+        ]);
+        assert_eq!(
+            panel.categories,
+            vec![
+                "Cursor".to_string(),
+                "Window".to_string(),
+                tr("settings-panel.keybindings")
+            ]
+        );
+        assert_eq!(
+            panel.rows[..7],
+            [
+                Row::Category(0),
+                Row::Setting(0),
+                Row::Category(1),
+                Row::Setting(1),
+                Row::Setting(2),
+                Row::Category(2),
+                Row::Binding(0),
+            ]
+        );
+        assert_eq!(panel.rows.len(), 7 + GuiAction::ALL.len() - 1);
+    }
+
+    #[test]
+    fn numbers_change_by_their_step_inside_the_range() {
+        assert_eq!(
+            changed_value("transparency", &Value::from(0.9), 1),
+            Some(Value::from(0.95))
+        );
+        assert_eq!(
+            changed_value("transparency", &Value::from(1.0), 1),
+            Some(Value::from(1.0))
+        );
+        assert_eq!(
+            changed_value("refresh_rate", &Value::from(60u64), -2),
+            Some(Value::from(58))
+        );
+        assert_eq!(
+            changed_value("padding_top", &Value::from(0u64), -1),
+            Some(Value::from(0))
+        );
+        assert_eq!(
+            changed_value("mode_badge", &Value::from(false), 1),
+            Some(Value::from(true))
+        );
+        assert_eq!(changed_value("theme", &Value::from("auto"), 1), None);
+    }
+
+    #[test]
+    fn only_changed_values_are_saved() {
+        let mut panel = SettingsPanel::default();
+        panel.set_values(vec![
+            setting("Window", "fullscreen", Value::from(false)),
+            setting("Window", "transparency", Value::from(1.0)),
+        ]);
+        panel.entries[1].setting.value = Value::from(0.9);
+        assert_eq!(
+            panel.changed_values(),
+            vec![("transparency".to_string(), Value::from(0.9))]
+        );
+    }
+}
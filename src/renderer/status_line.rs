@@ -0,0 +1,149 @@
+use skia_safe::{Color4f, Path, Rect};
+
+use crate::renderer::LineFragment;
+
+// How much lighter the top and darker the bottom of the gradient is
+const GRADIENT_SHADE: f32 = 0.12;
+
+/// A Powerline separator, drawn as a shape so that it fills the whole row regardless of the font.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Separator {
+    pub shape: SeparatorShape,
+    /// Whether it points to the right
+    pub points_right: bool,
+    /// Whether it is filled, or only an outline between segments of the same color
+    pub filled: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SeparatorShape {
+    Triangle,
+    Round,
+}
+
+impl Separator {
+    fn from_char(character: char) -> Option<Separator> {
+        let (shape, offset) = match character {
+            '\u{e0b0}'..='\u{e0b3}' => (SeparatorShape::Triangle, character as u32 - 0xe0b0),
+            '\u{e0b4}'..='\u{e0b7}' => (SeparatorShape::Round, character as u32 - 0xe0b4),
+            _ => return None,
+        };
+        Some(Separator {
+            shape,
+            points_right: offset < 2,
+            filled: offset % 2 == 0,
+        })
+    }
+
+    /// The outline of the separator in `cell`.
+    pub fn path(&self, cell: Rect) -> Path {
+        let (base, tip) = if self.points_right {
+            (cell.left, cell.right)
+        } else {
+            (cell.right, cell.left)
+        };
+        let mut path = Path::default();
+        path.move_to((base, cell.top));
+        match self.shape {
+            SeparatorShape::Triangle => {
+                path.line_to((tip, cell.center_y()));
+            }
+            SeparatorShape::Round => {
+                // A half ellipse, with the control points making it bulge to the tip
+                let control = base + (tip - base) * 4.0 / 3.0;
+                path.cubic_to(
+                    (control, cell.top),
+                    (control, cell.bottom),
+                    (base, cell.bottom),
+                );
+                return path;
+            }
+        }
+        path.line_to((base, cell.bottom));
+        path
+    }
+}
+
+/// Finds the separator in a fragment made of one separator and spaces, along with the cell it is
+/// in.
+pub fn separator_in(text: &str) -> Option<(u64, Separator)> {
+    let trimmed = text.trim_matches(' ');
+    let mut characters = trimmed.chars();
+    let separator = Separator::from_char(characters.next()?)?;
+    if characters.next().is_some() {
+        return None;
+    }
+    let offset = text.len() - text.trim_start_matches(' ').len();
+    Some((offset as u64, separator))
+}
+
+/// Whether a row is part of a status line or window bar, which is the case when any of it uses
+/// their highlights. Plugins color the segments with their own highlights, but keep the builtin
+/// ones for the space between them.
+pub fn is_status_line_row(fragments: &[LineFragment]) -> bool {
+    fragments.iter().any(|fragment| {
+        fragment
+            .style
+            .as_ref()
+            .map_or(false, |style| style.is_status_line)
+    })
+}
+
+/// Mixes a color with white for a positive `amount`, or with black for a negative one.
+fn shade(color: Color4f, amount: f32) -> Color4f {
+    let target = if amount > 0.0 { 1.0 } else { 0.0 };
+    let amount = amount.abs();
+    let mix = |component: f32| component + (target - component) * amount;
+    Color4f::new(mix(color.r), mix(color.g), mix(color.b), color.a)
+}
+
+/// The colors at the top and bottom of the background gradient of a status line segment.
+pub fn gradient_colors(background: Color4f) -> (Color4f, Color4f) {
+    (
+        shade(background, GRADIENT_SHADE),
+        shade(background, -GRADIENT_SHADE),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn powerline_separators_are_found() {
+        assert_eq!(
+            separator_in("\u{e0b0}"),
+            Some((
+                0,
+                Separator {
+                    shape: SeparatorShape::Triangle,
+                    points_right: true,
+                    filled: true,
+                }
+            ))
+        );
+        assert_eq!(
+            separator_in("  \u{e0b7} "),
+            Some((
+                2,
+                Separator {
+                    shape: SeparatorShape::Round,
+                    points_right: false,
+                    filled: false,
+                }
+            ))
+        );
+        assert_eq!(separator_in("main \u{e0b0}"), None);
+        assert_eq!(separator_in("  "), None);
+    }
+
+    #[test]
+    fn gradients_keep_the_alpha() {
+        let (top, bottom) = gradient_colors(Color4f::new(0.5, 0.0, 1.0, 0.5));
+        assert!(top.r > 0.5 && top.g > 0.0);
+        assert_eq!(top.b, 1.0);
+        assert!(bottom.r < 0.5 && bottom.b < 1.0);
+        assert_eq!(bottom.g, 0.0);
+        assert_eq!((top.a, bottom.a), (0.5, 0.5));
+    }
+}
@@ -0,0 +1,516 @@
+use std::{iter, sync::Arc};
+
+use skia_safe::{Canvas, Point, Rect};
+use unicode_segmentation::UnicodeSegmentation;
+use winit::{
+    dpi::PhysicalPosition,
+    event::{ElementState, Event, MouseButton, WindowEvent},
+};
+
+use crate::{
+    bridge::{ParallelCommand, TabContents, TabLineTab, UiCommand},
+    editor::Style,
+    event_aggregator::EVENT_AGGREGATOR,
+    redraw_scheduler::REDRAW_SCHEDULER,
+    renderer::GridRenderer,
+    settings::SETTINGS,
+    window::WindowSettings,
+};
+
+// Longer titles are cut off with an ellipsis
+const MAX_TITLE_WIDTH: usize = 24;
+// The codicons of the bundled Nerd Font
+const CLOSE_ICON: &str = "\u{ea76}";
+const MODIFIED_ICON: &str = "\u{ea71}";
+
+/// The highlights of the tabline, from the TabLine groups of Neovim.
+#[derive(Clone, Debug, Default)]
+pub struct TabLineStyles {
+    pub normal: Option<Arc<Style>>,
+    pub selected: Option<Arc<Style>>,
+    pub fill: Option<Arc<Style>>,
+}
+
+/// A tab as it's drawn, with the contents a plugin set for it or the name Neovim gave it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct DisplayedTab {
+    tab: u64,
+    title: String,
+    icon: String,
+    modified: bool,
+}
+
+impl DisplayedTab {
+    fn from_neovim(tab: &TabLineTab) -> Self {
+        DisplayedTab {
+            tab: tab.tab,
+            title: tab.name.clone(),
+            icon: String::new(),
+            modified: false,
+        }
+    }
+
+    // The icon and the title, with a space around them
+    fn label(&self) -> String {
+        let title = truncated(&self.title, MAX_TITLE_WIDTH);
+        if self.icon.is_empty() {
+            format!(" {title} ")
+        } else {
+            format!(" {} {title} ", self.icon)
+        }
+    }
+
+    // The label is followed by the close button or the modified dot, and another space
+    fn width(&self) -> u64 {
+        self.label().graphemes(true).count() as u64 + 2
+    }
+}
+
+fn truncated(text: &str, width: usize) -> String {
+    if text.graphemes(true).count() <= width {
+        return text.to_string();
+    }
+    text.graphemes(true)
+        .take(width - 1)
+        .chain(iter::once("…"))
+        .collect()
+}
+
+/// The tabs in the order a plugin set, followed by the ones it left out in the order of Neovim.
+/// Tabs which were closed in the meantime are skipped.
+fn displayed_tabs(tabs: &[TabLineTab], contents: Option<&[TabContents]>) -> Vec<DisplayedTab> {
+    let contents = match contents {
+        Some(contents) => contents,
+        None => return tabs.iter().map(DisplayedTab::from_neovim).collect(),
+    };
+
+    let mut displayed: Vec<DisplayedTab> = Vec::with_capacity(tabs.len());
+    for content in contents {
+        if displayed.iter().any(|shown| shown.tab == content.tab) {
+            continue;
+        }
+        if let Some(tab) = tabs.iter().find(|tab| tab.tab == content.tab) {
+            displayed.push(DisplayedTab {
+                tab: tab.tab,
+                title: content.title.clone().unwrap_or_else(|| tab.name.clone()),
+                icon: content.icon.clone(),
+                modified: content.modified,
+            });
+        }
+    }
+    for tab in tabs {
+        if !displayed.iter().any(|shown| shown.tab == tab.tab) {
+            displayed.push(DisplayedTab::from_neovim(tab));
+        }
+    }
+    displayed
+}
+
+/// The index and the first column of the tabs which fit in `width` columns. When they don't all
+/// fit, the first ones are left out until the current tab does.
+fn layout(tabs: &[DisplayedTab], current: usize, width: u64) -> Vec<(usize, u64)> {
+    let widths: Vec<u64> = tabs.iter().map(DisplayedTab::width).collect();
+    let mut first = 0;
+    while first < current && widths[first..=current].iter().sum::<u64>() > width {
+        first += 1;
+    }
+
+    let mut column = 0;
+    (first..tabs.len())
+        .map_while(|index| {
+            (column < width).then(|| {
+                let start = column;
+                column += widths[index];
+                (index, start)
+            })
+        })
+        .collect()
+}
+
+// The close button shows on the current and the hovered tab, and the dot on modified ones
+fn marker(is_current: bool, is_hovered: bool, modified: bool) -> &'static str {
+    if is_hovered {
+        CLOSE_ICON
+    } else if modified {
+        MODIFIED_ICON
+    } else if is_current {
+        CLOSE_ICON
+    } else {
+        " "
+    }
+}
+
+// A drawn tab, by its handle, first column and width in cells
+#[derive(Clone, Copy, Debug)]
+struct DrawnTab {
+    tab: u64,
+    column: u64,
+    width: u64,
+}
+
+// What the mouse is over
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Target {
+    Tab(u64),
+    CloseButton(u64),
+}
+
+impl Target {
+    fn tab(self) -> u64 {
+        match self {
+            Target::Tab(tab) | Target::CloseButton(tab) => tab,
+        }
+    }
+}
+
+/// The tabline Neovim hands over while `ext_tabline` is enabled, drawn above the grid. Plugins
+/// can change the titles, icons, modified dots and the order of the tabs with
+/// `neovide.set_tabline`. Clicking a tab switches to it, dragging moves it, and the close button
+/// or a middle click closes it.
+pub struct TabLine {
+    tabs: Vec<TabLineTab>,
+    current: u64,
+    styles: TabLineStyles,
+    contents: Option<Vec<TabContents>>,
+    show_tabline: u64,
+    region: Rect,
+    cell_width: f32,
+    drawn_tabs: Vec<DrawnTab>,
+    mouse_position: PhysicalPosition<f64>,
+    hovered: Option<Target>,
+    pressed: Option<(MouseButton, Target)>,
+}
+
+impl Default for TabLine {
+    fn default() -> Self {
+        TabLine {
+            tabs: Vec::new(),
+            current: 0,
+            styles: TabLineStyles::default(),
+            contents: None,
+            // The default of Neovim, only shown with two tabs or more
+            show_tabline: 1,
+            region: Rect::default(),
+            cell_width: 1.0,
+            drawn_tabs: Vec::new(),
+            mouse_position: PhysicalPosition::default(),
+            hovered: None,
+            pressed: None,
+        }
+    }
+}
+
+impl TabLine {
+    pub fn update(&mut self, current: u64, tabs: Vec<TabLineTab>, styles: TabLineStyles) {
+        self.current = current;
+        self.tabs = tabs;
+        self.styles = styles;
+    }
+
+    pub fn set_contents(&mut self, contents: Option<Vec<TabContents>>) {
+        self.contents = contents;
+    }
+
+    pub fn set_show_tabline(&mut self, show_tabline: u64) {
+        self.show_tabline = show_tabline;
+    }
+
+    /// Follows `showtabline`, so that it's hidden with a single tab by default, and is hidden in
+    /// zen mode.
+    pub fn is_visible(&self) -> bool {
+        let settings = SETTINGS.get::<WindowSettings>();
+        settings.ext_tabline
+            && !settings.zen_mode
+            && match self.show_tabline {
+                0 => false,
+                1 => self.tabs.len() > 1,
+                _ => true,
+            }
+    }
+
+    fn target_at_mouse(&self) -> Option<Target> {
+        if !self.contains_mouse() {
+            return None;
+        }
+        let x = self.mouse_position.x as f32;
+        let column = ((x - self.region.left) / self.cell_width) as u64;
+        self.drawn_tabs
+            .iter()
+            .find(|drawn| (drawn.column..drawn.column + drawn.width).contains(&column))
+            .map(|drawn| {
+                if column == drawn.column + drawn.width - 2 {
+                    Target::CloseButton(drawn.tab)
+                } else {
+                    Target::Tab(drawn.tab)
+                }
+            })
+    }
+
+    fn contains_mouse(&self) -> bool {
+        let (x, y) = (self.mouse_position.x as f32, self.mouse_position.y as f32);
+        x >= self.region.left
+            && x < self.region.right
+            && y >= self.region.top
+            && y < self.region.bottom
+    }
+
+    /// Handles the mouse over the tabline. Returns true when the event was used and shouldn't
+    /// reach Neovim.
+    pub fn handle_event(&mut self, event: &Event<()>) -> bool {
+        let window_event = match event {
+            Event::WindowEvent { event, .. } => event,
+            _ => return false,
+        };
+        match window_event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.mouse_position = *position;
+                let hovered = self.target_at_mouse();
+                if hovered != self.hovered {
+                    self.hovered = hovered;
+                    REDRAW_SCHEDULER.queue_next_frame();
+                }
+                self.pressed.is_some() || self.contains_mouse()
+            }
+            WindowEvent::CursorLeft { .. } => {
+                if self.hovered.take().is_some() {
+                    REDRAW_SCHEDULER.queue_next_frame();
+                }
+                false
+            }
+            WindowEvent::MouseInput {
+                button,
+                state: ElementState::Pressed,
+                ..
+            } if self.contains_mouse() => {
+                let target = self.target_at_mouse();
+                if let (MouseButton::Left, Some(Target::Tab(tab))) = (button, target) {
+                    EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::SelectTab(tab)));
+                }
+                self.pressed = target.map(|target| (*button, target));
+                true
+            }
+            WindowEvent::MouseInput {
+                button,
+                state: ElementState::Released,
+                ..
+            } => {
+                let pressed = match self.pressed.take() {
+                    Some(pressed) if pressed.0 == *button => pressed,
+                    Some(pressed) => {
+                        self.pressed = Some(pressed);
+                        return true;
+                    }
+                    None => return self.contains_mouse(),
+                };
+                let released = self.target_at_mouse();
+                let command = match (pressed, released) {
+                    ((MouseButton::Left, Target::CloseButton(tab)), Some(released))
+                        if released == Target::CloseButton(tab) =>
+                    {
+                        Some(ParallelCommand::CloseTab(tab))
+                    }
+                    ((MouseButton::Middle, pressed), Some(released))
+                        if released.tab() == pressed.tab() =>
+                    {
+                        Some(ParallelCommand::CloseTab(pressed.tab()))
+                    }
+                    ((MouseButton::Left, Target::Tab(tab)), Some(released))
+                        if released.tab() != tab =>
+                    {
+                        Some(ParallelCommand::MoveTab {
+                            tab,
+                            target: released.tab(),
+                        })
+                    }
+                    _ => None,
+                };
+                if let Some(command) = command {
+                    EVENT_AGGREGATOR.send(UiCommand::Parallel(command));
+                }
+                true
+            }
+            WindowEvent::MouseInput { .. } | WindowEvent::MouseWheel { .. } => {
+                self.contains_mouse()
+            }
+            _ => false,
+        }
+    }
+
+    /// Draws the tabs into `region`, which is empty while the tabline is hidden.
+    pub fn draw(&mut self, canvas: &mut Canvas, grid_renderer: &mut GridRenderer, region: Rect) {
+        self.region = region;
+        self.drawn_tabs.clear();
+        if region.height() <= 0.0 {
+            return;
+        }
+
+        let font_dimensions = grid_renderer.font_dimensions;
+        self.cell_width = font_dimensions.width as f32;
+        let width = (region.width() / self.cell_width).ceil() as u64;
+        let tabs = displayed_tabs(&self.tabs, self.contents.as_deref());
+        let current = tabs
+            .iter()
+            .position(|tab| tab.tab == self.current)
+            .unwrap_or(0);
+        let hovered = self.hovered.map(Target::tab);
+
+        canvas.save();
+        canvas.clip_rect(region, None, Some(false));
+        canvas.translate(Point::new(region.left, region.top));
+
+        let window_background = grid_renderer.get_default_background();
+        grid_renderer.draw_background(
+            canvas,
+            (0, 0),
+            width,
+            &self.styles.fill,
+            true,
+            window_background,
+            false,
+        );
+
+        for (index, column) in layout(&tabs, current, width) {
+            let tab = &tabs[index];
+            let style = if index == current {
+                &self.styles.selected
+            } else {
+                &self.styles.normal
+            };
+            let tab_width = tab.width();
+            let marker = marker(index == current, hovered == Some(tab.tab), tab.modified);
+            let text = format!("{}{} ", tab.label(), marker);
+            grid_renderer.draw_background(
+                canvas,
+                (column, 0),
+                tab_width,
+                style,
+                true,
+                window_background,
+                false,
+            );
+            grid_renderer.draw_foreground(canvas, text, (column, 0), tab_width, style, false);
+            self.drawn_tabs.push(DrawnTab {
+                tab: tab.tab,
+                column,
+                width: tab_width,
+            });
+        }
+
+        canvas.restore();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serial_test::serial;
+
+    use super::*;
+    use crate::cmd_line::CmdLineSettings;
+
+    fn neovim_tab(tab: u64, name: &str) -> TabLineTab {
+        TabLineTab {
+            tab,
+            name: name.to_string(),
+        }
+    }
+
+    fn tab_contents(tab: u64, title: Option<&str>, icon: &str, modified: bool) -> TabContents {
+        TabContents {
+            tab,
+            title: title.map(String::from),
+            icon: icon.to_string(),
+            modified,
+        }
+    }
+
+    fn titles(tabs: &[DisplayedTab]) -> Vec<&str> {
+        tabs.iter().map(|tab| tab.title.as_str()).collect()
+    }
+
+    #[test]
+    fn shows_the_tabs_of_neovim_without_contents() {
+        let tabs = [neovim_tab(1, "main.rs"), neovim_tab(2, "lib.rs")];
+        assert_eq!(titles(&displayed_tabs(&tabs, None)), ["main.rs", "lib.rs"]);
+    }
+
+    #[test]
+    fn puts_the_tabs_in_the_order_of_the_contents() {
+        let tabs = [
+            neovim_tab(1, "main.rs"),
+            neovim_tab(2, "lib.rs"),
+            neovim_tab(3, "mod.rs"),
+        ];
+        let contents = [
+            tab_contents(3, Some("Renderer"), "\u{ea7b}", true),
+            tab_contents(1, None, "", false),
+            tab_contents(7, Some("Closed"), "", false),
+        ];
+        let displayed = displayed_tabs(&tabs, Some(&contents));
+        assert_eq!(titles(&displayed), ["Renderer", "main.rs", "lib.rs"]);
+        assert_eq!(displayed[0].icon, "\u{ea7b}");
+        assert!(displayed[0].modified);
+    }
+
+    #[test]
+    fn shows_tabs_listed_twice_once() {
+        let tabs = [neovim_tab(1, "main.rs"), neovim_tab(2, "lib.rs")];
+        let contents = [
+            tab_contents(2, None, "", false),
+            tab_contents(2, None, "", false),
+        ];
+        assert_eq!(
+            titles(&displayed_tabs(&tabs, Some(&contents))),
+            ["lib.rs", "main.rs"]
+        );
+    }
+
+    #[test]
+    fn cuts_off_long_titles() {
+        let tab = DisplayedTab {
+            tab: 1,
+            title: "a".repeat(30),
+            icon: "x".to_string(),
+            modified: false,
+        };
+        assert_eq!(tab.label(), format!(" x {}… ", "a".repeat(23)));
+        assert_eq!(tab.width(), 30);
+    }
+
+    #[test]
+    fn scrolls_to_the_current_tab() {
+        // Every tab is " tab " and the marker with a space, 7 columns
+        let tabs: Vec<DisplayedTab> = (0..5)
+            .map(|tab| DisplayedTab::from_neovim(&neovim_tab(tab, "tab")))
+            .collect();
+        assert_eq!(layout(&tabs, 0, 20), [(0, 0), (1, 7), (2, 14)]);
+        assert_eq!(layout(&tabs, 4, 20), [(3, 0), (4, 7)]);
+        assert!(layout(&[], 0, 20).is_empty());
+    }
+
+    #[test]
+    fn shows_the_close_button_and_modified_dot() {
+        assert_eq!(marker(true, false, false), CLOSE_ICON);
+        assert_eq!(marker(true, false, true), MODIFIED_ICON);
+        assert_eq!(marker(false, true, true), CLOSE_ICON);
+        assert_eq!(marker(false, false, false), " ");
+    }
+
+    #[test]
+    #[serial]
+    fn is_hidden_in_zen_mode() {
+        SETTINGS.set(&CmdLineSettings::default());
+        let mut tab_line = TabLine::default();
+        tab_line.set_show_tabline(2);
+        SETTINGS.set(&WindowSettings {
+            ext_tabline: true,
+            ..Default::default()
+        });
+        assert!(tab_line.is_visible());
+        SETTINGS.set(&WindowSettings {
+            ext_tabline: true,
+            zen_mode: true,
+            ..Default::default()
+        });
+        assert!(!tab_line.is_visible());
+    }
+}
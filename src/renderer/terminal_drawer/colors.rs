@@ -0,0 +1,62 @@
+use skia_safe::Color;
+
+// The 16 colors of xterm
+const ANSI_COLORS: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// The color of the 256 color palette: the ANSI colors, a 6x6x6 cube, and 24 grays.
+pub fn indexed_color(index: u8) -> Color {
+    let (red, green, blue) = match index {
+        0..=15 => ANSI_COLORS[index as usize],
+        16..=231 => {
+            let index = index - 16;
+            let level = |value: u8| if value == 0 { 0 } else { 55 + value * 40 };
+            (level(index / 36), level(index / 6 % 6), level(index % 6))
+        }
+        232..=255 => {
+            let gray = 8 + (index - 232) * 10;
+            (gray, gray, gray)
+        }
+    };
+    Color::from_rgb(red, green, blue)
+}
+
+/// The color of a cell, or `default` for the default foreground or background.
+pub fn terminal_color(color: vt100::Color, default: Color) -> Color {
+    match color {
+        vt100::Color::Default => default,
+        vt100::Color::Idx(index) => indexed_color(index),
+        vt100::Color::Rgb(red, green, blue) => Color::from_rgb(red, green, blue),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palette_has_the_xterm_colors() {
+        assert_eq!(indexed_color(1), Color::from_rgb(205, 0, 0));
+        assert_eq!(indexed_color(16), Color::from_rgb(0, 0, 0));
+        assert_eq!(indexed_color(196), Color::from_rgb(255, 0, 0));
+        assert_eq!(indexed_color(231), Color::from_rgb(255, 255, 255));
+        assert_eq!(indexed_color(232), Color::from_rgb(8, 8, 8));
+        assert_eq!(indexed_color(255), Color::from_rgb(238, 238, 238));
+    }
+}
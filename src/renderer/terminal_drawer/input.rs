@@ -0,0 +1,115 @@
+use winit::keyboard::{Key, ModifiersState};
+
+/// The bytes a terminal sends for the key, like xterm does. `application_cursor` is the mode in
+/// which full screen programs get the arrow keys as `ESC O A` instead of `ESC [ A`.
+pub fn key_bytes(
+    key: &Key,
+    modifiers: ModifiersState,
+    application_cursor: bool,
+) -> Option<Vec<u8>> {
+    let arrow = |letter: char| {
+        let prefix = if application_cursor { "\x1bO" } else { "\x1b[" };
+        format!("{prefix}{letter}")
+    };
+    let sequence = match key {
+        Key::Enter => "\r".to_string(),
+        Key::Backspace => "\x7f".to_string(),
+        Key::Tab if modifiers.shift_key() => "\x1b[Z".to_string(),
+        Key::Tab => "\t".to_string(),
+        Key::Escape => "\x1b".to_string(),
+        Key::Space if modifiers.control_key() => "\0".to_string(),
+        Key::Space => " ".to_string(),
+        Key::ArrowUp => arrow('A'),
+        Key::ArrowDown => arrow('B'),
+        Key::ArrowRight => arrow('C'),
+        Key::ArrowLeft => arrow('D'),
+        Key::Home => "\x1b[H".to_string(),
+        Key::End => "\x1b[F".to_string(),
+        Key::Insert => "\x1b[2~".to_string(),
+        Key::Delete => "\x1b[3~".to_string(),
+        Key::PageUp => "\x1b[5~".to_string(),
+        Key::PageDown => "\x1b[6~".to_string(),
+        Key::F1 => "\x1bOP".to_string(),
+        Key::F2 => "\x1bOQ".to_string(),
+        Key::F3 => "\x1bOR".to_string(),
+        Key::F4 => "\x1bOS".to_string(),
+        Key::F5 => "\x1b[15~".to_string(),
+        Key::F6 => "\x1b[17~".to_string(),
+        Key::F7 => "\x1b[18~".to_string(),
+        Key::F8 => "\x1b[19~".to_string(),
+        Key::F9 => "\x1b[20~".to_string(),
+        Key::F10 => "\x1b[21~".to_string(),
+        Key::F11 => "\x1b[23~".to_string(),
+        Key::F12 => "\x1b[24~".to_string(),
+        Key::Character(text) => return Some(character_bytes(text, modifiers)),
+        _ => return None,
+    };
+
+    let mut bytes = sequence.into_bytes();
+    // Alt sends ESC first, like the meta key of xterm
+    if modifiers.alt_key() && bytes.len() == 1 {
+        bytes.insert(0, 0x1b);
+    }
+    Some(bytes)
+}
+
+fn character_bytes(text: &str, modifiers: ModifiersState) -> Vec<u8> {
+    let mut bytes = match text.as_bytes() {
+        // Ctrl turns letters and @[\]^_ into the control characters
+        [byte] if modifiers.control_key() && (b'@'..=b'_').contains(&byte.to_ascii_uppercase()) => {
+            vec![byte.to_ascii_uppercase() & 0x1f]
+        }
+        _ => text.as_bytes().to_vec(),
+    };
+    if modifiers.alt_key() {
+        bytes.insert(0, 0x1b);
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn character(text: &str) -> Key {
+        Key::Character(text.into())
+    }
+
+    #[test]
+    fn control_letters_are_control_characters() {
+        let control = ModifiersState::CONTROL;
+        assert_eq!(key_bytes(&character("c"), control, false), Some(vec![3]));
+        assert_eq!(key_bytes(&character("D"), control, false), Some(vec![4]));
+        assert_eq!(key_bytes(&character("["), control, false), Some(vec![0x1b]));
+        assert_eq!(
+            key_bytes(&character("é"), ModifiersState::empty(), false),
+            Some("é".as_bytes().to_vec())
+        );
+    }
+
+    #[test]
+    fn alt_sends_escape_first() {
+        assert_eq!(
+            key_bytes(&character("b"), ModifiersState::ALT, false),
+            Some(b"\x1bb".to_vec())
+        );
+        assert_eq!(
+            key_bytes(&Key::Backspace, ModifiersState::ALT, false),
+            Some(b"\x1b\x7f".to_vec())
+        );
+    }
+
+    #[test]
+    fn arrows_follow_the_cursor_mode() {
+        let none = ModifiersState::empty();
+        assert_eq!(
+            key_bytes(&Key::ArrowUp, none, false),
+            Some(b"\x1b[A".to_vec())
+        );
+        assert_eq!(
+            key_bytes(&Key::ArrowUp, none, true),
+            Some(b"\x1bOA".to_vec())
+        );
+        assert_eq!(key_bytes(&Key::Shift, none, false), None);
+    }
+}
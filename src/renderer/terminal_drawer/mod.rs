@@ -0,0 +1,438 @@
+mod colors;
+mod input;
+
+use std::{
+    io::{Read, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use log::error;
+use parking_lot::Mutex;
+use portable_pty::{native_pty_system, Child, CommandBuilder, MasterPty, PtySize};
+use skia_safe::{paint, Canvas, Color, Paint, Rect};
+use winit::{
+    dpi::PhysicalPosition,
+    event::{Event, KeyEvent, MouseScrollDelta},
+    keyboard::ModifiersState,
+};
+
+use crate::{
+    event_aggregator::EVENT_AGGREGATOR,
+    keybindings::bound_action_of,
+    redraw_scheduler::REDRAW_SCHEDULER,
+    renderer::{
+        overlay::{contains, Input},
+        GridRenderer,
+    },
+    window::{is_terminal_shortcut, WindowCommand},
+};
+
+use colors::terminal_color;
+use input::key_bytes;
+
+const SCROLLBACK_LINES: usize = 5000;
+// The space around the terminal, in columns
+const MARGIN_COLUMNS: f32 = 1.0;
+
+/// A shell running in a pseudo terminal, with the screen kept up to date by a reader thread.
+struct Session {
+    parser: Arc<Mutex<vt100::Parser>>,
+    master: Box<dyn MasterPty + Send>,
+    writer: Box<dyn Write + Send>,
+    child: Box<dyn Child + Send + Sync>,
+    exited: Arc<AtomicBool>,
+    size: (u16, u16),
+    reader: Option<JoinHandle<()>>,
+}
+
+impl Session {
+    fn spawn(shell: &str, cwd: &str, rows: u16, columns: u16) -> Result<Self, String> {
+        let size = PtySize {
+            rows,
+            cols: columns,
+            pixel_width: 0,
+            pixel_height: 0,
+        };
+        let pair = native_pty_system()
+            .openpty(size)
+            .map_err(|error| format!("Could not open a pseudo terminal: {error}"))?;
+
+        let mut command = CommandBuilder::new(shell);
+        command.env("TERM", "xterm-256color");
+        if !cwd.is_empty() {
+            command.cwd(cwd);
+        }
+        let child = pair
+            .slave
+            .spawn_command(command)
+            .map_err(|error| format!("Could not start {shell}: {error}"))?;
+        // Otherwise reading wouldn't end when the shell exits
+        drop(pair.slave);
+
+        let mut reader = pair
+            .master
+            .try_clone_reader()
+            .map_err(|error| error.to_string())?;
+        let writer = pair
+            .master
+            .take_writer()
+            .map_err(|error| error.to_string())?;
+
+        let parser = Arc::new(Mutex::new(vt100::Parser::new(
+            rows,
+            columns,
+            SCROLLBACK_LINES,
+        )));
+        let exited = Arc::new(AtomicBool::new(false));
+        let reader = {
+            let parser = parser.clone();
+            let exited = exited.clone();
+            thread::spawn(move || {
+                let mut buffer = [0; 8192];
+                loop {
+                    match reader.read(&mut buffer) {
+                        Ok(0) | Err(_) => break,
+                        Ok(length) => {
+                            parser.lock().process(&buffer[..length]);
+                            REDRAW_SCHEDULER.queue_next_frame();
+                        }
+                    }
+                }
+                exited.store(true, Ordering::Relaxed);
+                REDRAW_SCHEDULER.queue_next_frame();
+            })
+        };
+
+        Ok(Session {
+            parser,
+            master: pair.master,
+            writer,
+            child,
+            exited,
+            size: (rows, columns),
+            reader: Some(reader),
+        })
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        // Typing shows the bottom again
+        self.parser.lock().set_scrollback(0);
+        if let Err(error) = self
+            .writer
+            .write_all(bytes)
+            .and_then(|_| self.writer.flush())
+        {
+            error!("Could not write to the terminal: {}", error);
+        }
+    }
+
+    fn resize(&mut self, rows: u16, columns: u16) {
+        if self.size == (rows, columns) {
+            return;
+        }
+        self.size = (rows, columns);
+        self.parser.lock().set_size(rows, columns);
+        let size = PtySize {
+            rows,
+            cols: columns,
+            pixel_width: 0,
+            pixel_height: 0,
+        };
+        if let Err(error) = self.master.resize(size) {
+            error!("Could not resize the terminal: {}", error);
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        // Reaps the shell, so that it doesn't stay behind as a zombie
+        self.child.wait().ok();
+        if let Some(reader) = self.reader.take() {
+            // Processes the shell started in the background can keep the terminal open, so the
+            // reader is only joined once it has ended, and is left to end on its own otherwise
+            if reader.is_finished() {
+                reader.join().ok();
+            }
+        }
+    }
+}
+
+/// A terminal running the shell of the system in a drawer at the bottom of the window, separate
+/// from Neovim's `:terminal`. Toggled with Ctrl+` or `:NeovideTerminal`, and it keeps running
+/// while it's hidden.
+#[derive(Default)]
+pub struct TerminalDrawer {
+    open: bool,
+    focused: bool,
+    session: Option<Session>,
+    region: Rect,
+    modifiers: ModifiersState,
+    mouse_position: PhysicalPosition<f64>,
+    scrollback: usize,
+}
+
+impl TerminalDrawer {
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Opens and focuses the drawer, or closes it when it already has the focus. The shell starts
+    /// in `cwd` the first time.
+    pub fn toggle(&mut self, shell: &str, cwd: &str) {
+        if self.open && self.focused {
+            self.open = false;
+            self.focused = false;
+        } else {
+            if self.session.is_none() {
+                match Session::spawn(shell, cwd, 24, 80) {
+                    Ok(session) => self.session = Some(session),
+                    Err(error) => {
+                        error!("{}", error);
+                        return;
+                    }
+                }
+            }
+            self.open = true;
+            self.focused = true;
+        }
+        REDRAW_SCHEDULER.queue_next_frame();
+    }
+
+    /// Unlike the other overlays, the drawer only takes the mouse input over it, and the keyboard
+    /// input while it has the focus. Returns true when Neovim shouldn't get the event.
+    pub fn handle_event(&mut self, event: &Event<()>) -> bool {
+        let input = match Input::of(event) {
+            Some(input) => input,
+            None => return false,
+        };
+        let contains_mouse = contains(&self.region, self.mouse_position);
+        match input {
+            // Seen by Neovim too, so that it knows the modifiers after the focus moves back
+            Input::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers;
+                false
+            }
+            Input::CursorMoved(position) => {
+                self.mouse_position = position;
+                self.open && contains(&self.region, position)
+            }
+            _ if !self.open => false,
+            Input::Click => {
+                // Clicking moves the focus between the drawer and Neovim
+                self.focused = contains_mouse;
+                REDRAW_SCHEDULER.queue_next_frame();
+                self.focused
+            }
+            Input::MouseButton => contains_mouse,
+            Input::Wheel(delta) => {
+                if contains_mouse {
+                    let lines = match delta {
+                        MouseScrollDelta::LineDelta(_, lines) => lines as i64 * 3,
+                        MouseScrollDelta::PixelDelta(position) => (position.y / 20.0) as i64,
+                    };
+                    self.scroll(lines);
+                }
+                contains_mouse
+            }
+            _ if !self.focused => false,
+            Input::KeyPressed(key_event) => {
+                self.handle_key(key_event);
+                true
+            }
+            Input::Text(text) => {
+                if let Some(session) = &mut self.session {
+                    session.write(text.as_bytes());
+                }
+                true
+            }
+            Input::OtherKeyboard => true,
+        }
+    }
+
+    fn handle_key(&mut self, key_event: &KeyEvent) {
+        // The GUI keybindings still work, like the one hiding the drawer
+        if is_terminal_shortcut(key_event, self.modifiers) {
+            EVENT_AGGREGATOR.send(WindowCommand::ToggleTerminal);
+            return;
+        }
+        if let Some(action) = bound_action_of(key_event, self.modifiers) {
+            action.run(None);
+            return;
+        }
+
+        let session = match &mut self.session {
+            Some(session) => session,
+            None => return,
+        };
+        let application_cursor = session.parser.lock().screen().application_cursor();
+        if let Some(bytes) = key_bytes(&key_event.logical_key, self.modifiers, application_cursor) {
+            self.scrollback = 0;
+            session.write(&bytes);
+        }
+    }
+
+    fn scroll(&mut self, lines: i64) {
+        if let Some(session) = &self.session {
+            let mut parser = session.parser.lock();
+            let scrollback = (self.scrollback as i64 + lines).clamp(0, SCROLLBACK_LINES as i64);
+            parser.set_scrollback(scrollback as usize);
+            // Limited to the lines there are
+            self.scrollback = parser.screen().scrollback();
+        }
+        REDRAW_SCHEDULER.queue_next_frame();
+    }
+
+    pub fn draw(&mut self, canvas: &mut Canvas, grid_renderer: &mut GridRenderer, region: Rect) {
+        self.region = region;
+        if !self.open {
+            return;
+        }
+
+        // The drawer closes with the shell, and the next toggle starts a new one
+        if let Some(session) = &self.session {
+            if session.exited.load(Ordering::Relaxed) {
+                self.session = None;
+                self.open = false;
+                self.focused = false;
+                REDRAW_SCHEDULER.queue_next_frame();
+                return;
+            }
+        }
+        let session = match &mut self.session {
+            Some(session) => session,
+            None => return,
+        };
+
+        let font_dimensions = grid_renderer.font_dimensions;
+        let char_width = font_dimensions.width as f32;
+        let line_height = font_dimensions.height as f32;
+        let y_adjustment = grid_renderer.shaper.y_adjustment() as f32;
+        let left = region.left + MARGIN_COLUMNS * char_width;
+        let top = region.top + line_height / 2.0;
+        let columns = ((region.width() / char_width) - 2.0 * MARGIN_COLUMNS).max(1.0) as u16;
+        let rows = ((region.height() - line_height / 2.0) / line_height).max(1.0) as u16;
+        session.resize(rows, columns);
+
+        let colors = &grid_renderer.default_style.colors;
+        let default_background = colors
+            .background
+            .map_or(Color::BLACK, |color| color.to_color());
+        let default_foreground = colors
+            .foreground
+            .map_or(Color::WHITE, |color| color.to_color());
+
+        canvas.save();
+        canvas.clip_rect(region, None, Some(false));
+        let mut paint = Paint::default();
+        paint.set_color(default_background);
+        canvas.draw_rect(region, &paint);
+        paint.set_color(default_foreground.with_a(if self.focused { 90 } else { 40 }));
+        canvas.draw_rect(
+            Rect::from_xywh(region.left, region.top, region.width(), 1.0),
+            &paint,
+        );
+
+        let parser = session.parser.lock();
+        let screen = parser.screen();
+        for row in 0..rows {
+            let y = top + row as f32 * line_height;
+            // Cells with the same attributes are drawn together
+            let mut column = 0;
+            while column < columns {
+                let cell = match screen.cell(row, column) {
+                    Some(cell) => cell,
+                    None => break,
+                };
+                let attributes = (
+                    cell.fgcolor(),
+                    cell.bgcolor(),
+                    cell.bold(),
+                    cell.italic(),
+                    cell.underline(),
+                    cell.inverse(),
+                );
+                let start = column;
+                let mut text = String::new();
+                while column < columns {
+                    let cell = match screen.cell(row, column) {
+                        Some(cell) => cell,
+                        None => break,
+                    };
+                    let same = (
+                        cell.fgcolor(),
+                        cell.bgcolor(),
+                        cell.bold(),
+                        cell.italic(),
+                        cell.underline(),
+                        cell.inverse(),
+                    ) == attributes;
+                    if !same {
+                        break;
+                    }
+                    if !cell.is_wide_continuation() {
+                        match cell.contents() {
+                            contents if contents.is_empty() => text.push(' '),
+                            contents => text.push_str(&contents),
+                        }
+                    }
+                    column += 1;
+                }
+
+                let (foreground, background, bold, italic, underline, inverse) = attributes;
+                let mut foreground = terminal_color(foreground, default_foreground);
+                let mut background = terminal_color(background, default_background);
+                if inverse {
+                    std::mem::swap(&mut foreground, &mut background);
+                }
+                let x = left + start as f32 * char_width;
+                let width = (column - start) as f32 * char_width;
+                if background != default_background {
+                    paint.set_color(background);
+                    canvas.draw_rect(Rect::from_xywh(x, y, width, line_height), &paint);
+                }
+                if text.trim_end().is_empty() {
+                    continue;
+                }
+                paint.set_color(foreground);
+                for blob in grid_renderer
+                    .shaper
+                    .shape_cached(text.trim_end().to_string(), bold, italic, false)
+                    .iter()
+                {
+                    canvas.draw_text_blob(blob, (x, y + y_adjustment), &paint);
+                }
+                if underline {
+                    let underline_y =
+                        y + line_height - grid_renderer.shaper.underline_position() as f32;
+                    canvas.draw_rect(Rect::from_xywh(x, underline_y, width, 1.0), &paint);
+                }
+            }
+        }
+
+        // Scrolled back, the cursor is somewhere below
+        if !screen.hide_cursor() && self.scrollback == 0 {
+            let (row, column) = screen.cursor_position();
+            let cursor = Rect::from_xywh(
+                left + column as f32 * char_width,
+                top + row as f32 * line_height,
+                char_width,
+                line_height,
+            );
+            paint.set_color(default_foreground.with_a(160));
+            if self.focused {
+                canvas.draw_rect(cursor, &paint);
+            } else {
+                paint.set_style(paint::Style::Stroke).set_stroke_width(1.0);
+                canvas.draw_rect(cursor, &paint);
+            }
+        }
+
+        canvas.restore();
+    }
+}
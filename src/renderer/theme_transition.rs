@@ -0,0 +1,46 @@
+use std::time::Instant;
+
+use skia_safe::{Canvas, Image, Paint};
+
+use crate::renderer::animation_utils::{fade_out_opacity, reduce_motion};
+
+/// Crossfades from the last frame with the old colors when the colorscheme changes.
+#[derive(Default)]
+pub struct ThemeTransition {
+    last_frame: Option<Image>,
+    fading: Option<(Image, Instant)>,
+}
+
+impl ThemeTransition {
+    /// Whether the frames have to be kept, which costs a copy of every frame.
+    pub fn wants_last_frame(length: f32) -> bool {
+        length > 0.0 && !reduce_motion()
+    }
+
+    pub fn set_last_frame(&mut self, image: Image) {
+        self.last_frame = Some(image);
+    }
+
+    /// Starts fading out the last frame, when it was kept.
+    pub fn start(&mut self) {
+        if let Some(image) = self.last_frame.take() {
+            self.fading = Some((image, Instant::now()));
+        }
+    }
+
+    pub fn draw(&mut self, canvas: &mut Canvas, length: f32) {
+        let (image, started_at) = match &self.fading {
+            Some(fading) => fading,
+            None => return,
+        };
+        let opacity = fade_out_opacity(*started_at, 0.0, length);
+        if opacity <= 0.0 {
+            self.fading = None;
+            return;
+        }
+
+        let mut paint = Paint::default();
+        paint.set_alpha_f(opacity);
+        canvas.draw_image(image, (0, 0), Some(&paint));
+    }
+}
@@ -0,0 +1,32 @@
+/// How many rows of padding put the cursor row in the middle of a window in typewriter mode. Neovim
+/// scrolls the cursor line there with `zz`, so padding is only needed while the first line of the
+/// buffer is shown, since it can't scroll above it.
+pub fn typewriter_padding(top_line: f64, cursor_row: u64, window_height: u64) -> f32 {
+    if top_line > 0.0 {
+        return 0.0;
+    }
+    let center_row = window_height.saturating_sub(1) / 2;
+    center_row.saturating_sub(cursor_row) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_row_is_padded_to_the_center() {
+        assert_eq!(typewriter_padding(0.0, 0, 41), 20.0);
+        assert_eq!(typewriter_padding(0.0, 15, 41), 5.0);
+        assert_eq!(typewriter_padding(0.0, 20, 41), 0.0);
+        // Neovim centers the rows below the middle itself
+        assert_eq!(typewriter_padding(0.0, 30, 41), 0.0);
+        // Even heights put the cursor just above the middle
+        assert_eq!(typewriter_padding(0.0, 0, 10), 4.0);
+        assert_eq!(typewriter_padding(0.0, 0, 0), 0.0);
+    }
+
+    #[test]
+    fn scrolled_windows_are_not_padded() {
+        assert_eq!(typewriter_padding(12.0, 0, 41), 0.0);
+    }
+}
@@ -27,10 +27,9 @@ impl RunningTracker {
         info!("Quit {}", reason);
     }
 
-    pub fn quit_with_code(&self, code: i32, reason: &str) {
+    /// Records the exit code to use once Neovide quits, without quitting yet.
+    pub fn set_exit_code(&self, code: i32) {
         self.exit_code.store(code, Ordering::Relaxed);
-        self.running.store(false, Ordering::Relaxed);
-        info!("Quit with code {}: {}", code, reason);
     }
 
     pub fn is_running(&self) -> bool {
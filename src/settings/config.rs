@@ -1,15 +1,23 @@
 // Config file handling
 
-use std::env;
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+};
 
-use serde::Deserialize;
+use rmpv::Value;
+use serde::{Deserialize, Serialize};
+use toml_edit::{Document, Item};
 
-use crate::frame::Frame;
+use crate::{cmd_line::CmdLineSettings, frame::Frame, settings::SETTINGS};
 
 use std::path::{Path, PathBuf};
 
 const CONFIG_FILE: &str = "config.toml";
 
+/// Holds the `neovim_bin_candidates` from the config file, joined like the PATH.
+pub const NEOVIM_BIN_CANDIDATES_ENV: &str = "NEOVIDE_NEOVIM_BIN_CANDIDATES";
+
 #[cfg(unix)]
 fn neovide_config_dir() -> PathBuf {
     let xdg_dirs = xdg::BaseDirectories::with_prefix("neovide").unwrap();
@@ -38,18 +46,119 @@ pub struct Config {
     pub srgb: Option<bool>,
     pub idle: Option<bool>,
     pub neovim_bin: Option<PathBuf>,
+    #[serde(default)]
+    pub neovim_bin_candidates: Vec<PathBuf>,
     pub frame: Option<Frame>,
     pub theme: Option<String>,
+    pub background: Option<String>,
+    #[serde(default)]
+    pub profiles: HashMap<String, Profile>,
+    #[serde(default)]
+    pub editor: EditorConfig,
+    #[serde(default)]
+    pub settings: SavedSettings,
+    #[serde(default)]
+    pub keybindings: KeyBindings,
+}
+
+/// Values of `g:neovide_*` variables saved from the settings panel, named without the `neovide_`
+/// prefix. Like the editor settings, they're applied before the user's init.
+#[derive(Clone, Debug, Deserialize, Serialize, Default, PartialEq)]
+#[serde(transparent)]
+pub struct SavedSettings(pub toml::Table);
+
+/// GUI actions bound to key chords, like `"<C-S-f>" = "toggle_fullscreen"`. The chords are
+/// checked before the keys are sent to Neovim.
+#[derive(Clone, Debug, Deserialize, Serialize, Default, PartialEq)]
+#[serde(transparent)]
+pub struct KeyBindings(pub BTreeMap<String, String>);
+
+/// Editor settings, as picked in the setup wizard. They're applied before the user's init, which
+/// can still override them.
+#[derive(Clone, Debug, Deserialize, Serialize, Default, PartialEq)]
+pub struct EditorConfig {
+    pub font: Option<String>,
+    pub transparency: Option<f32>,
+    pub cursor_vfx_mode: Option<String>,
+}
+
+/// A named set of launch options, selected with `--profile <name>`.
+#[derive(Debug, Deserialize, Default)]
+pub struct Profile {
+    pub neovim_bin: Option<PathBuf>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    pub wsl: Option<bool>,
+    pub wsl_distro: Option<String>,
+    pub wsl_shell: Option<String>,
 }
 
 impl Config {
     /// Loads config from `config_path()` and writes it to env variables.
-    pub fn init() {
-        match Config::load_from_path(&config_path()) {
-            Ok(config) => config.write_to_env(),
-            Err(Some(err)) => eprintln!("{err}"),
-            Err(None) => {}
+    pub fn init() -> Config {
+        let config = match Config::load_from_path(&config_path()) {
+            Ok(config) => {
+                config.write_to_env();
+                config
+            }
+            Err(Some(err)) => {
+                eprintln!("{err}");
+                Config::default()
+            }
+            Err(None) => Config::default(),
         };
+        SETTINGS.set::<EditorConfig>(&config.editor);
+        SETTINGS.set::<SavedSettings>(&config.settings);
+        SETTINGS.set::<KeyBindings>(&config.keybindings);
+        config
+    }
+
+    /// Applies the profile selected on the command line on top of the parsed command line
+    /// settings. Has to be called after the command line was handled.
+    pub fn apply_profile(&self) -> Result<(), String> {
+        let mut settings = SETTINGS.get::<CmdLineSettings>();
+        let name = match &settings.profile {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+        let profile = self.profiles.get(name).ok_or_else(|| {
+            format!(
+                "Unknown profile {}, expected one of the profiles in {}",
+                name,
+                config_path().to_string_lossy()
+            )
+        })?;
+
+        // Explicit command line flags still win over the profile
+        settings.neovim_bin = profile_neovim_bin(
+            settings.neovim_bin,
+            self.neovim_bin.as_deref(),
+            profile.neovim_bin.as_deref(),
+        );
+        if let Some(wsl) = profile.wsl {
+            settings.wsl |= wsl;
+        }
+        if settings.wsl_distro.is_none() {
+            settings.wsl_distro = profile.wsl_distro.clone();
+        }
+        if settings.wsl_shell.is_none() {
+            settings.wsl_shell = profile.wsl_shell.clone();
+        }
+        settings.neovim_args = profile
+            .args
+            .iter()
+            .cloned()
+            .chain(settings.neovim_args)
+            .collect();
+        // Inherited by nvim once it gets started
+        for (key, value) in &profile.env {
+            env::set_var(key, value);
+        }
+
+        SETTINGS.set::<CmdLineSettings>(&settings);
+        Ok(())
     }
 
     fn write_to_env(&self) {
@@ -77,9 +186,45 @@ impl Config {
         if let Some(neovim_bin) = &self.neovim_bin {
             env::set_var("NEOVIM_BIN", neovim_bin.to_string_lossy().to_string());
         }
+        if !self.neovim_bin_candidates.is_empty() {
+            match env::join_paths(&self.neovim_bin_candidates) {
+                Ok(candidates) => env::set_var(NEOVIM_BIN_CANDIDATES_ENV, candidates),
+                Err(err) => eprintln!("Invalid neovim_bin_candidates in the config file: {err}"),
+            }
+        }
         if let Some(theme) = &self.theme {
             env::set_var("NEOVIDE_THEME", theme);
         }
+        if let Some(background) = &self.background {
+            env::set_var("NEOVIDE_BACKGROUND", background);
+        }
+    }
+
+    /// Stores the editor settings in the config file, keeping everything else in it.
+    pub fn save_editor_config(editor: &EditorConfig) -> Result<(), String> {
+        update_config_file("editor", &to_table(editor)?)?;
+        SETTINGS.set::<EditorConfig>(editor);
+        Ok(())
+    }
+
+    /// Adds the values to the `settings` table of the config file, replacing older ones.
+    pub fn save_settings(values: &[(String, Value)]) -> Result<(), String> {
+        let mut saved = SETTINGS.get::<SavedSettings>();
+        for (name, value) in values {
+            let value = value_to_toml(value)
+                .ok_or_else(|| format!("Can't save {} in the config file", name))?;
+            saved.0.insert(name.clone(), value);
+        }
+        update_config_file("settings", &saved.0)?;
+        SETTINGS.set::<SavedSettings>(&saved);
+        Ok(())
+    }
+
+    /// Replaces the `keybindings` table of the config file.
+    pub fn save_keybindings(keybindings: &KeyBindings) -> Result<(), String> {
+        update_config_file("keybindings", &to_table(keybindings)?)?;
+        SETTINGS.set::<KeyBindings>(keybindings);
+        Ok(())
     }
 
     fn load_from_path(path: &Path) -> Result<Self, Option<String>> {
@@ -103,3 +248,243 @@ impl Config {
         Ok(config)
     }
 }
+
+/// The binary to start with a profile. NEOVIM_BIN, which `neovim_bin` is read from, also holds
+/// the binary of the config file, so that one is replaced by the binary of the profile too.
+fn profile_neovim_bin(
+    neovim_bin: Option<String>,
+    config_bin: Option<&Path>,
+    profile_bin: Option<&Path>,
+) -> Option<String> {
+    let from_config = neovim_bin.as_deref().map(Path::new) == config_bin;
+    match profile_bin {
+        Some(profile_bin) if neovim_bin.is_none() || from_config => {
+            Some(profile_bin.to_string_lossy().to_string())
+        }
+        _ => neovim_bin,
+    }
+}
+
+fn to_table(value: impl Serialize) -> Result<toml::Table, String> {
+    toml::Value::try_from(value)
+        .map_err(|e| e.to_string())?
+        .try_into()
+        .map_err(|e: toml::de::Error| e.to_string())
+}
+
+/// Replaces a table of the config file, keeping everything else in it as it was written.
+fn update_config_file(section: &str, values: &toml::Table) -> Result<(), String> {
+    let path = config_path();
+    let toml = std::fs::read_to_string(&path).unwrap_or_default();
+    let toml = update_section(&toml, section, values)
+        .map_err(|e| format!("Could not update {}: {}", path.to_string_lossy(), e))?;
+
+    std::fs::create_dir_all(neovide_config_dir()).map_err(|e| e.to_string())?;
+    std::fs::write(&path, toml)
+        .map_err(|e| format!("Could not write {}: {}", path.to_string_lossy(), e))
+}
+
+/// Edits the document in place, so that the comments and the order of the keys survive.
+fn update_section(toml: &str, section: &str, values: &toml::Table) -> Result<String, String> {
+    let mut document = toml.parse::<Document>().map_err(|e| e.to_string())?;
+    let table = document
+        .entry(section)
+        .or_insert(toml_edit::table())
+        .as_table_like_mut()
+        .ok_or_else(|| format!("{section} isn't a table"))?;
+
+    let removed: Vec<String> = table
+        .iter()
+        .map(|(key, _)| key.to_string())
+        .filter(|key| !values.contains_key(key))
+        .collect();
+    for key in removed {
+        table.remove(&key);
+    }
+
+    for (key, value) in values {
+        let mut value = value
+            .to_string()
+            .parse::<toml_edit::Value>()
+            .map_err(|e| e.to_string())?;
+        match table.get_mut(key) {
+            Some(Item::Value(old_value)) => {
+                // The decor holds the comment after the value
+                *value.decor_mut() = old_value.decor().clone();
+                *old_value = value;
+            }
+            _ => {
+                table.insert(key, Item::Value(value));
+            }
+        }
+    }
+    Ok(document.to_string())
+}
+
+/// Converts a value of the `settings` table to what Neovim gets for the variable.
+pub fn toml_to_value(value: &toml::Value) -> Option<Value> {
+    match value {
+        toml::Value::Boolean(value) => Some(Value::from(*value)),
+        toml::Value::Integer(value) => Some(Value::from(*value)),
+        toml::Value::Float(value) => Some(Value::from(*value)),
+        toml::Value::String(value) => Some(Value::from(value.as_str())),
+        _ => None,
+    }
+}
+
+fn value_to_toml(value: &Value) -> Option<toml::Value> {
+    match value {
+        Value::Boolean(value) => Some(toml::Value::Boolean(*value)),
+        Value::Integer(value) => value.as_i64().map(toml::Value::Integer),
+        Value::F32(value) => Some(toml::Value::Float(*value as f64)),
+        Value::F64(value) => Some(toml::Value::Float(*value)),
+        Value::String(value) => value
+            .as_str()
+            .map(|value| toml::Value::String(value.into())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_profiles() {
+        let config: Config = toml::from_str(
+            r#"
+            neovim_bin = "/usr/bin/nvim"
+
+            [profiles.work]
+            neovim_bin = "/opt/nvim/bin/nvim"
+            args = ["-u", "~/work/init.lua"]
+            env = { NVIM_APPNAME = "nvim-work" }
+
+            [profiles.wsl]
+            wsl = true
+            wsl_distro = "Ubuntu"
+            wsl_shell = "fish"
+            "#,
+        )
+        .expect("Could not parse config");
+
+        let work = &config.profiles["work"];
+        assert_eq!(work.neovim_bin, Some(PathBuf::from("/opt/nvim/bin/nvim")));
+        assert_eq!(work.args, vec!["-u", "~/work/init.lua"]);
+        assert_eq!(work.env["NVIM_APPNAME"], "nvim-work");
+        assert_eq!(work.wsl, None);
+
+        let wsl = &config.profiles["wsl"];
+        assert_eq!(wsl.wsl, Some(true));
+        assert_eq!(wsl.wsl_distro.as_deref(), Some("Ubuntu"));
+        assert_eq!(wsl.wsl_shell.as_deref(), Some("fish"));
+        assert!(wsl.args.is_empty());
+        assert_eq!(config.editor, EditorConfig::default());
+        assert_eq!(config.settings, SavedSettings::default());
+        assert_eq!(config.keybindings, KeyBindings::default());
+    }
+
+    #[test]
+    fn test_profile_neovim_bin() {
+        let config_bin = Some(Path::new("/usr/bin/nvim"));
+        let profile_bin = Some(Path::new("/opt/nvim/bin/nvim"));
+
+        // Set from the config file
+        assert_eq!(
+            profile_neovim_bin(Some("/usr/bin/nvim".to_string()), config_bin, profile_bin),
+            Some("/opt/nvim/bin/nvim".to_string())
+        );
+        assert_eq!(
+            profile_neovim_bin(None, None, profile_bin),
+            Some("/opt/nvim/bin/nvim".to_string())
+        );
+        // Passed on the command line
+        assert_eq!(
+            profile_neovim_bin(Some("./nvim".to_string()), config_bin, profile_bin),
+            Some("./nvim".to_string())
+        );
+        // A profile without its own binary
+        assert_eq!(
+            profile_neovim_bin(Some("/usr/bin/nvim".to_string()), config_bin, None),
+            Some("/usr/bin/nvim".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_keybindings() {
+        let config: Config = toml::from_str(
+            r#"
+            [keybindings]
+            "<C-S-f>" = "toggle_fullscreen"
+            "<D-n>" = "new_window"
+            "#,
+        )
+        .expect("Could not parse config");
+
+        assert_eq!(config.keybindings.0["<C-S-f>"], "toggle_fullscreen");
+        assert_eq!(config.keybindings.0["<D-n>"], "new_window");
+    }
+
+    #[test]
+    fn test_settings_values_round_trip() {
+        for value in [
+            Value::from(true),
+            Value::from(60),
+            Value::from(0.5),
+            Value::from("railgun"),
+        ] {
+            let toml = value_to_toml(&value).expect("Could not convert to toml");
+            assert_eq!(toml_to_value(&toml), Some(value));
+        }
+        assert_eq!(value_to_toml(&Value::Nil), None);
+    }
+
+    #[test]
+    fn test_parse_editor() {
+        let config: Config = toml::from_str(
+            r#"
+            [editor]
+            font = "Fira Code:h14"
+            transparency = 0.9
+            "#,
+        )
+        .expect("Could not parse config");
+
+        assert_eq!(
+            config.editor,
+            EditorConfig {
+                font: Some("Fira Code:h14".to_string()),
+                transparency: Some(0.9),
+                cursor_vfx_mode: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_update_section_keeps_comments() {
+        let toml = r#"# Launch options
+multigrid = true
+
+[editor]
+# Picked in the setup wizard
+font = "Fira Code:h14" # the usual one
+cursor_vfx_mode = "railgun"
+"#;
+        let mut editor = toml::Table::new();
+        editor.insert("font".to_string(), toml::Value::from("Hack:h12"));
+        editor.insert("transparency".to_string(), toml::Value::from(0.5));
+
+        let updated = update_section(toml, "editor", &editor).expect("Could not update");
+        assert_eq!(
+            updated,
+            r#"# Launch options
+multigrid = true
+
+[editor]
+# Picked in the setup wizard
+font = "Hack:h12" # the usual one
+transparency = 0.5
+"#
+        );
+    }
+}
@@ -14,11 +14,15 @@ use std::{
 use crate::{bridge::NeovimWriter, error_handling::ResultPanicExplanation};
 pub use from_value::ParseFromValue;
 pub use window_size::{
-    load_last_window_settings, save_window_size, PersistentWindowSettings, DEFAULT_WINDOW_GEOMETRY,
+    has_persistent_settings, last_session_path, load_last_background, load_last_window_settings,
+    save_window_size, PersistentWindowSettings, DEFAULT_WINDOW_GEOMETRY,
 };
 
 mod config;
-pub use config::{config_path, Config};
+pub use config::{
+    config_path, toml_to_value, Config, EditorConfig, KeyBindings, SavedSettings,
+    NEOVIM_BIN_CANDIDATES_ENV,
+};
 
 lazy_static! {
     pub static ref SETTINGS: Settings = Settings::new();
@@ -43,6 +47,15 @@ pub struct Settings {
     settings: RwLock<HashMap<TypeId, Box<dyn Any + Send + Sync>>>,
     listeners: RwLock<HashMap<String, UpdateHandlerFunc>>,
     readers: RwLock<HashMap<String, ReaderFunc>>,
+    categories: RwLock<HashMap<String, String>>,
+}
+
+/// The current value of a setting, as listed in the settings panel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SettingValue {
+    pub category: String,
+    pub name: String,
+    pub value: Value,
 }
 
 impl Settings {
@@ -51,9 +64,33 @@ impl Settings {
             settings: RwLock::new(HashMap::new()),
             listeners: RwLock::new(HashMap::new()),
             readers: RwLock::new(HashMap::new()),
+            categories: RwLock::new(HashMap::new()),
         }
     }
 
+    pub fn set_setting_category(&self, property_name: &str, category: &str) {
+        self.categories
+            .write()
+            .insert(String::from(property_name), String::from(category));
+    }
+
+    /// Every setting with its current value, sorted by category and name.
+    pub fn values(&self) -> Vec<SettingValue> {
+        let categories = self.categories.read();
+        let mut values: Vec<SettingValue> = self
+            .readers
+            .read()
+            .iter()
+            .map(|(name, reader)| SettingValue {
+                category: categories.get(name).cloned().unwrap_or_default(),
+                name: name.clone(),
+                value: reader(),
+            })
+            .collect();
+        values.sort_by(|a, b| (&a.category, &a.name).cmp(&(&b.category, &b.name)));
+        values
+    }
+
     pub fn set_setting_handlers(
         &self,
         property_name: &str,
@@ -86,6 +123,17 @@ impl Settings {
         (*value).clone()
     }
 
+    /// Like `get`, but lends the value to `f` instead of cloning it.
+    pub fn with<T: Send + Sync + 'static, R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        let read_lock = self.settings.read();
+        let value = read_lock
+            .get(&TypeId::of::<T>())
+            .expect("Trying to retrieve a settings object that doesn't exist")
+            .downcast_ref::<T>()
+            .expect("Attempted to extract a settings object of the wrong type");
+        f(value)
+    }
+
     pub async fn read_initial_values(&self, nvim: &Neovim<NeovimWriter>) {
         let keys: Vec<String> = self.listeners.read().keys().cloned().collect();
 
@@ -186,6 +234,27 @@ mod tests {
         assert_eq!(&(noop_read as ReaderFunc), reader);
     }
 
+    #[test]
+    fn test_values() {
+        let settings = Settings::new();
+
+        fn noop_update(_v: Value) {}
+
+        fn read_true() -> Value {
+            Value::from(true)
+        }
+
+        settings.set_setting_handlers("foo", noop_update, read_true);
+        settings.set_setting_handlers("bar", noop_update, read_true);
+        settings.set_setting_category("foo", "Window");
+        settings.set_setting_category("bar", "Window");
+
+        let names: Vec<String> = settings.values().into_iter().map(|s| s.name).collect();
+        assert_eq!(names, vec!["bar", "foo"]);
+        assert_eq!(settings.values()[0].category, "Window");
+        assert_eq!(settings.values()[0].value, Value::from(true));
+    }
+
     #[test]
     fn test_set() {
         let settings = Settings::new();
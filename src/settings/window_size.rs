@@ -6,6 +6,7 @@ use winit::dpi::{PhysicalPosition, PhysicalSize};
 use crate::{dimensions::Dimensions, settings::SETTINGS, window::WindowSettings};
 
 const SETTINGS_FILE: &str = "neovide-settings.json";
+const SESSION_FILE: &str = "neovide-session.vim";
 
 pub const DEFAULT_WINDOW_GEOMETRY: Dimensions = Dimensions {
     width: 100,
@@ -26,6 +27,9 @@ pub enum PersistentWindowSettings {
 #[derive(Serialize, Deserialize)]
 struct PersistentSettings {
     window: PersistentWindowSettings,
+    /// The background color of Neovim, used until it draws in the next session
+    #[serde(default)]
+    background: Option<String>,
 }
 
 #[cfg(windows)]
@@ -47,6 +51,18 @@ fn settings_path() -> PathBuf {
     settings_path
 }
 
+/// The session of the last run, saved with `:mksession` while Neovide runs.
+pub fn last_session_path() -> PathBuf {
+    let mut session_path = neovim_std_datapath();
+    session_path.push(SESSION_FILE);
+    session_path
+}
+
+/// Whether Neovide ran before, as the window size is saved on every exit.
+pub fn has_persistent_settings() -> bool {
+    settings_path().exists()
+}
+
 fn load_settings() -> Result<PersistentSettings, String> {
     let settings_path = settings_path();
     let json = std::fs::read_to_string(settings_path).map_err(|e| e.to_string())?;
@@ -61,10 +77,15 @@ pub fn load_last_window_settings() -> Result<PersistentWindowSettings, String> {
     Ok(loaded_settings)
 }
 
+pub fn load_last_background() -> Option<String> {
+    load_settings().ok()?.background
+}
+
 pub fn save_window_size(
     maximized: bool,
     size: PhysicalSize<u32>,
     position: Option<PhysicalPosition<i32>>,
+    background: Option<String>,
 ) {
     let window_settings = SETTINGS.get::<WindowSettings>();
 
@@ -83,6 +104,7 @@ pub fn save_window_size(
                 },
             }
         },
+        background,
     };
 
     let settings_path = settings_path();
@@ -0,0 +1,136 @@
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use log::error;
+
+use crate::{cmd_line::CmdLineSettings, settings::SETTINGS};
+
+lazy_static! {
+    pub static ref STARTUP_TIME: StartupTime = StartupTime::new();
+}
+
+struct Phase {
+    name: &'static str,
+    finished_at: Instant,
+}
+
+#[derive(Default)]
+struct StartupState {
+    phases: Vec<Phase>,
+    written: bool,
+}
+
+/// Records when the phases of starting the GUI finished, for `--startuptime`.
+pub struct StartupTime {
+    started_at: Instant,
+    state: Mutex<StartupState>,
+}
+
+impl StartupTime {
+    fn new() -> Self {
+        Self {
+            started_at: Instant::now(),
+            state: Mutex::new(StartupState::default()),
+        }
+    }
+
+    /// Records that a phase finished. Only the first time counts for phases which can happen
+    /// again, like loading fonts.
+    pub fn record(&self, name: &'static str) {
+        let mut state = self.state.lock().unwrap();
+        if state.written || state.phases.iter().any(|phase| phase.name == name) {
+            return;
+        }
+        state.phases.push(Phase {
+            name,
+            finished_at: Instant::now(),
+        });
+    }
+
+    /// Appends the phases to the `--startuptime` file, once the first frame was presented.
+    pub fn finish(&self) {
+        self.record("first frame presented");
+        let mut state = self.state.lock().unwrap();
+        if state.written {
+            return;
+        }
+        state.written = true;
+
+        let path = match SETTINGS.get::<CmdLineSettings>().startuptime {
+            Some(path) => path,
+            None => return,
+        };
+        let phases: Vec<_> = state
+            .phases
+            .iter()
+            .map(|phase| (phase.name, phase.finished_at - self.started_at))
+            .collect();
+        if let Err(error) = append(Path::new(&path), &format_phases(&phases)) {
+            error!("Could not write the startup time to {}: {}", path, error);
+        }
+    }
+}
+
+fn append(path: &Path, text: &str) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(text.as_bytes())
+}
+
+fn milliseconds(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Formats the phases like Neovim formats its own startup time.
+fn format_phases(phases: &[(&str, Duration)]) -> String {
+    let mut text = String::from(
+        "\n\ntimes in msec\n clock   elapsed:  neovide phase\n\n000.000  000.000: --- NEOVIDE STARTING ---\n",
+    );
+    let mut previous = Duration::ZERO;
+    for (name, finished_at) in phases {
+        text.push_str(&format!(
+            "{:07.3}  {:07.3}: {}\n",
+            milliseconds(*finished_at),
+            milliseconds(finished_at.saturating_sub(previous)),
+            name
+        ));
+        previous = *finished_at;
+    }
+    text.push_str(&format!(
+        "{:07.3}  000.000: --- NEOVIDE STARTED ---\n",
+        milliseconds(previous)
+    ));
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phases_are_formatted_like_neovim() {
+        let text = format_phases(&[
+            ("window created", Duration::from_micros(12_500)),
+            ("first flush", Duration::from_micros(140_250)),
+        ]);
+        assert!(text.contains("000.000  000.000: --- NEOVIDE STARTING ---\n"));
+        assert!(text.contains("012.500  012.500: window created\n"));
+        assert!(text.contains("140.250  127.750: first flush\n"));
+        assert!(text.ends_with("140.250  000.000: --- NEOVIDE STARTED ---\n"));
+    }
+
+    #[test]
+    fn repeated_phases_keep_the_first_time() {
+        let startup_time = StartupTime::new();
+        startup_time.record("fonts loaded");
+        let first = startup_time.state.lock().unwrap().phases[0].finished_at;
+        startup_time.record("fonts loaded");
+        let state = startup_time.state.lock().unwrap();
+        assert_eq!(state.phases.len(), 1);
+        assert_eq!(state.phases[0].finished_at, first);
+    }
+}
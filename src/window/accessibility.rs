@@ -0,0 +1,463 @@
+use std::sync::Arc;
+
+#[cfg(target_os = "linux")]
+use accesskit::Rect;
+use accesskit::{
+    ActionHandler, ActionRequest, Node, NodeBuilder, NodeClassSet, NodeId, Role, TextPosition,
+    TextSelection, Tree, TreeUpdate,
+};
+use log::trace;
+use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
+use winit::{event::WindowEvent, window::Window};
+
+use crate::bridge::EditorMode;
+
+const ROOT_ID: NodeId = NodeId(0);
+
+/// The text content of a single grid, split into lines of characters.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccessibleGrid {
+    pub id: u64,
+    // Shared with the editor, which only rebuilds the lines of grids that changed
+    pub lines: Arc<Vec<Vec<String>>>,
+}
+
+/// Everything screen readers get to know about the editor, sent by the editor on every flush
+/// where it changed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccessibilityUpdate {
+    pub grids: Vec<AccessibleGrid>,
+    pub cursor_grid: u64,
+    pub cursor_line: usize,
+    pub cursor_character: usize,
+    pub mode: EditorMode,
+}
+
+fn grid_node_id(grid_id: u64) -> NodeId {
+    NodeId(grid_id << 32)
+}
+
+fn line_node_id(grid_id: u64, line: usize) -> NodeId {
+    NodeId(grid_id << 32 | (line as u64 + 1))
+}
+
+fn mode_description(mode: &EditorMode) -> String {
+    match mode {
+        EditorMode::Normal => "Normal mode".to_owned(),
+        EditorMode::Insert => "Insert mode".to_owned(),
+        EditorMode::Visual => "Visual mode".to_owned(),
+        EditorMode::Replace => "Replace mode".to_owned(),
+        EditorMode::CmdLine => "Command line mode".to_owned(),
+        EditorMode::Unknown(name) => name.clone(),
+    }
+}
+
+// Neovide has no widgets which could be activated, so screen reader actions are only logged
+struct NeovideActionHandler;
+
+impl ActionHandler for NeovideActionHandler {
+    fn do_action(&mut self, request: ActionRequest) {
+        trace!("Ignoring accessibility action {:?}", request.action);
+    }
+}
+
+fn build_root(classes: &mut NodeClassSet, grid_ids: &[u64]) -> Node {
+    let mut builder = NodeBuilder::new(Role::Window);
+    builder.set_name("Neovide");
+    builder.set_children(
+        grid_ids
+            .iter()
+            .copied()
+            .map(grid_node_id)
+            .collect::<Vec<_>>(),
+    );
+    builder.build(classes)
+}
+
+// The adapters of the platforms are used directly, accesskit_winit only supports released
+// versions of winit
+#[cfg(target_os = "windows")]
+struct PlatformAdapter(accesskit_windows::SubclassingAdapter);
+
+#[cfg(target_os = "windows")]
+impl PlatformAdapter {
+    fn new(window: &Window, source: impl 'static + FnOnce() -> TreeUpdate) -> Option<Self> {
+        let hwnd = match window.raw_window_handle() {
+            RawWindowHandle::Win32(handle) => accesskit_windows::HWND(handle.hwnd as isize),
+            _ => return None,
+        };
+        let adapter = accesskit_windows::SubclassingAdapter::new(
+            hwnd,
+            source,
+            Box::new(NeovideActionHandler),
+        );
+        Some(Self(adapter))
+    }
+
+    fn handle_event(&self, _window: &Window, _event: &WindowEvent) {}
+
+    fn update_if_active(&self, updater: impl FnOnce() -> TreeUpdate) {
+        if let Some(events) = self.0.update_if_active(updater) {
+            events.raise();
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+struct PlatformAdapter(accesskit_macos::SubclassingAdapter);
+
+#[cfg(target_os = "macos")]
+impl PlatformAdapter {
+    fn new(window: &Window, source: impl 'static + FnOnce() -> TreeUpdate) -> Option<Self> {
+        let view = match window.raw_window_handle() {
+            RawWindowHandle::AppKit(handle) => handle.ns_view,
+            _ => return None,
+        };
+        let adapter = unsafe {
+            accesskit_macos::SubclassingAdapter::new(view, source, Box::new(NeovideActionHandler))
+        };
+        Some(Self(adapter))
+    }
+
+    fn handle_event(&self, _window: &Window, event: &WindowEvent) {
+        if let WindowEvent::Focused(is_focused) = event {
+            if let Some(events) = self.0.update_view_focus_state(*is_focused) {
+                events.raise();
+            }
+        }
+    }
+
+    fn update_if_active(&self, updater: impl FnOnce() -> TreeUpdate) {
+        if let Some(events) = self.0.update_if_active(updater) {
+            events.raise();
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+struct PlatformAdapter(accesskit_unix::Adapter);
+
+#[cfg(target_os = "linux")]
+impl PlatformAdapter {
+    fn new(_window: &Window, source: impl 'static + FnOnce() -> TreeUpdate) -> Option<Self> {
+        // Without the accessibility bus there's nothing to connect to
+        accesskit_unix::Adapter::new(source, false, Box::new(NeovideActionHandler)).map(Self)
+    }
+
+    fn handle_event(&self, window: &Window, event: &WindowEvent) {
+        match event {
+            WindowEvent::Moved(_) | WindowEvent::Resized(_) => {
+                let outer_position: (f64, f64) = window
+                    .outer_position()
+                    .unwrap_or_default()
+                    .cast::<f64>()
+                    .into();
+                let outer_size: (f64, f64) = window.outer_size().cast::<f64>().into();
+                let inner_position: (f64, f64) = window
+                    .inner_position()
+                    .unwrap_or_default()
+                    .cast::<f64>()
+                    .into();
+                let inner_size: (f64, f64) = window.inner_size().cast::<f64>().into();
+                self.0.set_root_window_bounds(
+                    Rect::from_origin_size(outer_position, outer_size),
+                    Rect::from_origin_size(inner_position, inner_size),
+                );
+            }
+            WindowEvent::Focused(is_focused) => self.0.update_window_focus_state(*is_focused),
+            _ => {}
+        }
+    }
+
+    fn update_if_active(&self, updater: impl FnOnce() -> TreeUpdate) {
+        self.0.update(updater());
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+struct PlatformAdapter;
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+impl PlatformAdapter {
+    fn new(_window: &Window, _source: impl 'static + FnOnce() -> TreeUpdate) -> Option<Self> {
+        None
+    }
+
+    fn handle_event(&self, _window: &Window, _event: &WindowEvent) {}
+
+    fn update_if_active(&self, _updater: impl FnOnce() -> TreeUpdate) {}
+}
+
+pub struct AccessibilityTree {
+    adapter: Option<PlatformAdapter>,
+    classes: NodeClassSet,
+    // What the screen readers got last, so that only the nodes that changed are sent
+    sent: Option<AccessibilityUpdate>,
+}
+
+impl AccessibilityTree {
+    /// Has to be called before the window is shown for the first time.
+    pub fn new(window: &Window) -> Self {
+        let adapter = PlatformAdapter::new(window, || {
+            let mut classes = NodeClassSet::new();
+            TreeUpdate {
+                nodes: vec![(ROOT_ID, build_root(&mut classes, &[]))],
+                tree: Some(Tree::new(ROOT_ID)),
+                focus: ROOT_ID,
+            }
+        });
+
+        Self {
+            adapter,
+            classes: NodeClassSet::new(),
+            sent: None,
+        }
+    }
+
+    pub fn handle_event(&self, window: &Window, event: &WindowEvent) {
+        if let Some(adapter) = &self.adapter {
+            adapter.handle_event(window, event);
+        }
+    }
+
+    pub fn update(&mut self, update: AccessibilityUpdate) {
+        let adapter = match &self.adapter {
+            Some(adapter) => adapter,
+            None => return,
+        };
+        let classes = &mut self.classes;
+        let previous = self.sent.as_ref();
+        let mut was_sent = false;
+        adapter.update_if_active(|| {
+            was_sent = true;
+            tree_update(classes, previous, &update)
+        });
+        // Without screen readers nothing is sent, and everything has to be once one shows up
+        self.sent = was_sent.then_some(update);
+    }
+}
+
+// Where the cursor is, if it's in the grid
+fn cursor_in_grid(
+    update: &AccessibilityUpdate,
+    grid_id: u64,
+) -> Option<(usize, usize, &EditorMode)> {
+    (update.cursor_grid == grid_id).then_some((
+        update.cursor_line,
+        update.cursor_character,
+        &update.mode,
+    ))
+}
+
+/// The nodes that changed since the `previous` update, or all of them without one.
+fn tree_update(
+    classes: &mut NodeClassSet,
+    previous: Option<&AccessibilityUpdate>,
+    update: &AccessibilityUpdate,
+) -> TreeUpdate {
+    let grid_ids: Vec<u64> = update.grids.iter().map(|grid| grid.id).collect();
+    let previous_grid_ids = previous.map(|previous| {
+        previous
+            .grids
+            .iter()
+            .map(|grid| grid.id)
+            .collect::<Vec<_>>()
+    });
+    let mut nodes = Vec::new();
+    if previous_grid_ids.as_ref() != Some(&grid_ids) {
+        nodes.push((ROOT_ID, build_root(classes, &grid_ids)));
+    }
+    let mut focus = ROOT_ID;
+
+    for grid in &update.grids {
+        let cursor = cursor_in_grid(update, grid.id);
+        if matches!(cursor, Some((line, ..)) if line < grid.lines.len()) {
+            focus = grid_node_id(grid.id);
+        }
+
+        let previous_grid = previous.and_then(|previous| {
+            previous
+                .grids
+                .iter()
+                .find(|previous_grid| previous_grid.id == grid.id)
+                .map(|previous_grid| (previous_grid, cursor_in_grid(previous, grid.id)))
+        });
+        let previous_lines: &[Vec<String>] = match previous_grid {
+            // The editor shares the lines of grids that didn't change
+            Some((previous_grid, _)) if Arc::ptr_eq(&previous_grid.lines, &grid.lines) => {
+                &grid.lines
+            }
+            Some((previous_grid, _)) => &previous_grid.lines,
+            None => &[],
+        };
+        // The grid holds the cursor and the mode, so it changes with them too
+        let grid_changed = match previous_grid {
+            Some((previous_grid, previous_cursor)) => {
+                previous_grid.lines.len() != grid.lines.len() || previous_cursor != cursor
+            }
+            None => true,
+        };
+        if grid_changed {
+            nodes.push((grid_node_id(grid.id), build_grid(classes, grid, cursor)));
+        }
+
+        for (index, line) in grid.lines.iter().enumerate() {
+            if previous_lines.get(index) != Some(line) {
+                nodes.push((line_node_id(grid.id, index), build_line(classes, line)));
+            }
+        }
+    }
+
+    TreeUpdate {
+        nodes,
+        tree: None,
+        focus,
+    }
+}
+
+fn build_grid(
+    classes: &mut NodeClassSet,
+    grid: &AccessibleGrid,
+    cursor: Option<(usize, usize, &EditorMode)>,
+) -> Node {
+    let mut builder = NodeBuilder::new(Role::Document);
+    builder.set_children(
+        (0..grid.lines.len())
+            .map(|line| line_node_id(grid.id, line))
+            .collect::<Vec<_>>(),
+    );
+
+    if let Some((cursor_line, cursor_character, mode)) = cursor {
+        if let Some(line) = grid.lines.get(cursor_line) {
+            let position = TextPosition {
+                node: line_node_id(grid.id, cursor_line),
+                character_index: cursor_character.min(line.len()),
+            };
+            builder.set_text_selection(TextSelection {
+                anchor: position,
+                focus: position,
+            });
+            builder.set_description(mode_description(mode));
+        }
+    }
+    builder.build(classes)
+}
+
+fn build_line(classes: &mut NodeClassSet, line: &[String]) -> Node {
+    let (value, character_lengths) = line_text(line);
+    let mut builder = NodeBuilder::new(Role::InlineTextBox);
+    builder.set_value(value);
+    builder.set_character_lengths(character_lengths);
+    builder.build(classes)
+}
+
+// The byte length of every character has to fit into a u8, the rare longer clusters of combining
+// marks are cut off so that the lengths still add up to the text
+fn line_text(line: &[String]) -> (String, Vec<u8>) {
+    let mut value = String::new();
+    let mut lengths = Vec::with_capacity(line.len());
+    for character in line {
+        let mut end = character.len().min(u8::MAX as usize);
+        while !character.is_char_boundary(end) {
+            end -= 1;
+        }
+        value.push_str(&character[..end]);
+        lengths.push(u8::try_from(end).unwrap_or(u8::MAX));
+    }
+    (value, lengths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(id: u64, lines: &[&str]) -> AccessibleGrid {
+        AccessibleGrid {
+            id,
+            lines: Arc::new(
+                lines
+                    .iter()
+                    .map(|line| line.chars().map(String::from).collect())
+                    .collect(),
+            ),
+        }
+    }
+
+    fn accessibility_update(grids: Vec<AccessibleGrid>, cursor_line: usize) -> AccessibilityUpdate {
+        AccessibilityUpdate {
+            grids,
+            cursor_grid: 1,
+            cursor_line,
+            cursor_character: 0,
+            mode: EditorMode::Normal,
+        }
+    }
+
+    fn sent_nodes(
+        previous: Option<&AccessibilityUpdate>,
+        update: &AccessibilityUpdate,
+    ) -> Vec<NodeId> {
+        tree_update(&mut NodeClassSet::new(), previous, update)
+            .nodes
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    #[test]
+    fn everything_is_sent_the_first_time() {
+        let update = accessibility_update(vec![grid(1, &["ab", "c"]), grid(2, &["d"])], 0);
+        assert_eq!(
+            sent_nodes(None, &update),
+            vec![
+                ROOT_ID,
+                grid_node_id(1),
+                line_node_id(1, 0),
+                line_node_id(1, 1),
+                grid_node_id(2),
+                line_node_id(2, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_changed_lines_are_sent() {
+        let unchanged = grid(2, &["d"]);
+        let previous = accessibility_update(vec![grid(1, &["ab", "c"]), unchanged.clone()], 0);
+        let update = accessibility_update(vec![grid(1, &["ab", "e"]), unchanged], 0);
+        assert_eq!(
+            sent_nodes(Some(&previous), &update),
+            vec![line_node_id(1, 1)]
+        );
+        assert!(sent_nodes(Some(&update), &update).is_empty());
+    }
+
+    #[test]
+    fn grids_are_sent_when_the_cursor_or_their_lines_change() {
+        let previous = accessibility_update(vec![grid(1, &["ab", "c"])], 0);
+
+        let moved = accessibility_update(vec![grid(1, &["ab", "c"])], 1);
+        assert_eq!(sent_nodes(Some(&previous), &moved), vec![grid_node_id(1)]);
+
+        let added = accessibility_update(vec![grid(1, &["ab", "c", "d"])], 0);
+        assert_eq!(
+            sent_nodes(Some(&previous), &added),
+            vec![grid_node_id(1), line_node_id(1, 2)]
+        );
+
+        let opened = accessibility_update(vec![grid(1, &["ab", "c"]), grid(2, &[])], 0);
+        assert_eq!(
+            sent_nodes(Some(&previous), &opened),
+            vec![ROOT_ID, grid_node_id(2)]
+        );
+    }
+
+    #[test]
+    fn long_characters_are_cut_to_fit_their_length() {
+        let zalgo = format!("a{}", "\u{301}".repeat(200));
+        let (value, lengths) = line_text(&["b".to_owned(), zalgo.clone()]);
+        assert_eq!(lengths, vec![1, 255]);
+        assert_eq!(value.len(), 256);
+        assert!(zalgo.starts_with(&value[1..]));
+    }
+}
@@ -0,0 +1,147 @@
+use muda::{
+    ContextMenu as _, LogicalPosition, Menu, MenuEvent, MenuId, MenuItem, Position,
+    PredefinedMenuItem,
+};
+use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
+use winit::{
+    dpi::PhysicalPosition,
+    event::{ElementState, Event, MouseButton, WindowEvent},
+    window::Window,
+};
+
+use crate::{
+    bridge::{SerialCommand, UiCommand},
+    event_aggregator::EVENT_AGGREGATOR,
+    i18n::tr,
+    settings::SETTINGS,
+    window::WindowSettings,
+};
+
+// Menus on Linux need a GTK window, which winit doesn't create
+const SUPPORTED: bool = cfg!(any(target_os = "windows", target_os = "macos"));
+
+#[derive(Clone, Debug)]
+enum ContextMenuAction {
+    Cut,
+    Copy,
+    Paste,
+    SelectAll,
+    Command(String),
+}
+
+impl ContextMenuAction {
+    fn execute(&self) {
+        let command = match self {
+            ContextMenuAction::Cut => SerialCommand::YankSelection {
+                cut: true,
+                fallback: None,
+            },
+            ContextMenuAction::Copy => SerialCommand::YankSelection {
+                cut: false,
+                fallback: None,
+            },
+            ContextMenuAction::Paste => SerialCommand::PasteClipboard,
+            ContextMenuAction::SelectAll => SerialCommand::Keyboard("<C-\\><C-N>ggVG".into()),
+            ContextMenuAction::Command(command) => SerialCommand::Command(command.clone()),
+        };
+        EVENT_AGGREGATOR.send(UiCommand::Serial(command));
+    }
+}
+
+/// Shows a native menu on right click instead of forwarding the click to Neovim, when
+/// `g:neovide_context_menu` is set. Only Windows and macOS have one.
+pub struct ContextMenu {
+    // Label and Ex command of the entries registered through neovide.set_context_menu_items
+    user_items: Vec<(String, String)>,
+    shown_actions: Vec<(MenuId, ContextMenuAction)>,
+    mouse_position: PhysicalPosition<f64>,
+}
+
+impl ContextMenu {
+    pub fn new() -> Self {
+        Self {
+            user_items: Vec::new(),
+            shown_actions: Vec::new(),
+            mouse_position: PhysicalPosition::new(0.0, 0.0),
+        }
+    }
+
+    pub fn set_user_items(&mut self, user_items: Vec<(String, String)>) {
+        self.user_items = user_items;
+    }
+
+    /// Returns true when the event was used to show the menu and shouldn't reach Neovim.
+    pub fn handle_event(&mut self, event: &Event<()>, window: &Window) -> bool {
+        match event {
+            Event::WindowEvent {
+                event: WindowEvent::CursorMoved { position, .. },
+                ..
+            } => {
+                self.mouse_position = *position;
+                false
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::MouseInput {
+                        button: MouseButton::Right,
+                        state,
+                        ..
+                    },
+                ..
+            } if SUPPORTED && SETTINGS.get::<WindowSettings>().context_menu => {
+                if *state == ElementState::Pressed {
+                    self.show(window);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Runs the action of the entry picked from the menu, if any.
+    pub fn handle_menu_events(&mut self) {
+        while let Ok(event) = MenuEvent::receiver().try_recv() {
+            if let Some((_, action)) = self.shown_actions.iter().find(|(id, _)| *id == event.id) {
+                action.execute();
+            }
+        }
+    }
+
+    fn show(&mut self, window: &Window) {
+        let menu = Menu::new();
+        self.shown_actions.clear();
+
+        let mut append = |label: &str, action: ContextMenuAction| {
+            let item = MenuItem::new(label, true, None);
+            menu.append(&item).ok();
+            self.shown_actions.push((item.id().clone(), action));
+        };
+        append(&tr("context-menu.cut"), ContextMenuAction::Cut);
+        append(&tr("context-menu.copy"), ContextMenuAction::Copy);
+        append(&tr("context-menu.paste"), ContextMenuAction::Paste);
+        append(&tr("context-menu.select-all"), ContextMenuAction::SelectAll);
+
+        if !self.user_items.is_empty() {
+            menu.append(&PredefinedMenuItem::separator()).ok();
+            for (label, command) in &self.user_items {
+                append(label, ContextMenuAction::Command(command.clone()));
+            }
+        }
+
+        let position = self.mouse_position.to_logical::<f64>(window.scale_factor());
+        let position = Some(Position::Logical(LogicalPosition::new(
+            position.x, position.y,
+        )));
+        match window.raw_window_handle() {
+            #[cfg(target_os = "windows")]
+            RawWindowHandle::Win32(handle) => {
+                menu.show_context_menu_for_hwnd(handle.hwnd as isize, position)
+            }
+            #[cfg(target_os = "macos")]
+            RawWindowHandle::AppKit(handle) => {
+                menu.show_context_menu_for_nsview(handle.ns_view as _, position)
+            }
+            _ => {}
+        }
+    }
+}
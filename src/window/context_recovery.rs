@@ -0,0 +1,193 @@
+use std::time::{Duration, Instant};
+
+use log::{error, info, warn};
+
+// Recreating can fail while the driver is still resetting, so it's retried with a growing delay
+const FIRST_RETRY_DELAY: Duration = Duration::from_millis(100);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// What the window needs from its graphics context to recover from losing it.
+pub trait RecoverableContext {
+    /// Whether the context was lost, because of a driver reset, a suspend or a GPU switch.
+    fn is_lost(&mut self) -> bool;
+    /// Creates everything drawn with the context again.
+    fn recreate(&mut self) -> Result<(), String>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecoveryStatus {
+    /// The context works, drawing can go on.
+    Healthy,
+    /// The context was recreated, everything has to be drawn again.
+    Recovered,
+    /// The context is lost and couldn't be recreated yet, nothing can be drawn.
+    Lost,
+}
+
+/// Keeps track of a lost graphics context until it's recreated.
+pub struct ContextRecovery {
+    lost: bool,
+    failed_attempts: u32,
+    next_attempt: Option<Instant>,
+}
+
+impl ContextRecovery {
+    pub fn new() -> Self {
+        Self {
+            lost: false,
+            failed_attempts: 0,
+            next_attempt: None,
+        }
+    }
+
+    /// Marks the context as lost, for when drawing with it failed.
+    pub fn context_lost(&mut self) {
+        if !self.lost {
+            warn!("The graphics context was lost, recreating it");
+            self.lost = true;
+        }
+    }
+
+    /// Checks the context before drawing a frame and tries to recreate it when it's lost.
+    pub fn check(&mut self, context: &mut impl RecoverableContext, now: Instant) -> RecoveryStatus {
+        if !self.lost && context.is_lost() {
+            self.context_lost();
+        }
+        if !self.lost {
+            return RecoveryStatus::Healthy;
+        }
+        if self
+            .next_attempt
+            .map_or(false, |next_attempt| now < next_attempt)
+        {
+            return RecoveryStatus::Lost;
+        }
+
+        match context.recreate() {
+            Ok(()) => {
+                info!("Recreated the graphics context");
+                self.lost = false;
+                self.failed_attempts = 0;
+                self.next_attempt = None;
+                RecoveryStatus::Recovered
+            }
+            Err(message) => {
+                error!("Could not recreate the graphics context: {}", message);
+                self.next_attempt = Some(now + retry_delay(self.failed_attempts));
+                self.failed_attempts += 1;
+                RecoveryStatus::Lost
+            }
+        }
+    }
+}
+
+fn retry_delay(failed_attempts: u32) -> Duration {
+    FIRST_RETRY_DELAY
+        .saturating_mul(2u32.saturating_pow(failed_attempts))
+        .min(MAX_RETRY_DELAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stands in for the GL context and the surfaces drawn with it.
+    #[derive(Default)]
+    struct SimulatedContext {
+        generation: u32,
+        lost: bool,
+        failing_recreations: u32,
+        recreations: u32,
+    }
+
+    impl SimulatedContext {
+        /// Simulates a driver reset, which invalidates the context and everything drawn with it.
+        fn invalidate(&mut self) {
+            self.lost = true;
+        }
+    }
+
+    impl RecoverableContext for SimulatedContext {
+        fn is_lost(&mut self) -> bool {
+            self.lost
+        }
+
+        fn recreate(&mut self) -> Result<(), String> {
+            self.recreations += 1;
+            if self.failing_recreations > 0 {
+                self.failing_recreations -= 1;
+                return Err("driver is still resetting".to_string());
+            }
+            self.lost = false;
+            self.generation += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn healthy_context_is_left_alone() {
+        let mut recovery = ContextRecovery::new();
+        let mut context = SimulatedContext::default();
+        assert_eq!(
+            recovery.check(&mut context, Instant::now()),
+            RecoveryStatus::Healthy
+        );
+        assert_eq!(context.recreations, 0);
+    }
+
+    #[test]
+    fn invalidated_context_is_recreated() {
+        let mut recovery = ContextRecovery::new();
+        let mut context = SimulatedContext::default();
+        context.invalidate();
+
+        let now = Instant::now();
+        assert_eq!(recovery.check(&mut context, now), RecoveryStatus::Recovered);
+        assert_eq!(context.generation, 1);
+        assert_eq!(recovery.check(&mut context, now), RecoveryStatus::Healthy);
+    }
+
+    #[test]
+    fn failed_swap_marks_the_context_lost() {
+        let mut recovery = ContextRecovery::new();
+        let mut context = SimulatedContext::default();
+        recovery.context_lost();
+        assert_eq!(
+            recovery.check(&mut context, Instant::now()),
+            RecoveryStatus::Recovered
+        );
+    }
+
+    #[test]
+    fn failed_recreations_are_retried_later() {
+        let mut recovery = ContextRecovery::new();
+        let mut context = SimulatedContext {
+            failing_recreations: 2,
+            ..Default::default()
+        };
+        context.invalidate();
+
+        let start = Instant::now();
+        assert_eq!(recovery.check(&mut context, start), RecoveryStatus::Lost);
+        // Not retried before the delay passed
+        assert_eq!(recovery.check(&mut context, start), RecoveryStatus::Lost);
+        assert_eq!(context.recreations, 1);
+
+        let later = start + FIRST_RETRY_DELAY;
+        assert_eq!(recovery.check(&mut context, later), RecoveryStatus::Lost);
+        let later = later + FIRST_RETRY_DELAY * 2;
+        assert_eq!(
+            recovery.check(&mut context, later),
+            RecoveryStatus::Recovered
+        );
+        assert_eq!(context.recreations, 3);
+        assert_eq!(context.generation, 1);
+    }
+
+    #[test]
+    fn retry_delay_is_capped() {
+        assert_eq!(retry_delay(0), FIRST_RETRY_DELAY);
+        assert_eq!(retry_delay(3), FIRST_RETRY_DELAY * 8);
+        assert_eq!(retry_delay(100), MAX_RETRY_DELAY);
+    }
+}
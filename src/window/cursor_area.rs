@@ -0,0 +1,47 @@
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize, Position, Size},
+    window::Window,
+};
+
+#[cfg(windows)]
+use crate::windows_utils::{windows_destroy_caret, windows_set_caret};
+
+/// Keeps the OS informed about where the text cursor is, so that IME candidate windows, the macOS
+/// force touch dictionary lookup, magnifiers and screen readers can follow it.
+#[derive(Default)]
+pub struct CursorArea {
+    position: Option<PhysicalPosition<i32>>,
+    size: PhysicalSize<u32>,
+}
+
+impl CursorArea {
+    pub fn update(
+        &mut self,
+        window: &Window,
+        position: PhysicalPosition<i32>,
+        size: PhysicalSize<u32>,
+    ) {
+        if self.position == Some(position) && self.size == size {
+            return;
+        }
+        self.position = Some(position);
+        self.size = size;
+
+        // On macOS this rectangle is also what is returned to the text input system, which is used
+        // for the dictionary lookup popup and the accessibility zoom.
+        window.set_ime_cursor_area(Position::Physical(position), Size::Physical(size));
+
+        // Windows Magnifier and Narrator follow the system caret, which stays invisible since
+        // Neovide draws its own cursor.
+        #[cfg(windows)]
+        windows_set_caret(window, position, size);
+    }
+
+    pub fn handle_focus_lost(&mut self) {
+        // The system caret belongs to the focused window, so it's recreated on the next update
+        self.position = None;
+
+        #[cfg(windows)]
+        windows_destroy_caret();
+    }
+}
@@ -0,0 +1,55 @@
+use cocoa::{
+    appkit::NSApp,
+    base::{id, nil, NO},
+    foundation::{NSPoint, NSRect, NSSize, NSString},
+};
+use objc::{class, msg_send, rc::autoreleasepool, sel, sel_impl};
+
+/// Replaces the dock icon with the image at `path`, or restores the bundled one when it's empty.
+pub fn set_dock_icon(path: &str) {
+    autoreleasepool(|| unsafe {
+        let image: id = if path.is_empty() {
+            nil
+        } else {
+            let image: id = msg_send![class!(NSImage), alloc];
+            let image: id =
+                msg_send![image, initWithContentsOfFile: NSString::alloc(nil).init_str(path)];
+            msg_send![image, autorelease]
+        };
+        let _: () = msg_send![NSApp(), setApplicationIconImage: image];
+    });
+}
+
+/// Shows a progress bar over the dock icon, or removes it when `progress` is None.
+pub fn set_dock_progress(progress: Option<f64>) {
+    autoreleasepool(|| unsafe {
+        let dock_tile: id = msg_send![NSApp(), dockTile];
+
+        if let Some(progress) = progress {
+            // The content view replaces the icon, so it has to be drawn below the progress bar
+            let size: NSSize = msg_send![dock_tile, size];
+            let image_view: id = msg_send![class!(NSImageView), alloc];
+            let image_view: id = msg_send![image_view,
+                initWithFrame: NSRect::new(NSPoint::new(0.0, 0.0), size)];
+            let icon: id = msg_send![NSApp(), applicationIconImage];
+            let _: () = msg_send![image_view, setImage: icon];
+
+            let progress_indicator: id = msg_send![class!(NSProgressIndicator), alloc];
+            let progress_indicator: id = msg_send![progress_indicator,
+                initWithFrame: NSRect::new(NSPoint::new(0.0, 0.0), NSSize::new(size.width, 20.0))];
+            let _: () = msg_send![progress_indicator, setIndeterminate: NO];
+            let _: () = msg_send![progress_indicator, setMinValue: 0.0f64];
+            let _: () = msg_send![progress_indicator, setMaxValue: 100.0f64];
+            let _: () = msg_send![progress_indicator, setDoubleValue: progress];
+            let _: () = msg_send![image_view, addSubview: progress_indicator];
+            let _: () = msg_send![progress_indicator, release];
+
+            let _: () = msg_send![dock_tile, setContentView: image_view];
+            let _: () = msg_send![image_view, release];
+        } else {
+            let _: () = msg_send![dock_tile, setContentView: nil];
+        }
+
+        let _: () = msg_send![dock_tile, display];
+    });
+}
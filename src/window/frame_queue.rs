@@ -0,0 +1,71 @@
+use std::{collections::VecDeque, time::Duration};
+
+use gl::types::GLsync;
+use log::warn;
+
+// A frame taking longer than this on the GPU is given up on, instead of freezing the window
+const WAIT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Limits how many frames the GPU may be behind the renderer with OpenGL fences. Fewer queued
+/// frames mean input shows up sooner, more let the GPU smooth out slow frames.
+pub struct FrameQueue {
+    fences: VecDeque<GLsync>,
+}
+
+impl FrameQueue {
+    pub fn new() -> Self {
+        Self {
+            fences: VecDeque::new(),
+        }
+    }
+
+    fn is_supported() -> bool {
+        gl::FenceSync::is_loaded() && gl::ClientWaitSync::is_loaded() && gl::DeleteSync::is_loaded()
+    }
+
+    /// Blocks until fewer than `max_queued_frames` frames are still being drawn by the GPU, before
+    /// the next one is started. With 0 the driver decides how far it gets ahead.
+    pub fn wait_for_room(&mut self, max_queued_frames: u32) {
+        let max_queued_frames = max_queued_frames as usize;
+        if max_queued_frames == 0 {
+            self.clear();
+            return;
+        }
+        while self.fences.len() >= max_queued_frames {
+            let fence = self.fences.pop_front().unwrap();
+            let status = unsafe {
+                gl::ClientWaitSync(
+                    fence,
+                    gl::SYNC_FLUSH_COMMANDS_BIT,
+                    WAIT_TIMEOUT.as_nanos() as u64,
+                )
+            };
+            if status == gl::TIMEOUT_EXPIRED {
+                warn!("A frame took longer than {:?} on the GPU", WAIT_TIMEOUT);
+            }
+            unsafe { gl::DeleteSync(fence) };
+        }
+    }
+
+    /// Marks the end of the commands of a frame, after it was handed to the screen.
+    pub fn frame_submitted(&mut self, max_queued_frames: u32) {
+        if max_queued_frames == 0 || !Self::is_supported() {
+            return;
+        }
+        let fence = unsafe { gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0) };
+        if !fence.is_null() {
+            self.fences.push_back(fence);
+        }
+    }
+
+    fn clear(&mut self) {
+        for fence in self.fences.drain(..) {
+            unsafe { gl::DeleteSync(fence) };
+        }
+    }
+
+    /// Forgets the fences, for when the context they belonged to was lost.
+    pub fn reset(&mut self) {
+        self.fences.clear();
+    }
+}
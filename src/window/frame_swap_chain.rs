@@ -0,0 +1,55 @@
+use std::sync::Mutex;
+
+use winit::dpi::PhysicalSize;
+
+/// What the event loop knows about the window for the next frame. The render thread takes it from
+/// here instead of asking the window, which waits for the event loop on some platforms while it's
+/// stuck in a move or resize.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameDescription {
+    pub inner_size: PhysicalSize<u32>,
+    pub scale_factor: f64,
+}
+
+/// Hands the frame descriptions from the event loop over to the render thread. The event loop
+/// replaces the back description as often as it likes, and the render thread swaps it to the front
+/// when it starts a frame, so the descriptions in between are skipped instead of queued up.
+#[derive(Default)]
+pub struct FrameSwapChain {
+    back: Mutex<Option<FrameDescription>>,
+}
+
+impl FrameSwapChain {
+    pub fn publish(&self, description: FrameDescription) {
+        *self.back.lock().unwrap() = Some(description);
+    }
+
+    /// The latest description, if there is a new one since the last frame.
+    pub fn take(&self) -> Option<FrameDescription> {
+        self.back.lock().unwrap().take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn description(width: u32) -> FrameDescription {
+        FrameDescription {
+            inner_size: PhysicalSize::new(width, 600),
+            scale_factor: 1.0,
+        }
+    }
+
+    #[test]
+    fn only_the_latest_description_is_taken() {
+        let swap_chain = FrameSwapChain::default();
+        assert_eq!(swap_chain.take(), None);
+
+        swap_chain.publish(description(800));
+        swap_chain.publish(description(810));
+        swap_chain.publish(description(820));
+        assert_eq!(swap_chain.take(), Some(description(820)));
+        assert_eq!(swap_chain.take(), None);
+    }
+}
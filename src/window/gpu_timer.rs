@@ -0,0 +1,165 @@
+use std::{ffi::CStr, time::Duration};
+
+use gl::types::{GLenum, GLint, GLuint, GLuint64};
+use log::info;
+
+// Results arrive a few frames late, reading them earlier would stall until the GPU caught up
+const QUERY_COUNT: usize = 4;
+
+/// Measures how long the GPU works on each frame with OpenGL timer queries.
+pub struct GpuTimer {
+    supported: Option<bool>,
+    queries: Option<[GLuint; QUERY_COUNT]>,
+    in_flight: [bool; QUERY_COUNT],
+    next: usize,
+    active: Option<usize>,
+    last_gpu_time: Option<Duration>,
+}
+
+impl GpuTimer {
+    pub fn new() -> Self {
+        Self {
+            supported: None,
+            queries: None,
+            in_flight: [false; QUERY_COUNT],
+            next: 0,
+            active: None,
+            last_gpu_time: None,
+        }
+    }
+
+    /// Timer queries are core since OpenGL 3.3, and need an extension before that and on OpenGL ES.
+    /// Checked once the context is current, since the functions may load without them.
+    fn is_supported(&mut self) -> bool {
+        *self.supported.get_or_insert_with(|| {
+            let functions_loaded = gl::GenQueries::is_loaded()
+                && gl::BeginQuery::is_loaded()
+                && gl::GetQueryObjectui64v::is_loaded();
+            let version = gl_string(gl::VERSION).unwrap_or_default();
+            let supported = if version.starts_with("OpenGL ES") {
+                has_extension("GL_EXT_disjoint_timer_query")
+            } else {
+                is_at_least_3_3(&version) || has_extension("GL_ARB_timer_query")
+            };
+            if !supported {
+                info!("Timer queries aren't supported by OpenGL {}", version);
+            }
+            functions_loaded && supported
+        })
+    }
+
+    /// Starts timing the GPU work submitted until `end_frame`.
+    pub fn begin_frame(&mut self) {
+        if !self.is_supported() {
+            return;
+        }
+        self.collect_results();
+
+        let queries = *self.queries.get_or_insert_with(|| {
+            let mut queries = [0; QUERY_COUNT];
+            unsafe { gl::GenQueries(QUERY_COUNT as i32, queries.as_mut_ptr()) };
+            queries
+        });
+        // The GPU is so far behind that every query is still waiting, skip this frame
+        if self.in_flight[self.next] {
+            return;
+        }
+        unsafe { gl::BeginQuery(gl::TIME_ELAPSED, queries[self.next]) };
+        self.active = Some(self.next);
+    }
+
+    pub fn end_frame(&mut self) {
+        if let Some(index) = self.active.take() {
+            unsafe { gl::EndQuery(gl::TIME_ELAPSED) };
+            self.in_flight[index] = true;
+            self.next = (index + 1) % QUERY_COUNT;
+        }
+    }
+
+    fn collect_results(&mut self) {
+        let queries = match self.queries {
+            Some(queries) => queries,
+            None => return,
+        };
+        // Oldest first, so that the newest result is kept
+        for offset in 0..QUERY_COUNT {
+            let index = (self.next + offset) % QUERY_COUNT;
+            if !self.in_flight[index] {
+                continue;
+            }
+            let mut available: GLint = 0;
+            unsafe {
+                gl::GetQueryObjectiv(queries[index], gl::QUERY_RESULT_AVAILABLE, &mut available)
+            };
+            if available == 0 {
+                continue;
+            }
+            let mut nanoseconds: GLuint64 = 0;
+            unsafe { gl::GetQueryObjectui64v(queries[index], gl::QUERY_RESULT, &mut nanoseconds) };
+            self.in_flight[index] = false;
+            self.last_gpu_time = Some(Duration::from_nanos(nanoseconds));
+        }
+    }
+
+    /// The GPU time of the latest frame whose result arrived, if the driver supports timer
+    /// queries.
+    pub fn last_gpu_time(&self) -> Option<Duration> {
+        self.last_gpu_time
+    }
+
+    /// Forgets the queries, for when the context they belonged to was lost.
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+fn gl_string(name: GLenum) -> Option<String> {
+    let string = unsafe { gl::GetString(name) };
+    if string.is_null() {
+        return None;
+    }
+    let string = unsafe { CStr::from_ptr(string as *const _) };
+    Some(string.to_string_lossy().into_owned())
+}
+
+fn has_extension(extension: &str) -> bool {
+    // Core profiles only list them one by one
+    if gl::GetStringi::is_loaded() {
+        let mut count: GLint = 0;
+        unsafe { gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count) };
+        (0..count.max(0) as GLuint).any(|index| {
+            let name = unsafe { gl::GetStringi(gl::EXTENSIONS, index) };
+            !name.is_null()
+                && unsafe { CStr::from_ptr(name as *const _) }.to_bytes() == extension.as_bytes()
+        })
+    } else {
+        gl_string(gl::EXTENSIONS)
+            .map(|extensions| extensions.split(' ').any(|name| name == extension))
+            .unwrap_or(false)
+    }
+}
+
+// The version string starts with the version, like "4.6.0 NVIDIA 535.54.03"
+fn is_at_least_3_3(version: &str) -> bool {
+    let mut numbers = version
+        .split(|c: char| !c.is_ascii_digit())
+        .filter_map(|number| number.parse::<u32>().ok());
+    match (numbers.next(), numbers.next()) {
+        (Some(major), Some(minor)) => (major, minor) >= (3, 3),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timer_queries_are_core_since_3_3() {
+        assert!(is_at_least_3_3("4.6.0 NVIDIA 535.54.03"));
+        assert!(is_at_least_3_3("3.3 (Core Profile) Mesa 23.1.3"));
+        assert!(!is_at_least_3_3("3.2.0"));
+        assert!(!is_at_least_3_3("2.1 Metal - 83.1"));
+        assert!(!is_at_least_3_3(""));
+    }
+}
@@ -1,19 +1,93 @@
 use crate::{
-    bridge::{SerialCommand, UiCommand},
+    bridge::{ParallelCommand, SerialCommand, UiCommand},
     event_aggregator::EVENT_AGGREGATOR,
+    keybindings::bound_action_of,
+    latency_tracker::LATENCY_TRACKER,
+    redraw_scheduler::REDRAW_SCHEDULER,
+    renderer::{DrawCommand, RendererSettings},
+    settings::SETTINGS,
+    window::{
+        shortcuts::{is_terminal_shortcut, shortcut_command},
+        KeyboardLayout, KeyboardSettings, WindowCommand, WindowSettings,
+    },
 };
-#[cfg(target_os = "macos")]
-use crate::{settings::SETTINGS, window::KeyboardSettings};
 #[allow(unused_imports)]
 use winit::platform::modifier_supplement::KeyEventExtModifierSupplement;
 use winit::{
     event::{ElementState, Event, Ime, KeyEvent, Modifiers, WindowEvent},
-    keyboard::Key,
+    keyboard::{Key, KeyCode, ModifiersState},
 };
 
+// Longer IME commits are pasted, input methods for whole sentences stay well below this length
+const PASTE_MIN_CHARACTERS: usize = 256;
+
 pub struct KeyboardManager {
     modifiers: Modifiers,
     ime_preedit: (String, Option<(usize, usize)>),
+    composer: KeyComposer,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum ComposeResult {
+    /// The key is part of an unfinished dead key or compose sequence and shouldn't be sent
+    Pending,
+    /// The sequence finished and produced this text
+    Composed(String),
+    /// The key isn't part of a sequence and should be handled normally
+    Passthrough,
+}
+
+// Tracks dead key and compose key sequences. The OS does the actual composition and reports the
+// result as the text of the final key event, but the keys leading up to it have to be swallowed
+// and the final key has to be sent as text even if it's normally a special key, like space.
+#[derive(Default)]
+struct KeyComposer {
+    composing: bool,
+}
+
+impl KeyComposer {
+    fn process(&mut self, logical_key: &Key, text: Option<&str>) -> ComposeResult {
+        let text = text.filter(|text| !text.is_empty() && !text.chars().any(char::is_control));
+
+        match logical_key {
+            // Modifiers are needed for some sequences, like Shift for capital letters
+            Key::Shift | Key::Control | Key::Alt | Key::AltGraph | Key::Super if self.composing => {
+                ComposeResult::Pending
+            }
+            Key::Dead(_) | Key::Compose => match text {
+                // Windows reports the dead character itself when the dead key is pressed twice
+                Some(text) if self.composing => {
+                    self.composing = false;
+                    ComposeResult::Composed(text.to_string())
+                }
+                _ => {
+                    self.composing = true;
+                    ComposeResult::Pending
+                }
+            },
+            Key::Escape if self.composing => {
+                self.composing = false;
+                ComposeResult::Pending
+            }
+            _ if self.composing => match text {
+                Some(text) => {
+                    self.composing = false;
+                    ComposeResult::Composed(text.to_string())
+                }
+                // Intermediate keys of a compose sequence don't produce any text on X11
+                None if matches!(logical_key, Key::Character(_)) => ComposeResult::Pending,
+                None => {
+                    self.composing = false;
+                    ComposeResult::Passthrough
+                }
+            },
+            _ => ComposeResult::Passthrough,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.composing = false;
+    }
 }
 
 impl KeyboardManager {
@@ -21,6 +95,7 @@ impl KeyboardManager {
         KeyboardManager {
             modifiers: Modifiers::default(),
             ime_preedit: ("".to_string(), None),
+            composer: KeyComposer::default(),
         }
     }
 
@@ -38,9 +113,41 @@ impl KeyboardManager {
                 && self.ime_preedit.0.is_empty()
                 && !is_synthetic =>
             {
-                if let Some(text) = self.format_key(key_event) {
+                let text = self.format_key(key_event);
+                // GUI keybindings win over the shortcuts and Neovim's own mappings
+                if let Some(action) = bound_action_of(key_event, self.modifiers.state()) {
+                    log::trace!("GUI action {:?}", action);
+                    action.run(text);
+                } else if is_terminal_shortcut(key_event, self.modifiers.state()) {
+                    EVENT_AGGREGATOR.send(WindowCommand::ToggleTerminal);
+                } else if let Some(command) =
+                    shortcut_command(key_event, self.modifiers.state(), text.clone())
+                {
+                    log::trace!("Shortcut pressed {:?}", command);
+                    EVENT_AGGREGATOR.send(command);
+                } else if let Some(text) = text {
                     log::trace!("Key pressed {} {:?}", text, self.modifiers.state());
-                    EVENT_AGGREGATOR.send(UiCommand::Serial(SerialCommand::Keyboard(text)));
+                    EVENT_AGGREGATOR.send(UiCommand::Serial(SerialCommand::Keyboard(text.clone())));
+                    self.handle_input_sent(text, self.predicted_echo(key_event));
+                }
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        event: key_event,
+                        is_synthetic,
+                        ..
+                    },
+                ..
+            } if key_event.state == ElementState::Released
+                && extended_keys()
+                && self.ime_preedit.0.is_empty()
+                && !is_synthetic =>
+            {
+                // Neovim has no notation for released keys, they are reported as an autocommand
+                if let Some(text) = self.format_extended_key(key_event) {
+                    log::trace!("Key released {}", text);
+                    EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::KeyReleased(text)));
                 }
             }
             Event::WindowEvent {
@@ -48,7 +155,14 @@ impl KeyboardManager {
                 ..
             } => {
                 log::trace!("Ime commit {text}");
-                EVENT_AGGREGATOR.send(UiCommand::Serial(SerialCommand::Keyboard(text.to_string())));
+                self.composer.reset();
+                if is_paste(text) {
+                    EVENT_AGGREGATOR.send(UiCommand::Serial(SerialCommand::Paste(text.clone())));
+                } else {
+                    let text = escape_lt(text);
+                    EVENT_AGGREGATOR.send(UiCommand::Serial(SerialCommand::Keyboard(text.clone())));
+                    self.handle_input_sent(text, None);
+                }
             }
             Event::WindowEvent {
                 event: WindowEvent::Ime(Ime::Preedit(text, cursor_offset)),
@@ -66,7 +180,47 @@ impl KeyboardManager {
         }
     }
 
-    fn format_key(&self, key_event: &KeyEvent) -> Option<String> {
+    fn handle_input_sent(&self, text: String, echo: Option<String>) {
+        LATENCY_TRACKER.key_pressed();
+
+        if SETTINGS.get::<RendererSettings>().show_keys {
+            EVENT_AGGREGATOR.send(vec![DrawCommand::KeyPressed(text)]);
+        }
+
+        let settings = SETTINGS.get::<WindowSettings>();
+        if !settings.low_latency {
+            return;
+        }
+
+        REDRAW_SCHEDULER.input_sent();
+        if let Some(echo) = echo.filter(|_| settings.low_latency_echo) {
+            EVENT_AGGREGATOR.send(vec![DrawCommand::PredictEcho(echo)]);
+        }
+    }
+
+    fn predicted_echo(&self, key_event: &KeyEvent) -> Option<String> {
+        predicted_echo(
+            &key_event.logical_key,
+            key_event.text.as_deref(),
+            self.modifiers.state(),
+        )
+    }
+
+    fn format_key(&mut self, key_event: &KeyEvent) -> Option<String> {
+        // Leave Super combinations to the OS when they aren't forwarded to Neovim
+        if self.modifiers.state().super_key() && !forward_super(&key_event.logical_key) {
+            return None;
+        }
+
+        match self
+            .composer
+            .process(&key_event.logical_key, key_event.text.as_deref())
+        {
+            ComposeResult::Pending => return None,
+            ComposeResult::Composed(text) => return Some(escape_lt(&text)),
+            ComposeResult::Passthrough => {}
+        }
+
         if let Some(text) = get_special_key(&key_event.logical_key) {
             Some(self.format_key_text(text, true))
         } else {
@@ -75,6 +229,29 @@ impl KeyboardManager {
     }
 
     fn format_normal_key(&self, key_event: &KeyEvent) -> Option<String> {
+        // Windows reports AltGr as Ctrl+Alt, the produced character is what the user wants in
+        // that case, without any modifiers
+        #[cfg(target_os = "windows")]
+        if self.is_altgr(key_event) {
+            return key_event.text.as_ref().map(|text| escape_lt(text.as_str()));
+        }
+
+        // With the physical layout, keys pressed together with Ctrl or Alt are reported by their
+        // position on a US QWERTY keyboard, so that mappings like <C-w> work on AZERTY or Dvorak
+        if self.use_physical_layout() {
+            if let Some(text) = physical_key_text(&key_event.physical_key) {
+                return Some(self.format_key_text(text, extended_keys()));
+            }
+        }
+
+        // Extended keys report the unshifted key with all modifiers, so that <C-S-p> differs from
+        // <C-p> and <C-i> from <Tab>
+        if self.has_command_modifiers() && extended_keys() {
+            if let Some(text) = key_event.key_without_modifiers().to_text() {
+                return Some(self.format_key_text(text, true));
+            }
+        }
+
         // On macOs, when alt is held and alt_is_meta is set to true, then send the base key plus
         // the whole modifier state. Otherwise send the resulting character with "S-" and "M-"
         // removed.
@@ -96,43 +273,109 @@ impl KeyboardManager {
             .map(|text| self.format_key_text(text.as_str(), false))
     }
 
+    fn use_physical_layout(&self) -> bool {
+        SETTINGS.get::<KeyboardSettings>().layout == KeyboardLayout::Physical
+            && self.has_command_modifiers()
+    }
+
+    fn has_command_modifiers(&self) -> bool {
+        has_command_modifiers(self.modifiers.state())
+    }
+
+    fn format_extended_key(&self, key_event: &KeyEvent) -> Option<String> {
+        format_extended_key(
+            &key_event.logical_key,
+            &key_event.key_without_modifiers(),
+            key_event.text.as_deref(),
+            self.modifiers.state(),
+        )
+    }
+
+    #[cfg(target_os = "windows")]
+    fn is_altgr(&self, key_event: &KeyEvent) -> bool {
+        let state = self.modifiers.state();
+        state.control_key()
+            && state.alt_key()
+            && key_event
+                .text
+                .as_ref()
+                .map(|text| !text.chars().any(char::is_control))
+                .unwrap_or(false)
+    }
+
     fn format_key_text(&self, text: &str, is_special: bool) -> String {
-        let modifiers = self.format_modifier_string(is_special);
-        // < needs to be formatted as a special character, but note that it's not threated as a
-        // special key for the modifier formatting, so S- and -M are still potentially stripped
-        let (text, is_special) = if text == "<" {
-            ("lt", true)
-        } else {
-            (text, is_special)
-        };
-        if modifiers.is_empty() {
-            if is_special {
-                format!("<{text}>")
-            } else {
-                text.to_string()
-            }
-        } else {
-            format!("<{modifiers}{text}>")
-        }
+        format_key_text(text, is_special, self.modifiers.state())
     }
 
     pub fn format_modifier_string(&self, is_special: bool) -> String {
-        // Is special is used for special keys so that all modifiers are always included
-        // It's also true with alt_is_meta is set to true.
-        // When the key is not special, shift is removed, since the base character is already
-        // shifted. Furthermore on macOS, meta is additionally removed when alt_is_meta is set to false
-        let shift = or_empty(self.modifiers.state().shift_key() && is_special, "S-");
-        let ctrl = or_empty(self.modifiers.state().control_key(), "C-");
-        let alt = or_empty(
-            self.modifiers.state().alt_key() && (use_alt() || is_special),
-            "M-",
-        );
-        let logo = or_empty(self.modifiers.state().super_key(), "D-");
+        format_modifier_string(is_special, self.modifiers.state())
+    }
+}
+
+/// Whether Ctrl or Alt, when it's used as Meta, is held.
+fn has_command_modifiers(modifiers: ModifiersState) -> bool {
+    modifiers.control_key() || (modifiers.alt_key() && use_alt())
+}
 
-        shift.to_owned() + ctrl + alt + logo
+/// Formats a key with all its modifiers, without dead key or compose handling. `unmodified_key` is
+/// the key as it's pressed without any modifiers.
+fn format_extended_key(
+    logical_key: &Key,
+    unmodified_key: &Key,
+    text: Option<&str>,
+    modifiers: ModifiersState,
+) -> Option<String> {
+    if let Some(special) = get_special_key(logical_key) {
+        return Some(format_key_text(special, true, modifiers));
+    }
+    let unmodified_text = unmodified_key.to_text()?;
+    if has_command_modifiers(modifiers) {
+        Some(format_key_text(unmodified_text, true, modifiers))
+    } else {
+        Some(format_key_text(
+            text.unwrap_or(unmodified_text),
+            false,
+            modifiers,
+        ))
     }
 }
 
+fn format_key_text(text: &str, is_special: bool, modifiers: ModifiersState) -> String {
+    let modifiers = format_modifier_string(is_special, modifiers);
+    // < needs to be formatted as a special character, but note that it's not threated as a
+    // special key for the modifier formatting, so S- and -M are still potentially stripped
+    let (text, is_special) = if text == "<" {
+        ("lt", true)
+    } else {
+        (text, is_special)
+    };
+    if modifiers.is_empty() {
+        if is_special {
+            format!("<{text}>")
+        } else {
+            text.to_string()
+        }
+    } else {
+        format!("<{modifiers}{text}>")
+    }
+}
+
+fn format_modifier_string(is_special: bool, modifiers: ModifiersState) -> String {
+    // Is special is used for special keys so that all modifiers are always included
+    // It's also true with alt_is_meta is set to true.
+    // When the key is not special, shift is removed, since the base character is already
+    // shifted. Furthermore on macOS, meta is additionally removed when alt_is_meta is set to false
+    let shift = or_empty(modifiers.shift_key() && is_special, "S-");
+    let ctrl = or_empty(modifiers.control_key(), "C-");
+    let alt = or_empty(modifiers.alt_key() && (use_alt() || is_special), "M-");
+    let logo = or_empty(
+        modifiers.super_key() && SETTINGS.get::<KeyboardSettings>().forward_super,
+        "D-",
+    );
+
+    shift.to_owned() + ctrl + alt + logo
+}
+
 fn or_empty(condition: bool, text: &str) -> &str {
     if condition {
         text
@@ -154,7 +397,103 @@ fn use_alt() -> bool {
     settings.macos_alt_is_meta
 }
 
-fn get_special_key(key: &Key) -> Option<&str> {
+fn extended_keys() -> bool {
+    SETTINGS.get::<KeyboardSettings>().extended_keys
+}
+
+fn forward_super(key: &Key) -> bool {
+    SETTINGS.get::<KeyboardSettings>().forward_super && !is_os_reserved_super_key(key)
+}
+
+// Super combinations the OS reacts to itself. They are never sent to Neovim, since the key would
+// otherwise be handled twice.
+#[cfg(target_os = "macos")]
+fn is_os_reserved_super_key(key: &Key) -> bool {
+    match key {
+        Key::Character(text) => matches!(text.as_str(), "q" | "h" | "m" | "`"),
+        Key::Tab => true,
+        _ => false,
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn is_os_reserved_super_key(key: &Key) -> bool {
+    match key {
+        Key::Character(text) => matches!(text.as_str(), "l" | "d" | "e" | "r"),
+        Key::Tab => true,
+        _ => false,
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn is_os_reserved_super_key(_key: &Key) -> bool {
+    false
+}
+
+// Text committed by the IME or tools like clipboard managers, which is more than someone typed
+fn is_paste(text: &str) -> bool {
+    text.contains(['\n', '\r']) || text.chars().count() > PASTE_MIN_CHARACTERS
+}
+
+fn escape_lt(text: &str) -> String {
+    text.replace('<', "<lt>")
+}
+
+// Maps a physical key to the character it produces on a US QWERTY keyboard without any modifiers
+fn physical_key_text(key_code: &KeyCode) -> Option<&'static str> {
+    match key_code {
+        KeyCode::KeyA => Some("a"),
+        KeyCode::KeyB => Some("b"),
+        KeyCode::KeyC => Some("c"),
+        KeyCode::KeyD => Some("d"),
+        KeyCode::KeyE => Some("e"),
+        KeyCode::KeyF => Some("f"),
+        KeyCode::KeyG => Some("g"),
+        KeyCode::KeyH => Some("h"),
+        KeyCode::KeyI => Some("i"),
+        KeyCode::KeyJ => Some("j"),
+        KeyCode::KeyK => Some("k"),
+        KeyCode::KeyL => Some("l"),
+        KeyCode::KeyM => Some("m"),
+        KeyCode::KeyN => Some("n"),
+        KeyCode::KeyO => Some("o"),
+        KeyCode::KeyP => Some("p"),
+        KeyCode::KeyQ => Some("q"),
+        KeyCode::KeyR => Some("r"),
+        KeyCode::KeyS => Some("s"),
+        KeyCode::KeyT => Some("t"),
+        KeyCode::KeyU => Some("u"),
+        KeyCode::KeyV => Some("v"),
+        KeyCode::KeyW => Some("w"),
+        KeyCode::KeyX => Some("x"),
+        KeyCode::KeyY => Some("y"),
+        KeyCode::KeyZ => Some("z"),
+        KeyCode::Digit0 => Some("0"),
+        KeyCode::Digit1 => Some("1"),
+        KeyCode::Digit2 => Some("2"),
+        KeyCode::Digit3 => Some("3"),
+        KeyCode::Digit4 => Some("4"),
+        KeyCode::Digit5 => Some("5"),
+        KeyCode::Digit6 => Some("6"),
+        KeyCode::Digit7 => Some("7"),
+        KeyCode::Digit8 => Some("8"),
+        KeyCode::Digit9 => Some("9"),
+        KeyCode::Backquote => Some("`"),
+        KeyCode::Minus => Some("-"),
+        KeyCode::Equal => Some("="),
+        KeyCode::BracketLeft => Some("["),
+        KeyCode::BracketRight => Some("]"),
+        KeyCode::Backslash => Some("\\"),
+        KeyCode::Semicolon => Some(";"),
+        KeyCode::Quote => Some("'"),
+        KeyCode::Comma => Some(","),
+        KeyCode::Period => Some("."),
+        KeyCode::Slash => Some("/"),
+        _ => None,
+    }
+}
+
+pub fn get_special_key(key: &Key) -> Option<&str> {
     match key {
         Key::Backspace => Some("BS"),
         Key::Space => Some("Space"),
@@ -208,3 +547,236 @@ fn get_special_key(key: &Key) -> Option<&str> {
         _ => None,
     }
 }
+
+/// The text a key is expected to insert, for keys that simply type a character. Anything with
+/// modifiers or special meaning could be mapped, so it isn't predicted.
+fn predicted_echo(key: &Key, text: Option<&str>, modifiers: ModifiersState) -> Option<String> {
+    if modifiers.control_key() || modifiers.alt_key() || modifiers.super_key() {
+        return None;
+    }
+
+    match key {
+        Key::Character(_) => text
+            .filter(|text| text.chars().count() == 1 && !text.chars().any(char::is_control))
+            .map(String::from),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serial_test::serial;
+
+    use super::*;
+
+    fn character(text: &str) -> Key {
+        Key::Character(text.into())
+    }
+
+    #[test]
+    fn test_multiline_and_long_commits_are_pasted() {
+        assert!(!is_paste("日本語"));
+        assert!(is_paste("one\ntwo"));
+        assert!(is_paste("line\r\n"));
+        assert!(is_paste(&"a".repeat(PASTE_MIN_CHARACTERS + 1)));
+    }
+
+    #[test]
+    fn test_passthrough_without_sequence() {
+        let mut composer = KeyComposer::default();
+        assert_eq!(
+            composer.process(&character("e"), Some("e")),
+            ComposeResult::Passthrough
+        );
+        assert_eq!(
+            composer.process(&Key::Space, Some(" ")),
+            ComposeResult::Passthrough
+        );
+    }
+
+    #[test]
+    fn test_dead_key_composes_with_next_key() {
+        // Windows, X11 and macOS with the IME disabled report the dead key without text and the
+        // composed character as the text of the next key
+        let mut composer = KeyComposer::default();
+        assert_eq!(
+            composer.process(&Key::Dead(Some('´')), None),
+            ComposeResult::Pending
+        );
+        assert_eq!(
+            composer.process(&character("e"), Some("é")),
+            ComposeResult::Composed("é".to_string())
+        );
+        assert_eq!(
+            composer.process(&character("e"), Some("e")),
+            ComposeResult::Passthrough
+        );
+    }
+
+    #[test]
+    fn test_dead_key_with_shift() {
+        let mut composer = KeyComposer::default();
+        composer.process(&Key::Dead(Some('^')), None);
+        assert_eq!(composer.process(&Key::Shift, None), ComposeResult::Pending);
+        assert_eq!(
+            composer.process(&character("A"), Some("Â")),
+            ComposeResult::Composed("Â".to_string())
+        );
+    }
+
+    #[test]
+    fn test_dead_key_followed_by_space() {
+        // Space is normally sent as <Space>, but after a dead key it produces the accent itself
+        let mut composer = KeyComposer::default();
+        composer.process(&Key::Dead(Some('`')), None);
+        assert_eq!(
+            composer.process(&Key::Space, Some("`")),
+            ComposeResult::Composed("`".to_string())
+        );
+    }
+
+    #[test]
+    fn test_windows_dead_key_pressed_twice() {
+        let mut composer = KeyComposer::default();
+        composer.process(&Key::Dead(Some('´')), None);
+        assert_eq!(
+            composer.process(&Key::Dead(Some('´')), Some("´´")),
+            ComposeResult::Composed("´´".to_string())
+        );
+    }
+
+    #[test]
+    fn test_windows_dead_key_without_combination() {
+        // Windows reports both characters when the dead key can't be combined with the next key
+        let mut composer = KeyComposer::default();
+        composer.process(&Key::Dead(Some('´')), None);
+        assert_eq!(
+            composer.process(&character("q"), Some("´q")),
+            ComposeResult::Composed("´q".to_string())
+        );
+    }
+
+    #[test]
+    fn test_x11_compose_sequence() {
+        // With the compose key, the intermediate keys report their character but no text
+        let mut composer = KeyComposer::default();
+        assert_eq!(
+            composer.process(&Key::Compose, None),
+            ComposeResult::Pending
+        );
+        assert_eq!(
+            composer.process(&character("o"), None),
+            ComposeResult::Pending
+        );
+        assert_eq!(
+            composer.process(&character("e"), Some("œ")),
+            ComposeResult::Composed("œ".to_string())
+        );
+    }
+
+    #[test]
+    fn test_escape_cancels_sequence() {
+        let mut composer = KeyComposer::default();
+        composer.process(&Key::Dead(Some('¨')), None);
+        assert_eq!(composer.process(&Key::Escape, None), ComposeResult::Pending);
+        assert_eq!(
+            composer.process(&Key::Escape, None),
+            ComposeResult::Passthrough
+        );
+    }
+
+    #[test]
+    fn test_macos_ime_commit_resets_sequence() {
+        // With the IME enabled on macOS, the composed text arrives through an IME commit instead
+        let mut composer = KeyComposer::default();
+        composer.process(&Key::Dead(Some('´')), None);
+        composer.reset();
+        assert_eq!(
+            composer.process(&character("e"), Some("e")),
+            ComposeResult::Passthrough
+        );
+    }
+
+    #[test]
+    fn test_typed_characters_are_predicted() {
+        let none = ModifiersState::empty();
+        assert_eq!(
+            predicted_echo(&character("a"), Some("a"), none),
+            Some("a".to_string())
+        );
+        assert_eq!(
+            predicted_echo(&character("A"), Some("A"), ModifiersState::SHIFT),
+            Some("A".to_string())
+        );
+        assert_eq!(
+            predicted_echo(&character("é"), Some("é"), none),
+            Some("é".to_string())
+        );
+    }
+
+    #[test]
+    fn test_keys_that_could_be_mapped_are_not_predicted() {
+        let none = ModifiersState::empty();
+        assert_eq!(
+            predicted_echo(&character("a"), Some("a"), ModifiersState::CONTROL),
+            None
+        );
+        assert_eq!(
+            predicted_echo(&character("a"), Some("a"), ModifiersState::ALT),
+            None
+        );
+        assert_eq!(predicted_echo(&Key::Enter, Some("\r"), none), None);
+        assert_eq!(predicted_echo(&character("ab"), Some("ab"), none), None);
+        assert_eq!(predicted_echo(&character("a"), None, none), None);
+    }
+
+    #[test]
+    fn test_escape_lt() {
+        assert_eq!(escape_lt("<"), "<lt>");
+        assert_eq!(escape_lt("é"), "é");
+    }
+
+    #[test]
+    #[serial]
+    fn test_format_extended_key() {
+        SETTINGS.set(&KeyboardSettings {
+            macos_alt_is_meta: true,
+            forward_super: true,
+            ..Default::default()
+        });
+        let none = ModifiersState::empty();
+        let shift = ModifiersState::SHIFT;
+        let ctrl = ModifiersState::CONTROL;
+        let alt = ModifiersState::ALT;
+        let logo = ModifiersState::SUPER;
+        let ctrl_shift = ctrl | shift;
+        // The logical key, the key without modifiers, the text, the modifiers and the result.
+        // Special keys are formatted by their logical key.
+        let cases = [
+            (character("a"), "a", Some("a"), none, "a"),
+            (character("A"), "a", Some("A"), shift, "A"),
+            (character("a"), "a", Some("\u{1}"), ctrl, "<C-a>"),
+            (character("A"), "a", None, ctrl_shift, "<S-C-a>"),
+            (character("∫"), "b", Some("∫"), alt, "<M-b>"),
+            (character("s"), "s", Some("s"), logo, "<D-s>"),
+            (character("<"), ",", Some("<"), shift, "<lt>"),
+            (character("<"), ",", None, ctrl_shift, "<S-C-,>"),
+            (Key::Escape, "", None, none, "<Esc>"),
+            (Key::ArrowUp, "", None, shift, "<S-Up>"),
+            (Key::Tab, "", Some("\t"), ctrl, "<C-Tab>"),
+            (Key::F5, "", None, ctrl | alt, "<C-M-F5>"),
+        ];
+        for (logical_key, unmodified, text, modifiers, expected) in cases {
+            assert_eq!(
+                format_extended_key(&logical_key, &character(unmodified), text, modifiers),
+                Some(expected.to_string()),
+                "{logical_key:?} with {modifiers:?}"
+            );
+        }
+        // Modifiers alone don't type anything
+        assert_eq!(
+            format_extended_key(&Key::Shift, &Key::Shift, None, shift),
+            None
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use skia_safe::graphics;
+
+/// What the GUI holds in memory, for `:NeovideStats`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemoryStats {
+    pub glyph_cache_bytes: usize,
+    pub glyph_cache_limit: usize,
+    pub gpu_resources: usize,
+    pub gpu_resource_bytes: usize,
+    pub surfaces: usize,
+    pub surface_bytes: usize,
+    pub cached_shapes: usize,
+    pub cached_shape_bytes: usize,
+    pub pending_events: usize,
+}
+
+impl MemoryStats {
+    pub fn describe(&self) -> String {
+        [
+            "Neovide memory use:".to_string(),
+            format!(
+                "  Glyph cache: {} of {}",
+                format_bytes(self.glyph_cache_bytes),
+                format_bytes(self.glyph_cache_limit)
+            ),
+            format!(
+                "  GPU resources: {} in {} resources",
+                format_bytes(self.gpu_resource_bytes),
+                self.gpu_resources
+            ),
+            format!(
+                "  Window surfaces: {} in {} surfaces",
+                format_bytes(self.surface_bytes),
+                self.surfaces
+            ),
+            format!(
+                "  Shaping cache: {} in {} entries",
+                format_bytes(self.cached_shape_bytes),
+                self.cached_shapes
+            ),
+            format!(
+                "  Event buffers: {} events waiting for a listener",
+                self.pending_events
+            ),
+        ]
+        .join("\n")
+    }
+}
+
+pub fn glyph_cache_usage() -> (usize, usize) {
+    (graphics::font_cache_used(), graphics::font_cache_limit())
+}
+
+pub fn purge_glyph_cache() {
+    graphics::purge_font_cache();
+}
+
+fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_bytes_in_the_largest_unit() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(24 * 1024 * 1024), "24.0 MiB");
+    }
+
+    #[test]
+    fn describes_every_part() {
+        let stats = MemoryStats {
+            glyph_cache_bytes: 1024,
+            glyph_cache_limit: 2048,
+            gpu_resources: 3,
+            gpu_resource_bytes: 4096,
+            surfaces: 2,
+            surface_bytes: 8192,
+            cached_shapes: 42,
+            cached_shape_bytes: 16384,
+            pending_events: 5,
+        };
+        let description = stats.describe();
+        assert!(description.contains("Glyph cache: 1.0 KiB of 2.0 KiB"));
+        assert!(description.contains("Window surfaces: 8.0 KiB in 2 surfaces"));
+        assert!(description.contains("Shaping cache: 16.0 KiB in 42 entries"));
+        assert!(description.contains("Event buffers: 5 events waiting for a listener"));
+    }
+}
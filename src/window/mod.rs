@@ -1,72 +1,168 @@
+mod accessibility;
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+mod context_menu;
+mod context_recovery;
+mod cursor_area;
+mod frame_queue;
+mod frame_swap_chain;
+mod gpu_timer;
 mod keyboard_manager;
+mod memory_stats;
+mod monitor_profiles;
+mod monitor_rescue;
 mod mouse_manager;
+mod pointer_input;
+mod quake;
+mod render_suspension;
 mod renderer;
+mod screen_recorder;
 mod settings;
+mod shortcuts;
+mod split_separators;
+mod title;
+mod vsync_fallback;
+mod zen_mode;
 
+#[cfg(target_os = "macos")]
+mod dock;
 #[cfg(target_os = "macos")]
 mod draw_background;
+#[cfg(target_os = "macos")]
+mod vibrancy;
+#[cfg(target_os = "linux")]
+mod x11_blur;
 
 #[cfg(target_os = "linux")]
 use std::env;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc, Arc, Mutex,
+};
 use std::time::{Duration, Instant};
+use std::{
+    sync::mpsc::{Receiver, RecvTimeoutError},
+    thread,
+};
 
-use log::trace;
+use log::{error, trace, warn};
+use skia_safe::Color;
 use tokio::sync::mpsc::UnboundedReceiver;
 use winit::{
-    dpi::{PhysicalPosition, PhysicalSize, Position},
+    dpi::{PhysicalPosition, PhysicalSize},
     event::{Event, WindowEvent},
-    event_loop::{ControlFlow, EventLoop},
+    event_loop::{ControlFlow, EventLoop, EventLoopProxy},
     window::{self, Fullscreen, Icon, Theme},
 };
 
 #[cfg(target_os = "macos")]
-use winit::platform::macos::WindowBuilderExtMacOS;
+use winit::platform::macos::{WindowBuilderExtMacOS, WindowExtMacOS};
 
+#[cfg(target_os = "macos")]
+use dock::{set_dock_icon, set_dock_progress};
 #[cfg(target_os = "macos")]
 use draw_background::draw_background;
+#[cfg(target_os = "macos")]
+use vibrancy::set_vibrancy;
+#[cfg(target_os = "linux")]
+use x11_blur::set_x11_blur;
 
 #[cfg(target_os = "linux")]
 use winit::platform::wayland::WindowBuilderExtWayland;
 #[cfg(target_os = "linux")]
 use winit::platform::x11::WindowBuilderExtX11;
 
-use image::{load_from_memory, GenericImageView, Pixel};
+use accessibility::AccessibilityTree;
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+use context_menu::ContextMenu;
+use context_recovery::{ContextRecovery, RecoverableContext, RecoveryStatus};
+use cursor_area::CursorArea;
+use frame_queue::FrameQueue;
+use frame_swap_chain::{FrameDescription, FrameSwapChain};
+use gpu_timer::GpuTimer;
+use image::{load_from_memory, DynamicImage, GenericImageView, Pixel};
 use keyboard_manager::KeyboardManager;
+use memory_stats::{glyph_cache_usage, purge_glyph_cache, MemoryStats};
+use monitor_rescue::{MonitorInfo, MonitorRescue, WindowPlacement};
 use mouse_manager::MouseManager;
+use quake::QuakeMode;
+use render_suspension::{RenderState, RenderSuspension};
 use renderer::SkiaRenderer;
+use screen_recorder::ScreenRecorder;
+use title::{format_title, TitleInfo};
+use vsync_fallback::VsyncFallback;
+use zen_mode::zen_padding;
 
+#[cfg(target_os = "windows")]
+use crate::windows_utils::{windows_set_backdrop, windows_set_taskbar_progress};
 use crate::{
-    bridge::{ParallelCommand, UiCommand},
+    bridge::{InitialGridSize, ParallelCommand, UiCommand},
     cmd_line::CmdLineSettings,
     dimensions::Dimensions,
     editor::EditorCommand,
     event_aggregator::EVENT_AGGREGATOR,
     frame::Frame,
+    instances,
+    latency_tracker::LATENCY_TRACKER,
     profiling::{
         emit_frame_mark, tracy_create_gpu_context, tracy_gpu_collect, tracy_gpu_zone, tracy_zone,
     },
     redraw_scheduler::REDRAW_SCHEDULER,
     renderer::Renderer,
-    renderer::WindowPadding,
-    renderer::{build_context, WindowedContext},
+    renderer::{build_context, build_window, is_context_lost_error, PresentMode, WindowedContext},
+    renderer::{ImageCommand, RendererSettings, UserCommand, WindowPadding},
     running_tracker::*,
     settings::{
-        load_last_window_settings, save_window_size, PersistentWindowSettings,
-        DEFAULT_WINDOW_GEOMETRY, SETTINGS,
+        load_last_background, load_last_window_settings, save_window_size, EditorConfig,
+        PersistentWindowSettings, DEFAULT_WINDOW_GEOMETRY, SETTINGS,
     },
+    startup_time::STARTUP_TIME,
 };
-pub use settings::{KeyboardSettings, WindowSettings};
+pub use accessibility::{AccessibilityUpdate, AccessibleGrid};
+pub use keyboard_manager::get_special_key;
+pub use settings::{KeyboardLayout, KeyboardSettings, WindowSettings};
+pub use shortcuts::{is_terminal_shortcut, ShortcutSettings, ZOOM_STEP};
 
 static ICON: &[u8] = include_bytes!("../../assets/neovide.ico");
 
 const MIN_WINDOW_WIDTH: u64 = 20;
 const MIN_WINDOW_HEIGHT: u64 = 6;
+// Monitors don't announce being connected, so they are checked every now and then
+const MONITOR_CHECK_INTERVAL: Duration = Duration::from_secs(1);
 
 #[derive(Clone, Debug)]
 pub enum WindowCommand {
     TitleChanged(String),
+    IconChanged(String),
+    SetProgress(Option<f64>),
+    SetContextMenuItems(Vec<(String, String)>),
+    BufferInfoChanged {
+        filename: String,
+        cwd: String,
+        modified: bool,
+    },
     SetMouseEnabled(bool),
     ListAvailableFonts,
+    AccessibilityUpdate(AccessibilityUpdate),
+    FocusWindow,
+    StartRecording(String),
+    StopRecording,
+    BackgroundChanged(String),
+    ShowSettings,
+    ShowCommandPalette(Vec<UserCommand>),
+    /// The files are relative to `cwd`
+    ShowQuickOpen {
+        cwd: String,
+        files: Vec<String>,
+    },
+    Image(ImageCommand),
+    PreviewContent {
+        filetype: String,
+        lines: Vec<String>,
+    },
+    PreviewTopLine(u64),
+    ToggleTerminal,
+    /// Shows the memory use, after dropping the caches when the action is `trim`
+    MemoryStats(String),
 }
 
 pub struct WinitWindowWrapper {
@@ -75,15 +171,111 @@ pub struct WinitWindowWrapper {
     renderer: Renderer,
     keyboard_manager: KeyboardManager,
     mouse_manager: MouseManager,
+    title_info: TitleInfo,
     title: String,
     fullscreen: bool,
     font_changed_last_frame: bool,
     saved_inner_size: PhysicalSize<u32>,
     saved_grid_size: Option<Dimensions>,
+    initial_grid_size_sent: bool,
     size_at_startup: PhysicalSize<u32>,
     maximized_at_startup: bool,
     window_command_receiver: UnboundedReceiver<WindowCommand>,
     ime_enabled: bool,
+    window_icon: String,
+    present_mode: String,
+    srgb: bool,
+    typewriter_mode: bool,
+    ui_extensions: [(&'static str, bool); 4],
+    has_focus: bool,
+    window_blurred: bool,
+    window_blur_material: String,
+    // Shared with the event loop, see `create_window`
+    quake_mode: Arc<Mutex<QuakeMode>>,
+    cursor_area: CursorArea,
+    accessibility_tree: Arc<Mutex<AccessibilityTree>>,
+    screen_recorder: ScreenRecorder,
+    context_recovery: ContextRecovery,
+    render_suspension: RenderSuspension,
+    gpu_timer: GpuTimer,
+    frame_queue: FrameQueue,
+    vsync_fallback: VsyncFallback,
+    monitor_rescue: MonitorRescue,
+    last_monitor_check: Instant,
+    last_geometry_save: Instant,
+    monitor_refresh_rate: Option<f32>,
+    os_theme: Option<Theme>,
+    background_theme: Option<Theme>,
+    // The entries are handed to the context menu on the event loop, which can be stuck showing it
+    #[cfg(any(target_os = "windows", target_os = "macos"))]
+    context_menu_items: mpsc::Sender<Vec<(String, String)>>,
+    // Whether the command palette or the settings panel takes the mouse, for the context menu
+    overlay_open: Arc<AtomicBool>,
+    frame: FrameDescription,
+    event_loop_proxy: EventLoopProxy<()>,
+    main_thread_settings: (String, String),
+    // AppKit only allows the dock and the views to be changed from the main thread
+    #[cfg(target_os = "macos")]
+    appkit_commands: mpsc::Sender<AppKitCommand>,
+}
+
+/// Changes to the dock and the views of the window, applied by the event loop on the main thread.
+#[cfg(target_os = "macos")]
+enum AppKitCommand {
+    SetVibrancy(bool),
+    SetDockIcon(String),
+    SetDockProgress(Option<f64>),
+}
+
+/// The GL context together with the skia context and surface drawing with it.
+struct GpuContext<'a> {
+    windowed_context: &'a mut WindowedContext,
+    skia_renderer: &'a mut SkiaRenderer,
+}
+
+impl RecoverableContext for GpuContext<'_> {
+    fn is_lost(&mut self) -> bool {
+        self.windowed_context.is_lost() || self.skia_renderer.gr_context.abandoned()
+    }
+
+    fn recreate(&mut self) -> Result<(), String> {
+        let srgb = self.windowed_context.srgb();
+        self.replace_context(srgb)
+    }
+}
+
+impl GpuContext<'_> {
+    /// Replaces the GL context, requesting sRGB or not, together with the skia context drawing
+    /// with it. When the GL context can't be created, the current one is kept.
+    fn replace_context(&mut self, srgb: bool) -> Result<(), String> {
+        self.windowed_context
+            .recreate(srgb)
+            .map_err(|error| error.to_string())?;
+        self.rebuild_skia_renderer();
+        Ok(())
+    }
+
+    /// Creates the skia context again, for a GL context that was replaced.
+    fn rebuild_skia_renderer(&mut self) {
+        // The resources of the old context are gone with it, so they can't be freed
+        self.skia_renderer.gr_context.abandon();
+        *self.skia_renderer = SkiaRenderer::new(self.windowed_context);
+    }
+}
+
+/// Shows the outcome of a command in Neovim's message area.
+fn show_result(result: Result<String, String>) {
+    let (text, is_error) = match result {
+        Ok(text) => (text, false),
+        Err(text) => {
+            error!("{}", text);
+            (text, true)
+        }
+    };
+    EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::ShowMessage {
+        text,
+        is_error,
+    }));
 }
 
 pub fn set_background(background: &str) {
@@ -92,6 +284,53 @@ pub fn set_background(background: &str) {
     )));
 }
 
+fn theme_name(theme: Theme) -> &'static str {
+    match theme {
+        Theme::Light => "light",
+        Theme::Dark => "dark",
+    }
+}
+
+/// The background Neovim will likely draw, from the command line or config file, or else the one of
+/// the last session.
+fn startup_background() -> Option<Color> {
+    let background = SETTINGS
+        .get::<CmdLineSettings>()
+        .background
+        .or_else(load_last_background)?;
+    match background.parse::<csscolorparser::Color>() {
+        Ok(color) => {
+            let [red, green, blue, _] = color.to_rgba8();
+            Some(Color::from_rgb(red, green, blue))
+        }
+        Err(error) => {
+            warn!("Invalid background color {}: {}", background, error);
+            None
+        }
+    }
+}
+
+fn background_theme(background: Color) -> Theme {
+    // The perceived brightness, like 'background' is guessed by Neovim
+    let luma = 0.299 * background.r() as f32
+        + 0.587 * background.g() as f32
+        + 0.114 * background.b() as f32;
+    if luma < 128.0 {
+        Theme::Dark
+    } else {
+        Theme::Light
+    }
+}
+
+fn background_hex(background: Color) -> String {
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        background.r(),
+        background.g(),
+        background.b()
+    )
+}
+
 impl WinitWindowWrapper {
     pub fn toggle_fullscreen(&mut self) {
         let window = self.windowed_context.window();
@@ -122,6 +361,296 @@ impl WinitWindowWrapper {
         if self.ime_enabled != ime_enabled {
             self.set_ime(ime_enabled);
         }
+
+        self.update_title();
+
+        // The hotkey manager and the window background belong to the thread of the event loop, so
+        // it's woken up to apply them
+        let main_thread_settings = {
+            let settings = SETTINGS.get::<WindowSettings>();
+            (settings.quake_hotkey, settings.background_color)
+        };
+        if self.main_thread_settings != main_thread_settings {
+            self.main_thread_settings = main_thread_settings;
+            self.event_loop_proxy.send_event(()).ok();
+        }
+
+        let window_icon = { SETTINGS.get::<WindowSettings>().window_icon };
+
+        if self.window_icon != window_icon {
+            self.set_window_icon(&window_icon);
+            self.window_icon = window_icon;
+        }
+
+        let typewriter_mode = { SETTINGS.get::<RendererSettings>().typewriter_mode };
+
+        if self.typewriter_mode != typewriter_mode {
+            EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::SetTypewriterMode(
+                typewriter_mode,
+            )));
+            self.typewriter_mode = typewriter_mode;
+        }
+
+        let ui_extensions = { SETTINGS.get::<WindowSettings>().ui_extensions() };
+
+        for (&(name, enabled), (_, previous)) in ui_extensions.iter().zip(self.ui_extensions) {
+            if enabled != previous {
+                EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::SetUiOption(
+                    name.to_string(),
+                    enabled,
+                )));
+            }
+        }
+        self.ui_extensions = ui_extensions;
+
+        self.update_window_blur();
+
+        let present_mode = { SETTINGS.get::<WindowSettings>().present_mode };
+
+        if self.present_mode != present_mode {
+            self.set_present_mode(&present_mode);
+            self.present_mode = present_mode;
+        }
+
+        let srgb = { SETTINGS.get::<WindowSettings>().srgb };
+
+        if self.srgb != srgb {
+            self.set_srgb(srgb);
+            // Not retried when it failed, until the setting changes again
+            self.srgb = srgb;
+        }
+    }
+
+    /// Switches sRGB by replacing the GL context, while keeping the window. When the driver
+    /// refuses the new one, the current context stays in use.
+    fn set_srgb(&mut self, srgb: bool) {
+        let result = GpuContext {
+            windowed_context: &mut self.windowed_context,
+            skia_renderer: &mut self.skia_renderer,
+        }
+        .replace_context(srgb);
+        match result {
+            Ok(()) => self.handle_context_recreated(),
+            Err(error) => show_result(Err(format!(
+                "Could not switch sRGB {}, keeping the current context: {}",
+                if srgb { "on" } else { "off" },
+                error
+            ))),
+        }
+    }
+
+    fn handle_context_recreated(&mut self) {
+        // The window surfaces went away with the old context, so everything is drawn again
+        self.renderer
+            .handle_context_recreated(self.skia_renderer.canvas());
+        self.gpu_timer.reset();
+        self.frame_queue.reset();
+        EVENT_AGGREGATOR.send(EditorCommand::RedrawScreen);
+    }
+
+    /// Applies the blur of the current focus and fullscreen state, when it changed.
+    fn update_window_blur(&mut self) {
+        let (window_blurred, window_blur_material) = {
+            let settings = SETTINGS.get::<WindowSettings>();
+            (
+                settings.state_blurred(self.has_focus),
+                settings.window_blur_material,
+            )
+        };
+
+        if self.window_blurred != window_blurred
+            || self.window_blur_material != window_blur_material
+        {
+            self.set_window_blurred(window_blurred, &window_blur_material);
+            self.window_blurred = window_blurred;
+            self.window_blur_material = window_blur_material;
+        }
+    }
+
+    /// Blurs the desktop behind the transparent parts of the window. Materials can only be picked
+    /// on Windows 11, and on Linux only X11 compositors are asked.
+    fn set_window_blurred(&self, blurred: bool, material: &str) {
+        #[cfg(any(target_os = "windows", target_os = "linux"))]
+        let window = self.windowed_context.window();
+        #[cfg(target_os = "windows")]
+        windows_set_backdrop(window, blurred, material);
+        #[cfg(not(target_os = "windows"))]
+        let _ = material;
+        #[cfg(target_os = "macos")]
+        self.send_appkit_command(AppKitCommand::SetVibrancy(blurred));
+        #[cfg(target_os = "linux")]
+        set_x11_blur(window, blurred);
+    }
+
+    fn set_present_mode(&mut self, name: &str) {
+        let requested = if name.is_empty() {
+            PresentMode::from_vsync(SETTINGS.get::<CmdLineSettings>().vsync)
+        } else {
+            match PresentMode::parse(name) {
+                Some(present_mode) => present_mode,
+                None => {
+                    error!(
+                        "Unknown present mode {}, expected fifo, mailbox or immediate",
+                        name
+                    );
+                    return;
+                }
+            }
+        };
+        // The current mode is kept, instead of silently presenting another way than requested
+        match requested.check_supported(&self.windowed_context.supported_present_modes()) {
+            Ok(present_mode) => self.windowed_context.set_present_mode(present_mode),
+            Err(message) => show_result(Err(message)),
+        }
+    }
+
+    fn set_progress(&self, progress: Option<f64>) {
+        let progress = progress.map(|progress| progress.clamp(0.0, 100.0));
+
+        #[cfg(target_os = "macos")]
+        self.send_appkit_command(AppKitCommand::SetDockProgress(progress));
+
+        #[cfg(target_os = "windows")]
+        windows_set_taskbar_progress(self.windowed_context.window(), progress);
+
+        // There is no common progress API on other platforms
+        #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+        let _ = progress;
+    }
+
+    fn set_window_icon(&self, path: &str) {
+        let icon = if path.is_empty() {
+            default_icon()
+        } else {
+            match image::open(path) {
+                Ok(image) => create_icon(image),
+                Err(error) => {
+                    error!("Could not load window icon {}: {}", path, error);
+                    return;
+                }
+            }
+        };
+        self.windowed_context.window().set_window_icon(Some(icon));
+
+        // Windows don't have their own icon on macOS, the one in the dock is changed instead
+        #[cfg(target_os = "macos")]
+        self.send_appkit_command(AppKitCommand::SetDockIcon(path.to_string()));
+    }
+
+    #[cfg(target_os = "macos")]
+    fn send_appkit_command(&self, command: AppKitCommand) {
+        self.appkit_commands.send(command).ok();
+        self.event_loop_proxy.send_event(()).ok();
+    }
+
+    /// Saves the window size and position, which is otherwise only done on exit, so that it
+    /// survives crashes together with the session.
+    pub fn autosave_geometry(&mut self) {
+        let interval = SETTINGS.get::<WindowSettings>().session_autosave_interval;
+        if interval <= 0.0 || self.last_geometry_save.elapsed().as_secs_f32() < interval {
+            return;
+        }
+        self.last_geometry_save = Instant::now();
+        self.save_geometry();
+    }
+
+    fn save_geometry(&self) {
+        let window = self.windowed_context.window();
+        let background = self.renderer.grid_renderer.get_default_background();
+        // A minimized window has no size, so the last size it was drawn with is remembered
+        save_window_size(
+            window.is_maximized(),
+            self.saved_inner_size,
+            window.outer_position().ok(),
+            Some(background_hex(background)),
+        );
+    }
+
+    /// Moves the window onto a connected monitor when the one it was on got disconnected, and
+    /// back when that one returns. Also applies the settings for the monitor the window is on.
+    pub fn check_monitors(&mut self) {
+        if self.last_monitor_check.elapsed() < MONITOR_CHECK_INTERVAL {
+            return;
+        }
+        self.last_monitor_check = Instant::now();
+
+        let window = self.windowed_context.window();
+        let current_monitor = window.current_monitor();
+        self.monitor_refresh_rate = current_monitor
+            .as_ref()
+            .and_then(|monitor| monitor.refresh_rate_millihertz())
+            .map(|millihertz| millihertz as f32 / 1000.0);
+
+        // Moving between monitors with different DPIs may need another font size
+        if let Some(monitor) = &current_monitor {
+            let monitor_scale_factor = SETTINGS
+                .get::<WindowSettings>()
+                .monitor_profiles
+                .scale_factor(monitor.name().as_deref(), monitor.scale_factor());
+            if self
+                .renderer
+                .handle_monitor_scale_factor_change(monitor_scale_factor)
+            {
+                self.font_changed_last_frame = true;
+                EVENT_AGGREGATOR.send(EditorCommand::RedrawScreen);
+            }
+        }
+
+        // The window manager takes care of maximized and fullscreen windows
+        if self.fullscreen || window.is_maximized() {
+            return;
+        }
+        // Not every platform tells where the window is, like Wayland
+        let position = match window.outer_position() {
+            Ok(position) => position,
+            Err(_) => return,
+        };
+        let outer_size = window.outer_size();
+        let placement = WindowPlacement {
+            position,
+            size: outer_size,
+        };
+        let monitors: Vec<MonitorInfo> = window
+            .available_monitors()
+            .map(|monitor| MonitorInfo::from_handle(&monitor))
+            .collect();
+        let primary = window
+            .primary_monitor()
+            .map(|monitor| MonitorInfo::from_handle(&monitor));
+        let restore = SETTINGS.get::<WindowSettings>().restore_monitor_position;
+
+        let new_placement =
+            match self
+                .monitor_rescue
+                .update(placement, &monitors, primary.as_ref(), restore)
+            {
+                Some(new_placement) => new_placement,
+                None => return,
+            };
+        log::info!(
+            "The monitors changed, moving the window to {:?}",
+            new_placement
+        );
+        let inner_size = window.inner_size();
+        window.set_outer_position(new_placement.position);
+        window.set_inner_size(PhysicalSize::new(
+            new_placement.size.width - (outer_size.width - inner_size.width),
+            new_placement.size.height - (outer_size.height - inner_size.height),
+        ));
+
+        // The new monitor may have another DPI
+        let scale_factor = window.scale_factor();
+        self.handle_scale_factor_update(scale_factor);
+    }
+
+    /// Makes the title bar and other decorations match the background of Neovim. They follow the
+    /// OS while both agree, since forcing a theme stops some platforms from reporting changes of
+    /// the OS theme.
+    fn update_window_theme(&self) {
+        let theme = self
+            .background_theme
+            .filter(|theme| Some(*theme) != self.os_theme);
+        self.windowed_context.window().set_theme(theme);
     }
 
     #[allow(clippy::needless_collect)]
@@ -129,20 +658,126 @@ impl WinitWindowWrapper {
         tracy_zone!("handle_window_commands", 0);
         while let Ok(window_command) = self.window_command_receiver.try_recv() {
             match window_command {
-                WindowCommand::TitleChanged(new_title) => self.handle_title_changed(new_title),
+                WindowCommand::TitleChanged(new_title) => self.title_info.title = new_title,
+                WindowCommand::IconChanged(new_icon) => self.title_info.icon = new_icon,
+                WindowCommand::SetProgress(progress) => self.set_progress(progress),
+                #[cfg(any(target_os = "windows", target_os = "macos"))]
+                WindowCommand::SetContextMenuItems(items) => {
+                    self.context_menu_items.send(items).ok();
+                    self.event_loop_proxy.send_event(()).ok();
+                }
+                #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+                WindowCommand::SetContextMenuItems(_) => {}
+                WindowCommand::BufferInfoChanged {
+                    filename,
+                    cwd,
+                    modified,
+                } => {
+                    self.title_info.filename = filename;
+                    self.title_info.cwd = cwd;
+                    self.title_info.modified = modified;
+
+                    // Shows the dot in the close button
+                    #[cfg(target_os = "macos")]
+                    self.windowed_context.window().set_document_edited(modified);
+                }
                 WindowCommand::SetMouseEnabled(mouse_enabled) => {
                     self.mouse_manager.enabled = mouse_enabled
                 }
                 WindowCommand::ListAvailableFonts => self.send_font_names(),
+                WindowCommand::AccessibilityUpdate(update) => {
+                    self.accessibility_tree.lock().unwrap().update(update)
+                }
+                WindowCommand::FocusWindow => {
+                    let window = self.windowed_context.window();
+                    window.set_minimized(false);
+                    window.focus_window();
+                }
+                WindowCommand::StartRecording(path) => {
+                    show_result(self.screen_recorder.start(&path, self.frame.inner_size));
+                }
+                WindowCommand::StopRecording => show_result(self.screen_recorder.stop(show_result)),
+                WindowCommand::ShowSettings => self.renderer.show_settings(),
+                WindowCommand::ShowCommandPalette(commands) => {
+                    self.renderer.show_command_palette(commands)
+                }
+                WindowCommand::ShowQuickOpen { cwd, files } => {
+                    self.renderer.show_quick_open(cwd, files)
+                }
+                WindowCommand::Image(command) => self.renderer.handle_image_command(command),
+                WindowCommand::PreviewContent { filetype, lines } => {
+                    self.renderer.set_preview_content(filetype, lines)
+                }
+                WindowCommand::PreviewTopLine(line) => self.renderer.set_preview_top_line(line),
+                WindowCommand::ToggleTerminal => {
+                    let shell = SETTINGS.get::<WindowSettings>().terminal_shell;
+                    self.renderer.toggle_terminal(&shell, &self.title_info.cwd)
+                }
+                WindowCommand::MemoryStats(action) => show_result(self.memory_stats(&action)),
+                WindowCommand::BackgroundChanged(background) => {
+                    self.background_theme = match background.as_str() {
+                        "light" => Some(Theme::Light),
+                        "dark" => Some(Theme::Dark),
+                        _ => None,
+                    };
+                    self.update_window_theme();
+                }
             }
         }
+
+        let window = self.windowed_context.window();
+        let mut quake_mode = self.quake_mode.lock().unwrap();
+        quake_mode.handle_hotkey_events(window);
+        quake_mode.animate(window);
     }
 
-    pub fn handle_title_changed(&mut self, new_title: String) {
+    pub fn update_title(&mut self) {
+        let title_format = SETTINGS.get::<WindowSettings>().title_format;
+        let new_title = format_title(&title_format, &self.title_info);
+        if new_title == self.title {
+            return;
+        }
+
         self.title = new_title;
         self.windowed_context.window().set_title(&self.title);
     }
 
+    fn memory_stats(&mut self, action: &str) -> Result<String, String> {
+        let trimmed = match action {
+            "" => false,
+            "trim" => {
+                self.renderer.trim_caches();
+                purge_glyph_cache();
+                self.skia_renderer.gr_context.free_gpu_resources();
+                true
+            }
+            _ => return Err(format!("Unknown action {action}, only trim is supported")),
+        };
+
+        let (glyph_cache_bytes, glyph_cache_limit) = glyph_cache_usage();
+        let gpu_usage = self.skia_renderer.gr_context.resource_cache_usage();
+        let (surfaces, surface_bytes) = self.renderer.surface_memory();
+        let (cached_shapes, cached_shape_bytes) = self.renderer.cached_shapes();
+        let stats = MemoryStats {
+            glyph_cache_bytes,
+            glyph_cache_limit,
+            gpu_resources: gpu_usage.resource_count,
+            gpu_resource_bytes: gpu_usage.resource_bytes,
+            surfaces,
+            surface_bytes,
+            cached_shapes,
+            cached_shape_bytes,
+            pending_events: EVENT_AGGREGATOR.pending_unclaimed_events(),
+        };
+        let description = stats.describe();
+        REDRAW_SCHEDULER.queue_next_frame();
+        Ok(if trimmed {
+            format!("{description}\nCaches dropped")
+        } else {
+            description
+        })
+    }
+
     pub fn send_font_names(&self) {
         let font_names = self.renderer.font_names();
         EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::DisplayAvailableFonts(
@@ -151,31 +786,49 @@ impl WinitWindowWrapper {
     }
 
     pub fn handle_quit(&mut self) {
-        if SETTINGS.get::<CmdLineSettings>().server.is_none() {
-            EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::Quit));
+        let settings = SETTINGS.get::<CmdLineSettings>();
+        if settings.server.is_some() && settings.no_kill_on_exit {
+            EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::Detach));
         } else {
-            RUNNING_TRACKER.quit("window closed");
+            EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::Quit));
         }
     }
 
     pub fn handle_focus_lost(&mut self) {
+        self.has_focus = false;
+        self.update_window_blur();
+        self.cursor_area.handle_focus_lost();
+        self.vsync_fallback.pause();
         EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::FocusLost));
     }
 
     pub fn handle_focus_gained(&mut self) {
+        self.has_focus = true;
+        self.update_window_blur();
+        self.vsync_fallback.resume();
         EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::FocusGained));
         REDRAW_SCHEDULER.queue_next_frame();
     }
 
     pub fn handle_event(&mut self, event: Event<()>) {
         tracy_zone!("handle_event", 0);
-        self.keyboard_manager.handle_event(&event);
-        self.mouse_manager.handle_event(
-            &event,
-            &self.keyboard_manager,
-            &self.renderer,
-            self.windowed_context.window(),
-        );
+        // The open settings panel or command palette takes the input, instead of Neovim
+        let used_by_overlay = self.renderer.handle_overlay_event(&event);
+        self.overlay_open
+            .store(self.renderer.has_open_overlay(), Ordering::Relaxed);
+        if !used_by_overlay {
+            self.keyboard_manager.handle_event(&event);
+        }
+
+        // Right clicks showing the context menu don't get here, the event loop keeps them
+        if !used_by_overlay {
+            self.mouse_manager.handle_event(
+                &event,
+                &self.keyboard_manager,
+                &self.renderer,
+                self.windowed_context.window(),
+            );
+        }
         self.renderer.handle_event(&event);
         match event {
             Event::LoopDestroyed => {
@@ -190,12 +843,6 @@ impl WinitWindowWrapper {
             } => {
                 self.handle_quit();
             }
-            Event::WindowEvent {
-                event: WindowEvent::ScaleFactorChanged { scale_factor, .. },
-                ..
-            } => {
-                self.handle_scale_factor_update(scale_factor);
-            }
             Event::WindowEvent {
                 event: WindowEvent::DroppedFile(path),
                 ..
@@ -217,14 +864,15 @@ impl WinitWindowWrapper {
                 event: WindowEvent::ThemeChanged(theme),
                 ..
             } => {
+                self.os_theme = Some(theme);
+                EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::SetOsTheme(
+                    theme_name(theme).to_string(),
+                )));
                 let settings = SETTINGS.get::<WindowSettings>();
                 if settings.theme.as_str() == "auto" {
-                    let background = match theme {
-                        Theme::Light => "light",
-                        Theme::Dark => "dark",
-                    };
-                    set_background(background);
+                    set_background(theme_name(theme));
                 }
+                self.update_window_theme();
             }
             Event::RedrawRequested(..) | Event::WindowEvent { .. } => {
                 REDRAW_SCHEDULER.queue_next_frame()
@@ -235,23 +883,93 @@ impl WinitWindowWrapper {
 
     pub fn draw_frame(&mut self, dt: f32) {
         tracy_zone!("draw_frame");
-        let window = self.windowed_context.window();
+        self.mouse_manager
+            .update(dt, &self.keyboard_manager, &self.renderer);
+
+        let mut gpu_context = GpuContext {
+            windowed_context: &mut self.windowed_context,
+            skia_renderer: &mut self.skia_renderer,
+        };
+        match self
+            .context_recovery
+            .check(&mut gpu_context, Instant::now())
+        {
+            RecoveryStatus::Healthy => {}
+            RecoveryStatus::Recovered => self.handle_context_recreated(),
+            RecoveryStatus::Lost => {
+                REDRAW_SCHEDULER.schedule(Instant::now() + Duration::from_millis(100));
+                return;
+            }
+        }
+
+        let inner_size = self.frame.inner_size;
 
         let window_settings = SETTINGS.get::<WindowSettings>();
-        let window_padding = WindowPadding {
+        let mut window_padding = WindowPadding {
             top: window_settings.padding_top,
             left: window_settings.padding_left,
             right: window_settings.padding_right,
             bottom: window_settings.padding_bottom,
         };
+        if window_settings.zen_mode {
+            window_padding = zen_padding(
+                window_padding,
+                window_settings.zen_padding,
+                window_settings.zen_max_columns,
+                inner_size.width,
+                self.renderer.grid_renderer.font_dimensions.width,
+            );
+        }
+
+        // The preview pane takes its part of the window from the grid
+        let preview_width = if window_settings.preview {
+            let fraction = window_settings.preview_width.clamp(0.1, 0.9);
+            (inner_size.width as f32 * fraction) as u32
+        } else {
+            0
+        };
+        window_padding.right += preview_width;
+        self.renderer.preview_width = preview_width;
+
+        // And the terminal drawer from the bottom
+        let terminal_height = if self.renderer.is_terminal_open() {
+            let fraction = window_settings.terminal_height.clamp(0.1, 0.9);
+            (inner_size.height as f32 * fraction) as u32
+        } else {
+            0
+        };
+        window_padding.bottom += terminal_height;
+        self.renderer.terminal_height = terminal_height;
+
+        // And the tabline Neovim hands over from the top, one line high
+        let tab_line_height = if self.renderer.is_tab_line_visible() {
+            self.renderer.grid_renderer.font_dimensions.height as u32
+        } else {
+            0
+        };
+        window_padding.top += tab_line_height;
+        self.renderer.tab_line_height = tab_line_height;
 
         let padding_changed = window_padding != self.renderer.window_padding;
         if padding_changed {
             self.renderer.window_padding = window_padding;
         }
 
-        let new_size = window.inner_size();
-        if self.saved_inner_size != new_size || self.font_changed_last_frame || padding_changed {
+        let new_size = inner_size;
+        let resumed = match self.render_suspension.update(new_size) {
+            // The surface can't be resized to nothing, so it's kept until the window is restored
+            RenderState::Suspended => return,
+            RenderState::Resumed => {
+                REDRAW_SCHEDULER.queue_next_frame();
+                true
+            }
+            RenderState::Running => false,
+        };
+        if self.saved_inner_size != new_size
+            || self.font_changed_last_frame
+            || padding_changed
+            || resumed
+        {
             self.font_changed_last_frame = false;
             self.saved_inner_size = new_size;
 
@@ -260,31 +978,71 @@ impl WinitWindowWrapper {
         }
 
         if REDRAW_SCHEDULER.should_draw() || !SETTINGS.get::<WindowSettings>().idle {
-            let prev_cursor_position = self.renderer.get_cursor_position();
+            self.frame_queue
+                .wait_for_room(window_settings.max_queued_frames);
+            let frame_start = Instant::now();
+            let profiling = self.renderer.is_profiling();
+            if profiling {
+                self.gpu_timer.begin_frame();
+            }
             self.font_changed_last_frame =
                 self.renderer.draw_frame(self.skia_renderer.canvas(), dt);
             {
                 tracy_gpu_zone!("skia flush");
                 self.skia_renderer.gr_context.flush(None);
             }
+            if profiling {
+                self.gpu_timer.end_frame();
+                self.renderer
+                    .record_frame_timing(frame_start.elapsed(), self.gpu_timer.last_gpu_time());
+            }
+            if self.renderer.wants_last_frame() {
+                self.renderer.set_last_frame(self.skia_renderer.snapshot());
+            }
+            self.screen_recorder.capture(&mut self.skia_renderer);
             {
                 tracy_gpu_zone!("swap buffers");
-                self.windowed_context.swap_buffers().unwrap();
+                let swap_start = Instant::now();
+                if let Err(error) = self.windowed_context.swap_buffers() {
+                    if !is_context_lost_error(&error) {
+                        panic!("Could not swap buffers: {}", error);
+                    }
+                    self.context_recovery.context_lost();
+                    REDRAW_SCHEDULER.queue_next_frame();
+                } else {
+                    self.frame_queue
+                        .frame_submitted(window_settings.max_queued_frames);
+                    if self.windowed_context.present_mode() != PresentMode::Immediate {
+                        self.check_vsync(swap_start.elapsed());
+                    }
+                }
+            }
+            LATENCY_TRACKER.frame_presented();
+            if !self.renderer.is_loading() {
+                STARTUP_TIME.finish();
             }
             emit_frame_mark();
             tracy_gpu_collect();
-            let current_cursor_position = self.renderer.get_cursor_position();
-            if current_cursor_position != prev_cursor_position {
-                let font_dimensions = self.renderer.grid_renderer.font_dimensions;
-                let position = PhysicalPosition::new(
-                    current_cursor_position.x.round() as i32,
-                    current_cursor_position.y.round() as i32 + font_dimensions.height as i32,
-                );
-                self.windowed_context.window().set_ime_cursor_area(
-                    Position::Physical(position),
-                    PhysicalSize::new(100, font_dimensions.height as u32),
-                );
-            }
+            let cursor_position = self.renderer.get_cursor_position();
+            let font_dimensions = self.renderer.grid_renderer.font_dimensions;
+            self.cursor_area.update(
+                self.windowed_context.window(),
+                PhysicalPosition::new(
+                    cursor_position.x.round() as i32,
+                    cursor_position.y.round() as i32,
+                ),
+                PhysicalSize::new(font_dimensions.width as u32, font_dimensions.height as u32),
+            );
+        }
+
+        // Resize at startup happens when window is maximized or when using tiling WM
+        // which already resized window.
+        let resized_at_startup = self.maximized_at_startup || self.has_been_resized();
+
+        // Neovim only loads the config, which usually sets the font, after attaching, so this
+        // can't wait for the font
+        if !self.initial_grid_size_sent {
+            self.send_initial_grid_size(resized_at_startup);
         }
 
         // Wait until fonts are loaded, so we can set proper window size.
@@ -292,10 +1050,6 @@ impl WinitWindowWrapper {
             return;
         }
 
-        // Resize at startup happens when window is maximized or when using tiling WM
-        // which already resized window.
-        let resized_at_startup = self.maximized_at_startup || self.has_been_resized();
-
         log::trace!("Inner size: {:?}", new_size);
 
         if self.saved_grid_size.is_none() && !resized_at_startup {
@@ -303,20 +1057,47 @@ impl WinitWindowWrapper {
         }
     }
 
+    /// Tells the bridge the grid size the window ends up with, so that Neovim starts with it. It's
+    /// computed with the font of the config file, which is set before the user's init, or else with
+    /// the default font. The window is resized for the actual font once it's loaded.
+    fn send_initial_grid_size(&mut self, resized_at_startup: bool) {
+        if let Some(font) = SETTINGS.get::<EditorConfig>().font {
+            self.renderer.grid_renderer.update_font(&font);
+        }
+        let inner_size = if resized_at_startup {
+            self.frame.inner_size
+        } else {
+            self.startup_window_size()
+        };
+        let grid_size = self.grid_size_for(inner_size);
+        let grid_size =
+            if grid_size.width < MIN_WINDOW_WIDTH || grid_size.height < MIN_WINDOW_HEIGHT {
+                DEFAULT_WINDOW_GEOMETRY
+            } else {
+                grid_size
+            };
+        EVENT_AGGREGATOR.send(InitialGridSize(grid_size));
+        self.initial_grid_size_sent = true;
+    }
+
     fn init_window_size(&self) {
+        let inner_size = self.startup_window_size();
+        self.windowed_context.window().set_inner_size(inner_size);
+        // next frame will detect change in window.inner_size() and hence will
+        // handle_new_grid_size automatically
+    }
+
+    fn startup_window_size(&self) -> PhysicalSize<u32> {
         let settings = SETTINGS.get::<CmdLineSettings>();
-        log::trace!("Settings geometry {:?}", settings.geometry,);
+        log::trace!("Settings grid {:?}", settings.grid);
         log::trace!("Settings size {:?}", settings.size);
 
-        let window = self.windowed_context.window();
-        let inner_size = if let Some(size) = settings.size {
+        if let Some(size) = settings.size {
             // --size
             size.into()
-        } else if let Some(geometry) = settings.geometry {
-            // --geometry
-            self.renderer
-                .grid_renderer
-                .convert_grid_to_physical(geometry)
+        } else if let Some(grid) = settings.grid {
+            // --grid
+            self.renderer.grid_renderer.convert_grid_to_physical(grid)
         } else if let Ok(PersistentWindowSettings::Windowed {
             pixel_size: Some(size),
             ..
@@ -329,26 +1110,26 @@ impl WinitWindowWrapper {
             self.renderer
                 .grid_renderer
                 .convert_grid_to_physical(DEFAULT_WINDOW_GEOMETRY)
-        };
-        window.set_inner_size(inner_size);
-        // next frame will detect change in window.inner_size() and hence will
-        // handle_new_grid_size automatically
+        }
     }
 
-    fn handle_new_grid_size(&mut self, new_size: PhysicalSize<u32>) {
+    fn grid_size_for(&self, size: PhysicalSize<u32>) -> Dimensions {
         let window_padding = self.renderer.window_padding;
         let window_padding_width = window_padding.left + window_padding.right;
         let window_padding_height = window_padding.top + window_padding.bottom;
 
         let content_size = PhysicalSize {
-            width: new_size.width - window_padding_width,
-            height: new_size.height - window_padding_height,
+            width: size.width.saturating_sub(window_padding_width),
+            height: size.height.saturating_sub(window_padding_height),
         };
 
-        let grid_size = self
-            .renderer
+        self.renderer
             .grid_renderer
-            .convert_physical_to_grid(content_size);
+            .convert_physical_to_grid(content_size)
+    }
+
+    fn handle_new_grid_size(&mut self, new_size: PhysicalSize<u32>) {
+        let grid_size = self.grid_size_for(new_size);
 
         // Have a minimum size
         if grid_size.width < MIN_WINDOW_WIDTH || grid_size.height < MIN_WINDOW_HEIGHT {
@@ -366,41 +1147,73 @@ impl WinitWindowWrapper {
         }));
     }
 
+    /// Turns vsync off when the driver blocks too long waiting for it. The frames are then only
+    /// paced by the timer of the render thread.
+    fn check_vsync(&mut self, swap_duration: Duration) {
+        let refresh_rate = self
+            .monitor_refresh_rate
+            .unwrap_or(SETTINGS.get::<WindowSettings>().refresh_rate as f32)
+            .max(1.0);
+        let frame_length = Duration::from_secs_f32(1.0 / refresh_rate);
+        if self.vsync_fallback.record_swap(swap_duration, frame_length) {
+            self.windowed_context
+                .set_present_mode(PresentMode::Immediate);
+        }
+    }
+
+    /// Takes over the size and the scale factor the event loop last saw.
+    fn apply_frame_description(&mut self, frame: FrameDescription) {
+        if frame.scale_factor != self.frame.scale_factor {
+            self.handle_scale_factor_update(frame.scale_factor);
+        }
+        self.frame = frame;
+    }
+
     fn handle_scale_factor_update(&mut self, scale_factor: f64) {
         self.renderer.handle_os_scale_factor_change(scale_factor);
         EVENT_AGGREGATOR.send(EditorCommand::RedrawScreen);
     }
 
     fn has_been_resized(&self) -> bool {
-        self.windowed_context.window().inner_size() != self.size_at_startup
+        self.frame.inner_size != self.size_at_startup
     }
 }
 
+fn create_icon(image: DynamicImage) -> Icon {
+    let (width, height) = image.dimensions();
+    let mut rgba = Vec::with_capacity((width * height) as usize * 4);
+    for (_, _, pixel) in image.pixels() {
+        rgba.extend_from_slice(&pixel.to_rgba().0);
+    }
+    Icon::from_rgba(rgba, width, height).expect("Failed to create icon object")
+}
+
+fn default_icon() -> Icon {
+    create_icon(load_from_memory(ICON).expect("Failed to parse icon data"))
+}
+
 pub fn create_window() {
-    let icon = {
-        let icon = load_from_memory(ICON).expect("Failed to parse icon data");
-        let (width, height) = icon.dimensions();
-        let mut rgba = Vec::with_capacity((width * height) as usize * 4);
-        for (_, _, pixel) in icon.pixels() {
-            rgba.extend_from_slice(&pixel.to_rgba().0);
-        }
-        Icon::from_rgba(rgba, width, height).expect("Failed to create icon object")
-    };
+    let icon = default_icon();
 
     let event_loop = EventLoop::new();
 
     let cmd_line_settings = SETTINGS.get::<CmdLineSettings>();
 
     let mut maximized = cmd_line_settings.maximized;
-    let mut previous_position = None;
-    if let Ok(last_window_settings) = load_last_window_settings() {
-        match last_window_settings {
-            PersistentWindowSettings::Maximized => {
+    let mut previous_position = cmd_line_settings.position;
+    // Geometry given on the command line takes precedence over the remembered one
+    let explicit_geometry = cmd_line_settings.size.is_some()
+        || cmd_line_settings.grid.is_some()
+        || cmd_line_settings.position.is_some();
+    if !explicit_geometry {
+        match load_last_window_settings() {
+            Ok(PersistentWindowSettings::Maximized) => {
                 maximized = true;
             }
-            PersistentWindowSettings::Windowed { position, .. } => {
+            Ok(PersistentWindowSettings::Windowed { position, .. }) => {
                 previous_position = Some(position);
             }
+            Err(_) => {}
         }
     }
 
@@ -408,7 +1221,10 @@ pub fn create_window() {
         .with_title("Neovide")
         .with_window_icon(Some(icon))
         .with_maximized(maximized)
-        .with_transparent(true);
+        .with_transparent(true)
+        // Shown once the accessibility tree is set up, as it has to exist before the window is
+        // visible
+        .with_visible(false);
 
     let frame_decoration = cmd_line_settings.frame;
 
@@ -454,9 +1270,9 @@ pub fn create_window() {
     #[cfg(target_os = "macos")]
     let winit_window_builder = winit_window_builder.with_accepts_first_mouse(false);
 
-    let windowed_context = build_context(&cmd_line_settings, winit_window_builder, &event_loop);
+    let (window, config) = build_window(winit_window_builder, &event_loop);
+    STARTUP_TIME.record("window created");
 
-    let window = windowed_context.window();
     let initial_size = window.inner_size();
 
     // Check that window is visible in some monitor, and reposition it if not.
@@ -490,95 +1306,335 @@ pub fn create_window() {
 
     log::trace!("repositioned window: {}", did_reposition);
 
-    let scale_factor = windowed_context.window().scale_factor();
-    let renderer = Renderer::new(scale_factor);
-    let saved_inner_size = window.inner_size();
-
-    let skia_renderer = SkiaRenderer::new(&windowed_context);
-
-    let window_command_receiver = EVENT_AGGREGATOR.register_event::<WindowCommand>();
-
-    log::info!(
-        "window created (scale_factor: {:.4}, font_dimensions: {:?})",
-        scale_factor,
-        renderer.grid_renderer.font_dimensions,
-    );
-
-    let ime_enabled = { SETTINGS.get::<KeyboardSettings>().ime };
-
-    match SETTINGS.get::<WindowSettings>().theme.as_str() {
-        "light" => set_background("light"),
-        "dark" => set_background("dark"),
-        "auto" => match window.theme() {
-            Some(Theme::Light) => set_background("light"),
-            Some(Theme::Dark) => set_background("dark"),
-            None => {}
-        },
-        _ => {}
-    }
-
-    let mut window_wrapper = WinitWindowWrapper {
-        windowed_context,
-        skia_renderer,
-        renderer,
-        keyboard_manager: KeyboardManager::new(),
-        mouse_manager: MouseManager::new(),
-        title: String::from("Neovide"),
-        fullscreen: false,
-        font_changed_last_frame: false,
-        size_at_startup: initial_size,
-        maximized_at_startup: maximized,
-        saved_inner_size,
-        saved_grid_size: None,
-        window_command_receiver,
-        ime_enabled,
+    // The event loop only forwards the events to the render thread, which draws and handles the
+    // input. That way moving or resizing the window, which blocks the event loop on Windows and
+    // macOS, doesn't stop the animations or the input from Neovim. What has to stay on the thread
+    // of the event loop is shared with the render thread: the accessibility adapter and the global
+    // hotkeys. The native context menu only lives there and gets its entries through a channel. On
+    // macOS the GL context is made current on the render thread as well, only the changes to the
+    // dock and the views of the window are sent back to the event loop, as AppKit requires.
+    let accessibility_tree = Arc::new(Mutex::new(AccessibilityTree::new(&window)));
+    #[cfg(any(target_os = "windows", target_os = "macos"))]
+    let mut context_menu = ContextMenu::new();
+    #[cfg(any(target_os = "windows", target_os = "macos"))]
+    let (context_menu_items, context_menu_items_receiver) = mpsc::channel();
+    #[cfg(target_os = "macos")]
+    let (appkit_commands, appkit_commands_receiver) = mpsc::channel();
+    let quake_mode = Arc::new(Mutex::new(QuakeMode::new()));
+    let overlay_open = Arc::new(AtomicBool::new(false));
+    let frame_swap_chain = Arc::new(FrameSwapChain::default());
+    let window = Arc::new(window);
+
+    let mut frame = FrameDescription {
+        inner_size: initial_size,
+        scale_factor: window.scale_factor(),
     };
 
-    window_wrapper.set_ime(ime_enabled);
+    let (event_sender, event_receiver) = mpsc::channel();
+    {
+        let event_sender = event_sender.clone();
+        REDRAW_SCHEDULER.set_render_waker(Box::new(move || {
+            event_sender.send(Event::UserEvent(())).ok();
+        }));
+    }
 
-    tracy_create_gpu_context("main_render_context");
+    let create_render_loop = {
+        let window = window.clone();
+        let accessibility_tree = accessibility_tree.clone();
+        let quake_mode = quake_mode.clone();
+        let overlay_open = overlay_open.clone();
+        let frame_swap_chain = frame_swap_chain.clone();
+        let event_loop_proxy = event_loop.create_proxy();
+
+        move || {
+            let cmd_line_settings = SETTINGS.get::<CmdLineSettings>();
+            let windowed_context = build_context(&cmd_line_settings, window, config);
+            STARTUP_TIME.record("OpenGL context created");
+            let window = windowed_context.window();
+
+            let scale_factor = window.scale_factor();
+            let mut renderer = Renderer::new(scale_factor);
+            STARTUP_TIME.record("default font loaded");
+            let saved_inner_size = initial_size;
+
+            let mut skia_renderer = SkiaRenderer::new(&windowed_context);
+
+            // Fill the window with the expected background before showing it, instead of
+            // flashing white or black until Neovim draws
+            let os_theme = window.theme();
+            let startup_background = startup_background();
+            if let Some(background) = startup_background {
+                renderer.grid_renderer.set_default_background(background);
+                window.set_theme(
+                    Some(background_theme(background)).filter(|theme| Some(*theme) != os_theme),
+                );
+                skia_renderer.canvas().clear(background);
+                skia_renderer.gr_context.flush(None);
+                if let Err(error) = windowed_context.swap_buffers() {
+                    warn!("Could not draw the startup background: {}", error);
+                }
+            }
+            window.set_visible(true);
+            STARTUP_TIME.record("window shown");
+
+            let window_command_receiver = EVENT_AGGREGATOR.register_event::<WindowCommand>();
+
+            log::info!(
+                "window created (scale_factor: {:.4}, font_dimensions: {:?})",
+                scale_factor,
+                renderer.grid_renderer.font_dimensions,
+            );
 
-    let mut previous_frame_start = Instant::now();
+            let ime_enabled = { SETTINGS.get::<KeyboardSettings>().ime };
 
-    enum FocusedState {
-        Focused,
-        UnfocusedNotDrawn,
-        Unfocused,
+            if let Some(theme) = os_theme {
+                EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::SetOsTheme(
+                    theme_name(theme).to_string(),
+                )));
+            }
+
+            match SETTINGS.get::<WindowSettings>().theme.as_str() {
+                "light" => set_background("light"),
+                "dark" => set_background("dark"),
+                "auto" => {
+                    if let Some(theme) = os_theme {
+                        set_background(theme_name(theme));
+                    }
+                }
+                _ => {}
+            }
+
+            let mut window_wrapper = WinitWindowWrapper {
+                windowed_context,
+                skia_renderer,
+                renderer,
+                keyboard_manager: KeyboardManager::new(),
+                mouse_manager: MouseManager::new(),
+                title_info: TitleInfo {
+                    title: String::from("Neovide"),
+                    ..Default::default()
+                },
+                title: String::from("Neovide"),
+                fullscreen: false,
+                font_changed_last_frame: false,
+                size_at_startup: initial_size,
+                maximized_at_startup: maximized,
+                saved_inner_size,
+                saved_grid_size: None,
+                initial_grid_size_sent: false,
+                window_command_receiver,
+                ime_enabled,
+                window_icon: String::new(),
+                present_mode: String::new(),
+                srgb: SETTINGS.get::<CmdLineSettings>().srgb,
+                typewriter_mode: false,
+                ui_extensions: SETTINGS.get::<WindowSettings>().ui_extensions(),
+                has_focus: true,
+                window_blurred: false,
+                window_blur_material: String::new(),
+                quake_mode,
+                cursor_area: CursorArea::default(),
+                accessibility_tree,
+                screen_recorder: ScreenRecorder::default(),
+                context_recovery: ContextRecovery::new(),
+                render_suspension: RenderSuspension::default(),
+                gpu_timer: GpuTimer::new(),
+                frame_queue: FrameQueue::new(),
+                vsync_fallback: VsyncFallback::new(),
+                monitor_rescue: MonitorRescue::default(),
+                last_monitor_check: Instant::now(),
+                last_geometry_save: Instant::now(),
+                monitor_refresh_rate: None,
+                os_theme,
+                background_theme: startup_background.map(background_theme),
+                #[cfg(any(target_os = "windows", target_os = "macos"))]
+                context_menu_items,
+                overlay_open,
+                frame,
+                event_loop_proxy,
+                main_thread_settings: Default::default(),
+                #[cfg(target_os = "macos")]
+                appkit_commands,
+            };
+
+            window_wrapper.set_ime(ime_enabled);
+
+            tracy_create_gpu_context("main_render_context");
+
+            RenderLoop::new(window_wrapper, frame_swap_chain)
+        }
+    };
+
+    let spawn_result = thread::Builder::new()
+        .name("render".to_owned())
+        .spawn(move || run_render_loop(create_render_loop(), event_receiver));
+    if let Err(error) = spawn_result {
+        panic!("Could not start the render thread: {}", error);
     }
-    let mut focused = FocusedState::Focused;
+
+    #[cfg(target_os = "macos")]
+    draw_background(&window);
 
     event_loop.run(move |e, _window_target, control_flow| {
-        // Window focus changed
+        *control_flow = ControlFlow::Wait;
+
+        match &e {
+            Event::WindowEvent {
+                event: WindowEvent::Resized(size),
+                ..
+            } => {
+                frame.inner_size = *size;
+                frame_swap_chain.publish(frame);
+            }
+            Event::WindowEvent {
+                event: WindowEvent::ScaleFactorChanged { scale_factor, .. },
+                ..
+            } => {
+                frame.scale_factor = *scale_factor;
+                frame_swap_chain.publish(frame);
+            }
+            _ => {}
+        }
+
         if let Event::WindowEvent {
-            event: WindowEvent::Focused(focused_event),
+            event: window_event,
             ..
-        } = e
+        } = &e
         {
-            focused = if focused_event {
-                FocusedState::Focused
-            } else {
-                FocusedState::UnfocusedNotDrawn
-            };
+            accessibility_tree
+                .lock()
+                .unwrap()
+                .handle_event(&window, window_event);
         }
 
+        // Showing the menu blocks until an entry is picked. The render thread never waits for it,
+        // the entries are sent over
+        #[cfg(any(target_os = "windows", target_os = "macos"))]
+        {
+            let showed_context_menu =
+                !overlay_open.load(Ordering::Relaxed) && context_menu.handle_event(&e, &window);
+            context_menu.handle_menu_events();
+            if showed_context_menu {
+                return;
+            }
+        }
+
+        // Sent by the render loop when the settings or the menu entries applied here changed
+        if let Event::UserEvent(()) = e {
+            quake_mode.lock().unwrap().synchronize_settings();
+            #[cfg(any(target_os = "windows", target_os = "macos"))]
+            for items in context_menu_items_receiver.try_iter() {
+                context_menu.set_user_items(items);
+            }
+            #[cfg(target_os = "macos")]
+            {
+                draw_background(&window);
+                for command in appkit_commands_receiver.try_iter() {
+                    match command {
+                        AppKitCommand::SetVibrancy(blurred) => set_vibrancy(&window, blurred),
+                        AppKitCommand::SetDockIcon(path) => set_dock_icon(&path),
+                        AppKitCommand::SetDockProgress(progress) => set_dock_progress(progress),
+                    }
+                }
+            }
+            return;
+        }
+
+        // Only fails once the render thread is exiting the process
+        event_sender.send(e).ok();
+    });
+}
+
+enum FocusedState {
+    Focused,
+    UnfocusedNotDrawn,
+    Unfocused,
+}
+
+/// Handles the events and draws the frames, until Neovide quits.
+struct RenderLoop {
+    window_wrapper: WinitWindowWrapper,
+    frame_swap_chain: Arc<FrameSwapChain>,
+    previous_frame_start: Instant,
+    next_frame: Instant,
+    focused: FocusedState,
+}
+
+/// Runs the render loop on the render thread, sleeping until the next frame is due or the event
+/// loop sends something.
+fn run_render_loop(mut render_loop: RenderLoop, events: Receiver<Event<()>>) {
+    loop {
+        let timeout = render_loop
+            .next_frame
+            .saturating_duration_since(Instant::now());
+        let first_event = match events.recv_timeout(timeout) {
+            Ok(event) => Some(event),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+        render_loop.step(first_event.into_iter().chain(events.try_iter()));
+    }
+}
+
+impl RenderLoop {
+    fn new(window_wrapper: WinitWindowWrapper, frame_swap_chain: Arc<FrameSwapChain>) -> Self {
+        let now = Instant::now();
+        Self {
+            window_wrapper,
+            frame_swap_chain,
+            previous_frame_start: now,
+            next_frame: now,
+            focused: FocusedState::Focused,
+        }
+    }
+
+    /// Handles the events which arrived since the last step, then draws a frame if one is due.
+    fn step(&mut self, events: impl IntoIterator<Item = Event<()>>) {
+        let window_wrapper = &mut self.window_wrapper;
+
         if !RUNNING_TRACKER.is_running() {
-            let window = window_wrapper.windowed_context.window();
-            save_window_size(
-                window.is_maximized(),
-                window.inner_size(),
-                window.outer_position().ok(),
-            );
+            // Finish writing the recording, it would be unreadable otherwise
+            if window_wrapper.screen_recorder.is_recording() {
+                if let Err(error) = window_wrapper.screen_recorder.stop_and_wait() {
+                    error!("{}", error);
+                }
+            }
+
+            window_wrapper.save_geometry();
+            // Only now, quitting can still be cancelled before, like from the confirm quit dialog
+            instances::unregister_instance();
 
             std::process::exit(RUNNING_TRACKER.exit_code());
         }
 
         let frame_start = Instant::now();
 
+        if let Some(frame) = self.frame_swap_chain.take() {
+            window_wrapper.apply_frame_description(frame);
+        }
         window_wrapper.handle_window_commands();
         window_wrapper.synchronize_settings();
-        window_wrapper.handle_event(e);
+        window_wrapper.check_monitors();
+        window_wrapper.autosave_geometry();
+
+        // Everything which arrived in the meantime is handled before the frame
+        for event in events {
+            // Window focus changed
+            if let Event::WindowEvent {
+                event: WindowEvent::Focused(focused_event),
+                ..
+            } = event
+            {
+                self.focused = if focused_event {
+                    FocusedState::Focused
+                } else {
+                    FocusedState::UnfocusedNotDrawn
+                };
+            }
+            window_wrapper.handle_event(event);
+        }
 
-        let refresh_rate = match focused {
+        let refresh_rate = match self.focused {
             FocusedState::Focused | FocusedState::UnfocusedNotDrawn => {
                 SETTINGS.get::<WindowSettings>().refresh_rate as f32
             }
@@ -589,17 +1645,20 @@ pub fn create_window() {
         let expected_frame_length_seconds = 1.0 / refresh_rate;
         let frame_duration = Duration::from_secs_f32(expected_frame_length_seconds);
 
-        if frame_start - previous_frame_start > frame_duration {
-            let dt = previous_frame_start.elapsed().as_secs_f32();
+        // In low latency mode the answer to a key press is presented as soon as Neovim sent it,
+        // without waiting for the next frame
+        let immediate_frame =
+            REDRAW_SCHEDULER.take_immediate_frame() && SETTINGS.get::<WindowSettings>().low_latency;
+
+        if frame_start - self.previous_frame_start > frame_duration || immediate_frame {
+            let dt = self.previous_frame_start.elapsed().as_secs_f32();
             window_wrapper.draw_frame(dt);
-            if let FocusedState::UnfocusedNotDrawn = focused {
-                focused = FocusedState::Unfocused;
+            if let FocusedState::UnfocusedNotDrawn = self.focused {
+                self.focused = FocusedState::Unfocused;
             }
-            previous_frame_start = frame_start;
-            #[cfg(target_os = "macos")]
-            draw_background(window_wrapper.windowed_context.window());
+            self.previous_frame_start = frame_start;
         }
 
-        *control_flow = ControlFlow::WaitUntil(previous_frame_start + frame_duration)
-    });
+        self.next_frame = self.previous_frame_start + frame_duration;
+    }
 }
@@ -0,0 +1,165 @@
+use log::error;
+use rmpv::Value;
+
+use crate::settings::ParseFromValue;
+
+// The DPI of a monitor at a scale factor of 1
+const BASE_DPI: f64 = 96.0;
+
+/// A scale factor for the monitors matching a name or a DPI range.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MonitorProfile {
+    /// Part of the name of the monitor
+    pub name: Option<String>,
+    pub min_dpi: Option<f64>,
+    pub max_dpi: Option<f64>,
+    pub scale_factor: f32,
+}
+
+impl MonitorProfile {
+    fn matches(&self, name: Option<&str>, dpi: f64) -> bool {
+        let name_matches = match (&self.name, name) {
+            (Some(pattern), Some(name)) => name.contains(pattern.as_str()),
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        name_matches
+            && self.min_dpi.map_or(true, |min_dpi| dpi >= min_dpi)
+            && self.max_dpi.map_or(true, |max_dpi| dpi <= max_dpi)
+    }
+
+    fn parse(value: &Value) -> Option<MonitorProfile> {
+        let mut profile = MonitorProfile {
+            name: None,
+            min_dpi: None,
+            max_dpi: None,
+            scale_factor: 1.0,
+        };
+        for (key, value) in value.as_map()? {
+            match key.as_str()? {
+                "name" => profile.name = Some(value.as_str()?.to_string()),
+                "min_dpi" => profile.min_dpi = Some(as_f64(value)?),
+                "max_dpi" => profile.max_dpi = Some(as_f64(value)?),
+                "scale_factor" => profile.scale_factor = as_f64(value)? as f32,
+                _ => return None,
+            }
+        }
+        Some(profile)
+    }
+}
+
+fn as_f64(value: &Value) -> Option<f64> {
+    value
+        .as_f64()
+        .or_else(|| value.as_i64().map(|value| value as f64))
+}
+
+/// The monitor profiles from `g:neovide_monitor_profiles`, the first matching one applies.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MonitorProfiles(Vec<MonitorProfile>);
+
+impl MonitorProfiles {
+    /// The scale factor for a monitor, given its name and the scale factor of the OS.
+    pub fn scale_factor(&self, name: Option<&str>, os_scale_factor: f64) -> f64 {
+        let dpi = os_scale_factor * BASE_DPI;
+        self.0
+            .iter()
+            .find(|profile| profile.matches(name, dpi))
+            .map_or(1.0, |profile| profile.scale_factor.into())
+    }
+}
+
+impl ParseFromValue for MonitorProfiles {
+    fn parse_from_value(&mut self, value: Value) {
+        let profiles = value
+            .as_array()
+            .and_then(|profiles| profiles.iter().map(MonitorProfile::parse).collect());
+        match profiles {
+            Some(profiles) => *self = MonitorProfiles(profiles),
+            None => error!(
+                "Expected a list of monitor profiles, but received {:?}",
+                value
+            ),
+        }
+    }
+}
+
+impl From<MonitorProfiles> for Value {
+    fn from(profiles: MonitorProfiles) -> Self {
+        Value::Array(
+            profiles
+                .0
+                .into_iter()
+                .map(|profile| {
+                    let mut entries = Vec::new();
+                    if let Some(name) = profile.name {
+                        entries.push((Value::from("name"), Value::from(name)));
+                    }
+                    if let Some(min_dpi) = profile.min_dpi {
+                        entries.push((Value::from("min_dpi"), Value::from(min_dpi)));
+                    }
+                    if let Some(max_dpi) = profile.max_dpi {
+                        entries.push((Value::from("max_dpi"), Value::from(max_dpi)));
+                    }
+                    entries.push((
+                        Value::from("scale_factor"),
+                        Value::from(profile.scale_factor),
+                    ));
+                    Value::Map(entries)
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profiles() -> MonitorProfiles {
+        let mut profiles = MonitorProfiles::default();
+        profiles.parse_from_value(Value::Array(vec![
+            Value::Map(vec![
+                (Value::from("name"), Value::from("DELL")),
+                (Value::from("scale_factor"), Value::from(1.2)),
+            ]),
+            Value::Map(vec![
+                (Value::from("min_dpi"), Value::from(150)),
+                (Value::from("scale_factor"), Value::from(0.8)),
+            ]),
+        ]));
+        profiles
+    }
+
+    #[test]
+    fn profiles_match_by_name_then_dpi() {
+        let profiles = profiles();
+        assert_eq!(
+            profiles.scale_factor(Some("DELL U2720Q"), 2.0),
+            1.2f32 as f64
+        );
+        assert_eq!(
+            profiles.scale_factor(Some("Built-in Retina"), 2.0),
+            0.8f32 as f64
+        );
+        assert_eq!(profiles.scale_factor(Some("LG"), 1.0), 1.0);
+        assert_eq!(profiles.scale_factor(None, 1.0), 1.0);
+    }
+
+    #[test]
+    fn invalid_profiles_are_ignored() {
+        let mut profiles = profiles();
+        profiles.parse_from_value(Value::Array(vec![Value::Map(vec![(
+            Value::from("size"),
+            Value::from(12),
+        )])]));
+        assert_eq!(profiles, self::profiles());
+    }
+
+    #[test]
+    fn profiles_roundtrip() {
+        let mut parsed = MonitorProfiles::default();
+        parsed.parse_from_value(profiles().into());
+        assert_eq!(parsed, profiles());
+    }
+}
@@ -0,0 +1,252 @@
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    monitor::MonitorHandle,
+};
+
+// How much of the window has to be on a monitor to still be reachable
+const MIN_VISIBLE_WIDTH: i32 = 64;
+const MIN_VISIBLE_HEIGHT: i32 = 32;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MonitorInfo {
+    pub name: Option<String>,
+    pub position: PhysicalPosition<i32>,
+    pub size: PhysicalSize<u32>,
+}
+
+impl MonitorInfo {
+    pub fn from_handle(monitor: &MonitorHandle) -> Self {
+        Self {
+            name: monitor.name(),
+            position: monitor.position(),
+            size: monitor.size(),
+        }
+    }
+}
+
+/// The outer position and size of the window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WindowPlacement {
+    pub position: PhysicalPosition<i32>,
+    pub size: PhysicalSize<u32>,
+}
+
+impl WindowPlacement {
+    /// How much of the window is on the monitor, as width and height.
+    fn visible_on(&self, monitor: &MonitorInfo) -> (i32, i32) {
+        let left = self.position.x.max(monitor.position.x);
+        let right = (self.position.x + self.size.width as i32)
+            .min(monitor.position.x + monitor.size.width as i32);
+        let top = self.position.y.max(monitor.position.y);
+        let bottom = (self.position.y + self.size.height as i32)
+            .min(monitor.position.y + monitor.size.height as i32);
+        ((right - left).max(0), (bottom - top).max(0))
+    }
+
+    fn is_visible_on(&self, monitor: &MonitorInfo) -> bool {
+        let (width, height) = self.visible_on(monitor);
+        width >= MIN_VISIBLE_WIDTH.min(self.size.width as i32)
+            && height >= MIN_VISIBLE_HEIGHT.min(self.size.height as i32)
+    }
+}
+
+/// Where the window was before it was moved off a monitor that went away.
+#[derive(Clone, Debug)]
+struct Displacement {
+    monitor: MonitorInfo,
+    placement: WindowPlacement,
+    rescue_monitor: MonitorInfo,
+}
+
+/// Moves the window onto a connected monitor when the monitor it's on is disconnected, like when
+/// undocking a laptop, and back when it returns.
+#[derive(Default)]
+pub struct MonitorRescue {
+    last_monitor: Option<MonitorInfo>,
+    displacement: Option<Displacement>,
+}
+
+impl MonitorRescue {
+    /// Checks the window against the connected monitors, returns where it has to be moved, if
+    /// anywhere. The window is rescued to `primary`, or the first monitor without a primary one.
+    pub fn update(
+        &mut self,
+        window: WindowPlacement,
+        monitors: &[MonitorInfo],
+        primary: Option<&MonitorInfo>,
+        restore: bool,
+    ) -> Option<WindowPlacement> {
+        if let Some(displacement) = &self.displacement {
+            if !window.is_visible_on(&displacement.rescue_monitor) {
+                // Moved away by the user, who decided where it goes now
+                self.displacement = None;
+            } else if let Some(monitor) = monitors
+                .iter()
+                .find(|monitor| monitor.name.is_some() && monitor.name == displacement.monitor.name)
+                .filter(|_| restore)
+            {
+                let displacement = self.displacement.take().unwrap();
+                // The monitor may come back at another place in the layout
+                let position = PhysicalPosition::new(
+                    displacement.placement.position.x - displacement.monitor.position.x
+                        + monitor.position.x,
+                    displacement.placement.position.y - displacement.monitor.position.y
+                        + monitor.position.y,
+                );
+                self.last_monitor = Some(monitor.clone());
+                return Some(WindowPlacement {
+                    position,
+                    size: displacement.placement.size,
+                });
+            }
+        }
+
+        if let Some(monitor) = monitors
+            .iter()
+            .filter(|monitor| window.is_visible_on(monitor))
+            .max_by_key(|monitor| {
+                let (width, height) = window.visible_on(monitor);
+                width * height
+            })
+        {
+            self.last_monitor = Some(monitor.clone());
+            return None;
+        }
+
+        // Without any monitor, like while switching them, there's nowhere to go yet
+        let rescue_monitor = primary.or_else(|| monitors.first())?;
+        if let Some(monitor) = self.last_monitor.take() {
+            self.displacement = Some(Displacement {
+                monitor,
+                placement: window,
+                rescue_monitor: rescue_monitor.clone(),
+            });
+        }
+        self.last_monitor = Some(rescue_monitor.clone());
+        Some(fit_on_monitor(window, rescue_monitor))
+    }
+}
+
+/// Centers the window on the monitor, shrinking it to the size of the monitor if needed.
+fn fit_on_monitor(window: WindowPlacement, monitor: &MonitorInfo) -> WindowPlacement {
+    let size = PhysicalSize::new(
+        window.size.width.min(monitor.size.width),
+        window.size.height.min(monitor.size.height),
+    );
+    let position = PhysicalPosition::new(
+        monitor.position.x + (monitor.size.width - size.width) as i32 / 2,
+        monitor.position.y + (monitor.size.height - size.height) as i32 / 2,
+    );
+    WindowPlacement { position, size }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(name: &str, x: i32, width: u32, height: u32) -> MonitorInfo {
+        MonitorInfo {
+            name: Some(name.to_string()),
+            position: PhysicalPosition::new(x, 0),
+            size: PhysicalSize::new(width, height),
+        }
+    }
+
+    fn placement(x: i32, y: i32, width: u32, height: u32) -> WindowPlacement {
+        WindowPlacement {
+            position: PhysicalPosition::new(x, y),
+            size: PhysicalSize::new(width, height),
+        }
+    }
+
+    #[test]
+    fn visible_window_stays() {
+        let laptop = monitor("laptop", 0, 1920, 1080);
+        let external = monitor("external", 1920, 2560, 1440);
+        let mut rescue = MonitorRescue::default();
+        let window = placement(2000, 100, 1600, 1200);
+        assert_eq!(rescue.update(window, &[laptop, external], None, true), None);
+    }
+
+    #[test]
+    fn window_is_rescued_and_restored() {
+        let laptop = monitor("laptop", 0, 1920, 1080);
+        let external = monitor("external", 1920, 2560, 1440);
+        let mut rescue = MonitorRescue::default();
+        let window = placement(2000, 100, 2400, 1200);
+        rescue.update(window, &[laptop.clone(), external.clone()], None, true);
+
+        // Undocked
+        let rescued = rescue
+            .update(window, &[laptop.clone()], Some(&laptop), true)
+            .unwrap();
+        assert_eq!(rescued, placement(0, 0, 1920, 1080));
+
+        // Still undocked, nothing changes
+        assert_eq!(
+            rescue.update(rescued, &[laptop.clone()], Some(&laptop), true),
+            None
+        );
+
+        // Docked again, with the external monitor now on the left
+        let external = MonitorInfo {
+            position: PhysicalPosition::new(-2560, 0),
+            ..external
+        };
+        let restored = rescue.update(rescued, &[laptop.clone(), external], Some(&laptop), true);
+        assert_eq!(restored, Some(placement(-2480, 100, 2400, 1200)));
+    }
+
+    #[test]
+    fn restoring_can_be_disabled() {
+        let laptop = monitor("laptop", 0, 1920, 1080);
+        let external = monitor("external", 1920, 2560, 1440);
+        let mut rescue = MonitorRescue::default();
+        let window = placement(2000, 100, 800, 600);
+        rescue.update(window, &[laptop.clone(), external.clone()], None, false);
+
+        let rescued = rescue
+            .update(window, &[laptop.clone()], None, false)
+            .unwrap();
+        assert_eq!(rescued, placement(560, 240, 800, 600));
+        assert_eq!(
+            rescue.update(rescued, &[laptop, external], None, false),
+            None
+        );
+    }
+
+    #[test]
+    fn moving_the_rescued_window_forgets_the_old_place() {
+        let laptop = monitor("laptop", 0, 1920, 1080);
+        let external = monitor("external", 1920, 2560, 1440);
+        let projector = monitor("projector", -1280, 1280, 720);
+        let mut rescue = MonitorRescue::default();
+        let window = placement(2000, 100, 800, 600);
+        rescue.update(window, &[laptop.clone(), external.clone()], None, true);
+        rescue.update(
+            window,
+            &[laptop.clone(), projector.clone()],
+            Some(&laptop),
+            true,
+        );
+
+        let moved = placement(-1200, 50, 800, 600);
+        assert_eq!(
+            rescue.update(moved, &[laptop.clone(), projector.clone()], None, true),
+            None
+        );
+        assert_eq!(
+            rescue.update(moved, &[laptop, projector, external], None, true),
+            None
+        );
+    }
+
+    #[test]
+    fn window_waits_without_monitors() {
+        let mut rescue = MonitorRescue::default();
+        assert_eq!(
+            rescue.update(placement(0, 0, 800, 600), &[], None, true),
+            None
+        );
+    }
+}
@@ -11,15 +11,17 @@ use winit::{
         DeviceId, ElementState, Event, MouseButton, MouseScrollDelta, Touch, TouchPhase,
         WindowEvent,
     },
-    window::Window,
+    window::{CursorIcon, Window},
 };
 
 use crate::{
-    bridge::{SerialCommand, UiCommand},
+    bridge::{ParallelCommand, SerialCommand, UiCommand},
     event_aggregator::EVENT_AGGREGATOR,
     renderer::{Renderer, WindowDrawDetails},
     settings::SETTINGS,
     window::keyboard_manager::KeyboardManager,
+    window::pointer_input::{is_stylus, ScrollInertia},
+    window::split_separators::{separator_at, SeparatorKind},
     window::WindowSettings,
 };
 
@@ -50,6 +52,28 @@ fn to_grid_coords(
     )
 }
 
+/// Resizes the windows next to the dragged separator, so that it follows the mouse.
+fn drag_separator(split_drag: &mut SplitDrag, cell: PhysicalPosition<u32>) {
+    let (current, target) = match split_drag.kind {
+        SeparatorKind::Vertical => (split_drag.position.x, cell.x),
+        SeparatorKind::Horizontal => (split_drag.position.y, cell.y),
+    };
+    if current == target {
+        return;
+    }
+
+    EVENT_AGGREGATOR.send(UiCommand::Serial(SerialCommand::ResizeSplit {
+        vertical: split_drag.kind == SeparatorKind::Vertical,
+        separator: split_drag.position.into(),
+        target,
+    }));
+    match split_drag.kind {
+        SeparatorKind::Vertical => split_drag.position.x = target,
+        SeparatorKind::Horizontal => split_drag.position.y = target,
+    }
+    split_drag.has_moved = true;
+}
+
 fn mouse_button_to_button_text(mouse_button: &MouseButton) -> Option<String> {
     match mouse_button {
         MouseButton::Left => Some("left".to_owned()),
@@ -59,6 +83,15 @@ fn mouse_button_to_button_text(mouse_button: &MouseButton) -> Option<String> {
     }
 }
 
+/// A window separator being dragged to resize the windows next to it.
+#[derive(Debug)]
+struct SplitDrag {
+    kind: SeparatorKind,
+    /// Where the separator is now, on the root grid
+    position: PhysicalPosition<u32>,
+    has_moved: bool,
+}
+
 #[derive(Debug)]
 struct TouchTrace {
     start_time: Instant,
@@ -79,9 +112,14 @@ pub struct MouseManager {
 
     // the tuple allows to keep track of different fingers per device
     touch_position: HashMap<(DeviceId, u64), TouchTrace>,
+    scroll_inertia: ScrollInertia,
 
     window_details_under_mouse: Option<WindowDrawDetails>,
 
+    separator_under_mouse: Option<SeparatorKind>,
+    split_drag: Option<SplitDrag>,
+    cursor_icon: CursorIcon,
+
     mouse_hidden: bool,
     pub enabled: bool,
 }
@@ -96,7 +134,11 @@ impl MouseManager {
             drag_position: PhysicalPosition::new(0, 0),
             scroll_position: PhysicalPosition::new(0.0, 0.0),
             touch_position: HashMap::new(),
+            scroll_inertia: ScrollInertia::default(),
             window_details_under_mouse: None,
+            separator_under_mouse: None,
+            split_drag: None,
+            cursor_icon: CursorIcon::Default,
             mouse_hidden: false,
             enabled: true,
         }
@@ -117,6 +159,20 @@ impl MouseManager {
 
         let position: PhysicalPosition<f32> = PhysicalPosition::new(x as f32, y as f32);
 
+        let font_dimensions = renderer.grid_renderer.font_dimensions.into();
+        let cell = to_grid_coords(position, font_dimensions);
+        if let Some(split_drag) = &mut self.split_drag {
+            drag_separator(split_drag, cell);
+            return;
+        }
+        if self.dragging.is_none() {
+            self.separator_under_mouse = separator_at(
+                cell,
+                &renderer.window_regions,
+                renderer.grid_renderer.font_dimensions.into(),
+            );
+        }
+
         // If dragging, the relevant window (the one which we send all commands to) is the one
         // which the mouse drag started on. Otherwise its the top rendered window
         let relevant_window_details = if self.dragging.is_some() {
@@ -143,6 +199,15 @@ impl MouseManager {
                 .last()
         };
 
+        if self.dragging.is_none() {
+            let font_height = renderer.grid_renderer.font_dimensions.height as f32;
+            let is_text = relevant_window_details.map_or(false, |details| {
+                let row = ((position.y - details.region.top) / font_height) as u64;
+                renderer.is_text_row(details, row)
+            });
+            self.update_cursor_icon(is_text, window);
+        }
+
         let global_bounds = relevant_window_details
             .map(|details| details.region)
             .unwrap_or_else(|| Rect::from_wh(size.width as f32, size.height as f32));
@@ -160,7 +225,10 @@ impl MouseManager {
         if let Some(relevant_window_details) = relevant_window_details {
             let relative_position = PhysicalPosition::new(
                 clamped_position.x - relevant_window_details.region.left,
-                clamped_position.y - relevant_window_details.region.top,
+                (clamped_position.y
+                    - relevant_window_details.region.top
+                    - relevant_window_details.content_offset)
+                    .max(0.0),
             );
             self.relative_position = to_grid_coords(
                 relative_position,
@@ -192,7 +260,18 @@ impl MouseManager {
                     grid_id: relevant_window_details.id,
                     position: self.relative_position.into(),
                     modifier_string: keyboard_manager.format_modifier_string(true),
-                }))
+                }));
+
+                if self.enabled
+                    && self.dragging.is_none()
+                    && SETTINGS.get::<WindowSettings>().mouse_hover_events
+                {
+                    EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::MouseHover {
+                        grid_id: relevant_window_details.id,
+                        position: self.relative_position.into(),
+                        modifier_string: keyboard_manager.format_modifier_string(true),
+                    }));
+                }
             }
 
             self.has_moved = self.dragging.is_some() && (self.has_moved || has_moved);
@@ -210,28 +289,12 @@ impl MouseManager {
         // Non floating windows: rather than global coordinates, relative are needed
         if self.enabled {
             if let Some(button_text) = mouse_button_to_button_text(mouse_button) {
-                if let Some(details) = &self.window_details_under_mouse {
-                    let action = if down {
-                        "press".to_owned()
-                    } else {
-                        "release".to_owned()
-                    };
-
-                    let position = if !down && self.has_moved {
-                        self.drag_position
-                    } else {
-                        self.relative_position
-                    };
-
-                    EVENT_AGGREGATOR.send(UiCommand::Serial(SerialCommand::MouseButton {
-                        button: button_text.clone(),
-                        action,
-                        grid_id: details.id,
-                        position: position.into(),
-                        modifier_string: keyboard_manager.format_modifier_string(true),
-                    }));
+                if self.handle_split_drag_transition(mouse_button, down, keyboard_manager) {
+                    return;
                 }
 
+                self.send_mouse_button(&button_text, down, keyboard_manager);
+
                 if down {
                     self.dragging = Some(button_text);
                 } else {
@@ -245,6 +308,85 @@ impl MouseManager {
         }
     }
 
+    fn send_mouse_button(&self, button_text: &str, down: bool, keyboard_manager: &KeyboardManager) {
+        if let Some(details) = &self.window_details_under_mouse {
+            let action = if down {
+                "press".to_owned()
+            } else {
+                "release".to_owned()
+            };
+
+            let position = if !down && self.has_moved {
+                self.drag_position
+            } else {
+                self.relative_position
+            };
+
+            EVENT_AGGREGATOR.send(UiCommand::Serial(SerialCommand::MouseButton {
+                button: button_text.to_owned(),
+                action,
+                grid_id: details.id,
+                position: position.into(),
+                modifier_string: keyboard_manager.format_modifier_string(true),
+            }));
+        }
+    }
+
+    /// Starts and ends dragging window separators with the left button, returns whether the
+    /// button was handled.
+    fn handle_split_drag_transition(
+        &mut self,
+        mouse_button: &MouseButton,
+        down: bool,
+        keyboard_manager: &KeyboardManager,
+    ) -> bool {
+        if mouse_button != &MouseButton::Left {
+            return false;
+        }
+
+        if down {
+            match self.separator_under_mouse {
+                Some(kind) => {
+                    self.split_drag = Some(SplitDrag {
+                        kind,
+                        position: self.position,
+                        has_moved: false,
+                    });
+                    true
+                }
+                None => false,
+            }
+        } else {
+            match self.split_drag.take() {
+                Some(split_drag) => {
+                    // Clicking the status line without dragging it still focuses the window
+                    if !split_drag.has_moved {
+                        self.send_mouse_button("left", true, keyboard_manager);
+                        self.send_mouse_button("left", false, keyboard_manager);
+                    }
+                    true
+                }
+                None => false,
+            }
+        }
+    }
+
+    /// Shows what clicking does with the shape of the mouse cursor: resize arrows over window
+    /// separators, an I-beam over text and an arrow elsewhere.
+    fn update_cursor_icon(&mut self, is_text: bool, window: &Window) {
+        let cursor_icon = match self.separator_under_mouse {
+            _ if !self.enabled => CursorIcon::Default,
+            Some(SeparatorKind::Vertical) => CursorIcon::ColResize,
+            Some(SeparatorKind::Horizontal) => CursorIcon::RowResize,
+            None if is_text => CursorIcon::Text,
+            None => CursorIcon::Default,
+        };
+        if cursor_icon != self.cursor_icon {
+            window.set_cursor_icon(cursor_icon);
+            self.cursor_icon = cursor_icon;
+        }
+    }
+
     fn handle_line_scroll(&mut self, x: f32, y: f32, keyboard_manager: &KeyboardManager) {
         if !self.enabled {
             return;
@@ -329,6 +471,7 @@ impl MouseManager {
     ) {
         match phase {
             TouchPhase::Started => {
+                self.scroll_inertia.stop();
                 let settings = SETTINGS.get::<WindowSettings>();
                 let enable_deadzone = settings.touch_deadzone >= 0.0;
 
@@ -383,6 +526,7 @@ impl MouseManager {
                         // starting point
                         trace.last = location;
 
+                        self.scroll_inertia.track(Instant::now(), delta);
                         let font_size = renderer.grid_renderer.font_dimensions.into();
                         self.handle_pixel_scroll(font_size, delta, keyboard_manager);
                     }
@@ -401,6 +545,11 @@ impl MouseManager {
             }
             TouchPhase::Ended | TouchPhase::Cancelled => {
                 if let Some(trace) = self.touch_position.remove(&finger_id) {
+                    let settings = SETTINGS.get::<WindowSettings>();
+                    if trace.left_deadzone_once && self.dragging.is_none() && settings.touch_inertia
+                    {
+                        self.scroll_inertia.release(Instant::now());
+                    }
                     if self.dragging.is_some() {
                         self.handle_pointer_transition(&MouseButton::Left, false, keyboard_manager);
                     }
@@ -420,6 +569,43 @@ impl MouseManager {
         }
     }
 
+    /// Styluses work like a mouse with the left button held while touching the screen. The
+    /// pressure isn't used, and hovering is reported as mouse movement by the OS.
+    fn handle_stylus(
+        &mut self,
+        keyboard_manager: &KeyboardManager,
+        renderer: &Renderer,
+        window: &Window,
+        location: PhysicalPosition<f32>,
+        phase: &TouchPhase,
+    ) {
+        self.handle_pointer_motion(
+            location.x.round() as i32,
+            location.y.round() as i32,
+            keyboard_manager,
+            renderer,
+            window,
+        );
+        match phase {
+            TouchPhase::Started => {
+                self.handle_pointer_transition(&MouseButton::Left, true, keyboard_manager)
+            }
+            TouchPhase::Moved => {}
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                self.handle_pointer_transition(&MouseButton::Left, false, keyboard_manager)
+            }
+        }
+    }
+
+    /// Continues touch scrolling after the finger was lifted, called every frame.
+    pub fn update(&mut self, dt: f32, keyboard_manager: &KeyboardManager, renderer: &Renderer) {
+        let decay = SETTINGS.get::<WindowSettings>().touch_inertia_decay;
+        if let Some(delta) = self.scroll_inertia.step(dt, decay) {
+            let font_size = renderer.grid_renderer.font_dimensions.into();
+            self.handle_pixel_scroll(font_size, delta, keyboard_manager);
+        }
+    }
+
     pub fn handle_event(
         &mut self,
         event: &Event<()>,
@@ -451,7 +637,11 @@ impl MouseManager {
                         ..
                     },
                 ..
-            } => self.handle_line_scroll(*x, *y, keyboard_manager),
+            } => {
+                // Every wheel tick scrolls by 'mousescroll' lines in Neovim
+                let multiplier = SETTINGS.get::<WindowSettings>().scroll_wheel_multiplier;
+                self.handle_line_scroll(*x * multiplier, *y * multiplier, keyboard_manager)
+            }
             Event::WindowEvent {
                 event:
                     WindowEvent::MouseWheel {
@@ -459,11 +649,27 @@ impl MouseManager {
                         ..
                     },
                 ..
-            } => self.handle_pixel_scroll(
-                renderer.grid_renderer.font_dimensions.into(),
-                (delta.x as f32, delta.y as f32),
-                keyboard_manager,
-            ),
+            } => {
+                // Precision touchpads report pixels, which usually need other tuning than wheels
+                let multiplier = SETTINGS.get::<WindowSettings>().scroll_touchpad_multiplier;
+                self.handle_pixel_scroll(
+                    renderer.grid_renderer.font_dimensions.into(),
+                    (delta.x as f32 * multiplier, delta.y as f32 * multiplier),
+                    keyboard_manager,
+                )
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::Touch(Touch {
+                        location,
+                        phase,
+                        force,
+                        ..
+                    }),
+                ..
+            } if is_stylus(force.as_ref()) => {
+                self.handle_stylus(keyboard_manager, renderer, window, location.cast(), phase)
+            }
             Event::WindowEvent {
                 event:
                     WindowEvent::Touch(Touch {
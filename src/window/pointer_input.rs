@@ -0,0 +1,162 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use winit::event::Force;
+
+// Only the last moves of a swipe decide how fast it was
+const VELOCITY_WINDOW: Duration = Duration::from_millis(100);
+// In pixels per second, slower swipes stop right away and flings stop below this speed
+const MIN_FLING_VELOCITY: f32 = 50.0;
+
+/// Whether a touch comes from a stylus instead of a finger. Winit reports both as touches without
+/// the type of the pointer, and fingers can report a force too, so only the angle of the stylus,
+/// which only styluses have, tells them apart.
+pub fn is_stylus(force: Option<&Force>) -> bool {
+    matches!(
+        force,
+        Some(Force::Calibrated {
+            altitude_angle: Some(_),
+            ..
+        })
+    )
+}
+
+fn speed((x, y): (f32, f32)) -> f32 {
+    (x * x + y * y).sqrt()
+}
+
+/// Keeps a touch scroll going after the finger was lifted, slowing down over time.
+#[derive(Default)]
+pub struct ScrollInertia {
+    moves: VecDeque<(Instant, (f32, f32))>,
+    velocity: Option<(f32, f32)>,
+}
+
+impl ScrollInertia {
+    /// Records a scroll by the finger, in pixels.
+    pub fn track(&mut self, now: Instant, delta: (f32, f32)) {
+        self.velocity = None;
+        self.moves.push_back((now, delta));
+        while let Some((time, _)) = self.moves.front() {
+            if now.duration_since(*time) <= VELOCITY_WINDOW {
+                break;
+            }
+            self.moves.pop_front();
+        }
+    }
+
+    /// Starts the fling with the speed of the last moves, when the finger is lifted.
+    pub fn release(&mut self, now: Instant) {
+        let moves: Vec<_> = self
+            .moves
+            .drain(..)
+            .filter(|(time, _)| now.duration_since(*time) <= VELOCITY_WINDOW)
+            .collect();
+        let start = match moves.first() {
+            Some((start, _)) => *start,
+            None => return,
+        };
+        // A single move still took about a frame
+        let duration = now
+            .duration_since(start)
+            .max(Duration::from_millis(8))
+            .as_secs_f32();
+        let (x, y) = moves
+            .iter()
+            .fold((0.0, 0.0), |(x, y), (_, (dx, dy))| (x + dx, y + dy));
+        let velocity = (x / duration, y / duration);
+        self.velocity = Some(velocity).filter(|velocity| speed(*velocity) >= MIN_FLING_VELOCITY);
+    }
+
+    pub fn stop(&mut self) {
+        self.moves.clear();
+        self.velocity = None;
+    }
+
+    /// Advances the fling by `dt` seconds, returns how far to scroll in pixels. The speed is
+    /// divided by e every 1 / `decay` seconds.
+    pub fn step(&mut self, dt: f32, decay: f32) -> Option<(f32, f32)> {
+        let (x, y) = self.velocity?;
+        let factor = (-decay * dt).exp();
+        let velocity = (x * factor, y * factor);
+        self.velocity = Some(velocity).filter(|velocity| speed(*velocity) >= MIN_FLING_VELOCITY);
+        Some((x * dt, y * dt))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fling(inertia: &mut ScrollInertia, start: Instant) {
+        for frame in 0..5 {
+            inertia.track(start + Duration::from_millis(frame * 10), (0.0, 10.0));
+        }
+        inertia.release(start + Duration::from_millis(50));
+    }
+
+    #[test]
+    fn fling_keeps_the_swipe_speed() {
+        let mut inertia = ScrollInertia::default();
+        fling(&mut inertia, Instant::now());
+        // 50 pixels in 50ms
+        let (x, y) = inertia.step(0.01, 0.0).unwrap();
+        assert_eq!(x, 0.0);
+        assert!((y - 10.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn fling_slows_down_and_stops() {
+        let mut inertia = ScrollInertia::default();
+        fling(&mut inertia, Instant::now());
+        let first = inertia.step(0.1, 4.0).unwrap().1;
+        let second = inertia.step(0.1, 4.0).unwrap().1;
+        assert!(second < first);
+
+        let steps = std::iter::from_fn(|| inertia.step(0.1, 4.0))
+            .take(100)
+            .count();
+        assert!(steps < 100);
+    }
+
+    #[test]
+    fn slow_or_old_swipes_dont_fling() {
+        let start = Instant::now();
+        let mut inertia = ScrollInertia::default();
+        inertia.track(start, (0.0, 1.0));
+        inertia.release(start + Duration::from_millis(50));
+        assert_eq!(inertia.step(0.01, 4.0), None);
+
+        // The finger rested before being lifted
+        inertia.track(start, (0.0, 10.0));
+        inertia.release(start + Duration::from_secs(1));
+        assert_eq!(inertia.step(0.01, 4.0), None);
+    }
+
+    #[test]
+    fn touching_again_stops_the_fling() {
+        let start = Instant::now();
+        let mut inertia = ScrollInertia::default();
+        fling(&mut inertia, start);
+        inertia.stop();
+        assert_eq!(inertia.step(0.01, 4.0), None);
+    }
+
+    #[test]
+    fn only_touches_with_an_angle_are_styluses() {
+        assert!(!is_stylus(None));
+        assert!(!is_stylus(Some(&Force::Normalized(0.5))));
+        assert!(!is_stylus(Some(&Force::Calibrated {
+            force: 1.0,
+            max_possible_force: 2.0,
+            altitude_angle: None,
+        })));
+        assert!(is_stylus(Some(&Force::Calibrated {
+            force: 1.0,
+            max_possible_force: 2.0,
+            altitude_angle: Some(1.0),
+        })));
+    }
+}
@@ -0,0 +1,179 @@
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use global_hotkey::{hotkey::HotKey, GlobalHotKeyEvent, GlobalHotKeyManager};
+use log::error;
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    window::Window,
+};
+
+use crate::{
+    redraw_scheduler::REDRAW_SCHEDULER,
+    renderer::animation_utils::{ease, ease_out_cubic, reduce_motion},
+    settings::SETTINGS,
+    window::WindowSettings,
+};
+
+const SLIDE_DURATION: Duration = Duration::from_millis(200);
+
+struct Slide {
+    start: Instant,
+    showing: bool,
+}
+
+/// Toggles the window with a global hotkey, sliding it down from the top of the screen like the
+/// console in Quake.
+pub struct QuakeMode {
+    manager: Option<GlobalHotKeyManager>,
+    hotkey: Option<HotKey>,
+    hotkey_text: String,
+    visible: bool,
+    slide: Option<Slide>,
+}
+
+impl QuakeMode {
+    pub fn new() -> Self {
+        Self {
+            manager: None,
+            hotkey: None,
+            hotkey_text: String::new(),
+            visible: true,
+            slide: None,
+        }
+    }
+
+    pub fn synchronize_settings(&mut self) {
+        let hotkey_text = SETTINGS.get::<WindowSettings>().quake_hotkey;
+        if hotkey_text == self.hotkey_text {
+            return;
+        }
+        self.hotkey_text = hotkey_text;
+
+        if let (Some(manager), Some(hotkey)) = (&self.manager, self.hotkey.take()) {
+            manager.unregister(hotkey).ok();
+        }
+        if self.hotkey_text.is_empty() {
+            return;
+        }
+
+        let hotkey = match HotKey::from_str(&self.hotkey_text) {
+            Ok(hotkey) => hotkey,
+            Err(error) => {
+                error!("Invalid quake hotkey {}: {}", self.hotkey_text, error);
+                return;
+            }
+        };
+
+        // The manager is only created once a hotkey is set, since it installs OS hooks
+        if self.manager.is_none() {
+            match GlobalHotKeyManager::new() {
+                Ok(manager) => self.manager = Some(manager),
+                Err(error) => {
+                    error!("Could not set up global hotkeys: {}", error);
+                    return;
+                }
+            }
+        }
+
+        match self.manager.as_ref().unwrap().register(hotkey) {
+            Ok(()) => self.hotkey = Some(hotkey),
+            Err(error) => error!("Could not register hotkey {}: {}", self.hotkey_text, error),
+        }
+    }
+
+    pub fn handle_hotkey_events(&mut self, window: &Window) {
+        while let Ok(event) = GlobalHotKeyEvent::receiver().try_recv() {
+            if self.hotkey.map(|hotkey| hotkey.id()) != Some(event.id) {
+                continue;
+            }
+
+            if self.visible && window.has_focus() {
+                self.hide(window);
+            } else {
+                self.show(window);
+            }
+        }
+    }
+
+    fn show(&mut self, window: &Window) {
+        self.visible = true;
+        self.dock(window);
+        window.set_visible(true);
+        window.set_minimized(false);
+        window.focus_window();
+        self.start_slide(window, true);
+    }
+
+    fn hide(&mut self, window: &Window) {
+        self.visible = false;
+        self.start_slide(window, false);
+    }
+
+    fn start_slide(&mut self, window: &Window, showing: bool) {
+        if !self.is_docked() || reduce_motion() {
+            self.slide = None;
+            if !showing {
+                window.set_visible(false);
+            }
+            return;
+        }
+        self.slide = Some(Slide {
+            start: Instant::now(),
+            showing,
+        });
+        self.animate(window);
+    }
+
+    fn is_docked(&self) -> bool {
+        SETTINGS.get::<WindowSettings>().quake_height > 0.0
+    }
+
+    // Stretches the window across the top of the current monitor
+    fn dock(&self, window: &Window) {
+        if !self.is_docked() {
+            return;
+        }
+        if let Some(monitor) = window.current_monitor() {
+            let height_percentage = SETTINGS.get::<WindowSettings>().quake_height.min(100.0);
+            let monitor_size = monitor.size();
+            let height = (monitor_size.height as f32 * height_percentage / 100.0) as u32;
+            window.set_inner_size(PhysicalSize::new(monitor_size.width, height));
+            window.set_outer_position(monitor.position());
+        }
+    }
+
+    /// Moves the window along while it's sliding in or out.
+    pub fn animate(&mut self, window: &Window) {
+        let (slide, monitor) = match (&self.slide, window.current_monitor()) {
+            (Some(slide), Some(monitor)) => (slide, monitor),
+            _ => return,
+        };
+
+        let t = (slide.start.elapsed().as_secs_f32() / SLIDE_DURATION.as_secs_f32()).min(1.0);
+        let top = monitor.position().y as f32;
+        let hidden_top = top - window.outer_size().height as f32;
+        let (from, to) = if slide.showing {
+            (hidden_top, top)
+        } else {
+            (top, hidden_top)
+        };
+
+        let y = ease(ease_out_cubic, from, to, t);
+        window.set_outer_position(PhysicalPosition::new(
+            monitor.position().x,
+            y.round() as i32,
+        ));
+
+        if t < 1.0 {
+            REDRAW_SCHEDULER.queue_next_frame();
+        } else {
+            if !slide.showing {
+                window.set_visible(false);
+            }
+            self.slide = None;
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use winit::dpi::PhysicalSize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenderState {
+    /// The window has an area to draw into.
+    Running,
+    /// The window has no area, like while minimized on Windows, so nothing can be drawn.
+    Suspended,
+    /// The window got an area back, so the surface has to be rebuilt and everything drawn again.
+    Resumed,
+}
+
+/// Suspends rendering while the window has a size of zero, and tells when to resume.
+#[derive(Default)]
+pub struct RenderSuspension {
+    suspended: bool,
+}
+
+impl RenderSuspension {
+    pub fn update(&mut self, size: PhysicalSize<u32>) -> RenderState {
+        let empty = size.width == 0 || size.height == 0;
+        match (self.suspended, empty) {
+            (_, true) => {
+                if !self.suspended {
+                    log::debug!("Window has no area, suspending rendering");
+                }
+                self.suspended = true;
+                RenderState::Suspended
+            }
+            (true, false) => {
+                log::debug!(
+                    "Window restored to {}x{}, resuming rendering",
+                    size.width,
+                    size.height
+                );
+                self.suspended = false;
+                RenderState::Resumed
+            }
+            (false, false) => RenderState::Running,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn minimize_restore_cycle() {
+        let mut suspension = RenderSuspension::default();
+        assert_eq!(
+            suspension.update(PhysicalSize::new(800, 600)),
+            RenderState::Running
+        );
+        assert_eq!(
+            suspension.update(PhysicalSize::new(0, 0)),
+            RenderState::Suspended
+        );
+        assert_eq!(
+            suspension.update(PhysicalSize::new(0, 0)),
+            RenderState::Suspended
+        );
+        assert_eq!(
+            suspension.update(PhysicalSize::new(800, 600)),
+            RenderState::Resumed
+        );
+        assert_eq!(
+            suspension.update(PhysicalSize::new(800, 600)),
+            RenderState::Running
+        );
+    }
+
+    #[test]
+    fn one_empty_dimension_suspends() {
+        let mut suspension = RenderSuspension::default();
+        assert_eq!(
+            suspension.update(PhysicalSize::new(800, 0)),
+            RenderState::Suspended
+        );
+        assert_eq!(
+            suspension.update(PhysicalSize::new(0, 600)),
+            RenderState::Suspended
+        );
+        assert_eq!(
+            suspension.update(PhysicalSize::new(1, 1)),
+            RenderState::Resumed
+        );
+    }
+}
@@ -1,13 +1,12 @@
-use std::num::NonZeroU32;
 use std::{convert::TryInto, ffi::CString};
 
 use crate::redraw_scheduler::REDRAW_SCHEDULER;
-use crate::renderer::WindowedContext;
+use crate::renderer::{load_surface_size_limits, WindowedContext};
 use gl::types::*;
 use glutin::prelude::GlConfig;
 use skia_safe::{
     gpu::{gl::FramebufferInfo, BackendRenderTarget, DirectContext, SurfaceOrigin},
-    Canvas, ColorType, Surface,
+    AlphaType, Canvas, ColorType, IRect, Image, ImageInfo, Surface,
 };
 
 fn create_surface(
@@ -26,10 +25,7 @@ fn create_surface(
             .expect("Could not convert stencil"),
         fb_info,
     );
-    windowed_context.resize(
-        NonZeroU32::new(size.width).unwrap(),
-        NonZeroU32::new(size.height).unwrap(),
-    );
+    windowed_context.resize(size);
     Surface::from_backend_render_target(
         gr_context,
         &backend_render_target,
@@ -50,6 +46,7 @@ pub struct SkiaRenderer {
 impl SkiaRenderer {
     pub fn new(windowed_context: &WindowedContext) -> SkiaRenderer {
         gl::load_with(|s| windowed_context.get_proc_address(CString::new(s).unwrap().as_c_str()));
+        load_surface_size_limits();
 
         let interface = skia_safe::gpu::gl::Interface::new_load_with(|name| {
             if name == "eglGetCurrentDisplay" {
@@ -83,6 +80,26 @@ impl SkiaRenderer {
         self.surface.canvas()
     }
 
+    /// A copy of the last drawn frame.
+    pub fn snapshot(&mut self) -> Image {
+        self.surface.image_snapshot()
+    }
+
+    /// Reads back the RGBA pixels of a region of the last drawn frame.
+    pub fn read_pixels(&mut self, region: IRect) -> Option<Vec<u8>> {
+        let info = ImageInfo::new(
+            region.size(),
+            ColorType::RGBA8888,
+            AlphaType::Unpremul,
+            None,
+        );
+        let row_bytes = info.min_row_bytes();
+        let mut pixels = vec![0; row_bytes * region.height() as usize];
+        self.surface
+            .read_pixels(&info, &mut pixels, row_bytes, region.top_left())
+            .then_some(pixels)
+    }
+
     pub fn resize(&mut self, windowed_context: &WindowedContext) {
         self.surface = create_surface(windowed_context, &mut self.gr_context, self.fb_info);
         REDRAW_SCHEDULER.queue_next_frame();
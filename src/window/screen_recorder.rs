@@ -0,0 +1,461 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{sync_channel, SyncSender, TrySendError},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use skia_safe::IRect;
+use winit::dpi::PhysicalSize;
+
+use crate::{settings::SETTINGS, window::WindowSettings};
+
+use super::renderer::SkiaRenderer;
+
+// Frames waiting for the encoder. When it can't keep up, more frames are dropped instead of piling
+// up in memory.
+const QUEUED_FRAMES: usize = 8;
+
+/// The part of the window that is recorded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordingRegion {
+    Window,
+    /// In physical pixels, from the top left corner of the window
+    Pixels(IRect),
+}
+
+impl RecordingRegion {
+    /// Parses either `window` or `x,y,width,height`.
+    pub fn parse(region: &str) -> Result<Self, String> {
+        if region.is_empty() || region == "window" {
+            return Ok(RecordingRegion::Window);
+        }
+
+        let values = region
+            .split(',')
+            .map(|value| value.trim().parse::<i32>())
+            .collect::<Result<Vec<i32>, _>>();
+        match values.as_deref() {
+            Ok([x, y, width, height]) if *width > 0 && *height > 0 => Ok(RecordingRegion::Pixels(
+                IRect::from_xywh(*x, *y, *width, *height),
+            )),
+            _ => Err(format!(
+                "Invalid recording region {region:?}, expected \"window\" or \"x,y,width,height\""
+            )),
+        }
+    }
+
+    /// The region within a window of the given size. Video encoders want even dimensions, so
+    /// an odd row or column is left out.
+    pub fn clamp(&self, window_size: PhysicalSize<u32>) -> Option<IRect> {
+        let window = IRect::from_wh(window_size.width as i32, window_size.height as i32);
+        let mut region = match self {
+            RecordingRegion::Window => window,
+            RecordingRegion::Pixels(region) => IRect::intersect(region, &window)?,
+        };
+        region.right -= region.width() % 2;
+        region.bottom -= region.height() % 2;
+        (!region.is_empty()).then_some(region)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RecordingFormat {
+    Gif,
+    Mp4,
+}
+
+impl RecordingFormat {
+    fn from_path(path: &Path) -> Result<Self, String> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("gif") => Ok(RecordingFormat::Gif),
+            Some(extension) if extension.eq_ignore_ascii_case("mp4") => Ok(RecordingFormat::Mp4),
+            _ => Err(format!(
+                "Can't record to {}, only .gif and .mp4 files are supported",
+                path.display()
+            )),
+        }
+    }
+}
+
+// Only read by the encoders, which need the recording feature
+#[cfg_attr(not(feature = "recording"), allow(dead_code))]
+struct CapturedFrame {
+    pixels: Vec<u8>,
+    width: u32,
+    height: u32,
+    captured_at: Instant,
+}
+
+#[cfg_attr(not(feature = "recording"), allow(dead_code))]
+enum RecorderMessage {
+    Frame(CapturedFrame),
+    Stop(Instant),
+}
+
+struct Recording {
+    path: PathBuf,
+    region: IRect,
+    frame_interval: Duration,
+    last_capture: Option<Instant>,
+    dropped_frames: usize,
+    sender: SyncSender<RecorderMessage>,
+    encoder_thread: JoinHandle<Result<(), String>>,
+}
+
+impl Recording {
+    /// Waits for the encoder to write the remaining frames and finish the file.
+    fn finish(self) -> Result<String, String> {
+        self.sender.send(RecorderMessage::Stop(Instant::now())).ok();
+        self.encoder_thread
+            .join()
+            .map_err(|_| "The recording encoder crashed".to_string())??;
+
+        let mut message = format!("Recording saved to {}", self.path.display());
+        if self.dropped_frames > 0 {
+            message += &format!(
+                ", {} frames were dropped since the encoder couldn't keep up",
+                self.dropped_frames
+            );
+        }
+        log::info!("{message}");
+        Ok(message)
+    }
+}
+
+/// Records the rendered frames into a GIF or MP4 file, started and stopped with
+/// `:NeovideRecordStart` and `:NeovideRecordStop`.
+#[derive(Default)]
+pub struct ScreenRecorder {
+    recording: Option<Recording>,
+}
+
+impl ScreenRecorder {
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Starts recording to `path`, or a timestamped GIF in the working directory when it's empty.
+    /// Returns the message to show to the user.
+    pub fn start(&mut self, path: &str, window_size: PhysicalSize<u32>) -> Result<String, String> {
+        if let Some(recording) = &self.recording {
+            return Err(format!("Already recording to {}", recording.path.display()));
+        }
+
+        let path = if path.is_empty() {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default();
+            PathBuf::from(format!("neovide-recording-{timestamp}.gif"))
+        } else {
+            PathBuf::from(path)
+        };
+        let format = RecordingFormat::from_path(&path)?;
+
+        let settings = SETTINGS.get::<WindowSettings>();
+        let region = RecordingRegion::parse(&settings.recording_region)?
+            .clamp(window_size)
+            .ok_or_else(|| "The recording region is outside of the window".to_string())?;
+        let fps = settings.recording_fps.clamp(1, 60);
+
+        let (sender, receiver) = sync_channel(QUEUED_FRAMES);
+        let encoder = encoder::create(
+            format,
+            &path,
+            region.width() as u32,
+            region.height() as u32,
+            fps,
+        )?;
+        let encoder_thread = thread::spawn(move || encoder::run(encoder, receiver));
+
+        let message = format!("Recording to {}", path.display());
+        log::info!("{message} at {fps} fps, region {region:?}");
+        self.recording = Some(Recording {
+            path,
+            region,
+            frame_interval: Duration::from_secs_f64(1.0 / fps as f64),
+            last_capture: None,
+            dropped_frames: 0,
+            sender,
+            encoder_thread,
+        });
+        Ok(message)
+    }
+
+    /// Stops recording. The file is finished on another thread, which calls `on_finished` once
+    /// it's written, so that the window doesn't freeze while the encoder catches up.
+    pub fn stop(
+        &mut self,
+        on_finished: impl FnOnce(Result<String, String>) + Send + 'static,
+    ) -> Result<String, String> {
+        let recording = self
+            .recording
+            .take()
+            .ok_or_else(|| "Not recording".to_string())?;
+
+        let message = format!("Finishing the recording to {}", recording.path.display());
+        thread::spawn(move || on_finished(recording.finish()));
+        Ok(message)
+    }
+
+    /// Stops recording and waits for the file to be written, for when Neovide exits.
+    pub fn stop_and_wait(&mut self) -> Result<String, String> {
+        self.recording
+            .take()
+            .ok_or_else(|| "Not recording".to_string())?
+            .finish()
+    }
+
+    /// Captures the frame that was just drawn, unless the last capture is more recent than the
+    /// frame rate allows.
+    pub fn capture(&mut self, skia_renderer: &mut SkiaRenderer) {
+        let recording = match &mut self.recording {
+            Some(recording) => recording,
+            None => return,
+        };
+
+        let now = Instant::now();
+        if recording.last_capture.map_or(false, |last_capture| {
+            now - last_capture < recording.frame_interval
+        }) {
+            return;
+        }
+
+        // Fails when the window got smaller than the region, those frames are skipped
+        if let Some(pixels) = skia_renderer.read_pixels(recording.region) {
+            recording.last_capture = Some(now);
+            let frame = RecorderMessage::Frame(CapturedFrame {
+                pixels,
+                width: recording.region.width() as u32,
+                height: recording.region.height() as u32,
+                captured_at: now,
+            });
+            if let Err(TrySendError::Full(_)) = recording.sender.try_send(frame) {
+                recording.dropped_frames += 1;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "recording")]
+mod encoder {
+    use std::{
+        fs::File,
+        io::{BufWriter, Write},
+        path::Path,
+        process::{Child, ChildStdin, Command, Stdio},
+        sync::mpsc::Receiver,
+        time::Duration,
+    };
+
+    use image::{
+        codecs::gif::{GifEncoder, Repeat},
+        Delay, Frame, RgbaImage,
+    };
+
+    use super::{CapturedFrame, RecorderMessage, RecordingFormat};
+
+    pub enum Encoder {
+        Gif(GifEncoder<BufWriter<File>>),
+        /// Raw frames are piped to ffmpeg, which writes the video
+        Ffmpeg {
+            process: Child,
+            stdin: ChildStdin,
+            fps: u64,
+        },
+    }
+
+    pub fn create(
+        format: RecordingFormat,
+        path: &Path,
+        width: u32,
+        height: u32,
+        fps: u64,
+    ) -> Result<Encoder, String> {
+        match format {
+            RecordingFormat::Gif => {
+                let file = File::create(path)
+                    .map_err(|error| format!("Could not create {}: {error}", path.display()))?;
+                let mut encoder = GifEncoder::new_with_speed(BufWriter::new(file), 10);
+                encoder
+                    .set_repeat(Repeat::Infinite)
+                    .map_err(|error| error.to_string())?;
+                Ok(Encoder::Gif(encoder))
+            }
+            RecordingFormat::Mp4 => {
+                let mut process = Command::new("ffmpeg")
+                    .args([
+                        "-y",
+                        "-loglevel",
+                        "error",
+                        "-f",
+                        "rawvideo",
+                        "-pix_fmt",
+                        "rgba",
+                    ])
+                    .arg("-s")
+                    .arg(format!("{width}x{height}"))
+                    .arg("-r")
+                    .arg(fps.to_string())
+                    .args(["-i", "-", "-pix_fmt", "yuv420p"])
+                    .arg(path)
+                    .stdin(Stdio::piped())
+                    .spawn()
+                    .map_err(|error| {
+                        format!("Could not start ffmpeg for the MP4 recording: {error}")
+                    })?;
+                let stdin = process.stdin.take().unwrap();
+                Ok(Encoder::Ffmpeg {
+                    process,
+                    stdin,
+                    fps,
+                })
+            }
+        }
+    }
+
+    impl Encoder {
+        /// Adds a frame that is shown for `duration`.
+        fn encode(&mut self, frame: CapturedFrame, duration: Duration) -> Result<(), String> {
+            match self {
+                Encoder::Gif(encoder) => {
+                    let image = RgbaImage::from_raw(frame.width, frame.height, frame.pixels)
+                        .ok_or_else(|| "Captured frame has the wrong size".to_string())?;
+                    encoder
+                        .encode_frame(Frame::from_parts(
+                            image,
+                            0,
+                            0,
+                            Delay::from_saturating_duration(duration),
+                        ))
+                        .map_err(|error| error.to_string())
+                }
+                Encoder::Ffmpeg { stdin, fps, .. } => {
+                    // The video has a constant frame rate, so frames shown longer are repeated
+                    let count = (duration.as_secs_f64() * *fps as f64).round().max(1.0) as usize;
+                    for _ in 0..count {
+                        stdin
+                            .write_all(&frame.pixels)
+                            .map_err(|error| format!("Could not write to ffmpeg: {error}"))?;
+                    }
+                    Ok(())
+                }
+            }
+        }
+
+        fn finish(self) -> Result<(), String> {
+            match self {
+                // The trailer is written when the encoder is dropped
+                Encoder::Gif(_) => Ok(()),
+                Encoder::Ffmpeg {
+                    mut process, stdin, ..
+                } => {
+                    drop(stdin);
+                    match process.wait() {
+                        Ok(status) if status.success() => Ok(()),
+                        Ok(status) => Err(format!("ffmpeg failed with {status}")),
+                        Err(error) => Err(format!("Could not wait for ffmpeg: {error}")),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Encodes the frames until the recording stops. Each frame is only written once the next
+    /// one arrives, since that decides how long it's shown.
+    pub fn run(mut encoder: Encoder, receiver: Receiver<RecorderMessage>) -> Result<(), String> {
+        let mut previous: Option<CapturedFrame> = None;
+        while let Ok(message) = receiver.recv() {
+            match message {
+                RecorderMessage::Frame(frame) => {
+                    if let Some(previous) = previous.take() {
+                        let duration = frame.captured_at - previous.captured_at;
+                        encoder.encode(previous, duration)?;
+                    }
+                    previous = Some(frame);
+                }
+                RecorderMessage::Stop(stopped_at) => {
+                    if let Some(previous) = previous.take() {
+                        let duration = stopped_at - previous.captured_at;
+                        encoder.encode(previous, duration)?;
+                    }
+                    break;
+                }
+            }
+        }
+        encoder.finish()
+    }
+}
+
+#[cfg(not(feature = "recording"))]
+mod encoder {
+    use std::{path::Path, sync::mpsc::Receiver};
+
+    use super::{RecorderMessage, RecordingFormat};
+
+    pub struct Encoder;
+
+    pub fn create(
+        _format: RecordingFormat,
+        _path: &Path,
+        _width: u32,
+        _height: u32,
+        _fps: u64,
+    ) -> Result<Encoder, String> {
+        Err("Neovide was built without the recording feature".to_string())
+    }
+
+    pub fn run(_encoder: Encoder, _receiver: Receiver<RecorderMessage>) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_region() {
+        assert_eq!(
+            RecordingRegion::parse("window"),
+            Ok(RecordingRegion::Window)
+        );
+        assert_eq!(
+            RecordingRegion::parse("10, 20,300,200"),
+            Ok(RecordingRegion::Pixels(IRect::from_xywh(10, 20, 300, 200)))
+        );
+        assert!(RecordingRegion::parse("10,20,0,200").is_err());
+        assert!(RecordingRegion::parse("10,20").is_err());
+    }
+
+    #[test]
+    fn clamp_region_to_the_window_with_even_size() {
+        let window_size = PhysicalSize::new(801, 600);
+        assert_eq!(
+            RecordingRegion::Window.clamp(window_size),
+            Some(IRect::from_wh(800, 600))
+        );
+        assert_eq!(
+            RecordingRegion::Pixels(IRect::from_xywh(700, 500, 300, 300)).clamp(window_size),
+            Some(IRect::from_xywh(700, 500, 100, 100))
+        );
+        assert_eq!(
+            RecordingRegion::Pixels(IRect::from_xywh(900, 0, 10, 10)).clamp(window_size),
+            None
+        );
+    }
+
+    #[test]
+    fn format_from_extension() {
+        assert_eq!(
+            RecordingFormat::from_path(Path::new("demo.GIF")),
+            Ok(RecordingFormat::Gif)
+        );
+        assert_eq!(
+            RecordingFormat::from_path(Path::new("demo.mp4")),
+            Ok(RecordingFormat::Mp4)
+        );
+        assert!(RecordingFormat::from_path(Path::new("demo.webm")).is_err());
+    }
+}
@@ -1,3 +1,7 @@
+use log::error;
+use rmpv::Value;
+
+use super::monitor_profiles::MonitorProfiles;
 use crate::{cmd_line::CmdLineSettings, settings::*};
 
 #[derive(Clone, SettingGroup)]
@@ -5,48 +9,199 @@ pub struct WindowSettings {
     pub refresh_rate: u64,
     pub refresh_rate_idle: u64,
     pub idle: bool,
+    pub low_latency: bool,
+    pub low_latency_echo: bool,
+    pub present_mode: String,
+    pub max_queued_frames: u32,
     pub transparency: f32,
+    pub transparency_inactive: f32,
+    pub transparency_unfocused: f32,
+    pub transparency_fullscreen: f32,
+    pub transparency_transition: f32,
+    pub window_blurred: bool,
+    pub window_blurred_unfocused: bool,
+    pub window_blurred_fullscreen: bool,
+    pub window_blur_material: String,
     pub scale_factor: f32,
     pub fullscreen: bool,
     pub iso_layout: bool,
     pub remember_window_size: bool,
     pub remember_window_position: bool,
+    pub restore_monitor_position: bool,
+    pub monitor_profiles: MonitorProfiles,
     pub hide_mouse_when_typing: bool,
+    pub scroll_wheel_multiplier: f32,
+    pub scroll_touchpad_multiplier: f32,
     pub touch_deadzone: f32,
     pub touch_drag_timeout: f32,
+    pub touch_inertia: bool,
+    pub touch_inertia_decay: f32,
+    pub mouse_hover_events: bool,
+    pub mouse_hover_delay: f32,
     pub background_color: String,
     pub confirm_quit: bool,
+    pub confirm_paste_size: u64,
+    pub confirm_paste_cmdline: bool,
     pub padding_top: u32,
     pub padding_left: u32,
     pub padding_right: u32,
     pub padding_bottom: u32,
+    pub padding_extend_highlights: bool,
     pub theme: String,
+    pub title_format: String,
+    pub window_icon: String,
+    pub context_menu: bool,
+    pub quake_hotkey: String,
+    pub quake_height: f32,
+    pub recording_fps: u64,
+    pub recording_region: String,
+    pub session_autosave_interval: f32,
+    pub zen_mode: bool,
+    pub zen_font_scale: f32,
+    pub zen_max_columns: u64,
+    pub zen_padding: u32,
+    pub zen_dim_inactive: f32,
+    pub preview: bool,
+    pub preview_width: f32,
+    pub terminal_height: f32,
+    pub terminal_shell: String,
+    pub ext_messages: bool,
+    pub ext_cmdline: bool,
+    pub ext_popupmenu: bool,
+    pub ext_tabline: bool,
+    pub srgb: bool,
 }
 
 impl Default for WindowSettings {
     fn default() -> Self {
         Self {
             transparency: 1.0,
+            transparency_inactive: -1.0,
+            transparency_unfocused: -1.0,
+            transparency_fullscreen: -1.0,
+            transparency_transition: 0.2,
+            window_blurred: false,
+            window_blurred_unfocused: true,
+            window_blurred_fullscreen: true,
+            window_blur_material: "acrylic".to_string(),
             scale_factor: 1.0,
-            fullscreen: false,
+            fullscreen: SETTINGS.get::<CmdLineSettings>().fullscreen,
             iso_layout: false,
             refresh_rate: 60,
             refresh_rate_idle: 5,
             idle: SETTINGS.get::<CmdLineSettings>().idle,
+            low_latency: false,
+            low_latency_echo: false,
+            present_mode: "".to_string(),
+            max_queued_frames: 0,
             remember_window_size: true,
             remember_window_position: true,
+            restore_monitor_position: true,
+            monitor_profiles: MonitorProfiles::default(),
             hide_mouse_when_typing: false,
+            scroll_wheel_multiplier: 1.0,
+            scroll_touchpad_multiplier: 1.0,
             touch_deadzone: 6.0,
             touch_drag_timeout: 0.17,
+            touch_inertia: false,
+            touch_inertia_decay: 4.0,
+            mouse_hover_events: false,
+            mouse_hover_delay: 0.5,
             background_color: "".to_string(),
             confirm_quit: true,
+            confirm_paste_size: 0,
+            confirm_paste_cmdline: false,
             padding_top: 0,
             padding_left: 0,
             padding_right: 0,
             padding_bottom: 0,
+            padding_extend_highlights: false,
             theme: "".to_string(),
+            title_format: "".to_string(),
+            window_icon: "".to_string(),
+            context_menu: false,
+            quake_hotkey: "".to_string(),
+            quake_height: 0.0,
+            recording_fps: 15,
+            recording_region: "window".to_string(),
+            session_autosave_interval: 0.0,
+            zen_mode: false,
+            zen_font_scale: 1.2,
+            zen_max_columns: 100,
+            zen_padding: 40,
+            zen_dim_inactive: 0.0,
+            preview: false,
+            preview_width: 0.4,
+            terminal_height: 0.3,
+            terminal_shell: "".to_string(),
+            ext_messages: SETTINGS.get::<CmdLineSettings>().ext_messages,
+            ext_cmdline: SETTINGS.get::<CmdLineSettings>().ext_cmdline,
+            ext_popupmenu: SETTINGS.get::<CmdLineSettings>().ext_popupmenu,
+            ext_tabline: SETTINGS.get::<CmdLineSettings>().ext_tabline,
+            srgb: SETTINGS.get::<CmdLineSettings>().srgb,
+        }
+    }
+}
+
+impl WindowSettings {
+    /// The UI elements Neovim hands over instead of drawing them into the grid, by the name of
+    /// their nvim_ui_attach option. Unlike multigrid, they can be switched while running.
+    pub fn ui_extensions(&self) -> [(&'static str, bool); 4] {
+        [
+            ("ext_messages", self.ext_messages),
+            ("ext_cmdline", self.ext_cmdline),
+            ("ext_popupmenu", self.ext_popupmenu),
+            // Zen mode takes the tabline over to hide it, without changing showtabline
+            ("ext_tabline", self.ext_tabline || self.zen_mode),
+        ]
+    }
+
+    /// Whether the command line is handed over to Neovide, which Neovim also does for the
+    /// messages.
+    pub fn cmdline_external(&self) -> bool {
+        self.ext_cmdline || self.ext_messages
+    }
+
+    /// The transparency for the state of the window. In fullscreen `transparency_fullscreen` wins,
+    /// otherwise `transparency_unfocused` is used while another application has the focus. Both
+    /// fall back to the regular transparency when negative.
+    pub fn state_transparency(&self, focused: bool) -> f32 {
+        if self.fullscreen && self.transparency_fullscreen >= 0.0 {
+            self.transparency_fullscreen
+        } else if !focused && self.transparency_unfocused >= 0.0 {
+            self.transparency_unfocused
+        } else {
+            self.transparency
+        }
+    }
+
+    /// Whether the desktop behind the window is blurred in its state. The blur can be dropped
+    /// while another application has the focus, or in fullscreen.
+    pub fn state_blurred(&self, focused: bool) -> bool {
+        self.window_blurred
+            && (focused || self.window_blurred_unfocused)
+            && (!self.fullscreen || self.window_blurred_fullscreen)
+    }
+
+    /// The scale factor, including the bigger font of zen mode.
+    pub fn effective_scale_factor(&self) -> f32 {
+        if self.zen_mode {
+            self.scale_factor * self.zen_font_scale
+        } else {
+            self.scale_factor
         }
     }
+
+    pub fn has_transparency(&self) -> bool {
+        [
+            self.transparency,
+            self.transparency_inactive,
+            self.transparency_unfocused,
+            self.transparency_fullscreen,
+        ]
+        .into_iter()
+        .any(|transparency| transparency >= 0.0 && (transparency - 1.0).abs() > f32::EPSILON)
+    }
 }
 
 #[derive(Clone, SettingGroup)]
@@ -54,6 +209,9 @@ impl Default for WindowSettings {
 pub struct KeyboardSettings {
     pub macos_alt_is_meta: bool,
     pub ime: bool,
+    pub layout: KeyboardLayout,
+    pub forward_super: bool,
+    pub extended_keys: bool,
 }
 
 #[allow(clippy::derivable_impls)]
@@ -62,6 +220,47 @@ impl Default for KeyboardSettings {
         Self {
             macos_alt_is_meta: false,
             ime: true,
+            layout: KeyboardLayout::Logical,
+            forward_super: true,
+            extended_keys: false,
+        }
+    }
+}
+
+/// Decides which key is reported to Neovim when a modifier like Ctrl or Alt is held.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyboardLayout {
+    /// Use the character produced by the active keyboard layout
+    Logical,
+    /// Use the key at the same position on a US QWERTY keyboard
+    Physical,
+}
+
+impl ParseFromValue for KeyboardLayout {
+    fn parse_from_value(&mut self, value: Value) {
+        if value.is_str() {
+            *self = match value.as_str().unwrap() {
+                "logical" => KeyboardLayout::Logical,
+                "physical" => KeyboardLayout::Physical,
+                value => {
+                    error!("Expected a keyboard layout name, but received {:?}", value);
+                    return;
+                }
+            };
+        } else {
+            error!(
+                "Expected a keyboard layout string, but received {:?}",
+                value
+            );
+        }
+    }
+}
+
+impl From<KeyboardLayout> for Value {
+    fn from(layout: KeyboardLayout) -> Self {
+        match layout {
+            KeyboardLayout::Logical => Value::from("logical"),
+            KeyboardLayout::Physical => Value::from("physical"),
         }
     }
 }
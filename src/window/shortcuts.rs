@@ -0,0 +1,110 @@
+#[allow(unused_imports)]
+use winit::platform::modifier_supplement::KeyEventExtModifierSupplement;
+use winit::{
+    event::KeyEvent,
+    keyboard::{Key, ModifiersState},
+};
+
+use crate::{
+    bridge::{ParallelCommand, SerialCommand, UiCommand},
+    settings::*,
+};
+
+// Scale factor multiplier applied by a single zoom step
+pub const ZOOM_STEP: f32 = 1.1;
+
+// OS conventional shortcuts, handled by Neovide itself instead of being sent to Neovim. They are
+// disabled by default, since they shadow common Vim mappings like <C-v>.
+#[derive(Clone, SettingGroup)]
+#[setting_prefix = "shortcut"]
+pub struct ShortcutSettings {
+    pub copy: bool,
+    pub cut: bool,
+    pub paste: bool,
+    pub save: bool,
+    pub quit: bool,
+    pub zoom: bool,
+    pub command_palette: bool,
+    pub quick_open: bool,
+    pub terminal: bool,
+}
+
+impl Default for ShortcutSettings {
+    fn default() -> Self {
+        Self {
+            copy: false,
+            cut: false,
+            paste: false,
+            save: false,
+            quit: false,
+            zoom: false,
+            command_palette: false,
+            quick_open: false,
+            terminal: false,
+        }
+    }
+}
+
+/// Returns the command for the shortcut triggered by the key event, if any. The fallback is the
+/// formatted key, which is sent to Neovim instead when the shortcut doesn't apply, like copying
+/// without a selection.
+pub fn shortcut_command(
+    key_event: &KeyEvent,
+    modifiers: ModifiersState,
+    fallback: Option<String>,
+) -> Option<UiCommand> {
+    if !is_primary_modifier(modifiers) {
+        return None;
+    }
+
+    let settings = SETTINGS.get::<ShortcutSettings>();
+    let text = key_event.key_without_modifiers().to_text()?.to_lowercase();
+
+    let command: UiCommand = match text.as_str() {
+        "c" if settings.copy => SerialCommand::YankSelection {
+            cut: false,
+            fallback,
+        }
+        .into(),
+        "x" if settings.cut => SerialCommand::YankSelection {
+            cut: true,
+            fallback,
+        }
+        .into(),
+        "v" if settings.paste => SerialCommand::PasteClipboard.into(),
+        "s" if settings.save => ParallelCommand::Save.into(),
+        "q" if settings.quit => ParallelCommand::Quit.into(),
+        "=" | "+" if settings.zoom => ParallelCommand::Zoom(ZOOM_STEP).into(),
+        "-" if settings.zoom => ParallelCommand::Zoom(1.0 / ZOOM_STEP).into(),
+        "0" if settings.zoom => ParallelCommand::ResetZoom.into(),
+        "p" if settings.command_palette && modifiers.shift_key() => {
+            ParallelCommand::ShowCommandPalette.into()
+        }
+        "p" if settings.quick_open && !modifiers.shift_key() => {
+            ParallelCommand::ShowQuickOpen.into()
+        }
+        _ => return None,
+    };
+
+    Some(command)
+}
+
+/// Whether the key event toggles the terminal drawer. It's checked on its own, since the drawer
+/// takes the keys while it has the focus.
+pub fn is_terminal_shortcut(key_event: &KeyEvent, modifiers: ModifiersState) -> bool {
+    SETTINGS.get::<ShortcutSettings>().terminal
+        && is_primary_modifier(modifiers)
+        && !modifiers.shift_key()
+        && key_event.key_without_modifiers() == Key::Character("`".into())
+}
+
+// Cmd on macOS and Ctrl everywhere else, optionally combined with Shift
+#[cfg(target_os = "macos")]
+fn is_primary_modifier(modifiers: ModifiersState) -> bool {
+    modifiers.super_key() && !modifiers.control_key() && !modifiers.alt_key()
+}
+
+#[cfg(not(target_os = "macos"))]
+fn is_primary_modifier(modifiers: ModifiersState) -> bool {
+    modifiers.control_key() && !modifiers.super_key() && !modifiers.alt_key()
+}
@@ -0,0 +1,156 @@
+use winit::dpi::PhysicalPosition;
+
+use crate::renderer::WindowDrawDetails;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SeparatorKind {
+    /// The column between two windows side by side
+    Vertical,
+    /// The status line or separator row below a window
+    Horizontal,
+}
+
+/// The cells a window covers on the root grid.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct GridRegion {
+    left: u32,
+    top: u32,
+    width: u32,
+    height: u32,
+}
+
+impl GridRegion {
+    fn of(details: &WindowDrawDetails, (font_width, font_height): (u64, u64)) -> Self {
+        let region = details.region;
+        // The regions are in pixels and may be in the middle of an animation
+        let to_cells = |pixels: f32, size: u64| (pixels / size as f32).round().max(0.0) as u32;
+        GridRegion {
+            left: to_cells(region.left, font_width),
+            top: to_cells(region.top, font_height),
+            width: to_cells(region.width(), font_width),
+            height: to_cells(region.height(), font_height),
+        }
+    }
+
+    fn contains(&self, cell: PhysicalPosition<u32>) -> bool {
+        cell.x >= self.left
+            && cell.x < self.left + self.width
+            && cell.y >= self.top
+            && cell.y < self.top + self.height
+    }
+}
+
+fn grid_regions(
+    windows: &[WindowDrawDetails],
+    font_dimensions: (u64, u64),
+) -> impl Iterator<Item = GridRegion> + '_ {
+    windows
+        .iter()
+        // The root grid is behind everything, the separators are on it
+        .filter(|details| details.id != 1 && details.floating_order.is_none())
+        .map(move |details| GridRegion::of(details, font_dimensions))
+}
+
+// Including the message grid, which is sorted above the other floating windows
+fn floating_regions(
+    windows: &[WindowDrawDetails],
+    font_dimensions: (u64, u64),
+) -> impl Iterator<Item = GridRegion> + '_ {
+    windows
+        .iter()
+        .filter(|details| details.floating_order.is_some())
+        .map(move |details| GridRegion::of(details, font_dimensions))
+}
+
+/// Finds the window separator at a cell of the root grid, if there is one and no floating window
+/// covers it. Only works with multigrid, without it every window is drawn into the root grid and
+/// Neovim resizes the splits itself when they're dragged.
+pub fn separator_at(
+    cell: PhysicalPosition<u32>,
+    windows: &[WindowDrawDetails],
+    font_dimensions: (u64, u64),
+) -> Option<SeparatorKind> {
+    if floating_regions(windows, font_dimensions).any(|region| region.contains(cell)) {
+        return None;
+    }
+    let mut kind = None;
+    for region in grid_regions(windows, font_dimensions) {
+        let inside = |position, start, length| position >= start && position < start + length;
+        // A cell inside a window isn't a separator
+        if region.contains(cell) {
+            return None;
+        }
+        if cell.x == region.left + region.width && inside(cell.y, region.top, region.height) {
+            kind = Some(SeparatorKind::Vertical);
+        } else if cell.y == region.top + region.height
+            && inside(cell.x, region.left, region.width)
+            && kind.is_none()
+        {
+            kind = Some(SeparatorKind::Horizontal);
+        }
+    }
+    kind
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use skia_safe::Rect;
+
+    const FONT: (u64, u64) = (10, 20);
+
+    fn window(id: u64, left: u32, top: u32, width: u32, height: u32) -> WindowDrawDetails {
+        WindowDrawDetails {
+            id,
+            region: Rect::from_xywh(
+                (left * 10) as f32,
+                (top * 20) as f32,
+                (width * 10) as f32,
+                (height * 20) as f32,
+            ),
+            floating_order: None,
+            content_offset: 0.0,
+        }
+    }
+
+    // Two windows side by side over a third one, each with a status line
+    fn layout() -> Vec<WindowDrawDetails> {
+        vec![
+            window(1, 0, 0, 80, 24),
+            window(2, 0, 0, 40, 10),
+            window(3, 41, 0, 39, 10),
+            window(4, 0, 11, 80, 11),
+        ]
+    }
+
+    #[test]
+    fn vertical_separators_are_found() {
+        let at = |x, y| separator_at(PhysicalPosition::new(x, y), &layout(), FONT);
+        assert_eq!(at(40, 5), Some(SeparatorKind::Vertical));
+        assert_eq!(at(39, 5), None);
+        assert_eq!(at(41, 5), None);
+    }
+
+    #[test]
+    fn status_lines_are_found() {
+        let at = |x, y| separator_at(PhysicalPosition::new(x, y), &layout(), FONT);
+        assert_eq!(at(10, 10), Some(SeparatorKind::Horizontal));
+        assert_eq!(at(60, 10), Some(SeparatorKind::Horizontal));
+        assert_eq!(at(10, 22), Some(SeparatorKind::Horizontal));
+        assert_eq!(at(10, 23), None);
+    }
+
+    #[test]
+    fn separators_under_floating_windows_are_hidden() {
+        let mut windows = layout();
+        windows.push(WindowDrawDetails {
+            floating_order: Some(1),
+            ..window(5, 30, 2, 20, 5)
+        });
+        let at = |x, y| separator_at(PhysicalPosition::new(x, y), &windows, FONT);
+        assert_eq!(at(40, 5), None);
+        // The floating window doesn't cover this part of the separator
+        assert_eq!(at(40, 8), Some(SeparatorKind::Vertical));
+        assert_eq!(at(10, 10), Some(SeparatorKind::Horizontal));
+    }
+}
@@ -0,0 +1,84 @@
+/// Everything the title format can refer to.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TitleInfo {
+    pub title: String,
+    pub icon: String,
+    pub filename: String,
+    pub cwd: String,
+    pub modified: bool,
+}
+
+const MODIFIED_INDICATOR: &str = "●";
+
+/// Fills in the `{title}`, `{icon}`, `{filename}`, `{cwd}` and `{modified}` placeholders of the
+/// title format.
+pub fn format_title(format: &str, info: &TitleInfo) -> String {
+    if format.is_empty() {
+        return info.title.clone();
+    }
+
+    format
+        .replace("{title}", &info.title)
+        .replace("{icon}", &info.icon)
+        .replace("{filename}", &info.filename)
+        .replace("{cwd}", &info.cwd)
+        .replace(
+            "{modified}",
+            if info.modified {
+                MODIFIED_INDICATOR
+            } else {
+                ""
+            },
+        )
+        .trim()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info() -> TitleInfo {
+        TitleInfo {
+            title: "main.rs (~/neovide/src) - NVIM".to_string(),
+            icon: "main.rs".to_string(),
+            filename: "main.rs".to_string(),
+            cwd: "~/neovide".to_string(),
+            modified: false,
+        }
+    }
+
+    #[test]
+    fn test_empty_format_uses_neovim_title() {
+        assert_eq!(format_title("", &info()), "main.rs (~/neovide/src) - NVIM");
+    }
+
+    #[test]
+    fn test_placeholders() {
+        assert_eq!(
+            format_title("{filename} — {cwd}", &info()),
+            "main.rs — ~/neovide"
+        );
+        assert_eq!(
+            format_title("{icon}: {title}", &info()),
+            "main.rs: main.rs (~/neovide/src) - NVIM"
+        );
+    }
+
+    #[test]
+    fn test_modified_indicator() {
+        let mut info = info();
+        assert_eq!(format_title("{modified} {filename}", &info), "main.rs");
+
+        info.modified = true;
+        assert_eq!(format_title("{modified} {filename}", &info), "● main.rs");
+    }
+
+    #[test]
+    fn test_unknown_placeholders_are_kept() {
+        assert_eq!(
+            format_title("{filename} {branch}", &info()),
+            "main.rs {branch}"
+        );
+    }
+}
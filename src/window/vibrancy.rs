@@ -0,0 +1,58 @@
+use cocoa::{
+    base::{id, nil, YES},
+    foundation::NSRect,
+};
+use objc::{class, msg_send, rc::autoreleasepool, runtime::BOOL, sel, sel_impl};
+use winit::{platform::macos::WindowExtMacOS, window::Window};
+
+// Values of the AppKit enums, which the cocoa crate doesn't cover
+const NS_VIEW_WIDTH_SIZABLE: u64 = 1 << 1;
+const NS_VIEW_HEIGHT_SIZABLE: u64 = 1 << 4;
+const NS_VISUAL_EFFECT_MATERIAL_UNDER_WINDOW_BACKGROUND: i64 = 21;
+const NS_VISUAL_EFFECT_BLENDING_MODE_BEHIND_WINDOW: i64 = 0;
+const NS_VISUAL_EFFECT_STATE_ACTIVE: i64 = 1;
+const NS_WINDOW_BELOW: i64 = -1;
+
+/// Blurs what's behind the window with a visual effect view below the OpenGL content, or removes
+/// the blur. The effect view is a sibling of the OpenGL view, since subviews are always drawn on
+/// top of the view they're in.
+pub fn set_vibrancy(window: &Window, blurred: bool) {
+    autoreleasepool(|| unsafe {
+        let ns_view = window.ns_view() as id;
+        let superview: id = msg_send![ns_view, superview];
+        if superview == nil {
+            return;
+        }
+        remove_effect_views(superview);
+        if !blurred {
+            return;
+        }
+
+        let frame: NSRect = msg_send![ns_view, frame];
+        let effect_view: id = msg_send![class!(NSVisualEffectView), alloc];
+        let effect_view: id = msg_send![effect_view, initWithFrame: frame];
+        let _: () = msg_send![effect_view,
+            setAutoresizingMask: NS_VIEW_WIDTH_SIZABLE | NS_VIEW_HEIGHT_SIZABLE];
+        let _: () = msg_send![effect_view,
+            setMaterial: NS_VISUAL_EFFECT_MATERIAL_UNDER_WINDOW_BACKGROUND];
+        let _: () = msg_send![effect_view,
+            setBlendingMode: NS_VISUAL_EFFECT_BLENDING_MODE_BEHIND_WINDOW];
+        let _: () = msg_send![effect_view, setState: NS_VISUAL_EFFECT_STATE_ACTIVE];
+        let _: () = msg_send![superview,
+            addSubview: effect_view positioned: NS_WINDOW_BELOW relativeTo: ns_view];
+        let _: () = msg_send![effect_view, release];
+    });
+}
+
+unsafe fn remove_effect_views(superview: id) {
+    // A copy of the subviews, so removing them doesn't change it
+    let subviews: id = msg_send![superview, subviews];
+    let count: usize = msg_send![subviews, count];
+    for index in 0..count {
+        let subview: id = msg_send![subviews, objectAtIndex: index];
+        let is_effect_view: BOOL = msg_send![subview, isKindOfClass: class!(NSVisualEffectView)];
+        if is_effect_view == YES {
+            let _: () = msg_send![subview, removeFromSuperview];
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use log::warn;
+
+// Enough swaps that a few slow ones, like while the window is being dragged, don't count
+const SAMPLE_COUNT: usize = 30;
+const SLOW_SAMPLE_COUNT: usize = SAMPLE_COUNT * 3 / 4;
+// Waiting for vsync takes at most a frame, some drivers also queue one or two more
+const SLOW_SWAP_FRAMES: u32 = 4;
+const MIN_SLOW_SWAP: Duration = Duration::from_millis(50);
+
+/// Watches how long swapping buffers with vsync takes. Some drivers, mostly in virtual machines,
+/// block for far longer than a frame, which makes animations crawl at a few frames per second.
+/// Those are detected, so that the window can pace the frames with its own timer instead.
+pub struct VsyncFallback {
+    slow_swaps: VecDeque<bool>,
+    paused: bool,
+    fallen_back: bool,
+}
+
+impl VsyncFallback {
+    pub fn new() -> Self {
+        Self {
+            slow_swaps: VecDeque::with_capacity(SAMPLE_COUNT),
+            paused: false,
+            fallen_back: false,
+        }
+    }
+
+    /// Stops watching, for while the window is in the background. Compositors may throttle
+    /// hidden windows on purpose, which isn't a broken driver.
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.slow_swaps.clear();
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    /// Records how long swapping the buffers took. Returns true once, when vsync should be turned
+    /// off.
+    pub fn record_swap(&mut self, swap_duration: Duration, frame_length: Duration) -> bool {
+        if self.paused || self.fallen_back {
+            return false;
+        }
+
+        let slow_swap = (frame_length * SLOW_SWAP_FRAMES).max(MIN_SLOW_SWAP);
+        if self.slow_swaps.len() == SAMPLE_COUNT {
+            self.slow_swaps.pop_front();
+        }
+        self.slow_swaps.push_back(swap_duration > slow_swap);
+
+        let slow_count = self.slow_swaps.iter().filter(|slow| **slow).count();
+        if self.slow_swaps.len() == SAMPLE_COUNT && slow_count >= SLOW_SAMPLE_COUNT {
+            warn!(
+                "Swapping buffers with vsync blocked longer than {}ms in {} of the last {} frames, \
+                 turning vsync off and pacing frames with a timer instead",
+                slow_swap.as_millis(),
+                slow_count,
+                SAMPLE_COUNT
+            );
+            self.fallen_back = true;
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_micros(16_667);
+
+    fn record(fallback: &mut VsyncFallback, swap_millis: u64, count: usize) -> bool {
+        (0..count)
+            .map(|_| fallback.record_swap(Duration::from_millis(swap_millis), FRAME))
+            .fold(false, |fell_back, now| fell_back || now)
+    }
+
+    #[test]
+    fn normal_vsync_is_kept() {
+        let mut fallback = VsyncFallback::new();
+        assert!(!record(&mut fallback, 16, 100));
+    }
+
+    #[test]
+    fn blocking_vsync_falls_back_once() {
+        let mut fallback = VsyncFallback::new();
+        assert!(!record(&mut fallback, 16, 10));
+        assert!(record(&mut fallback, 500, SAMPLE_COUNT));
+        assert!(!record(&mut fallback, 500, SAMPLE_COUNT));
+    }
+
+    #[test]
+    fn occasional_slow_swaps_are_ignored() {
+        let mut fallback = VsyncFallback::new();
+        for _ in 0..20 {
+            assert!(!record(&mut fallback, 500, 2));
+            assert!(!record(&mut fallback, 16, 2));
+        }
+    }
+
+    #[test]
+    fn slow_swaps_in_the_background_are_ignored() {
+        let mut fallback = VsyncFallback::new();
+        fallback.pause();
+        assert!(!record(&mut fallback, 1000, 100));
+        fallback.resume();
+        assert!(!record(&mut fallback, 16, 100));
+    }
+}
@@ -0,0 +1,53 @@
+use std::{ffi::CString, ptr::null};
+
+use log::warn;
+use raw_window_handle::{
+    HasRawDisplayHandle, HasRawWindowHandle, RawDisplayHandle, RawWindowHandle,
+};
+use winit::window::Window;
+use x11_dl::xlib::{self, Xlib};
+
+// Read by KWin, and by picom with its blur enabled
+const BLUR_REGION_PROPERTY: &str = "_KDE_NET_WM_BLUR_BEHIND_REGION";
+
+/// Asks the X11 compositor to blur what's behind the window, or to stop. Wayland compositors don't
+/// get asked, they blur windows based on their own rules.
+pub fn set_x11_blur(window: &Window, blurred: bool) {
+    let (display, window_id) = match (window.raw_display_handle(), window.raw_window_handle()) {
+        (RawDisplayHandle::Xlib(display), RawWindowHandle::Xlib(window)) => {
+            (display.display as *mut xlib::Display, window.window)
+        }
+        _ => return,
+    };
+    if display.is_null() {
+        return;
+    }
+    let xlib = match Xlib::open() {
+        Ok(xlib) => xlib,
+        Err(error) => {
+            warn!("Could not blur the window: {}", error);
+            return;
+        }
+    };
+
+    let property = CString::new(BLUR_REGION_PROPERTY).unwrap();
+    unsafe {
+        let atom = (xlib.XInternAtom)(display, property.as_ptr(), xlib::False);
+        if blurred {
+            // An empty region blurs behind the whole window
+            (xlib.XChangeProperty)(
+                display,
+                window_id,
+                atom,
+                xlib::XA_CARDINAL,
+                32,
+                xlib::PropModeReplace,
+                null(),
+                0,
+            );
+        } else {
+            (xlib.XDeleteProperty)(display, window_id, atom);
+        }
+        (xlib.XFlush)(display);
+    }
+}
@@ -0,0 +1,63 @@
+use crate::renderer::WindowPadding;
+
+/// The padding in zen mode: at least `min_padding` on every side, and wide enough on the left and
+/// the right to center at most `max_columns` columns. A `max_columns` of 0 doesn't limit them.
+pub fn zen_padding(
+    padding: WindowPadding,
+    min_padding: u32,
+    max_columns: u64,
+    window_width: u32,
+    column_width: u64,
+) -> WindowPadding {
+    let mut zen_padding = WindowPadding {
+        top: padding.top.max(min_padding),
+        left: padding.left.max(min_padding),
+        right: padding.right.max(min_padding),
+        bottom: padding.bottom.max(min_padding),
+    };
+
+    let content_width = window_width.saturating_sub(zen_padding.left + zen_padding.right);
+    let max_width = (max_columns * column_width).min(u32::MAX as u64) as u32;
+    if max_columns > 0 && content_width > max_width {
+        let extra = content_width - max_width;
+        zen_padding.left += extra / 2;
+        zen_padding.right += extra - extra / 2;
+    }
+    zen_padding
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn padding(top: u32, left: u32, right: u32, bottom: u32) -> WindowPadding {
+        WindowPadding {
+            top,
+            left,
+            right,
+            bottom,
+        }
+    }
+
+    #[test]
+    fn padding_is_at_least_the_zen_padding() {
+        assert_eq!(
+            zen_padding(padding(0, 60, 0, 0), 40, 0, 1000, 10),
+            padding(40, 60, 40, 40)
+        );
+    }
+
+    #[test]
+    fn wide_windows_center_the_columns() {
+        // 1000 - 2 * 40 leaves 920 pixels, 720 more than 20 columns need
+        assert_eq!(
+            zen_padding(padding(0, 0, 0, 0), 40, 20, 1000, 10),
+            padding(40, 400, 400, 40)
+        );
+        // Narrow windows keep the plain zen padding
+        assert_eq!(
+            zen_padding(padding(0, 0, 0, 0), 40, 100, 1000, 10),
+            padding(40, 40, 40, 40)
+        );
+    }
+}
@@ -3,19 +3,41 @@ use std::{
     ptr::{null, null_mut},
 };
 
+use log::error;
+use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
 use winapi::{
     shared::{
         minwindef::{DWORD, HKEY, MAX_PATH},
-        windef::DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2,
+        windef::{DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2, HWND},
+        winerror::SUCCEEDED,
+        wtypesbase::CLSCTX_INPROC_SERVER,
     },
     um::{
+        combaseapi::CoCreateInstance,
+        dwmapi::{DwmExtendFrameIntoClientArea, DwmSetWindowAttribute},
         libloaderapi::GetModuleFileNameA,
+        shobjidl_core::{CLSID_TaskbarList, ITaskbarList3, TBPF_NOPROGRESS, TBPF_NORMAL},
+        uxtheme::MARGINS,
         wincon::{AttachConsole, FreeConsole, ATTACH_PARENT_PROCESS},
         winnt::{KEY_WRITE, REG_OPTION_NON_VOLATILE, REG_SZ},
         winreg::{RegCloseKey, RegCreateKeyExA, RegDeleteTreeA, RegSetValueExA, HKEY_CURRENT_USER},
-        winuser::SetProcessDpiAwarenessContext,
+        winuser::{
+            CreateCaret, DestroyCaret, NotifyWinEvent, SetCaretPos, SetProcessDpiAwarenessContext,
+            CHILDID_SELF, EVENT_OBJECT_LOCATIONCHANGE, OBJID_CARET,
+        },
     },
+    Interface,
 };
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    window::Window,
+};
+
+// The system backdrop attribute and its values, available from Windows 11 22H2 on
+const DWMWA_SYSTEMBACKDROP_TYPE: DWORD = 38;
+const DWMSBT_NONE: DWORD = 1;
+const DWMSBT_MAINWINDOW: DWORD = 2;
+const DWMSBT_TRANSIENTWINDOW: DWORD = 3;
 
 fn get_binary_path() -> String {
     let mut buffer = vec![0u8; MAX_PATH];
@@ -237,3 +259,102 @@ pub fn windows_detach_from_console() {
         FreeConsole();
     }
 }
+
+pub fn windows_set_caret(
+    window: &Window,
+    position: PhysicalPosition<i32>,
+    size: PhysicalSize<u32>,
+) {
+    let hwnd = match window.raw_window_handle() {
+        RawWindowHandle::Win32(handle) => handle.hwnd as HWND,
+        _ => return,
+    };
+    unsafe {
+        // The caret is never shown, it only exists for accessibility tools to track
+        CreateCaret(hwnd, null_mut(), size.width as i32, size.height as i32);
+        SetCaretPos(position.x, position.y);
+        NotifyWinEvent(EVENT_OBJECT_LOCATIONCHANGE, hwnd, OBJID_CARET, CHILDID_SELF);
+    }
+}
+
+pub fn windows_set_taskbar_progress(window: &Window, progress: Option<f64>) {
+    let hwnd = match window.raw_window_handle() {
+        RawWindowHandle::Win32(handle) => handle.hwnd as HWND,
+        _ => return,
+    };
+    unsafe {
+        // COM is already initialized on the window thread by winit for drag and drop
+        let mut taskbar_list: *mut ITaskbarList3 = null_mut();
+        let result = CoCreateInstance(
+            &CLSID_TaskbarList,
+            null_mut(),
+            CLSCTX_INPROC_SERVER,
+            &ITaskbarList3::uuidof(),
+            &mut taskbar_list as *mut *mut ITaskbarList3 as *mut _,
+        );
+        if !SUCCEEDED(result) || taskbar_list.is_null() {
+            return;
+        }
+
+        let taskbar_list = &*taskbar_list;
+        if SUCCEEDED(taskbar_list.HrInit()) {
+            match progress {
+                Some(progress) => {
+                    taskbar_list.SetProgressState(hwnd, TBPF_NORMAL);
+                    taskbar_list.SetProgressValue(hwnd, progress.round() as u64, 100);
+                }
+                None => {
+                    taskbar_list.SetProgressState(hwnd, TBPF_NOPROGRESS);
+                }
+            }
+        }
+        taskbar_list.Release();
+    }
+}
+
+/// Blurs what's behind the window with the acrylic or the mica material, or removes the blur.
+pub fn windows_set_backdrop(window: &Window, blurred: bool, material: &str) {
+    let hwnd = match window.raw_window_handle() {
+        RawWindowHandle::Win32(handle) => handle.hwnd as HWND,
+        _ => return,
+    };
+    let backdrop = match (blurred, material) {
+        (false, _) => DWMSBT_NONE,
+        (true, "acrylic") => DWMSBT_TRANSIENTWINDOW,
+        (true, "mica") => DWMSBT_MAINWINDOW,
+        (true, _) => {
+            error!(
+                "Unknown blur material {}, expected acrylic or mica",
+                material
+            );
+            return;
+        }
+    };
+    // The backdrop is drawn in the frame, which has to cover the whole window to show through the
+    // transparent OpenGL surface
+    let extent = if blurred { -1 } else { 0 };
+    let margins = MARGINS {
+        cxLeftWidth: extent,
+        cxRightWidth: extent,
+        cyTopHeight: extent,
+        cyBottomHeight: extent,
+    };
+    unsafe {
+        DwmExtendFrameIntoClientArea(hwnd, &margins);
+        let result = DwmSetWindowAttribute(
+            hwnd,
+            DWMWA_SYSTEMBACKDROP_TYPE,
+            &backdrop as *const DWORD as *const _,
+            std::mem::size_of::<DWORD>() as DWORD,
+        );
+        if blurred && !SUCCEEDED(result) {
+            error!("Could not blur the window, which needs Windows 11");
+        }
+    }
+}
+
+pub fn windows_destroy_caret() {
+    unsafe {
+        DestroyCaret();
+    }
+}